@@ -0,0 +1,236 @@
+//! Bots: programs that take part in a session as ordinary clients, reacting
+//! to what collaborators do (autoformatters, link expanders, spell fixers).
+//!
+//! Implement `Bot`, open documents on a `Session`, and hand both to `run`.
+
+use std::{collections::HashSet, io, time::Duration};
+
+use common::space::OperationProto;
+
+use crate::{
+    connection::{DocumentHandle, DocumentSnapshot},
+    session::{EventKind, HandleId, Session},
+};
+
+/// Hooks called from `run` as events arrive. An error stops the bot and is
+/// returned from `run`.
+pub trait Bot {
+    /// The document was synced; `document.snapshot()` is its new state. The
+    /// server syncs after every batch of operations, so this is where edits
+    /// based on the whole text belong.
+    fn on_sync(&mut self, document: &BotDocument) -> io::Result<()> {
+        let _ = document;
+        Ok(())
+    }
+
+    /// A collaborator's operation was applied. The snapshot may not include
+    /// it yet; the sync that follows will.
+    fn on_operation(&mut self, document: &BotDocument, op: &OperationProto) -> io::Result<()> {
+        let _ = (document, op);
+        Ok(())
+    }
+}
+
+/// The document an event is about, with helpers for editing it.
+pub struct BotDocument {
+    id: HandleId,
+    handle: DocumentHandle,
+    snapshot: DocumentSnapshot,
+}
+
+impl BotDocument {
+    pub fn id(&self) -> HandleId {
+        self.id
+    }
+
+    pub fn handle(&self) -> &DocumentHandle {
+        &self.handle
+    }
+
+    /// The document as of the event.
+    pub fn snapshot(&self) -> &DocumentSnapshot {
+        &self.snapshot
+    }
+
+    /// Edits the document into `content` with one replace covering only the
+    /// part that differs. Returns false, sending nothing, if it already
+    /// matches, so a bot that rewrites on every sync settles after its own
+    /// edit comes back.
+    pub fn rewrite(&self, content: &str) -> io::Result<bool> {
+        let current = self.snapshot.content.as_str();
+        let Some((start, end, text)) = changed_range(current, content) else {
+            return Ok(false);
+        };
+        self.handle.replace(start as u32, end as u32, text)?;
+        Ok(true)
+    }
+
+    /// Replaces every occurrence of `from` with `to`. Returns false if there
+    /// were none.
+    pub fn replace_all(&self, from: &str, to: &str) -> io::Result<bool> {
+        if from.is_empty() || !self.snapshot.content.contains(from) {
+            return Ok(false);
+        }
+        self.rewrite(&self.snapshot.content.replace(from, to))
+    }
+}
+
+/// The byte range of `old` to replace, and its replacement, to turn `old`
+/// into `new`; `None` if they are equal.
+fn changed_range<'a>(old: &str, new: &'a str) -> Option<(usize, usize, &'a str)> {
+    if old == new {
+        return None;
+    }
+    let prefix = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(old.len().min(new.len()), |((i, _), _)| i);
+    let suffix = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    Some((prefix, old.len() - suffix, &new[prefix..new.len() - suffix]))
+}
+
+/// Feeds the session's events to `bot` until every open document has
+/// disconnected or a hook fails.
+pub fn run(session: &Session, bot: &mut impl Bot) -> io::Result<()> {
+    let mut disconnected = HashSet::new();
+    while disconnected.len() < session.handles().count() {
+        let Some(event) = session.next_event() else {
+            return Ok(());
+        };
+        // Notices are for people; bots have no use for them
+        let Some(id) = event.handle else {
+            continue;
+        };
+        if !dispatch(session, bot, id, event.kind)? {
+            disconnected.insert(id);
+        }
+    }
+    Ok(())
+}
+
+/// Like `run`, but also returns once no event has arrived for `idle`.
+/// Returns the number of events handled.
+pub fn run_until_idle(session: &Session, bot: &mut impl Bot, idle: Duration) -> io::Result<usize> {
+    let mut handled = 0;
+    let mut disconnected = HashSet::new();
+    while disconnected.len() < session.handles().count() {
+        let Some(event) = session.next_event_timeout(idle) else {
+            break;
+        };
+        handled += 1;
+        let Some(id) = event.handle else {
+            continue;
+        };
+        if !dispatch(session, bot, id, event.kind)? {
+            disconnected.insert(id);
+        }
+    }
+    Ok(handled)
+}
+
+/// Calls the hook for one event. Returns false if the document disconnected.
+fn dispatch(
+    session: &Session,
+    bot: &mut impl Bot,
+    id: HandleId,
+    kind: EventKind,
+) -> io::Result<bool> {
+    let Some(handle) = session.handle(id) else {
+        return Ok(true);
+    };
+    let document = BotDocument {
+        id,
+        handle: handle.clone(),
+        snapshot: handle.snapshot(),
+    };
+    match kind {
+        EventKind::Synced { .. } => bot.on_sync(&document)?,
+        EventKind::RemoteOperation(op) => bot.on_operation(&document, &op)?,
+        EventKind::Acknowledged(_)
+        | EventKind::Rebased(_)
+        | EventKind::RolledBack { .. }
+        | EventKind::Notice { .. } => {}
+        // The document now lives on another server this session isn't on
+        EventKind::Redirected { .. } | EventKind::Disconnected(_) => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{ConnectOptions, read_message, write_message};
+    use common::{
+        protocol::ServerMessage,
+        space::{SyncDocumentProto, operation_proto::Kind},
+    };
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_changed_range() {
+        assert_eq!(changed_range("abc", "abc"), None);
+        assert_eq!(changed_range("teh cat", "the cat"), Some((1, 3, "he")));
+        assert_eq!(changed_range("ab", "abc"), Some((2, 2, "c")));
+        assert_eq!(changed_range("aXa", "aa"), Some((1, 2, "")));
+        assert_eq!(changed_range("é!", "è!"), Some((0, 2, "è")));
+    }
+
+    /// Fixes "teh" whenever the document is synced.
+    struct SpellFix;
+
+    impl Bot for SpellFix {
+        fn on_sync(&mut self, document: &BotDocument) -> io::Result<()> {
+            document.replace_all("teh", "the")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bot_edits_on_sync_and_stops_on_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let served = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "teh end".to_string(),
+                version: 2,
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            let op = match read_message(&mut stream).unwrap() {
+                ServerMessage::Operation(op) => op,
+                _ => panic!("expected Operation"),
+            };
+            // Dropping the stream disconnects the bot
+            op
+        });
+
+        let mut session = Session::new();
+        session
+            .open(&ConnectOptions {
+                server,
+                ..Default::default()
+            })
+            .unwrap();
+        run(&session, &mut SpellFix).unwrap();
+
+        let op = served.join().unwrap();
+        assert_eq!(op.client_version, 2);
+        match op.kind {
+            Some(Kind::Replace(replace)) => {
+                assert_eq!((replace.start, replace.end), (1, 3));
+                assert_eq!(replace.text, "he");
+            }
+            _ => panic!("expected Replace"),
+        }
+    }
+}
@@ -0,0 +1,639 @@
+/// A location in the local buffer, given either as a raw byte offset (`12`)
+/// or as a 1-based `line:column` pair (`3:5`, column counted in characters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Offset(usize),
+    LineCol(usize, usize),
+}
+
+impl Position {
+    pub fn parse(token: &str) -> Result<Self, String> {
+        match token.split_once(':') {
+            Some((line, column)) => {
+                let line = line
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid line in position '{}'", token))?;
+                let column = column
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid column in position '{}'", token))?;
+                if line == 0 || column == 0 {
+                    return Err(format!("Line and column are 1-based: '{}'", token));
+                }
+                Ok(Position::LineCol(line, column))
+            }
+            None => token
+                .parse::<usize>()
+                .map(Position::Offset)
+                .map_err(|_| format!("Invalid offset '{}'", token)),
+        }
+    }
+
+    /// Resolves the position to a byte offset into `buffer`.
+    /// A column one past the end of a line addresses the end of that line.
+    pub fn resolve(&self, buffer: &str) -> Result<usize, String> {
+        match *self {
+            Position::Offset(offset) => {
+                if offset > buffer.len() {
+                    return Err(format!(
+                        "Offset {} out of bounds (len {})",
+                        offset,
+                        buffer.len()
+                    ));
+                }
+                if !buffer.is_char_boundary(offset) {
+                    return Err(format!("Offset {} is not on a character boundary", offset));
+                }
+                Ok(offset)
+            }
+            Position::LineCol(line, column) => {
+                let mut line_start = 0;
+                for (index, text) in buffer.split('\n').enumerate() {
+                    if index + 1 == line {
+                        let chars = text.chars().count();
+                        if column > chars + 1 {
+                            return Err(format!(
+                                "Column {} out of bounds on line {} ({} chars)",
+                                column, line, chars
+                            ));
+                        }
+                        let within = text
+                            .char_indices()
+                            .nth(column - 1)
+                            .map(|(byte, _)| byte)
+                            .unwrap_or(text.len());
+                        return Ok(line_start + within);
+                    }
+                    line_start += text.len() + 1;
+                }
+                Err(format!("Line {} out of bounds", line))
+            }
+        }
+    }
+}
+
+/// A parsed CLI command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    /// Replace the whole document with multi-line input.
+    Put,
+    /// Edit the buffer in `$EDITOR` and submit the differences.
+    Edit,
+    /// Write the buffer to a local file.
+    Save(String),
+    /// Submit a local file's contents as a diff against the buffer.
+    Load(String),
+    /// Ask the server for the document and its history, written to a local file.
+    Export(String),
+    /// Ask the server for a copy of the document, rendered from Markdown to
+    /// HTML with `html`, written to a local file.
+    Download {
+        path: String,
+        html: bool,
+    },
+    /// Upload a local file as an attachment of the document.
+    Attach(String),
+    /// Ask the server for an attachment the document references, written to
+    /// a local file.
+    Fetch {
+        attachment_id: String,
+        path: String,
+    },
+    /// Restore a document from an archive written by `export`.
+    Import(String),
+    /// Create a document at `path` from a server-side template.
+    New {
+        template: String,
+        path: String,
+        variables: Vec<(String, String)>,
+    },
+    /// Claim `[start, end)` so other connections cannot edit it.
+    Lock {
+        start: Position,
+        end: Position,
+    },
+    /// Release a lock taken with `lock`, by the id the server assigned.
+    Unlock(u64),
+    /// Raise a hand to collaborators, about a range or the whole document.
+    Hand(Option<(Position, Position)>),
+    /// Point collaborators at `[start, end)`.
+    Point {
+        start: Position,
+        end: Position,
+    },
+    /// React to `[start, end)` with an emoji.
+    React {
+        emoji: String,
+        start: Position,
+        end: Position,
+    },
+    /// Tell collaborators we stepped away (`away`) or are back (`back`).
+    Away(bool),
+    /// Make someone a member of our workspace; the server answers with
+    /// their token.
+    Invite(String),
+    /// Take someone out of our workspace.
+    Uninvite(String),
+    /// Ask for a guest invite to the document at `path`, good for
+    /// `minutes`; the server answers with its token.
+    Guest {
+        path: String,
+        minutes: u64,
+        edit: bool,
+    },
+    /// Ask the server to commit its persisted documents to git, with an
+    /// optional summary line.
+    Checkpoint(String),
+    /// Squash the open document's history so far into a labelled milestone.
+    Milestone(String),
+    /// Name the open document's current version.
+    Tag(String),
+    /// List the open document's milestones and tags; with a tag, also print
+    /// the document as it was at it.
+    History(String),
+    /// List the workspace's events after the given one: documents created,
+    /// members coming and going, checkpoints.
+    Events(u64),
+    /// Measure the round trip to the server and how far its clock is off.
+    Clock,
+    /// Print the buffer with line numbers.
+    Show,
+    /// Print `line_count` lines from `first_line` (1-based) and share them
+    /// with followers as the viewport.
+    View {
+        first_line: u32,
+        line_count: u32,
+    },
+    /// Mirror another connection's viewport; empty to stop.
+    Follow(String),
+    /// Full-screen view of the document and activity feed until Enter is pressed.
+    Watch,
+    Insert {
+        at: Position,
+        text: String,
+    },
+    Delete {
+        start: Position,
+        end: Position,
+    },
+    Replace {
+        start: Position,
+        end: Position,
+        text: String,
+    },
+}
+
+/// Lines `view` prints when not given a count.
+pub const DEFAULT_VIEW_LINES: u32 = 20;
+
+/// Command names offered by tab completion.
+pub const COMMAND_NAMES: &[&str] = &[
+    "show",
+    "view",
+    "follow",
+    "unfollow",
+    "watch",
+    "insert",
+    "delete",
+    "replace",
+    "edit",
+    "save",
+    "load",
+    "export",
+    "download",
+    "attach",
+    "fetch",
+    "import",
+    "new",
+    "lock",
+    "unlock",
+    "hand",
+    "point",
+    "react",
+    "away",
+    "back",
+    "invite",
+    "uninvite",
+    "guest",
+    "checkpoint",
+    "milestone",
+    "tag",
+    "history",
+    "events",
+    "clock",
+    "put",
+    "quit",
+];
+
+pub const USAGE: &str = "\
+Commands:
+  show                             print the buffer with line numbers
+  view <line> [count]              print lines from a line on, and show followers you are there
+  follow <client_id>               print where another connection is looking as it moves
+  unfollow                         stop following
+  watch                            follow the document and activity feed live
+  insert <pos> <text>              insert text at a position
+  delete <start> <end>             delete the range [start, end)
+  replace <start> <end> <text>     replace the range [start, end) with text
+  edit                             edit the buffer in $EDITOR and send the changes
+  save <path>                      write the buffer to a local file
+  load <path>                      replace the document with a local file's contents
+  export <path>                    archive the document and its history to a local file
+  download <path> [html]           save a copy of the document, or of it rendered as HTML
+  attach <path>                    upload a local file for the document to reference as attachment:<id>
+  fetch <id> <path>                save an attachment the document references to a local file
+  import <path>                    restore an archived document on the server
+  new <template> <path> [k=v...]   create a document from a server template
+  lock <start> <end>               stop other connections editing [start, end)
+  unlock <id>                      release a lock
+  hand [<start> <end>]             raise a hand to collaborators, optionally about a range
+  point <start> <end>              point collaborators at the range [start, end)
+  react <emoji> <start> <end>      react to the range [start, end) with an emoji
+  away / back                      show collaborators you stepped away, or are back
+  invite <name>                    make someone a member of the workspace and print their token
+  uninvite <name>                  take someone out of the workspace
+  guest <path> <minutes> [edit]    invite a guest to view (or edit) one document for a while
+  checkpoint [message]             commit the server's saved documents to its git history
+  milestone <label>                squash the document's edit history so far into a labelled milestone
+  tag <name>                       name the document's current version
+  history [tag]                    list milestones and tags, or print the document as it was at a tag
+  events [after]                   list the workspace's events (documents created, members, checkpoints)
+  clock                            measure the round trip to the server and its clock's offset from ours
+  put                              replace the whole document
+  quit                             close the connection and exit
+Positions are byte offsets (12) or 1-based line:column pairs (3:5).
+Text may use \\n, \\t and \\\\ escapes.";
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim_start();
+
+        match name {
+            "quit" => Ok(Command::Quit),
+            "put" | "send" => Ok(Command::Put),
+            "edit" => Ok(Command::Edit),
+            "save" if !rest.is_empty() => Ok(Command::Save(rest.to_string())),
+            "save" => Err("Usage: save <path>".to_string()),
+            "load" if !rest.is_empty() => Ok(Command::Load(rest.to_string())),
+            "load" => Err("Usage: load <path>".to_string()),
+            "export" if !rest.is_empty() => Ok(Command::Export(rest.to_string())),
+            "export" => Err("Usage: export <path>".to_string()),
+            "download" => match rest.rsplit_once(' ') {
+                Some((path, "html")) => Ok(Command::Download {
+                    path: path.trim().to_string(),
+                    html: true,
+                }),
+                _ if !rest.is_empty() => Ok(Command::Download {
+                    path: rest.to_string(),
+                    html: false,
+                }),
+                _ => Err("Usage: download <path> [html]".to_string()),
+            },
+            "attach" if !rest.is_empty() => Ok(Command::Attach(rest.to_string())),
+            "attach" => Err("Usage: attach <path>".to_string()),
+            "fetch" => match rest.split_once(' ') {
+                Some((attachment_id, path)) if !path.trim().is_empty() => Ok(Command::Fetch {
+                    attachment_id: attachment_id.to_string(),
+                    path: path.trim().to_string(),
+                }),
+                _ => Err("Usage: fetch <id> <path>".to_string()),
+            },
+            "import" if !rest.is_empty() => Ok(Command::Import(rest.to_string())),
+            "import" => Err("Usage: import <path>".to_string()),
+            "new" => {
+                let mut args = rest.split_whitespace();
+                let (Some(template), Some(path)) = (args.next(), args.next()) else {
+                    return Err("Usage: new <template> <path> [name=value...]".to_string());
+                };
+                let variables = args
+                    .map(|arg| {
+                        arg.split_once('=')
+                            .map(|(name, value)| (name.to_string(), unescape(value)))
+                            .ok_or_else(|| format!("Expected name=value, got '{}'", arg))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Command::New {
+                    template: template.to_string(),
+                    path: path.to_string(),
+                    variables,
+                })
+            }
+            "unlock" => rest
+                .parse::<u64>()
+                .map(Command::Unlock)
+                .map_err(|_| "Usage: unlock <id>".to_string()),
+            "invite" if !rest.is_empty() => Ok(Command::Invite(rest.to_string())),
+            "invite" => Err("Usage: invite <name>".to_string()),
+            "uninvite" if !rest.is_empty() => Ok(Command::Uninvite(rest.to_string())),
+            "uninvite" => Err("Usage: uninvite <name>".to_string()),
+            "guest" => {
+                let usage = "Usage: guest <path> <minutes> [edit]";
+                let mut args = rest.split_whitespace();
+                let (Some(path), Some(minutes)) = (args.next(), args.next()) else {
+                    return Err(usage.to_string());
+                };
+                let minutes = minutes
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|minutes| *minutes > 0)
+                    .ok_or_else(|| format!("Invalid number of minutes '{}'", minutes))?;
+                let edit = match (args.next(), args.next()) {
+                    (None, None) => false,
+                    (Some("edit"), None) => true,
+                    _ => return Err(usage.to_string()),
+                };
+                Ok(Command::Guest {
+                    path: path.to_string(),
+                    minutes,
+                    edit,
+                })
+            }
+            "checkpoint" => Ok(Command::Checkpoint(rest.to_string())),
+            "milestone" if !rest.is_empty() => Ok(Command::Milestone(rest.to_string())),
+            "milestone" => Err("Usage: milestone <label>".to_string()),
+            "tag" if !rest.is_empty() => Ok(Command::Tag(rest.to_string())),
+            "tag" => Err("Usage: tag <name>".to_string()),
+            "history" => Ok(Command::History(rest.to_string())),
+            "events" if rest.is_empty() => Ok(Command::Events(0)),
+            "events" => rest
+                .parse()
+                .map(Command::Events)
+                .map_err(|_| "Usage: events [after]".to_string()),
+            "clock" => Ok(Command::Clock),
+            "away" => Ok(Command::Away(true)),
+            "back" => Ok(Command::Away(false)),
+            "show" => Ok(Command::Show),
+            "view" => {
+                let usage = "Usage: view <line> [count]";
+                let mut args = rest.split_whitespace();
+                let first_line = args
+                    .next()
+                    .and_then(|line| line.parse::<u32>().ok())
+                    .filter(|line| *line > 0)
+                    .ok_or(usage)?;
+                let line_count = match (args.next(), args.next()) {
+                    (None, None) => DEFAULT_VIEW_LINES,
+                    (Some(count), None) => count.parse::<u32>().map_err(|_| usage)?,
+                    _ => return Err(usage.to_string()),
+                };
+                Ok(Command::View {
+                    first_line,
+                    line_count,
+                })
+            }
+            "follow" if !rest.is_empty() => Ok(Command::Follow(rest.to_string())),
+            "follow" => Err("Usage: follow <client_id>".to_string()),
+            "unfollow" => Ok(Command::Follow(String::new())),
+            "watch" => Ok(Command::Watch),
+            "insert" => {
+                let (at, text) = rest.split_once(' ').ok_or("Usage: insert <pos> <text>")?;
+                Ok(Command::Insert {
+                    at: Position::parse(at)?,
+                    text: unescape(text),
+                })
+            }
+            "delete" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), args.next(), args.next()) {
+                    (Some(start), Some(end), None) => Ok(Command::Delete {
+                        start: Position::parse(start)?,
+                        end: Position::parse(end)?,
+                    }),
+                    _ => Err("Usage: delete <start> <end>".to_string()),
+                }
+            }
+            "lock" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), args.next(), args.next()) {
+                    (Some(start), Some(end), None) => Ok(Command::Lock {
+                        start: Position::parse(start)?,
+                        end: Position::parse(end)?,
+                    }),
+                    _ => Err("Usage: lock <start> <end>".to_string()),
+                }
+            }
+            "hand" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), args.next(), args.next()) {
+                    (None, None, None) => Ok(Command::Hand(None)),
+                    (Some(start), Some(end), None) => Ok(Command::Hand(Some((
+                        Position::parse(start)?,
+                        Position::parse(end)?,
+                    )))),
+                    _ => Err("Usage: hand [<start> <end>]".to_string()),
+                }
+            }
+            "point" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), args.next(), args.next()) {
+                    (Some(start), Some(end), None) => Ok(Command::Point {
+                        start: Position::parse(start)?,
+                        end: Position::parse(end)?,
+                    }),
+                    _ => Err("Usage: point <start> <end>".to_string()),
+                }
+            }
+            "react" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), args.next(), args.next(), args.next()) {
+                    (Some(emoji), Some(start), Some(end), None) => Ok(Command::React {
+                        emoji: emoji.to_string(),
+                        start: Position::parse(start)?,
+                        end: Position::parse(end)?,
+                    }),
+                    _ => Err("Usage: react <emoji> <start> <end>".to_string()),
+                }
+            }
+            "replace" => {
+                let mut args = rest.splitn(3, ' ');
+                match (args.next(), args.next(), args.next()) {
+                    (Some(start), Some(end), Some(text)) => Ok(Command::Replace {
+                        start: Position::parse(start)?,
+                        end: Position::parse(end)?,
+                        text: unescape(text),
+                    }),
+                    _ => Err("Usage: replace <start> <end> <text>".to_string()),
+                }
+            }
+            _ => Err(format!("Unknown command: {}", name)),
+        }
+    }
+}
+
+/// Resolves a `[start, end)` pair of positions to byte offsets.
+pub fn resolve_range(start: Position, end: Position, buffer: &str) -> Result<(u32, u32), String> {
+    let start = start.resolve(buffer)?;
+    let end = end.resolve(buffer)?;
+    if start > end {
+        return Err(format!("Range start {} is after end {}", start, end));
+    }
+    Ok((start as u32, end as u32))
+}
+
+/// Renders the buffer with right-aligned, 1-based line numbers.
+pub fn render_buffer(buffer: &str) -> String {
+    render_lines(buffer, 0, usize::MAX)
+}
+
+/// `count` lines of `buffer` from the 0-based `first`, numbered as in
+/// `render_buffer`.
+pub fn render_lines(buffer: &str, first: usize, count: usize) -> String {
+    let lines: Vec<&str> = buffer.split('\n').collect();
+    let width = lines.len().to_string().len();
+    lines
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(count)
+        .map(|(index, line)| format!("{:>width$} | {}", index + 1, line, width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_line_col() {
+        let buffer = "hello\nwörld\n";
+        assert_eq!(Position::LineCol(1, 1).resolve(buffer), Ok(0));
+        assert_eq!(Position::LineCol(1, 6).resolve(buffer), Ok(5));
+        assert_eq!(Position::LineCol(2, 3).resolve(buffer), Ok(9));
+        assert_eq!(Position::LineCol(3, 1).resolve(buffer), Ok(13));
+        assert!(Position::LineCol(2, 7).resolve(buffer).is_err());
+        assert!(Position::LineCol(4, 1).resolve(buffer).is_err());
+    }
+
+    #[test]
+    fn test_resolve_offset_rejects_char_interior() {
+        let buffer = "wörld";
+        assert_eq!(Position::Offset(1).resolve(buffer), Ok(1));
+        assert!(Position::Offset(2).resolve(buffer).is_err());
+        assert!(Position::Offset(7).resolve(buffer).is_err());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            Command::parse("insert 2:1 a\\nb"),
+            Ok(Command::Insert {
+                at: Position::LineCol(2, 1),
+                text: "a\nb".to_string(),
+            })
+        );
+        assert_eq!(
+            Command::parse("replace 0 3 two words"),
+            Ok(Command::Replace {
+                start: Position::Offset(0),
+                end: Position::Offset(3),
+                text: "two words".to_string(),
+            })
+        );
+        assert_eq!(
+            Command::parse("new meeting notes/mon.md title=Standup\\ttoday"),
+            Ok(Command::New {
+                template: "meeting".to_string(),
+                path: "notes/mon.md".to_string(),
+                variables: vec![("title".to_string(), "Standup\ttoday".to_string())],
+            })
+        );
+        assert!(Command::parse("new meeting notes/mon.md title").is_err());
+        assert_eq!(
+            Command::parse("invite grace"),
+            Ok(Command::Invite("grace".to_string()))
+        );
+        assert!(Command::parse("uninvite").is_err());
+        assert_eq!(
+            Command::parse("guest notes.txt 30 edit"),
+            Ok(Command::Guest {
+                path: "notes.txt".to_string(),
+                minutes: 30,
+                edit: true,
+            })
+        );
+        assert!(Command::parse("guest notes.txt soon").is_err());
+        assert_eq!(
+            Command::parse("download notes.html html"),
+            Ok(Command::Download {
+                path: "notes.html".to_string(),
+                html: true,
+            })
+        );
+        assert_eq!(
+            Command::parse("fetch 9f86d081 diagrams/plan.png"),
+            Ok(Command::Fetch {
+                attachment_id: "9f86d081".to_string(),
+                path: "diagrams/plan.png".to_string(),
+            })
+        );
+        assert!(Command::parse("fetch 9f86d081").is_err());
+        assert_eq!(
+            Command::parse("view 40"),
+            Ok(Command::View {
+                first_line: 40,
+                line_count: DEFAULT_VIEW_LINES,
+            })
+        );
+        assert!(Command::parse("view 0 10").is_err());
+        assert_eq!(
+            Command::parse("unfollow"),
+            Ok(Command::Follow(String::new()))
+        );
+        assert_eq!(render_lines("a\nb\nc", 1, 1), "2 | b");
+        assert_eq!(Command::parse("hand"), Ok(Command::Hand(None)));
+        assert_eq!(
+            Command::parse("react 🎉 2:1 2:4"),
+            Ok(Command::React {
+                emoji: "🎉".to_string(),
+                start: Position::LineCol(2, 1),
+                end: Position::LineCol(2, 4),
+            })
+        );
+        assert!(Command::parse("point 3").is_err());
+        assert_eq!(
+            Command::parse("checkpoint Before the release"),
+            Ok(Command::Checkpoint("Before the release".to_string()))
+        );
+        assert_eq!(
+            Command::parse("milestone First draft"),
+            Ok(Command::Milestone("First draft".to_string()))
+        );
+        assert!(Command::parse("milestone").is_err());
+        assert_eq!(
+            Command::parse("history draft-1"),
+            Ok(Command::History("draft-1".to_string()))
+        );
+        assert_eq!(Command::parse("events"), Ok(Command::Events(0)));
+        assert_eq!(Command::parse("events 12"), Ok(Command::Events(12)));
+        assert!(Command::parse("events latest").is_err());
+        assert_eq!(Command::parse("clock"), Ok(Command::Clock));
+        assert!(Command::parse("tag").is_err());
+        assert!(Command::parse("delete 1").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+}
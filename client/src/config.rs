@@ -0,0 +1,175 @@
+use std::{env, time::Duration};
+
+use clap::Parser;
+
+const DEFAULT_SERVER: &str = "127.0.0.1:8000";
+const DEFAULT_RECONNECT_DELAY_MS: u64 = 1000;
+
+/// What to do when the connection to the server is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    Never,
+    Always,
+    /// Give up after this many consecutive failed attempts.
+    Attempts(u32),
+}
+
+impl ReconnectPolicy {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "never" => Ok(ReconnectPolicy::Never),
+            "always" => Ok(ReconnectPolicy::Always),
+            n => n
+                .parse::<u32>()
+                .map(ReconnectPolicy::Attempts)
+                .map_err(|_| format!("Invalid reconnect policy '{}'", value)),
+        }
+    }
+
+    /// Whether another attempt should be made after `failed` consecutive failures.
+    pub fn should_retry(&self, failed: u32) -> bool {
+        match *self {
+            ReconnectPolicy::Never => false,
+            ReconnectPolicy::Always => true,
+            ReconnectPolicy::Attempts(max) => failed < max,
+        }
+    }
+}
+
+// The command line; doc comments here are its --help. Options left out fall
+// back to the environment, then to their defaults, in `ClientConfig::from_args`.
+#[derive(Debug, Parser)]
+#[command(name = "client")]
+struct Args {
+    /// server address [env: DIST_SPACE_SERVER] [default: 127.0.0.1:8000]
+    #[arg(short, long, value_name = "ADDR")]
+    server: Option<String>,
+    /// document path to open [env: DIST_SPACE_DOC]
+    #[arg(short, long, value_name = "PATH")]
+    doc: Option<String>,
+    /// display name shown to collaborators [env: DIST_SPACE_NAME]
+    #[arg(short, long)]
+    name: Option<String>,
+    /// auth token sent in the handshake [env: DIST_SPACE_TOKEN]
+    #[arg(short, long)]
+    token: Option<String>,
+    /// workspace to join [env: DIST_SPACE_WORKSPACE] [default: the server's]
+    #[arg(short, long, value_name = "NAME")]
+    workspace: Option<String>,
+    /// never, always, or a maximum attempt count [env: DIST_SPACE_RECONNECT] [default: never]
+    #[arg(long, value_name = "POLICY", value_parser = ReconnectPolicy::parse)]
+    reconnect: Option<ReconnectPolicy>,
+    /// delay between reconnect attempts [env: DIST_SPACE_RECONNECT_DELAY_MS] [default: 1000]
+    #[arg(long, value_name = "MS")]
+    reconnect_delay_ms: Option<u64>,
+    /// read-only watch mode (the server rejects edits)
+    #[arg(long)]
+    watch: bool,
+}
+
+/// Connection parameters, from command-line flags falling back to environment variables.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub server: String,
+    pub doc_path: Option<String>,
+    pub display_name: String,
+    pub auth_token: Option<String>,
+    pub workspace: Option<String>,
+    pub reconnect: ReconnectPolicy,
+    pub reconnect_delay: Duration,
+    pub watch: bool,
+}
+
+impl ClientConfig {
+    /// Parses `env::args()`. Prints the help for `--help`, or what is wrong
+    /// with the flags, and exits; returns `Err` with a message for an
+    /// invalid environment variable.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_args(Args::parse(), |key| env::var(key).ok())
+    }
+
+    fn from_args(args: Args, var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let reconnect = match (args.reconnect, var("DIST_SPACE_RECONNECT")) {
+            (Some(policy), _) => policy,
+            (None, Some(value)) => ReconnectPolicy::parse(&value)?,
+            (None, None) => ReconnectPolicy::Never,
+        };
+        let reconnect_delay_ms = match (
+            args.reconnect_delay_ms,
+            var("DIST_SPACE_RECONNECT_DELAY_MS"),
+        ) {
+            (Some(ms), _) => ms,
+            (None, Some(value)) => parse_millis(&value)?,
+            (None, None) => DEFAULT_RECONNECT_DELAY_MS,
+        };
+        Ok(ClientConfig {
+            server: args
+                .server
+                .or_else(|| var("DIST_SPACE_SERVER"))
+                .unwrap_or_else(|| DEFAULT_SERVER.to_string()),
+            doc_path: args.doc.or_else(|| var("DIST_SPACE_DOC")),
+            display_name: args
+                .name
+                .or_else(|| var("DIST_SPACE_NAME"))
+                .or_else(|| var("USER"))
+                .unwrap_or_else(|| "anonymous".to_string()),
+            auth_token: args.token.or_else(|| var("DIST_SPACE_TOKEN")),
+            workspace: args.workspace.or_else(|| var("DIST_SPACE_WORKSPACE")),
+            reconnect,
+            reconnect_delay: Duration::from_millis(reconnect_delay_ms),
+            watch: args.watch,
+        })
+    }
+}
+
+fn parse_millis(value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid delay '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], vars: &[(&str, &str)]) -> Result<ClientConfig, String> {
+        let args =
+            Args::try_parse_from(["client"].iter().chain(args)).map_err(|e| e.to_string())?;
+        ClientConfig::from_args(args, |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_flags_override_env() {
+        let config = parse(
+            &["--server=10.0.0.1:9000", "-n", "ada", "--reconnect", "3"],
+            &[
+                ("DIST_SPACE_SERVER", "ignored:1"),
+                ("DIST_SPACE_TOKEN", "secret"),
+                ("DIST_SPACE_WORKSPACE", "docs-team"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.server, "10.0.0.1:9000");
+        assert_eq!(config.display_name, "ada");
+        assert_eq!(config.auth_token.as_deref(), Some("secret"));
+        assert_eq!(config.workspace.as_deref(), Some("docs-team"));
+        assert_eq!(config.reconnect, ReconnectPolicy::Attempts(3));
+    }
+
+    #[test]
+    fn test_defaults_and_errors() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config.server, DEFAULT_SERVER);
+        assert_eq!(config.reconnect, ReconnectPolicy::Never);
+        assert!(parse(&["--doc"], &[]).is_err());
+        assert!(parse(&["--bogus"], &[]).is_err());
+        assert!(parse(&["--reconnect", "sometimes"], &[]).is_err());
+        assert!(parse(&[], &[("DIST_SPACE_RECONNECT", "sometimes")]).is_err());
+        assert!(parse(&["--reconnect-delay-ms", "soon"], &[]).is_err());
+        assert!(parse(&["--watch", "-d", "notes.txt"], &[]).unwrap().watch);
+    }
+}
@@ -0,0 +1,1445 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufReader, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{
+    clock,
+    document::apply_to_text,
+    ids,
+    lines::line_range,
+    operation::{Operation, OperationKind},
+    protocol::{PROTOCOL_VERSION, ServerMessage, message_type_name},
+    space::{
+        CapabilitiesProto, CloseDocumentProto, CreditProto, DeleteLineOp, DeleteOp, DiagnosticKind,
+        DiagnosticsProto, ErrorCode, HelloProto, InsertLineOp, InsertOp, MoveLineOp,
+        OpenDocumentProto, OperationBatchProto, OperationProto, OverlayProto, RedirectProto,
+        ReplaceOp, ResendProto, SetOverlaysProto, SyncDocumentProto, operation_proto::Kind,
+    },
+};
+
+use crate::optimistic::Pending;
+
+/// Inserts of more text than this are split into a chunked transaction,
+/// keeping each op well under the server's per-op text limit.
+pub const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Ops sent per OperationBatch frame of a chunked transaction, keeping each
+/// frame well under the server's payload limit.
+const CHUNKS_PER_FRAME: usize = 8;
+
+/// Frames the reader thread reads ahead of the owner before waiting for it.
+const READ_AHEAD_FRAMES: usize = 64;
+
+/// This build, as sent in the Hello.
+pub const CLIENT_VERSION: &str = concat!("dist-space-client/", env!("CARGO_PKG_VERSION"));
+
+/// The send half of a connection to a server.
+pub trait Link: Write + Send {
+    /// Shuts the connection down both ways, which ends its reader too.
+    fn close(&self);
+}
+
+impl Link for TcpStream {
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+/// The send half, shared by the connection's documents.
+type Writer = Arc<Mutex<Box<dyn Link>>>;
+
+/// How connections reach a server: over TCP, or to a mock server in tests.
+pub trait Dialer: Send + Sync {
+    /// The read and send halves of a new stream to `address`.
+    fn dial(&self, address: &str) -> io::Result<(Box<dyn Read + Send>, Box<dyn Link>)>;
+}
+
+/// Dials servers over TCP; what connections use unless told otherwise.
+pub struct TcpDialer;
+
+impl Dialer for TcpDialer {
+    fn dial(&self, address: &str) -> io::Result<(Box<dyn Read + Send>, Box<dyn Link>)> {
+        let stream = TcpStream::connect(address)?;
+        let writer = stream.try_clone()?;
+        Ok((Box::new(stream), Box::new(writer)))
+    }
+}
+
+/// Called from a connection's reader thread whenever a frame is ready for
+/// `Connection::try_next_message`.
+pub type Waker = Arc<dyn Fn() + Send + Sync>;
+
+/// The waker shared between a connection and its reader thread, which is
+/// respawned when the connection follows a redirect.
+type SharedWaker = Arc<Mutex<Option<Waker>>>;
+
+/// Operations per document remembered until the server acknowledges them.
+const MAX_IN_FLIGHT: usize = 256;
+
+/// Sent ops not yet acknowledged, oldest first: op id and the range edited.
+type InFlight = VecDeque<(u64, (u32, u32))>;
+
+/// One of our operations that the server had to transform through ops we
+/// hadn't seen when sending it, so collaborators' edits landed around it.
+/// UIs can use it to briefly highlight where the edit ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rebased {
+    pub op_id: u64,
+    /// The range the op was written for, in the version it was based on.
+    pub sent: (u32, u32),
+    /// Where it was applied; `None` if the transform dropped it.
+    pub landed: Option<(u32, u32)>,
+    /// Ops applied between the version it was based on and it.
+    pub skipped_versions: u64,
+}
+
+/// Parameters for opening a document on a server.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    pub server: String,
+    /// Document to open; empty means the server default.
+    pub doc_path: String,
+    pub display_name: String,
+    pub auth_token: String,
+    /// Workspace to join; empty for the server's default one.
+    pub workspace: String,
+    /// Connect as a viewer: syncs are received, but edits are rejected.
+    pub read_only: bool,
+    /// Frames the server may send ahead of us reading them; 0 leaves the
+    /// server unpaced. Granted again in halves as frames are read.
+    pub credit_window: u32,
+    /// Show our edits in the snapshot as soon as they are sent, rather than
+    /// once the server echoes them. Edits the server refuses are rolled
+    /// back. Applies to every document opened over the connection.
+    pub optimistic: bool,
+    /// How long single inserts and deletes may wait to be combined with
+    /// the ones typed after them, so a fast typist sends a frame per burst
+    /// rather than per keystroke; zero sends each at once. Ignored unless
+    /// `optimistic`, as only then is each edit written against the text the
+    /// one before leaves.
+    pub flush_interval: Duration,
+}
+
+/// Last synchronized state of a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentSnapshot {
+    pub doc_id: String,
+    /// Path the document was opened with; empty until the first sync when
+    /// the server default was requested.
+    pub path: String,
+    pub version: u64,
+    pub content: String,
+    /// Our edits included in `content` that the server has not answered
+    /// yet; always 0 unless the connection is optimistic. `version` is the
+    /// server's, without them.
+    pub pending: usize,
+}
+
+impl DocumentSnapshot {
+    /// The version edits written against `content` are based on: the
+    /// server's, plus our pending edits, which it will have applied first.
+    pub fn base_version(&self) -> u64 {
+        self.version + self.pending as u64
+    }
+}
+
+/// An insert or delete held back to be combined with the next.
+struct Held {
+    operation: OperationProto,
+    since: Instant,
+}
+
+/// Handle to one open document: submits operations and exposes the latest sync.
+/// Cheap to clone; all clones share the same connection and state.
+#[derive(Clone)]
+pub struct DocumentHandle {
+    client_id: String,
+    writer: Writer,
+    snapshot: Arc<Mutex<DocumentSnapshot>>,
+    in_flight: Arc<Mutex<InFlight>>,
+    /// Our unanswered edits, when optimistic. Locked after `snapshot`.
+    pending: Option<Arc<Mutex<Pending>>>,
+    /// Locked before `snapshot`, and while sending, so that held edits
+    /// always go before the ones after them.
+    held: Arc<Mutex<Option<Held>>>,
+    flush_interval: Duration,
+}
+
+impl DocumentHandle {
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn snapshot(&self) -> DocumentSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    /// Applies the server's echo of our own operation if it landed exactly on
+    /// the snapshot's version; otherwise a sync already covers it.
+    fn apply_own(&self, op: &OperationProto) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if let Some(pending) = &self.pending {
+            let mut pending = pending.lock().unwrap();
+            pending.remove(op.op_id);
+            apply_confirmed(&mut snapshot, &mut pending, op);
+            return;
+        }
+        if op.server_version != snapshot.version {
+            return;
+        }
+        let Some(kind) = Operation::convert_operation(op.clone()) else {
+            return;
+        };
+        if apply_to_text(&mut snapshot.content, &kind).is_ok() {
+            snapshot.version += 1;
+        }
+    }
+
+    /// Applies a collaborator's operation under our pending edits, so they
+    /// don't wait for the sync that follows. Only optimistic snapshots
+    /// show collaborators' operations before then.
+    fn apply_remote(&self, op: &OperationProto) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let mut snapshot = self.snapshot.lock().unwrap();
+        apply_confirmed(&mut snapshot, &mut pending.lock().unwrap(), op);
+    }
+
+    /// Takes the server's text from a sync, with our pending edits on top.
+    fn apply_sync(&self, doc: &SyncDocumentProto) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        snapshot.doc_id = doc.doc_id.clone();
+        snapshot.version = doc.version;
+        if !doc.path.is_empty() {
+            snapshot.path = doc.path.clone();
+        }
+        match &self.pending {
+            Some(pending) => {
+                let mut pending = pending.lock().unwrap();
+                pending.confirm(&doc.content);
+                show_pending(&mut snapshot, &pending);
+            }
+            None => snapshot.content = doc.content.clone(),
+        }
+    }
+
+    /// Rolls back our edit `op_id` after the server refused it. Returns
+    /// false if it was not one of ours still pending.
+    fn roll_back(&self, op_id: u64) -> bool {
+        let Some(pending) = &self.pending else {
+            return false;
+        };
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let mut pending = pending.lock().unwrap();
+        if !pending.remove(op_id) {
+            return false;
+        }
+        show_pending(&mut snapshot, &pending);
+        true
+    }
+
+    fn is_pending(&self, op_id: u64) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| pending.lock().unwrap().contains(op_id))
+    }
+
+    /// Shows operations just sent in the snapshot, when optimistic.
+    fn stage(&self, operations: &[OperationProto]) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let mut pending = pending.lock().unwrap();
+        for operation in operations.iter().filter(|op| op.doc_id == snapshot.doc_id) {
+            if let Some(kind) = Operation::convert_operation(operation.clone()) {
+                pending.push(operation.op_id, kind);
+            }
+        }
+        show_pending(&mut snapshot, &pending);
+    }
+
+    /// Handles the server's echo of our own operation: applies it, and
+    /// reports it if it was transformed through ops we hadn't seen.
+    fn acknowledge(&self, op: &OperationProto) -> Option<Rebased> {
+        let sent = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            let index = in_flight.iter().position(|(op_id, _)| *op_id == op.op_id);
+            index.and_then(|index| in_flight.remove(index))
+        };
+        self.apply_own(op);
+        let (op_id, sent) = sent?;
+        (op.server_version > op.client_version).then(|| Rebased {
+            op_id,
+            sent,
+            landed: op.kind.as_ref().and_then(edit_range),
+            skipped_versions: op.server_version - op.client_version,
+        })
+    }
+
+    /// Whether both handles refer to the same open document.
+    pub fn is_same(&self, other: &DocumentHandle) -> bool {
+        Arc::ptr_eq(&self.snapshot, &other.snapshot)
+    }
+
+    /// Routes the next sync for the document's path to it, as when it is
+    /// reopened on another server, whose id for it may differ.
+    fn forget_id(&self) {
+        self.snapshot.lock().unwrap().doc_id.clear();
+    }
+
+    /// Inserts `text` at `index`. Text longer than `CHUNK_BYTES` goes as a
+    /// chunked transaction, which collaborators see land all at once.
+    pub fn insert(&self, index: u32, text: &str) -> io::Result<()> {
+        if text.len() > CHUNK_BYTES {
+            return self.insert_chunked(index, text);
+        }
+        let version = self.snapshot.lock().unwrap().base_version();
+        self.submit(Kind::Insert(InsertOp {
+            index,
+            text: text.to_string(),
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    pub fn delete(&self, start: u32, end: u32) -> io::Result<()> {
+        let version = self.snapshot.lock().unwrap().base_version();
+        self.submit(Kind::Delete(DeleteOp {
+            start,
+            end,
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    pub fn replace(&self, start: u32, end: u32, text: &str) -> io::Result<()> {
+        let version = self.snapshot.lock().unwrap().base_version();
+        self.submit(Kind::Replace(ReplaceOp {
+            start,
+            end,
+            text: text.to_string(),
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    /// Inserts `text` as consecutive inserts of at most `CHUNK_BYTES`, each
+    /// based on the version the ones before it leave.
+    fn insert_chunked(&self, index: u32, text: &str) -> io::Result<()> {
+        let mut held = self.held.lock().unwrap();
+        self.flush_held(&mut held)?;
+        let mut at = index;
+        let mut operations = Vec::new();
+        for (n, chunk) in chunks(text, CHUNK_BYTES).enumerate() {
+            let mut operation = self.operation(Kind::Insert(InsertOp {
+                index: at,
+                text: chunk.to_string(),
+                client_id: self.client_id.clone(),
+                client_version: 0,
+            }))?;
+            operation.client_version += n as u64;
+            if let Some(Kind::Insert(insert)) = &mut operation.kind {
+                insert.client_version = operation.client_version;
+            }
+            at += chunk.len() as u32;
+            operations.push(operation);
+        }
+        self.stage(&operations);
+        send_chunked(&mut *self.writer.lock().unwrap(), operations)
+    }
+
+    /// Inserts `text` as a new line before line `line` (counting from 0), or
+    /// after the last line if there is no such line.
+    pub fn insert_line(&self, line: usize, text: &str) -> io::Result<()> {
+        let (index, version) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            (line_start(&snapshot.content, line), snapshot.base_version())
+        };
+        self.submit(Kind::InsertLine(InsertLineOp {
+            index,
+            text: text.to_string(),
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    /// Deletes line `line`, along with anything collaborators type into it
+    /// meanwhile.
+    pub fn delete_line(&self, line: usize) -> io::Result<()> {
+        let ((start, end), version) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            (
+                existing_line(&snapshot.content, line)?,
+                snapshot.base_version(),
+            )
+        };
+        self.submit(Kind::DeleteLine(DeleteLineOp {
+            start,
+            end,
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    /// Moves line `line` to before line `to`, or to the end if there is no
+    /// such line.
+    pub fn move_line(&self, line: usize, to: usize) -> io::Result<()> {
+        let ((start, end), to, version) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            (
+                existing_line(&snapshot.content, line)?,
+                line_start(&snapshot.content, to),
+                snapshot.base_version(),
+            )
+        };
+        self.submit(Kind::MoveLine(MoveLineOp {
+            start,
+            end,
+            to,
+            client_id: self.client_id.clone(),
+            client_version: version,
+        }))
+    }
+
+    /// Replaces this connection's overlays of `kind`, given as `(start,
+    /// end, payload)` in the current snapshot; an empty list clears them.
+    pub fn set_overlays(&self, kind: &str, overlays: Vec<(u32, u32, String)>) -> io::Result<()> {
+        self.flush()?;
+        let (doc_id, version) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            (snapshot.doc_id.clone(), snapshot.base_version())
+        };
+        if doc_id.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Document not synchronized yet",
+            ));
+        }
+        let overlays = overlays
+            .into_iter()
+            .map(|(start, end, payload)| OverlayProto {
+                start,
+                end,
+                payload,
+                ..Default::default()
+            })
+            .collect();
+        self.send(&ServerMessage::SetOverlays(SetOverlaysProto {
+            doc_id,
+            version,
+            kind: kind.to_string(),
+            overlays,
+        }))
+    }
+
+    /// Sends an operation based on the current snapshot. With a flush
+    /// interval, inserts and deletes may first be held back and combined.
+    pub fn submit(&self, kind: Kind) -> io::Result<()> {
+        let mut held = self.held.lock().unwrap();
+        let operation = self.operation(kind)?;
+        if let Some(composed) = held.as_ref().and_then(|held| compose(held, &operation)) {
+            self.stage_composed(operation.op_id, &composed);
+            if let Some(held) = held.as_mut() {
+                held.operation.kind = Some(composed.to_proto());
+            }
+            return Ok(());
+        }
+        self.flush_held(&mut held)?;
+        self.stage(std::slice::from_ref(&operation));
+        let combinable = matches!(operation.kind, Some(Kind::Insert(_) | Kind::Delete(_)));
+        if combinable && !self.flush_interval.is_zero() {
+            *held = Some(Held {
+                operation,
+                since: Instant::now(),
+            });
+            return Ok(());
+        }
+        self.send(&ServerMessage::Operation(operation))
+    }
+
+    /// Sends any edit held back to be combined with the next.
+    pub fn flush(&self) -> io::Result<()> {
+        self.flush_held(&mut self.held.lock().unwrap())
+    }
+
+    /// Sends the held edit if it has waited out the flush interval.
+    fn flush_due(&self) -> io::Result<()> {
+        let mut held = self.held.lock().unwrap();
+        match held.as_ref() {
+            Some(due) if due.since.elapsed() >= self.flush_interval => self.flush_held(&mut held),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush_held(&self, held: &mut Option<Held>) -> io::Result<()> {
+        match held.take() {
+            Some(held) => self.send(&ServerMessage::Operation(held.operation)),
+            None => Ok(()),
+        }
+    }
+
+    /// Shows the held edit as `composed` now that `op_id` was combined into
+    /// it, rather than as two.
+    fn stage_composed(&self, op_id: u64, composed: &OperationKind) {
+        {
+            // The held edit is the newest in flight once `op_id` is gone
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight.retain(|(id, _)| *id != op_id);
+            if let (Some((_, range)), Some(composed)) =
+                (in_flight.back_mut(), edit_range(&composed.to_proto()))
+            {
+                *range = composed;
+            }
+        }
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let mut pending = pending.lock().unwrap();
+        pending.replace_last(composed.clone());
+        show_pending(&mut snapshot, &pending);
+    }
+
+    /// An operation on this document based on the current snapshot.
+    fn operation(&self, kind: Kind) -> io::Result<OperationProto> {
+        let (doc_id, version) = {
+            let snapshot = self.snapshot.lock().unwrap();
+            (snapshot.doc_id.clone(), snapshot.base_version())
+        };
+        if doc_id.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Document not synchronized yet",
+            ));
+        }
+
+        let operation = OperationProto {
+            op_id: ids::new_op_id(),
+            kind: Some(kind),
+            doc_id,
+            client_id: self.client_id.clone(),
+            client_version: version,
+            server_version: 0,
+            new_content: String::new(),
+            // Stamped by the server when applied
+            applied_at_ms: 0,
+            applied_mono_ms: 0,
+            global_version: 0,
+        };
+        if let Some(range) = operation.kind.as_ref().and_then(edit_range) {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight.len() == MAX_IN_FLIGHT {
+                in_flight.pop_front();
+            }
+            in_flight.push_back((operation.op_id, range));
+        }
+        Ok(operation)
+    }
+
+    pub fn send(&self, message: &ServerMessage) -> io::Result<()> {
+        write_message(&mut *self.writer.lock().unwrap(), message)
+    }
+}
+
+/// Send half of a connection, shared by every document opened on it.
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    client_id: String,
+    writer: Writer,
+    documents: Arc<Mutex<Vec<DocumentHandle>>>,
+    optimistic: bool,
+    flush_interval: Duration,
+}
+
+impl ConnectionHandle {
+    /// Opens another document over this connection. The handle becomes usable
+    /// once the server's sync for it arrives. Opening an already open path
+    /// returns the existing handle.
+    pub fn open_document(&self, path: &str) -> io::Result<DocumentHandle> {
+        if let Some(handle) = self
+            .documents()
+            .into_iter()
+            .find(|h| !path.is_empty() && h.snapshot().path == path)
+        {
+            return Ok(handle);
+        }
+
+        let handle = self.track(path);
+        let open = ServerMessage::OpenDocument(OpenDocumentProto {
+            path: path.to_string(),
+            resume: false,
+        });
+        write_message(&mut *self.writer.lock().unwrap(), &open)?;
+        Ok(handle)
+    }
+
+    /// Tells the server to stop sending updates for the document and forgets it.
+    pub fn close_document(&self, handle: &DocumentHandle) -> io::Result<()> {
+        handle.flush()?;
+        self.documents
+            .lock()
+            .unwrap()
+            .retain(|h| !h.is_same(handle));
+
+        let doc_id = handle.snapshot().doc_id;
+        if doc_id.is_empty() {
+            return Ok(());
+        }
+        let close = ServerMessage::CloseDocument(CloseDocumentProto { doc_id });
+        write_message(&mut *self.writer.lock().unwrap(), &close)
+    }
+
+    pub fn documents(&self) -> Vec<DocumentHandle> {
+        self.documents.lock().unwrap().clone()
+    }
+
+    /// Sends edits to any of the connection's documents as one transaction:
+    /// the server applies all of them or, if any is rejected, none. Each edit
+    /// is based on its document's current snapshot version. Many edits go
+    /// over several frames, which the server holds until the last.
+    pub fn submit_transaction(&self, edits: Vec<(&DocumentHandle, Kind)>) -> io::Result<()> {
+        for (handle, _) in &edits {
+            handle.flush()?;
+        }
+        let operations = edits
+            .into_iter()
+            .map(|(handle, kind)| handle.operation(kind))
+            .collect::<io::Result<Vec<_>>>()?;
+        for document in self.documents() {
+            document.stage(&operations);
+        }
+        send_chunked(&mut *self.writer.lock().unwrap(), operations)
+    }
+
+    /// Sends held edits as they wait out the flush interval, until the
+    /// connection is dropped or a send fails.
+    fn spawn_flusher(&self) {
+        let documents = Arc::downgrade(&self.documents);
+        let interval = self.flush_interval;
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval / 2);
+                let Some(documents) = documents.upgrade() else {
+                    return;
+                };
+                let documents = documents.lock().unwrap().clone();
+                if documents
+                    .iter()
+                    .any(|document| document.flush_due().is_err())
+                {
+                    return;
+                }
+            }
+        });
+    }
+
+    fn track(&self, path: &str) -> DocumentHandle {
+        let handle = DocumentHandle {
+            client_id: self.client_id.clone(),
+            writer: Arc::clone(&self.writer),
+            snapshot: Arc::new(Mutex::new(DocumentSnapshot {
+                path: path.to_string(),
+                ..Default::default()
+            })),
+            in_flight: Arc::new(Mutex::new(VecDeque::new())),
+            pending: self
+                .optimistic
+                .then(|| Arc::new(Mutex::new(Pending::default()))),
+            held: Arc::new(Mutex::new(None)),
+            flush_interval: self.flush_interval,
+        };
+        self.documents.lock().unwrap().push(handle.clone());
+        handle
+    }
+
+    /// Finds the document a sync belongs to: by id once known, otherwise the
+    /// pending open for its path, otherwise a pending open of the server default.
+    fn route_sync(&self, doc: &SyncDocumentProto) -> Option<DocumentHandle> {
+        let documents = self.documents.lock().unwrap();
+        let snapshots: Vec<DocumentSnapshot> = documents.iter().map(|h| h.snapshot()).collect();
+        let pending = |s: &DocumentSnapshot| s.doc_id.is_empty();
+
+        let index = snapshots
+            .iter()
+            .position(|s| s.doc_id == doc.doc_id)
+            .or_else(|| {
+                snapshots
+                    .iter()
+                    .position(|s| pending(s) && s.path == doc.path)
+            })
+            .or_else(|| {
+                snapshots
+                    .iter()
+                    .position(|s| pending(s) && s.path.is_empty())
+            })?;
+        Some(documents[index].clone())
+    }
+
+    /// Rolls back our refused edit `op_id` in whichever optimistic document
+    /// it is pending on, returning that document.
+    fn roll_back(&self, op_id: u64) -> Option<DocumentHandle> {
+        let documents = self.documents.lock().unwrap();
+        let document = documents.iter().find(|h| h.is_pending(op_id))?;
+        document.roll_back(op_id).then(|| document.clone())
+    }
+
+    fn route_operation(&self, doc_id: &str) -> Option<DocumentHandle> {
+        self.documents
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.snapshot().doc_id == doc_id)
+            .cloned()
+    }
+}
+
+/// A message received on a connection, with the document it was routed to.
+pub struct Received {
+    pub message: ServerMessage,
+    /// `None` for connection-level messages and for documents that were closed.
+    pub document: Option<DocumentHandle>,
+    /// Our own operations in the message that were transformed on the way.
+    pub rebased: Vec<Rebased>,
+}
+
+/// An established connection: the shared send half plus the read half,
+/// which the owner drives with `next_message`.
+pub struct Connection {
+    /// What the connection was opened with; `server` follows redirects.
+    options: ConnectOptions,
+    dialer: Arc<dyn Dialer>,
+    handle: ConnectionHandle,
+    first: DocumentHandle,
+    frames: Receiver<io::Result<ServerMessage>>,
+    waker: SharedWaker,
+    /// Number of the next server frame to pass on; `None` takes whatever
+    /// number comes next (at the start, and after the server could not fill
+    /// a gap).
+    next_seq: Option<u64>,
+    /// Where the outstanding Resend asked the server to start from.
+    resend_from: Option<u64>,
+    credit_window: u32,
+    /// Frames read since credit was last granted.
+    uncredited: u32,
+}
+
+impl Connection {
+    /// Connects to `options.server` and sends the Hello handshake, which
+    /// opens `options.doc_path`.
+    pub fn open(options: &ConnectOptions, client_id: &str) -> io::Result<Self> {
+        Self::open_with(options, client_id, Arc::new(TcpDialer))
+    }
+
+    /// Like `open`, reaching the server (and any it redirects to) through
+    /// `dialer`.
+    pub fn open_with(
+        options: &ConnectOptions,
+        client_id: &str,
+        dialer: Arc<dyn Dialer>,
+    ) -> io::Result<Self> {
+        let (stream, writer) = handshake(&*dialer, options, client_id)?;
+        let writer = Arc::new(Mutex::new(writer));
+        let waker = SharedWaker::default();
+        let frames = spawn_waking_reader(stream, Arc::clone(&writer), Arc::clone(&waker));
+        let handle = ConnectionHandle {
+            client_id: client_id.to_string(),
+            writer,
+            documents: Arc::new(Mutex::new(Vec::new())),
+            optimistic: options.optimistic,
+            flush_interval: match options.optimistic {
+                true => options.flush_interval,
+                false => Duration::ZERO,
+            },
+        };
+        let first = handle.track(&options.doc_path);
+        if !handle.flush_interval.is_zero() {
+            handle.spawn_flusher();
+        }
+
+        Ok(Self {
+            options: options.clone(),
+            dialer,
+            handle,
+            first,
+            frames,
+            waker,
+            next_seq: None,
+            resend_from: None,
+            credit_window: options.credit_window,
+            uncredited: 0,
+        })
+    }
+
+    /// The document opened by the handshake.
+    pub fn handle(&self) -> DocumentHandle {
+        self.first.clone()
+    }
+
+    pub fn connection_handle(&self) -> ConnectionHandle {
+        self.handle.clone()
+    }
+
+    /// Has `waker` called whenever a frame arrives, so an event loop knows
+    /// when `try_next_message` has something. It is called once straight
+    /// away for whatever arrived before it was set.
+    pub fn set_waker(&self, waker: Waker) {
+        *self.waker.lock().unwrap() = Some(Arc::clone(&waker));
+        waker();
+    }
+
+    /// Blocks for the next message, applying syncs (and echoes of our own
+    /// operations) to the matching document's snapshot before returning the
+    /// message to the caller. Pings have already been answered by then.
+    pub fn next_message(&mut self) -> io::Result<Received> {
+        loop {
+            let frame = self.frames.recv().map_err(|_| reader_stopped())?;
+            if let Some(received) = self.receive(frame)? {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// Like `next_message`, but returns `None` instead of waiting when no
+    /// message has arrived.
+    pub fn try_next_message(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            let frame = match self.frames.try_recv() {
+                Ok(frame) => frame,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(reader_stopped()),
+            };
+            if let Some(received) = self.receive(frame)? {
+                return Ok(Some(received));
+            }
+        }
+    }
+
+    /// Handles one frame from the reader thread. `None` if it is not passed
+    /// on: a frame out of sequence.
+    fn receive(&mut self, frame: io::Result<ServerMessage>) -> io::Result<Option<Received>> {
+        let message = frame?;
+        self.replenish_credit()?;
+        let message = match message {
+            ServerMessage::Sequenced(seq, message) => match self.in_sequence(seq)? {
+                true => *message,
+                false => return Ok(None),
+            },
+            message => message,
+        };
+        let mut rebased = Vec::new();
+        let document = match &message {
+            ServerMessage::SyncDocument(doc) => {
+                let document = self.handle.route_sync(doc);
+                if let Some(document) = &document {
+                    document.apply_sync(doc);
+                }
+                document
+            }
+            ServerMessage::Operation(op) => {
+                let document = self.handle.route_operation(&op.doc_id);
+                if let Some(document) = &document {
+                    if op.client_id == self.handle.client_id {
+                        rebased.extend(document.acknowledge(op));
+                    } else {
+                        document.apply_remote(op);
+                    }
+                }
+                document
+            }
+            ServerMessage::OperationBatch(batch) => {
+                let document = batch
+                    .operations
+                    .first()
+                    .and_then(|op| self.handle.route_operation(&op.doc_id));
+                if let Some(document) = &document {
+                    for op in &batch.operations {
+                        if op.client_id == self.handle.client_id {
+                            rebased.extend(document.acknowledge(op));
+                        } else {
+                            document.apply_remote(op);
+                        }
+                    }
+                }
+                document
+            }
+            ServerMessage::Error(error) if error.code() == ErrorCode::ResendUnavailable => {
+                self.resync()?;
+                None
+            }
+            ServerMessage::Redirect(redirect) => self.follow_redirect(redirect)?,
+            ServerMessage::Diagnostics(diagnostics)
+                if diagnostics.kind() != DiagnosticKind::UnknownMessage =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    describe_diagnostics(diagnostics),
+                ));
+            }
+            ServerMessage::Error(error) if error.op_id != 0 => self.handle.roll_back(error.op_id),
+            _ => None,
+        };
+        Ok(Some(Received {
+            message,
+            document,
+            rebased,
+        }))
+    }
+
+    /// Counts a frame read against the credit window, granting the server
+    /// the frames read so far once half the window is used.
+    fn replenish_credit(&mut self) -> io::Result<()> {
+        if self.credit_window == 0 {
+            return Ok(());
+        }
+        self.uncredited += 1;
+        if self.uncredited < self.credit_window.div_ceil(2) {
+            return Ok(());
+        }
+        let credit = ServerMessage::Credit(CreditProto {
+            frames: self.uncredited,
+            bytes: 0,
+        });
+        self.uncredited = 0;
+        write_message(&mut *self.handle.writer.lock().unwrap(), &credit)
+    }
+
+    /// Whether the frame numbered `seq` is the next one. A later number means
+    /// frames were lost: it is dropped along with everything else until the
+    /// server, asked once per gap, sends them again from the first one
+    /// missing. An earlier number is a duplicate from such a resend.
+    fn in_sequence(&mut self, seq: u64) -> io::Result<bool> {
+        let expected = self.next_seq.unwrap_or(seq);
+        if seq == expected {
+            self.next_seq = Some(seq + 1);
+            self.resend_from = None;
+            return Ok(true);
+        }
+        if seq > expected && self.resend_from != Some(expected) {
+            self.resend_from = Some(expected);
+            let resend = ServerMessage::Resend(ResendProto { from_seq: expected });
+            write_message(&mut *self.handle.writer.lock().unwrap(), &resend)?;
+        }
+        Ok(false)
+    }
+
+    /// Moves the connection to `redirect.address` and reopens every document
+    /// there, keeping the handles. Unless just one of several documents
+    /// moved: a connection can't be on two servers, so that one is returned
+    /// for the owner to reopen over a connection to the new server.
+    fn follow_redirect(&mut self, redirect: &RedirectProto) -> io::Result<Option<DocumentHandle>> {
+        let documents = self.handle.documents();
+        if !redirect.doc_id.is_empty()
+            && documents
+                .iter()
+                .any(|document| document.snapshot().doc_id != redirect.doc_id)
+        {
+            return Ok(self.handle.route_operation(&redirect.doc_id));
+        }
+
+        let mut options = self.options.clone();
+        options.server = redirect.address.clone();
+        // The Hello reopens the first document, and OpenDocument the others
+        let mut paths = documents.iter().map(|document| document.snapshot().path);
+        options.doc_path = paths.next().unwrap_or_default();
+        let (stream, mut writer) = handshake(&*self.dialer, &options, &self.handle.client_id)?;
+        for path in paths {
+            write_message(
+                &mut writer,
+                &ServerMessage::OpenDocument(OpenDocumentProto {
+                    path,
+                    resume: false,
+                }),
+            )?;
+        }
+        for document in &documents {
+            document.forget_id();
+        }
+
+        let old = std::mem::replace(&mut *self.handle.writer.lock().unwrap(), writer);
+        // Ends the old reader thread, and whatever it had read ahead is dropped
+        old.close();
+        self.frames = spawn_waking_reader(
+            stream,
+            Arc::clone(&self.handle.writer),
+            Arc::clone(&self.waker),
+        );
+        self.next_seq = None;
+        self.resend_from = None;
+        self.uncredited = 0;
+        self.options = options;
+        Ok(None)
+    }
+
+    /// The server no longer has the frames we are missing: take its numbering
+    /// from the next frame and reopen every document for a fresh sync.
+    fn resync(&mut self) -> io::Result<()> {
+        self.next_seq = None;
+        self.resend_from = None;
+        let mut writer = self.handle.writer.lock().unwrap();
+        for document in self.handle.documents() {
+            let path = document.snapshot().path;
+            let open = ServerMessage::OpenDocument(OpenDocumentProto {
+                path,
+                resume: false,
+            });
+            write_message(&mut *writer, &open)?;
+        }
+        Ok(())
+    }
+}
+
+/// Connects to `options.server` and sends the Hello handshake, and the
+/// credit window if any. Returns the read half and a cloned write half.
+fn handshake(
+    dialer: &dyn Dialer,
+    options: &ConnectOptions,
+    client_id: &str,
+) -> io::Result<(Box<dyn Read + Send>, Box<dyn Link>)> {
+    let (stream, mut writer) = dialer.dial(&options.server)?;
+
+    let hello = ServerMessage::Hello(HelloProto {
+        client_id: client_id.to_string(),
+        display_name: options.display_name.clone(),
+        doc_path: options.doc_path.clone(),
+        auth_token: options.auth_token.clone(),
+        client_time_ms: clock::unix_time_ms(),
+        read_only: options.read_only,
+        sequenced: true,
+        capabilities: Some(CapabilitiesProto {
+            batches: true,
+            presence: true,
+            ..CapabilitiesProto::default()
+        }),
+        workspace: options.workspace.clone(),
+        protocol_version: PROTOCOL_VERSION,
+        client_version: CLIENT_VERSION.to_string(),
+    });
+    write_message(&mut writer, &hello)?;
+    if options.credit_window > 0 {
+        let credit = ServerMessage::Credit(CreditProto {
+            frames: options.credit_window,
+            bytes: 0,
+        });
+        write_message(&mut writer, &credit)?;
+    }
+    Ok((stream, writer))
+}
+
+/// Reads frames from `stream` on a thread of their own, answering Pings
+/// as soon as they arrive however long the owner spends on what came before
+/// them (a large sync, say). Every message, Pings included, is passed on in
+/// order. The thread stops after a read error, which is passed on too,
+/// unless it was only a frame that failed to decode; or once the receiver
+/// is dropped.
+pub fn spawn_reader(
+    stream: TcpStream,
+    writer: Arc<Mutex<TcpStream>>,
+) -> Receiver<io::Result<ServerMessage>> {
+    spawn_waking_reader(stream, writer, SharedWaker::default())
+}
+
+/// Like `spawn_reader`, calling the waker, if one is set, after passing
+/// each message on.
+fn spawn_waking_reader<W: Write + Send + 'static>(
+    stream: impl Read + Send + 'static,
+    writer: Arc<Mutex<W>>,
+    waker: SharedWaker,
+) -> Receiver<io::Result<ServerMessage>> {
+    let (frames, receiver) = mpsc::sync_channel(READ_AHEAD_FRAMES);
+    let wake = move || {
+        if let Some(wake) = waker.lock().unwrap().clone() {
+            wake();
+        }
+    };
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let message = read_message(&mut reader);
+            if let Some(seq) = message.as_ref().ok().and_then(ping) {
+                let pong = ServerMessage::Pong(seq);
+                if let Err(e) = write_message(&mut *writer.lock().unwrap(), &pong) {
+                    let _ = frames.send(Err(e));
+                    wake();
+                    return;
+                }
+            }
+            let fatal = matches!(&message, Err(e) if e.kind() != io::ErrorKind::InvalidData);
+            if frames.send(message).is_err() {
+                return;
+            }
+            wake();
+            if fatal {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+fn reader_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "Reader thread stopped")
+}
+
+/// The sequence number of a Ping, sequenced or not.
+fn ping(message: &ServerMessage) -> Option<u64> {
+    match message {
+        ServerMessage::Ping(seq) => Some(*seq),
+        ServerMessage::Sequenced(_, message) => ping(message),
+        _ => None,
+    }
+}
+
+/// Reads one length-prefixed message.
+pub fn read_message(reader: &mut impl Read) -> io::Result<ServerMessage> {
+    // Read 4 bytes (big-endian u32) -> N (payload length)
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let payload_length = u32::from_be_bytes(len_bytes) as usize;
+
+    // Read exactly N bytes -> payload
+    let mut payload_buffer = vec![0u8; payload_length];
+    reader.read_exact(&mut payload_buffer)?;
+
+    ServerMessage::decode(&payload_buffer).map_err(|e| {
+        let message = match payload_buffer.get(4) {
+            Some(&type_id) if message_type_name(type_id) == "Unknown" => format!(
+                "The server sent message type {}, unknown to {} (protocol {}); it is likely newer: upgrade the client",
+                type_id, CLIENT_VERSION, PROTOCOL_VERSION
+            ),
+            _ => e.to_string(),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })
+}
+
+/// What the server said in `diagnostics` and what to do about it, with the
+/// versions on either side, to show the user.
+pub fn describe_diagnostics(diagnostics: &DiagnosticsProto) -> String {
+    let mut text = format!("{}. {}", diagnostics.message, diagnostics.guidance);
+    if !diagnostics.server_version.is_empty() {
+        text.push_str(&format!(
+            " (server: {}, protocol {}",
+            diagnostics.server_version, diagnostics.protocol_version
+        ));
+        if diagnostics.min_protocol_version > 0 {
+            text.push_str(&format!(
+                ", accepting {} or later",
+                diagnostics.min_protocol_version
+            ));
+        }
+        text.push_str(&format!(
+            "; this client: {}, protocol {})",
+            CLIENT_VERSION, PROTOCOL_VERSION
+        ));
+    }
+    text
+}
+
+/// Writes one length-prefixed message.
+pub fn write_message(writer: &mut impl Write, message: &ServerMessage) -> io::Result<()> {
+    let encoded = message.encode();
+    let len_bytes = (encoded.len() as u32).to_be_bytes();
+
+    writer.write_all(&len_bytes)?;
+    writer.write_all(&encoded)?;
+    writer.flush()
+}
+
+/// Sends `operations` as one transaction, `CHUNKS_PER_FRAME` to a batch,
+/// every batch but the last marked as having more to come.
+fn send_chunked(writer: &mut impl Write, operations: Vec<OperationProto>) -> io::Result<()> {
+    let frames = operations.len().div_ceil(CHUNKS_PER_FRAME).max(1);
+    let mut operations = operations.into_iter();
+    for frame in 1..=frames {
+        let batch = ServerMessage::OperationBatch(OperationBatchProto {
+            operations: operations.by_ref().take(CHUNKS_PER_FRAME).collect(),
+            more: frame < frames,
+        });
+        write_message(writer, &batch)?;
+    }
+    Ok(())
+}
+
+/// `text` in pieces of at most `max` bytes, split between characters and
+/// never inside a CRLF.
+fn chunks(text: &str, max: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = max.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end > 1
+            && end < rest.len()
+            && rest[..end].ends_with('\r')
+            && rest[end..].starts_with('\n')
+        {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// `next` combined into the held edit, if it was written against the text
+/// the held one leaves and the two combine.
+fn compose(held: &Held, next: &OperationProto) -> Option<OperationKind> {
+    if next.client_version != held.operation.client_version + 1 {
+        return None;
+    }
+    let first = Operation::convert_operation(held.operation.clone())?;
+    first.compose(&Operation::convert_operation(next.clone())?)
+}
+
+/// Applies an op the server applied to an optimistic snapshot's confirmed
+/// text, if it landed exactly on its version, and shows the pending edits
+/// on top again.
+fn apply_confirmed(snapshot: &mut DocumentSnapshot, pending: &mut Pending, op: &OperationProto) {
+    if op.server_version == snapshot.version
+        && let Some(kind) = Operation::convert_operation(op.clone())
+        && pending.apply(&kind)
+    {
+        snapshot.version += 1;
+    }
+    show_pending(snapshot, pending);
+}
+
+fn show_pending(snapshot: &mut DocumentSnapshot, pending: &Pending) {
+    snapshot.content = pending.view();
+    snapshot.pending = pending.len();
+}
+
+/// The range an op edits: the text it deletes or replaces, or an empty range
+/// where it inserts. `None` for a noop.
+fn edit_range(kind: &Kind) -> Option<(u32, u32)> {
+    match kind {
+        Kind::Insert(op) => Some((op.index, op.index)),
+        Kind::Delete(op) => Some((op.start, op.end)),
+        Kind::Replace(op) => Some((op.start, op.end)),
+        Kind::InsertLine(op) => Some((op.index, op.index)),
+        Kind::DeleteLine(op) => Some((op.start, op.end)),
+        Kind::MoveLine(op) => Some((op.start, op.end)),
+        Kind::Noop(_) => None,
+    }
+}
+
+/// Where line `line` starts, or the end of `content` past the last line.
+fn line_start(content: &str, line: usize) -> u32 {
+    line_range(content, line).map_or(content.len() as u32, |(start, _)| start)
+}
+
+fn existing_line(content: &str, line: usize) -> io::Result<(u32, u32)> {
+    line_range(content, line).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the document has no line {}", line),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_split_between_characters_and_keep_crlf_whole() {
+        assert_eq!(
+            chunks("abcdefg", 3).collect::<Vec<_>>(),
+            ["abc", "def", "g"]
+        );
+        // 'é' is two bytes and can't be split
+        assert_eq!(chunks("aébc", 2).collect::<Vec<_>>(), ["a", "é", "bc"]);
+        assert_eq!(
+            chunks("ab\r\ncd", 3).collect::<Vec<_>>(),
+            ["ab", "\r\nc", "d"]
+        );
+        assert_eq!(chunks("", 3).count(), 0);
+    }
+
+    #[test]
+    fn test_large_transactions_go_over_several_frames() {
+        let operations = vec![OperationProto::default(); CHUNKS_PER_FRAME + 1];
+        let mut sent = Vec::new();
+        send_chunked(&mut sent, operations).unwrap();
+
+        let mut reader = sent.as_slice();
+        let mut batches = Vec::new();
+        while !reader.is_empty() {
+            match read_message(&mut reader).unwrap() {
+                ServerMessage::OperationBatch(batch) => {
+                    batches.push((batch.operations.len(), batch.more))
+                }
+                other => panic!("expected OperationBatch, got {:?}", other),
+            }
+        }
+        assert_eq!(batches, [(CHUNKS_PER_FRAME, true), (1, false)]);
+    }
+
+    #[test]
+    fn test_pings_are_answered_while_the_owner_is_busy() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let served = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "x".repeat(1 << 20),
+                version: 1,
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            let ping = ServerMessage::Sequenced(2, Box::new(ServerMessage::Ping(7)));
+            write_message(&mut stream, &ping).unwrap();
+            read_message(&mut stream).unwrap()
+        });
+
+        // Nothing is read from the connection until the Pong is back
+        let mut connection = Connection::open(
+            &ConnectOptions {
+                server,
+                ..Default::default()
+            },
+            "client",
+        )
+        .unwrap();
+        assert!(matches!(served.join().unwrap(), ServerMessage::Pong(7)));
+        let received = connection.next_message().unwrap();
+        assert!(matches!(received.message, ServerMessage::SyncDocument(_)));
+    }
+
+    /// Answers a Hello for `path` with a sync of `content` under `doc_id`.
+    fn serve_sync(listener: std::net::TcpListener, doc_id: &str, content: &str) -> TcpStream {
+        let (mut stream, _) = listener.accept().unwrap();
+        let path = match read_message(&mut stream).unwrap() {
+            ServerMessage::Hello(hello) => hello.doc_path,
+            other => panic!("expected Hello, got {:?}", other),
+        };
+        let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+            doc_id: doc_id.to_string(),
+            path,
+            content: content.to_string(),
+            version: 1,
+            ..Default::default()
+        });
+        write_message(&mut stream, &ServerMessage::Sequenced(1, Box::new(sync))).unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_redirects_move_the_connection_and_keep_the_handles() {
+        let old = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let new = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let (server, address) = (
+            old.local_addr().unwrap().to_string(),
+            new.local_addr().unwrap().to_string(),
+        );
+        let redirect_to = address.clone();
+        thread::spawn(move || {
+            let mut stream = serve_sync(old, "old-id", "before");
+            let redirect = ServerMessage::Redirect(RedirectProto {
+                address: redirect_to,
+                ..Default::default()
+            });
+            write_message(
+                &mut stream,
+                &ServerMessage::Sequenced(2, Box::new(redirect)),
+            )
+            .unwrap();
+            // Held open: the client leaves on its own
+            let _ = read_message(&mut stream);
+        });
+        let moved = thread::spawn(move || serve_sync(new, "new-id", "after"));
+
+        let mut connection = Connection::open(
+            &ConnectOptions {
+                server,
+                doc_path: "notes.txt".to_string(),
+                ..Default::default()
+            },
+            "client",
+        )
+        .unwrap();
+        let handle = connection.handle();
+        connection.next_message().unwrap();
+        assert_eq!(handle.snapshot().doc_id, "old-id");
+
+        let received = connection.next_message().unwrap();
+        assert!(matches!(received.message, ServerMessage::Redirect(_)));
+        assert!(received.document.is_none());
+        // The new server numbers its frames from 1 again
+        let received = connection.next_message().unwrap();
+        assert!(received.document.unwrap().is_same(&handle));
+        let snapshot = handle.snapshot();
+        assert_eq!(
+            (snapshot.doc_id.as_str(), snapshot.path.as_str()),
+            ("new-id", "notes.txt")
+        );
+        assert_eq!(snapshot.content, "after");
+        assert_eq!(connection.options.server, address);
+        drop(moved.join().unwrap());
+    }
+
+    #[test]
+    fn test_being_turned_away_is_an_error_saying_why() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let hello = match read_message(&mut stream).unwrap() {
+                ServerMessage::Hello(hello) => hello,
+                other => panic!("expected Hello, got {:?}", other),
+            };
+            assert_eq!(hello.protocol_version, PROTOCOL_VERSION);
+            assert_eq!(hello.client_version, CLIENT_VERSION);
+            let diagnostics = ServerMessage::Diagnostics(DiagnosticsProto {
+                kind: DiagnosticKind::ProtocolTooOld as i32,
+                message: "The client speaks protocol 1".to_string(),
+                guidance: "Upgrade the client".to_string(),
+                protocol_version: 2,
+                min_protocol_version: 2,
+                server_version: "dist-space-server/0.2.0".to_string(),
+                ..Default::default()
+            });
+            write_message(&mut stream, &diagnostics).unwrap();
+            let _ = read_message(&mut stream);
+        });
+
+        let mut connection = Connection::open(
+            &ConnectOptions {
+                server,
+                ..Default::default()
+            },
+            "client",
+        )
+        .unwrap();
+        let Err(error) = connection.next_message() else {
+            panic!("expected the Diagnostics to end the connection");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert!(
+            error.to_string().starts_with(
+                "The client speaks protocol 1. Upgrade the client (server: dist-space-server/0.2.0, protocol 2, accepting 2 or later"
+            ),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    env,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+use common::ids::IdGenerator;
+
+/// Editor used when `$VISUAL` and `$EDITOR` are unset.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// A directory only we can use, removed with everything in it when dropped.
+struct PrivateDir(PathBuf);
+
+impl PrivateDir {
+    /// Creates a fresh one in the system temp directory. Its name is random
+    /// and it must not exist yet, so nobody can have put anything in it.
+    fn create() -> io::Result<Self> {
+        // Random even when the process's ids are seeded
+        let name = format!("dist-space-{}", IdGenerator::random().uuid());
+        let path = env::temp_dir().join(name);
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes `content` to a temporary file, opens it in the user's editor and
+/// returns the saved contents once the editor exits. The file is readable by
+/// the user alone, and is gone again however this returns.
+pub fn edit(content: &str, doc_id: &str) -> io::Result<String> {
+    let dir = PrivateDir::create()?;
+    // The id comes from the server; keep it from naming another directory
+    let name: String = doc_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let path = dir.0.join(format!("{}.txt", name));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&path)?.write_all(content.as_bytes())?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    // Allow editors configured with arguments, e.g. `code --wait`.
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(DEFAULT_EDITOR);
+
+    let status = Command::new(program).args(parts).arg(&path).status();
+    match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        ))),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("failed to launch {}: {}", program, e),
+        )),
+    }
+}
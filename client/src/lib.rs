@@ -0,0 +1,18 @@
+//! Client library: connections to a Dist-Space server and handles to open documents.
+
+pub mod connection;
+pub use connection::{
+    ConnectOptions, Connection, ConnectionHandle, DocumentHandle, DocumentSnapshot, Rebased,
+    Received,
+};
+
+mod optimistic;
+
+pub mod session;
+pub use session::{Event, EventKind, HandleId, Reactor, Session};
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+pub mod bot;
+pub use bot::{Bot, BotDocument};
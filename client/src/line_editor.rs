@@ -0,0 +1,315 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of remembered history entries.
+const MAX_HISTORY: usize = 500;
+
+/// The line currently being edited. Shared with `Printer` so asynchronous
+/// output can clear the prompt, print, and redraw it without interleaving.
+#[derive(Default)]
+struct EditLine {
+    active: bool,
+    /// While set (e.g. an external editor owns the terminal), output is held back.
+    suspended: bool,
+    held: Vec<String>,
+    prompt: String,
+    buffer: Vec<char>,
+    cursor: usize,
+}
+
+impl EditLine {
+    fn redraw(&self, out: &mut impl Write) -> io::Result<()> {
+        let text: String = self.buffer.iter().collect();
+        write!(out, "\r\x1b[2K{}{}", self.prompt, text)?;
+        let back = self.buffer.len() - self.cursor;
+        if back > 0 {
+            write!(out, "\x1b[{}D", back)?;
+        }
+        out.flush()
+    }
+}
+
+/// Prints lines without corrupting an in-progress prompt.
+#[derive(Clone)]
+pub struct Printer {
+    line: Arc<Mutex<EditLine>>,
+}
+
+impl Printer {
+    pub fn println(&self, message: &str) {
+        let mut line = self.line.lock().unwrap();
+        if line.suspended {
+            line.held.push(message.to_string());
+            return;
+        }
+        let mut out = io::stdout().lock();
+        if line.active {
+            let _ = write!(out, "\r\x1b[2K{}\n", message);
+            let _ = line.redraw(&mut out);
+        } else {
+            let _ = writeln!(out, "{}", message);
+        }
+    }
+
+    /// Holds back output until `resume`, so nothing is drawn over another program.
+    pub fn suspend(&self) {
+        self.line.lock().unwrap().suspended = true;
+    }
+
+    /// Prints everything held back while suspended.
+    pub fn resume(&self) {
+        let held = {
+            let mut line = self.line.lock().unwrap();
+            line.suspended = false;
+            std::mem::take(&mut line.held)
+        };
+        for message in held {
+            self.println(&message);
+        }
+    }
+}
+
+/// Outcome of reading a single line.
+pub enum ReadLine {
+    Line(String),
+    /// Ctrl-C: discard the current line.
+    Interrupted,
+    /// Ctrl-D on an empty line, or end of input.
+    Eof,
+}
+
+/// A minimal readline replacement: cursor movement, history, and tab completion.
+/// Falls back to plain buffered reads when stdin is not a terminal.
+pub struct LineEditor {
+    line: Arc<Mutex<EditLine>>,
+    history: Vec<String>,
+    completions: Arc<Mutex<Vec<String>>>,
+    interactive: bool,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            line: Arc::new(Mutex::new(EditLine::default())),
+            history: Vec::new(),
+            completions: Arc::new(Mutex::new(Vec::new())),
+            interactive: unsafe { libc::isatty(libc::STDIN_FILENO) == 1 },
+        }
+    }
+
+    pub fn printer(&self) -> Printer {
+        Printer {
+            line: Arc::clone(&self.line),
+        }
+    }
+
+    /// Shared list of words offered by tab completion (commands, document paths).
+    pub fn completions(&self) -> Arc<Mutex<Vec<String>>> {
+        Arc::clone(&self.completions)
+    }
+
+    pub fn add_history(&mut self, entry: &str) {
+        if entry.trim().is_empty() || self.history.last().map(String::as_str) == Some(entry) {
+            return;
+        }
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(entry.to_string());
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine> {
+        if !self.interactive {
+            return self.read_line_plain(prompt);
+        }
+
+        let _raw = RawMode::enable()?;
+        {
+            let mut line = self.line.lock().unwrap();
+            line.active = true;
+            line.prompt = prompt.to_string();
+            line.buffer.clear();
+            line.cursor = 0;
+            line.redraw(&mut io::stdout())?;
+        }
+
+        let result = self.edit_loop();
+
+        let mut line = self.line.lock().unwrap();
+        line.active = false;
+        println!();
+        result
+    }
+
+    fn read_line_plain(&self, prompt: &str) -> io::Result<ReadLine> {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(ReadLine::Eof);
+        }
+        Ok(ReadLine::Line(
+            input.trim_end_matches(['\r', '\n']).to_string(),
+        ))
+    }
+
+    fn edit_loop(&self) -> io::Result<ReadLine> {
+        let mut stdin = io::stdin().lock();
+        // Index into history while browsing with Up/Down; `history.len()` means the live line.
+        let mut history_index = self.history.len();
+        let mut live_line: Vec<char> = Vec::new();
+        let mut utf8 = Vec::new();
+
+        loop {
+            let mut byte = [0u8; 1];
+            if stdin.read(&mut byte)? == 0 {
+                return Ok(ReadLine::Eof);
+            }
+
+            let mut line = self.line.lock().unwrap();
+            match byte[0] {
+                b'\r' | b'\n' => return Ok(ReadLine::Line(line.buffer.iter().collect())),
+                3 => return Ok(ReadLine::Interrupted),
+                4 if line.buffer.is_empty() => return Ok(ReadLine::Eof),
+                // Ctrl-A / Ctrl-E
+                1 => line.cursor = 0,
+                5 => line.cursor = line.buffer.len(),
+                // Backspace
+                8 | 127 => {
+                    if line.cursor > 0 {
+                        line.cursor -= 1;
+                        let cursor = line.cursor;
+                        line.buffer.remove(cursor);
+                    }
+                }
+                b'\t' => self.complete(&mut line),
+                // Escape sequences: arrows
+                27 => {
+                    drop(line);
+                    let mut seq = [0u8; 2];
+                    stdin.read_exact(&mut seq)?;
+                    let mut line = self.line.lock().unwrap();
+                    match seq {
+                        [b'[', b'D'] => line.cursor = line.cursor.saturating_sub(1),
+                        [b'[', b'C'] => line.cursor = (line.cursor + 1).min(line.buffer.len()),
+                        [b'[', b'H'] => line.cursor = 0,
+                        [b'[', b'F'] => line.cursor = line.buffer.len(),
+                        [b'[', b'A'] if history_index > 0 => {
+                            if history_index == self.history.len() {
+                                live_line = line.buffer.clone();
+                            }
+                            history_index -= 1;
+                            line.buffer = self.history[history_index].chars().collect();
+                            line.cursor = line.buffer.len();
+                        }
+                        [b'[', b'B'] if history_index < self.history.len() => {
+                            history_index += 1;
+                            line.buffer = match self.history.get(history_index) {
+                                Some(entry) => entry.chars().collect(),
+                                None => live_line.clone(),
+                            };
+                            line.cursor = line.buffer.len();
+                        }
+                        _ => {}
+                    }
+                    line.redraw(&mut io::stdout())?;
+                    continue;
+                }
+                b if b < 32 => {}
+                b => {
+                    // Accumulate multi-byte UTF-8 sequences before inserting.
+                    utf8.push(b);
+                    match std::str::from_utf8(&utf8) {
+                        Ok(s) => {
+                            for c in s.chars() {
+                                let cursor = line.cursor;
+                                line.buffer.insert(cursor, c);
+                                line.cursor += 1;
+                            }
+                            utf8.clear();
+                        }
+                        Err(e) if e.error_len().is_some() => utf8.clear(),
+                        Err(_) => {}
+                    }
+                }
+            }
+            line.redraw(&mut io::stdout())?;
+        }
+    }
+
+    /// Completes the word under the cursor: command names for the first word,
+    /// registered completion words for arguments.
+    fn complete(&self, line: &mut EditLine) {
+        let before: String = line.buffer[..line.cursor].iter().collect();
+        let word_start = before.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let prefix = &before[word_start..];
+
+        let completions = self.completions.lock().unwrap();
+        let candidates: Vec<&str> = if word_start == 0 {
+            crate::commands::COMMAND_NAMES.to_vec()
+        } else {
+            completions.iter().map(String::as_str).collect()
+        };
+        let matches: Vec<&str> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(prefix))
+            .collect();
+
+        let completion = match matches.as_slice() {
+            [] => return,
+            [single] => format!("{} ", single),
+            many => common_prefix(many),
+        };
+
+        for c in completion.chars().skip(prefix.chars().count()) {
+            let cursor = line.cursor;
+            line.buffer.insert(cursor, c);
+            line.cursor += 1;
+        }
+    }
+}
+
+fn common_prefix(words: &[&str]) -> String {
+    let mut prefix: String = words[0].to_string();
+    for word in &words[1..] {
+        while !word.starts_with(&prefix) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+/// Puts the terminal into non-canonical, no-echo mode until dropped.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
@@ -1,202 +1,1266 @@
 use std::{
-    io::{self, BufReader, Read, Write},
+    collections::VecDeque,
+    fs, io,
     net::TcpStream,
+    path::PathBuf,
     process,
     sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
 
 use common::{
-    protocol::ServerMessage,
-    space::{OperationProto, ReplaceOp, operation_proto::Kind},
+    clock::{self, ClockSample},
+    diff::diff,
+    document::apply_to_text,
+    ids,
+    operation::Operation,
+    protocol::{PROTOCOL_VERSION, ServerMessage},
+    space::{
+        CapabilitiesProto, CheckpointProto, CreateFromTemplateProto, CreateInviteProto, DeleteOp,
+        DiagnosticKind, DisconnectReason, DocumentArchiveProto, ExportDocumentProto, ExportFormat,
+        ExportRequestProto, FetchAttachmentProto, FollowProto, GetHistoryProto, HelloProto,
+        InsertOp, InviteMemberProto, LockRangeProto, OperationProto, PropagationProto,
+        PropagationSampleProto, RemoveMemberProto, ReplaceOp, SetPresenceProto, SetViewportProto,
+        SignalKind, SignalProto, TagVersionProto, TemplateVariableProto, TimeSyncProto,
+        UnlockRangeProto, UploadAttachmentProto, WatchActivityProto, operation_proto::Kind,
+    },
 };
-use uuid::Uuid;
+use prost::Message;
 
+use client::connection::{CLIENT_VERSION, describe_diagnostics, spawn_reader, write_message};
+
+use crate::commands::{Command, USAGE, render_buffer, render_lines, resolve_range};
+use crate::config::ClientConfig;
+use crate::line_editor::{LineEditor, Printer, ReadLine};
 use crate::types::ClientState;
 
+mod commands;
+mod config;
+mod external_editor;
+mod line_editor;
 mod types;
+mod watch;
+
+const PROMPT: &str = "> ";
+const WATCH_PROMPT: &str = "[watching: press Enter to stop] ";
+/// Data sent in each UploadAttachment frame, well under the frame size limit.
+const ATTACHMENT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Write half of the server connection; replaced in place when reconnecting.
+type SharedStream = Arc<Mutex<TcpStream>>;
 
 fn main() {
-    let stream = TcpStream::connect("127.0.0.1:8000");
+    let config = match ClientConfig::from_env() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let client_id = ids::new_uuid().to_string();
+
+    let (stream, writer) = match connect(&config, &client_id) {
+        Ok(streams) => streams,
+        Err(e) => {
+            eprintln!("Failed to connect to server {}: {}", config.server, e);
+            return;
+        }
+    };
+    let writer: SharedStream = Arc::new(Mutex::new(writer));
 
     // Use SyncDocumentProto instead of Document for shared state
-    let client_id = Uuid::new_v4().to_string();
     let state = Arc::new(Mutex::new(ClientState {
-        client_id,
+        client_id: client_id.clone(),
         doc_id: String::new(),
         version: 0,
         buffer: String::new(),
+        activity: VecDeque::new(),
+        // Read-only observers start directly in watch mode and never submit edits.
+        watching: config.watch,
+        retry_after: None,
+        disconnect_reason: None,
+        clock_offset_ms: 0,
+        clock_sample: None,
+        show_clock: false,
+        traces: Vec::new(),
+        pending_export: None,
+        pending_download: None,
+        pending_fetch: None,
+        redirect: None,
     }));
 
-    let state_clone = Arc::clone(&state);
+    let editor = LineEditor::new();
+    let printer = editor.printer();
+    let completions = editor.completions();
+
+    // Spawn reader thread
+    let reader_config = config.clone();
+    let reader_writer = Arc::clone(&writer);
+    let reader_state = Arc::clone(&state);
+    thread::spawn(move || {
+        run_reader(
+            stream,
+            reader_config,
+            &client_id,
+            reader_writer,
+            reader_state,
+            printer,
+            completions,
+        );
+    });
+
+    // Run CLI loop in main thread
+    let result = if config.watch {
+        watch_loop(editor)
+    } else {
+        cli_loop(writer, state, editor)
+    };
+    if let Err(e) = result {
+        eprintln!("CLI loop error: {}", e);
+    }
+}
+
+/// Connects to the server and sends the Hello handshake.
+/// Returns the read half and a cloned write half of the connection.
+fn connect(config: &ClientConfig, client_id: &str) -> io::Result<(TcpStream, TcpStream)> {
+    let stream = TcpStream::connect(&config.server)?;
+    let mut writer = stream.try_clone()?;
+
+    let hello = ServerMessage::Hello(HelloProto {
+        client_id: client_id.to_string(),
+        display_name: config.display_name.clone(),
+        doc_path: config.doc_path.clone().unwrap_or_default(),
+        auth_token: config.auth_token.clone().unwrap_or_default(),
+        client_time_ms: clock::unix_time_ms(),
+        read_only: config.watch,
+        // Reconnecting resyncs anyway; sequencing is left to library users
+        sequenced: false,
+        capabilities: Some(CapabilitiesProto {
+            batches: true,
+            presence: true,
+            ..CapabilitiesProto::default()
+        }),
+        workspace: config.workspace.clone().unwrap_or_default(),
+        protocol_version: PROTOCOL_VERSION,
+        client_version: CLIENT_VERSION.to_string(),
+    });
+    write_message(&mut writer, &hello)?;
+    write_message(&mut writer, &time_sync(None))?;
+
+    Ok((stream, writer))
+}
+
+/// A time sync request, reporting `sample` for the server's stats.
+fn time_sync(sample: Option<ClockSample>) -> ServerMessage {
+    let sample = sample.unwrap_or_default();
+    ServerMessage::TimeSync(TimeSyncProto {
+        client_send_ms: clock::unix_time_ms(),
+        rtt_ms: sample.rtt_ms,
+        offset_ms: sample.offset_ms,
+        ..TimeSyncProto::default()
+    })
+}
+
+/// Runs the reader loop, reconnecting according to the configured policy when
+/// the connection drops. Exits the process once the policy gives up.
+fn run_reader(
+    mut stream: TcpStream,
+    mut config: ClientConfig,
+    client_id: &str,
+    writer: SharedStream,
+    state: Arc<Mutex<ClientState>>,
+    printer: Printer,
+    completions: Arc<Mutex<Vec<String>>>,
+) {
+    loop {
+        if let Err(e) = reader_loop(
+            stream,
+            &writer,
+            Arc::clone(&state),
+            printer.clone(),
+            Arc::clone(&completions),
+        ) {
+            printer.println(&format!("Reader thread error: {}", e));
+        }
+
+        let reason = state.lock().unwrap().disconnect_reason.take();
+        if matches!(
+            reason,
+            Some(
+                DisconnectReason::Kicked
+                    | DisconnectReason::ProtocolViolation
+                    | DisconnectReason::NotAMember
+                    | DisconnectReason::Incompatible
+            )
+        ) {
+            eprintln!("Not reconnecting: the server closed this session deliberately.");
+            process::exit(1);
+        }
 
-    match stream {
-        Ok(stream) => {
-            let stream_clone = match stream.try_clone() {
-                Ok(stream) => stream,
+        // A server handing over names its replacement: go there, without
+        // waiting the first time, and stay there
+        let redirect = state.lock().unwrap().redirect.take();
+        let mut redirected = redirect.is_some();
+        if let Some(server) = redirect {
+            config.server = server;
+        }
+
+        let mut failed = 0;
+        stream = loop {
+            if !config.reconnect.should_retry(failed) {
+                eprintln!("Exiting application due to socket error.");
+                process::exit(1);
+            }
+            let hint = state.lock().unwrap().retry_after.take();
+            if !std::mem::take(&mut redirected) {
+                thread::sleep(hint.map_or(config.reconnect_delay, |hint| {
+                    hint.max(config.reconnect_delay)
+                }));
+            }
+            match connect(&config, client_id) {
+                Ok((stream, new_writer)) => {
+                    *writer.lock().unwrap() = new_writer;
+                    printer.println(&format!("Reconnected to {}", config.server));
+                    break stream;
+                }
                 Err(e) => {
-                    eprintln!("Failed to clone stream: {}", e);
-                    return;
+                    failed += 1;
+                    printer.println(&format!("Reconnect attempt {} failed: {}", failed, e));
                 }
-            };
+            }
+        };
+    }
+}
 
-            // Spawn reader thread
-            thread::spawn(move || {
-                if let Err(e) = reader_loop(stream, state_clone) {
-                    eprintln!("\nReader thread error: {}", e);
-                    eprintln!("Exiting application due to socket error.");
-                    process::exit(1);
-                }
-            });
+/// Applies the server's echo of one of our operations to the local buffer when it
+/// landed exactly on our version. Otherwise a sync has already covered it (or
+/// will), so it is dropped.
+fn apply_own_operation(state: &mut ClientState, op: OperationProto) {
+    if op.server_version != state.version {
+        return;
+    }
+    let Some(kind) = Operation::convert_operation(op) else {
+        return;
+    };
+    if apply_to_text(&mut state.buffer, &kind).is_ok() {
+        state.version += 1;
+    }
+}
 
-            // Run CLI loop in main thread
-            if let Err(e) = cli_loop(stream_clone, Arc::clone(&state)) {
-                eprintln!("CLI loop error: {}", e);
-            }
-        }
-        Err(e) => {
-            eprintln!("Failed to connect to server: {}", e)
+/// Records a collaborator's operation in the activity feed, or reconciles the
+/// echo of one of ours.
+fn handle_operation(
+    state: &Mutex<ClientState>,
+    printer: &Printer,
+    op: OperationProto,
+    received_ms: u64,
+) {
+    let mut current_state = state.lock().unwrap();
+    let trace = (clock::is_traced(op.server_version) && op.applied_at_ms > 0).then(|| {
+        PropagationSampleProto {
+            doc_id: op.doc_id.clone(),
+            server_version: op.server_version,
+            applied_at_ms: op.applied_at_ms,
+            received_ms,
+            displayed_ms: 0,
         }
+    });
+    if op.client_id == current_state.client_id {
+        // Echo of our own edit as the server transformed it
+        apply_own_operation(&mut current_state, op);
+    } else {
+        let entry = watch::describe_operation(&op, current_state.clock_offset_ms);
+        show_activity(&mut current_state, printer, entry);
+    }
+    if let Some(trace) = trace {
+        current_state.traces.push(PropagationSampleProto {
+            displayed_ms: clock::unix_time_ms(),
+            ..trace
+        });
+    }
+}
+
+/// Adds `entry` to the activity feed and shows it, redrawing the watch view
+/// if it is open.
+fn show_activity(state: &mut ClientState, printer: &Printer, entry: String) {
+    watch::record_activity(state, entry.clone());
+    if state.watching {
+        printer.println(&watch::render(state));
+    } else {
+        printer.println(&entry);
     }
 }
 
-fn reader_loop(stream: TcpStream, state: Arc<Mutex<ClientState>>) -> io::Result<()> {
-    let mut reader = BufReader::new(stream);
+fn reader_loop(
+    stream: TcpStream,
+    writer: &SharedStream,
+    state: Arc<Mutex<ClientState>>,
+    printer: Printer,
+    completions: Arc<Mutex<Vec<String>>>,
+) -> io::Result<()> {
+    // Pings are answered on the reader's own thread, so rendering a large
+    // sync here can't make us miss the heartbeat window
+    let frames = spawn_reader(stream, Arc::clone(writer));
 
     loop {
-        // Read 4 bytes (big-endian u32) -> N (payload length)
-        let mut len_bytes = [0u8; 4];
-        reader.read_exact(&mut len_bytes)?;
-        let payload_length = u32::from_be_bytes(len_bytes) as usize;
-
-        // Read exactly N bytes -> payload
-        let mut payload_buffer = vec![0u8; payload_length];
-        reader.read_exact(&mut payload_buffer)?;
-
-            match ServerMessage::decode(&*payload_buffer) {
-            Ok(message) => match message {
-                ServerMessage::Operation(_) => {
-                    println!("Received an Operation message.");
-                }
-                ServerMessage::SyncDocument(doc) => {
-                    println!("Received a SyncDocument message.");
-
-                    // Update shared state
-                    let mut current_state = state.lock().unwrap();
-                    current_state.buffer = doc.content.clone();
-                    current_state.version = doc.version;
-
-                    // Store doc_id upon initial sync
-                    if current_state.doc_id.is_empty() && !doc.doc_id.is_empty() {
-                        current_state.doc_id = doc.doc_id.clone();
-                    }
+        let message = match frames.recv().map_err(|_| {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "Reader thread stopped")
+        })? {
+            Ok(message) => message,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                printer.println(&format!("Failed to decode protobuf message: {}", e));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
-                    // Print short summary
-                    let content_preview = doc.content.chars().take(80).collect::<String>();
-                    println!(
-                        "\n[SYNC] version={} doc_id={} content='{}...'",
-                        doc.version, doc.doc_id, content_preview
-                    );
+        let received_ms = clock::unix_time_ms();
+        match message {
+            ServerMessage::Operation(op) => handle_operation(&state, &printer, op, received_ms),
+            ServerMessage::OperationBatch(batch) => {
+                for op in batch.operations {
+                    handle_operation(&state, &printer, op, received_ms);
+                }
+            }
+            ServerMessage::SyncDocument(doc) => {
+                // Update shared state
+                let mut current_state = state.lock().unwrap();
+                current_state.buffer = doc.content.clone();
+                current_state.version = doc.version;
+                if doc.server_time_ms > 0 && current_state.clock_sample.is_none() {
+                    current_state.clock_offset_ms =
+                        doc.server_time_ms as i64 - clock::unix_time_ms() as i64;
+                }
 
-                    print!("\nEnter command (put/send/quit): ");
-                    io::stdout().flush()?;
+                // Store doc_id upon initial sync (and again if a reconnect lands elsewhere)
+                if current_state.doc_id != doc.doc_id && !doc.doc_id.is_empty() {
+                    current_state.doc_id = doc.doc_id.clone();
+                    completions.lock().unwrap().push(doc.doc_id.clone());
                 }
-                ServerMessage::Ping(seq) => {
-                    // Server is checking if we're alive - respond with Pong
-                    // Note: We'd need access to the write stream here to respond
-                    // For now, just log it. The proper solution is to share the writer
-                    // between threads or use a channel.
-                    println!("[Heartbeat] Received ping #{}", seq);
+
+                if current_state.watching {
+                    printer.println(&watch::render(&current_state));
+                    continue;
                 }
-                ServerMessage::Pong(_seq) => {
-                    // We sent a ping (unusual for client), server responded
-                    // Just ignore
+
+                // Print short summary
+                let content_preview = doc.content.chars().take(80).collect::<String>();
+                printer.println(&format!(
+                    "[SYNC] version={} doc_id={} content='{}...'",
+                    doc.version, doc.doc_id, content_preview
+                ));
+            }
+            ServerMessage::Ping(_seq) => {
+                // Already answered by the reader thread
+            }
+            ServerMessage::Pong(_seq) => {
+                // We sent a ping (unusual for client), server responded
+                // Just ignore
+            }
+            ServerMessage::Error(error) => {
+                let mut line = format!("[ERROR] {}: {}", error.code().as_str_name(), error.message);
+                if error.retry_after_ms > 0 {
+                    line.push_str(&format!(" (retry after {}ms)", error.retry_after_ms));
+                    state.lock().unwrap().retry_after =
+                        Some(Duration::from_millis(error.retry_after_ms));
+                }
+                printer.println(&line);
+            }
+            ServerMessage::Disconnect(disconnect) => {
+                let reason = disconnect.reason_code();
+                printer.println(&format!(
+                    "[DISCONNECTED] {}: {}",
+                    reason.as_str_name(),
+                    disconnect.message
+                ));
+                state.lock().unwrap().disconnect_reason = Some(reason);
+            }
+            ServerMessage::Redirect(redirect) => {
+                printer.println(&format!(
+                    "[REDIRECT] {}; reconnecting to {}",
+                    redirect.message, redirect.address
+                ));
+                state.lock().unwrap().redirect = Some(redirect.address);
+            }
+            ServerMessage::Activity(activity) => {
+                let mut current_state = state.lock().unwrap();
+                for entry in watch::describe_activity(&activity) {
+                    show_activity(&mut current_state, &printer, entry);
                 }
-            },
-            Err(e) => {
-                eprintln!("\nFailed to decode protobuf message: {}", e);
+            }
+            ServerMessage::Diagnostics(diagnostics) => {
+                let tag = match diagnostics.kind() {
+                    DiagnosticKind::UnknownMessage => "DIAGNOSTICS",
+                    _ => "INCOMPATIBLE",
+                };
+                printer.println(&format!("[{}] {}", tag, describe_diagnostics(&diagnostics)));
+            }
+            ServerMessage::DocumentArchive(archive) => {
+                let Some(path) = state.lock().unwrap().pending_export.take() else {
+                    printer.println("Ignoring unrequested DocumentArchive");
+                    continue;
+                };
+                match fs::write(&path, archive.encode_to_vec()) {
+                    Ok(()) => printer.println(&format!(
+                        "[EXPORT] '{}' version {} with {} operation(s) and {} milestone(s) saved to {}",
+                        archive.path,
+                        archive.version,
+                        archive.operations.len(),
+                        archive.milestones.len(),
+                        path.display()
+                    )),
+                    Err(e) => printer.println(&format!(
+                        "Failed to write export to {}: {}",
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+            ServerMessage::ExportChunk(chunk) => {
+                let mut state = state.lock().unwrap();
+                let Some((_, data)) = &mut state.pending_download else {
+                    printer.println("Ignoring unrequested ExportChunk");
+                    continue;
+                };
+                data.extend_from_slice(&chunk.data);
+                if chunk.more {
+                    continue;
+                }
+                let Some((path, data)) = state.pending_download.take() else {
+                    continue;
+                };
+                match fs::write(&path, &data) {
+                    Ok(()) => printer.println(&format!(
+                        "[DOWNLOAD] '{}' version {} ({} bytes) saved to {}",
+                        chunk.file_name,
+                        chunk.version,
+                        data.len(),
+                        path.display()
+                    )),
+                    Err(e) => printer.println(&format!(
+                        "Failed to write download to {}: {}",
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+            ServerMessage::Attachment(attachment) => {
+                printer.println(&format!(
+                    "[ATTACH] {} bytes stored as {}; reference it in the document as attachment:{}",
+                    attachment.size, attachment.attachment_id, attachment.attachment_id
+                ));
+            }
+            ServerMessage::AttachmentChunk(chunk) => {
+                let mut state = state.lock().unwrap();
+                let Some((_, data)) = &mut state.pending_fetch else {
+                    printer.println("Ignoring unrequested AttachmentChunk");
+                    continue;
+                };
+                data.extend_from_slice(&chunk.data);
+                if chunk.more {
+                    continue;
+                }
+                let Some((path, data)) = state.pending_fetch.take() else {
+                    continue;
+                };
+                match fs::write(&path, &data) {
+                    Ok(()) => printer.println(&format!(
+                        "[FETCH] Attachment {} ({} bytes) saved to {}",
+                        chunk.attachment_id,
+                        data.len(),
+                        path.display()
+                    )),
+                    Err(e) => printer.println(&format!(
+                        "Failed to write attachment to {}: {}",
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+            ServerMessage::Viewport(viewport) => {
+                let state = state.lock().unwrap();
+                let last_line = viewport.first_line + viewport.line_count.max(1);
+                printer.println(&format!(
+                    "[FOLLOW] {} is viewing lines {}-{} of '{}'",
+                    viewport.client_id,
+                    viewport.first_line + 1,
+                    last_line,
+                    viewport.path
+                ));
+                if viewport.doc_id == state.doc_id {
+                    printer.println(&render_lines(
+                        &state.buffer,
+                        viewport.first_line as usize,
+                        viewport.line_count as usize,
+                    ));
+                }
+            }
+            ServerMessage::RangeLocks(locks) => {
+                let held = locks
+                    .locks
+                    .iter()
+                    .map(|lock| {
+                        format!(
+                            "#{} {}..{} by {}",
+                            lock.lock_id, lock.start, lock.end, lock.client_id
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                printer.println(&format!(
+                    "[LOCKS] version={} doc_id={}: {}",
+                    locks.version,
+                    locks.doc_id,
+                    if held.is_empty() {
+                        "none".to_string()
+                    } else {
+                        held.join(", ")
+                    }
+                ));
+            }
+            ServerMessage::Signal(signal) => {
+                let range = format!("{}..{}", signal.start, signal.end);
+                printer.println(&match signal.kind() {
+                    SignalKind::RaiseHand if signal.start == signal.end => {
+                        format!("[HAND] {} raised a hand", signal.client_id)
+                    }
+                    SignalKind::RaiseHand => {
+                        format!("[HAND] {} raised a hand about {}", signal.client_id, range)
+                    }
+                    SignalKind::PointAtRange => {
+                        format!("[POINT] {} points at {}", signal.client_id, range)
+                    }
+                    SignalKind::Reaction => format!(
+                        "[REACT] {} reacted {} to {}",
+                        signal.client_id, signal.emoji, range
+                    ),
+                    SignalKind::Unspecified => {
+                        format!("[SIGNAL] {} signalled {}", signal.client_id, range)
+                    }
+                });
+            }
+            ServerMessage::Overlays(overlays) => {
+                printer.println(&format!(
+                    "[OVERLAYS] version={} doc_id={}: {}",
+                    overlays.version,
+                    overlays.doc_id,
+                    overlays.overlays.len()
+                ));
+            }
+            ServerMessage::Presence(presence) => {
+                let entry = watch::describe_presence(&presence);
+                show_activity(&mut state.lock().unwrap(), &printer, entry);
+            }
+            ServerMessage::Capabilities(_) => {
+                // Everything the server might leave out, we handle anyway
+            }
+            ServerMessage::Invite(invite) => {
+                let access = if invite.read_only { "view" } else { "edit" };
+                let minutes = invite.expires_at_ms.saturating_sub(clock::unix_time_ms()) / 60_000;
+                printer.println(&format!(
+                    "[GUEST] token {} lets a guest {} '{}' in workspace '{}' for about {} minute(s)",
+                    invite.token, access, invite.path, invite.workspace, minutes
+                ));
+            }
+            ServerMessage::Checkpoint(checkpoint) => {
+                let line = match (&checkpoint.milestone, checkpoint.commit.as_str()) {
+                    (Some(milestone), _) => format!(
+                        "[CHECKPOINT] Squashed {} version(s) into milestone '{}' at version {}",
+                        milestone.squashed, milestone.label, milestone.version
+                    ),
+                    (None, "") => "[CHECKPOINT] Nothing changed since the last commit".to_string(),
+                    (None, commit) => format!(
+                        "[CHECKPOINT] Committed {} (contributors: {})",
+                        commit,
+                        match checkpoint.contributors.is_empty() {
+                            true => "none".to_string(),
+                            false => checkpoint.contributors.join(", "),
+                        }
+                    ),
+                };
+                printer.println(&line);
+            }
+            ServerMessage::History(history) if history.doc_id.is_empty() => {
+                let mut lines = vec![format!("[EVENTS] {} event(s)", history.events.len())];
+                lines.extend(
+                    history
+                        .events
+                        .iter()
+                        .map(|event| format!("  #{} {}", event.seq, watch::describe_event(event))),
+                );
+                printer.println(&lines.join("\n"));
+            }
+            ServerMessage::History(history) => {
+                let mut lines = vec![format!(
+                    "[HISTORY] '{}' version {}, edits kept from version {}",
+                    history.path, history.version, history.first_version
+                )];
+                lines.extend(history.milestones.iter().map(|milestone| {
+                    format!(
+                        "  milestone '{}' at version {} ({} version(s) squashed)",
+                        milestone.label, milestone.version, milestone.squashed
+                    )
+                }));
+                lines.extend(history.tags.iter().map(|tag| {
+                    format!(
+                        "  tag '{}' at version {} by {}",
+                        tag.name, tag.version, tag.client_id
+                    )
+                }));
+                if !history.tag.is_empty() {
+                    lines.push(format!("At tag '{}':", history.tag));
+                    lines.push(render_buffer(&history.content));
+                }
+                printer.println(&lines.join("\n"));
+            }
+            ServerMessage::Freeze(freeze) => {
+                let target = match freeze.path.as_str() {
+                    "" => "every document".to_string(),
+                    path => format!("'{}'", path),
+                };
+                let line = match (freeze.frozen, freeze.ends_at_ms) {
+                    (true, 0) => format!("[FREEZE] {} is read-only until unfrozen", target),
+                    (true, ends_at_ms) => format!(
+                        "[FREEZE] {} is read-only for {}s",
+                        target,
+                        ends_at_ms.saturating_sub(clock::unix_time_ms()) / 1000
+                    ),
+                    (false, _) => format!("[FREEZE] {} may be edited again", target),
+                };
+                printer.println(&line);
+            }
+            ServerMessage::Notice(notice) => {
+                printer.println(&format!("[NOTICE] {}", notice.message));
+            }
+            ServerMessage::TimeSync(sync) => {
+                let sample = ClockSample::measure(
+                    sync.client_send_ms,
+                    sync.server_receive_ms,
+                    sync.server_send_ms,
+                    clock::unix_time_ms(),
+                );
+                let mut current_state = state.lock().unwrap();
+                current_state.clock_sample = Some(sample);
+                current_state.clock_offset_ms = sample.offset_ms;
+                if std::mem::take(&mut current_state.show_clock) {
+                    printer.println(&format!(
+                        "[CLOCK] round trip {}ms; the server's clock is {:+}ms off ours",
+                        sample.rtt_ms, sample.offset_ms
+                    ));
+                }
+                drop(current_state);
+                // The request carried no sample, so report this one
+                if sync.rtt_ms == 0 && sync.offset_ms == 0 {
+                    write_message(&mut *writer.lock().unwrap(), &time_sync(Some(sample)))?;
+                }
+            }
+            ServerMessage::MemberToken(token) => {
+                printer.println(&format!(
+                    "[MEMBER] '{}' may now join workspace '{}' with token {}",
+                    token.member, token.workspace, token.token
+                ));
+            }
+            ServerMessage::Hello(_)
+            | ServerMessage::OpenDocument(_)
+            | ServerMessage::CloseDocument(_)
+            | ServerMessage::ExportDocument(_)
+            | ServerMessage::ExportRequest(_)
+            | ServerMessage::UploadAttachment(_)
+            | ServerMessage::FetchAttachment(_)
+            | ServerMessage::SetViewport(_)
+            | ServerMessage::Follow(_)
+            | ServerMessage::TagVersion(_)
+            | ServerMessage::GetHistory(_)
+            | ServerMessage::CreateFromTemplate(_)
+            | ServerMessage::LockRange(_)
+            | ServerMessage::UnlockRange(_)
+            | ServerMessage::SetPresence(_)
+            | ServerMessage::Resend(_)
+            | ServerMessage::Credit(_)
+            | ServerMessage::SetOverlays(_)
+            | ServerMessage::SetDocumentSettings(_)
+            | ServerMessage::InviteMember(_)
+            | ServerMessage::RemoveMember(_)
+            | ServerMessage::CreateInvite(_)
+            | ServerMessage::Propagation(_)
+            | ServerMessage::WatchActivity(_)
+            | ServerMessage::Ack(_) => {
+                // Client-to-server only
+            }
+            ServerMessage::Sequenced(..) => {
+                // Only sent to connections that ask for it in their Hello
             }
         }
+
+        let samples = std::mem::take(&mut state.lock().unwrap().traces);
+        if !samples.is_empty() {
+            write_message(
+                &mut *writer.lock().unwrap(),
+                &ServerMessage::Propagation(PropagationProto { samples }),
+            )?;
+        }
     }
 }
 
-fn cli_loop(mut stream: TcpStream, state: Arc<Mutex<ClientState>>) -> io::Result<()> {
-    let stdin = io::stdin();
-    let mut command_buffer = String::new();
+fn cli_loop(
+    stream: SharedStream,
+    state: Arc<Mutex<ClientState>>,
+    mut editor: LineEditor,
+) -> io::Result<()> {
+    println!("{}", USAGE);
 
     loop {
-        command_buffer.clear();
-        print!("\nEnter command (put/send/quit): ");
-        io::stdout().flush()?;
-        stdin.read_line(&mut command_buffer)?;
-        let command = command_buffer.trim();
-
-        match command {
-            "quit" => {
-                println!("Closing socket and exiting.");
-                break;
-            }
-            "put" | "send" => {
-                // Lock state to read doc_id and version
-                let current_state = state.lock().unwrap();
-                let doc_id = current_state.doc_id.clone();
-                let client_version = current_state.version;
-                let client_id = current_state.client_id.clone();
-                let current_buffer_len = current_state.buffer.len();
-                drop(current_state); // Unlock state quickly
-
-                if doc_id.is_empty() {
-                    println!("Cannot 'put' yet. Awaiting initial SyncDocument from server...");
+        let command_buffer = match editor.read_line(PROMPT)? {
+            ReadLine::Line(line) => line,
+            ReadLine::Interrupted => continue,
+            ReadLine::Eof => "quit".to_string(),
+        };
+        if command_buffer.trim().is_empty() {
+            continue;
+        }
+        editor.add_history(&command_buffer);
+
+        let command = match Command::parse(&command_buffer) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                println!("{}", USAGE);
+                continue;
+            }
+        };
+
+        // Lock state to read doc_id, version and buffer
+        let current_state = state.lock().unwrap();
+        let doc_id = current_state.doc_id.clone();
+        let client_version = current_state.version;
+        let client_id = current_state.client_id.clone();
+        let buffer = current_state.buffer.clone();
+        drop(current_state); // Unlock state quickly
+
+        if command == Command::Quit {
+            println!("Closing socket and exiting.");
+            break;
+        }
+
+        if command == Command::Show {
+            println!("[version {}]", client_version);
+            println!("{}", render_buffer(&buffer));
+            continue;
+        }
+
+        if command == Command::Watch {
+            let watch_activity = |watch| {
+                let message = ServerMessage::WatchActivity(WatchActivityProto { watch });
+                write_message(&mut *stream.lock().unwrap(), &message)
+            };
+            watch_activity(true)?;
+            set_watching(&state, &editor.printer(), true);
+            editor.read_line(WATCH_PROMPT)?;
+            set_watching(&state, &editor.printer(), false);
+            watch_activity(false)?;
+            continue;
+        }
+
+        if let Command::Save(path) = &command {
+            match fs::write(path, &buffer) {
+                Ok(()) => println!("Saved version {} to {}", client_version, path),
+                Err(e) => println!("Failed to save {}: {}", path, e),
+            }
+            continue;
+        }
+
+        if let Command::Import(path) = &command {
+            let archive = match fs::read(path) {
+                Ok(bytes) => DocumentArchiveProto::decode(bytes.as_slice())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+                Err(e) => Err(e),
+            };
+            match archive {
+                Ok(archive) => {
+                    println!(
+                        "Importing '{}' version {} from {}...",
+                        archive.path, archive.version, path
+                    );
+                    write_message(
+                        &mut *stream.lock().unwrap(),
+                        &ServerMessage::DocumentArchive(archive),
+                    )?;
+                }
+                Err(e) => println!("Failed to read archive {}: {}", path, e),
+            }
+            continue;
+        }
+
+        if let Command::New {
+            template,
+            path,
+            variables,
+        } = command
+        {
+            let variables = variables
+                .into_iter()
+                .map(|(name, value)| TemplateVariableProto { name, value })
+                .collect();
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::CreateFromTemplate(CreateFromTemplateProto {
+                    template,
+                    path,
+                    variables,
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Away(away) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::SetPresence(SetPresenceProto { away }),
+            )?;
+            match away {
+                true => println!("Marked away."),
+                false => println!("Welcome back."),
+            }
+            continue;
+        }
+
+        if let Command::Invite(member) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::InviteMember(InviteMemberProto { member }),
+            )?;
+            continue;
+        }
+
+        if let Command::Uninvite(member) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::RemoveMember(RemoveMemberProto { member }),
+            )?;
+            continue;
+        }
+
+        if let Command::Guest {
+            path,
+            minutes,
+            edit,
+        } = command
+        {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::CreateInvite(CreateInviteProto {
+                    path,
+                    read_only: !edit,
+                    ttl_ms: minutes * 60_000,
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Follow(leader) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::Follow(FollowProto {
+                    client_id: leader.clone(),
+                }),
+            )?;
+            match leader.is_empty() {
+                true => println!("Stopped following"),
+                false => println!("Following {}", leader),
+            }
+            continue;
+        }
+
+        if let Command::Checkpoint(message) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::Checkpoint(CheckpointProto {
+                    message,
+                    ..Default::default()
+                }),
+            )?;
+            continue;
+        }
+
+        if doc_id.is_empty() {
+            println!("Cannot edit yet. Awaiting initial SyncDocument from server...");
+            continue;
+        }
+
+        if let Command::Export(path) = command {
+            state.lock().unwrap().pending_export = Some(PathBuf::from(path));
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::ExportDocument(ExportDocumentProto { doc_id }),
+            )?;
+            continue;
+        }
+
+        if let Command::Download { path, html } = command {
+            state.lock().unwrap().pending_download = Some((PathBuf::from(path), Vec::new()));
+            let format = match html {
+                true => ExportFormat::Html,
+                false => ExportFormat::Plain,
+            };
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::ExportRequest(ExportRequestProto {
+                    doc_id,
+                    format: format as i32,
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::View {
+            first_line,
+            line_count,
+        } = command
+        {
+            println!(
+                "{}",
+                render_lines(&buffer, first_line as usize - 1, line_count as usize)
+            );
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::SetViewport(SetViewportProto {
+                    doc_id,
+                    first_line: first_line - 1,
+                    line_count,
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Attach(path) = command {
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("Failed to read {}: {}", path, e);
                     continue;
                 }
+            };
+            let mut stream = stream.lock().unwrap();
+            let mut chunks = data.chunks(ATTACHMENT_CHUNK_BYTES).peekable();
+            if chunks.peek().is_none() {
+                println!("{} is empty", path);
+                continue;
+            }
+            while let Some(chunk) = chunks.next() {
+                write_message(
+                    &mut *stream,
+                    &ServerMessage::UploadAttachment(UploadAttachmentProto {
+                        doc_id: doc_id.clone(),
+                        data: chunk.to_vec(),
+                        more: chunks.peek().is_some(),
+                    }),
+                )?;
+            }
+            continue;
+        }
+
+        if let Command::Fetch {
+            attachment_id,
+            path,
+        } = command
+        {
+            state.lock().unwrap().pending_fetch = Some((PathBuf::from(path), Vec::new()));
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::FetchAttachment(FetchAttachmentProto {
+                    doc_id,
+                    attachment_id,
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Tag(name) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::TagVersion(TagVersionProto { doc_id, name }),
+            )?;
+            continue;
+        }
+
+        if command == Command::Clock {
+            let mut current_state = state.lock().unwrap();
+            current_state.show_clock = true;
+            let sample = current_state.clock_sample;
+            drop(current_state);
+            write_message(&mut *stream.lock().unwrap(), &time_sync(sample))?;
+            continue;
+        }
 
+        if let Command::History(tag) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::GetHistory(GetHistoryProto {
+                    doc_id,
+                    tag,
+                    ..Default::default()
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Events(events_after) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::GetHistory(GetHistoryProto {
+                    events_after,
+                    ..Default::default()
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Milestone(label) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::Checkpoint(CheckpointProto {
+                    message: label,
+                    doc_id,
+                    ..Default::default()
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Lock { start, end } = command {
+            match resolve_range(start, end, &buffer) {
+                Ok((start, end)) => write_message(
+                    &mut *stream.lock().unwrap(),
+                    &ServerMessage::LockRange(LockRangeProto { doc_id, start, end }),
+                )?,
+                Err(e) => println!("{}", e),
+            }
+            continue;
+        }
+
+        let signal = match &command {
+            Command::Hand(range) => Some((SignalKind::RaiseHand, *range, String::new())),
+            Command::Point { start, end } => Some((
+                SignalKind::PointAtRange,
+                Some((*start, *end)),
+                String::new(),
+            )),
+            Command::React { emoji, start, end } => {
+                Some((SignalKind::Reaction, Some((*start, *end)), emoji.clone()))
+            }
+            _ => None,
+        };
+        if let Some((kind, range, emoji)) = signal {
+            let (start, end) = match range.map(|(start, end)| resolve_range(start, end, &buffer)) {
+                None => (0, 0),
+                Some(Ok(range)) => range,
+                Some(Err(e)) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::Signal(SignalProto {
+                    doc_id,
+                    version: client_version,
+                    kind: kind as i32,
+                    start,
+                    end,
+                    emoji,
+                    ..Default::default()
+                }),
+            )?;
+            continue;
+        }
+
+        if let Command::Unlock(lock_id) = command {
+            write_message(
+                &mut *stream.lock().unwrap(),
+                &ServerMessage::UnlockRange(UnlockRangeProto { doc_id, lock_id }),
+            )?;
+            continue;
+        }
+
+        let op_kinds = match command {
+            Command::Edit => {
+                let printer = editor.printer();
+                printer.suspend();
+                let edited = external_editor::edit(&buffer, &doc_id);
+                printer.resume();
+                match edited {
+                    Ok(new_content) => diff_ops(&buffer, &new_content, &client_id, client_version),
+                    Err(e) => {
+                        println!("Edit aborted: {}", e);
+                        continue;
+                    }
+                }
+            }
+            Command::Load(path) => match fs::read_to_string(&path) {
+                Ok(new_content) => diff_ops(&buffer, &new_content, &client_id, client_version),
+                Err(e) => {
+                    println!("Failed to load {}: {}", path, e);
+                    continue;
+                }
+            },
+            Command::Put => {
                 // Prompt user for new text
                 println!("Enter full new document text (press Enter twice to finish input):");
                 let mut new_content = String::new();
-                loop {
-                    let mut line = String::new();
-                    stdin.read_line(&mut line)?;
+                while let ReadLine::Line(line) = editor.read_line("... ")? {
                     if line.trim().is_empty() {
                         break;
                     }
                     new_content.push_str(&line);
+                    new_content.push('\n');
                 }
 
-                let op_kind = Kind::Replace(ReplaceOp {
+                vec![Kind::Replace(ReplaceOp {
                     start: 0,
-                    end: current_buffer_len as u32,
-                    text: new_content.clone(),
+                    end: buffer.len() as u32,
+                    text: new_content,
                     client_id: client_id.clone(),
                     client_version,
-                });
-
-                let operation = OperationProto {
-                    op_id: Uuid::new_v4().as_u64_pair().0,
-                    kind: Some(op_kind),
-                    doc_id,
-                    client_id,
-                    client_version,
-                    server_version: 0,
-                    new_content,
-                };
-                // Create ServerMessage containing the operation
-                let server_message = ServerMessage::Operation(operation);
-                let encoded = server_message.encode();
-                let len_bytes = (encoded.len() as u32).to_be_bytes();
-
-                // Send bytes to server
-                stream.write_all(&len_bytes)?;
-                stream.write_all(&encoded)?;
-                stream.flush()?;
-
-                println!(
-                    "Sent Operation to server. Waiting for server confirmation (SyncDocument update)..."
-                );
-            }
-            _ => {
-                println!("Unknown command: {}", command);
+                })]
             }
+            Command::Insert { at, text } => match at.resolve(&buffer) {
+                Ok(index) => vec![Kind::Insert(InsertOp {
+                    index: index as u32,
+                    text,
+                    client_id: client_id.clone(),
+                    client_version,
+                })],
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            },
+            Command::Delete { start, end } => match resolve_range(start, end, &buffer) {
+                Ok((start, end)) => vec![Kind::Delete(DeleteOp {
+                    start,
+                    end,
+                    client_id: client_id.clone(),
+                    client_version,
+                })],
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            },
+            Command::Replace { start, end, text } => match resolve_range(start, end, &buffer) {
+                Ok((start, end)) => vec![Kind::Replace(ReplaceOp {
+                    start,
+                    end,
+                    text,
+                    client_id: client_id.clone(),
+                    client_version,
+                })],
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            },
+            Command::Quit
+            | Command::Show
+            | Command::View { .. }
+            | Command::Follow(_)
+            | Command::Watch
+            | Command::Save(_)
+            | Command::Export(_)
+            | Command::Download { .. }
+            | Command::Attach(_)
+            | Command::Fetch { .. }
+            | Command::Import(_)
+            | Command::New { .. }
+            | Command::Lock { .. }
+            | Command::Unlock(_)
+            | Command::Hand(_)
+            | Command::Point { .. }
+            | Command::React { .. }
+            | Command::Away(_)
+            | Command::Invite(_)
+            | Command::Uninvite(_)
+            | Command::Guest { .. }
+            | Command::Checkpoint(_)
+            | Command::Milestone(_)
+            | Command::Tag(_)
+            | Command::History(_)
+            | Command::Events(_)
+            | Command::Clock => unreachable!(),
+        };
+
+        if op_kinds.is_empty() {
+            println!("No changes to send.");
+            continue;
         }
+
+        let count = op_kinds.len();
+        for op_kind in op_kinds {
+            send_operation(
+                &stream,
+                op_kind,
+                doc_id.clone(),
+                client_id.clone(),
+                client_version,
+            )?;
+        }
+
+        println!(
+            "Sent {} operation(s) to server. Waiting for server confirmation (SyncDocument update)...",
+            count
+        );
     }
     Ok(())
 }
+
+/// Wire operations turning `old` into `new`, all based on `client_version`.
+fn diff_ops(old: &str, new: &str, client_id: &str, client_version: u64) -> Vec<Kind> {
+    diff(old, new, client_id, client_version)
+        .iter()
+        .map(|op| op.to_proto())
+        .collect()
+}
+
+/// Read-only observer loop for `--watch`: keeps rendering until Ctrl-C or end of input.
+fn watch_loop(mut editor: LineEditor) -> io::Result<()> {
+    loop {
+        match editor.read_line(WATCH_PROMPT)? {
+            ReadLine::Line(_) => continue,
+            ReadLine::Interrupted | ReadLine::Eof => return Ok(()),
+        }
+    }
+}
+
+fn set_watching(state: &Arc<Mutex<ClientState>>, printer: &Printer, watching: bool) {
+    let mut current_state = state.lock().unwrap();
+    current_state.watching = watching;
+    if watching {
+        printer.println(&watch::render(&current_state));
+    }
+}
+
+fn send_operation(
+    stream: &SharedStream,
+    op_kind: Kind,
+    doc_id: String,
+    client_id: String,
+    client_version: u64,
+) -> io::Result<()> {
+    let operation = OperationProto {
+        op_id: ids::new_op_id(),
+        kind: Some(op_kind),
+        doc_id,
+        client_id,
+        client_version,
+        server_version: 0,
+        new_content: String::new(),
+        // Stamped by the server when applied
+        applied_at_ms: 0,
+        applied_mono_ms: 0,
+        global_version: 0,
+    };
+    // Create ServerMessage containing the operation
+    let server_message = ServerMessage::Operation(operation);
+    write_message(&mut *stream.lock().unwrap(), &server_message)
+}
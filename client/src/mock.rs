@@ -0,0 +1,233 @@
+//! A scriptable in-memory server for testing code built on the client
+//! library without sockets. Hand a `MockServer` to `Session::with_dialer`
+//! (or `Connection::open_with`), then `accept` each connection the client
+//! makes: queue frames for it with `send` and check what it sent with
+//! `expect`. Redirects dial the same mock server, at the new address.
+//!
+//! Built for this crate's tests, and for others with the `mock` feature.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::protocol::ServerMessage;
+
+use crate::connection::{Dialer, Link, read_message, write_message};
+
+/// How long `accept` and `expect` wait before failing the test.
+pub const MOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bytes written to one end of a pipe and not yet read from the other.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct PipeState {
+    bytes: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.condvar.notify_all();
+    }
+
+    fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.bytes.extend(buf);
+        self.condvar.notify_all();
+        Ok(buf.len())
+    }
+
+    /// Waits for bytes until `timeout`, if any. Reads nothing once closed
+    /// and drained.
+    fn read(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        while state.bytes.is_empty() && !state.closed {
+            state = match deadline {
+                None => self.condvar.wait(state).unwrap(),
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    self.condvar.wait_timeout(state, remaining).unwrap().0
+                }
+            };
+        }
+        let n = buf.len().min(state.bytes.len());
+        for (byte, read) in buf.iter_mut().zip(state.bytes.drain(..n)) {
+            *byte = read;
+        }
+        Ok(n)
+    }
+}
+
+struct PipeReader {
+    pipe: Arc<Pipe>,
+    timeout: Option<Duration>,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.pipe.read(buf, self.timeout)
+    }
+}
+
+/// The client's send half: writes to the server, and closes both ways.
+struct PipeLink {
+    to_server: Arc<Pipe>,
+    to_client: Arc<Pipe>,
+}
+
+impl Write for PipeLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.to_server.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Link for PipeLink {
+    fn close(&self) {
+        self.to_server.close();
+        self.to_client.close();
+    }
+}
+
+/// Connections dialed but not yet accepted, oldest first.
+#[derive(Default)]
+pub struct MockServer {
+    dialed: Mutex<VecDeque<MockPeer>>,
+    condvar: Condvar,
+}
+
+impl MockServer {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// The next connection the client made, waiting for it if need be.
+    /// Panics after `MOCK_TIMEOUT`.
+    pub fn accept(&self) -> MockPeer {
+        let dialed = self.dialed.lock().unwrap();
+        let (mut dialed, _) = self
+            .condvar
+            .wait_timeout_while(dialed, MOCK_TIMEOUT, |dialed| dialed.is_empty())
+            .unwrap();
+        dialed.pop_front().expect("the client never connected")
+    }
+}
+
+impl Dialer for MockServer {
+    fn dial(&self, address: &str) -> io::Result<(Box<dyn Read + Send>, Box<dyn Link>)> {
+        let to_server = Arc::new(Pipe::default());
+        let to_client = Arc::new(Pipe::default());
+        self.dialed.lock().unwrap().push_back(MockPeer {
+            address: address.to_string(),
+            to_client: Arc::clone(&to_client),
+            from_client: PipeReader {
+                pipe: Arc::clone(&to_server),
+                timeout: Some(MOCK_TIMEOUT),
+            },
+        });
+        self.condvar.notify_all();
+        let reader = PipeReader {
+            pipe: Arc::clone(&to_client),
+            timeout: None,
+        };
+        Ok((
+            Box::new(reader),
+            Box::new(PipeLink {
+                to_server,
+                to_client,
+            }),
+        ))
+    }
+}
+
+/// The server's end of one connection.
+pub struct MockPeer {
+    address: String,
+    to_client: Arc<Pipe>,
+    from_client: PipeReader,
+}
+
+impl MockPeer {
+    /// The address the client dialed.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Queues `message` for the client to read.
+    pub fn send(&self, message: &ServerMessage) {
+        let mut encoded = Vec::new();
+        write_message(&mut encoded, message).unwrap();
+        let _ = self.to_client.write(&encoded);
+    }
+
+    /// The next frame the client sent. Panics if none comes within
+    /// `MOCK_TIMEOUT` or the client hung up.
+    pub fn expect(&mut self) -> ServerMessage {
+        match read_message(&mut self.from_client) {
+            Ok(message) => message,
+            Err(e) => panic!("expected a frame from the client: {}", e),
+        }
+    }
+
+    /// Hangs up, as a server going away would.
+    pub fn disconnect(&self) {
+        self.to_client.close();
+        self.from_client.pipe.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{ConnectOptions, Connection};
+    use common::space::SyncDocumentProto;
+
+    #[test]
+    fn test_frames_go_both_ways_until_disconnected() {
+        let server = MockServer::new();
+        let options = ConnectOptions {
+            server: "mock".to_string(),
+            doc_path: "notes.txt".to_string(),
+            ..Default::default()
+        };
+        let mut connection = Connection::open_with(&options, "client", server.clone()).unwrap();
+        let mut peer = server.accept();
+        assert_eq!(peer.address(), "mock");
+        match peer.expect() {
+            ServerMessage::Hello(hello) => assert_eq!(hello.doc_path, "notes.txt"),
+            other => panic!("expected Hello, got {:?}", other),
+        }
+
+        peer.send(&ServerMessage::SyncDocument(SyncDocumentProto {
+            doc_id: "doc".to_string(),
+            path: "notes.txt".to_string(),
+            content: "hello".to_string(),
+            version: 1,
+            ..Default::default()
+        }));
+        connection.next_message().unwrap();
+        assert_eq!(connection.handle().snapshot().content, "hello");
+
+        peer.disconnect();
+        assert!(connection.next_message().is_err());
+    }
+}
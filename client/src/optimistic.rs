@@ -0,0 +1,110 @@
+//! Optimistic local echo: with `ConnectOptions::optimistic`, our edits show
+//! in a document's snapshot as soon as they are sent, and are settled
+//! against what the server makes of them.
+
+use std::collections::VecDeque;
+
+use common::{document::apply_to_text, operation::OperationKind};
+
+/// Our edits the server has not answered yet, on top of the text it last
+/// confirmed.
+#[derive(Debug, Default)]
+pub(crate) struct Pending {
+    /// The document as of the last sync and the echoes since.
+    confirmed: String,
+    /// Oldest first, by op id, each written against the text the ones
+    /// before it leave.
+    ops: VecDeque<(u64, OperationKind)>,
+}
+
+impl Pending {
+    pub(crate) fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub(crate) fn contains(&self, op_id: u64) -> bool {
+        self.ops.iter().any(|(id, _)| *id == op_id)
+    }
+
+    /// The confirmed text with the pending ops applied in order. An op that
+    /// no longer fits, because a collaborator's edit changed the text under
+    /// it, is left out until the server answers for it.
+    pub(crate) fn view(&self) -> String {
+        let mut view = self.confirmed.clone();
+        for (_, op) in &self.ops {
+            let mut applied = view.clone();
+            if apply_to_text(&mut applied, op).is_ok() {
+                view = applied;
+            }
+        }
+        view
+    }
+
+    pub(crate) fn push(&mut self, op_id: u64, op: OperationKind) {
+        self.ops.push_back((op_id, op));
+    }
+
+    /// Swaps the newest pending op for `op`, which it has been combined into.
+    pub(crate) fn replace_last(&mut self, op: OperationKind) {
+        if let Some((_, last)) = self.ops.back_mut() {
+            *last = op;
+        }
+    }
+
+    /// Takes the server's text, after a sync.
+    pub(crate) fn confirm(&mut self, content: &str) {
+        self.confirmed = content.to_string();
+    }
+
+    /// Applies an op the server applied at the confirmed version. Returns
+    /// false if it does not fit, in which case the next sync covers it.
+    pub(crate) fn apply(&mut self, op: &OperationKind) -> bool {
+        apply_to_text(&mut self.confirmed, op).is_ok()
+    }
+
+    /// Stops predicting `op_id`: the server has applied it, or refused it.
+    /// Returns false if it was not pending.
+    pub(crate) fn remove(&mut self, op_id: u64) -> bool {
+        match self.ops.iter().position(|(id, _)| *id == op_id) {
+            Some(index) => self.ops.remove(index).is_some(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_settles_as_the_server_answers() {
+        let mut pending = Pending::default();
+        pending.confirm("ac");
+        pending.push(1, OperationKind::insert_at(1, "b"));
+        pending.push(2, OperationKind::insert_at(3, "!"));
+        assert_eq!(pending.view(), "abc!");
+
+        // A collaborator's edit lands first; ours came back moved past it
+        assert!(pending.apply(&OperationKind::insert_at(0, "x")));
+        assert!(pending.remove(1));
+        assert!(pending.apply(&OperationKind::insert_at(2, "b")));
+        assert_eq!(pending.view(), "xab!c");
+
+        // Refused: rolled back, leaving the confirmed text
+        assert!(pending.remove(2));
+        assert!(!pending.remove(2));
+        assert_eq!(pending.view(), "xabc");
+        assert_eq!(pending.len(), 0);
+    }
+
+    #[test]
+    fn test_ops_that_no_longer_fit_are_left_out() {
+        let mut pending = Pending::default();
+        pending.confirm("abcdef");
+        pending.push(1, OperationKind::insert_at(6, "!"));
+        pending.push(2, OperationKind::delete_range(0, 1));
+        pending.confirm("abc");
+        assert_eq!(pending.view(), "bc");
+        assert!(pending.contains(1));
+    }
+}
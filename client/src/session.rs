@@ -0,0 +1,993 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use common::{ids, protocol::ServerMessage, space::OperationProto};
+
+use crate::connection::{
+    ConnectOptions, Connection, ConnectionHandle, Dialer, DocumentHandle, Rebased, Received,
+    TcpDialer,
+};
+
+/// Identifies one open document within a `Session`.
+pub type HandleId = u64;
+
+/// Something that happened on one of the session's documents, or to the
+/// session as a whole.
+#[derive(Debug)]
+pub struct Event {
+    /// The document; `None` for what is about no document: notices.
+    pub handle: Option<HandleId>,
+    pub kind: EventKind,
+}
+
+#[derive(Debug)]
+pub enum EventKind {
+    /// The document state was replaced; read it via `DocumentHandle::snapshot`.
+    Synced { version: u64 },
+    /// A collaborator's operation, as transformed and applied by the server.
+    RemoteOperation(OperationProto),
+    /// One of our own operations as the server transformed it, with the
+    /// server_version it landed at.
+    Acknowledged(OperationProto),
+    /// One of our operations was transformed through collaborators' edits
+    /// before it applied. Comes just before its `Acknowledged`.
+    Rebased(Rebased),
+    /// The server refused one of our edits, which an optimistic snapshot
+    /// had already shown; it has been taken back out.
+    RolledBack { op_id: u64, message: String },
+    /// The document moved to the server at this address, while others
+    /// open on its connection did not; close it and open it there to carry
+    /// on. When every document moves, the connection follows by itself.
+    Redirected { address: String },
+    /// An announcement from the server's operator, to show the user. It is
+    /// about no document, so comes once per connection, with no handle,
+    /// also once every document on it has been closed.
+    Notice { message: String },
+    /// The connection closed; the handle is no longer usable.
+    Disconnected(String),
+}
+
+/// Tells whatever loop drives a `Session` that `Session::poll` has events
+/// for it. The session has no threads of its own to deliver them on, so a
+/// GUI main loop, an async runtime and a test that polls by hand can all
+/// drive it the same way.
+///
+/// `wake` is called from the connections' reader threads and should only
+/// hand off: post to the GUI loop, notify the task that polls.
+pub trait Reactor: Send + Sync {
+    fn wake(&self);
+}
+
+/// Set when a connection has something, for `next_event` to wait on.
+#[derive(Default)]
+struct Ready {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Ready {
+    fn wake(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+
+    /// Waits for a wake since the last wait, until `deadline` if any.
+    /// Returns false if the deadline passed first.
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            woken = match deadline {
+                None => self.condvar.wait(woken).unwrap(),
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return false;
+                    }
+                    self.condvar.wait_timeout(woken, remaining).unwrap().0
+                }
+            };
+        }
+        *woken = false;
+        true
+    }
+}
+
+/// Handle ids of the documents hosted on one connection.
+type Routes = Mutex<Vec<(HandleId, DocumentHandle)>>;
+
+/// A connection owned by the session and the documents routed over it.
+struct SessionConnection {
+    handle: ConnectionHandle,
+    connection: Mutex<Connection>,
+    routes: Routes,
+    alive: AtomicBool,
+}
+
+/// Manages several open documents, possibly on different servers, and
+/// multiplexes their events into a single stream. Documents on the same
+/// server share one connection.
+pub struct Session {
+    client_id: String,
+    next_id: HandleId,
+    handles: HashMap<HandleId, DocumentHandle>,
+    /// By server and workspace.
+    connections: HashMap<(String, String), SessionConnection>,
+    /// Events read from the connections but not yet polled.
+    events: Mutex<VecDeque<Event>>,
+    ready: Arc<Ready>,
+    reactor: Option<Arc<dyn Reactor>>,
+    dialer: Arc<dyn Dialer>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            client_id: ids::new_uuid().to_string(),
+            next_id: 0,
+            handles: HashMap::new(),
+            connections: HashMap::new(),
+            events: Mutex::new(VecDeque::new()),
+            ready: Arc::new(Ready::default()),
+            reactor: None,
+            dialer: Arc::new(TcpDialer),
+        }
+    }
+
+    /// Has `reactor` woken whenever there are events to `poll`. Set it
+    /// before opening documents.
+    pub fn with_reactor(mut self, reactor: Arc<dyn Reactor>) -> Self {
+        self.reactor = Some(reactor);
+        self
+    }
+
+    /// Reaches servers through `dialer` rather than over TCP.
+    pub fn with_dialer(mut self, dialer: Arc<dyn Dialer>) -> Self {
+        self.dialer = dialer;
+        self
+    }
+
+    /// Opens a document. If the session is already connected to `options.server`
+    /// in `options.workspace` the document is opened over that connection (its name and token were sent
+    /// with the first Hello); otherwise a new connection is made, which wakes
+    /// the session's reactor as messages arrive on it.
+    pub fn open(&mut self, options: &ConnectOptions) -> io::Result<(HandleId, DocumentHandle)> {
+        let id = self.next_id;
+
+        let key = (options.server.clone(), options.workspace.clone());
+        let existing = self
+            .connections
+            .get(&key)
+            .filter(|c| c.alive.load(Ordering::SeqCst));
+        let handle = match existing {
+            Some(connection) => {
+                let handle = connection.handle.open_document(&options.doc_path)?;
+                let mut routes = connection.routes.lock().unwrap();
+                if let Some((existing_id, _)) = routes.iter().find(|(_, h)| h.is_same(&handle)) {
+                    return Ok((*existing_id, handle));
+                }
+                routes.push((id, handle.clone()));
+                handle
+            }
+            None => {
+                let connection =
+                    Connection::open_with(options, &self.client_id, Arc::clone(&self.dialer))?;
+                let handle = connection.handle();
+                let ready = Arc::clone(&self.ready);
+                let reactor = self.reactor.clone();
+                connection.set_waker(Arc::new(move || {
+                    ready.wake();
+                    if let Some(reactor) = &reactor {
+                        reactor.wake();
+                    }
+                }));
+                self.connections.insert(
+                    key,
+                    SessionConnection {
+                        handle: connection.connection_handle(),
+                        connection: Mutex::new(connection),
+                        routes: Mutex::new(vec![(id, handle.clone())]),
+                        alive: AtomicBool::new(true),
+                    },
+                );
+                handle
+            }
+        };
+
+        self.next_id += 1;
+        self.handles.insert(id, handle.clone());
+        Ok((id, handle))
+    }
+
+    /// Reads at most one message from each live connection, queueing the
+    /// events it makes. Returns whether any connection had one.
+    fn read_connections(&self) -> bool {
+        let mut read = false;
+        for connection in self.connections.values() {
+            if !connection.alive.load(Ordering::SeqCst) {
+                continue;
+            }
+            let received = connection.connection.lock().unwrap().try_next_message();
+            let routes = connection.routes.lock().unwrap();
+            let mut events = self.events.lock().unwrap();
+            match received {
+                Ok(None) => continue,
+                Ok(Some(received)) => events.extend(self.route(&routes, received)),
+                Err(e) => {
+                    connection.alive.store(false, Ordering::SeqCst);
+                    events.extend(routes.iter().map(|(id, _)| Event {
+                        handle: Some(*id),
+                        kind: EventKind::Disconnected(e.to_string()),
+                    }));
+                }
+            }
+            read = true;
+        }
+        read
+    }
+
+    /// The events `received` makes for the document it was routed to.
+    fn route(&self, routes: &[(HandleId, DocumentHandle)], received: Received) -> Vec<Event> {
+        let operation_event = |op: OperationProto| {
+            if op.client_id == self.client_id {
+                EventKind::Acknowledged(op)
+            } else {
+                EventKind::RemoteOperation(op)
+            }
+        };
+        let mut rebased = received.rebased.into_iter().peekable();
+        let mut operation_events = |op: OperationProto| {
+            let rebased = rebased
+                .next_if(|rebased| rebased.op_id == op.op_id)
+                .map(EventKind::Rebased);
+            rebased.into_iter().chain([operation_event(op)])
+        };
+        let kinds: Vec<EventKind> = match received.message {
+            ServerMessage::SyncDocument(doc) => vec![EventKind::Synced {
+                version: doc.version,
+            }],
+            ServerMessage::Operation(op) => operation_events(op).collect(),
+            ServerMessage::OperationBatch(batch) => batch
+                .operations
+                .into_iter()
+                .flat_map(operation_events)
+                .collect(),
+            // Only routed to a document when it rolled back an edit
+            ServerMessage::Error(error) => vec![EventKind::RolledBack {
+                op_id: error.op_id,
+                message: error.message,
+            }],
+            // Only routed to a document when it moved without the others
+            ServerMessage::Redirect(redirect) => vec![EventKind::Redirected {
+                address: redirect.address,
+            }],
+            ServerMessage::Notice(notice) => {
+                return vec![Event {
+                    handle: None,
+                    kind: EventKind::Notice {
+                        message: notice.message,
+                    },
+                }];
+            }
+            _ => return Vec::new(),
+        };
+        let handle = received.document.and_then(|document| {
+            routes
+                .iter()
+                .find(|(_, h)| h.is_same(&document))
+                .map(|(id, _)| *id)
+        });
+        let Some(handle) = handle else {
+            return Vec::new();
+        };
+        kinds
+            .into_iter()
+            .map(|kind| Event {
+                handle: Some(handle),
+                kind,
+            })
+            .collect()
+    }
+
+    /// Closes a document, telling the server to stop sending its updates.
+    /// The connection stays open for the session's other documents.
+    pub fn close(&mut self, id: HandleId) -> Option<DocumentHandle> {
+        let handle = self.handles.remove(&id)?;
+        for connection in self.connections.values() {
+            let mut routes = connection.routes.lock().unwrap();
+            if let Some(index) = routes.iter().position(|(route_id, _)| *route_id == id) {
+                routes.remove(index);
+                let _ = connection.handle.close_document(&handle);
+                break;
+            }
+        }
+        Some(handle)
+    }
+
+    pub fn handle(&self, id: HandleId) -> Option<&DocumentHandle> {
+        self.handles.get(&id)
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = (HandleId, &DocumentHandle)> {
+        self.handles.iter().map(|(id, handle)| (*id, handle))
+    }
+
+    /// The next event on an open document, or on the session, if one has
+    /// arrived; never waits. Call it until it returns `None` each time the
+    /// reactor is woken. Events for closed handles are skipped.
+    pub fn poll(&self) -> Option<Event> {
+        loop {
+            let event = self.events.lock().unwrap().pop_front();
+            match event {
+                Some(event) if event.handle.is_none_or(|id| self.handles.contains_key(&id)) => {
+                    return Some(event);
+                }
+                Some(_) => continue,
+                None if self.read_connections() => continue,
+                None => return None,
+            }
+        }
+    }
+
+    /// Blocks until any open document produces an event. `None` once every
+    /// connection has closed.
+    pub fn next_event(&self) -> Option<Event> {
+        self.wait_for_event(None)
+    }
+
+    /// Like `next_event`, but gives up after `timeout`.
+    pub fn next_event_timeout(&self, timeout: Duration) -> Option<Event> {
+        self.wait_for_event(Some(Instant::now() + timeout))
+    }
+
+    fn wait_for_event(&self, deadline: Option<Instant>) -> Option<Event> {
+        loop {
+            if let Some(event) = self.poll() {
+                return Some(event);
+            }
+            let alive = |c: &SessionConnection| c.alive.load(Ordering::SeqCst);
+            if !self.connections.values().any(alive) || !self.ready.wait(deadline) {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{read_message, write_message};
+    use crate::mock::{MockPeer, MockServer};
+    use common::space::{
+        ErrorCode, ErrorProto, InsertOp, NoticeProto, RedirectProto, SyncDocumentProto,
+        operation_proto::Kind,
+    };
+    use std::{
+        net::{TcpListener, TcpStream},
+        sync::mpsc::{self, Sender},
+        thread,
+    };
+
+    /// Accepts one connection, checks the Hello, and sends a sync for `doc_id`.
+    fn serve_one(doc_id: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let hello = read_message(&mut stream).unwrap();
+            assert!(matches!(hello, ServerMessage::Hello(_)));
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: doc_id.to_string(),
+                content: format!("content of {}", doc_id),
+                version: 1,
+                path: String::new(),
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            // Hold the connection open until the client goes away.
+            let _ = read_message(&mut stream);
+        });
+        addr
+    }
+
+    #[test]
+    fn test_events_from_multiple_servers_are_multiplexed() {
+        let mut session = Session::new();
+        let (a, handle_a) = session
+            .open(&ConnectOptions {
+                server: serve_one("doc-a"),
+                ..Default::default()
+            })
+            .unwrap();
+        let (b, handle_b) = session
+            .open(&ConnectOptions {
+                server: serve_one("doc-b"),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut synced = Vec::new();
+        while synced.len() < 2 {
+            let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+            assert!(matches!(event.kind, EventKind::Synced { version: 1 }));
+            synced.push(event.handle.unwrap());
+        }
+        synced.sort();
+        assert_eq!(synced, vec![a, b]);
+        assert_eq!(handle_a.snapshot().content, "content of doc-a");
+        assert_eq!(handle_b.snapshot().content, "content of doc-b");
+    }
+
+    /// Forwards wakes to whoever drives the session, like a GUI loop would.
+    struct ChannelReactor(Mutex<Sender<()>>);
+
+    impl Reactor for ChannelReactor {
+        fn wake(&self) {
+            let _ = self.0.lock().unwrap().send(());
+        }
+    }
+
+    #[test]
+    fn test_reactor_is_woken_for_events_to_poll() {
+        let (woken_tx, woken) = mpsc::channel();
+        let reactor = Arc::new(ChannelReactor(Mutex::new(woken_tx)));
+        let mut session = Session::new().with_reactor(reactor);
+        assert!(session.poll().is_none());
+        let (id, handle) = session
+            .open(&ConnectOptions {
+                server: serve_one("doc"),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Woken once when the waker is set, and again for the sync
+        let mut event = None;
+        while event.is_none() {
+            woken.recv_timeout(Duration::from_secs(5)).unwrap();
+            event = session.poll();
+        }
+        let event = event.unwrap();
+        assert_eq!(event.handle, Some(id));
+        assert!(matches!(event.kind, EventKind::Synced { version: 1 }));
+        assert_eq!(handle.snapshot().content, "content of doc");
+        assert!(session.poll().is_none());
+    }
+
+    #[test]
+    fn test_documents_on_one_server_share_a_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for doc_id in ["doc-a", "doc-b"] {
+                let path = match read_message(&mut stream).unwrap() {
+                    ServerMessage::Hello(hello) => hello.doc_path,
+                    ServerMessage::OpenDocument(open) => open.path,
+                    _ => panic!("expected Hello or OpenDocument"),
+                };
+                let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                    doc_id: doc_id.to_string(),
+                    content: format!("content of {}", path),
+                    version: 1,
+                    path,
+                    ..Default::default()
+                });
+                write_message(&mut stream, &sync).unwrap();
+            }
+            match read_message(&mut stream).unwrap() {
+                ServerMessage::CloseDocument(close) => assert_eq!(close.doc_id, "doc-a"),
+                _ => panic!("expected CloseDocument"),
+            }
+            // A second connection would never be accepted.
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        let open = |path: &str| ConnectOptions {
+            server: server.clone(),
+            doc_path: path.to_string(),
+            ..Default::default()
+        };
+        let (a, handle_a) = session.open(&open("a.txt")).unwrap();
+        let (b, handle_b) = session.open(&open("b.txt")).unwrap();
+        assert_ne!(a, b);
+
+        let mut synced = Vec::new();
+        while synced.len() < 2 {
+            let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+            assert!(matches!(event.kind, EventKind::Synced { version: 1 }));
+            synced.push(event.handle.unwrap());
+        }
+        synced.sort();
+        assert_eq!(synced, vec![a, b]);
+        assert_eq!(handle_a.snapshot().content, "content of a.txt");
+        assert_eq!(handle_b.snapshot().doc_id, "doc-b");
+
+        session.close(a);
+        assert!(session.handle(a).is_none());
+        assert!(session.handle(b).is_some());
+    }
+
+    #[test]
+    fn test_own_operation_echo_is_acknowledged_and_applied() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "ac".to_string(),
+                version: 3,
+                path: "main.txt".to_string(),
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            // Echo the operation back as applied at version 3
+            if let ServerMessage::Operation(mut op) = read_message(&mut stream).unwrap() {
+                op.server_version = 3;
+                write_message(&mut stream, &ServerMessage::Operation(op)).unwrap();
+            }
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        let (id, handle) = session
+            .open(&ConnectOptions {
+                server,
+                ..Default::default()
+            })
+            .unwrap();
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.kind, EventKind::Synced { version: 3 }));
+
+        handle.insert(1, "b").unwrap();
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.handle, Some(id));
+        assert!(matches!(event.kind, EventKind::Acknowledged(_)));
+        assert_eq!(handle.snapshot().content, "abc");
+        assert_eq!(handle.snapshot().version, 4);
+    }
+
+    #[test]
+    fn test_echo_transformed_through_remote_ops_is_reported_as_rebased() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "ac".to_string(),
+                version: 3,
+                path: "main.txt".to_string(),
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            let ServerMessage::Operation(mut op) = read_message(&mut stream).unwrap() else {
+                panic!("expected Operation");
+            };
+            // Someone else's insert at the front applies first, shifting ours
+            let mut remote = op.clone();
+            remote.client_id = "other".to_string();
+            remote.op_id = 1;
+            remote.server_version = 3;
+            if let Some(Kind::Insert(insert)) = &mut remote.kind {
+                insert.index = 0;
+                insert.text = "x".to_string();
+            }
+            write_message(&mut stream, &ServerMessage::Operation(remote)).unwrap();
+            op.server_version = 4;
+            if let Some(Kind::Insert(insert)) = &mut op.kind {
+                insert.index = 2;
+            }
+            write_message(&mut stream, &ServerMessage::Operation(op)).unwrap();
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        let (_, handle) = session
+            .open(&ConnectOptions {
+                server,
+                ..Default::default()
+            })
+            .unwrap();
+        let next = || {
+            session
+                .next_event_timeout(Duration::from_secs(5))
+                .unwrap()
+                .kind
+        };
+        assert!(matches!(next(), EventKind::Synced { version: 3 }));
+
+        handle.insert(1, "b").unwrap();
+        assert!(matches!(next(), EventKind::RemoteOperation(_)));
+        match next() {
+            EventKind::Rebased(rebased) => {
+                assert_eq!(rebased.sent, (1, 1));
+                assert_eq!(rebased.landed, Some((2, 2)));
+                assert_eq!(rebased.skipped_versions, 1);
+            }
+            _ => panic!("expected Rebased"),
+        }
+        assert!(matches!(next(), EventKind::Acknowledged(_)));
+    }
+
+    #[test]
+    fn test_optimistic_edits_show_at_once_and_roll_back_when_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let (checked_tx, checked) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "ac".to_string(),
+                version: 3,
+                path: "main.txt".to_string(),
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            let mut read_op = || match read_message(&mut stream).unwrap() {
+                ServerMessage::Operation(op) => op,
+                _ => panic!("expected Operation"),
+            };
+            let (refused, mut accepted) = (read_op(), read_op());
+            assert_eq!((refused.client_version, accepted.client_version), (3, 4));
+            let error = ServerMessage::Error(ErrorProto {
+                code: ErrorCode::RangeLocked as i32,
+                message: "locked".to_string(),
+                op_id: refused.op_id,
+                ..Default::default()
+            });
+            write_message(&mut stream, &error).unwrap();
+            checked.recv().unwrap();
+            accepted.server_version = 3;
+            if let Some(Kind::Insert(insert)) = &mut accepted.kind {
+                insert.index = 2;
+            }
+            write_message(&mut stream, &ServerMessage::Operation(accepted)).unwrap();
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        let (_, handle) = session
+            .open(&ConnectOptions {
+                server,
+                optimistic: true,
+                ..Default::default()
+            })
+            .unwrap();
+        let next = || {
+            session
+                .next_event_timeout(Duration::from_secs(5))
+                .unwrap()
+                .kind
+        };
+        assert!(matches!(next(), EventKind::Synced { version: 3 }));
+
+        handle.insert(1, "b").unwrap();
+        handle.insert(3, "d").unwrap();
+        let snapshot = handle.snapshot();
+        assert_eq!((snapshot.content.as_str(), snapshot.pending), ("abcd", 2));
+        assert_eq!(snapshot.version, 3);
+
+        // The refused edit is taken back out; the one written on top of it
+        // no longer fits until the server answers for it
+        assert!(matches!(next(), EventKind::RolledBack { .. }));
+        let snapshot = handle.snapshot();
+        assert_eq!((snapshot.content.as_str(), snapshot.pending), ("ac", 1));
+        checked_tx.send(()).unwrap();
+
+        assert!(matches!(next(), EventKind::Acknowledged(_)));
+        let snapshot = handle.snapshot();
+        assert_eq!((snapshot.content.as_str(), snapshot.pending), ("acd", 0));
+        assert_eq!(snapshot.version, 4);
+    }
+
+    #[test]
+    fn test_keystrokes_are_combined_until_flushed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let (flushed_tx, flushed) = mpsc::channel();
+        let served = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "doc".to_string(),
+                content: "x".to_string(),
+                version: 1,
+                ..Default::default()
+            });
+            write_message(&mut stream, &sync).unwrap();
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                if let ServerMessage::Operation(op) = read_message(&mut stream).unwrap() {
+                    received.push((op.client_version, op.kind.unwrap()));
+                    flushed_tx.send(()).unwrap();
+                }
+            }
+            received
+        });
+
+        let mut session = Session::new();
+        let (_, handle) = session
+            .open(&ConnectOptions {
+                server,
+                optimistic: true,
+                flush_interval: Duration::from_millis(50),
+                ..Default::default()
+            })
+            .unwrap();
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.kind, EventKind::Synced { version: 1 }));
+
+        // Typed, with a typo backspaced: sent as one insert once the
+        // interval passes
+        for (index, text) in [(1, "a"), (2, "b"), (3, "z")] {
+            handle.insert(index, text).unwrap();
+        }
+        handle.delete(3, 4).unwrap();
+        assert_eq!(handle.snapshot().content, "xab");
+        assert_eq!(handle.snapshot().pending, 1);
+        flushed.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.replace(0, 1, "y").unwrap();
+
+        let received = served.join().unwrap();
+        let expected_insert = Kind::Insert(InsertOp {
+            index: 1,
+            text: "ab".to_string(),
+            client_id: handle.client_id().to_string(),
+            client_version: 1,
+        });
+        assert_eq!(received[0], (1, expected_insert));
+        assert!(matches!(received[1], (2, Kind::Replace(_))));
+    }
+
+    #[test]
+    fn test_gap_in_sequenced_frames_is_resent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            match read_message(&mut stream).unwrap() {
+                ServerMessage::Hello(hello) => assert!(hello.sequenced),
+                _ => panic!("expected Hello"),
+            }
+            let sync = |seq: u64| {
+                let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                    doc_id: "doc".to_string(),
+                    version: seq,
+                    ..Default::default()
+                });
+                ServerMessage::Sequenced(seq, Box::new(sync))
+            };
+            // #2 goes missing
+            write_message(&mut stream, &sync(1)).unwrap();
+            write_message(&mut stream, &sync(3)).unwrap();
+            write_message(&mut stream, &sync(4)).unwrap();
+            match read_message(&mut stream).unwrap() {
+                ServerMessage::Resend(resend) => assert_eq!(resend.from_seq, 2),
+                _ => panic!("expected Resend"),
+            }
+            for seq in 2..=4 {
+                write_message(&mut stream, &sync(seq)).unwrap();
+            }
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        session
+            .open(&ConnectOptions {
+                server,
+                ..Default::default()
+            })
+            .unwrap();
+        for expected in 1..=4 {
+            let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+            match event.kind {
+                EventKind::Synced { version } => assert_eq!(version, expected),
+                _ => panic!("expected Synced"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_credit_is_granted_as_frames_are_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let credit = |stream: &mut TcpStream| match read_message(stream).unwrap() {
+                ServerMessage::Credit(credit) => credit.frames,
+                _ => panic!("expected Credit"),
+            };
+            assert!(matches!(
+                read_message(&mut stream),
+                Ok(ServerMessage::Hello(_))
+            ));
+            assert_eq!(credit(&mut stream), 4);
+            for version in 1..=2 {
+                let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                    doc_id: "doc".to_string(),
+                    version,
+                    ..Default::default()
+                });
+                write_message(&mut stream, &sync).unwrap();
+            }
+            assert_eq!(credit(&mut stream), 2);
+            let _ = read_message(&mut stream);
+        });
+
+        let mut session = Session::new();
+        session
+            .open(&ConnectOptions {
+                server,
+                credit_window: 4,
+                ..Default::default()
+            })
+            .unwrap();
+        for _ in 0..2 {
+            let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+            assert!(matches!(event.kind, EventKind::Synced { .. }));
+        }
+    }
+
+    /// Opens notes.txt on a mock server, which syncs it as "ac" at version 3.
+    fn open_mock(optimistic: bool) -> (Session, DocumentHandle, MockPeer, Arc<MockServer>) {
+        let server = MockServer::new();
+        let mut session = Session::new().with_dialer(server.clone());
+        let (_, handle) = session
+            .open(&ConnectOptions {
+                server: "first".to_string(),
+                doc_path: "notes.txt".to_string(),
+                optimistic,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut peer = server.accept();
+        assert!(matches!(peer.expect(), ServerMessage::Hello(_)));
+        peer.send(&ServerMessage::SyncDocument(SyncDocumentProto {
+            doc_id: "doc".to_string(),
+            content: "ac".to_string(),
+            version: 3,
+            path: "notes.txt".to_string(),
+            ..Default::default()
+        }));
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.kind, EventKind::Synced { version: 3 }));
+        (session, handle, peer, server)
+    }
+
+    #[test]
+    fn test_notices_come_once_per_connection() {
+        let (mut session, handle, mut peer, _server) = open_mock(false);
+        let (plan, _) = session
+            .open(&ConnectOptions {
+                server: "first".to_string(),
+                doc_path: "plan.txt".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(matches!(peer.expect(), ServerMessage::OpenDocument(_)));
+        peer.send(&ServerMessage::SyncDocument(SyncDocumentProto {
+            doc_id: "plan".to_string(),
+            path: "plan.txt".to_string(),
+            version: 1,
+            ..Default::default()
+        }));
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.handle, Some(plan));
+
+        peer.send(&ServerMessage::Notice(NoticeProto {
+            message: "Maintenance in 5 minutes".to_string(),
+            ..Default::default()
+        }));
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.handle, None);
+        assert!(
+            matches!(event.kind, EventKind::Notice { message } if message == "Maintenance in 5 minutes")
+        );
+        assert!(session.poll().is_none());
+
+        // Still shown with nothing open on the connection
+        let (notes, _) = session.handles().find(|(_, h)| h.is_same(&handle)).unwrap();
+        session.close(notes);
+        session.close(plan);
+        peer.send(&ServerMessage::Notice(NoticeProto {
+            message: "Back soon".to_string(),
+            ..Default::default()
+        }));
+        let event = session.next_event_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.handle, None);
+        assert!(matches!(event.kind, EventKind::Notice { message } if message == "Back soon"));
+    }
+
+    #[test]
+    fn test_pending_edit_is_transformed_and_acknowledged_on_a_mock_server() {
+        let (session, handle, mut peer, _server) = open_mock(true);
+        handle.insert(1, "b").unwrap();
+        assert_eq!(handle.snapshot().content, "abc");
+        let ServerMessage::Operation(mut op) = peer.expect() else {
+            panic!("expected Operation");
+        };
+        assert_eq!(op.client_version, 3);
+
+        // A collaborator's insert at the front lands first
+        let mut remote = op.clone();
+        remote.client_id = "other".to_string();
+        remote.server_version = 3;
+        remote.kind = Some(Kind::Insert(InsertOp {
+            index: 0,
+            text: "x".to_string(),
+            client_id: "other".to_string(),
+            client_version: 3,
+        }));
+        peer.send(&ServerMessage::Operation(remote));
+        op.server_version = 4;
+        if let Some(Kind::Insert(insert)) = &mut op.kind {
+            insert.index = 2;
+        }
+        peer.send(&ServerMessage::Operation(op));
+
+        let next = || {
+            session
+                .next_event_timeout(Duration::from_secs(5))
+                .unwrap()
+                .kind
+        };
+        assert!(matches!(next(), EventKind::RemoteOperation(_)));
+        assert!(matches!(next(), EventKind::Rebased(_)));
+        assert!(matches!(next(), EventKind::Acknowledged(_)));
+        let snapshot = handle.snapshot();
+        assert_eq!((snapshot.content.as_str(), snapshot.pending), ("xabc", 0));
+        assert_eq!(snapshot.version, 5);
+    }
+
+    #[test]
+    fn test_redirected_connection_reconnects_to_the_mock_server_there() {
+        let (session, handle, old, server) = open_mock(false);
+        old.send(&ServerMessage::Redirect(RedirectProto {
+            address: "second".to_string(),
+            ..Default::default()
+        }));
+
+        // The session follows the redirect as it reads, and waits there
+        let (event, mut new) = thread::scope(|scope| {
+            let event = scope.spawn(|| session.next_event_timeout(Duration::from_secs(5)));
+            let mut new = server.accept();
+            assert_eq!(new.address(), "second");
+            match new.expect() {
+                ServerMessage::Hello(hello) => assert_eq!(hello.doc_path, "notes.txt"),
+                other => panic!("expected Hello, got {:?}", other),
+            }
+            new.send(&ServerMessage::SyncDocument(SyncDocumentProto {
+                doc_id: "moved".to_string(),
+                content: "ac".to_string(),
+                version: 7,
+                path: "notes.txt".to_string(),
+                ..Default::default()
+            }));
+            (event.join().unwrap().unwrap(), new)
+        });
+        assert!(matches!(event.kind, EventKind::Synced { version: 7 }));
+        assert_eq!(handle.snapshot().doc_id, "moved");
+
+        // Edits go to the new server
+        handle.insert(0, "z").unwrap();
+        assert!(matches!(new.expect(), ServerMessage::Operation(_)));
+    }
+}
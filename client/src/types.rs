@@ -1,6 +1,41 @@
+use std::{collections::VecDeque, path::PathBuf, time::Duration};
+
+use common::{
+    clock::ClockSample,
+    space::{DisconnectReason, PropagationSampleProto},
+};
+
 pub struct ClientState {
     pub client_id: String,
     pub doc_id: String,
     pub buffer: String,
     pub version: u64,
+    /// Recent remote changes, newest last (bounded by `watch::MAX_ACTIVITY`).
+    pub activity: VecDeque<String>,
+    /// When set, every incoming op/sync redraws the full-screen watch view.
+    pub watching: bool,
+    /// Retry hint from the last server Error; overrides the reconnect delay once.
+    pub retry_after: Option<Duration>,
+    /// Reason from the server's Disconnect frame, if it sent one before closing.
+    pub disconnect_reason: Option<DisconnectReason>,
+    /// Server clock minus local clock in ms, from the latest time sync, or
+    /// roughly from the latest document sync before there is one; used to
+    /// show server timestamps in local time.
+    pub clock_offset_ms: i64,
+    /// Latest time sync exchange with the server.
+    pub clock_sample: Option<ClockSample>,
+    /// Print the next time sync answer (`clock`).
+    pub show_clock: bool,
+    /// Traced ops not yet reported to the server.
+    pub traces: Vec<PropagationSampleProto>,
+    /// Where the next DocumentArchive from the server is written (`export`).
+    pub pending_export: Option<PathBuf>,
+    /// Where the copy asked for with `download` is written, and its chunks
+    /// so far.
+    pub pending_download: Option<(PathBuf, Vec<u8>)>,
+    /// Where the attachment asked for with `fetch` is written, and its
+    /// chunks so far.
+    pub pending_fetch: Option<(PathBuf, Vec<u8>)>,
+    /// Server to reconnect to, from the last server's Redirect.
+    pub redirect: Option<String>,
 }
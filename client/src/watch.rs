@@ -0,0 +1,172 @@
+use chrono::{Local, TimeZone};
+use common::space::{
+    ActivityProto, OperationProto, PresenceProto, PresenceStatus, WorkspaceEventKind,
+    WorkspaceEventProto, operation_proto::Kind,
+};
+
+use crate::commands::render_buffer;
+use crate::types::ClientState;
+
+/// Number of activity entries kept for the watch view.
+pub const MAX_ACTIVITY: usize = 20;
+
+/// Clears the terminal and moves the cursor home.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// One-line description of who changed what range, and when in local time
+/// given the server's `clock_offset_ms`.
+pub fn describe_operation(op: &OperationProto, clock_offset_ms: i64) -> String {
+    let author = short_id(&op.client_id);
+    let change = match &op.kind {
+        Some(Kind::Insert(insert)) => {
+            format!("inserted {:?} at {}", preview(&insert.text), insert.index)
+        }
+        Some(Kind::Delete(delete)) => format!("deleted {}..{}", delete.start, delete.end),
+        Some(Kind::Replace(replace)) => format!(
+            "replaced {}..{} with {:?}",
+            replace.start,
+            replace.end,
+            preview(&replace.text)
+        ),
+        Some(Kind::InsertLine(insert)) => {
+            format!(
+                "inserted line {:?} at {}",
+                preview(&insert.text),
+                insert.index
+            )
+        }
+        Some(Kind::DeleteLine(delete)) => {
+            format!("deleted lines at {}..{}", delete.start, delete.end)
+        }
+        Some(Kind::MoveLine(moved)) => format!(
+            "moved lines at {}..{} to {}",
+            moved.start, moved.end, moved.to
+        ),
+        Some(Kind::Noop(_)) | None => "made no change".to_string(),
+    };
+    let version = op.server_version + 1;
+    let stamp = match local_time(op.applied_at_ms, clock_offset_ms) {
+        Some(time) => format!("{} v{}", time, version),
+        None => format!("v{}", version),
+    };
+    format!("[{}] {} {}", stamp, author, change)
+}
+
+/// One-line description of a collaborator becoming active, idle or away, or
+/// leaving.
+pub fn describe_presence(presence: &PresenceProto) -> String {
+    let who = if presence.display_name.is_empty() || presence.display_name == presence.client_id {
+        short_id(&presence.client_id)
+    } else {
+        &presence.display_name
+    };
+    let status = match presence.status() {
+        PresenceStatus::Active => "is back",
+        PresenceStatus::Idle => "is idle",
+        PresenceStatus::Away => "is away",
+        PresenceStatus::Offline if presence.reason.is_empty() => "left",
+        PresenceStatus::Offline => &format!("was disconnected ({})", presence.reason),
+    };
+    format!("[presence] {} {}", who, status)
+}
+
+/// One-line description of a workspace event, such as a document created or
+/// a member added.
+pub fn describe_event(event: &WorkspaceEventProto) -> String {
+    let by = match event.member.as_str() {
+        "" => String::new(),
+        member => format!(" by '{}'", member),
+    };
+    match event.kind() {
+        WorkspaceEventKind::DocumentCreated => format!("'{}' {}{}", event.path, event.detail, by),
+        WorkspaceEventKind::MemberJoined => format!("'{}' joined", event.member),
+        WorkspaceEventKind::MemberAdded => format!("'{}' became a member", event.member),
+        WorkspaceEventKind::MemberRemoved => format!("'{}' stopped being a member", event.member),
+        WorkspaceEventKind::CheckpointTaken if event.path.is_empty() => {
+            format!("committed {}{}", event.detail, by)
+        }
+        WorkspaceEventKind::CheckpointTaken => {
+            format!("'{}' milestone '{}'{}", event.path, event.detail, by)
+        }
+        WorkspaceEventKind::Unspecified => "unknown event".to_string(),
+    }
+}
+
+/// Activity feed lines for what happened in the workspace lately: its
+/// events, then edits per document, then connections coming and going.
+pub fn describe_activity(activity: &ActivityProto) -> Vec<String> {
+    let events = activity
+        .events
+        .iter()
+        .map(|event| format!("[workspace] {}", describe_event(event)));
+    let edits = activity.edits.iter().map(|edits| {
+        let editors: Vec<&str> = edits.editors.iter().map(|e| short_id(e)).collect();
+        format!(
+            "[workspace] '{}' {} edit(s) by {}, now v{}",
+            edits.path,
+            edits.ops,
+            editors.join(", "),
+            edits.version
+        )
+    });
+    let joined = activity
+        .joined
+        .iter()
+        .map(|who| format!("[workspace] {} joined", short_id(who)));
+    let left = activity
+        .left
+        .iter()
+        .map(|who| format!("[workspace] {} left", short_id(who)));
+    events.chain(edits).chain(joined).chain(left).collect()
+}
+
+/// A server wall-clock timestamp as local `HH:MM:SS`; `None` if it is unset.
+fn local_time(server_ms: u64, clock_offset_ms: i64) -> Option<String> {
+    if server_ms == 0 {
+        return None;
+    }
+    let local_ms = server_ms as i64 - clock_offset_ms;
+    Local
+        .timestamp_millis_opt(local_ms)
+        .single()
+        .map(|time| time.format("%H:%M:%S").to_string())
+}
+
+pub fn record_activity(state: &mut ClientState, entry: String) {
+    if state.activity.len() == MAX_ACTIVITY {
+        state.activity.pop_front();
+    }
+    state.activity.push_back(entry);
+}
+
+/// Full-screen view: document with line numbers followed by the activity feed.
+pub fn render(state: &ClientState) -> String {
+    let mut screen = String::from(CLEAR_SCREEN);
+    screen.push_str(&format!(
+        "== doc {} @ version {} ==\n",
+        state.doc_id, state.version
+    ));
+    screen.push_str(&render_buffer(&state.buffer));
+    screen.push_str("\n\n-- activity --\n");
+    if state.activity.is_empty() {
+        screen.push_str("(none yet)");
+    }
+    let feed: Vec<&str> = state.activity.iter().map(String::as_str).collect();
+    screen.push_str(&feed.join("\n"));
+    screen
+}
+
+fn short_id(client_id: &str) -> &str {
+    client_id.get(..8).unwrap_or(client_id)
+}
+
+fn preview(text: &str) -> String {
+    const MAX_PREVIEW: usize = 24;
+    if text.chars().count() <= MAX_PREVIEW {
+        text.to_string()
+    } else {
+        let mut preview: String = text.chars().take(MAX_PREVIEW).collect();
+        preview.push('…');
+        preview
+    }
+}
@@ -2,11 +2,18 @@ fn main() -> std::io::Result<()> {
     // The output directory is *relative* to the project root
     let out_dir = std::path::PathBuf::from("src/proto");
 
+    // Package space.v1 is written to src/proto/space.v1.rs
     prost_build::Config::new()
         .out_dir(out_dir) // Directs output to src/proto/
         .compile_protos(
-            &["proto/space.proto"], // List of all .proto files
-            &["proto"],                 // The root directory for .proto files
+            &[
+                "proto/space/v1/operations.proto",
+                "proto/space/v1/sync.proto",
+                "proto/space/v1/presence.proto",
+                "proto/space/v1/workspace.proto",
+                "proto/space/v1/admin.proto",
+            ], // List of all .proto files
+            &["proto"], // The root directory for .proto files
         )?;
     Ok(())
 }
@@ -0,0 +1,81 @@
+//! Checked arithmetic on document versions and text positions.
+//!
+//! Versions never wrap. Clients, the op log and resumes all key on them, so
+//! a document at `u64::MAX` has no next version and refuses further ops
+//! instead of reusing old numbers. At a million ops a second that is over
+//! half a million years away; in practice the check only stops forged or
+//! corrupt versions from overflowing.
+//!
+//! Positions are `u32` byte offsets. Shifting one past `u32::MAX` saturates,
+//! and the edit is then refused as out of bounds like any other position
+//! past the end of the document. A range that ends before it starts is an
+//! error, never a wrapped length.
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    #[error("Version {version} can't advance by {by}: versions end at {max}", max = u64::MAX)]
+    Version { version: u64, by: u64 },
+
+    #[error("Range {start}..{end} ends before it starts")]
+    InvertedRange { start: u32, end: u32 },
+}
+
+/// The version after `version`.
+pub fn next_version(version: u64) -> Result<u64, Overflow> {
+    advance(version, 1)
+}
+
+/// The version `by` ops after `version`.
+pub fn advance(version: u64, by: u64) -> Result<u64, Overflow> {
+    version
+        .checked_add(by)
+        .ok_or(Overflow::Version { version, by })
+}
+
+/// The length of `start..end`.
+pub fn span(start: u32, end: u32) -> Result<u32, Overflow> {
+    end.checked_sub(start)
+        .ok_or(Overflow::InvertedRange { start, end })
+}
+
+/// `position` moved right by `by` bytes, stopping at `u32::MAX`.
+pub fn shift(position: u32, by: usize) -> u32 {
+    u32::try_from(by).map_or(u32::MAX, |by| position.saturating_add(by))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_stop_at_the_last_one() {
+        assert_eq!(next_version(0), Ok(1));
+        assert_eq!(next_version(u64::MAX - 1), Ok(u64::MAX));
+        assert_eq!(
+            next_version(u64::MAX),
+            Err(Overflow::Version {
+                version: u64::MAX,
+                by: 1
+            })
+        );
+        assert_eq!(advance(u64::MAX, 0), Ok(u64::MAX));
+        assert!(advance(u64::MAX - 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_spans_and_shifts_at_the_edges() {
+        assert_eq!(span(3, 3), Ok(0));
+        assert_eq!(span(0, u32::MAX), Ok(u32::MAX));
+        assert_eq!(
+            span(4, 3),
+            Err(Overflow::InvertedRange { start: 4, end: 3 })
+        );
+
+        assert_eq!(shift(3, 2), 5);
+        assert_eq!(shift(u32::MAX - 1, 1), u32::MAX);
+        assert_eq!(shift(u32::MAX, 1), u32::MAX);
+        assert_eq!(shift(0, usize::MAX), u32::MAX);
+    }
+}
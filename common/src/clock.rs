@@ -0,0 +1,104 @@
+use std::{
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A point in time as the server saw it. The wall clock is for display and
+/// analytics; the monotonic clock orders and spaces events reliably even if
+/// the wall clock is adjusted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Milliseconds since the Unix epoch (0 if unknown).
+    pub wall_ms: u64,
+    /// Milliseconds since this process started.
+    pub mono_ms: u64,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self {
+            wall_ms: unix_time_ms(),
+            mono_ms: monotonic_ms(),
+        }
+    }
+}
+
+/// Wall-clock time in milliseconds since the Unix epoch (0 if the clock is
+/// set before it).
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Milliseconds since the first call in this process.
+pub fn monotonic_ms() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Clients report how long one op in this many took to reach them.
+pub const TRACE_EVERY: u64 = 16;
+
+/// Whether clients trace the op applied at `server_version`. Picked by
+/// version rather than at random so every client traces the same ops.
+pub fn is_traced(server_version: u64) -> bool {
+    server_version.is_multiple_of(TRACE_EVERY)
+}
+
+/// What one time sync exchange says about the other end's clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClockSample {
+    /// The server's clock minus the client's.
+    pub offset_ms: i64,
+    /// Time on the wire both ways, without the time the server held the
+    /// request.
+    pub rtt_ms: u64,
+}
+
+impl ClockSample {
+    /// Works the sample out from the client's clock when it sent the request
+    /// and got the answer, and the server's when it got the request and
+    /// answered, assuming the request and the answer took as long.
+    pub fn measure(
+        client_send_ms: u64,
+        server_receive_ms: u64,
+        server_send_ms: u64,
+        client_receive_ms: u64,
+    ) -> Self {
+        let (t0, t1, t2, t3) = (
+            client_send_ms as i64,
+            server_receive_ms as i64,
+            server_send_ms as i64,
+            client_receive_ms as i64,
+        );
+        Self {
+            offset_ms: ((t1 - t0) + (t2 - t3)) / 2,
+            rtt_ms: ((t3 - t0) - (t2 - t1)).max(0) as u64,
+        }
+    }
+
+    /// `server_ms`, a server timestamp, on the client's clock.
+    pub fn to_local(&self, server_ms: u64) -> u64 {
+        (server_ms as i64 - self.offset_ms).max(0) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_separates_skew_from_latency() {
+        // The server's clock is 100ms ahead; 20ms each way, 5ms at the server
+        let sample = ClockSample::measure(1_000, 1_120, 1_125, 1_045);
+        assert_eq!(
+            sample,
+            ClockSample {
+                offset_ms: 100,
+                rtt_ms: 40
+            }
+        );
+        assert_eq!(sample.to_local(1_125), 1_025);
+    }
+}
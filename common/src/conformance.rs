@@ -0,0 +1,268 @@
+//! Conformance vectors for transform engines: concurrent operation pairs and
+//! the text every replica must converge on, kept as data in
+//! `common/conformance/` so any engine can be held to the same cases.
+
+use crate::document::apply_to_text;
+use crate::operation::{DeleteOp, InsertOp, NoopOp, OperationKind, ReplaceOp};
+
+/// The transform corpus; see the file's header for its format.
+pub const TRANSFORM_VECTORS: &str = include_str!("../conformance/transform.txt");
+
+/// Two operations made concurrently against `initial`.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: String,
+    pub initial: String,
+    pub a: OperationKind,
+    pub b: OperationKind,
+    pub want: String,
+}
+
+/// The cases in `TRANSFORM_VECTORS`.
+pub fn transform_cases() -> Result<Vec<Case>, String> {
+    parse(TRANSFORM_VECTORS)
+}
+
+/// Runs every case in `TRANSFORM_VECTORS` through `transform(op, prev)`,
+/// which rebases `op` past the already-applied `prev`. Returns one message
+/// per failing case.
+pub fn check_transform(
+    transform: impl Fn(&OperationKind, &OperationKind) -> OperationKind,
+) -> Vec<String> {
+    let cases = match transform_cases() {
+        Ok(cases) => cases,
+        Err(e) => return vec![e],
+    };
+    cases
+        .iter()
+        .filter_map(|case| check_case(case, &transform).err())
+        .collect()
+}
+
+/// Applies a then b (rebased past a), and b then a (rebased past b); both
+/// must give `case.want`.
+pub fn check_case(
+    case: &Case,
+    transform: impl Fn(&OperationKind, &OperationKind) -> OperationKind,
+) -> Result<(), String> {
+    let apply = |first: &OperationKind, second: &OperationKind| {
+        let mut text = case.initial.clone();
+        apply_to_text(&mut text, first)?;
+        apply_to_text(&mut text, &transform(second, first))?;
+        Ok::<_, String>(text)
+    };
+    for (order, result) in [
+        ("a then b", apply(&case.a, &case.b)),
+        ("b then a", apply(&case.b, &case.a)),
+    ] {
+        match result {
+            Ok(text) if text == case.want => {}
+            Ok(text) => {
+                return Err(format!(
+                    "{}: {} gave {:?}, want {:?}",
+                    case.name, order, text, case.want
+                ));
+            }
+            Err(e) => return Err(format!("{}: {} failed: {}", case.name, order, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Parses cases in the corpus format. Errors name the offending line.
+pub fn parse(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    let mut current: Option<PartialCase> = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at_line = |e: String| format!("line {}: {}", number + 1, e);
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if keyword == "case" {
+            if let Some(case) = current.take() {
+                cases.push(case.finish().map_err(at_line)?);
+            }
+            current = Some(PartialCase::new(rest.trim()));
+            continue;
+        }
+        let case = current
+            .as_mut()
+            .ok_or_else(|| at_line(format!("'{}' before any case", keyword)))?;
+        case.set(keyword, rest).map_err(at_line)?;
+    }
+    if let Some(case) = current {
+        cases.push(case.finish()?);
+    }
+    Ok(cases)
+}
+
+#[derive(Default)]
+struct PartialCase {
+    name: String,
+    initial: Option<String>,
+    a: Option<OperationKind>,
+    b: Option<OperationKind>,
+    want: Option<String>,
+}
+
+impl PartialCase {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn set(&mut self, keyword: &str, rest: &str) -> Result<(), String> {
+        let mut words = Words(rest);
+        match keyword {
+            "doc" => self.initial = Some(words.string()?),
+            "want" => self.want = Some(words.string()?),
+            "a" => self.a = Some(words.operation()?),
+            "b" => self.b = Some(words.operation()?),
+            other => return Err(format!("unknown keyword '{}'", other)),
+        }
+        words.end()
+    }
+
+    fn finish(self) -> Result<Case, String> {
+        let missing = |field: &str| format!("case {} has no '{}' line", self.name, field);
+        Ok(Case {
+            initial: self.initial.clone().ok_or_else(|| missing("doc"))?,
+            a: self.a.clone().ok_or_else(|| missing("a"))?,
+            b: self.b.clone().ok_or_else(|| missing("b"))?,
+            want: self.want.clone().ok_or_else(|| missing("want"))?,
+            name: self.name,
+        })
+    }
+}
+
+/// The unread rest of a line.
+struct Words<'a>(&'a str);
+
+impl Words<'_> {
+    fn word(&mut self) -> Result<&str, String> {
+        let rest = self.0.trim_start();
+        if rest.is_empty() {
+            return Err("line ends early".to_string());
+        }
+        let end = rest.find(' ').unwrap_or(rest.len());
+        let (word, rest) = rest.split_at(end);
+        self.0 = rest;
+        Ok(word)
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, found '{}'", word))
+    }
+
+    /// A double-quoted string with \" \\ \n and \t escapes.
+    fn string(&mut self) -> Result<String, String> {
+        let rest = self.0.trim_start();
+        let mut chars = rest.char_indices();
+        if !matches!(chars.next(), Some((_, '"'))) {
+            return Err("expected a quoted string".to_string());
+        }
+        let mut string = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.0 = &rest[i + 1..];
+                    return Ok(string);
+                }
+                '\\' => match chars.next() {
+                    Some((_, '"')) => string.push('"'),
+                    Some((_, '\\')) => string.push('\\'),
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    _ => return Err("unknown escape in string".to_string()),
+                },
+                c => string.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    fn operation(&mut self) -> Result<OperationKind, String> {
+        let client_id = self.word()?.to_string();
+        let client_version = 0;
+        let op = match self.word()? {
+            "insert" => OperationKind::Insert(InsertOp {
+                index: self.number()?,
+                text: self.string()?,
+                client_id,
+                client_version,
+            }),
+            "delete" => OperationKind::Delete(DeleteOp {
+                start: self.number()?,
+                end: self.number()?,
+                client_id,
+                client_version,
+            }),
+            "replace" => OperationKind::Replace(ReplaceOp {
+                start: self.number()?,
+                end: self.number()?,
+                text: self.string()?,
+                client_id,
+                client_version,
+            }),
+            "noop" => OperationKind::Noop(NoopOp {
+                client_id,
+                client_version,
+            }),
+            other => return Err(format!("unknown operation '{}'", other)),
+        };
+        Ok(op)
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.0.trim() {
+            "" => Ok(()),
+            extra => Err(format!("unexpected '{}'", extra)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_corpus_parses_with_unique_names() {
+        let cases = transform_cases().unwrap();
+        assert!(cases.len() >= 20);
+        let names: HashSet<_> = cases.iter().map(|case| case.name.as_str()).collect();
+        assert_eq!(names.len(), cases.len());
+        let newlines = cases.iter().find(|case| case.name == "newlines").unwrap();
+        assert_eq!(newlines.initial, "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let error = parse("case broken\ndoc \"abc\"\na A insert x \"y\"\n").unwrap_err();
+        assert_eq!(error, "line 3: expected a number, found 'x'");
+        let error = parse("case short\ndoc \"abc\"\n").unwrap_err();
+        assert!(error.contains("no 'a' line"), "{}", error);
+    }
+
+    #[test]
+    fn test_wrong_engine_is_caught() {
+        // Never rebasing only converges when the ops commute
+        let failures = check_transform(|op, _| op.clone());
+        assert!(
+            failures
+                .iter()
+                .any(|failure| failure.starts_with("insert_insert_disjoint:"))
+        );
+        assert!(
+            !failures
+                .iter()
+                .any(|failure| failure.starts_with("noop_noop:"))
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::operation::{DeleteOp, InsertOp, OperationKind, ReplaceOp};
+
+/// Computes the operations that turn `old` into `new`.
+///
+/// Lines are matched with Myers' algorithm and each changed hunk is narrowed to
+/// the characters that actually differ. Operations are ordered from the end of
+/// the document towards the start, so every operation's offsets are valid against
+/// `old` and they can all be submitted with the same `client_version`: the server
+/// transforms each one against the previously applied ones, which all lie after it.
+pub fn diff(old: &str, new: &str, client_id: &str, client_version: u64) -> Vec<OperationKind> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let mut ops = Vec::new();
+    for hunk in line_hunks(&old_lines, &new_lines).into_iter().rev() {
+        let old_start: usize = old_lines[..hunk.old.0].iter().map(|l| l.len()).sum();
+        let old_text = old_lines[hunk.old.0..hunk.old.1].concat();
+        let new_text = new_lines[hunk.new.0..hunk.new.1].concat();
+
+        let (prefix, suffix) = common_affixes(&old_text, &new_text);
+        let start = old_start + prefix;
+        let end = old_start + old_text.len() - suffix;
+        let text = &new_text[prefix..new_text.len() - suffix];
+
+        let (start, end) = (start as u32, end as u32);
+        let op = if start == end {
+            OperationKind::Insert(InsertOp {
+                index: start,
+                text: text.to_string(),
+                client_id: client_id.to_string(),
+                client_version,
+            })
+        } else if text.is_empty() {
+            OperationKind::Delete(DeleteOp {
+                start,
+                end,
+                client_id: client_id.to_string(),
+                client_version,
+            })
+        } else {
+            OperationKind::Replace(ReplaceOp {
+                start,
+                end,
+                text: text.to_string(),
+                client_id: client_id.to_string(),
+                client_version,
+            })
+        };
+        ops.push(op);
+    }
+    ops
+}
+
+/// A changed region: half-open line ranges in the old and new sequences.
+struct Hunk {
+    old: (usize, usize),
+    new: (usize, usize),
+}
+
+/// Myers' O((N+M)D) shortest edit script, reduced to maximal changed hunks.
+fn line_hunks(a: &[&str], b: &[&str]) -> Vec<Hunk> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk the trace backwards collecting the matched (diagonal) line pairs.
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (mi, mj) in matches
+        .into_iter()
+        .chain(std::iter::once((a.len(), b.len())))
+    {
+        if mi > i || mj > j {
+            hunks.push(Hunk {
+                old: (i, mi),
+                new: (j, mj),
+            });
+        }
+        i = mi + 1;
+        j = mj + 1;
+    }
+    hunks
+}
+
+/// Byte lengths of the longest common prefix and (non-overlapping) suffix,
+/// both on character boundaries.
+pub(crate) fn common_affixes(a: &str, b: &str) -> (usize, usize) {
+    let prefix: usize = a
+        .chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+    let max_suffix = a.len().min(b.len()) - prefix;
+    let suffix: usize = a[prefix..]
+        .chars()
+        .rev()
+        .zip(b[prefix..].chars().rev())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .scan(0, |total, len| {
+            *total += len;
+            Some(*total)
+        })
+        .take_while(|total| *total <= max_suffix)
+        .last()
+        .unwrap_or(0);
+    (prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Document;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn apply_all(old: &str, ops: &[OperationKind]) -> String {
+        let mut doc = Document {
+            uuid: Uuid::nil(),
+            content: Arc::new(old.to_string()),
+            version: 0,
+        };
+        for op in ops {
+            doc.apply_op(op).unwrap();
+        }
+        doc.content.to_string()
+    }
+
+    fn assert_roundtrip(old: &str, new: &str) {
+        let ops = diff(old, new, "A", 0);
+        assert_eq!(apply_all(old, &ops), new, "ops: {:?}", ops);
+    }
+
+    #[test]
+    fn test_identical_produces_no_ops() {
+        assert!(diff("same\ntext\n", "same\ntext\n", "A", 0).is_empty());
+    }
+
+    #[test]
+    fn test_single_char_change_is_narrow() {
+        let ops = diff("hello world\n", "hello wurld\n", "A", 0);
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(
+            &ops[0],
+            OperationKind::Replace(ReplaceOp { start: 7, end: 8, text, .. }) if text == "u"
+        ));
+    }
+
+    #[test]
+    fn test_roundtrips() {
+        assert_roundtrip("", "new file\n");
+        assert_roundtrip("old file\n", "");
+        assert_roundtrip("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n");
+        assert_roundtrip("one\ntwo\nthree", "zero\none\nthree\nfour");
+        assert_roundtrip("aaa\naaa\n", "aaa\n");
+        assert_roundtrip("héllo\nwörld\n", "hëllo\nwörld!\n");
+    }
+}
@@ -1,55 +1,95 @@
+use std::sync::Arc;
+
 use uuid::Uuid;
 
 pub struct Document {
     pub uuid: Uuid,
-    pub content: String,
+    /// Shared copy-on-write so readers can take a snapshot without holding the
+    /// document lock while the text is copied.
+    pub content: Arc<String>,
+    /// Number of ops applied to this document, bumped once per `apply_op`.
+    /// Ops, syncs and the op log are all keyed by it.
     pub version: u64,
 }
 
+use crate::checked;
+use crate::lines;
 use crate::operation::{DeleteOp, InsertOp, OperationKind, ReplaceOp};
 
 impl Document {
+    /// Cheap snapshot of the current text. The next edit copies the text only
+    /// if a snapshot is still alive at that point.
+    pub fn snapshot(&self) -> Arc<String> {
+        Arc::clone(&self.content)
+    }
+
     pub fn apply_op(&mut self, op: &OperationKind) -> Result<(), String> {
-        match op {
-            OperationKind::Insert(InsertOp { index, text, .. }) => {
-                if *index as usize > self.content.len() {
-                    return Err(format!(
-                        "Index out of bounds: {} > {}",
-                        index,
-                        self.content.len()
-                    ));
-                }
-                self.content.insert_str(*index as usize, text);
+        let version = checked::next_version(self.version).map_err(|e| e.to_string())?;
+        apply_to_text(Arc::make_mut(&mut self.content), op)?;
+        self.version = version;
+        Ok(())
+    }
+}
+
+/// Applies an operation to plain text. Used by `Document` and by clients that
+/// mirror a document locally.
+pub fn apply_to_text(content: &mut String, op: &OperationKind) -> Result<(), String> {
+    match op {
+        OperationKind::Insert(InsertOp { index, text, .. }) => {
+            if *index as usize > content.len() {
+                return Err(format!(
+                    "Index out of bounds: {} > {}",
+                    index,
+                    content.len()
+                ));
             }
-            OperationKind::Delete(DeleteOp { start, end, .. }) => {
-                if *end as usize > self.content.len() || start > end {
-                    return Err(format!(
-                        "Invalid deletion range: {}..{} (len {})",
-                        start,
-                        end,
-                        self.content.len()
-                    ));
-                }
-                self.content
-                    .replace_range(*start as usize..*end as usize, "");
+            check_boundaries(content, &[*index])?;
+            content.insert_str(*index as usize, text);
+        }
+        OperationKind::Delete(DeleteOp { start, end, .. }) => {
+            if *end as usize > content.len() || start > end {
+                return Err(format!(
+                    "Invalid deletion range: {}..{} (len {})",
+                    start,
+                    end,
+                    content.len()
+                ));
             }
-            OperationKind::Replace(ReplaceOp {
-                start, end, text, ..
-            }) => {
-                if *end as usize > self.content.len() || start > end {
-                    return Err(format!(
-                        "Invalid replacement range: {}..{} (len {})",
-                        start,
-                        end,
-                        self.content.len()
-                    ));
-                }
-                self.content
-                    .replace_range(*start as usize..*end as usize, text);
+            check_boundaries(content, &[*start, *end])?;
+            content.replace_range(*start as usize..*end as usize, "");
+        }
+        OperationKind::Replace(ReplaceOp {
+            start, end, text, ..
+        }) => {
+            if *end as usize > content.len() || start > end {
+                return Err(format!(
+                    "Invalid replacement range: {}..{} (len {})",
+                    start,
+                    end,
+                    content.len()
+                ));
             }
-            OperationKind::Noop(_) => {}
+            check_boundaries(content, &[*start, *end])?;
+            content.replace_range(*start as usize..*end as usize, text);
         }
-        self.version += 1;
-        Ok(())
+        OperationKind::Noop(_) => {}
+        OperationKind::InsertLine(_)
+        | OperationKind::DeleteLine(_)
+        | OperationKind::MoveLine(_) => {
+            let op = lines::to_char_op(op, content)?;
+            return apply_to_text(content, &op);
+        }
+    }
+    Ok(())
+}
+
+/// String editing panics on indices inside a UTF-8 character; report them instead.
+fn check_boundaries(content: &str, positions: &[u32]) -> Result<(), String> {
+    match positions
+        .iter()
+        .find(|p| !content.is_char_boundary(**p as usize))
+    {
+        Some(p) => Err(format!("Index {} is not a character boundary", p)),
+        None => Ok(()),
     }
 }
@@ -8,6 +8,9 @@ pub enum FrameError {
     #[error("Peer disconnected")]
     Disconnected,
 
+    #[error("Read timed out: {0}")]
+    Timeout(String),
+
     #[error("Payload too large: {0} bytes (max: {1})")]
     PayloadTooLarge(usize, usize),
 
@@ -1,8 +1,18 @@
-use std::sync::Arc;
+use std::{io, sync::Arc};
+
+use bytes::Bytes;
+
+/// The type ID follows the u32 length inside the payload.
+const TYPE_ID_OFFSET: usize = 4;
 
 #[derive(Debug, Clone)]
 pub struct Frame {
-    pub payload: Vec<u8>,
+    /// Message type ID from the payload header (`protocol::MSG_TYPE_*`), read once
+    /// when the frame is built so routing doesn't need to decode the payload.
+    /// Zero if the payload is too short to carry a header.
+    pub type_id: u8,
+    /// Reference-counted, so decoding and broadcasting slice it without copying.
+    pub payload: Bytes,
 }
 
 impl Frame {
@@ -10,7 +20,33 @@ impl Frame {
         4 + self.payload.len()
     }
 
-    pub fn new_arc(payload: Vec<u8>) -> Arc<Frame> {
-        Arc::new(Frame { payload })
+    pub fn new_arc(payload: impl Into<Bytes>) -> Arc<Frame> {
+        let payload = payload.into();
+        Arc::new(Frame {
+            type_id: payload.get(TYPE_ID_OFFSET).copied().unwrap_or(0),
+            payload,
+        })
+    }
+
+    /// Writes the frame as it goes on the wire: the big-endian u32 payload
+    /// length followed by the payload.
+    pub fn write_to<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.payload.len() as u32).to_be_bytes())?;
+        writer.write_all(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_to_prefixes_payload_length() {
+        let frame = Frame::new_arc(vec![0, 0, 0, 1, 3, 9]);
+        let mut out = Vec::new();
+        frame.write_to(&mut out).unwrap();
+        assert_eq!(out, vec![0, 0, 0, 6, 0, 0, 0, 1, 3, 9]);
+        assert_eq!(out.len(), frame.total_len());
+        assert_eq!(frame.type_id, 3);
     }
 }
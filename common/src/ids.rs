@@ -0,0 +1,183 @@
+//! Client, document and operation ids. Random in production; a seeded or
+//! sequential generator makes logs and simulation traces reproducible.
+//!
+//! The process-wide generator is chosen by `DIST_SPACE_ID_SEED` (a number,
+//! or `sequential`) unless `install` is called first. Processes that talk to
+//! each other need different seeds, or their ids collide.
+
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicU64, Ordering},
+};
+
+use uuid::{Builder, Uuid};
+
+/// Environment variable read by `global` when nothing was installed.
+pub const ID_SEED_ENV: &str = "DIST_SPACE_ID_SEED";
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Random,
+    /// Pseudo-random from the seed: well spread, but the same every run.
+    Seeded(u64),
+    /// 00000000-0000-0000-0000-000000000001, ...2, and so on.
+    Sequential,
+}
+
+/// Source of fresh ids. Deterministic generators depend only on how many
+/// ids were taken before, so the same calls in the same order give the
+/// same ids.
+#[derive(Debug)]
+pub struct IdGenerator {
+    mode: Mode,
+    taken: AtomicU64,
+}
+
+impl IdGenerator {
+    pub const fn random() -> Self {
+        Self::with_mode(Mode::Random)
+    }
+
+    pub const fn seeded(seed: u64) -> Self {
+        Self::with_mode(Mode::Seeded(seed))
+    }
+
+    pub const fn sequential() -> Self {
+        Self::with_mode(Mode::Sequential)
+    }
+
+    const fn with_mode(mode: Mode) -> Self {
+        Self {
+            mode,
+            taken: AtomicU64::new(0),
+        }
+    }
+
+    /// The generator described by a `DIST_SPACE_ID_SEED` value.
+    pub fn from_setting(setting: &str) -> Result<Self, String> {
+        let setting = setting.trim();
+        if setting == "sequential" {
+            return Ok(Self::sequential());
+        }
+        setting
+            .parse()
+            .map(Self::seeded)
+            .map_err(|_| format!("{ID_SEED_ENV} must be a number or \"sequential\": {setting:?}"))
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.mode != Mode::Random
+    }
+
+    /// A client or document id.
+    pub fn uuid(&self) -> Uuid {
+        match self.mode {
+            Mode::Random => Uuid::new_v4(),
+            Mode::Seeded(seed) => {
+                let n = self.take();
+                let state = seed.wrapping_add(n.wrapping_mul(2).wrapping_mul(GOLDEN_GAMMA));
+                let high = splitmix64(state);
+                let low = splitmix64(state.wrapping_add(GOLDEN_GAMMA));
+                let mut bytes = [0; 16];
+                bytes[..8].copy_from_slice(&high.to_be_bytes());
+                bytes[8..].copy_from_slice(&low.to_be_bytes());
+                Builder::from_random_bytes(bytes).into_uuid()
+            }
+            Mode::Sequential => Uuid::from_u64_pair(0, self.take()),
+        }
+    }
+
+    /// An operation id.
+    pub fn op_id(&self) -> u64 {
+        match self.mode {
+            Mode::Sequential => self.take(),
+            _ => self.uuid().as_u64_pair().0,
+        }
+    }
+
+    /// Counts an id taken, starting from 1.
+    fn take(&self) -> u64 {
+        self.taken.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+static GLOBAL: OnceLock<IdGenerator> = OnceLock::new();
+
+/// Makes `generator` the process-wide one. Fails, handing it back, if ids
+/// were already taken from another.
+pub fn install(generator: IdGenerator) -> Result<(), IdGenerator> {
+    GLOBAL.set(generator)
+}
+
+/// The process-wide generator: the installed one, else as configured by
+/// `DIST_SPACE_ID_SEED`, else random. An unusable setting is reported once
+/// and ignored.
+pub fn global() -> &'static IdGenerator {
+    GLOBAL.get_or_init(|| match std::env::var(ID_SEED_ENV) {
+        Ok(setting) => IdGenerator::from_setting(&setting).unwrap_or_else(|e| {
+            eprintln!("{e}; using random ids");
+            IdGenerator::random()
+        }),
+        Err(_) => IdGenerator::random(),
+    })
+}
+
+/// A fresh client or document id from the process-wide generator.
+pub fn new_uuid() -> Uuid {
+    global().uuid()
+}
+
+/// A fresh operation id from the process-wide generator.
+pub fn new_op_id() -> u64 {
+    global().op_id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_ids_repeat_per_seed() {
+        let take = |seed| {
+            let ids = IdGenerator::seeded(seed);
+            (0..4).map(|_| ids.uuid()).collect::<Vec<_>>()
+        };
+        let run = take(7);
+        assert_eq!(run, take(7));
+        assert_ne!(run, take(8));
+        assert!(run.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(run.iter().all(|id| id.get_version_num() == 4));
+    }
+
+    #[test]
+    fn test_sequential_ids_count_up() {
+        let ids = IdGenerator::sequential();
+        assert_eq!(
+            ids.uuid().to_string(),
+            "00000000-0000-0000-0000-000000000001"
+        );
+        assert_eq!(ids.op_id(), 2);
+        assert!(ids.is_deterministic());
+        assert!(!IdGenerator::random().is_deterministic());
+    }
+
+    #[test]
+    fn test_setting_is_parsed() {
+        assert!(
+            IdGenerator::from_setting(" sequential ")
+                .unwrap()
+                .is_deterministic()
+        );
+        assert!(IdGenerator::from_setting("42").unwrap().is_deterministic());
+        assert!(IdGenerator::from_setting("soon").is_err());
+    }
+}
@@ -1,3 +1,7 @@
+pub mod checked;
+
+pub mod clock;
+
 pub mod frame;
 pub use frame::Frame;
 
@@ -6,11 +10,23 @@ pub use document::Document;
 
 pub mod operation;
 
+pub mod diff;
+
+pub mod lines;
+
+pub mod conformance;
+
 pub mod error;
 
+pub mod ids;
+
 pub mod proto;
 pub use proto::space;
 
 pub mod protocol;
 
+pub mod protocol_spec;
+
+pub mod transport;
+
 pub mod workspace;
@@ -0,0 +1,249 @@
+//! Line operations: resolving `InsertLine`, `DeleteLine` and `MoveLine`
+//! against a text into the character operation they amount to, and finding
+//! the offsets that name a line.
+
+use crate::diff::common_affixes;
+use crate::operation::{DeleteOp, InsertOp, NoopOp, OperationKind, ReplaceOp};
+
+/// The offsets of line `line` (counting from 0), its line break included;
+/// `None` past the last line.
+pub fn line_range(content: &str, line: usize) -> Option<(u32, u32)> {
+    let mut start = 0;
+    for (number, text) in content.split_inclusive('\n').enumerate() {
+        if number == line {
+            return Some((start as u32, (start + text.len()) as u32));
+        }
+        start += text.len();
+    }
+    None
+}
+
+/// The character operation `op` amounts to in `content`. Character
+/// operations are returned as they are.
+pub fn to_char_op(op: &OperationKind, content: &str) -> Result<OperationKind, String> {
+    let mut lines = Lines::new(content);
+    let (client_id, client_version) = match op {
+        OperationKind::InsertLine(op) => {
+            let at = lines.line_at(op.index)?;
+            lines.lines.insert(at, format!("{}\n", op.text));
+            (&op.client_id, op.client_version)
+        }
+        OperationKind::DeleteLine(op) => {
+            let (first, last) = lines.span(op.start, op.end)?;
+            if first == lines.lines.len() {
+                // Only the empty line after a final line break
+                lines.final_break = false;
+            }
+            lines.lines.drain(first..last);
+            (&op.client_id, op.client_version)
+        }
+        OperationKind::MoveLine(op) => {
+            let (first, last) = lines.span(op.start, op.end)?;
+            let to = lines.line_at(op.to)?;
+            if (first..=last).contains(&to) {
+                return Ok(noop(&op.client_id, op.client_version));
+            }
+            let moved: Vec<String> = lines.lines.drain(first..last).collect();
+            let to = if to > last { to - moved.len() } else { to };
+            lines.lines.splice(to..to, moved);
+            (&op.client_id, op.client_version)
+        }
+        op => return Ok(op.clone()),
+    };
+    Ok(char_op(content, &lines.join(), client_id, client_version))
+}
+
+/// A text as lines that all end in a line break, remembering whether its
+/// last line really did.
+struct Lines {
+    lines: Vec<String>,
+    /// Offset of each line's start, and of the end of the text.
+    starts: Vec<usize>,
+    final_break: bool,
+}
+
+impl Lines {
+    fn new(content: &str) -> Self {
+        let mut lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+        let mut starts = vec![0];
+        for line in &lines {
+            starts.push(starts[starts.len() - 1] + line.len());
+        }
+        let final_break = content.ends_with('\n');
+        if let Some(last) = lines.last_mut().filter(|_| !final_break) {
+            last.push('\n');
+        }
+        Self {
+            lines,
+            starts,
+            final_break,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.starts[self.starts.len() - 1]
+    }
+
+    /// The line containing `offset`. The text's length names the position
+    /// after the last line.
+    fn line_at(&self, offset: u32) -> Result<usize, String> {
+        let offset = offset as usize;
+        if offset > self.len() {
+            return Err(format!("Index out of bounds: {} > {}", offset, self.len()));
+        }
+        if offset == self.len() {
+            return Ok(self.lines.len());
+        }
+        Ok(self.starts.partition_point(|start| *start <= offset) - 1)
+    }
+
+    /// The lines `start..end` touches, as a range of line numbers.
+    fn span(&self, start: u32, end: u32) -> Result<(usize, usize), String> {
+        if start > end {
+            return Err(format!("Invalid line range: {}..{}", start, end));
+        }
+        let first = self.line_at(start)?;
+        let last = if end > start {
+            self.line_at(end - 1)? + 1
+        } else {
+            first + 1
+        };
+        Ok((first, last.min(self.lines.len())))
+    }
+
+    fn join(&self) -> String {
+        let mut text = self.lines.concat();
+        if !self.final_break && text.ends_with('\n') {
+            text.pop();
+        }
+        text
+    }
+}
+
+/// The one character operation turning `old` into `new`.
+fn char_op(old: &str, new: &str, client_id: &str, client_version: u64) -> OperationKind {
+    let (prefix, suffix) = common_affixes(old, new);
+    let (start, end) = (prefix as u32, (old.len() - suffix) as u32);
+    let text = new[prefix..new.len() - suffix].to_string();
+    let client_id = client_id.to_string();
+    match (start == end, text.is_empty()) {
+        (true, true) => noop(&client_id, client_version),
+        (true, false) => OperationKind::Insert(InsertOp {
+            index: start,
+            text,
+            client_id,
+            client_version,
+        }),
+        (false, true) => OperationKind::Delete(DeleteOp {
+            start,
+            end,
+            client_id,
+            client_version,
+        }),
+        (false, false) => OperationKind::Replace(ReplaceOp {
+            start,
+            end,
+            text,
+            client_id,
+            client_version,
+        }),
+    }
+}
+
+fn noop(client_id: &str, client_version: u64) -> OperationKind {
+    OperationKind::Noop(NoopOp {
+        client_id: client_id.to_string(),
+        client_version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::apply_to_text;
+    use crate::operation::{DeleteLineOp, InsertLineOp, MoveLineOp};
+
+    fn apply(content: &str, op: OperationKind) -> String {
+        let mut text = content.to_string();
+        apply_to_text(&mut text, &to_char_op(&op, content).unwrap()).unwrap();
+        text
+    }
+
+    fn insert_line(index: u32, text: &str) -> OperationKind {
+        OperationKind::InsertLine(InsertLineOp {
+            index,
+            text: text.to_string(),
+            client_id: "A".to_string(),
+            client_version: 0,
+        })
+    }
+
+    fn delete_line(start: u32, end: u32) -> OperationKind {
+        OperationKind::DeleteLine(DeleteLineOp {
+            start,
+            end,
+            client_id: "A".to_string(),
+            client_version: 0,
+        })
+    }
+
+    fn move_line(start: u32, end: u32, to: u32) -> OperationKind {
+        OperationKind::MoveLine(MoveLineOp {
+            start,
+            end,
+            to,
+            client_id: "A".to_string(),
+            client_version: 0,
+        })
+    }
+
+    #[test]
+    fn test_line_range() {
+        assert_eq!(line_range("one\ntwo", 0), Some((0, 4)));
+        assert_eq!(line_range("one\ntwo", 1), Some((4, 7)));
+        assert_eq!(line_range("one\ntwo\n", 2), None);
+    }
+
+    #[test]
+    fn test_insert_line() {
+        assert_eq!(
+            apply("one\ntwo\n", insert_line(5, "new")),
+            "one\nnew\ntwo\n"
+        );
+        assert_eq!(
+            apply("one\ntwo\n", insert_line(8, "new")),
+            "one\ntwo\nnew\n"
+        );
+        assert_eq!(apply("one\ntwo", insert_line(7, "new")), "one\ntwo\nnew");
+        assert_eq!(apply("", insert_line(0, "new")), "new");
+    }
+
+    #[test]
+    fn test_delete_line() {
+        assert_eq!(apply("one\ntwo\nthree", delete_line(5, 5)), "one\nthree");
+        assert_eq!(apply("one\ntwo\nthree", delete_line(2, 9)), "");
+        assert_eq!(apply("one\ntwo\nthree", delete_line(9, 10)), "one\ntwo");
+        assert_eq!(apply("one\n", delete_line(4, 4)), "one");
+        assert!(to_char_op(&delete_line(3, 9), "one").is_err());
+    }
+
+    #[test]
+    fn test_move_line() {
+        let text = "one\ntwo\nthree";
+        assert_eq!(apply(text, move_line(8, 8, 0)), "three\none\ntwo");
+        assert_eq!(apply(text, move_line(0, 2, 13)), "two\nthree\none");
+        assert_eq!(apply(text, move_line(0, 5, 13)), "three\none\ntwo");
+        assert_eq!(apply(text, move_line(0, 0, 2)), text);
+        // Resolved to the smallest edit covering the moved lines
+        assert_eq!(
+            to_char_op(&move_line(4, 4, 0), text).unwrap(),
+            OperationKind::Replace(ReplaceOp {
+                start: 0,
+                end: 7,
+                text: "two\none".to_string(),
+                client_id: "A".to_string(),
+                client_version: 0,
+            })
+        );
+    }
+}
@@ -1,20 +1,21 @@
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard},
 };
 
 use uuid::Uuid;
 
-use crate::space::{OperationProto, operation_proto::Kind};
+use crate::clock::Timestamp;
+use crate::space::{self, MilestoneProto, OperationProto, TagProto, operation_proto::Kind};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InsertOp {
     pub index: u32,
     pub text: String,
     pub client_id: String,
     pub client_version: u64,
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 
 pub struct DeleteOp {
     pub start: u32,
@@ -22,7 +23,7 @@ pub struct DeleteOp {
     pub client_id: String,
     pub client_version: u64,
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 
 pub struct ReplaceOp {
     pub start: u32,
@@ -31,19 +32,55 @@ pub struct ReplaceOp {
     pub client_id: String,
     pub client_version: u64,
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 
 pub struct NoopOp {
     pub client_id: String,
     pub client_version: u64,
 }
 
-#[derive(Clone, Debug)]
+/// Inserts `text` and a line break as a new line before the line containing
+/// `index`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InsertLineOp {
+    pub index: u32,
+    pub text: String,
+    pub client_id: String,
+    pub client_version: u64,
+}
+
+/// Deletes the whole lines `start..end` touches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeleteLineOp {
+    pub start: u32,
+    pub end: u32,
+    pub client_id: String,
+    pub client_version: u64,
+}
+
+/// Moves the whole lines `start..end` touches to before the line containing
+/// `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveLineOp {
+    pub start: u32,
+    pub end: u32,
+    pub to: u32,
+    pub client_id: String,
+    pub client_version: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum OperationKind {
     Insert(InsertOp),
     Delete(DeleteOp),
     Replace(ReplaceOp),
     Noop(NoopOp),
+    /// Line operations are resolved into one of the character operations
+    /// above against the text they apply to (see `lines::to_char_op`); only
+    /// that form is logged.
+    InsertLine(InsertLineOp),
+    DeleteLine(DeleteLineOp),
+    MoveLine(MoveLineOp),
 }
 
 // Engine Types
@@ -56,13 +93,280 @@ pub struct Operation {
     pub client_id: Uuid,
     pub client_version: u64,
     pub server_version: u64,
+    /// When the server applied the op (zero if unknown).
+    pub applied_at: Timestamp,
 }
 
+/// The applied ops of one document, indexed by document version: the op with
+/// `server_version` N took the document from version N to N + 1.
 pub struct OperationLog {
-    logs: Mutex<VecDeque<Operation>>,
+    logs: Mutex<Logs>,
+}
+
+/// Retained entries and the versions they cover.
+#[derive(Default)]
+struct Logs {
+    entries: VecDeque<LogEntry>,
+    /// First version the log can serve ops from. Earlier ops were compacted
+    /// or squashed away or, for a restored document, never recorded.
+    first_version: u64,
+    /// Labels left by `squash`, oldest first.
+    milestones: Vec<Milestone>,
+    /// Named versions, oldest first.
+    tags: Vec<Tag>,
+}
+
+impl Logs {
+    /// The document version the next appended op must have been applied to.
+    fn next_version(&self) -> u64 {
+        self.entries
+            .back()
+            .map_or(self.first_version, LogEntry::end_version)
+    }
+}
+
+/// A labelled version of a document, where `OperationLog::squash` dropped the
+/// ops before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Milestone {
+    pub version: u64,
+    pub label: String,
+    /// Versions of history squashed into it.
+    pub squashed: u64,
+    /// Wall-clock ms since the Unix epoch.
+    pub created_ms: u64,
+}
+
+impl Milestone {
+    pub fn to_proto(&self) -> MilestoneProto {
+        MilestoneProto {
+            version: self.version,
+            label: self.label.clone(),
+            squashed: self.squashed,
+            created_at_ms: self.created_ms,
+        }
+    }
+
+    pub fn from_proto(proto: &MilestoneProto) -> Self {
+        Self {
+            version: proto.version,
+            label: proto.label.clone(),
+            squashed: proto.squashed,
+            created_ms: proto.created_at_ms,
+        }
+    }
+}
+
+/// A name for a version of a document. It keeps the content at that version,
+/// so the version can still be viewed once its ops are compacted or squashed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub version: u64,
+    pub content: Arc<String>,
+    pub client_id: Uuid,
+    /// Wall-clock ms since the Unix epoch.
+    pub created_ms: u64,
+}
+
+impl Tag {
+    pub fn to_proto(&self) -> TagProto {
+        TagProto {
+            name: self.name.clone(),
+            version: self.version,
+            client_id: self.client_id.to_string(),
+            created_at_ms: self.created_ms,
+        }
+    }
+}
+
+/// Upper bound on how many consecutive ops one log entry may absorb.
+const MAX_COMPOSED_OPS: usize = 256;
+
+/// How a run of ops was composed into one entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Run {
+    /// A single op.
+    Single,
+    /// Inserts, each starting where the previous one ended.
+    Typing,
+    /// Deletes, each ending where the previous one started.
+    Backspace,
+    /// Deletes at the same start position.
+    ForwardDelete,
+}
+
+/// One or more consecutive ops by the same client, composed into a single op.
+/// `chunks` holds the byte length each original op contributed, so the op for
+/// any sub-range of versions can be rebuilt and the version mapping is preserved.
+#[derive(Clone)]
+struct LogEntry {
+    op: Operation,
+    run: Run,
+    chunks: Vec<u32>,
+    /// `applied_at` of each original op, parallel to `chunks`.
+    applied_at: Vec<Timestamp>,
+}
+
+impl LogEntry {
+    fn new(op: Operation) -> Self {
+        let len = match &op.kind {
+            OperationKind::Insert(insert) => insert.text.len() as u32,
+            OperationKind::Delete(delete) => delete.end - delete.start,
+            _ => 0,
+        };
+        Self {
+            applied_at: vec![op.applied_at],
+            op,
+            run: Run::Single,
+            chunks: vec![len],
+        }
+    }
+
+    fn first_version(&self) -> u64 {
+        self.op.server_version
+    }
+
+    /// Roughly the memory the entry holds: its text and bookkeeping.
+    fn bytes(&self) -> usize {
+        let text = match &self.op.kind {
+            OperationKind::Insert(insert) => insert.text.len(),
+            OperationKind::Replace(replace) => replace.text.len(),
+            _ => 0,
+        };
+        std::mem::size_of::<Self>()
+            + text
+            + self.op.new_content.len()
+            + self.chunks.len() * std::mem::size_of::<u32>()
+            + self.applied_at.len() * std::mem::size_of::<Timestamp>()
+    }
+
+    /// One past the last version this entry covers.
+    fn end_version(&self) -> u64 {
+        self.op.server_version + self.chunks.len() as u64
+    }
+
+    /// Absorbs `next` if it directly continues this entry's run.
+    fn try_compose(&mut self, next: &Operation) -> bool {
+        let composed = self.compose(next);
+        if composed {
+            self.applied_at.push(next.applied_at);
+        }
+        composed
+    }
+
+    fn compose(&mut self, next: &Operation) -> bool {
+        if next.client_id != self.op.client_id
+            || next.server_version != self.end_version()
+            || self.chunks.len() >= MAX_COMPOSED_OPS
+        {
+            return false;
+        }
+
+        match (&mut self.op.kind, &next.kind) {
+            (OperationKind::Insert(insert), OperationKind::Insert(next_insert))
+                if matches!(self.run, Run::Single | Run::Typing)
+                    && next_insert.index as usize == insert.index as usize + insert.text.len() =>
+            {
+                insert.text.push_str(&next_insert.text);
+                self.run = Run::Typing;
+                self.chunks.push(next_insert.text.len() as u32);
+                true
+            }
+            (OperationKind::Delete(delete), OperationKind::Delete(next_delete))
+                if matches!(self.run, Run::Single | Run::Backspace)
+                    && next_delete.end == delete.start =>
+            {
+                delete.start = next_delete.start;
+                self.run = Run::Backspace;
+                self.chunks.push(next_delete.end - next_delete.start);
+                true
+            }
+            (OperationKind::Delete(delete), OperationKind::Delete(next_delete))
+                if matches!(self.run, Run::Single | Run::ForwardDelete)
+                    && next_delete.start == delete.start =>
+            {
+                let len = next_delete.end - next_delete.start;
+                delete.end += len;
+                self.run = Run::ForwardDelete;
+                self.chunks.push(len);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The op equivalent to this entry's versions `[from, to)`, expressed
+    /// against the document state at `from`.
+    fn slice(&self, from: u64, to: u64) -> Operation {
+        let from = from.max(self.first_version());
+        let to = to.min(self.end_version());
+        let skip = (from - self.first_version()) as usize;
+        let take = (to - from) as usize;
+        // A composed op counts as applied when its last part was
+        let applied_at = self.applied_at[skip + take - 1];
+        if from == self.first_version() && to == self.end_version() {
+            return Operation {
+                applied_at,
+                ..self.op.clone()
+            };
+        }
+
+        let before: u32 = self.chunks[..skip].iter().sum();
+        let len: u32 = self.chunks[skip..skip + take].iter().sum();
+
+        let kind = match &self.op.kind {
+            OperationKind::Insert(insert) => {
+                let text = &insert.text[before as usize..(before + len) as usize];
+                OperationKind::Insert(InsertOp {
+                    index: insert.index + before,
+                    text: text.to_string(),
+                    ..insert.clone()
+                })
+            }
+            OperationKind::Delete(delete) if self.run == Run::Backspace => {
+                OperationKind::Delete(DeleteOp {
+                    start: delete.end - before - len,
+                    end: delete.end - before,
+                    ..delete.clone()
+                })
+            }
+            OperationKind::Delete(delete) => OperationKind::Delete(DeleteOp {
+                start: delete.start,
+                end: delete.start + len,
+                ..delete.clone()
+            }),
+            // Only single ops of other kinds are logged, and those are never sliced
+            other => other.clone(),
+        };
+
+        Operation {
+            kind,
+            server_version: from,
+            applied_at,
+            ..self.op.clone()
+        }
+    }
 }
 
 impl Operation {
+    /// Wire representation of a logged operation, as broadcast to collaborators.
+    pub fn to_proto(&self) -> OperationProto {
+        OperationProto {
+            op_id: self.op_id,
+            kind: Some(self.kind.to_proto()),
+            doc_id: self.doc_id.clone(),
+            client_id: self.client_id.to_string(),
+            client_version: self.client_version,
+            server_version: self.server_version,
+            new_content: self.new_content.clone(),
+            applied_at_ms: self.applied_at.wall_ms,
+            applied_mono_ms: self.applied_at.mono_ms,
+            // The log keys ops by document version only
+            global_version: 0,
+        }
+    }
+
     pub fn convert_operation(proto_op: OperationProto) -> Option<OperationKind> {
         match proto_op.kind {
             Some(Kind::Insert(insert_op)) => Some(OperationKind::Insert(InsertOp {
@@ -88,6 +392,25 @@ impl Operation {
                 client_id: noop_op.client_id,
                 client_version: noop_op.client_version,
             })),
+            Some(Kind::InsertLine(op)) => Some(OperationKind::InsertLine(InsertLineOp {
+                index: op.index,
+                text: op.text,
+                client_id: op.client_id,
+                client_version: op.client_version,
+            })),
+            Some(Kind::DeleteLine(op)) => Some(OperationKind::DeleteLine(DeleteLineOp {
+                start: op.start,
+                end: op.end,
+                client_id: op.client_id,
+                client_version: op.client_version,
+            })),
+            Some(Kind::MoveLine(op)) => Some(OperationKind::MoveLine(MoveLineOp {
+                start: op.start,
+                end: op.end,
+                to: op.to,
+                client_id: op.client_id,
+                client_version: op.client_version,
+            })),
             None => {
                 // Handle the case where no operation type was set (valid for a oneof)
                 None
@@ -96,31 +419,347 @@ impl Operation {
     }
 }
 
+/// Ops from no client in particular, for tests across the workspace.
+#[cfg(any(test, feature = "test-support"))]
+impl OperationKind {
+    pub fn insert_at(index: u32, text: &str) -> Self {
+        OperationKind::Insert(InsertOp {
+            index,
+            text: text.to_string(),
+            client_id: String::new(),
+            client_version: 0,
+        })
+    }
+
+    pub fn delete_range(start: u32, end: u32) -> Self {
+        OperationKind::Delete(DeleteOp {
+            start,
+            end,
+            client_id: String::new(),
+            client_version: 0,
+        })
+    }
+}
+
+impl OperationKind {
+    /// Converts the engine operation back into its wire representation.
+    pub fn to_proto(&self) -> Kind {
+        match self {
+            OperationKind::Insert(op) => Kind::Insert(space::InsertOp {
+                index: op.index,
+                text: op.text.clone(),
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::Delete(op) => Kind::Delete(space::DeleteOp {
+                start: op.start,
+                end: op.end,
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::Replace(op) => Kind::Replace(space::ReplaceOp {
+                start: op.start,
+                end: op.end,
+                text: op.text.clone(),
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::Noop(op) => Kind::Noop(space::Noop {
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::InsertLine(op) => Kind::InsertLine(space::InsertLineOp {
+                index: op.index,
+                text: op.text.clone(),
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::DeleteLine(op) => Kind::DeleteLine(space::DeleteLineOp {
+                start: op.start,
+                end: op.end,
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+            OperationKind::MoveLine(op) => Kind::MoveLine(space::MoveLineOp {
+                start: op.start,
+                end: op.end,
+                to: op.to,
+                client_id: op.client_id.clone(),
+                client_version: op.client_version,
+            }),
+        }
+    }
+
+    /// The one op with the effect of this op followed by `next`, which is
+    /// written against the text this one leaves; `None` if they don't
+    /// combine. Inserts combine with edits inside the text they insert, and
+    /// deletes with deletes that touch them, as typing and backspacing do.
+    pub fn compose(&self, next: &OperationKind) -> Option<OperationKind> {
+        match (self, next) {
+            (OperationKind::Insert(insert), OperationKind::Insert(next)) => {
+                let at = next.index.checked_sub(insert.index)? as usize;
+                if at > insert.text.len() || !insert.text.is_char_boundary(at) {
+                    return None;
+                }
+                let mut text = insert.text.clone();
+                text.insert_str(at, &next.text);
+                Some(OperationKind::Insert(InsertOp {
+                    text,
+                    ..insert.clone()
+                }))
+            }
+            (OperationKind::Insert(insert), OperationKind::Delete(next)) => {
+                let start = next.start.checked_sub(insert.index)? as usize;
+                let end = next.end.checked_sub(insert.index)? as usize;
+                if end > insert.text.len()
+                    || !insert.text.is_char_boundary(start)
+                    || !insert.text.is_char_boundary(end)
+                {
+                    return None;
+                }
+                let mut text = insert.text.clone();
+                text.replace_range(start..end, "");
+                Some(OperationKind::Insert(InsertOp {
+                    text,
+                    ..insert.clone()
+                }))
+            }
+            (OperationKind::Delete(delete), OperationKind::Delete(next))
+                if next.start <= delete.start && delete.start <= next.end =>
+            {
+                Some(OperationKind::Delete(DeleteOp {
+                    start: next.start,
+                    end: next.end + (delete.end - delete.start),
+                    ..delete.clone()
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for OperationLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OperationLog {
     pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// An empty log for a document already at `version`, whose earlier ops
+    /// are not available.
+    pub fn starting_at(version: u64) -> Self {
         Self {
-            logs: Mutex::new(VecDeque::new()),
+            logs: Mutex::new(Logs {
+                entries: VecDeque::new(),
+                first_version: version,
+                milestones: Vec::new(),
+                tags: Vec::new(),
+            }),
         }
     }
 
+    fn lock(&self) -> MutexGuard<'_, Logs> {
+        match self.logs.lock() {
+            Ok(logs) => logs,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// First version ops can be served from.
+    pub fn first_version(&self) -> u64 {
+        self.lock().first_version
+    }
+
+    /// The version the document reaches once every logged op is applied.
+    pub fn next_version(&self) -> u64 {
+        self.lock().next_version()
+    }
+
+    /// Appends an applied op, composing it into the previous entry when the
+    /// same client is continuing a typing or deleting run. Ops must arrive in
+    /// version order with no gaps.
     pub fn append_log(&self, op: Operation) -> Result<(), String> {
         let mut logs = self
             .logs
             .lock()
             .map_err(|e| format!("Failed to lock logs: {}", e))?;
-        logs.push_back(op);
+        let expected = logs.next_version();
+        if op.server_version != expected {
+            return Err(format!(
+                "Op applied to version {} but the log is at version {}",
+                op.server_version, expected
+            ));
+        }
+        Self::push(&mut logs.entries, op);
         Ok(())
     }
 
     pub fn append_log_arc(op_log: Arc<OperationLog>, op: Operation) -> Result<(), String> {
-        let mut logs = op_log
-            .logs
-            .lock()
-            .map_err(|e| format!("Failed to lock logs: {}", e))?;
-        logs.push_back(op);
+        op_log.append_log(op)
+    }
+
+    fn push(logs: &mut VecDeque<LogEntry>, op: Operation) {
+        if let Some(last) = logs.back_mut()
+            && last.try_compose(&op)
+        {
+            return;
+        }
+        logs.push_back(LogEntry::new(op));
+    }
+
+    /// Number of log entries (composed runs count once).
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every retained op, one per version, oldest first. Composed entries are
+    /// expanded, so appending the result to an empty log rebuilds this one.
+    pub fn history(&self) -> Vec<Operation> {
+        self.lock()
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                (entry.first_version()..entry.end_version())
+                    .map(move |version| entry.slice(version, version + 1))
+            })
+            .collect()
+    }
+
+    /// Roughly the memory the retained entries hold.
+    pub fn bytes(&self) -> usize {
+        self.lock().entries.iter().map(LogEntry::bytes).sum()
+    }
+
+    /// Drops entries that end at or before `version`, keeping any entry that
+    /// straddles it and always the newest entry. Returns the number dropped.
+    /// Ranges starting before the new first entry can no longer be served.
+    pub fn truncate_before(&self, version: u64) -> usize {
+        self.drop_oldest(|entry| entry.end_version() <= version)
+    }
+
+    /// Drops the oldest entries until the rest hold at most `max_bytes`
+    /// (see `bytes`), always keeping the newest. Returns the number dropped.
+    pub fn truncate_to_bytes(&self, max_bytes: usize) -> usize {
+        let mut excess = self.bytes().saturating_sub(max_bytes);
+        self.drop_oldest(|entry| {
+            let drop = excess > 0;
+            excess = excess.saturating_sub(entry.bytes());
+            drop
+        })
+    }
+
+    /// Drops entries whose last op was applied before `wall_ms`, always
+    /// keeping the newest. Returns the number dropped.
+    pub fn truncate_applied_before(&self, wall_ms: u64) -> usize {
+        self.truncate_applied_before_keeping(wall_ms, u64::MAX)
+    }
+
+    /// `truncate_applied_before`, but keeping the entries from `version` on,
+    /// and any that straddles it, however old.
+    pub fn truncate_applied_before_keeping(&self, wall_ms: u64, version: u64) -> usize {
+        self.drop_oldest(|entry| {
+            entry.end_version() <= version
+                && entry
+                    .applied_at
+                    .last()
+                    .is_none_or(|at| at.wall_ms < wall_ms)
+        })
+    }
+
+    /// Drops the entries before `keep_from`, newest included if it is
+    /// before it too, so history starts afresh where edits still in flight
+    /// need it to (at the version the log has reached, with `None`), and
+    /// labels the version the log has reached. Unlike compaction, this is
+    /// asked for: the ops a milestone replaces are the keystrokes nobody
+    /// wants to browse through.
+    pub fn squash(&self, label: &str, created_ms: u64, keep_from: Option<u64>) -> Milestone {
+        let mut logs = self.lock();
+        let version = logs.next_version();
+        let keep_from = keep_from.unwrap_or(version);
+        let before = logs.first_version;
+        while logs
+            .entries
+            .front()
+            .is_some_and(|entry| entry.end_version() <= keep_from)
+        {
+            logs.entries.pop_front();
+        }
+        logs.first_version = logs
+            .entries
+            .front()
+            .map_or(version, LogEntry::first_version);
+        let milestone = Milestone {
+            version,
+            label: label.to_string(),
+            squashed: logs.first_version - before,
+            created_ms,
+        };
+        logs.milestones.push(milestone.clone());
+        milestone
+    }
+
+    /// The milestones squashes left, oldest first.
+    pub fn milestones(&self) -> Vec<Milestone> {
+        self.lock().milestones.clone()
+    }
+
+    /// Puts back milestones from an archive, before any squash of this log.
+    pub fn restore_milestones(&self, milestones: Vec<Milestone>) {
+        self.lock().milestones = milestones;
+    }
+
+    /// Adds `tag`, refused if its name is taken or the log already holds
+    /// `max` tags.
+    pub fn add_tag(&self, tag: Tag, max: usize) -> Result<(), String> {
+        let mut logs = self.lock();
+        if let Some(taken) = logs.tags.iter().find(|taken| taken.name == tag.name) {
+            return Err(format!(
+                "tag '{}' already names version {}",
+                taken.name, taken.version
+            ));
+        }
+        if logs.tags.len() >= max {
+            return Err(format!("the document already has {} tags", max));
+        }
+        logs.tags.push(tag);
         Ok(())
     }
 
+    /// The tags, oldest first.
+    pub fn tags(&self) -> Vec<Tag> {
+        self.lock().tags.clone()
+    }
+
+    pub fn find_tag(&self, name: &str) -> Option<Tag> {
+        self.lock()
+            .tags
+            .iter()
+            .find(|tag| tag.name == name)
+            .cloned()
+    }
+
+    /// Pops entries off the front while `drop` says so, but never the last.
+    fn drop_oldest(&self, mut drop: impl FnMut(&LogEntry) -> bool) -> usize {
+        let mut logs = self.lock();
+        let mut dropped = 0;
+        while logs.entries.len() > 1 && logs.entries.front().is_some_and(&mut drop) {
+            logs.entries.pop_front();
+            dropped += 1;
+        }
+        if let Some(first) = logs.entries.front() {
+            logs.first_version = first.first_version();
+        }
+        dropped
+    }
+
     pub fn get_ops_in_range(
         &self,
         from_version: u64,
@@ -131,14 +770,318 @@ impl OperationLog {
             .lock()
             .map_err(|e| format!("Failed to lock logs: {}", e))?;
 
-        // We want ops that were applied to versions [from_version, to_version - 1]
-        // Assuming op.server_version represents the version it was applied TO.
+        // The ops applied to versions [from_version, to_version), i.e. those
+        // taking the document from from_version to to_version. Composed entries
+        // that straddle either bound are sliced to the overlap.
+        if from_version < to_version {
+            if from_version < logs.first_version {
+                return Err(format!(
+                    "Ops from version {} were compacted (log starts at {})",
+                    from_version, logs.first_version
+                ));
+            }
+            let next = logs.next_version();
+            if to_version > next {
+                return Err(format!(
+                    "Ops up to version {} have not been applied (log ends at {})",
+                    to_version, next
+                ));
+            }
+        }
+
         let mut result = Vec::new();
-        for op in logs.iter() {
-            if op.server_version >= from_version && op.server_version < to_version {
-                result.push(op.clone());
+        for entry in logs.entries.iter() {
+            if entry.end_version() > from_version && entry.first_version() < to_version {
+                result.push(entry.slice(from_version, to_version));
             }
         }
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::apply_to_text;
+
+    fn logged(server_version: u64, client: u128, kind: OperationKind) -> Operation {
+        Operation {
+            op_id: server_version,
+            kind,
+            doc_id: "doc".to_string(),
+            new_content: String::new(),
+            client_id: Uuid::from_u128(client),
+            client_version: server_version,
+            server_version,
+            applied_at: Timestamp {
+                wall_ms: server_version * 1_000,
+                mono_ms: server_version,
+            },
+        }
+    }
+
+    /// Applies the log's ops for `[from, to)` to `text`.
+    fn replay(log: &OperationLog, text: &str, from: u64, to: u64) -> String {
+        let mut text = text.to_string();
+        for op in log.get_ops_in_range(from, to).unwrap() {
+            apply_to_text(&mut text, &op.kind).unwrap();
+        }
+        text
+    }
+
+    #[test]
+    fn test_typing_run_composes_and_slices() {
+        let log = OperationLog::new();
+        for (v, c) in ["h", "e", "l", "l", "o"].iter().enumerate() {
+            log.append_log(logged(v as u64, 1, OperationKind::insert_at(v as u32, c)))
+                .unwrap();
+        }
+        assert_eq!(log.len(), 1);
+
+        assert_eq!(replay(&log, "", 0, 5), "hello");
+        // A client that saw "he" catches up from version 2
+        assert_eq!(replay(&log, "he", 2, 5), "hello");
+        assert_eq!(replay(&log, "he", 2, 4), "hell");
+    }
+
+    #[test]
+    fn test_backspace_and_forward_delete_runs() {
+        let log = OperationLog::new();
+        // "abcdef": backspace f, e, d
+        for (v, end) in [6u32, 5, 4].iter().enumerate() {
+            log.append_log(logged(
+                v as u64,
+                1,
+                OperationKind::delete_range(end - 1, *end),
+            ))
+            .unwrap();
+        }
+        // then forward-delete twice at 1: "abc" -> "a"
+        for v in 3..5 {
+            log.append_log(logged(v, 1, OperationKind::delete_range(1, 2)))
+                .unwrap();
+        }
+        assert_eq!(log.len(), 2);
+        assert_eq!(replay(&log, "abcdef", 0, 5), "a");
+        assert_eq!(replay(&log, "abcde", 1, 3), "abc");
+        assert_eq!(replay(&log, "ac", 4, 5), "a");
+    }
+
+    #[test]
+    fn test_other_clients_break_runs() {
+        let log = OperationLog::new();
+        log.append_log(logged(0, 1, OperationKind::insert_at(0, "a")))
+            .unwrap();
+        log.append_log(logged(1, 2, OperationKind::insert_at(1, "b")))
+            .unwrap();
+        log.append_log(logged(2, 1, OperationKind::insert_at(2, "c")))
+            .unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(replay(&log, "a", 1, 3), "abc");
+    }
+
+    #[test]
+    fn test_truncate_before_keeps_recent_history() {
+        let log = OperationLog::new();
+        for v in 0..4 {
+            // Alternating clients, so every op is its own entry
+            log.append_log(logged(
+                v,
+                (v % 2) as u128,
+                OperationKind::insert_at(v as u32, "x"),
+            ))
+            .unwrap();
+        }
+        assert_eq!(log.truncate_before(2), 2);
+        assert_eq!(log.len(), 2);
+        assert_eq!(replay(&log, "xx", 2, 4), "xxxx");
+        assert!(log.get_ops_in_range(1, 4).is_err());
+
+        // The newest entry always survives
+        assert_eq!(log.truncate_before(10), 1);
+        assert_eq!(log.len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_by_size_and_age() {
+        let log = OperationLog::new();
+        for v in 0..6 {
+            log.append_log(logged(
+                v,
+                (v % 2) as u128,
+                OperationKind::insert_at(v as u32, "x"),
+            ))
+            .unwrap();
+        }
+        // Applied at 0s, 1s, ... 5s: entries applied before 2s go, unless
+        // they are still needed from version 1
+        assert_eq!(log.truncate_applied_before_keeping(2_000, 1), 1);
+        assert_eq!(log.first_version(), 1);
+        assert_eq!(log.truncate_applied_before(2_000), 1);
+        assert_eq!(log.first_version(), 2);
+
+        let entry = log.bytes() / log.len();
+        assert_eq!(log.truncate_to_bytes(2 * entry + 1), 2);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.truncate_to_bytes(0), 1);
+        assert_eq!(log.truncate_applied_before(u64::MAX), 0);
+        assert_eq!(log.first_version(), 5);
+    }
+
+    #[test]
+    fn test_squash_leaves_a_milestone_at_the_current_version() {
+        let log = OperationLog::new();
+        for v in 0..3 {
+            log.append_log(logged(
+                v,
+                (v % 2) as u128,
+                OperationKind::insert_at(v as u32, "x"),
+            ))
+            .unwrap();
+        }
+        // Ops still needed from version 1 are kept
+        let milestone = log.squash("Outline", 5_000, Some(1));
+        assert_eq!((milestone.version, milestone.squashed), (3, 1));
+        assert_eq!(replay(&log, "x", 1, 3), "xxx");
+        let milestone = log.squash("Draft", 7_000, None);
+        assert_eq!((milestone.version, milestone.squashed), (3, 2));
+        assert!(log.is_empty());
+        assert_eq!((log.first_version(), log.next_version()), (3, 3));
+        assert!(log.get_ops_in_range(2, 3).is_err());
+
+        // Editing goes on from the milestone
+        log.append_log(logged(3, 1, OperationKind::insert_at(3, "y")))
+            .unwrap();
+        assert_eq!(replay(&log, "xxx", 3, 4), "xxxy");
+        assert_eq!(log.squash("Final", 9_000, None).squashed, 1);
+        let labels: Vec<String> = log.milestones().into_iter().map(|m| m.label).collect();
+        assert_eq!(labels, vec!["Outline", "Draft", "Final"]);
+    }
+
+    #[test]
+    fn test_tags_keep_their_content_through_a_squash() {
+        let log = OperationLog::new();
+        let tag = |name: &str, content: &str| Tag {
+            name: name.to_string(),
+            version: log.next_version(),
+            content: Arc::new(content.to_string()),
+            client_id: Uuid::from_u128(1),
+            created_ms: 0,
+        };
+        log.add_tag(tag("empty", ""), 2).unwrap();
+        log.append_log(logged(0, 1, OperationKind::insert_at(0, "x")))
+            .unwrap();
+        assert!(log.add_tag(tag("empty", "x"), 2).is_err());
+        log.add_tag(tag("draft-1", "x"), 2).unwrap();
+        assert!(log.add_tag(tag("draft-2", "x"), 2).is_err());
+
+        log.squash("Draft", 0, None);
+        let draft = log.find_tag("draft-1").unwrap();
+        assert_eq!((draft.version, draft.content.as_str()), (1, "x"));
+        assert!(log.find_tag("draft-2").is_none());
+    }
+
+    #[test]
+    fn test_history_expands_composed_entries() {
+        let log = OperationLog::new();
+        for (v, c) in ["a", "b", "c"].iter().enumerate() {
+            log.append_log(logged(v as u64, 1, OperationKind::insert_at(v as u32, c)))
+                .unwrap();
+        }
+        let history = log.history();
+        let versions: Vec<u64> = history.iter().map(|op| op.server_version).collect();
+        assert_eq!(versions, vec![0, 1, 2]);
+        // Each op keeps its own timestamp through composition
+        let times: Vec<u64> = history.iter().map(|op| op.applied_at.wall_ms).collect();
+        assert_eq!(times, vec![0, 1_000, 2_000]);
+
+        let rebuilt = OperationLog::new();
+        for op in history {
+            rebuilt.append_log(op).unwrap();
+        }
+        assert_eq!(rebuilt.len(), 1);
+        assert_eq!(replay(&rebuilt, "a", 1, 3), "abc");
+    }
+
+    #[test]
+    fn test_log_serves_only_its_own_versions() {
+        // A restored document at version 5 with no recorded history
+        let log = OperationLog::starting_at(5);
+        assert!(log.get_ops_in_range(3, 5).is_err());
+        assert!(log.get_ops_in_range(5, 5).unwrap().is_empty());
+        assert!(
+            log.append_log(logged(4, 1, OperationKind::insert_at(0, "x")))
+                .is_err()
+        );
+
+        log.append_log(logged(5, 1, OperationKind::insert_at(0, "x")))
+            .unwrap();
+        assert_eq!((log.first_version(), log.next_version()), (5, 6));
+        assert_eq!(replay(&log, "", 5, 6), "x");
+        // Versions the document has not reached yet
+        assert!(log.get_ops_in_range(5, 7).is_err());
+    }
+
+    #[test]
+    fn test_compose_matches_applying_both() {
+        let cases = [
+            (
+                OperationKind::insert_at(1, "ab"),
+                OperationKind::insert_at(3, "c"),
+                true,
+            ),
+            (
+                OperationKind::insert_at(1, "ab"),
+                OperationKind::insert_at(2, "é"),
+                true,
+            ),
+            (
+                OperationKind::insert_at(1, "abc"),
+                OperationKind::delete_range(2, 4),
+                true,
+            ),
+            (
+                OperationKind::delete_range(3, 4),
+                OperationKind::delete_range(2, 3),
+                true,
+            ),
+            (
+                OperationKind::delete_range(1, 2),
+                OperationKind::delete_range(1, 3),
+                true,
+            ),
+            (
+                OperationKind::insert_at(1, "ab"),
+                OperationKind::insert_at(4, "c"),
+                false,
+            ),
+            (
+                OperationKind::insert_at(1, "ab"),
+                OperationKind::delete_range(0, 2),
+                false,
+            ),
+            (
+                OperationKind::delete_range(3, 4),
+                OperationKind::delete_range(0, 1),
+                false,
+            ),
+            (
+                OperationKind::delete_range(1, 2),
+                OperationKind::insert_at(1, "x"),
+                false,
+            ),
+        ];
+        for (first, next, combines) in cases {
+            let Some(composed) = first.compose(&next) else {
+                assert!(!combines, "{:?} then {:?}", first, next);
+                continue;
+            };
+            assert!(combines, "{:?} then {:?}", first, next);
+            let (mut stepwise, mut at_once) = ("0123456".to_string(), "0123456".to_string());
+            apply_to_text(&mut stepwise, &first).unwrap();
+            apply_to_text(&mut stepwise, &next).unwrap();
+            apply_to_text(&mut at_once, &composed).unwrap();
+            assert_eq!(stepwise, at_once);
+        }
+    }
+}
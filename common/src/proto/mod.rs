@@ -1 +1,8 @@
-pub mod space;
+/// Wire messages, generated from `proto/space/v1/*.proto` by build.rs.
+pub mod space {
+    pub mod v1 {
+        include!("space.v1.rs");
+    }
+
+    pub use v1::*;
+}
@@ -0,0 +1,1530 @@
+// This file is @generated by prost-build.
+/// Defines an insertion operation.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct InsertOp {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub text: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub client_version: u64,
+}
+/// Defines a deletion operation.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct DeleteOp {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub client_version: u64,
+}
+/// Defines a replacement operation (delete then insert).
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ReplaceOp {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+    #[prost(string, tag = "3")]
+    pub text: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "5")]
+    pub client_version: u64,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Noop {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub client_version: u64,
+}
+/// Inserts text as a whole new line before the line containing index (or after
+/// the last line, if index is the document's length).
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct InsertLineOp {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    /// The line without its line break.
+    #[prost(string, tag = "2")]
+    pub text: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub client_version: u64,
+}
+/// Deletes every line that start..end touches, line breaks included, along
+/// with anything typed into them concurrently.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct DeleteLineOp {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub client_version: u64,
+}
+/// Moves the lines start..end touches to before the line containing to (or
+/// to the end, if to is the document's length).
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct MoveLineOp {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+    #[prost(uint32, tag = "3")]
+    pub to: u32,
+    #[prost(string, tag = "4")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "5")]
+    pub client_version: u64,
+}
+/// Represents a single collaborative editing operation.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct OperationProto {
+    #[prost(uint64, tag = "1")]
+    pub op_id: u64,
+    /// Metadata related to the operation's source and state.
+    #[prost(string, tag = "6")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "7")]
+    pub client_id: ::prost::alloc::string::String,
+    /// Document version the client wrote the op against; the server transforms
+    /// it through the ops applied since, which must still be in its history.
+    #[prost(uint64, tag = "8")]
+    pub client_version: u64,
+    /// Document version the op was applied to (0 until it has been).
+    #[prost(uint64, tag = "9")]
+    pub server_version: u64,
+    #[prost(string, tag = "10")]
+    pub new_content: ::prost::alloc::string::String,
+    /// When the server applied the op (0 until it has): wall-clock ms since the
+    /// Unix epoch, and monotonic ms since the server started.
+    #[prost(uint64, tag = "11")]
+    pub applied_at_ms: u64,
+    #[prost(uint64, tag = "12")]
+    pub applied_mono_ms: u64,
+    /// Workspace version after the change that applied this op (0 until applied,
+    /// or when read back from history). Counts changes across all documents: each
+    /// single op bumps it once, and every op of a transaction shares one value.
+    #[prost(uint64, tag = "13")]
+    pub global_version: u64,
+    #[prost(oneof = "operation_proto::Kind", tags = "2, 3, 4, 5, 14, 15, 16")]
+    pub kind: ::core::option::Option<operation_proto::Kind>,
+}
+/// Nested message and enum types in `OperationProto`.
+pub mod operation_proto {
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum Kind {
+        #[prost(message, tag = "2")]
+        Insert(super::InsertOp),
+        #[prost(message, tag = "3")]
+        Delete(super::DeleteOp),
+        #[prost(message, tag = "4")]
+        Replace(super::ReplaceOp),
+        #[prost(message, tag = "5")]
+        Noop(super::Noop),
+        #[prost(message, tag = "14")]
+        InsertLine(super::InsertLineOp),
+        #[prost(message, tag = "15")]
+        DeleteLine(super::DeleteLineOp),
+        #[prost(message, tag = "16")]
+        MoveLine(super::MoveLineOp),
+    }
+}
+/// Several applied operations on one document, in server_version order. Sent by
+/// a client, the batch is a transaction instead: its operations, on any documents
+/// the connection has open, are applied all together or not at all.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OperationBatchProto {
+    #[prost(message, repeated, tag = "1")]
+    pub operations: ::prost::alloc::vec::Vec<OperationProto>,
+    /// Client only: the transaction continues in the next batch, for one too
+    /// large for a single frame. The server holds the operations and applies
+    /// nothing until a batch without it completes the transaction.
+    #[prost(bool, tag = "2")]
+    pub more: bool,
+}
+/// Bytes start..end of a document's content.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TextRangeProto {
+    #[prost(uint32, tag = "1")]
+    pub start: u32,
+    #[prost(uint32, tag = "2")]
+    pub end: u32,
+}
+/// Settings the server keeps with a document.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DocumentSettingsProto {
+    /// Taken from the content when the document is created, if it uses one
+    /// style throughout.
+    #[prost(enumeration = "LineEnding", tag = "1")]
+    pub line_ending: i32,
+    /// Columns per tab stop, for editors; 0 when not set.
+    #[prost(uint32, tag = "2")]
+    pub tab_width: u32,
+    /// Language of the text ("rust", "markdown", ...), guessed from the path's
+    /// extension when the document is created; empty when unknown.
+    #[prost(string, tag = "3")]
+    pub language_id: ::prost::alloc::string::String,
+    /// Largest the content may grow to, in bytes; 0 for no limit. Edits past
+    /// it are rejected with ERROR_CODE_DOCUMENT_TOO_LARGE.
+    #[prost(uint64, tag = "4")]
+    pub max_bytes: u64,
+    /// Regions text may be added to but not removed from, e.g. a log section:
+    /// deletes and replaces intersecting one are rejected with
+    /// ERROR_CODE_APPEND_ONLY. The server moves them through edits; text
+    /// inserted inside a region or at its end joins it. Settings a client
+    /// sends must keep every region, or widen it, or they are rejected with
+    /// ERROR_CODE_APPEND_ONLY too: only the operator lifts them.
+    #[prost(message, repeated, tag = "5")]
+    pub append_only: ::prost::alloc::vec::Vec<TextRangeProto>,
+}
+/// Edits applied to a document by one connection.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AuthorEditsProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub edits: u64,
+}
+/// Figures the server keeps up to date as edits are applied, so they cost
+/// nothing to read however large the document.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DocumentStatsProto {
+    /// Length of the content in bytes.
+    #[prost(uint64, tag = "1")]
+    pub length: u64,
+    /// Lines, counting a last line without a line break; an empty document has one.
+    #[prost(uint64, tag = "2")]
+    pub line_count: u64,
+    /// When the last edit was applied, wall-clock ms since the Unix epoch; 0 if
+    /// there has been none since the server loaded the document.
+    #[prost(uint64, tag = "3")]
+    pub last_edit_ms: u64,
+    /// Edits applied since then, by author, in the order authors first edited.
+    #[prost(message, repeated, tag = "4")]
+    pub edits: ::prost::alloc::vec::Vec<AuthorEditsProto>,
+}
+/// Changes an open document's settings. Subscribers, the sender included, are
+/// sent a SyncDocumentProto carrying them. Refused if the content already
+/// breaks them, e.g. LF line endings for text containing \r\n.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetDocumentSettingsProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub settings: ::core::option::Option<DocumentSettingsProto>,
+}
+/// Represents a full document state for synchronization.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncDocumentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub content: ::prost::alloc::string::String,
+    /// The document's version: content reflects exactly this many ops.
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+    /// Path the document was opened by, so clients can route syncs for documents they requested.
+    #[prost(string, tag = "4")]
+    pub path: ::prost::alloc::string::String,
+    /// Server clock when the snapshot was taken: wall-clock ms since the Unix
+    /// epoch, and monotonic ms since the server started.
+    #[prost(uint64, tag = "5")]
+    pub server_time_ms: u64,
+    #[prost(uint64, tag = "6")]
+    pub server_mono_ms: u64,
+    #[prost(message, optional, tag = "7")]
+    pub settings: ::core::option::Option<DocumentSettingsProto>,
+    #[prost(message, optional, tag = "8")]
+    pub stats: ::core::option::Option<DocumentStatsProto>,
+    /// Answers a resuming OpenDocumentProto: `content` is left out, as the
+    /// client has it once it applies the ops sent just before.
+    #[prost(bool, tag = "9")]
+    pub resumed: bool,
+}
+/// Asks the server to send again every frame from `from_seq` on, after the
+/// client saw a gap in the sequence numbers of a sequenced connection (see
+/// HelloProto.sequenced). Frames still in the server's resend window are sent
+/// with their original numbers; if `from_seq` has left the window the answer is
+/// an ERROR_CODE_RESEND_UNAVAILABLE error and the client should resync.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ResendProto {
+    #[prost(uint64, tag = "1")]
+    pub from_seq: u64,
+}
+/// The client has applied the document up to `version`. The server keeps
+/// each client's latest acknowledgement, across restarts if it is configured
+/// to, so the client can resume the document from there (see
+/// OpenDocumentProto.resume), and holds back compacting history a client that
+/// was recently connected still needs. Ops count as acknowledging the
+/// version they were written against.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AckProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub version: u64,
+}
+/// Flow control: lets the server send `frames` more frames and `bytes` more
+/// payload bytes on this connection. The first grant of each kind turns on
+/// pacing by it; a kind never granted stays unlimited, and zero grants nothing.
+/// Frames the server cannot send yet wait on the server, up to a limit, rather
+/// than the connection being dropped as a slow consumer. A frame larger than
+/// the byte credit left is still sent once any is left. Heartbeat pings count
+/// too, so keep some credit granted.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CreditProto {
+    #[prost(uint32, tag = "1")]
+    pub frames: u32,
+    #[prost(uint64, tag = "2")]
+    pub bytes: u64,
+}
+/// How a document's lines end. Edits that would bring in the other style are
+/// rejected with ERROR_CODE_OP_LINE_ENDING.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum LineEnding {
+    /// Either style, or both.
+    Any = 0,
+    Lf = 1,
+    Crlf = 2,
+}
+impl LineEnding {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Any => "LINE_ENDING_ANY",
+            Self::Lf => "LINE_ENDING_LF",
+            Self::Crlf => "LINE_ENDING_CRLF",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "LINE_ENDING_ANY" => Some(Self::Any),
+            "LINE_ENDING_LF" => Some(Self::Lf),
+            "LINE_ENDING_CRLF" => Some(Self::Crlf),
+            _ => None,
+        }
+    }
+}
+/// Marks the sending connection away, or back. Other connections are told with
+/// a PresenceProto.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct SetPresenceProto {
+    #[prost(bool, tag = "1")]
+    pub away: bool,
+}
+/// A connection's presence changed. Sent to every other connection.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct PresenceProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub display_name: ::prost::alloc::string::String,
+    #[prost(enumeration = "PresenceStatus", tag = "3")]
+    pub status: i32,
+    /// Why an OFFLINE connection was dropped ("timed out", "kicked", ...);
+    /// empty when it closed the connection itself.
+    #[prost(string, tag = "4")]
+    pub reason: ::prost::alloc::string::String,
+}
+/// Whether a connection's user is around, as shown in participant lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PresenceStatus {
+    Active = 0,
+    /// No edits or other input for the server's idle timeout.
+    Idle = 1,
+    /// Stepped away, as announced by the client itself.
+    Away = 2,
+    /// Disconnected, or dropped by the server; see PresenceProto.reason.
+    Offline = 3,
+}
+impl PresenceStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Active => "PRESENCE_STATUS_ACTIVE",
+            Self::Idle => "PRESENCE_STATUS_IDLE",
+            Self::Away => "PRESENCE_STATUS_AWAY",
+            Self::Offline => "PRESENCE_STATUS_OFFLINE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "PRESENCE_STATUS_ACTIVE" => Some(Self::Active),
+            "PRESENCE_STATUS_IDLE" => Some(Self::Idle),
+            "PRESENCE_STATUS_AWAY" => Some(Self::Away),
+            "PRESENCE_STATUS_OFFLINE" => Some(Self::Offline),
+            _ => None,
+        }
+    }
+}
+/// Subscribes the connection to a document; answered with a SyncDocumentProto.
+/// A missing document is created, unless the server is configured to refuse
+/// with an ERROR_CODE_NO_SUCH_DOCUMENT error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct OpenDocumentProto {
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// The client still has the document as of the last version it
+    /// acknowledged (see AckProto) under this client id. If the server still
+    /// has every op since then, it sends them as an OperationBatchProto (none
+    /// if nothing changed) followed by a sync with `resumed` set and no
+    /// content, instead of the whole document.
+    #[prost(bool, tag = "2")]
+    pub resume: bool,
+}
+/// Unsubscribes the connection from a document.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CloseDocumentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+}
+/// Requests a DocumentArchiveProto for a document the connection has open.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ExportDocumentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+}
+/// Asks for a copy of a document the connection has open, in `format`, to save
+/// as a file (message type EXPORT_REQUEST). Answered with the copy as
+/// ExportChunkProtos, or an error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ExportRequestProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "ExportFormat", tag = "2")]
+    pub format: i32,
+}
+/// Part of an exported copy (message type EXPORT_CHUNK). The copy is the data
+/// of the chunks in the order they arrive, up to the first whose `more` is
+/// false. The server sends a copy's chunks one after another, so chunks of two
+/// copies never interleave.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ExportChunkProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "ExportFormat", tag = "2")]
+    pub format: i32,
+    /// Name to save the copy under: the document's own, with an .html
+    /// extension for HTML.
+    #[prost(string, tag = "3")]
+    pub file_name: ::prost::alloc::string::String,
+    /// Document version the copy was made at.
+    #[prost(uint64, tag = "4")]
+    pub version: u64,
+    #[prost(bytes = "vec", tag = "5")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    /// Chunks of this copy follow.
+    #[prost(bool, tag = "6")]
+    pub more: bool,
+}
+/// Part of a binary file (an image, a PDF) a client attaches to a document it
+/// has open (message type UPLOAD_ATTACHMENT). The file is the data of the
+/// chunks in the order they arrive, up to the first whose `more` is false; a
+/// connection uploads one file at a time. Answered with an AttachmentProto, or
+/// an ERROR_CODE_ATTACHMENT_REJECTED error. A document references the file by
+/// containing `attachment:<attachment_id>`, as in `![plan](attachment:9f86...)`;
+/// files no document references are deleted after a while.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct UploadAttachmentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    /// Chunks of this file follow.
+    #[prost(bool, tag = "3")]
+    pub more: bool,
+}
+/// A stored attachment (message type ATTACHMENT), in reply to its upload.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AttachmentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    /// The SHA-256 of the file, as 64 lowercase hex digits. Uploading the same
+    /// file twice gives the same id.
+    #[prost(string, tag = "2")]
+    pub attachment_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+}
+/// Asks for an attachment referenced by a document the connection has open
+/// (message type FETCH_ATTACHMENT). Answered with AttachmentChunkProtos, or an
+/// ERROR_CODE_ATTACHMENT_REJECTED error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct FetchAttachmentProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub attachment_id: ::prost::alloc::string::String,
+}
+/// Part of a fetched attachment (message type ATTACHMENT_CHUNK), sent like
+/// the chunks of an exported copy.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AttachmentChunkProto {
+    #[prost(string, tag = "1")]
+    pub attachment_id: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    /// Chunks of this attachment follow.
+    #[prost(bool, tag = "3")]
+    pub more: bool,
+}
+/// A label on a version of a document, left where a checkpoint squashed the
+/// operations before it out of the document's history.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct MilestoneProto {
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    #[prost(string, tag = "2")]
+    pub label: ::prost::alloc::string::String,
+    /// Versions of history squashed into it.
+    #[prost(uint64, tag = "3")]
+    pub squashed: u64,
+    /// When the checkpoint was made, in milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "4")]
+    pub created_at_ms: u64,
+}
+/// A document with its retained history, portable between servers. Sent by the
+/// server in reply to ExportDocumentProto; sent by a client to import it, which is
+/// answered with a SyncDocumentProto for the restored document.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DocumentArchiveProto {
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub content: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+    /// One operation per version, oldest first, ending at version - 1. Older
+    /// history may have been compacted away.
+    #[prost(message, repeated, tag = "4")]
+    pub operations: ::prost::alloc::vec::Vec<OperationProto>,
+    /// When the archive was made, in milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "5")]
+    pub exported_at_ms: u64,
+    /// The document's milestones, oldest first.
+    #[prost(message, repeated, tag = "6")]
+    pub milestones: ::prost::alloc::vec::Vec<MilestoneProto>,
+}
+/// Claims [start, end) of a document for the sending connection. Answered with
+/// a RangeLocksProto, or an ERROR_CODE_RANGE_LOCKED error if the range overlaps
+/// another connection's lock.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct LockRangeProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub start: u32,
+    #[prost(uint32, tag = "3")]
+    pub end: u32,
+}
+/// Releases a lock held by the sending connection.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct UnlockRangeProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub lock_id: u64,
+}
+/// A held lock. Its range moves with edits like any other position.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct RangeLockProto {
+    #[prost(uint64, tag = "1")]
+    pub lock_id: u64,
+    #[prost(string, tag = "2")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub start: u32,
+    #[prost(uint32, tag = "4")]
+    pub end: u32,
+}
+/// Every lock on a document, positioned as of `version`. Sent to the document's
+/// subscribers whenever a lock is taken or released.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RangeLocksProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub version: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub locks: ::prost::alloc::vec::Vec<RangeLockProto>,
+}
+/// A range drawn over a document without being part of its content: a
+/// diagnostic, a lint squiggle, a search match. Overlays are never logged or
+/// exported, and go away with the connection that set them.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct OverlayProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// What the overlay marks, chosen by the tool ("spelling", "lint",
+    /// "search", ...).
+    #[prost(string, tag = "2")]
+    pub kind: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub start: u32,
+    #[prost(uint32, tag = "4")]
+    pub end: u32,
+    /// Anything the tool wants shown with the range, e.g. a diagnostic message.
+    #[prost(string, tag = "5")]
+    pub payload: ::prost::alloc::string::String,
+}
+/// Replaces the sending connection's overlays of one kind on a document; an
+/// empty list clears them. Positions refer to `version` and are moved through
+/// any edits made since. Read-only connections may set overlays too.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SetOverlaysProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub version: u64,
+    #[prost(string, tag = "3")]
+    pub kind: ::prost::alloc::string::String,
+    /// client_id and kind are filled in by the server.
+    #[prost(message, repeated, tag = "4")]
+    pub overlays: ::prost::alloc::vec::Vec<OverlayProto>,
+}
+/// Every overlay on a document, positioned as of `version`. Sent to the
+/// document's subscribers whenever a connection's overlays change; in between,
+/// they move with edits like any other position.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct OverlaysProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub version: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub overlays: ::prost::alloc::vec::Vec<OverlayProto>,
+}
+/// The lines of a document the sending connection has on screen (message type
+/// SET_VIEWPORT). Relayed as a ViewportProto to the connections following it,
+/// and to no one else.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct SetViewportProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    /// 0-based.
+    #[prost(uint32, tag = "2")]
+    pub first_line: u32,
+    #[prost(uint32, tag = "3")]
+    pub line_count: u32,
+}
+/// Mirrors the viewport of another connection in the workspace from now on
+/// (message type FOLLOW); an empty `client_id` stops following. Answered with
+/// that connection's current ViewportProto if it has shared one, or an
+/// ERROR_CODE_FOLLOW_REJECTED error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct FollowProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+}
+/// Where a followed connection is looking (message type VIEWPORT). Viewports
+/// are ephemeral: the server drops them rather than hold them for flow control,
+/// queue them behind a backlog or resend them, since the next one supersedes
+/// them. Guests only get the viewports of documents they have open.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ViewportProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub doc_id: ::prost::alloc::string::String,
+    /// The document's path, to open it when the followed connection moves to
+    /// another document.
+    #[prost(string, tag = "3")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "4")]
+    pub first_line: u32,
+    #[prost(uint32, tag = "5")]
+    pub line_count: u32,
+}
+/// A momentary nudge to everyone with a document open (message type SIGNAL):
+/// sent by a connection with `version` and a range as of that version, and
+/// broadcast by the server, its own included, with the range moved through
+/// the edits made since, like overlay positions. Signals are never stored,
+/// logged or resent; read-only connections may send them too.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct SignalProto {
+    /// Set by the server.
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+    #[prost(enumeration = "SignalKind", tag = "4")]
+    pub kind: i32,
+    /// Empty (start == end) for a raised hand about the whole document.
+    #[prost(uint32, tag = "5")]
+    pub start: u32,
+    #[prost(uint32, tag = "6")]
+    pub end: u32,
+    /// The reaction, for SIGNAL_KIND_REACTION only.
+    #[prost(string, tag = "7")]
+    pub emoji: ::prost::alloc::string::String,
+}
+/// A name given to a version of a document ("draft-1", "sent-to-review").
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TagProto {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub version: u64,
+    /// Who tagged it.
+    #[prost(string, tag = "3")]
+    pub client_id: ::prost::alloc::string::String,
+    /// When, in milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "4")]
+    pub created_at_ms: u64,
+}
+/// Names the current version of an open document (message type TAG_VERSION).
+/// The document's subscribers, the sender included, are sent a HistoryProto
+/// listing its tags; refused with an ERROR_CODE_TAG_REJECTED error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TagVersionProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+}
+/// Asks for the history of an open document (message type GET_HISTORY),
+/// answered with a HistoryProto; with a `tag`, for its content at that tag too.
+/// Without a `doc_id`, asks for the events of the connection's workspace
+/// instead, the oldest first, starting after `events_after`.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct GetHistoryProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub tag: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub events_after: u64,
+}
+/// The milestones and tags of a document, and the versions its op log still
+/// holds (message type HISTORY).
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+    /// Edits based on versions before this one are rejected.
+    #[prost(uint64, tag = "4")]
+    pub first_version: u64,
+    #[prost(message, repeated, tag = "5")]
+    pub milestones: ::prost::alloc::vec::Vec<MilestoneProto>,
+    #[prost(message, repeated, tag = "6")]
+    pub tags: ::prost::alloc::vec::Vec<TagProto>,
+    /// The tag asked for in a GetHistoryProto, and the content at it.
+    #[prost(string, tag = "7")]
+    pub tag: ::prost::alloc::string::String,
+    #[prost(string, tag = "8")]
+    pub content: ::prost::alloc::string::String,
+    /// The workspace's events, when no document was asked for. At most a page
+    /// of them; ask again after the last for more.
+    #[prost(message, repeated, tag = "9")]
+    pub events: ::prost::alloc::vec::Vec<WorkspaceEventProto>,
+}
+/// Something that happened in a workspace, for an activity feed.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct WorkspaceEventProto {
+    /// Counts up from 1 across the workspace's events.
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    /// In milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "2")]
+    pub at_ms: u64,
+    #[prost(enumeration = "WorkspaceEventKind", tag = "3")]
+    pub kind: i32,
+    /// The document created or checkpointed; empty otherwise.
+    #[prost(string, tag = "4")]
+    pub path: ::prost::alloc::string::String,
+    /// The member the event is about, or who brought it about; empty for the
+    /// operator and for connections that are not members.
+    #[prost(string, tag = "5")]
+    pub member: ::prost::alloc::string::String,
+    /// How a document was created (opened, imported or created from a
+    /// template), or the commit or milestone a checkpoint made.
+    #[prost(string, tag = "6")]
+    pub detail: ::prost::alloc::string::String,
+}
+/// Subscribes the connection to its workspace's activity feed, or with
+/// `watch` false unsubscribes it (message type WATCH_ACTIVITY). Subscribers
+/// get an ActivityProto now and then, without having to open the documents.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct WatchActivityProto {
+    #[prost(bool, tag = "1")]
+    pub watch: bool,
+}
+/// The edits made to one document since the last ActivityProto.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct DocumentActivityProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// Ops applied.
+    #[prost(uint32, tag = "3")]
+    pub ops: u32,
+    /// Who made them, as members or connection ids, in the order they first
+    /// edited.
+    #[prost(string, repeated, tag = "4")]
+    pub editors: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// The document's version after the last of them.
+    #[prost(uint64, tag = "5")]
+    pub version: u64,
+}
+/// What happened in the workspace since the last ActivityProto (message type
+/// ACTIVITY), sent to connections watching its activity; nothing is sent
+/// while nothing happens.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActivityProto {
+    #[prost(message, repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<WorkspaceEventProto>,
+    #[prost(message, repeated, tag = "2")]
+    pub edits: ::prost::alloc::vec::Vec<DocumentActivityProto>,
+    /// Connections that joined or left the workspace, as members or
+    /// connection ids.
+    #[prost(string, repeated, tag = "3")]
+    pub joined: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "4")]
+    pub left: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// A freeze window the operator set on a workspace or one of its documents
+/// starting or ending (message type FREEZE), sent to every connection in the
+/// workspace, and to a connection opening a frozen document. While frozen,
+/// edits are refused with an ERROR_CODE_FROZEN error; syncs and presence are
+/// not affected.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct FreezeProto {
+    #[prost(string, tag = "1")]
+    pub workspace: ::prost::alloc::string::String,
+    /// The document frozen; empty for the whole workspace.
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// True when the window starts, false when it ends or is lifted.
+    #[prost(bool, tag = "3")]
+    pub frozen: bool,
+    /// In milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "4")]
+    pub starts_at_ms: u64,
+    /// 0 if it lasts until the operator lifts it.
+    #[prost(uint64, tag = "5")]
+    pub ends_at_ms: u64,
+}
+/// A value for the {{name}} placeholders of a template.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TemplateVariableProto {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub value: ::prost::alloc::string::String,
+}
+/// Creates the document at `path` from one of the server's named templates and
+/// opens it for the sending connection. Answered with a SyncDocumentProto for the
+/// new document, which carries its doc_id, or an ERROR_CODE_TEMPLATE_REJECTED error.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateFromTemplateProto {
+    #[prost(string, tag = "1")]
+    pub template: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "3")]
+    pub variables: ::prost::alloc::vec::Vec<TemplateVariableProto>,
+}
+/// Asks the server to commit its persisted documents to git now, in the
+/// repository it keeps their history in (message type CHECKPOINT); or, with a
+/// `doc_id`, to squash the operations of that open document up to its current
+/// version into a milestone labelled `message`. Edits based on an earlier
+/// version are rejected from then on. The server answers with a
+/// CheckpointProto of its own saying what it did, or an
+/// ERROR_CODE_CHECKPOINT_FAILED error.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CheckpointProto {
+    /// Summary line of the commit, or the milestone's label; empty for the
+    /// server's own commits.
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+    /// Set by the server: the new commit's id, or empty if nothing changed
+    /// since the last commit.
+    #[prost(string, tag = "2")]
+    pub commit: ::prost::alloc::string::String,
+    /// Set by the server: who edited the committed documents since the last
+    /// commit, by display name or client id.
+    #[prost(string, repeated, tag = "3")]
+    pub contributors: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// The document to squash the history of instead of committing.
+    #[prost(string, tag = "4")]
+    pub doc_id: ::prost::alloc::string::String,
+    /// Set by the server: the milestone the squash left.
+    #[prost(message, optional, tag = "5")]
+    pub milestone: ::core::option::Option<MilestoneProto>,
+}
+/// Forms a copy of a document can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ExportFormat {
+    /// The content as it is.
+    Plain = 0,
+    /// The content read as Markdown and rendered to a standalone HTML page.
+    Html = 1,
+}
+impl ExportFormat {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Plain => "EXPORT_FORMAT_PLAIN",
+            Self::Html => "EXPORT_FORMAT_HTML",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "EXPORT_FORMAT_PLAIN" => Some(Self::Plain),
+            "EXPORT_FORMAT_HTML" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SignalKind {
+    Unspecified = 0,
+    /// Wants the others' attention, optionally about a range.
+    RaiseHand = 1,
+    /// Draws the others' eyes to a range.
+    PointAtRange = 2,
+    /// An emoji reaction to a range.
+    Reaction = 3,
+}
+impl SignalKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "SIGNAL_KIND_UNSPECIFIED",
+            Self::RaiseHand => "SIGNAL_KIND_RAISE_HAND",
+            Self::PointAtRange => "SIGNAL_KIND_POINT_AT_RANGE",
+            Self::Reaction => "SIGNAL_KIND_REACTION",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SIGNAL_KIND_UNSPECIFIED" => Some(Self::Unspecified),
+            "SIGNAL_KIND_RAISE_HAND" => Some(Self::RaiseHand),
+            "SIGNAL_KIND_POINT_AT_RANGE" => Some(Self::PointAtRange),
+            "SIGNAL_KIND_REACTION" => Some(Self::Reaction),
+            _ => None,
+        }
+    }
+}
+/// What a WorkspaceEventProto records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum WorkspaceEventKind {
+    Unspecified = 0,
+    /// A document was opened for the first time, imported or created from a
+    /// template.
+    DocumentCreated = 1,
+    /// A member joined the workspace with their token.
+    MemberJoined = 2,
+    /// Someone was made a member.
+    MemberAdded = 3,
+    /// A member was removed.
+    MemberRemoved = 4,
+    /// A client committed the saved documents, or squashed a document's
+    /// history into a milestone.
+    CheckpointTaken = 5,
+}
+impl WorkspaceEventKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "WORKSPACE_EVENT_KIND_UNSPECIFIED",
+            Self::DocumentCreated => "WORKSPACE_EVENT_KIND_DOCUMENT_CREATED",
+            Self::MemberJoined => "WORKSPACE_EVENT_KIND_MEMBER_JOINED",
+            Self::MemberAdded => "WORKSPACE_EVENT_KIND_MEMBER_ADDED",
+            Self::MemberRemoved => "WORKSPACE_EVENT_KIND_MEMBER_REMOVED",
+            Self::CheckpointTaken => "WORKSPACE_EVENT_KIND_CHECKPOINT_TAKEN",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "WORKSPACE_EVENT_KIND_UNSPECIFIED" => Some(Self::Unspecified),
+            "WORKSPACE_EVENT_KIND_DOCUMENT_CREATED" => Some(Self::DocumentCreated),
+            "WORKSPACE_EVENT_KIND_MEMBER_JOINED" => Some(Self::MemberJoined),
+            "WORKSPACE_EVENT_KIND_MEMBER_ADDED" => Some(Self::MemberAdded),
+            "WORKSPACE_EVENT_KIND_MEMBER_REMOVED" => Some(Self::MemberRemoved),
+            "WORKSPACE_EVENT_KIND_CHECKPOINT_TAKEN" => Some(Self::CheckpointTaken),
+            _ => None,
+        }
+    }
+}
+/// First message a client sends after connecting.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct HelloProto {
+    #[prost(string, tag = "1")]
+    pub client_id: ::prost::alloc::string::String,
+    /// Human-readable name shown to collaborators.
+    #[prost(string, tag = "2")]
+    pub display_name: ::prost::alloc::string::String,
+    /// Document the client wants to open; empty means the server default.
+    #[prost(string, tag = "3")]
+    pub doc_path: ::prost::alloc::string::String,
+    /// Needed to join a workspace that has members: the token one of them
+    /// was given when invited (see MemberTokenProto), or a guest invite's
+    /// (see InviteProto). Ignored otherwise.
+    #[prost(string, tag = "4")]
+    pub auth_token: ::prost::alloc::string::String,
+    /// Client wall clock (ms since the Unix epoch) when the Hello was sent;
+    /// the server's reply sync carries its own clock for comparison.
+    #[prost(uint64, tag = "5")]
+    pub client_time_ms: u64,
+    /// Viewer connection: receives syncs but may not edit. Cannot be undone
+    /// by a later Hello on the same connection.
+    #[prost(bool, tag = "6")]
+    pub read_only: bool,
+    /// Ask for outbound sequence numbers: every later frame from the server
+    /// arrives wrapped with its number (message type SEQUENCED), so the client
+    /// can spot a gap and ask for a ResendProto instead of resyncing.
+    #[prost(bool, tag = "7")]
+    pub sequenced: bool,
+    /// What this client handles. Without them the server takes the client
+    /// to handle everything it sent before capabilities existed: batches
+    /// and presence, but none of the rest.
+    #[prost(message, optional, tag = "8")]
+    pub capabilities: ::core::option::Option<CapabilitiesProto>,
+    /// Workspace to join; empty for the server's default one. Workspaces
+    /// share nothing: the same path names a different document in each, and
+    /// presence and workspace versions stay within one. Cannot be changed by
+    /// a later Hello on the same connection.
+    #[prost(string, tag = "9")]
+    pub workspace: ::prost::alloc::string::String,
+    /// The protocol version the client speaks (`protocol::PROTOCOL_VERSION`);
+    /// 0 for clients from before versions were exchanged. A server pinned to
+    /// a newer minimum answers with a DiagnosticsProto and disconnects.
+    #[prost(uint32, tag = "10")]
+    pub protocol_version: u32,
+    /// The client's build, e.g. "dist-space-client/0.1.0", for the server's
+    /// logs.
+    #[prost(string, tag = "11")]
+    pub client_version: ::prost::alloc::string::String,
+}
+/// Asks for `member` to be made a member of the sender's workspace (message
+/// type INVITE_MEMBER). Only members may invite; the first member of a
+/// workspace is invited by the server operator. The server answers with a
+/// MemberTokenProto.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct InviteMemberProto {
+    #[prost(string, tag = "1")]
+    pub member: ::prost::alloc::string::String,
+}
+/// A member's token for a workspace (message type MEMBER_TOKEN), sent once,
+/// to whoever invited them. Whoever holds it joins the workspace as that
+/// member by sending it as the auth_token of their Hello.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct MemberTokenProto {
+    #[prost(string, tag = "1")]
+    pub workspace: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub member: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub token: ::prost::alloc::string::String,
+}
+/// Takes `member` out of the sender's workspace (message type
+/// REMOVE_MEMBER): their token stops working and their connections to the
+/// workspace are closed. Only members may remove members.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct RemoveMemberProto {
+    #[prost(string, tag = "1")]
+    pub member: ::prost::alloc::string::String,
+}
+/// Asks for a guest invite to one document of the sender's workspace
+/// (message type CREATE_INVITE). Only members may create them. The server
+/// answers with an InviteProto.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CreateInviteProto {
+    /// Document the guest may open; empty for the server default.
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// The guest may view the document but not edit it.
+    #[prost(bool, tag = "2")]
+    pub read_only: bool,
+    /// How long the invite is good for; 0 for a day. Capped at a week.
+    #[prost(uint64, tag = "3")]
+    pub ttl_ms: u64,
+}
+/// A guest invite (message type INVITE). Whoever holds the token joins the
+/// workspace as a guest by sending it as the auth_token of their Hello, and
+/// may then open `path` and nothing else. Once it expires the token is
+/// refused, and guests who joined with it can no longer open or edit.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct InviteProto {
+    #[prost(string, tag = "1")]
+    pub token: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub workspace: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(bool, tag = "4")]
+    pub read_only: bool,
+    /// Server wall clock, in ms since the Unix epoch.
+    #[prost(uint64, tag = "5")]
+    pub expires_at_ms: u64,
+}
+/// What one side of a connection handles. A client puts its own in its
+/// Hello; the server answers with its own (message type CAPABILITIES) ahead
+/// of the Hello's sync, and from then on sends that client only what it said
+/// it handles.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CapabilitiesProto {
+    /// OperationBatch frames. A client without them gets a batch as the
+    /// Operation frames it holds, one per op.
+    #[prost(bool, tag = "1")]
+    pub batches: bool,
+    /// Presence frames about other connections; dropped for a client
+    /// without them.
+    #[prost(bool, tag = "2")]
+    pub presence: bool,
+    /// Compressed payloads. Not implemented: the server answers false.
+    #[prost(bool, tag = "3")]
+    pub compression: bool,
+    /// Syncs carrying only what changed. Not implemented: the server answers
+    /// false and sends every client full syncs.
+    #[prost(bool, tag = "4")]
+    pub delta_sync: bool,
+    /// CRDT document state. Not implemented: the server answers false.
+    #[prost(bool, tag = "5")]
+    pub crdt: bool,
+}
+/// Sent by the server when it refuses a request or connection.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ErrorProto {
+    #[prost(enumeration = "ErrorCode", tag = "1")]
+    pub code: i32,
+    /// Human-readable detail for logs.
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// How long the client should wait before retrying; 0 means no hint.
+    #[prost(uint64, tag = "3")]
+    pub retry_after_ms: u64,
+    /// The rejected operation, for op-level errors; 0 otherwise.
+    #[prost(uint64, tag = "4")]
+    pub op_id: u64,
+}
+/// Last frame the server sends before closing a connection.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct DisconnectProto {
+    #[prost(enumeration = "DisconnectReason", tag = "1")]
+    pub reason_code: i32,
+    /// Human-readable detail to show the user.
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Tells a client to carry on with another server (message type REDIRECT):
+/// the replacement of one shutting down, which sends it once every document
+/// is saved to the storage they share, just before the Disconnect; or the
+/// server a document was moved to. Clients reconnect to `address` straight
+/// away and reopen their documents there.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct RedirectProto {
+    /// host:port of the server to go to.
+    #[prost(string, tag = "1")]
+    pub address: ::prost::alloc::string::String,
+    /// Human-readable detail to show the user.
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// The one document that moved; empty for every document.
+    #[prost(string, tag = "3")]
+    pub doc_id: ::prost::alloc::string::String,
+}
+/// An announcement from the server's operator (message type NOTICE), such as
+/// "maintenance in 5 minutes", sent to every connection or to every one in a
+/// workspace. Clients show it to the user.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct NoticeProto {
+    #[prost(string, tag = "1")]
+    pub message: ::prost::alloc::string::String,
+    /// The workspace it went to; empty when it went to every connection.
+    #[prost(string, tag = "2")]
+    pub workspace: ::prost::alloc::string::String,
+    /// When the operator sent it, in milliseconds since the Unix epoch.
+    #[prost(uint64, tag = "3")]
+    pub sent_at_ms: u64,
+}
+/// One exchange of clock readings (message type TIME_SYNC), all wall-clock ms
+/// since the Unix epoch. The client sends its clock; the server answers with
+/// the same message, adding its clock when the request arrived and when it
+/// answered. From the four readings the client works out the round trip and
+/// how far its clock is off the server's, and reports both in its next
+/// request, so the server's view of the connection's latency is not skewed
+/// by either clock.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct TimeSyncProto {
+    #[prost(uint64, tag = "1")]
+    pub client_send_ms: u64,
+    /// Set by the server.
+    #[prost(uint64, tag = "2")]
+    pub server_receive_ms: u64,
+    #[prost(uint64, tag = "3")]
+    pub server_send_ms: u64,
+    /// The client's latest round trip, not counting the server's own time;
+    /// 0 before it has one.
+    #[prost(uint64, tag = "4")]
+    pub rtt_ms: u64,
+    /// The client's latest estimate of the server's clock minus its own.
+    #[prost(sint64, tag = "5")]
+    pub offset_ms: i64,
+}
+/// When a client got and applied one of the ops it traces (see
+/// `clock::is_traced`), on its own clock.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct PropagationSampleProto {
+    #[prost(string, tag = "1")]
+    pub doc_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub server_version: u64,
+    /// The op's applied_at_ms, on the server's clock.
+    #[prost(uint64, tag = "3")]
+    pub applied_at_ms: u64,
+    #[prost(uint64, tag = "4")]
+    pub received_ms: u64,
+    #[prost(uint64, tag = "5")]
+    pub displayed_ms: u64,
+}
+/// Traced ops reported back by a client (message type PROPAGATION), for the
+/// server's per-connection propagation latency.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PropagationProto {
+    #[prost(message, repeated, tag = "1")]
+    pub samples: ::prost::alloc::vec::Vec<PropagationSampleProto>,
+}
+/// Why the server turned a client away, or what it could not make of a frame
+/// (message type DIAGNOSTICS), with what to do about it. A client turned
+/// away gets a Disconnect with DISCONNECT_REASON_INCOMPATIBLE next.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct DiagnosticsProto {
+    #[prost(enumeration = "DiagnosticKind", tag = "1")]
+    pub kind: i32,
+    /// Human-readable detail for logs.
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// What the user can do about it, e.g. which version to upgrade to.
+    #[prost(string, tag = "3")]
+    pub guidance: ::prost::alloc::string::String,
+    /// The protocol version the server speaks, and the oldest it accepts.
+    #[prost(uint32, tag = "4")]
+    pub protocol_version: u32,
+    #[prost(uint32, tag = "5")]
+    pub min_protocol_version: u32,
+    /// The server's build, e.g. "dist-space-server/0.1.0".
+    #[prost(string, tag = "6")]
+    pub server_version: ::prost::alloc::string::String,
+    /// For DIAGNOSTIC_KIND_MISSING_CAPABILITY: the CapabilitiesProto fields
+    /// the server requires that the Hello left false.
+    #[prost(string, repeated, tag = "7")]
+    pub missing_capabilities: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// For DIAGNOSTIC_KIND_UNKNOWN_MESSAGE: the type ID the server didn't know.
+    #[prost(uint32, tag = "8")]
+    pub message_type: u32,
+}
+/// Machine-readable reason carried by ErrorProto.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorCode {
+    Unspecified = 0,
+    /// The server is at its connection limit or shedding load; see retry_after_ms.
+    ServerFull = 1,
+    /// An index or range lies past the end of the document.
+    OpOutOfBounds = 2,
+    /// A range whose start is after its end.
+    OpInvalidRange = 3,
+    /// An index that splits a UTF-8 character.
+    OpNotCharBoundary = 4,
+    /// Inserted text exceeds the server's size cap.
+    OpTextTooLarge = 5,
+    /// The operation names a document that does not exist.
+    OpUnknownDocument = 6,
+    /// The operation targets a document the connection has not opened.
+    OpNotSubscribed = 7,
+    /// A DocumentArchiveProto was malformed or its path already holds a document.
+    ImportRejected = 8,
+    /// The connection is read-only and may not change documents.
+    ReadOnly = 9,
+    /// The edit or lock request overlaps a range locked by another connection.
+    RangeLocked = 10,
+    /// The op's client_version is newer than the document or older than its retained history.
+    OpUnknownVersion = 11,
+    /// The template does not exist, a placeholder has no value, or the path already holds a document.
+    TemplateRejected = 12,
+    /// Frames asked for by a ResendProto are no longer retained; reopen the documents instead.
+    ResendUnavailable = 13,
+    /// The edit brings in line endings of the style the document doesn't use.
+    OpLineEnding = 14,
+    /// The edit would grow the document past its max_bytes setting.
+    DocumentTooLarge = 15,
+    /// Text in the message is not valid UTF-8; strings must be sent as UTF-8.
+    InvalidText = 16,
+    /// A transaction sent in chunks grew past the server's limit; nothing was applied.
+    TransactionTooLarge = 17,
+    /// The workspace is at its limit of connections (in reply to a Hello) or
+    /// of documents (in reply to opening, importing or creating one).
+    WorkspaceFull = 18,
+    /// The workspace has members and the connection is not one of them: its
+    /// Hello carried no member's token, or it joined before the workspace
+    /// had members.
+    NotAMember = 19,
+    /// An invitation for someone already a member or without a name, or the
+    /// removal of someone who is not a member.
+    MembershipRejected = 20,
+    /// The guest invite in the Hello, or the one the connection joined with,
+    /// has expired.
+    InviteExpired = 21,
+    /// Guests may only open the document their invite names, and may not
+    /// import documents or create them from templates.
+    GuestRestricted = 22,
+    /// The server keeps no git history of its documents, committing them
+    /// failed, or a document checkpoint has no label.
+    CheckpointFailed = 23,
+    /// An attachment upload too large or over the server's quota, or a fetch
+    /// of an attachment the document does not reference.
+    AttachmentRejected = 24,
+    /// The connection asked to follow is not in the workspace, or is the
+    /// connection itself.
+    FollowRejected = 25,
+    /// A signal of no kind, or a reaction without exactly one short emoji.
+    SignalRejected = 26,
+    /// A tag without a name, with a name already in use or past the
+    /// document's limit, or a history request for a tag that does not exist.
+    TagRejected = 27,
+    /// An edit to a document the operator has frozen, on its own or with its
+    /// whole workspace.
+    Frozen = 28,
+    /// The connection sent ops faster than the server allows; see retry_after_ms.
+    RateLimited = 29,
+    /// One of the server's own op policies refused the edit; the message says which.
+    OpRefused = 30,
+    /// The server is shutting down and handing its documents over to another;
+    /// resend the edit there once the Redirect that follows arrives.
+    HandingOver = 31,
+    /// No document is at the path opened, and the server is configured not to
+    /// create documents on open.
+    NoSuchDocument = 32,
+    /// The document is at the last version it can reach and takes no more ops.
+    VersionExhausted = 33,
+    /// A rule of the deployment's does not let the sender make this edit; the
+    /// message says which.
+    OpUnauthorized = 34,
+    /// The edit would remove text from an append-only region of the
+    /// document, or the settings would lift or narrow one.
+    AppendOnly = 35,
+}
+impl ErrorCode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "ERROR_CODE_UNSPECIFIED",
+            Self::ServerFull => "ERROR_CODE_SERVER_FULL",
+            Self::OpOutOfBounds => "ERROR_CODE_OP_OUT_OF_BOUNDS",
+            Self::OpInvalidRange => "ERROR_CODE_OP_INVALID_RANGE",
+            Self::OpNotCharBoundary => "ERROR_CODE_OP_NOT_CHAR_BOUNDARY",
+            Self::OpTextTooLarge => "ERROR_CODE_OP_TEXT_TOO_LARGE",
+            Self::OpUnknownDocument => "ERROR_CODE_OP_UNKNOWN_DOCUMENT",
+            Self::OpNotSubscribed => "ERROR_CODE_OP_NOT_SUBSCRIBED",
+            Self::ImportRejected => "ERROR_CODE_IMPORT_REJECTED",
+            Self::ReadOnly => "ERROR_CODE_READ_ONLY",
+            Self::RangeLocked => "ERROR_CODE_RANGE_LOCKED",
+            Self::OpUnknownVersion => "ERROR_CODE_OP_UNKNOWN_VERSION",
+            Self::TemplateRejected => "ERROR_CODE_TEMPLATE_REJECTED",
+            Self::ResendUnavailable => "ERROR_CODE_RESEND_UNAVAILABLE",
+            Self::OpLineEnding => "ERROR_CODE_OP_LINE_ENDING",
+            Self::DocumentTooLarge => "ERROR_CODE_DOCUMENT_TOO_LARGE",
+            Self::InvalidText => "ERROR_CODE_INVALID_TEXT",
+            Self::TransactionTooLarge => "ERROR_CODE_TRANSACTION_TOO_LARGE",
+            Self::WorkspaceFull => "ERROR_CODE_WORKSPACE_FULL",
+            Self::NotAMember => "ERROR_CODE_NOT_A_MEMBER",
+            Self::MembershipRejected => "ERROR_CODE_MEMBERSHIP_REJECTED",
+            Self::InviteExpired => "ERROR_CODE_INVITE_EXPIRED",
+            Self::GuestRestricted => "ERROR_CODE_GUEST_RESTRICTED",
+            Self::CheckpointFailed => "ERROR_CODE_CHECKPOINT_FAILED",
+            Self::AttachmentRejected => "ERROR_CODE_ATTACHMENT_REJECTED",
+            Self::FollowRejected => "ERROR_CODE_FOLLOW_REJECTED",
+            Self::SignalRejected => "ERROR_CODE_SIGNAL_REJECTED",
+            Self::TagRejected => "ERROR_CODE_TAG_REJECTED",
+            Self::Frozen => "ERROR_CODE_FROZEN",
+            Self::RateLimited => "ERROR_CODE_RATE_LIMITED",
+            Self::OpRefused => "ERROR_CODE_OP_REFUSED",
+            Self::HandingOver => "ERROR_CODE_HANDING_OVER",
+            Self::NoSuchDocument => "ERROR_CODE_NO_SUCH_DOCUMENT",
+            Self::VersionExhausted => "ERROR_CODE_VERSION_EXHAUSTED",
+            Self::OpUnauthorized => "ERROR_CODE_OP_UNAUTHORIZED",
+            Self::AppendOnly => "ERROR_CODE_APPEND_ONLY",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ERROR_CODE_UNSPECIFIED" => Some(Self::Unspecified),
+            "ERROR_CODE_SERVER_FULL" => Some(Self::ServerFull),
+            "ERROR_CODE_OP_OUT_OF_BOUNDS" => Some(Self::OpOutOfBounds),
+            "ERROR_CODE_OP_INVALID_RANGE" => Some(Self::OpInvalidRange),
+            "ERROR_CODE_OP_NOT_CHAR_BOUNDARY" => Some(Self::OpNotCharBoundary),
+            "ERROR_CODE_OP_TEXT_TOO_LARGE" => Some(Self::OpTextTooLarge),
+            "ERROR_CODE_OP_UNKNOWN_DOCUMENT" => Some(Self::OpUnknownDocument),
+            "ERROR_CODE_OP_NOT_SUBSCRIBED" => Some(Self::OpNotSubscribed),
+            "ERROR_CODE_IMPORT_REJECTED" => Some(Self::ImportRejected),
+            "ERROR_CODE_READ_ONLY" => Some(Self::ReadOnly),
+            "ERROR_CODE_RANGE_LOCKED" => Some(Self::RangeLocked),
+            "ERROR_CODE_OP_UNKNOWN_VERSION" => Some(Self::OpUnknownVersion),
+            "ERROR_CODE_TEMPLATE_REJECTED" => Some(Self::TemplateRejected),
+            "ERROR_CODE_RESEND_UNAVAILABLE" => Some(Self::ResendUnavailable),
+            "ERROR_CODE_OP_LINE_ENDING" => Some(Self::OpLineEnding),
+            "ERROR_CODE_DOCUMENT_TOO_LARGE" => Some(Self::DocumentTooLarge),
+            "ERROR_CODE_INVALID_TEXT" => Some(Self::InvalidText),
+            "ERROR_CODE_TRANSACTION_TOO_LARGE" => Some(Self::TransactionTooLarge),
+            "ERROR_CODE_WORKSPACE_FULL" => Some(Self::WorkspaceFull),
+            "ERROR_CODE_NOT_A_MEMBER" => Some(Self::NotAMember),
+            "ERROR_CODE_MEMBERSHIP_REJECTED" => Some(Self::MembershipRejected),
+            "ERROR_CODE_INVITE_EXPIRED" => Some(Self::InviteExpired),
+            "ERROR_CODE_GUEST_RESTRICTED" => Some(Self::GuestRestricted),
+            "ERROR_CODE_CHECKPOINT_FAILED" => Some(Self::CheckpointFailed),
+            "ERROR_CODE_ATTACHMENT_REJECTED" => Some(Self::AttachmentRejected),
+            "ERROR_CODE_FOLLOW_REJECTED" => Some(Self::FollowRejected),
+            "ERROR_CODE_SIGNAL_REJECTED" => Some(Self::SignalRejected),
+            "ERROR_CODE_TAG_REJECTED" => Some(Self::TagRejected),
+            "ERROR_CODE_FROZEN" => Some(Self::Frozen),
+            "ERROR_CODE_RATE_LIMITED" => Some(Self::RateLimited),
+            "ERROR_CODE_OP_REFUSED" => Some(Self::OpRefused),
+            "ERROR_CODE_HANDING_OVER" => Some(Self::HandingOver),
+            "ERROR_CODE_NO_SUCH_DOCUMENT" => Some(Self::NoSuchDocument),
+            "ERROR_CODE_VERSION_EXHAUSTED" => Some(Self::VersionExhausted),
+            "ERROR_CODE_OP_UNAUTHORIZED" => Some(Self::OpUnauthorized),
+            "ERROR_CODE_APPEND_ONLY" => Some(Self::AppendOnly),
+            _ => None,
+        }
+    }
+}
+/// Why the server is closing a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DisconnectReason {
+    Unspecified = 0,
+    /// The server is at its connection limit; reconnecting later may succeed.
+    ServerFull = 1,
+    /// Removed by the server operator; do not reconnect automatically.
+    Kicked = 2,
+    /// The client sent something the server cannot accept (oversized or stalled frames).
+    ProtocolViolation = 3,
+    /// The server is shutting down.
+    Shutdown = 4,
+    /// The client missed too many heartbeats.
+    TimedOut = 5,
+    /// The client is not, or is no longer, a member of the workspace it
+    /// joined, or its guest invite has expired; reconnecting with the same
+    /// token will not succeed.
+    NotAMember = 6,
+    /// The client's protocol version or capabilities fall short of what the
+    /// server requires; the DiagnosticsProto sent before the Disconnect says
+    /// what to upgrade. Reconnecting unchanged will not succeed.
+    Incompatible = 7,
+}
+impl DisconnectReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "DISCONNECT_REASON_UNSPECIFIED",
+            Self::ServerFull => "DISCONNECT_REASON_SERVER_FULL",
+            Self::Kicked => "DISCONNECT_REASON_KICKED",
+            Self::ProtocolViolation => "DISCONNECT_REASON_PROTOCOL_VIOLATION",
+            Self::Shutdown => "DISCONNECT_REASON_SHUTDOWN",
+            Self::TimedOut => "DISCONNECT_REASON_TIMED_OUT",
+            Self::NotAMember => "DISCONNECT_REASON_NOT_A_MEMBER",
+            Self::Incompatible => "DISCONNECT_REASON_INCOMPATIBLE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DISCONNECT_REASON_UNSPECIFIED" => Some(Self::Unspecified),
+            "DISCONNECT_REASON_SERVER_FULL" => Some(Self::ServerFull),
+            "DISCONNECT_REASON_KICKED" => Some(Self::Kicked),
+            "DISCONNECT_REASON_PROTOCOL_VIOLATION" => Some(Self::ProtocolViolation),
+            "DISCONNECT_REASON_SHUTDOWN" => Some(Self::Shutdown),
+            "DISCONNECT_REASON_TIMED_OUT" => Some(Self::TimedOut),
+            "DISCONNECT_REASON_NOT_A_MEMBER" => Some(Self::NotAMember),
+            "DISCONNECT_REASON_INCOMPATIBLE" => Some(Self::Incompatible),
+            _ => None,
+        }
+    }
+}
+/// What a DiagnosticsProto is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DiagnosticKind {
+    Unspecified = 0,
+    /// The Hello's protocol_version is older than the server accepts.
+    ProtocolTooOld = 1,
+    /// The Hello's capabilities lack some the server requires.
+    MissingCapability = 2,
+    /// The client sent a message type the server does not know.
+    UnknownMessage = 3,
+}
+impl DiagnosticKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "DIAGNOSTIC_KIND_UNSPECIFIED",
+            Self::ProtocolTooOld => "DIAGNOSTIC_KIND_PROTOCOL_TOO_OLD",
+            Self::MissingCapability => "DIAGNOSTIC_KIND_MISSING_CAPABILITY",
+            Self::UnknownMessage => "DIAGNOSTIC_KIND_UNKNOWN_MESSAGE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DIAGNOSTIC_KIND_UNSPECIFIED" => Some(Self::Unspecified),
+            "DIAGNOSTIC_KIND_PROTOCOL_TOO_OLD" => Some(Self::ProtocolTooOld),
+            "DIAGNOSTIC_KIND_MISSING_CAPABILITY" => Some(Self::MissingCapability),
+            "DIAGNOSTIC_KIND_UNKNOWN_MESSAGE" => Some(Self::UnknownMessage),
+            _ => None,
+        }
+    }
+}
@@ -1,9 +1,20 @@
-use crate::proto::space::{OperationProto, SyncDocumentProto};
-use bytes::{Buf, BufMut, BytesMut};
+use crate::proto::space::{
+    AckProto, ActivityProto, AttachmentChunkProto, AttachmentProto, CapabilitiesProto,
+    CheckpointProto, CloseDocumentProto, CreateFromTemplateProto, CreateInviteProto, CreditProto,
+    DiagnosticsProto, DisconnectProto, DocumentArchiveProto, ErrorProto, ExportChunkProto,
+    ExportDocumentProto, ExportRequestProto, FetchAttachmentProto, FollowProto, FreezeProto,
+    GetHistoryProto, HelloProto, HistoryProto, InviteMemberProto, InviteProto, LockRangeProto,
+    MemberTokenProto, NoticeProto, OpenDocumentProto, OperationBatchProto, OperationProto,
+    OverlaysProto, PresenceProto, PropagationProto, RangeLocksProto, RedirectProto,
+    RemoveMemberProto, ResendProto, SetDocumentSettingsProto, SetOverlaysProto, SetPresenceProto,
+    SetViewportProto, SignalProto, SyncDocumentProto, TagVersionProto, TimeSyncProto,
+    UnlockRangeProto, UploadAttachmentProto, ViewportProto, WatchActivityProto,
+};
+use bytes::{Buf, BufMut, Bytes};
 use prost::Message;
-use std::io::Cursor;
 
 /// Server-to-client and client-to-server message types.
+#[derive(Debug)]
 pub enum ServerMessage {
     /// An operation (edit) to be applied.
     Operation(OperationProto),
@@ -13,13 +24,178 @@ pub enum ServerMessage {
     Ping(u64),
     /// Pong message - response to Ping with the same sequence number.
     Pong(u64),
+    /// Handshake - sent by the client right after connecting.
+    Hello(HelloProto),
+    /// Subscribe to a document by path; the server answers with a SyncDocument.
+    OpenDocument(OpenDocumentProto),
+    /// Unsubscribe from a document.
+    CloseDocument(CloseDocumentProto),
+    /// The server refused a request or the connection itself.
+    Error(ErrorProto),
+    /// Operations applied during one batching window, sent instead of
+    /// individual Operation frames when batching is enabled.
+    OperationBatch(OperationBatchProto),
+    /// Sent by the server just before it closes the connection.
+    Disconnect(DisconnectProto),
+    /// Ask for an open document's archive; answered with a DocumentArchive.
+    ExportDocument(ExportDocumentProto),
+    /// A document with its history: the reply to ExportDocument, or an
+    /// import request from a client (answered with a SyncDocument).
+    DocumentArchive(DocumentArchiveProto),
+    /// Claim an exclusive range of a document.
+    LockRange(LockRangeProto),
+    /// Release a range lock.
+    UnlockRange(UnlockRangeProto),
+    /// A document's current range locks, sent by the server.
+    RangeLocks(RangeLocksProto),
+    /// Client asks for a new document made from a named template.
+    CreateFromTemplate(CreateFromTemplateProto),
+    /// Mark the sending connection away, or back.
+    SetPresence(SetPresenceProto),
+    /// Another connection became active, idle or away, sent by the server.
+    Presence(PresenceProto),
+    /// Client asks for frames again from a sequence number on.
+    Resend(ResendProto),
+    /// A server frame with its number on the connection, for clients that
+    /// asked for sequencing in their Hello. Wraps the message as encoded.
+    Sequenced(u64, Box<ServerMessage>),
+    /// Client lets the server send more frames or bytes.
+    Credit(CreditProto),
+    /// Replace the sending connection's overlays of one kind on a document.
+    SetOverlays(SetOverlaysProto),
+    /// A document's current overlays, sent by the server.
+    Overlays(OverlaysProto),
+    /// Change an open document's settings; answered with a SyncDocument.
+    SetDocumentSettings(SetDocumentSettingsProto),
+    /// What the server handles, in answer to a Hello that said what the
+    /// client does.
+    Capabilities(CapabilitiesProto),
+    /// Make someone a member of the sending connection's workspace;
+    /// answered with a MemberToken.
+    InviteMember(InviteMemberProto),
+    /// The token an invited member joins the workspace with, sent by the
+    /// server.
+    MemberToken(MemberTokenProto),
+    /// Take someone out of the sending connection's workspace.
+    RemoveMember(RemoveMemberProto),
+    /// Ask for a guest invite to a document; answered with an Invite.
+    CreateInvite(CreateInviteProto),
+    /// A guest invite, sent by the server.
+    Invite(InviteProto),
+    /// Commit persisted documents to git: asked by a client, and answered
+    /// with what the server committed.
+    Checkpoint(CheckpointProto),
+    /// Ask for a copy of an open document in some format; answered with
+    /// ExportChunks.
+    ExportRequest(ExportRequestProto),
+    /// Part of an exported copy, sent by the server.
+    ExportChunk(ExportChunkProto),
+    /// Part of a file a client attaches to a document; answered with an
+    /// Attachment once the last part arrives.
+    UploadAttachment(UploadAttachmentProto),
+    /// The id an uploaded attachment is stored under, sent by the server.
+    Attachment(AttachmentProto),
+    /// Ask for an attachment a document references; answered with
+    /// AttachmentChunks.
+    FetchAttachment(FetchAttachmentProto),
+    /// Part of a fetched attachment, sent by the server.
+    AttachmentChunk(AttachmentChunkProto),
+    /// The lines a client has on screen, relayed to its followers.
+    SetViewport(SetViewportProto),
+    /// Follow another client's viewport, or stop following.
+    Follow(FollowProto),
+    /// A followed client's viewport, sent by the server.
+    Viewport(ViewportProto),
+    /// A raised hand, pointer or reaction on a range, broadcast to the
+    /// document's subscribers.
+    Signal(SignalProto),
+    /// Name the current version of a document.
+    TagVersion(TagVersionProto),
+    /// Ask for a document's milestones and tags, or its content at a tag.
+    GetHistory(GetHistoryProto),
+    /// A document's history, sent by the server.
+    History(HistoryProto),
+    /// A freeze window starting or ending, sent by the server.
+    Freeze(FreezeProto),
+    /// A clock reading from the client, answered by the server with its own.
+    TimeSync(TimeSyncProto),
+    /// When traced ops reached the client, sent by the client.
+    Propagation(PropagationProto),
+    /// Where to reconnect to, sent by a server handing its documents over.
+    Redirect(RedirectProto),
+    /// Why the server turned the client away, and what to do about it.
+    Diagnostics(DiagnosticsProto),
+    /// Subscribe to, or unsubscribe from, the workspace's activity feed.
+    WatchActivity(WatchActivityProto),
+    /// What happened in the workspace lately, for activity feed subscribers.
+    Activity(ActivityProto),
+    /// How far into a document the client has applied what it was sent.
+    Ack(AckProto),
+    /// An announcement from the server's operator.
+    Notice(NoticeProto),
 }
 
-/// Message type IDs for protocol encoding.
-const MSG_TYPE_OPERATION: u8 = 1;
-const MSG_TYPE_SYNC_DOCUMENT: u8 = 2;
-const MSG_TYPE_PING: u8 = 3;
-const MSG_TYPE_PONG: u8 = 4;
+/// Version of the protocol this build speaks, sent in the Hello. Bumped
+/// whenever a change would leave an older peer misreading frames.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Message type IDs for protocol encoding, also exposed on `Frame::type_id`.
+pub const MSG_TYPE_OPERATION: u8 = 1;
+pub const MSG_TYPE_SYNC_DOCUMENT: u8 = 2;
+pub const MSG_TYPE_PING: u8 = 3;
+pub const MSG_TYPE_PONG: u8 = 4;
+pub const MSG_TYPE_HELLO: u8 = 5;
+pub const MSG_TYPE_OPEN_DOCUMENT: u8 = 6;
+pub const MSG_TYPE_CLOSE_DOCUMENT: u8 = 7;
+pub const MSG_TYPE_ERROR: u8 = 8;
+pub const MSG_TYPE_OPERATION_BATCH: u8 = 9;
+pub const MSG_TYPE_DISCONNECT: u8 = 10;
+pub const MSG_TYPE_EXPORT_DOCUMENT: u8 = 11;
+pub const MSG_TYPE_DOCUMENT_ARCHIVE: u8 = 12;
+pub const MSG_TYPE_LOCK_RANGE: u8 = 13;
+pub const MSG_TYPE_UNLOCK_RANGE: u8 = 14;
+pub const MSG_TYPE_RANGE_LOCKS: u8 = 15;
+pub const MSG_TYPE_CREATE_FROM_TEMPLATE: u8 = 16;
+pub const MSG_TYPE_SET_PRESENCE: u8 = 17;
+pub const MSG_TYPE_PRESENCE: u8 = 18;
+pub const MSG_TYPE_RESEND: u8 = 19;
+pub const MSG_TYPE_SEQUENCED: u8 = 20;
+pub const MSG_TYPE_CREDIT: u8 = 21;
+pub const MSG_TYPE_SET_OVERLAYS: u8 = 22;
+pub const MSG_TYPE_OVERLAYS: u8 = 23;
+pub const MSG_TYPE_SET_DOCUMENT_SETTINGS: u8 = 24;
+pub const MSG_TYPE_CAPABILITIES: u8 = 25;
+pub const MSG_TYPE_INVITE_MEMBER: u8 = 26;
+pub const MSG_TYPE_MEMBER_TOKEN: u8 = 27;
+pub const MSG_TYPE_REMOVE_MEMBER: u8 = 28;
+pub const MSG_TYPE_CREATE_INVITE: u8 = 29;
+pub const MSG_TYPE_INVITE: u8 = 30;
+pub const MSG_TYPE_CHECKPOINT: u8 = 31;
+pub const MSG_TYPE_EXPORT_REQUEST: u8 = 32;
+pub const MSG_TYPE_EXPORT_CHUNK: u8 = 33;
+pub const MSG_TYPE_UPLOAD_ATTACHMENT: u8 = 34;
+pub const MSG_TYPE_ATTACHMENT: u8 = 35;
+pub const MSG_TYPE_FETCH_ATTACHMENT: u8 = 36;
+pub const MSG_TYPE_ATTACHMENT_CHUNK: u8 = 37;
+pub const MSG_TYPE_SET_VIEWPORT: u8 = 38;
+pub const MSG_TYPE_FOLLOW: u8 = 39;
+pub const MSG_TYPE_VIEWPORT: u8 = 40;
+pub const MSG_TYPE_SIGNAL: u8 = 41;
+pub const MSG_TYPE_TAG_VERSION: u8 = 42;
+pub const MSG_TYPE_GET_HISTORY: u8 = 43;
+pub const MSG_TYPE_HISTORY: u8 = 44;
+pub const MSG_TYPE_FREEZE: u8 = 45;
+pub const MSG_TYPE_TIME_SYNC: u8 = 46;
+pub const MSG_TYPE_PROPAGATION: u8 = 47;
+pub const MSG_TYPE_REDIRECT: u8 = 48;
+pub const MSG_TYPE_DIAGNOSTICS: u8 = 49;
+pub const MSG_TYPE_WATCH_ACTIVITY: u8 = 50;
+pub const MSG_TYPE_ACTIVITY: u8 = 51;
+pub const MSG_TYPE_ACK: u8 = 52;
+pub const MSG_TYPE_NOTICE: u8 = 53;
+
+/// Bytes before the protobuf payload: u32 length + u8 type ID.
+const HEADER_LEN: usize = 5;
 
 impl ServerMessage {
     /// Serializes the inner Protobuf message and wraps it in a length-prefixed buffer with a type ID.
@@ -38,71 +214,422 @@ impl ServerMessage {
                 // Encode as 8 bytes (u64)
                 (MSG_TYPE_PONG, seq.to_be_bytes().to_vec())
             }
+            ServerMessage::Hello(hello_proto) => (MSG_TYPE_HELLO, hello_proto.encode_to_vec()),
+            ServerMessage::OpenDocument(open_proto) => {
+                (MSG_TYPE_OPEN_DOCUMENT, open_proto.encode_to_vec())
+            }
+            ServerMessage::CloseDocument(close_proto) => {
+                (MSG_TYPE_CLOSE_DOCUMENT, close_proto.encode_to_vec())
+            }
+            ServerMessage::Error(error_proto) => (MSG_TYPE_ERROR, error_proto.encode_to_vec()),
+            ServerMessage::OperationBatch(batch_proto) => {
+                (MSG_TYPE_OPERATION_BATCH, batch_proto.encode_to_vec())
+            }
+            ServerMessage::Disconnect(disconnect_proto) => {
+                (MSG_TYPE_DISCONNECT, disconnect_proto.encode_to_vec())
+            }
+            ServerMessage::ExportDocument(export_proto) => {
+                (MSG_TYPE_EXPORT_DOCUMENT, export_proto.encode_to_vec())
+            }
+            ServerMessage::DocumentArchive(archive_proto) => {
+                (MSG_TYPE_DOCUMENT_ARCHIVE, archive_proto.encode_to_vec())
+            }
+            ServerMessage::LockRange(lock_range_proto) => {
+                (MSG_TYPE_LOCK_RANGE, lock_range_proto.encode_to_vec())
+            }
+            ServerMessage::UnlockRange(unlock_range_proto) => {
+                (MSG_TYPE_UNLOCK_RANGE, unlock_range_proto.encode_to_vec())
+            }
+            ServerMessage::RangeLocks(range_locks_proto) => {
+                (MSG_TYPE_RANGE_LOCKS, range_locks_proto.encode_to_vec())
+            }
+            ServerMessage::CreateFromTemplate(create_from_template_proto) => (
+                MSG_TYPE_CREATE_FROM_TEMPLATE,
+                create_from_template_proto.encode_to_vec(),
+            ),
+            ServerMessage::SetPresence(set_presence_proto) => {
+                (MSG_TYPE_SET_PRESENCE, set_presence_proto.encode_to_vec())
+            }
+            ServerMessage::Presence(presence_proto) => {
+                (MSG_TYPE_PRESENCE, presence_proto.encode_to_vec())
+            }
+            ServerMessage::Resend(resend_proto) => (MSG_TYPE_RESEND, resend_proto.encode_to_vec()),
+            ServerMessage::Sequenced(seq, message) => {
+                // Encode as 8 bytes (u64) followed by the whole wrapped message
+                let mut body = seq.to_be_bytes().to_vec();
+                body.extend_from_slice(&message.encode());
+                (MSG_TYPE_SEQUENCED, body)
+            }
+            ServerMessage::Credit(credit_proto) => (MSG_TYPE_CREDIT, credit_proto.encode_to_vec()),
+            ServerMessage::SetOverlays(set_overlays_proto) => {
+                (MSG_TYPE_SET_OVERLAYS, set_overlays_proto.encode_to_vec())
+            }
+            ServerMessage::Overlays(overlays_proto) => {
+                (MSG_TYPE_OVERLAYS, overlays_proto.encode_to_vec())
+            }
+            ServerMessage::SetDocumentSettings(set_document_settings_proto) => (
+                MSG_TYPE_SET_DOCUMENT_SETTINGS,
+                set_document_settings_proto.encode_to_vec(),
+            ),
+            ServerMessage::Capabilities(capabilities_proto) => {
+                (MSG_TYPE_CAPABILITIES, capabilities_proto.encode_to_vec())
+            }
+            ServerMessage::InviteMember(invite_member_proto) => {
+                (MSG_TYPE_INVITE_MEMBER, invite_member_proto.encode_to_vec())
+            }
+            ServerMessage::MemberToken(member_token_proto) => {
+                (MSG_TYPE_MEMBER_TOKEN, member_token_proto.encode_to_vec())
+            }
+            ServerMessage::RemoveMember(remove_member_proto) => {
+                (MSG_TYPE_REMOVE_MEMBER, remove_member_proto.encode_to_vec())
+            }
+            ServerMessage::CreateInvite(create_invite_proto) => {
+                (MSG_TYPE_CREATE_INVITE, create_invite_proto.encode_to_vec())
+            }
+            ServerMessage::Invite(invite_proto) => (MSG_TYPE_INVITE, invite_proto.encode_to_vec()),
+            ServerMessage::Checkpoint(checkpoint_proto) => {
+                (MSG_TYPE_CHECKPOINT, checkpoint_proto.encode_to_vec())
+            }
+            ServerMessage::ExportRequest(export_request_proto) => (
+                MSG_TYPE_EXPORT_REQUEST,
+                export_request_proto.encode_to_vec(),
+            ),
+            ServerMessage::ExportChunk(export_chunk_proto) => {
+                (MSG_TYPE_EXPORT_CHUNK, export_chunk_proto.encode_to_vec())
+            }
+            ServerMessage::UploadAttachment(upload_attachment_proto) => (
+                MSG_TYPE_UPLOAD_ATTACHMENT,
+                upload_attachment_proto.encode_to_vec(),
+            ),
+            ServerMessage::Attachment(attachment_proto) => {
+                (MSG_TYPE_ATTACHMENT, attachment_proto.encode_to_vec())
+            }
+            ServerMessage::FetchAttachment(fetch_attachment_proto) => (
+                MSG_TYPE_FETCH_ATTACHMENT,
+                fetch_attachment_proto.encode_to_vec(),
+            ),
+            ServerMessage::AttachmentChunk(attachment_chunk_proto) => (
+                MSG_TYPE_ATTACHMENT_CHUNK,
+                attachment_chunk_proto.encode_to_vec(),
+            ),
+            ServerMessage::SetViewport(set_viewport_proto) => {
+                (MSG_TYPE_SET_VIEWPORT, set_viewport_proto.encode_to_vec())
+            }
+            ServerMessage::Follow(follow_proto) => (MSG_TYPE_FOLLOW, follow_proto.encode_to_vec()),
+            ServerMessage::Viewport(viewport_proto) => {
+                (MSG_TYPE_VIEWPORT, viewport_proto.encode_to_vec())
+            }
+            ServerMessage::Signal(signal_proto) => (MSG_TYPE_SIGNAL, signal_proto.encode_to_vec()),
+            ServerMessage::TagVersion(tag_version_proto) => {
+                (MSG_TYPE_TAG_VERSION, tag_version_proto.encode_to_vec())
+            }
+            ServerMessage::GetHistory(get_history_proto) => {
+                (MSG_TYPE_GET_HISTORY, get_history_proto.encode_to_vec())
+            }
+            ServerMessage::History(history_proto) => {
+                (MSG_TYPE_HISTORY, history_proto.encode_to_vec())
+            }
+            ServerMessage::Freeze(freeze_proto) => (MSG_TYPE_FREEZE, freeze_proto.encode_to_vec()),
+            ServerMessage::TimeSync(time_sync_proto) => {
+                (MSG_TYPE_TIME_SYNC, time_sync_proto.encode_to_vec())
+            }
+            ServerMessage::Propagation(propagation_proto) => {
+                (MSG_TYPE_PROPAGATION, propagation_proto.encode_to_vec())
+            }
+            ServerMessage::Redirect(redirect_proto) => {
+                (MSG_TYPE_REDIRECT, redirect_proto.encode_to_vec())
+            }
+            ServerMessage::Diagnostics(diagnostics_proto) => {
+                (MSG_TYPE_DIAGNOSTICS, diagnostics_proto.encode_to_vec())
+            }
+            ServerMessage::WatchActivity(watch_activity_proto) => (
+                MSG_TYPE_WATCH_ACTIVITY,
+                watch_activity_proto.encode_to_vec(),
+            ),
+            ServerMessage::Activity(activity_proto) => {
+                (MSG_TYPE_ACTIVITY, activity_proto.encode_to_vec())
+            }
+            ServerMessage::Ack(ack_proto) => (MSG_TYPE_ACK, ack_proto.encode_to_vec()),
+            ServerMessage::Notice(notice_proto) => (MSG_TYPE_NOTICE, notice_proto.encode_to_vec()),
         };
 
         // Total length includes the 1-byte type_id + the payload length
         let total_payload_length = (serialized_payload.len() + 1) as u32;
 
         // Buffer capacity needed: 4 bytes for the length prefix + the data itself
-        let mut buffer = BytesMut::with_capacity(4 + total_payload_length as usize);
+        let mut buffer = Vec::with_capacity(4 + total_payload_length as usize);
 
         buffer.put_u32(total_payload_length); // Write the length
         buffer.put_u8(type_id); // Write the type ID
         buffer.put(serialized_payload.as_slice()); // Write the data
 
-        buffer.freeze().to_vec()
+        buffer
     }
 
     /// Deserializes a raw byte slice (from a Frame payload) into a ServerMessage enum variant.
     /// This function reads the type ID to know which protobuf struct to decode into.
     pub fn decode(frame_bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
-        // We use a Cursor to track our position as we read through the bytes
-        let mut cursor = Cursor::new(frame_bytes);
+        let (type_id, payload_slice) = Self::split_header(frame_bytes)?;
+        Self::decode_payload(type_id, payload_slice)
+    }
+
+    /// Like `decode`, but takes a shared buffer: the protobuf payload is decoded
+    /// straight from a subslice of `frame_bytes` without copying it first.
+    pub fn decode_bytes(frame_bytes: &Bytes) -> Result<Self, Box<dyn std::error::Error>> {
+        let (type_id, _) = Self::split_header(frame_bytes)?;
+        Self::decode_payload(type_id, frame_bytes.slice(HEADER_LEN..))
+    }
+
+    /// Reads the header ([u32 total length][u8 type_id]) and returns the type ID
+    /// and the remaining protobuf payload.
+    fn split_header(frame_bytes: &[u8]) -> Result<(u8, &[u8]), Box<dyn std::error::Error>> {
+        if frame_bytes.len() < HEADER_LEN {
+            return Err(format!("Frame too short: {} bytes", frame_bytes.len()).into());
+        }
+
+        let mut header = &frame_bytes[..HEADER_LEN];
 
         // Read the total length
-        let _total_length = cursor.get_u32();
+        let _total_length = header.get_u32();
 
         // Read the type ID discriminator
-        let type_id = cursor.get_u8();
+        let type_id = header.get_u8();
 
-        // The remaining bytes are the actual protobuf payload
-        // We create a slice of the bytes starting from the cursor's current position
-        let payload_slice = &frame_bytes[cursor.position() as usize..];
+        Ok((type_id, &frame_bytes[HEADER_LEN..]))
+    }
 
+    fn decode_payload(
+        type_id: u8,
+        mut payload: impl Buf,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         match type_id {
             MSG_TYPE_OPERATION => {
                 // Decode as OperationProto
-                let proto = OperationProto::decode(payload_slice)?;
+                let proto = OperationProto::decode(payload)?;
                 Ok(ServerMessage::Operation(proto))
             }
             MSG_TYPE_SYNC_DOCUMENT => {
                 // Decode as SyncDocumentProto
-                let proto = SyncDocumentProto::decode(payload_slice)?;
+                let proto = SyncDocumentProto::decode(payload)?;
                 Ok(ServerMessage::SyncDocument(proto))
             }
             MSG_TYPE_PING => {
                 // Decode sequence number
-                if payload_slice.len() < 8 {
+                if payload.remaining() < 8 {
                     return Err("Ping payload too short".into());
                 }
-                let seq = u64::from_be_bytes(
-                    payload_slice[..8]
-                        .try_into()
-                        .map_err(|_| "Invalid ping payload")?,
-                );
-                Ok(ServerMessage::Ping(seq))
+                Ok(ServerMessage::Ping(payload.get_u64()))
             }
             MSG_TYPE_PONG => {
                 // Decode sequence number
-                if payload_slice.len() < 8 {
+                if payload.remaining() < 8 {
                     return Err("Pong payload too short".into());
                 }
-                let seq = u64::from_be_bytes(
-                    payload_slice[..8]
-                        .try_into()
-                        .map_err(|_| "Invalid pong payload")?,
-                );
-                Ok(ServerMessage::Pong(seq))
+                Ok(ServerMessage::Pong(payload.get_u64()))
+            }
+            MSG_TYPE_HELLO => {
+                // Decode as HelloProto
+                let proto = HelloProto::decode(payload)?;
+                Ok(ServerMessage::Hello(proto))
+            }
+            MSG_TYPE_OPEN_DOCUMENT => {
+                let proto = OpenDocumentProto::decode(payload)?;
+                Ok(ServerMessage::OpenDocument(proto))
+            }
+            MSG_TYPE_CLOSE_DOCUMENT => {
+                let proto = CloseDocumentProto::decode(payload)?;
+                Ok(ServerMessage::CloseDocument(proto))
+            }
+            MSG_TYPE_ERROR => {
+                let proto = ErrorProto::decode(payload)?;
+                Ok(ServerMessage::Error(proto))
+            }
+            MSG_TYPE_OPERATION_BATCH => {
+                let proto = OperationBatchProto::decode(payload)?;
+                Ok(ServerMessage::OperationBatch(proto))
+            }
+            MSG_TYPE_DISCONNECT => {
+                let proto = DisconnectProto::decode(payload)?;
+                Ok(ServerMessage::Disconnect(proto))
+            }
+            MSG_TYPE_EXPORT_DOCUMENT => {
+                let proto = ExportDocumentProto::decode(payload)?;
+                Ok(ServerMessage::ExportDocument(proto))
+            }
+            MSG_TYPE_DOCUMENT_ARCHIVE => {
+                let proto = DocumentArchiveProto::decode(payload)?;
+                Ok(ServerMessage::DocumentArchive(proto))
+            }
+            MSG_TYPE_LOCK_RANGE => {
+                let proto = LockRangeProto::decode(payload)?;
+                Ok(ServerMessage::LockRange(proto))
+            }
+            MSG_TYPE_UNLOCK_RANGE => {
+                let proto = UnlockRangeProto::decode(payload)?;
+                Ok(ServerMessage::UnlockRange(proto))
+            }
+            MSG_TYPE_RANGE_LOCKS => {
+                let proto = RangeLocksProto::decode(payload)?;
+                Ok(ServerMessage::RangeLocks(proto))
+            }
+            MSG_TYPE_CREATE_FROM_TEMPLATE => {
+                let proto = CreateFromTemplateProto::decode(payload)?;
+                Ok(ServerMessage::CreateFromTemplate(proto))
+            }
+            MSG_TYPE_SET_PRESENCE => {
+                let proto = SetPresenceProto::decode(payload)?;
+                Ok(ServerMessage::SetPresence(proto))
+            }
+            MSG_TYPE_PRESENCE => {
+                let proto = PresenceProto::decode(payload)?;
+                Ok(ServerMessage::Presence(proto))
+            }
+            MSG_TYPE_RESEND => {
+                let proto = ResendProto::decode(payload)?;
+                Ok(ServerMessage::Resend(proto))
+            }
+            MSG_TYPE_SEQUENCED => {
+                if payload.remaining() < 8 {
+                    return Err("Sequenced payload too short".into());
+                }
+                let seq = payload.get_u64();
+                let message = payload.copy_to_bytes(payload.remaining());
+                Ok(ServerMessage::Sequenced(
+                    seq,
+                    Box::new(Self::decode_bytes(&message)?),
+                ))
+            }
+            MSG_TYPE_CREDIT => {
+                let proto = CreditProto::decode(payload)?;
+                Ok(ServerMessage::Credit(proto))
+            }
+            MSG_TYPE_SET_OVERLAYS => {
+                let proto = SetOverlaysProto::decode(payload)?;
+                Ok(ServerMessage::SetOverlays(proto))
+            }
+            MSG_TYPE_OVERLAYS => {
+                let proto = OverlaysProto::decode(payload)?;
+                Ok(ServerMessage::Overlays(proto))
+            }
+            MSG_TYPE_SET_DOCUMENT_SETTINGS => {
+                let proto = SetDocumentSettingsProto::decode(payload)?;
+                Ok(ServerMessage::SetDocumentSettings(proto))
+            }
+            MSG_TYPE_CAPABILITIES => {
+                let proto = CapabilitiesProto::decode(payload)?;
+                Ok(ServerMessage::Capabilities(proto))
+            }
+            MSG_TYPE_INVITE_MEMBER => {
+                let proto = InviteMemberProto::decode(payload)?;
+                Ok(ServerMessage::InviteMember(proto))
+            }
+            MSG_TYPE_MEMBER_TOKEN => {
+                let proto = MemberTokenProto::decode(payload)?;
+                Ok(ServerMessage::MemberToken(proto))
+            }
+            MSG_TYPE_REMOVE_MEMBER => {
+                let proto = RemoveMemberProto::decode(payload)?;
+                Ok(ServerMessage::RemoveMember(proto))
+            }
+            MSG_TYPE_CREATE_INVITE => {
+                let proto = CreateInviteProto::decode(payload)?;
+                Ok(ServerMessage::CreateInvite(proto))
+            }
+            MSG_TYPE_INVITE => {
+                let proto = InviteProto::decode(payload)?;
+                Ok(ServerMessage::Invite(proto))
+            }
+            MSG_TYPE_CHECKPOINT => {
+                let proto = CheckpointProto::decode(payload)?;
+                Ok(ServerMessage::Checkpoint(proto))
+            }
+            MSG_TYPE_EXPORT_REQUEST => {
+                let proto = ExportRequestProto::decode(payload)?;
+                Ok(ServerMessage::ExportRequest(proto))
+            }
+            MSG_TYPE_EXPORT_CHUNK => {
+                let proto = ExportChunkProto::decode(payload)?;
+                Ok(ServerMessage::ExportChunk(proto))
+            }
+            MSG_TYPE_UPLOAD_ATTACHMENT => {
+                let proto = UploadAttachmentProto::decode(payload)?;
+                Ok(ServerMessage::UploadAttachment(proto))
+            }
+            MSG_TYPE_ATTACHMENT => {
+                let proto = AttachmentProto::decode(payload)?;
+                Ok(ServerMessage::Attachment(proto))
+            }
+            MSG_TYPE_FETCH_ATTACHMENT => {
+                let proto = FetchAttachmentProto::decode(payload)?;
+                Ok(ServerMessage::FetchAttachment(proto))
+            }
+            MSG_TYPE_ATTACHMENT_CHUNK => {
+                let proto = AttachmentChunkProto::decode(payload)?;
+                Ok(ServerMessage::AttachmentChunk(proto))
+            }
+            MSG_TYPE_SET_VIEWPORT => {
+                let proto = SetViewportProto::decode(payload)?;
+                Ok(ServerMessage::SetViewport(proto))
+            }
+            MSG_TYPE_FOLLOW => {
+                let proto = FollowProto::decode(payload)?;
+                Ok(ServerMessage::Follow(proto))
+            }
+            MSG_TYPE_VIEWPORT => {
+                let proto = ViewportProto::decode(payload)?;
+                Ok(ServerMessage::Viewport(proto))
+            }
+            MSG_TYPE_SIGNAL => {
+                let proto = SignalProto::decode(payload)?;
+                Ok(ServerMessage::Signal(proto))
+            }
+            MSG_TYPE_TAG_VERSION => {
+                let proto = TagVersionProto::decode(payload)?;
+                Ok(ServerMessage::TagVersion(proto))
+            }
+            MSG_TYPE_GET_HISTORY => {
+                let proto = GetHistoryProto::decode(payload)?;
+                Ok(ServerMessage::GetHistory(proto))
+            }
+            MSG_TYPE_HISTORY => {
+                let proto = HistoryProto::decode(payload)?;
+                Ok(ServerMessage::History(proto))
+            }
+            MSG_TYPE_FREEZE => {
+                let proto = FreezeProto::decode(payload)?;
+                Ok(ServerMessage::Freeze(proto))
+            }
+            MSG_TYPE_TIME_SYNC => {
+                let proto = TimeSyncProto::decode(payload)?;
+                Ok(ServerMessage::TimeSync(proto))
+            }
+            MSG_TYPE_PROPAGATION => {
+                let proto = PropagationProto::decode(payload)?;
+                Ok(ServerMessage::Propagation(proto))
+            }
+            MSG_TYPE_REDIRECT => {
+                let proto = RedirectProto::decode(payload)?;
+                Ok(ServerMessage::Redirect(proto))
+            }
+            MSG_TYPE_DIAGNOSTICS => {
+                let proto = DiagnosticsProto::decode(payload)?;
+                Ok(ServerMessage::Diagnostics(proto))
+            }
+            MSG_TYPE_WATCH_ACTIVITY => {
+                let proto = WatchActivityProto::decode(payload)?;
+                Ok(ServerMessage::WatchActivity(proto))
+            }
+            MSG_TYPE_ACTIVITY => {
+                let proto = ActivityProto::decode(payload)?;
+                Ok(ServerMessage::Activity(proto))
+            }
+            MSG_TYPE_ACK => {
+                let proto = AckProto::decode(payload)?;
+                Ok(ServerMessage::Ack(proto))
+            }
+            MSG_TYPE_NOTICE => {
+                let proto = NoticeProto::decode(payload)?;
+                Ok(ServerMessage::Notice(proto))
             }
             _ => Err(format!("Unknown message type ID: {}", type_id).into()),
         }
@@ -114,6 +641,269 @@ impl ServerMessage {
             ServerMessage::SyncDocument(_) => MSG_TYPE_SYNC_DOCUMENT,
             ServerMessage::Ping(_) => MSG_TYPE_PING,
             ServerMessage::Pong(_) => MSG_TYPE_PONG,
+            ServerMessage::Hello(_) => MSG_TYPE_HELLO,
+            ServerMessage::OpenDocument(_) => MSG_TYPE_OPEN_DOCUMENT,
+            ServerMessage::CloseDocument(_) => MSG_TYPE_CLOSE_DOCUMENT,
+            ServerMessage::Error(_) => MSG_TYPE_ERROR,
+            ServerMessage::OperationBatch(_) => MSG_TYPE_OPERATION_BATCH,
+            ServerMessage::Disconnect(_) => MSG_TYPE_DISCONNECT,
+            ServerMessage::ExportDocument(_) => MSG_TYPE_EXPORT_DOCUMENT,
+            ServerMessage::DocumentArchive(_) => MSG_TYPE_DOCUMENT_ARCHIVE,
+            ServerMessage::LockRange(_) => MSG_TYPE_LOCK_RANGE,
+            ServerMessage::UnlockRange(_) => MSG_TYPE_UNLOCK_RANGE,
+            ServerMessage::RangeLocks(_) => MSG_TYPE_RANGE_LOCKS,
+            ServerMessage::CreateFromTemplate(_) => MSG_TYPE_CREATE_FROM_TEMPLATE,
+            ServerMessage::SetPresence(_) => MSG_TYPE_SET_PRESENCE,
+            ServerMessage::Presence(_) => MSG_TYPE_PRESENCE,
+            ServerMessage::Resend(_) => MSG_TYPE_RESEND,
+            ServerMessage::Sequenced(..) => MSG_TYPE_SEQUENCED,
+            ServerMessage::Credit(_) => MSG_TYPE_CREDIT,
+            ServerMessage::SetOverlays(_) => MSG_TYPE_SET_OVERLAYS,
+            ServerMessage::Overlays(_) => MSG_TYPE_OVERLAYS,
+            ServerMessage::SetDocumentSettings(_) => MSG_TYPE_SET_DOCUMENT_SETTINGS,
+            ServerMessage::Capabilities(_) => MSG_TYPE_CAPABILITIES,
+            ServerMessage::InviteMember(_) => MSG_TYPE_INVITE_MEMBER,
+            ServerMessage::MemberToken(_) => MSG_TYPE_MEMBER_TOKEN,
+            ServerMessage::RemoveMember(_) => MSG_TYPE_REMOVE_MEMBER,
+            ServerMessage::CreateInvite(_) => MSG_TYPE_CREATE_INVITE,
+            ServerMessage::Invite(_) => MSG_TYPE_INVITE,
+            ServerMessage::Checkpoint(_) => MSG_TYPE_CHECKPOINT,
+            ServerMessage::ExportRequest(_) => MSG_TYPE_EXPORT_REQUEST,
+            ServerMessage::ExportChunk(_) => MSG_TYPE_EXPORT_CHUNK,
+            ServerMessage::UploadAttachment(_) => MSG_TYPE_UPLOAD_ATTACHMENT,
+            ServerMessage::Attachment(_) => MSG_TYPE_ATTACHMENT,
+            ServerMessage::FetchAttachment(_) => MSG_TYPE_FETCH_ATTACHMENT,
+            ServerMessage::AttachmentChunk(_) => MSG_TYPE_ATTACHMENT_CHUNK,
+            ServerMessage::SetViewport(_) => MSG_TYPE_SET_VIEWPORT,
+            ServerMessage::Follow(_) => MSG_TYPE_FOLLOW,
+            ServerMessage::Viewport(_) => MSG_TYPE_VIEWPORT,
+            ServerMessage::Signal(_) => MSG_TYPE_SIGNAL,
+            ServerMessage::TagVersion(_) => MSG_TYPE_TAG_VERSION,
+            ServerMessage::GetHistory(_) => MSG_TYPE_GET_HISTORY,
+            ServerMessage::History(_) => MSG_TYPE_HISTORY,
+            ServerMessage::Freeze(_) => MSG_TYPE_FREEZE,
+            ServerMessage::TimeSync(_) => MSG_TYPE_TIME_SYNC,
+            ServerMessage::Propagation(_) => MSG_TYPE_PROPAGATION,
+            ServerMessage::Redirect(_) => MSG_TYPE_REDIRECT,
+            ServerMessage::Diagnostics(_) => MSG_TYPE_DIAGNOSTICS,
+            ServerMessage::WatchActivity(_) => MSG_TYPE_WATCH_ACTIVITY,
+            ServerMessage::Activity(_) => MSG_TYPE_ACTIVITY,
+            ServerMessage::Ack(_) => MSG_TYPE_ACK,
+            ServerMessage::Notice(_) => MSG_TYPE_NOTICE,
+        }
+    }
+}
+
+/// Name of a message type ID, for logs and diagnostics.
+pub fn message_type_name(type_id: u8) -> &'static str {
+    match type_id {
+        MSG_TYPE_OPERATION => "Operation",
+        MSG_TYPE_SYNC_DOCUMENT => "SyncDocument",
+        MSG_TYPE_PING => "Ping",
+        MSG_TYPE_PONG => "Pong",
+        MSG_TYPE_HELLO => "Hello",
+        MSG_TYPE_OPEN_DOCUMENT => "OpenDocument",
+        MSG_TYPE_CLOSE_DOCUMENT => "CloseDocument",
+        MSG_TYPE_ERROR => "Error",
+        MSG_TYPE_OPERATION_BATCH => "OperationBatch",
+        MSG_TYPE_DISCONNECT => "Disconnect",
+        MSG_TYPE_EXPORT_DOCUMENT => "ExportDocument",
+        MSG_TYPE_DOCUMENT_ARCHIVE => "DocumentArchive",
+        MSG_TYPE_LOCK_RANGE => "LockRange",
+        MSG_TYPE_UNLOCK_RANGE => "UnlockRange",
+        MSG_TYPE_RANGE_LOCKS => "RangeLocks",
+        MSG_TYPE_CREATE_FROM_TEMPLATE => "CreateFromTemplate",
+        MSG_TYPE_SET_PRESENCE => "SetPresence",
+        MSG_TYPE_PRESENCE => "Presence",
+        MSG_TYPE_RESEND => "Resend",
+        MSG_TYPE_SEQUENCED => "Sequenced",
+        MSG_TYPE_CREDIT => "Credit",
+        MSG_TYPE_SET_OVERLAYS => "SetOverlays",
+        MSG_TYPE_OVERLAYS => "Overlays",
+        MSG_TYPE_SET_DOCUMENT_SETTINGS => "SetDocumentSettings",
+        MSG_TYPE_CAPABILITIES => "Capabilities",
+        MSG_TYPE_INVITE_MEMBER => "InviteMember",
+        MSG_TYPE_MEMBER_TOKEN => "MemberToken",
+        MSG_TYPE_REMOVE_MEMBER => "RemoveMember",
+        MSG_TYPE_CREATE_INVITE => "CreateInvite",
+        MSG_TYPE_INVITE => "Invite",
+        MSG_TYPE_CHECKPOINT => "Checkpoint",
+        MSG_TYPE_EXPORT_REQUEST => "ExportRequest",
+        MSG_TYPE_EXPORT_CHUNK => "ExportChunk",
+        MSG_TYPE_UPLOAD_ATTACHMENT => "UploadAttachment",
+        MSG_TYPE_ATTACHMENT => "Attachment",
+        MSG_TYPE_FETCH_ATTACHMENT => "FetchAttachment",
+        MSG_TYPE_ATTACHMENT_CHUNK => "AttachmentChunk",
+        MSG_TYPE_SET_VIEWPORT => "SetViewport",
+        MSG_TYPE_FOLLOW => "Follow",
+        MSG_TYPE_VIEWPORT => "Viewport",
+        MSG_TYPE_SIGNAL => "Signal",
+        MSG_TYPE_TAG_VERSION => "TagVersion",
+        MSG_TYPE_GET_HISTORY => "GetHistory",
+        MSG_TYPE_HISTORY => "History",
+        MSG_TYPE_FREEZE => "Freeze",
+        MSG_TYPE_TIME_SYNC => "TimeSync",
+        MSG_TYPE_PROPAGATION => "Propagation",
+        MSG_TYPE_REDIRECT => "Redirect",
+        MSG_TYPE_DIAGNOSTICS => "Diagnostics",
+        MSG_TYPE_WATCH_ACTIVITY => "WatchActivity",
+        MSG_TYPE_ACTIVITY => "Activity",
+        MSG_TYPE_ACK => "Ack",
+        MSG_TYPE_NOTICE => "Notice",
+        _ => "Unknown",
+    }
+}
+
+/// Wraps an already encoded message (a frame payload) as `Sequenced(seq, _)`
+/// without decoding it: the same bytes `ServerMessage::encode` would produce.
+pub fn encode_sequenced(seq: u64, encoded: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HEADER_LEN + 8 + encoded.len());
+    buffer.put_u32((1 + 8 + encoded.len()) as u32);
+    buffer.put_u8(MSG_TYPE_SEQUENCED);
+    buffer.put_u64(seq);
+    buffer.put(encoded);
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_bytes_matches_decode() {
+        let message = ServerMessage::Hello(HelloProto {
+            client_id: "c".to_string(),
+            display_name: "ada".to_string(),
+            ..Default::default()
+        });
+        let encoded = Bytes::from(message.encode());
+
+        match ServerMessage::decode_bytes(&encoded).unwrap() {
+            ServerMessage::Hello(hello) => assert_eq!(hello.display_name, "ada"),
+            _ => panic!("expected Hello"),
+        }
+        match ServerMessage::decode(&ServerMessage::Ping(7).encode()).unwrap() {
+            ServerMessage::Ping(seq) => assert_eq!(seq, 7),
+            _ => panic!("expected Ping"),
+        }
+        assert!(ServerMessage::decode_bytes(&encoded.slice(..3)).is_err());
+    }
+
+    #[test]
+    fn test_disconnect_round_trip() {
+        use crate::proto::space::DisconnectReason;
+
+        let message = ServerMessage::Disconnect(DisconnectProto {
+            reason_code: DisconnectReason::Shutdown as i32,
+            message: "bye".to_string(),
+        });
+        let encoded = message.encode();
+        assert_eq!(encoded[4], MSG_TYPE_DISCONNECT);
+
+        match ServerMessage::decode(&encoded).unwrap() {
+            ServerMessage::Disconnect(disconnect) => {
+                assert_eq!(disconnect.reason_code(), DisconnectReason::Shutdown);
+                assert_eq!(disconnect.message, "bye");
+            }
+            _ => panic!("expected Disconnect"),
+        }
+    }
+
+    #[test]
+    fn test_sequenced_wraps_encoded_message() {
+        let inner = ServerMessage::Ping(3);
+        let encoded = encode_sequenced(42, &inner.encode());
+        assert_eq!(
+            encoded,
+            ServerMessage::Sequenced(42, Box::new(inner)).encode()
+        );
+
+        match ServerMessage::decode(&encoded).unwrap() {
+            ServerMessage::Sequenced(42, message) => {
+                assert!(matches!(*message, ServerMessage::Ping(3)))
+            }
+            _ => panic!("expected Sequenced"),
+        }
+    }
+
+    /// Frames as encoded with the schema before it was split into space.v1.
+    /// Package names never reach the wire, so these must keep decoding.
+    #[test]
+    fn test_pre_v1_frames_still_decode() {
+        use crate::proto::space::{ErrorCode, PresenceStatus, operation_proto::Kind};
+
+        let hello = [
+            0, 0, 0, 30, 5, 10, 2, 99, 49, 18, 3, 65, 100, 97, 26, 9, 110, 111, 116, 101, 115, 46,
+            116, 120, 116, 40, 128, 208, 149, 255, 188, 49, 56, 1,
+        ];
+        match ServerMessage::decode(&hello).unwrap() {
+            ServerMessage::Hello(hello) => {
+                assert_eq!(hello.client_id, "c1");
+                assert_eq!(hello.display_name, "Ada");
+                assert_eq!(hello.doc_path, "notes.txt");
+                assert_eq!(hello.client_time_ms, 1_700_000_000_000);
+                assert!(hello.sequenced);
+            }
+            _ => panic!("expected Hello"),
+        }
+
+        let operation = [
+            0, 0, 0, 29, 1, 8, 7, 18, 12, 8, 3, 18, 2, 104, 105, 26, 2, 99, 49, 32, 2, 50, 2, 100,
+            49, 58, 2, 99, 49, 64, 2, 72, 2,
+        ];
+        match ServerMessage::decode(&operation).unwrap() {
+            ServerMessage::Operation(op) => {
+                assert_eq!((op.op_id, op.doc_id.as_str()), (7, "d1"));
+                assert_eq!((op.client_version, op.server_version), (2, 2));
+                match op.kind {
+                    Some(Kind::Insert(insert)) => {
+                        assert_eq!((insert.index, insert.text.as_str()), (3, "hi"))
+                    }
+                    _ => panic!("expected Insert"),
+                }
+            }
+            _ => panic!("expected Operation"),
+        }
+
+        let sync = [
+            0, 0, 0, 25, 2, 10, 2, 100, 49, 18, 5, 104, 101, 108, 108, 111, 24, 3, 34, 9, 110, 111,
+            116, 101, 115, 46, 116, 120, 116,
+        ];
+        match ServerMessage::decode(&sync).unwrap() {
+            ServerMessage::SyncDocument(sync) => {
+                assert_eq!(
+                    (sync.doc_id.as_str(), sync.content.as_str()),
+                    ("d1", "hello")
+                );
+                assert_eq!((sync.version, sync.path.as_str()), (3, "notes.txt"));
+            }
+            _ => panic!("expected SyncDocument"),
+        }
+
+        let error = [
+            0, 0, 0, 13, 8, 8, 10, 18, 6, 108, 111, 99, 107, 101, 100, 32, 7,
+        ];
+        match ServerMessage::decode(&error).unwrap() {
+            ServerMessage::Error(error) => {
+                assert_eq!(error.code(), ErrorCode::RangeLocked);
+                assert_eq!((error.message.as_str(), error.op_id), ("locked", 7));
+            }
+            _ => panic!("expected Error"),
+        }
+
+        let presence = [
+            0, 0, 0, 20, 18, 10, 2, 99, 50, 18, 3, 66, 111, 98, 24, 3, 34, 6, 107, 105, 99, 107,
+            101, 100,
+        ];
+        match ServerMessage::decode(&presence).unwrap() {
+            ServerMessage::Presence(presence) => {
+                assert_eq!(presence.status(), PresenceStatus::Offline);
+                assert_eq!(
+                    (presence.display_name.as_str(), presence.reason.as_str()),
+                    ("Bob", "kicked")
+                );
+            }
+            _ => panic!("expected Presence"),
         }
     }
 }
@@ -0,0 +1,902 @@
+use std::fmt::Write as _;
+
+use crate::proto::space::{
+    AckProto, ActivityProto, AttachmentChunkProto, AttachmentProto, AuthorEditsProto,
+    CapabilitiesProto, CheckpointProto, CloseDocumentProto, CreateFromTemplateProto,
+    CreateInviteProto, CreditProto, DeleteOp, DiagnosticKind, DiagnosticsProto, DisconnectProto,
+    DisconnectReason, DocumentActivityProto, DocumentArchiveProto, DocumentSettingsProto,
+    DocumentStatsProto, ErrorCode, ErrorProto, ExportChunkProto, ExportDocumentProto, ExportFormat,
+    ExportRequestProto, FetchAttachmentProto, FollowProto, FreezeProto, GetHistoryProto,
+    HelloProto, HistoryProto, InsertOp, InviteMemberProto, InviteProto, LineEnding, LockRangeProto,
+    MemberTokenProto, MilestoneProto, NoticeProto, OpenDocumentProto, OperationBatchProto,
+    OperationProto, OverlayProto, OverlaysProto, PresenceProto, PresenceStatus, PropagationProto,
+    PropagationSampleProto, RangeLockProto, RangeLocksProto, RedirectProto, RemoveMemberProto,
+    ReplaceOp, ResendProto, SetDocumentSettingsProto, SetOverlaysProto, SetPresenceProto,
+    SetViewportProto, SignalKind, SignalProto, SyncDocumentProto, TagProto, TagVersionProto,
+    TemplateVariableProto, TextRangeProto, TimeSyncProto, UnlockRangeProto, UploadAttachmentProto,
+    ViewportProto, WatchActivityProto, WorkspaceEventKind, WorkspaceEventProto,
+    operation_proto::Kind,
+};
+use crate::protocol::*;
+
+/// Protobuf package of every message body.
+pub const PACKAGE: &str = "space.v1";
+
+/// Schema files, relative to `common/proto`.
+pub const SCHEMA: &[&str] = &[
+    "space/v1/operations.proto",
+    "space/v1/sync.proto",
+    "space/v1/presence.proto",
+    "space/v1/workspace.proto",
+    "space/v1/admin.proto",
+];
+
+/// How a message's body follows its type ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    /// A protobuf message of the space.v1 package, by name.
+    Proto(&'static str),
+    /// A big-endian u64.
+    U64,
+    /// A big-endian u64 sequence number followed by a whole encoded message.
+    Sequenced,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SentBy {
+    Client,
+    Server,
+    Both,
+}
+
+impl SentBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SentBy::Client => "client",
+            SentBy::Server => "server",
+            SentBy::Both => "both",
+        }
+    }
+}
+
+/// One message type as it appears in a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSpec {
+    pub type_id: u8,
+    pub body: Body,
+    pub sent_by: SentBy,
+}
+
+const fn message(type_id: u8, body: Body, sent_by: SentBy) -> MessageSpec {
+    MessageSpec {
+        type_id,
+        body,
+        sent_by,
+    }
+}
+
+/// Every message type, by type ID.
+pub const MESSAGES: &[MessageSpec] = {
+    use Body::*;
+    use SentBy::*;
+    &[
+        message(MSG_TYPE_OPERATION, Proto("OperationProto"), Both),
+        message(MSG_TYPE_SYNC_DOCUMENT, Proto("SyncDocumentProto"), Server),
+        message(MSG_TYPE_PING, U64, Server),
+        message(MSG_TYPE_PONG, U64, Client),
+        message(MSG_TYPE_HELLO, Proto("HelloProto"), Client),
+        message(MSG_TYPE_OPEN_DOCUMENT, Proto("OpenDocumentProto"), Client),
+        message(MSG_TYPE_CLOSE_DOCUMENT, Proto("CloseDocumentProto"), Client),
+        message(MSG_TYPE_ERROR, Proto("ErrorProto"), Server),
+        message(MSG_TYPE_OPERATION_BATCH, Proto("OperationBatchProto"), Both),
+        message(MSG_TYPE_DISCONNECT, Proto("DisconnectProto"), Server),
+        message(
+            MSG_TYPE_EXPORT_DOCUMENT,
+            Proto("ExportDocumentProto"),
+            Client,
+        ),
+        message(
+            MSG_TYPE_DOCUMENT_ARCHIVE,
+            Proto("DocumentArchiveProto"),
+            Both,
+        ),
+        message(MSG_TYPE_LOCK_RANGE, Proto("LockRangeProto"), Client),
+        message(MSG_TYPE_UNLOCK_RANGE, Proto("UnlockRangeProto"), Client),
+        message(MSG_TYPE_RANGE_LOCKS, Proto("RangeLocksProto"), Server),
+        message(
+            MSG_TYPE_CREATE_FROM_TEMPLATE,
+            Proto("CreateFromTemplateProto"),
+            Client,
+        ),
+        message(MSG_TYPE_SET_PRESENCE, Proto("SetPresenceProto"), Client),
+        message(MSG_TYPE_PRESENCE, Proto("PresenceProto"), Server),
+        message(MSG_TYPE_RESEND, Proto("ResendProto"), Client),
+        message(MSG_TYPE_SEQUENCED, Sequenced, Server),
+        message(MSG_TYPE_CREDIT, Proto("CreditProto"), Client),
+        message(MSG_TYPE_SET_OVERLAYS, Proto("SetOverlaysProto"), Client),
+        message(MSG_TYPE_OVERLAYS, Proto("OverlaysProto"), Server),
+        message(
+            MSG_TYPE_SET_DOCUMENT_SETTINGS,
+            Proto("SetDocumentSettingsProto"),
+            Client,
+        ),
+        message(MSG_TYPE_CAPABILITIES, Proto("CapabilitiesProto"), Server),
+        message(MSG_TYPE_INVITE_MEMBER, Proto("InviteMemberProto"), Client),
+        message(MSG_TYPE_MEMBER_TOKEN, Proto("MemberTokenProto"), Server),
+        message(MSG_TYPE_REMOVE_MEMBER, Proto("RemoveMemberProto"), Client),
+        message(MSG_TYPE_CREATE_INVITE, Proto("CreateInviteProto"), Client),
+        message(MSG_TYPE_INVITE, Proto("InviteProto"), Server),
+        message(MSG_TYPE_CHECKPOINT, Proto("CheckpointProto"), Both),
+        message(MSG_TYPE_EXPORT_REQUEST, Proto("ExportRequestProto"), Client),
+        message(MSG_TYPE_EXPORT_CHUNK, Proto("ExportChunkProto"), Server),
+        message(
+            MSG_TYPE_UPLOAD_ATTACHMENT,
+            Proto("UploadAttachmentProto"),
+            Client,
+        ),
+        message(MSG_TYPE_ATTACHMENT, Proto("AttachmentProto"), Server),
+        message(
+            MSG_TYPE_FETCH_ATTACHMENT,
+            Proto("FetchAttachmentProto"),
+            Client,
+        ),
+        message(
+            MSG_TYPE_ATTACHMENT_CHUNK,
+            Proto("AttachmentChunkProto"),
+            Server,
+        ),
+        message(MSG_TYPE_SET_VIEWPORT, Proto("SetViewportProto"), Client),
+        message(MSG_TYPE_FOLLOW, Proto("FollowProto"), Client),
+        message(MSG_TYPE_VIEWPORT, Proto("ViewportProto"), Server),
+        message(MSG_TYPE_SIGNAL, Proto("SignalProto"), Both),
+        message(MSG_TYPE_TAG_VERSION, Proto("TagVersionProto"), Client),
+        message(MSG_TYPE_GET_HISTORY, Proto("GetHistoryProto"), Client),
+        message(MSG_TYPE_HISTORY, Proto("HistoryProto"), Server),
+        message(MSG_TYPE_FREEZE, Proto("FreezeProto"), Server),
+        message(MSG_TYPE_TIME_SYNC, Proto("TimeSyncProto"), Both),
+        message(MSG_TYPE_PROPAGATION, Proto("PropagationProto"), Client),
+        message(MSG_TYPE_REDIRECT, Proto("RedirectProto"), Server),
+        message(MSG_TYPE_DIAGNOSTICS, Proto("DiagnosticsProto"), Server),
+        message(MSG_TYPE_WATCH_ACTIVITY, Proto("WatchActivityProto"), Client),
+        message(MSG_TYPE_ACTIVITY, Proto("ActivityProto"), Server),
+        message(MSG_TYPE_ACK, Proto("AckProto"), Client),
+        message(MSG_TYPE_NOTICE, Proto("NoticeProto"), Server),
+    ]
+};
+
+/// The protocol as JSON: framing rules and every message type, for checking
+/// other client implementations against this one.
+pub fn description() -> String {
+    let mut json = String::from("{\n");
+    let _ = writeln!(json, "  \"package\": {},", json_string(PACKAGE));
+    let schema: Vec<String> = SCHEMA.iter().map(|file| json_string(file)).collect();
+    let _ = writeln!(json, "  \"schema\": [{}],", schema.join(", "));
+    json.push_str(concat!(
+        "  \"framing\": {\n",
+        "    \"byte_order\": \"big-endian\",\n",
+        "    \"layout\": [\"u32 frame_len\", \"u32 message_len\", \"u8 type_id\", \"body\"],\n",
+        "    \"frame_len\": \"bytes that follow it: 4 + message_len\",\n",
+        "    \"message_len\": \"bytes that follow it: 1 + the body's length\"\n",
+        "  },\n",
+        "  \"bodies\": {\n",
+        "    \"proto\": \"the protobuf message named by the message's body field\",\n",
+        "    \"u64\": \"8 bytes: a ping sequence number, echoed by the pong\",\n",
+        "    \"sequenced\": \"8 bytes of sequence number, then a whole message (message_len, type_id, body) as the server would otherwise have sent it\"\n",
+        "  },\n",
+    ));
+    json.push_str("  \"messages\": [\n");
+    let messages: Vec<String> = MESSAGES
+        .iter()
+        .map(|spec| {
+            let body = match spec.body {
+                Body::Proto(name) => format!("{}.{}", PACKAGE, name),
+                Body::U64 => "u64".to_string(),
+                Body::Sequenced => "sequenced".to_string(),
+            };
+            format!(
+                "    {{\"type_id\": {}, \"name\": {}, \"body\": {}, \"sent_by\": {}}}",
+                spec.type_id,
+                json_string(message_type_name(spec.type_id)),
+                json_string(&body),
+                json_string(spec.sent_by.as_str())
+            )
+        })
+        .collect();
+    json.push_str(&messages.join(",\n"));
+    json.push_str("\n  ]\n}\n");
+    json
+}
+
+/// A canonical message for each type, under a stable name.
+pub fn examples() -> Vec<(&'static str, ServerMessage)> {
+    let insert = OperationProto {
+        op_id: 7,
+        kind: Some(Kind::Insert(InsertOp {
+            index: 3,
+            text: "hi".to_string(),
+            client_id: "c1".to_string(),
+            client_version: 2,
+        })),
+        doc_id: "d1".to_string(),
+        client_id: "c1".to_string(),
+        client_version: 2,
+        ..Default::default()
+    };
+    let applied = |op: OperationProto, server_version| OperationProto {
+        server_version,
+        applied_at_ms: 1_700_000_000_000,
+        applied_mono_ms: 5_000,
+        global_version: server_version,
+        ..op
+    };
+    let delete = OperationProto {
+        op_id: 8,
+        kind: Some(Kind::Delete(DeleteOp {
+            start: 0,
+            end: 2,
+            client_id: "c1".to_string(),
+            client_version: 3,
+        })),
+        doc_id: "d1".to_string(),
+        client_id: "c1".to_string(),
+        client_version: 3,
+        ..Default::default()
+    };
+    let replace = OperationProto {
+        op_id: 9,
+        kind: Some(Kind::Replace(ReplaceOp {
+            start: 0,
+            end: 1,
+            text: "H".to_string(),
+            client_id: "c2".to_string(),
+            client_version: 3,
+        })),
+        doc_id: "d1".to_string(),
+        client_id: "c2".to_string(),
+        client_version: 3,
+        ..Default::default()
+    };
+    let sync = SyncDocumentProto {
+        doc_id: "d1".to_string(),
+        content: "hello".to_string(),
+        version: 3,
+        path: "notes.txt".to_string(),
+        server_time_ms: 1_700_000_000_000,
+        server_mono_ms: 5_000,
+        settings: Some(DocumentSettingsProto {
+            line_ending: LineEnding::Lf as i32,
+            language_id: "plaintext".to_string(),
+            ..Default::default()
+        }),
+        stats: Some(DocumentStatsProto {
+            length: 5,
+            line_count: 1,
+            last_edit_ms: 1_700_000_000_000,
+            edits: vec![AuthorEditsProto {
+                client_id: "c2".to_string(),
+                edits: 3,
+            }],
+        }),
+        resumed: false,
+    };
+
+    vec![
+        ("operation_insert", ServerMessage::Operation(insert.clone())),
+        (
+            "operation_applied",
+            ServerMessage::Operation(applied(insert.clone(), 2)),
+        ),
+        ("sync_document", ServerMessage::SyncDocument(sync.clone())),
+        ("ping", ServerMessage::Ping(42)),
+        ("pong", ServerMessage::Pong(42)),
+        (
+            "hello",
+            ServerMessage::Hello(HelloProto {
+                client_id: "c1".to_string(),
+                display_name: "Ada".to_string(),
+                doc_path: "notes.txt".to_string(),
+                auth_token: "secret".to_string(),
+                client_time_ms: 1_700_000_000_000,
+                read_only: false,
+                sequenced: true,
+                capabilities: Some(CapabilitiesProto {
+                    batches: true,
+                    presence: true,
+                    ..CapabilitiesProto::default()
+                }),
+                workspace: "docs-team".to_string(),
+                protocol_version: PROTOCOL_VERSION,
+                client_version: "dist-space-client/0.1.0".to_string(),
+            }),
+        ),
+        (
+            "open_document",
+            ServerMessage::OpenDocument(OpenDocumentProto {
+                path: "notes.txt".to_string(),
+                resume: false,
+            }),
+        ),
+        (
+            "close_document",
+            ServerMessage::CloseDocument(CloseDocumentProto {
+                doc_id: "d1".to_string(),
+            }),
+        ),
+        (
+            "error",
+            ServerMessage::Error(ErrorProto {
+                code: ErrorCode::RangeLocked as i32,
+                message: "locked".to_string(),
+                retry_after_ms: 0,
+                op_id: 7,
+            }),
+        ),
+        (
+            "operation_batch",
+            ServerMessage::OperationBatch(OperationBatchProto {
+                operations: vec![applied(delete, 3), applied(replace, 4)],
+                more: false,
+            }),
+        ),
+        (
+            "disconnect",
+            ServerMessage::Disconnect(DisconnectProto {
+                reason_code: DisconnectReason::Shutdown as i32,
+                message: "server shutting down".to_string(),
+            }),
+        ),
+        (
+            "export_document",
+            ServerMessage::ExportDocument(ExportDocumentProto {
+                doc_id: "d1".to_string(),
+            }),
+        ),
+        (
+            "document_archive",
+            ServerMessage::DocumentArchive(DocumentArchiveProto {
+                path: "notes.txt".to_string(),
+                content: "hi".to_string(),
+                version: 1,
+                operations: vec![applied(
+                    OperationProto {
+                        kind: Some(Kind::Insert(InsertOp {
+                            index: 0,
+                            text: "hi".to_string(),
+                            client_id: "c1".to_string(),
+                            client_version: 0,
+                        })),
+                        client_version: 0,
+                        ..insert
+                    },
+                    0,
+                )],
+                exported_at_ms: 1_700_000_000_000,
+                milestones: Vec::new(),
+            }),
+        ),
+        (
+            "lock_range",
+            ServerMessage::LockRange(LockRangeProto {
+                doc_id: "d1".to_string(),
+                start: 0,
+                end: 5,
+            }),
+        ),
+        (
+            "unlock_range",
+            ServerMessage::UnlockRange(UnlockRangeProto {
+                doc_id: "d1".to_string(),
+                lock_id: 1,
+            }),
+        ),
+        (
+            "range_locks",
+            ServerMessage::RangeLocks(RangeLocksProto {
+                doc_id: "d1".to_string(),
+                version: 3,
+                locks: vec![RangeLockProto {
+                    lock_id: 1,
+                    client_id: "c1".to_string(),
+                    start: 0,
+                    end: 5,
+                }],
+            }),
+        ),
+        (
+            "create_from_template",
+            ServerMessage::CreateFromTemplate(CreateFromTemplateProto {
+                template: "meeting".to_string(),
+                path: "standup.md".to_string(),
+                variables: vec![TemplateVariableProto {
+                    name: "date".to_string(),
+                    value: "2026-10-15".to_string(),
+                }],
+            }),
+        ),
+        (
+            "set_presence",
+            ServerMessage::SetPresence(SetPresenceProto { away: true }),
+        ),
+        (
+            "presence",
+            ServerMessage::Presence(PresenceProto {
+                client_id: "c2".to_string(),
+                display_name: "Bob".to_string(),
+                status: PresenceStatus::Offline as i32,
+                reason: "kicked".to_string(),
+            }),
+        ),
+        (
+            "resend",
+            ServerMessage::Resend(ResendProto { from_seq: 17 }),
+        ),
+        (
+            "sequenced_sync_document",
+            ServerMessage::Sequenced(17, Box::new(ServerMessage::SyncDocument(sync))),
+        ),
+        (
+            "credit",
+            ServerMessage::Credit(CreditProto {
+                frames: 64,
+                bytes: 65_536,
+            }),
+        ),
+        (
+            "set_overlays",
+            ServerMessage::SetOverlays(SetOverlaysProto {
+                doc_id: "d1".to_string(),
+                version: 3,
+                kind: "spelling".to_string(),
+                overlays: vec![OverlayProto {
+                    start: 0,
+                    end: 5,
+                    payload: "Unknown word".to_string(),
+                    ..Default::default()
+                }],
+            }),
+        ),
+        (
+            "overlays",
+            ServerMessage::Overlays(OverlaysProto {
+                doc_id: "d1".to_string(),
+                version: 4,
+                overlays: vec![OverlayProto {
+                    client_id: "c1".to_string(),
+                    kind: "spelling".to_string(),
+                    start: 2,
+                    end: 7,
+                    payload: "Unknown word".to_string(),
+                }],
+            }),
+        ),
+        (
+            "set_document_settings",
+            ServerMessage::SetDocumentSettings(SetDocumentSettingsProto {
+                doc_id: "d1".to_string(),
+                settings: Some(DocumentSettingsProto {
+                    line_ending: LineEnding::Lf as i32,
+                    tab_width: 4,
+                    language_id: "markdown".to_string(),
+                    max_bytes: 1_048_576,
+                    append_only: vec![TextRangeProto { start: 0, end: 12 }],
+                }),
+            }),
+        ),
+        (
+            "capabilities",
+            ServerMessage::Capabilities(CapabilitiesProto {
+                batches: true,
+                presence: true,
+                ..CapabilitiesProto::default()
+            }),
+        ),
+        (
+            "invite_member",
+            ServerMessage::InviteMember(InviteMemberProto {
+                member: "grace".to_string(),
+            }),
+        ),
+        (
+            "member_token",
+            ServerMessage::MemberToken(MemberTokenProto {
+                workspace: "docs-team".to_string(),
+                member: "grace".to_string(),
+                token: "6f1c2b9e4d3a4e0f8a7b5c6d7e8f9a0b".to_string(),
+            }),
+        ),
+        (
+            "remove_member",
+            ServerMessage::RemoveMember(RemoveMemberProto {
+                member: "grace".to_string(),
+            }),
+        ),
+        (
+            "create_invite",
+            ServerMessage::CreateInvite(CreateInviteProto {
+                path: "notes.txt".to_string(),
+                read_only: true,
+                ttl_ms: 3_600_000,
+            }),
+        ),
+        (
+            "invite",
+            ServerMessage::Invite(InviteProto {
+                token: "0d9e8f7a6b5c4d3e2f1a0b9c8d7e6f5a".to_string(),
+                workspace: "docs-team".to_string(),
+                path: "notes.txt".to_string(),
+                read_only: true,
+                expires_at_ms: 1_700_003_600_000,
+            }),
+        ),
+        (
+            "checkpoint",
+            ServerMessage::Checkpoint(CheckpointProto {
+                message: "Before the release".to_string(),
+                commit: "3f2a9c1e8b7d6a5f4e3d2c1b0a9f8e7d6c5b4a39".to_string(),
+                contributors: vec!["ada".to_string(), "grace".to_string()],
+                ..Default::default()
+            }),
+        ),
+        (
+            "checkpoint_document",
+            ServerMessage::Checkpoint(CheckpointProto {
+                message: "First draft".to_string(),
+                doc_id: "d1".to_string(),
+                milestone: Some(MilestoneProto {
+                    version: 42,
+                    label: "First draft".to_string(),
+                    squashed: 42,
+                    created_at_ms: 1_700_000_000_000,
+                }),
+                ..Default::default()
+            }),
+        ),
+        (
+            "export_request",
+            ServerMessage::ExportRequest(ExportRequestProto {
+                doc_id: "d1".to_string(),
+                format: ExportFormat::Html as i32,
+            }),
+        ),
+        (
+            "export_chunk",
+            ServerMessage::ExportChunk(ExportChunkProto {
+                doc_id: "d1".to_string(),
+                format: ExportFormat::Html as i32,
+                file_name: "notes.html".to_string(),
+                version: 42,
+                data: b"<h1>Notes</h1>".to_vec(),
+                more: true,
+            }),
+        ),
+        (
+            "upload_attachment",
+            ServerMessage::UploadAttachment(UploadAttachmentProto {
+                doc_id: "d1".to_string(),
+                data: b"test".to_vec(),
+                more: false,
+            }),
+        ),
+        (
+            "attachment",
+            ServerMessage::Attachment(AttachmentProto {
+                doc_id: "d1".to_string(),
+                attachment_id: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                    .to_string(),
+                size: 4,
+            }),
+        ),
+        (
+            "fetch_attachment",
+            ServerMessage::FetchAttachment(FetchAttachmentProto {
+                doc_id: "d1".to_string(),
+                attachment_id: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                    .to_string(),
+            }),
+        ),
+        (
+            "attachment_chunk",
+            ServerMessage::AttachmentChunk(AttachmentChunkProto {
+                attachment_id: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                    .to_string(),
+                data: b"test".to_vec(),
+                more: false,
+            }),
+        ),
+        (
+            "set_viewport",
+            ServerMessage::SetViewport(SetViewportProto {
+                doc_id: "d1".to_string(),
+                first_line: 40,
+                line_count: 25,
+            }),
+        ),
+        (
+            "follow",
+            ServerMessage::Follow(FollowProto {
+                client_id: "c2".to_string(),
+            }),
+        ),
+        (
+            "viewport",
+            ServerMessage::Viewport(ViewportProto {
+                client_id: "c2".to_string(),
+                doc_id: "d1".to_string(),
+                path: "notes.txt".to_string(),
+                first_line: 40,
+                line_count: 25,
+            }),
+        ),
+        (
+            "signal",
+            ServerMessage::Signal(SignalProto {
+                client_id: "c2".to_string(),
+                doc_id: "d1".to_string(),
+                version: 12,
+                kind: SignalKind::Reaction as i32,
+                start: 0,
+                end: 5,
+                emoji: "👍".to_string(),
+            }),
+        ),
+        (
+            "tag_version",
+            ServerMessage::TagVersion(TagVersionProto {
+                doc_id: "d1".to_string(),
+                name: "sent-to-review".to_string(),
+            }),
+        ),
+        (
+            "get_history",
+            ServerMessage::GetHistory(GetHistoryProto {
+                doc_id: "d1".to_string(),
+                tag: "draft-1".to_string(),
+                events_after: 0,
+            }),
+        ),
+        (
+            "get_workspace_history",
+            ServerMessage::GetHistory(GetHistoryProto {
+                events_after: 7,
+                ..Default::default()
+            }),
+        ),
+        (
+            "history",
+            ServerMessage::History(HistoryProto {
+                doc_id: "d1".to_string(),
+                path: "notes.txt".to_string(),
+                version: 57,
+                first_version: 42,
+                milestones: vec![MilestoneProto {
+                    version: 42,
+                    label: "First draft".to_string(),
+                    squashed: 42,
+                    created_at_ms: 1_700_000_000_000,
+                }],
+                tags: vec![TagProto {
+                    name: "draft-1".to_string(),
+                    version: 12,
+                    client_id: "c1".to_string(),
+                    created_at_ms: 1_699_999_000_000,
+                }],
+                tag: "draft-1".to_string(),
+                content: "hello".to_string(),
+                events: Vec::new(),
+            }),
+        ),
+        (
+            "workspace_history",
+            ServerMessage::History(HistoryProto {
+                events: vec![
+                    WorkspaceEventProto {
+                        seq: 8,
+                        at_ms: 1_700_000_000_000,
+                        kind: WorkspaceEventKind::MemberJoined as i32,
+                        member: "ada".to_string(),
+                        ..Default::default()
+                    },
+                    WorkspaceEventProto {
+                        seq: 9,
+                        at_ms: 1_700_000_060_000,
+                        kind: WorkspaceEventKind::DocumentCreated as i32,
+                        path: "notes.txt".to_string(),
+                        member: "ada".to_string(),
+                        detail: "opened".to_string(),
+                    },
+                ],
+                ..Default::default()
+            }),
+        ),
+        (
+            "freeze",
+            ServerMessage::Freeze(FreezeProto {
+                workspace: "docs".to_string(),
+                path: String::new(),
+                frozen: true,
+                starts_at_ms: 1_700_000_000_000,
+                ends_at_ms: 1_700_003_600_000,
+            }),
+        ),
+        (
+            "time_sync",
+            ServerMessage::TimeSync(TimeSyncProto {
+                client_send_ms: 1_700_000_000_000,
+                server_receive_ms: 1_700_000_000_140,
+                server_send_ms: 1_700_000_000_141,
+                rtt_ms: 38,
+                offset_ms: 121,
+            }),
+        ),
+        (
+            "propagation",
+            ServerMessage::Propagation(PropagationProto {
+                samples: vec![PropagationSampleProto {
+                    doc_id: "d1".to_string(),
+                    server_version: 32,
+                    applied_at_ms: 1_700_000_000_000,
+                    received_ms: 1_699_999_999_902,
+                    displayed_ms: 1_699_999_999_905,
+                }],
+            }),
+        ),
+        (
+            "redirect",
+            ServerMessage::Redirect(RedirectProto {
+                address: "10.0.0.2:8000".to_string(),
+                message: "Server handed over to 10.0.0.2:8000".to_string(),
+                doc_id: String::new(),
+            }),
+        ),
+        (
+            "diagnostics",
+            ServerMessage::Diagnostics(DiagnosticsProto {
+                kind: DiagnosticKind::ProtocolTooOld as i32,
+                message: "Client protocol 1 is older than the oldest accepted, 2".to_string(),
+                guidance: "Upgrade the client to one speaking protocol 2 or later".to_string(),
+                protocol_version: 2,
+                min_protocol_version: 2,
+                server_version: "dist-space-server/0.2.0".to_string(),
+                missing_capabilities: Vec::new(),
+                message_type: 0,
+            }),
+        ),
+        (
+            "watch_activity",
+            ServerMessage::WatchActivity(WatchActivityProto { watch: true }),
+        ),
+        (
+            "activity",
+            ServerMessage::Activity(ActivityProto {
+                events: vec![WorkspaceEventProto {
+                    seq: 10,
+                    at_ms: 1_700_000_120_000,
+                    kind: WorkspaceEventKind::CheckpointTaken as i32,
+                    path: "notes.txt".to_string(),
+                    member: "ada".to_string(),
+                    detail: "First draft".to_string(),
+                }],
+                edits: vec![DocumentActivityProto {
+                    doc_id: "d1".to_string(),
+                    path: "notes.txt".to_string(),
+                    ops: 14,
+                    editors: vec!["ada".to_string(), "c2".to_string()],
+                    version: 71,
+                }],
+                joined: vec!["grace".to_string()],
+                left: Vec::new(),
+            }),
+        ),
+        (
+            "ack",
+            ServerMessage::Ack(AckProto {
+                doc_id: "d1".to_string(),
+                version: 71,
+            }),
+        ),
+        (
+            "notice",
+            ServerMessage::Notice(NoticeProto {
+                message: "Maintenance in 5 minutes".to_string(),
+                workspace: String::new(),
+                sent_at_ms: 1_700_000_000_000,
+            }),
+        ),
+    ]
+}
+
+/// `examples` as JSON: each message's frame as it goes on the wire, in hex,
+/// with the values it carries.
+pub fn examples_json() -> String {
+    let entries: Vec<String> = examples()
+        .iter()
+        .map(|(name, message)| {
+            let encoded = message.encode();
+            let mut frame = (encoded.len() as u32).to_be_bytes().to_vec();
+            frame.extend_from_slice(&encoded);
+            let hex: String = frame.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!(
+                "  {{\"name\": {}, \"type_id\": {}, \"message\": {}, \"frame_hex\": {}, \"value\": {}}}",
+                json_string(name),
+                message.get_message_type_id(),
+                json_string(message_type_name(message.get_message_type_id())),
+                json_string(&hex),
+                json_string(&format!("{:?}", message))
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+/// `text` as a quoted JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf};
+
+    /// Compares `generated` with the committed `common/protocol/<file>`, or
+    /// rewrites it when UPDATE_PROTOCOL_SPEC is set.
+    fn check_committed(file: &str, generated: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("protocol")
+            .join(file);
+        if env::var_os("UPDATE_PROTOCOL_SPEC").is_some() {
+            fs::write(&path, generated).unwrap();
+            return;
+        }
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "{} is out of date; rerun with UPDATE_PROTOCOL_SPEC=1",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_committed_protocol_files_are_current() {
+        check_committed("description.json", &description());
+        check_committed("examples.json", &examples_json());
+    }
+
+    #[test]
+    fn test_every_message_type_is_described_and_exemplified() {
+        let examples = examples();
+        for (index, spec) in MESSAGES.iter().enumerate() {
+            assert_eq!(spec.type_id as usize, index + 1);
+            assert_ne!(message_type_name(spec.type_id), "Unknown");
+            assert!(
+                examples
+                    .iter()
+                    .any(|(_, message)| message.get_message_type_id() == spec.type_id),
+                "no example of type {}",
+                spec.type_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_examples_round_trip() {
+        for (name, message) in examples() {
+            let encoded = message.encode();
+            let decoded = ServerMessage::decode(&encoded).unwrap();
+            assert_eq!(decoded.encode(), encoded, "{}", name);
+        }
+    }
+}
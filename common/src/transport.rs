@@ -0,0 +1,182 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// A byte stream whose blocking reads can be bounded, so a reader can tell a
+/// stalled peer from an idle one. Timed-out reads fail with `WouldBlock` or
+/// `TimedOut`, as they do on a socket.
+pub trait ReadTimeout: Read {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Bytes travelling in one direction of a `duplex` pipe.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+#[derive(Default)]
+struct PipeState {
+    data: VecDeque<u8>,
+    /// Set when either end is dropped.
+    closed: bool,
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.readable.notify_all();
+    }
+}
+
+/// One end of an in-memory, socket-like pipe created by `duplex`. Lets framing
+/// code be tested without real sockets: reads block (honouring the read
+/// timeout), dropping an end is seen as EOF by the other, and
+/// `with_read_chunk` forces short reads to exercise frames split across reads.
+pub struct DuplexStream {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    read_timeout: Option<Duration>,
+    read_chunk: usize,
+}
+
+/// Two connected streams: bytes written to one are read from the other.
+pub fn duplex() -> (DuplexStream, DuplexStream) {
+    let a_to_b = Arc::new(Pipe::default());
+    let b_to_a = Arc::new(Pipe::default());
+    let end = |incoming: &Arc<Pipe>, outgoing: &Arc<Pipe>| DuplexStream {
+        incoming: Arc::clone(incoming),
+        outgoing: Arc::clone(outgoing),
+        read_timeout: None,
+        read_chunk: usize::MAX,
+    };
+    (end(&b_to_a, &a_to_b), end(&a_to_b, &b_to_a))
+}
+
+impl DuplexStream {
+    /// Returns at most `chunk` bytes per read.
+    pub fn with_read_chunk(mut self, chunk: usize) -> Self {
+        self.read_chunk = chunk.max(1);
+        self
+    }
+}
+
+impl Read for DuplexStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.incoming.lock();
+        while state.data.is_empty() {
+            if state.closed {
+                return Ok(0);
+            }
+            state = match deadline {
+                None => match self.incoming.readable.wait(state) {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                },
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::ErrorKind::WouldBlock.into());
+                    }
+                    match self.incoming.readable.wait_timeout(state, deadline - now) {
+                        Ok((guard, _)) => guard,
+                        Err(poisoned) => poisoned.into_inner().0,
+                    }
+                }
+            };
+        }
+
+        let n = buf.len().min(self.read_chunk).min(state.data.len());
+        for (slot, byte) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl ReadTimeout for DuplexStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        if timeout == Some(Duration::ZERO) {
+            // Same contract as TcpStream
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout",
+            ));
+        }
+        self.read_timeout = timeout;
+        Ok(())
+    }
+}
+
+impl Write for DuplexStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.outgoing.lock();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.data.extend(buf);
+        drop(state);
+        self.outgoing.readable.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplex_round_trip_and_eof() {
+        let (mut a, b) = duplex();
+        let mut b = b.with_read_chunk(2);
+        a.write_all(b"hello").unwrap();
+
+        let mut buf = [0u8; 8];
+        assert_eq!(b.read(&mut buf).unwrap(), 2);
+        b.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        let mut rest = [0u8; 3];
+        b.read_exact(&mut rest).unwrap();
+        assert_eq!(&rest, b"llo");
+        assert_eq!(
+            b.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        drop(a);
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+        assert!(b.write_all(b"x").is_err());
+    }
+}
@@ -9,6 +9,7 @@ pub struct Workspace {
     /// Key is the relative path (e.g., "src/main.rs")
     pub files: HashMap<String, Document>,
 
-    /// Monotonically increasing version for the entire workspace
+    /// Monotonically increasing version for the entire workspace, separate
+    /// from each document's own `version`
     pub global_version: u64,
 }
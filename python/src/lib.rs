@@ -0,0 +1,399 @@
+//! Python bindings for the client library, for scripting collaborative
+//! sessions and end-to-end tests against a running server.
+//!
+//! ```python
+//! import dist_space
+//!
+//! conn = dist_space.Connection("127.0.0.1:8000", "notes.txt", display_name="qa-bot")
+//! doc = conn.document
+//! conn.wait_synced(doc)
+//! doc.insert(0, "hello")
+//! for event in conn.events(timeout=5.0):
+//!     print(event.kind, event.version)
+//! ```
+
+use std::{
+    io,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use client::{ConnectOptions, EventKind, HandleId, Session};
+use common::space::{OperationProto, operation_proto::Kind};
+use pyo3::{
+    exceptions::{PyConnectionError, PyKeyError, PyTimeoutError, PyValueError},
+    prelude::*,
+};
+
+fn io_error(e: io::Error) -> PyErr {
+    match e.kind() {
+        io::ErrorKind::NotConnected => PyValueError::new_err(e.to_string()),
+        _ => PyConnectionError::new_err(e.to_string()),
+    }
+}
+
+fn timeout(seconds: Option<f64>) -> PyResult<Option<Duration>> {
+    seconds
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|_| PyValueError::new_err("timeout must be a non-negative number"))
+        })
+        .transpose()
+}
+
+/// One open document. Edits are based on the latest snapshot and sent
+/// straight away; the server's answer arrives as an event.
+#[pyclass(name = "DocumentHandle", module = "dist_space")]
+struct PyDocumentHandle {
+    id: HandleId,
+    handle: client::DocumentHandle,
+}
+
+#[pymethods]
+impl PyDocumentHandle {
+    /// Identifies the document in `Event.handle`.
+    #[getter]
+    fn id(&self) -> HandleId {
+        self.id
+    }
+
+    #[getter]
+    fn client_id(&self) -> &str {
+        self.handle.client_id()
+    }
+
+    /// Empty until the first sync.
+    #[getter]
+    fn doc_id(&self) -> String {
+        self.handle.snapshot().doc_id
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.handle.snapshot().path
+    }
+
+    #[getter]
+    fn version(&self) -> u64 {
+        self.handle.snapshot().version
+    }
+
+    #[getter]
+    fn content(&self) -> String {
+        self.handle.snapshot().content
+    }
+
+    fn insert(&self, index: u32, text: &str) -> PyResult<()> {
+        self.handle.insert(index, text).map_err(io_error)
+    }
+
+    fn delete(&self, start: u32, end: u32) -> PyResult<()> {
+        self.handle.delete(start, end).map_err(io_error)
+    }
+
+    fn replace(&self, start: u32, end: u32, text: &str) -> PyResult<()> {
+        self.handle.replace(start, end, text).map_err(io_error)
+    }
+
+    fn __repr__(&self) -> String {
+        let snapshot = self.handle.snapshot();
+        format!(
+            "DocumentHandle(id={}, path={:?}, version={})",
+            self.id, snapshot.path, snapshot.version
+        )
+    }
+}
+
+/// An applied operation, as reported in `Event.operation`.
+#[pyclass(name = "Operation", module = "dist_space", get_all, frozen)]
+struct PyOperation {
+    op_id: u64,
+    doc_id: String,
+    client_id: String,
+    server_version: u64,
+    /// "insert", "delete", "replace" or "noop".
+    kind: String,
+    /// Insert position, or the start of a deleted or replaced range.
+    start: u32,
+    /// End of a deleted or replaced range; `start` for inserts.
+    end: u32,
+    text: String,
+}
+
+impl From<OperationProto> for PyOperation {
+    fn from(op: OperationProto) -> Self {
+        let (kind, start, end, text) = match op.kind {
+            Some(Kind::Insert(insert)) => ("insert", insert.index, insert.index, insert.text),
+            Some(Kind::Delete(delete)) => ("delete", delete.start, delete.end, String::new()),
+            Some(Kind::Replace(replace)) => ("replace", replace.start, replace.end, replace.text),
+            Some(Kind::InsertLine(insert)) => {
+                ("insert_line", insert.index, insert.index, insert.text)
+            }
+            Some(Kind::DeleteLine(delete)) => {
+                ("delete_line", delete.start, delete.end, String::new())
+            }
+            Some(Kind::MoveLine(moved)) => ("move_line", moved.start, moved.end, String::new()),
+            Some(Kind::Noop(_)) | None => ("noop", 0, 0, String::new()),
+        };
+        PyOperation {
+            op_id: op.op_id,
+            doc_id: op.doc_id,
+            client_id: op.client_id,
+            server_version: op.server_version,
+            kind: kind.to_string(),
+            start,
+            end,
+            text,
+        }
+    }
+}
+
+#[pymethods]
+impl PyOperation {
+    fn __repr__(&self) -> String {
+        format!(
+            "Operation(kind={:?}, start={}, end={}, text={:?}, server_version={})",
+            self.kind, self.start, self.end, self.text, self.server_version
+        )
+    }
+}
+
+/// Something that happened on one of the connection's documents, or to the
+/// connection as a whole.
+#[pyclass(name = "Event", module = "dist_space", get_all, frozen)]
+struct PyEvent {
+    /// `DocumentHandle.id` of the document; `None` for a notice.
+    handle: Option<HandleId>,
+    /// "synced", "remote_operation", "rebased", "acknowledged",
+    /// "rolled_back", "redirected", "notice" or "disconnected".
+    kind: String,
+    /// The document version after a sync; `None` otherwise.
+    version: Option<u64>,
+    operation: Option<Py<PyOperation>>,
+    /// Why the connection closed, for "disconnected", why the server refused
+    /// our edit, for "rolled_back", the server the document moved to, for
+    /// "redirected", or the operator's message, for "notice".
+    reason: Option<String>,
+    /// For "rebased": the `(start, end)` our edit was written for, and where
+    /// collaborators' edits moved it to (`None` if it was dropped).
+    sent: Option<(u32, u32)>,
+    landed: Option<(u32, u32)>,
+}
+
+impl PyEvent {
+    fn new(py: Python<'_>, event: client::Event) -> PyResult<Self> {
+        let operation = |op: OperationProto| Py::new(py, PyOperation::from(op)).map(Some);
+        let (mut sent, mut landed) = (None, None);
+        let (kind, version, operation, reason) = match event.kind {
+            EventKind::Synced { version } => ("synced", Some(version), None, None),
+            EventKind::RemoteOperation(op) => ("remote_operation", None, operation(op)?, None),
+            EventKind::Acknowledged(op) => ("acknowledged", None, operation(op)?, None),
+            EventKind::Rebased(rebased) => {
+                (sent, landed) = (Some(rebased.sent), rebased.landed);
+                ("rebased", None, None, None)
+            }
+            EventKind::RolledBack { message, .. } => ("rolled_back", None, None, Some(message)),
+            EventKind::Redirected { address } => ("redirected", None, None, Some(address)),
+            EventKind::Notice { message } => ("notice", None, None, Some(message)),
+            EventKind::Disconnected(reason) => ("disconnected", None, None, Some(reason)),
+        };
+        Ok(PyEvent {
+            handle: event.handle,
+            kind: kind.to_string(),
+            version,
+            operation,
+            reason,
+            sent,
+            landed,
+        })
+    }
+}
+
+#[pymethods]
+impl PyEvent {
+    fn __repr__(&self) -> String {
+        match self.handle {
+            Some(handle) => format!("Event(handle={}, kind={:?})", handle, self.kind),
+            None => format!("Event(kind={:?})", self.kind),
+        }
+    }
+}
+
+/// A client session: one connection per server, with every document opened
+/// through it reporting into a single event stream.
+#[pyclass(name = "Connection", module = "dist_space")]
+struct PyConnection {
+    server: String,
+    display_name: String,
+    auth_token: String,
+    workspace: String,
+    read_only: bool,
+    session: Mutex<Session>,
+    /// The document opened with the connection.
+    document: Py<PyDocumentHandle>,
+}
+
+impl PyConnection {
+    fn session(&self) -> MutexGuard<'_, Session> {
+        match self.session.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn next_event_blocking(
+        &self,
+        py: Python<'_>,
+        timeout: Option<Duration>,
+    ) -> PyResult<Option<PyEvent>> {
+        // Waiting must not hold the GIL, or Python threads stall with us
+        let event = py.detach(|| {
+            let session = self.session();
+            match timeout {
+                Some(timeout) => session.next_event_timeout(timeout),
+                None => session.next_event(),
+            }
+        });
+        event.map(|event| PyEvent::new(py, event)).transpose()
+    }
+}
+
+#[pymethods]
+impl PyConnection {
+    /// Connects to `server` and opens `doc_path` (the server default if empty)
+    /// in `workspace` (likewise).
+    #[new]
+    #[pyo3(signature = (server, doc_path = "", display_name = "python", auth_token = "", read_only = false, workspace = ""))]
+    fn new(
+        py: Python<'_>,
+        server: &str,
+        doc_path: &str,
+        display_name: &str,
+        auth_token: &str,
+        read_only: bool,
+        workspace: &str,
+    ) -> PyResult<Self> {
+        let options = ConnectOptions {
+            server: server.to_string(),
+            doc_path: doc_path.to_string(),
+            display_name: display_name.to_string(),
+            auth_token: auth_token.to_string(),
+            workspace: workspace.to_string(),
+            read_only,
+            ..Default::default()
+        };
+        let mut session = Session::new();
+        let (id, handle) = py.detach(|| session.open(&options)).map_err(io_error)?;
+        Ok(PyConnection {
+            server: options.server,
+            display_name: options.display_name,
+            auth_token: options.auth_token,
+            workspace: options.workspace,
+            read_only,
+            session: Mutex::new(session),
+            document: Py::new(py, PyDocumentHandle { id, handle })?,
+        })
+    }
+
+    #[getter]
+    fn document(&self, py: Python<'_>) -> Py<PyDocumentHandle> {
+        self.document.clone_ref(py)
+    }
+
+    /// Opens another document on the same server, over the same connection.
+    fn open(&self, py: Python<'_>, path: &str) -> PyResult<PyDocumentHandle> {
+        let options = ConnectOptions {
+            server: self.server.clone(),
+            doc_path: path.to_string(),
+            display_name: self.display_name.clone(),
+            auth_token: self.auth_token.clone(),
+            workspace: self.workspace.clone(),
+            read_only: self.read_only,
+            ..Default::default()
+        };
+        let (id, handle) = py
+            .detach(|| self.session().open(&options))
+            .map_err(io_error)?;
+        Ok(PyDocumentHandle { id, handle })
+    }
+
+    /// Stops receiving a document's updates.
+    fn close(&self, document: &PyDocumentHandle) -> PyResult<()> {
+        match self.session().close(document.id) {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(format!(
+                "document {} is not open",
+                document.id
+            ))),
+        }
+    }
+
+    /// The next event on any open document; `None` once `timeout` seconds
+    /// pass without one, or the connection has gone.
+    #[pyo3(signature = (timeout = None))]
+    fn next_event(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyEvent>> {
+        self.next_event_blocking(py, self::timeout(timeout)?)
+    }
+
+    /// Iterates over events until `timeout` seconds pass without one (forever
+    /// if `None`) or the connection goes.
+    #[pyo3(signature = (timeout = None))]
+    fn events(slf: Py<Self>, timeout: Option<f64>) -> PyResult<PyEventIterator> {
+        Ok(PyEventIterator {
+            connection: slf,
+            timeout: self::timeout(timeout)?,
+        })
+    }
+
+    /// Blocks until `document` has been synced at least once, so edits can
+    /// be based on it. Events read while waiting are discarded.
+    #[pyo3(signature = (document, timeout = 10.0))]
+    fn wait_synced(
+        &self,
+        py: Python<'_>,
+        document: &PyDocumentHandle,
+        timeout: f64,
+    ) -> PyResult<()> {
+        let timeout = self::timeout(Some(timeout))?;
+        while document.handle.snapshot().doc_id.is_empty() {
+            match self.next_event_blocking(py, timeout)? {
+                Some(event) if event.kind == "disconnected" => {
+                    return Err(PyConnectionError::new_err(event.reason.unwrap_or_default()));
+                }
+                Some(_) => {}
+                None => return Err(PyTimeoutError::new_err("document was not synced in time")),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Blocking iterator over a connection's events.
+#[pyclass(name = "EventIterator", module = "dist_space")]
+struct PyEventIterator {
+    connection: Py<PyConnection>,
+    timeout: Option<Duration>,
+}
+
+#[pymethods]
+impl PyEventIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyEvent>> {
+        self.connection
+            .borrow(py)
+            .next_event_blocking(py, self.timeout)
+    }
+}
+
+#[pymodule]
+fn dist_space(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyConnection>()?;
+    m.add_class::<PyDocumentHandle>()?;
+    m.add_class::<PyEvent>()?;
+    m.add_class::<PyOperation>()?;
+    m.add_class::<PyEventIterator>()?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+"""End-to-end checks against a running server.
+
+Start one with `cargo run -p server`, then:
+
+    cd python && maturin develop && DIST_SPACE_SERVER=127.0.0.1:8000 pytest
+"""
+
+import os
+import uuid
+
+import pytest
+
+import dist_space
+
+SERVER = os.environ.get("DIST_SPACE_SERVER")
+
+pytestmark = pytest.mark.skipif(not SERVER, reason="DIST_SPACE_SERVER is not set")
+
+
+def wait_for(conn, kind, timeout=5.0):
+    for event in conn.events(timeout=timeout):
+        if event.kind == kind:
+            return event
+    pytest.fail(f"no {kind} event within {timeout}s")
+
+
+def test_edits_reach_other_clients():
+    path = f"py-{uuid.uuid4().hex}.txt"
+    alice = dist_space.Connection(SERVER, path, display_name="alice")
+    bob = dist_space.Connection(SERVER, path, display_name="bob")
+    alice.wait_synced(alice.document)
+    bob.wait_synced(bob.document)
+
+    alice.document.insert(0, "hello")
+    ack = wait_for(alice, "acknowledged")
+    assert ack.operation.kind == "insert"
+    assert ack.operation.text == "hello"
+
+    remote = wait_for(bob, "remote_operation")
+    assert remote.handle == bob.document.id
+    assert remote.operation.client_id == alice.document.client_id
+    assert bob.document.content == "hello"
+
+
+def test_next_event_times_out():
+    conn = dist_space.Connection(SERVER, f"py-{uuid.uuid4().hex}.txt")
+    conn.wait_synced(conn.document)
+    assert conn.next_event(timeout=0.2) is None
+
+
+def test_closing_an_unknown_document_raises():
+    conn = dist_space.Connection(SERVER, f"py-{uuid.uuid4().hex}.txt")
+    other = conn.open(f"py-{uuid.uuid4().hex}.txt")
+    conn.close(other)
+    with pytest.raises(KeyError):
+        conn.close(other)
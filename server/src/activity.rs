@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use common::space::{ActivityProto, DocumentActivityProto, WorkspaceEventProto};
+
+/// What happened in a workspace since its activity feed was last sent:
+/// its events as they were recorded, and edits summed up per document so
+/// subscribers get one line per document however many ops it took.
+#[derive(Debug, Default)]
+pub struct ActivityDigest {
+    events: Vec<WorkspaceEventProto>,
+    /// Edits by document id.
+    edits: BTreeMap<String, DocumentActivityProto>,
+    joined: Vec<String>,
+    left: Vec<String>,
+}
+
+impl ActivityDigest {
+    pub fn event(&mut self, event: WorkspaceEventProto) {
+        self.events.push(event);
+    }
+
+    /// Counts `ops` ops by `editor` that took the document to `version`.
+    pub fn edit(&mut self, doc_id: &str, path: &str, editor: &str, ops: u32, version: u64) {
+        let edits = self
+            .edits
+            .entry(doc_id.to_string())
+            .or_insert_with(|| DocumentActivityProto {
+                doc_id: doc_id.to_string(),
+                path: path.to_string(),
+                ..Default::default()
+            });
+        edits.ops = edits.ops.saturating_add(ops);
+        edits.version = edits.version.max(version);
+        if !edits.editors.iter().any(|e| e == editor) {
+            edits.editors.push(editor.to_string());
+        }
+    }
+
+    pub fn joined(&mut self, who: String) {
+        self.joined.push(who);
+    }
+
+    pub fn left(&mut self, who: String) {
+        self.left.push(who);
+    }
+
+    /// Everything since the last call, or `None` if nothing happened.
+    pub fn take(&mut self) -> Option<ActivityProto> {
+        let digest = std::mem::take(self);
+        if digest.events.is_empty()
+            && digest.edits.is_empty()
+            && digest.joined.is_empty()
+            && digest.left.is_empty()
+        {
+            return None;
+        }
+        Some(ActivityProto {
+            events: digest.events,
+            edits: digest.edits.into_values().collect(),
+            joined: digest.joined,
+            left: digest.left,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_are_summed_per_document() {
+        let mut digest = ActivityDigest::default();
+        assert_eq!(digest.take(), None);
+
+        digest.edit("d1", "notes.txt", "ada", 1, 4);
+        digest.edit("d1", "notes.txt", "grace", 3, 7);
+        digest.edit("d1", "notes.txt", "ada", 1, 8);
+        digest.edit("d2", "todo.md", "grace", 1, 1);
+        digest.joined("alan".to_string());
+
+        let activity = digest.take().unwrap();
+        assert_eq!(activity.edits.len(), 2);
+        assert_eq!(activity.edits[0].ops, 5);
+        assert_eq!(activity.edits[0].version, 8);
+        assert_eq!(activity.edits[0].editors, ["ada", "grace"]);
+        assert_eq!(activity.joined, ["alan"]);
+        assert_eq!(digest.take(), None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use common::operation::{Operation, OperationKind};
+
+use crate::log::error;
+use crate::state::ServerState;
+
+/// Appends applied operations to a JSON Lines file, one object per op, for
+/// analysis outside the server. Each export picks up where the previous one
+/// stopped, so the file grows into a complete record of every document's edits.
+///
+/// ```text
+/// {"applied_at_ms":1760000000000,"applied_mono_ms":52000,"path":"main.txt","doc_id":"…","version":4,"op_id":7,"author":"…","client_version":3,"kind":"insert","index":12,"text":"hi"}
+/// ```
+pub struct OpLogExporter {
+    file: PathBuf,
+    /// Next version to export, by document id.
+    next_version: HashMap<String, u64>,
+}
+
+impl OpLogExporter {
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            file,
+            next_version: HashMap::new(),
+        }
+    }
+
+    /// Appends every op applied since the previous export. Returns the number
+    /// of lines written.
+    pub fn export(&mut self, state: &ServerState) -> io::Result<usize> {
+        let mut out = String::new();
+        let mut written = 0;
+        let mut advanced = Vec::new();
+        for entry in state.documents() {
+            let doc_id = entry.sync_proto().doc_id;
+            let from = self.next_version.get(&doc_id).copied().unwrap_or(0);
+            let history = entry.op_log.history();
+            let Some(last) = history.last() else {
+                continue;
+            };
+            if history[0].server_version > from {
+                error!(
+                    "[Analytics] '{}' v{}..v{} were compacted before export",
+                    entry.path, from, history[0].server_version
+                );
+            }
+            for op in history.iter().filter(|op| op.server_version >= from) {
+                out.push_str(&op_line(&entry.path, op));
+                out.push('\n');
+                written += 1;
+            }
+            advanced.push((doc_id, last.server_version + 1));
+        }
+        if written == 0 {
+            return Ok(0);
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?;
+        file.write_all(out.as_bytes())?;
+        // Only move on once the lines are safely written
+        self.next_version.extend(advanced);
+        Ok(written)
+    }
+}
+
+/// One op as a single-line JSON object.
+pub fn op_line(path: &str, op: &Operation) -> String {
+    let mut line = format!(
+        "{{\"applied_at_ms\":{},\"applied_mono_ms\":{},\"path\":{},\"doc_id\":{},\"version\":{},\"op_id\":{},\"author\":\"{}\",\"client_version\":{}",
+        op.applied_at.wall_ms,
+        op.applied_at.mono_ms,
+        json_string(path),
+        json_string(&op.doc_id),
+        op.server_version,
+        op.op_id,
+        op.client_id,
+        op.client_version
+    );
+    // Writing to a String cannot fail
+    let _ = match &op.kind {
+        OperationKind::Insert(insert) => write!(
+            line,
+            ",\"kind\":\"insert\",\"index\":{},\"text\":{}",
+            insert.index,
+            json_string(&insert.text)
+        ),
+        OperationKind::Delete(delete) => write!(
+            line,
+            ",\"kind\":\"delete\",\"start\":{},\"end\":{}",
+            delete.start, delete.end
+        ),
+        OperationKind::Replace(replace) => write!(
+            line,
+            ",\"kind\":\"replace\",\"start\":{},\"end\":{},\"text\":{}",
+            replace.start,
+            replace.end,
+            json_string(&replace.text)
+        ),
+        OperationKind::Noop(_) => write!(line, ",\"kind\":\"noop\""),
+        OperationKind::InsertLine(insert) => write!(
+            line,
+            ",\"kind\":\"insert_line\",\"index\":{},\"text\":{}",
+            insert.index,
+            json_string(&insert.text)
+        ),
+        OperationKind::DeleteLine(delete) => write!(
+            line,
+            ",\"kind\":\"delete_line\",\"start\":{},\"end\":{}",
+            delete.start, delete.end
+        ),
+        OperationKind::MoveLine(moved) => write!(
+            line,
+            ",\"kind\":\"move_line\",\"start\":{},\"end\":{},\"to\":{}",
+            moved.start, moved.end, moved.to
+        ),
+    };
+    line.push('}');
+    line
+}
+
+/// `text` as a quoted JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::clock::Timestamp;
+    use common::operation::{DeleteOp, InsertOp};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_op_lines_are_escaped_json() {
+        let op = Operation {
+            op_id: 7,
+            kind: OperationKind::Insert(InsertOp {
+                index: 12,
+                text: "say \"hi\"\n\u{1}".to_string(),
+                client_id: String::new(),
+                client_version: 3,
+            }),
+            doc_id: "doc".to_string(),
+            new_content: String::new(),
+            client_id: Uuid::nil(),
+            client_version: 3,
+            server_version: 4,
+            applied_at: Timestamp {
+                wall_ms: 1_000,
+                mono_ms: 20,
+            },
+        };
+        assert_eq!(
+            op_line("notes.txt", &op),
+            "{\"applied_at_ms\":1000,\"applied_mono_ms\":20,\"path\":\"notes.txt\",\"doc_id\":\"doc\",\"version\":4,\"op_id\":7,\"author\":\"00000000-0000-0000-0000-000000000000\",\"client_version\":3,\"kind\":\"insert\",\"index\":12,\"text\":\"say \\\"hi\\\"\\n\\u0001\"}"
+        );
+
+        let delete = Operation {
+            kind: OperationKind::Delete(DeleteOp {
+                start: 1,
+                end: 3,
+                client_id: String::new(),
+                client_version: 3,
+            }),
+            ..op
+        };
+        assert!(
+            op_line("notes.txt", &delete).ends_with(",\"kind\":\"delete\",\"start\":1,\"end\":3}")
+        );
+    }
+}
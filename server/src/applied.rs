@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+
+use common::space::OperationProto;
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use uuid::Uuid;
+
+use crate::log::error;
+
+/// Ops a subscriber may fall behind by before the ones after are dropped.
+pub const SUBSCRIBER_CAPACITY: usize = 4096;
+
+/// An op as it was applied and broadcast, with where it was applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedOp {
+    /// The connection that sent it.
+    pub origin: Uuid,
+    pub workspace: String,
+    pub path: String,
+    pub operation: OperationProto,
+}
+
+struct Subscriber {
+    name: String,
+    sender: Sender<Arc<AppliedOp>>,
+    /// Set while its queue is full, so the overflow is logged once.
+    lagging: bool,
+}
+
+/// Every op applied in any workspace, fanned out to whatever derives data
+/// from documents inside the server (a search index, statistics, webhooks)
+/// without going through the connections' broadcast. Each subscriber gets a
+/// bounded queue of its own: one that falls behind misses ops rather than
+/// holding up edits, and one that drops its receiver is forgotten.
+#[derive(Default)]
+pub struct AppliedOps {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl AppliedOps {
+    /// A new subscriber, named for logs, receiving the ops applied from now on.
+    pub fn subscribe(&self, name: &str) -> Receiver<Arc<AppliedOp>> {
+        let (sender, receiver) = channel::bounded(SUBSCRIBER_CAPACITY);
+        self.lock().push(Subscriber {
+            name: name.to_string(),
+            sender,
+            lagging: false,
+        });
+        receiver
+    }
+
+    pub fn publish(&self, applied: AppliedOp) {
+        let applied = Arc::new(applied);
+        self.lock()
+            .retain_mut(|subscriber| match subscriber.sender.try_send(Arc::clone(&applied)) {
+                Ok(()) => {
+                    subscriber.lagging = false;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    if !subscriber.lagging {
+                        error!(
+                            "[AppliedOps] '{}' fell {} ops behind; dropping ops until it catches up",
+                            subscriber.name, SUBSCRIBER_CAPACITY
+                        );
+                    }
+                    subscriber.lagging = true;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Subscriber>> {
+        match self.subscribers.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(op_id: u64) -> AppliedOp {
+        AppliedOp {
+            origin: Uuid::nil(),
+            workspace: "default".to_string(),
+            path: "main.txt".to_string(),
+            operation: OperationProto {
+                op_id,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_ops_fan_out_and_slow_subscribers_miss_them() {
+        let ops = AppliedOps::default();
+        let search = ops.subscribe("search");
+        let stats = ops.subscribe("stats");
+
+        for op_id in 0..=SUBSCRIBER_CAPACITY as u64 {
+            ops.publish(applied(op_id));
+            if op_id == 0 {
+                assert_eq!(search.try_recv().unwrap().operation.op_id, 0);
+            }
+        }
+        // The search index kept up; the statistics missed the last op
+        assert_eq!(search.len(), SUBSCRIBER_CAPACITY);
+        assert_eq!(stats.len(), SUBSCRIBER_CAPACITY);
+        assert_eq!(stats.try_recv().unwrap().operation.op_id, 0);
+
+        drop(stats);
+        ops.publish(applied(1));
+        assert_eq!(ops.lock().len(), 1);
+        drop(search);
+        ops.publish(applied(2));
+        assert!(ops.lock().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::autosave;
+use crate::log::{error, info};
+use crate::sha256;
+
+/// What a document contains, right before an attachment's id, to reference it.
+pub const REFERENCE_PREFIX: &str = "attachment:";
+
+/// How long an attachment no document references is kept, so an upload can
+/// be referenced after it completes.
+pub const UNREFERENCED_GRACE_MS: u64 = 60 * 60 * 1000;
+
+/// Limits on the attachments clients upload; `None` for no limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentLimits {
+    /// Bytes of a single attachment.
+    pub max_bytes: Option<u64>,
+    /// Bytes of all attachments together.
+    pub quota: Option<u64>,
+}
+
+struct Blob {
+    size: u64,
+    /// When it was stored, or last uploaded again; unix milliseconds.
+    stored_ms: u64,
+    /// The content, for a store kept in memory.
+    data: Option<Arc<Vec<u8>>>,
+}
+
+/// Binary files attached to documents, stored once per content under the
+/// SHA-256 of it: in a directory, one file per attachment named by its id,
+/// or in memory for a server without one.
+pub struct AttachmentStore {
+    dir: Option<PathBuf>,
+    limits: AttachmentLimits,
+    blobs: HashMap<String, Blob>,
+}
+
+impl AttachmentStore {
+    /// A store in memory, losing its attachments at shutdown.
+    pub fn new(limits: AttachmentLimits) -> Self {
+        Self {
+            dir: None,
+            limits,
+            blobs: HashMap::new(),
+        }
+    }
+
+    /// A store in `dir`, creating it if needed and picking up the
+    /// attachments already there. They all get a fresh grace period.
+    pub fn open(dir: PathBuf, limits: AttachmentLimits, now_ms: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut blobs = HashMap::new();
+        for file in fs::read_dir(&dir)? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().into_owned();
+            if is_id(&name) {
+                let size = file.metadata()?.len();
+                let blob = Blob {
+                    size,
+                    stored_ms: now_ms,
+                    data: None,
+                };
+                blobs.insert(name, blob);
+            }
+        }
+        info!(
+            "[Attachments] Found {} attachment(s) in {}",
+            blobs.len(),
+            dir.display()
+        );
+        Ok(Self {
+            dir: Some(dir),
+            limits,
+            blobs,
+        })
+    }
+
+    pub fn limits(&self) -> AttachmentLimits {
+        self.limits
+    }
+
+    /// Attachments stored, and their bytes.
+    pub fn usage(&self) -> (usize, u64) {
+        (
+            self.blobs.len(),
+            self.blobs.values().map(|blob| blob.size).sum(),
+        )
+    }
+
+    /// Stores `data`, refused if it is larger than an attachment may be or
+    /// would take the store past its quota. Returns its id. Data stored
+    /// already is not stored again, and counts against nothing.
+    pub fn store(&mut self, data: Vec<u8>, now_ms: u64) -> Result<String, String> {
+        let size = data.len() as u64;
+        if let Some(max) = self.limits.max_bytes
+            && size > max
+        {
+            return Err(format!(
+                "attachment of {} bytes is over the {} allowed",
+                size, max
+            ));
+        }
+        let id = sha256::hex_digest(&data);
+        if let Some(blob) = self.blobs.get_mut(&id) {
+            blob.stored_ms = now_ms;
+            return Ok(id);
+        }
+        let (_, used) = self.usage();
+        if let Some(quota) = self.limits.quota
+            && used + size > quota
+        {
+            return Err(format!(
+                "attachments would take {} bytes, over the server's quota of {}",
+                used + size,
+                quota
+            ));
+        }
+
+        let data = match &self.dir {
+            Some(dir) => {
+                autosave::write_atomically(&dir.join(&id), &data)
+                    .map_err(|e| format!("cannot store the attachment: {}", e))?;
+                None
+            }
+            None => Some(Arc::new(data)),
+        };
+        let blob = Blob {
+            size,
+            stored_ms: now_ms,
+            data,
+        };
+        self.blobs.insert(id.clone(), blob);
+        Ok(id)
+    }
+
+    /// The content of attachment `id`.
+    pub fn get(&self, id: &str) -> Result<Arc<Vec<u8>>, String> {
+        let blob = self
+            .blobs
+            .get(id)
+            .ok_or_else(|| format!("no attachment {}", id))?;
+        match (&blob.data, &self.dir) {
+            (Some(data), _) => Ok(Arc::clone(data)),
+            (None, Some(dir)) => fs::read(dir.join(id))
+                .map(Arc::new)
+                .map_err(|e| format!("cannot read attachment {}: {}", id, e)),
+            (None, None) => Err(format!("no attachment {}", id)),
+        }
+    }
+
+    /// Deletes the attachments not in `referenced` whose grace period is
+    /// over. Returns how many went, and their bytes.
+    pub fn collect_garbage(&mut self, referenced: &HashSet<String>, now_ms: u64) -> (usize, u64) {
+        let unreferenced: Vec<String> = self
+            .blobs
+            .iter()
+            .filter(|(id, blob)| {
+                !referenced.contains(*id)
+                    && now_ms.saturating_sub(blob.stored_ms) >= UNREFERENCED_GRACE_MS
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut freed = (0, 0);
+        for id in unreferenced {
+            if let Some(dir) = &self.dir
+                && let Err(e) = fs::remove_file(dir.join(&id))
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                error!("[Attachments] Failed to delete {}: {}", id, e);
+                continue;
+            }
+            if let Some(blob) = self.blobs.remove(&id) {
+                freed.0 += 1;
+                freed.1 += blob.size;
+            }
+        }
+        freed
+    }
+}
+
+/// Ids of the attachments `content` references.
+pub fn references(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .match_indices(REFERENCE_PREFIX)
+        .filter_map(|(at, prefix)| content.get(at + prefix.len()..at + prefix.len() + 64))
+        .filter(|id| is_id(id))
+}
+
+/// Whether `id` could be an attachment's: 64 lowercase hex digits.
+fn is_id(id: &str) -> bool {
+    id.len() == 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachments_are_deduplicated_limited_and_collected() {
+        let dir = std::env::temp_dir().join(format!("dist-space-attach-{}", uuid::Uuid::new_v4()));
+        let limits = AttachmentLimits {
+            max_bytes: Some(8),
+            quota: Some(11),
+        };
+        let mut store = AttachmentStore::open(dir.clone(), limits, 0).unwrap();
+        let a = store.store(b"picture".to_vec(), 0).unwrap();
+        assert_eq!(a, sha256::hex_digest(b"picture"));
+        assert_eq!(store.store(b"picture".to_vec(), 10).unwrap(), a);
+        assert!(store.store(b"too large".to_vec(), 0).is_err());
+        assert!(
+            store
+                .store(b"pdf 2".to_vec(), 0)
+                .unwrap_err()
+                .contains("quota")
+        );
+        let b = store.store(b"pdf".to_vec(), 0).unwrap();
+        assert_eq!(store.usage(), (2, 10));
+        assert_eq!(*store.get(&b).unwrap(), b"pdf");
+
+        let content = format!(
+            "![x]({}{}) and {}{}",
+            REFERENCE_PREFIX, a, REFERENCE_PREFIX, "ab"
+        );
+        let referenced: HashSet<String> = references(&content).map(String::from).collect();
+        assert_eq!(referenced, HashSet::from([a.clone()]));
+        assert_eq!(store.collect_garbage(&referenced, 1), (0, 0));
+        assert_eq!(
+            store.collect_garbage(&referenced, UNREFERENCED_GRACE_MS),
+            (1, 3)
+        );
+        assert!(store.get(&b).is_err());
+
+        let reopened = AttachmentStore::open(dir.clone(), limits, 0).unwrap();
+        assert_eq!(reopened.usage(), (1, 7));
+        assert_eq!(*reopened.get(&a).unwrap(), b"picture");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+use std::{collections::BTreeMap, fmt};
+
+use common::operation::{
+    DeleteLineOp, DeleteOp, InsertLineOp, InsertOp, MoveLineOp, OperationKind, ReplaceOp,
+};
+use uuid::Uuid;
+
+/// An op as its client sent it, before it is transformed, and who sent it.
+pub struct OpRequest<'a> {
+    /// The connection that sent the op.
+    pub origin: Uuid,
+    /// The member the connection authenticated as, if any.
+    pub member: Option<&'a str>,
+    pub workspace: &'a str,
+    pub doc_id: &'a str,
+    pub path: &'a str,
+    pub op: &'a OperationKind,
+    /// The document's length at the op's client version, which the op was
+    /// written against.
+    pub len: usize,
+}
+
+impl OpRequest<'_> {
+    /// The op's name, as rules are written against.
+    pub fn kind(&self) -> &'static str {
+        match self.op {
+            OperationKind::Insert(_) => "insert",
+            OperationKind::Delete(_) => "delete",
+            OperationKind::Replace(_) => "replace",
+            OperationKind::InsertLine(_) => "insert-line",
+            OperationKind::DeleteLine(_) => "delete-line",
+            OperationKind::MoveLine(_) => "move-line",
+            OperationKind::Noop(_) => "noop",
+        }
+    }
+
+    /// The byte range the op touches at its client version; empty at the
+    /// index for inserts. Line ops cover at least this range, and the whole
+    /// lines it falls in.
+    pub fn range(&self) -> (u32, u32) {
+        match self.op {
+            OperationKind::Insert(InsertOp { index, .. })
+            | OperationKind::InsertLine(InsertLineOp { index, .. }) => (*index, *index),
+            OperationKind::Delete(DeleteOp { start, end, .. })
+            | OperationKind::Replace(ReplaceOp { start, end, .. })
+            | OperationKind::DeleteLine(DeleteLineOp { start, end, .. })
+            | OperationKind::MoveLine(MoveLineOp { start, end, .. }) => (*start, *end),
+            OperationKind::Noop(_) => (0, 0),
+        }
+    }
+
+    /// Bytes the op removes from the document, at least.
+    pub fn removed(&self) -> u32 {
+        match self.op {
+            OperationKind::Delete(_) | OperationKind::Replace(_) | OperationKind::DeleteLine(_) => {
+                let (start, end) = self.range();
+                end.saturating_sub(start)
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Decides whether a client may make an edit, before the server transforms
+/// it. Lets a deployment enforce its own rules on who edits what, e.g. that
+/// a bot only appends. Unlike a middleware it sees the op as its client
+/// wrote it, not as it lands.
+pub trait Authorizer: Send + Sync {
+    /// For logs and the refusal.
+    fn name(&self) -> &str;
+
+    /// Refuses the op with a reason for the client.
+    fn authorize(&self, request: &OpRequest) -> Result<(), String>;
+}
+
+/// What a member is held to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpRule {
+    /// Only inserts at the end of the document.
+    AppendOnly,
+    /// Deletes and replaces of at most this many bytes at once.
+    MaxDelete(u32),
+}
+
+impl fmt::Display for OpRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpRule::AppendOnly => write!(f, "append-only"),
+            OpRule::MaxDelete(max) => write!(f, "max-delete:{}", max),
+        }
+    }
+}
+
+/// Rules by member, from `--op-rules`. Connections that are no member, or
+/// a member without rules, edit freely.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpRules {
+    rules: BTreeMap<String, Vec<OpRule>>,
+}
+
+impl OpRules {
+    /// Parses `MEMBER=RULE` pairs, comma-separated, where a rule is
+    /// `append-only` or `max-delete:N`. A member may be given several.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for pair in rules.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((member, rule)) = pair.split_once('=') else {
+                return Err(format!("Expected MEMBER=RULE, got '{}'", pair));
+            };
+            let member = member.trim();
+            if member.is_empty() {
+                return Err(format!("No member in '{}'", pair));
+            }
+            let rule = match rule.trim() {
+                "append-only" => OpRule::AppendOnly,
+                rule => match rule.strip_prefix("max-delete:") {
+                    Some(max) => OpRule::MaxDelete(
+                        max.parse()
+                            .map_err(|_| format!("Invalid byte count in '{}'", pair))?,
+                    ),
+                    None => return Err(format!("Unknown rule in '{}'", pair)),
+                },
+            };
+            parsed
+                .rules
+                .entry(member.to_string())
+                .or_default()
+                .push(rule);
+        }
+        Ok(parsed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl fmt::Display for OpRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .rules
+            .iter()
+            .flat_map(|(member, rules)| {
+                rules.iter().map(move |rule| format!("{}={}", member, rule))
+            })
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+impl Authorizer for OpRules {
+    fn name(&self) -> &str {
+        "op rules"
+    }
+
+    fn authorize(&self, request: &OpRequest) -> Result<(), String> {
+        let Some(rules) = request.member.and_then(|member| self.rules.get(member)) else {
+            return Ok(());
+        };
+        for rule in rules {
+            match rule {
+                OpRule::AppendOnly => {
+                    let appends = match request.op {
+                        OperationKind::Insert(InsertOp { index, .. }) => {
+                            *index as usize >= request.len
+                        }
+                        OperationKind::Noop(_) => true,
+                        _ => false,
+                    };
+                    if !appends {
+                        return Err(format!(
+                            "{} may only append to the document",
+                            request.member.unwrap_or_default()
+                        ));
+                    }
+                }
+                OpRule::MaxDelete(max) => {
+                    if request.removed() > *max {
+                        return Err(format!(
+                            "{} may delete at most {} bytes at once, not {}",
+                            request.member.unwrap_or_default(),
+                            max,
+                            request.removed()
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request<'a>(member: Option<&'a str>, op: &'a OperationKind) -> OpRequest<'a> {
+        OpRequest {
+            origin: Uuid::new_v4(),
+            member,
+            workspace: "team-a",
+            doc_id: "d1",
+            path: "notes.md",
+            op,
+            len: 10,
+        }
+    }
+
+    #[test]
+    fn test_op_rules() {
+        let rules =
+            OpRules::parse("bot=append-only, intern=max-delete:4,intern=max-delete:6").unwrap();
+        assert_eq!(
+            rules.to_string(),
+            "bot=append-only,intern=max-delete:4,intern=max-delete:6"
+        );
+
+        assert!(
+            rules
+                .authorize(&request(Some("bot"), &OperationKind::insert_at(10, "hi")))
+                .is_ok()
+        );
+        assert!(
+            rules
+                .authorize(&request(Some("bot"), &OperationKind::insert_at(3, "hi")))
+                .is_err()
+        );
+        assert!(
+            rules
+                .authorize(&request(Some("bot"), &OperationKind::delete_range(9, 10)))
+                .is_err()
+        );
+
+        assert!(
+            rules
+                .authorize(&request(Some("intern"), &OperationKind::delete_range(0, 4)))
+                .is_ok()
+        );
+        let refused = rules
+            .authorize(&request(Some("intern"), &OperationKind::delete_range(0, 5)))
+            .unwrap_err();
+        assert_eq!(refused, "intern may delete at most 4 bytes at once, not 5");
+
+        // Others, members or not, edit freely
+        assert!(
+            rules
+                .authorize(&request(Some("ada"), &OperationKind::delete_range(0, 10)))
+                .is_ok()
+        );
+        assert!(
+            rules
+                .authorize(&request(None, &OperationKind::insert_at(0, "hi")))
+                .is_ok()
+        );
+
+        assert!(OpRules::parse("").unwrap().is_empty());
+        assert!(OpRules::parse("bot").is_err());
+        assert!(OpRules::parse("=append-only").is_err());
+        assert!(OpRules::parse("bot=read-only").is_err());
+        assert!(OpRules::parse("intern=max-delete:lots").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::documents::DocumentEntry;
+use crate::log::{error, info};
+use crate::state::ServerState;
+
+/// Reads a document's backing file. A missing file is an empty document.
+pub fn load(file: &Path) -> io::Result<String> {
+    match fs::read_to_string(file) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the document to its backing file if it changed since the last save.
+/// Returns whether anything was written.
+pub fn save(entry: &DocumentEntry) -> io::Result<bool> {
+    let Some(file) = &entry.backing_file else {
+        return Ok(false);
+    };
+    // Only the refcount is taken under the lock; the write happens after.
+    let (content, version) = {
+        let doc = match entry.document.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        (doc.snapshot(), doc.version)
+    };
+    if version == entry.saved_version.load(Ordering::Acquire) {
+        return Ok(false);
+    }
+
+    write_atomically(file, content.as_bytes())?;
+    entry.saved_version.store(version, Ordering::Release);
+    info!(
+        "[Autosave] Saved '{}' v{} to {}",
+        entry.path,
+        version,
+        file.display()
+    );
+    Ok(true)
+}
+
+/// Writes through a temporary file and renames it over `file`, so a crash
+/// mid-write never leaves a truncated file behind.
+pub fn write_atomically(file: &Path, content: &[u8]) -> io::Result<()> {
+    let mut tmp_name = file.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = file.with_file_name(tmp_name);
+
+    let mut out = fs::File::create(&tmp)?;
+    out.write_all(content)?;
+    out.sync_all()?;
+    fs::rename(&tmp, file)
+}
+
+/// Saves every persisted document with unsaved edits (used at shutdown).
+/// Returns the number of documents written.
+pub fn save_all(state: &ServerState) -> usize {
+    state
+        .documents()
+        .iter()
+        .filter(|entry| match save(entry) {
+            Ok(saved) => saved,
+            Err(e) => {
+                error!("[Autosave] Failed to save '{}': {}", entry.path, e);
+                false
+            }
+        })
+        .count()
+}
+
+/// Debounced saving, driven by a periodic tick: a document is written once
+/// its version has stopped moving for a whole tick, so a burst of edits
+/// becomes one write instead of one per op.
+#[derive(Default)]
+pub struct Autosave {
+    /// Version of each persisted document at the previous tick, by path.
+    seen: HashMap<PathBuf, u64>,
+}
+
+impl Autosave {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&mut self, state: &ServerState) {
+        for entry in state.documents() {
+            let Some(file) = &entry.backing_file else {
+                continue;
+            };
+            let version = match entry.document.lock() {
+                Ok(doc) => doc.version,
+                Err(poisoned) => poisoned.into_inner().version,
+            };
+            let settled = self.seen.insert(file.clone(), version) == Some(version);
+            if settled && let Err(e) = save(&entry) {
+                error!("[Autosave] Failed to save '{}': {}", entry.path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::operation::{InsertOp, OperationKind};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_edits_are_saved_once_they_settle() {
+        let file = std::env::temp_dir().join(format!("dist-space-{}.txt", Uuid::new_v4()));
+        fs::write(&file, "hello").unwrap();
+
+        let state = ServerState::new().with_backing_file(file.clone()).unwrap();
+        let entry = state.documents().pop().unwrap();
+        assert_eq!(entry.sync_proto().content, "hello");
+
+        let mut autosave = Autosave::new();
+        entry
+            .document
+            .lock()
+            .unwrap()
+            .apply_op(&OperationKind::Insert(InsertOp {
+                index: 5,
+                text: "!".to_string(),
+                client_id: String::new(),
+                client_version: 0,
+            }))
+            .unwrap();
+
+        // First tick only notices the new version; the next one saves it
+        autosave.tick(&state);
+        assert_eq!(load(&file).unwrap(), "hello");
+        autosave.tick(&state);
+        assert_eq!(load(&file).unwrap(), "hello!");
+        assert_eq!(save_all(&state), 0);
+
+        fs::remove_file(&file).unwrap();
+        assert_eq!(load(&file).unwrap(), "");
+    }
+}
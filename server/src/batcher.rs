@@ -0,0 +1,159 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use common::{
+    Frame,
+    protocol::ServerMessage,
+    space::{OperationBatchProto, OperationProto},
+};
+use uuid::Uuid;
+
+use crate::broadcaster::BroadcastFn;
+use crate::log::info;
+use crate::state::{AppliedDocument, ServerState};
+
+/// Operations applied to one document since the last flush.
+struct PendingBatch {
+    operations: Vec<OperationProto>,
+    /// Sync for the state after the newest operation; earlier ones are superseded.
+    sync: Arc<Frame>,
+}
+
+/// Micro-batching for the broadcast path: applied operations are collected
+/// per document and flushed once per window as a single OperationBatch frame
+/// followed by one SyncDocument, instead of two frames per operation.
+pub struct Batcher {
+    window: Duration,
+    pending: Mutex<HashMap<String, PendingBatch>>,
+}
+
+impl Batcher {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, HashMap<String, PendingBatch>> {
+        match self.pending.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn push(&self, doc_id: &str, operation: OperationProto, sync: Arc<Frame>) {
+        add(&mut self.lock_pending(), doc_id, vec![operation], sync);
+    }
+
+    /// Queues a transaction's ops on every document it touched at once, so
+    /// one flush carries all of them.
+    pub fn push_transaction(&self, documents: &[AppliedDocument]) {
+        let mut pending = self.lock_pending();
+        for document in documents {
+            add(
+                &mut pending,
+                &document.doc_id,
+                document.operations.clone(),
+                Arc::clone(&document.sync),
+            );
+        }
+    }
+
+    /// Drains everything pending as `(doc_id, batch frame, sync frame)`.
+    pub fn take(&self) -> Vec<(String, Arc<Frame>, Arc<Frame>)> {
+        let pending = std::mem::take(&mut *self.lock_pending());
+        pending
+            .into_iter()
+            .map(|(doc_id, batch)| {
+                let message = ServerMessage::OperationBatch(OperationBatchProto {
+                    operations: batch.operations,
+                    more: false,
+                });
+                let frame = Frame::new_arc(ServerMessage::encode(&message));
+                (doc_id, frame, batch.sync)
+            })
+            .collect()
+    }
+}
+
+fn add(
+    pending: &mut HashMap<String, PendingBatch>,
+    doc_id: &str,
+    operations: Vec<OperationProto>,
+    sync: Arc<Frame>,
+) {
+    match pending.get_mut(doc_id) {
+        Some(batch) => {
+            batch.operations.extend(operations);
+            batch.sync = sync;
+        }
+        None => {
+            pending.insert(doc_id.to_string(), PendingBatch { operations, sync });
+        }
+    }
+}
+
+/// Flushes the state's batcher every window. Batches go to every subscriber,
+/// originators included: the batch doubles as their echo.
+pub fn spawn_flusher(
+    state: Arc<ServerState>,
+    broadcast_fn: BroadcastFn,
+) -> Option<thread::JoinHandle<()>> {
+    let window = state.batcher()?.window;
+    info!("[Batcher] Broadcasting in {}ms windows", window.as_millis());
+
+    Some(thread::spawn(move || {
+        loop {
+            thread::sleep(window);
+            let Some(batcher) = state.batcher() else {
+                return;
+            };
+            for (doc_id, batch, sync) in batcher.take() {
+                for frame in [batch, sync] {
+                    // Nil origin: nobody is excluded
+                    broadcast_fn(Uuid::nil(), &doc_id, frame, &state);
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_groups_by_document_and_keeps_latest_sync() {
+        let batcher = Batcher::new(Duration::from_millis(5));
+        let op = |op_id| OperationProto {
+            op_id,
+            ..Default::default()
+        };
+        let sync_1 = Frame::new_arc(vec![1]);
+        let sync_2 = Frame::new_arc(vec![2]);
+        batcher.push("a", op(1), sync_1);
+        batcher.push("a", op(2), Arc::clone(&sync_2));
+        batcher.push("b", op(3), Frame::new_arc(vec![3]));
+
+        let mut flushed = batcher.take();
+        flushed.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(flushed.len(), 2);
+
+        let (doc_id, batch, sync) = &flushed[0];
+        assert_eq!(doc_id, "a");
+        assert!(Arc::ptr_eq(sync, &sync_2));
+        match ServerMessage::decode_bytes(&batch.payload).unwrap() {
+            ServerMessage::OperationBatch(batch) => {
+                let ids: Vec<u64> = batch.operations.iter().map(|op| op.op_id).collect();
+                assert_eq!(ids, vec![1, 2]);
+            }
+            _ => panic!("expected OperationBatch"),
+        }
+        assert!(batcher.take().is_empty());
+    }
+}
@@ -1,16 +1,20 @@
-use std::{
-    collections::HashSet,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, sync::Arc};
 
 use common::Frame;
-use crossbeam::channel::TrySendError;
 use uuid::Uuid;
 
 use crate::client_entry::ClientEntry;
+use crate::error::ServerError;
+use crate::log::trace;
+use crate::metrics::{Eviction, TRAFFIC};
+use crate::state::ServerState;
 
-pub fn broadcast(origin_id: Uuid, frame: Arc<Frame>, clients: Arc<Mutex<Vec<Arc<ClientEntry>>>>) {
-    let mut failed_clients: HashSet<Uuid> = HashSet::new();
+pub type BroadcastFn = fn(origin_id: Uuid, doc_id: &str, frame: Arc<Frame>, state: &ServerState);
+
+/// Sends `frame` to every client other than the origin that has `doc_id` open.
+pub fn broadcast(origin_id: Uuid, doc_id: &str, frame: Arc<Frame>, state: &ServerState) {
+    let mut failed_clients: HashMap<Uuid, Eviction> = HashMap::new();
+    let clients = state.get_clients_arc();
     let clients_snapshot: Vec<Arc<ClientEntry>>;
 
     {
@@ -25,35 +29,75 @@ pub fn broadcast(origin_id: Uuid, frame: Arc<Frame>, clients: Arc<Mutex<Vec<Arc<
         //      - proxies → unpredictable
         //      - ephemeral ports → randomness
 
-        if client_entry.client_id != origin_id {
-            let sender = &client_entry.writer_sender;
-
-            match sender.try_send(Arc::clone(&frame)) {
-                Ok(()) => println!("Message sent!"),
+        if client_entry.client_id != origin_id && client_entry.is_subscribed(doc_id) {
+            match client_entry
+                .send(Arc::clone(&frame))
+                .map_err(ServerError::from)
+            {
+                Ok(()) => {
+                    TRAFFIC.record_broadcast();
+                    trace!("Message sent!");
+                }
 
-                Err(TrySendError::Full(_)) => {
+                Err(ServerError::QueueFull) => {
                     // A slow client must not affect the performance of the rest of the system;
                     // any client whose writer channel is full is immediately dropped.
-                    failed_clients.insert(client_entry.client_id.clone());
+                    // Its writer may be blocked on the socket, so hang up rather than
+                    // wait for the queue to drain.
+                    client_entry.hang_up();
+                    failed_clients.insert(client_entry.client_id, Eviction::SlowConsumer);
                 }
 
-                Err(TrySendError::Disconnected(_)) => {
-                    failed_clients.insert(client_entry.client_id.clone());
+                Err(_) => {
+                    failed_clients.insert(client_entry.client_id, Eviction::WriterGone);
                 }
             }
         }
     }
 
-    if !failed_clients.is_empty() {
-        let mut clients_guard = clients.lock().unwrap();
+    // Announced gone to the rest of their workspace, and recorded, like any departure
+    for (client_id, eviction) in failed_clients {
+        state.evict(client_id, eviction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{protocol::ServerMessage, space::PresenceStatus};
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_slow_consumer_is_hung_up_and_announced() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+
+        let state = ServerState::new();
+        let (tx, _rx) = crossbeam::channel::bounded(1);
+        let slow = ClientEntry::new(Uuid::new_v4(), tx).with_socket(socket);
+        slow.subscribe("doc");
+        slow.send(Frame::new_arc(vec![1])).unwrap();
+        state.add_client(slow).unwrap();
+        let (tx, bystander_rx) = crossbeam::channel::bounded(4);
+        state
+            .add_client(ClientEntry::new(Uuid::new_v4(), tx))
+            .unwrap();
 
-        clients_guard.retain(|client_entry| {
-            if failed_clients.contains(&client_entry.client_id) {
-                println!("Removing disconnected client: {}", client_entry.client_id);
-                false
-            } else {
-                true
+        broadcast(Uuid::nil(), "doc", Frame::new_arc(vec![2]), &state);
+
+        assert_eq!(state.get_clients_arc().lock().unwrap().len(), 1);
+        match ServerMessage::decode_bytes(&bystander_rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Presence(presence)) => {
+                assert_eq!(presence.status(), PresenceStatus::Offline);
+                assert_eq!(presence.reason, "slow consumer");
             }
-        });
+            _ => panic!("expected Presence"),
+        }
+        // Nothing was written; the peer just sees the connection end
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).unwrap();
+        assert!(received.is_empty());
     }
 }
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use common::{
+    Frame,
+    protocol::{MSG_TYPE_OPERATION_BATCH, MSG_TYPE_PRESENCE, ServerMessage},
+    space::CapabilitiesProto,
+};
+
+/// What a connection said it handles in its Hello, which decides the form
+/// the frames sent to it take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub batches: bool,
+    pub presence: bool,
+}
+
+impl Default for Capabilities {
+    /// A client whose Hello says nothing: it gets what clients got before
+    /// capabilities were exchanged.
+    fn default() -> Self {
+        Self {
+            batches: true,
+            presence: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Compression, delta syncs and CRDT state are not implemented, so what
+    /// the client says about them changes nothing.
+    pub fn from_proto(proto: &CapabilitiesProto) -> Self {
+        Self {
+            batches: proto.batches,
+            presence: proto.presence,
+        }
+    }
+
+    /// What this server handles, sent in answer to a Hello with capabilities.
+    pub fn server() -> CapabilitiesProto {
+        CapabilitiesProto {
+            batches: true,
+            presence: true,
+            compression: false,
+            delta_sync: false,
+            crdt: false,
+        }
+    }
+
+    /// `frame` as this connection should get it: a batch split into its
+    /// operations for a client without batches, and nothing for presence it
+    /// did not ask for.
+    pub fn tailor(&self, frame: Arc<Frame>) -> Vec<Arc<Frame>> {
+        match frame.type_id {
+            MSG_TYPE_OPERATION_BATCH if !self.batches => {
+                match ServerMessage::decode_bytes(&frame.payload) {
+                    Ok(ServerMessage::OperationBatch(batch)) => batch
+                        .operations
+                        .into_iter()
+                        .map(|op| {
+                            Frame::new_arc(ServerMessage::encode(&ServerMessage::Operation(op)))
+                        })
+                        .collect(),
+                    _ => vec![frame],
+                }
+            }
+            MSG_TYPE_PRESENCE if !self.presence => Vec::new(),
+            _ => vec![frame],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::space::{OperationBatchProto, OperationProto, PresenceProto};
+
+    fn encode(message: ServerMessage) -> Arc<Frame> {
+        Frame::new_arc(ServerMessage::encode(&message))
+    }
+
+    #[test]
+    fn test_frames_are_tailored_to_what_the_client_handles() {
+        let op = |op_id| OperationProto {
+            op_id,
+            ..Default::default()
+        };
+        let batch = encode(ServerMessage::OperationBatch(OperationBatchProto {
+            operations: vec![op(1), op(2)],
+            ..Default::default()
+        }));
+        let presence = encode(ServerMessage::Presence(PresenceProto::default()));
+
+        let legacy = Capabilities::default();
+        assert_eq!(legacy.tailor(Arc::clone(&batch)).len(), 1);
+        assert_eq!(legacy.tailor(Arc::clone(&presence)).len(), 1);
+
+        let minimal = Capabilities::from_proto(&CapabilitiesProto::default());
+        let split: Vec<u64> = minimal
+            .tailor(batch)
+            .iter()
+            .map(|frame| match ServerMessage::decode_bytes(&frame.payload) {
+                Ok(ServerMessage::Operation(op)) => op.op_id,
+                _ => panic!("expected Operation frames"),
+            })
+            .collect();
+        assert_eq!(split, vec![1, 2]);
+        assert!(minimal.tailor(presence).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use common::{Frame, clock::unix_time_ms, protocol::message_type_name};
+use uuid::Uuid;
+
+/// Frames kept per captured connection, both directions together.
+pub const CAPTURE_CAPACITY: usize = 128;
+
+/// Payload bytes shown per frame by `dump`.
+const DUMP_BYTES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Read from the client.
+    In,
+    /// Written to the client.
+    Out,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Captured {
+    pub at_ms: u64,
+    pub direction: Direction,
+    /// Shares the payload with the frame that was sent or decoded.
+    pub frame: Arc<Frame>,
+}
+
+/// Connections being captured since the console's `capture`.
+pub static CAPTURES: Captures = Captures::new(CAPTURE_CAPACITY);
+
+/// Opt-in recording of the most recent frames on chosen connections. A
+/// capture outlives its connection, so a client can be examined after it
+/// was kicked, until `stop` discards it.
+pub struct Captures {
+    capacity: usize,
+    /// How many connections are captured, so the others pay one atomic load.
+    active: AtomicUsize,
+    connections: Mutex<Vec<(Uuid, VecDeque<Captured>)>>,
+}
+
+impl Captures {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            active: AtomicUsize::new(0),
+            connections: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(Uuid, VecDeque<Captured>)>> {
+        match self.connections.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Starts capturing `client_id`. False if it already was.
+    pub fn start(&self, client_id: Uuid) -> bool {
+        let mut connections = self.lock();
+        if connections.iter().any(|(id, _)| *id == client_id) {
+            return false;
+        }
+        connections.push((client_id, VecDeque::new()));
+        self.active.store(connections.len(), Ordering::Relaxed);
+        true
+    }
+
+    /// Stops capturing `client_id` and discards its frames. False if it
+    /// wasn't captured.
+    pub fn stop(&self, client_id: Uuid) -> bool {
+        let mut connections = self.lock();
+        let before = connections.len();
+        connections.retain(|(id, _)| *id != client_id);
+        self.active.store(connections.len(), Ordering::Relaxed);
+        connections.len() < before
+    }
+
+    /// Captured connections, in the order capturing started.
+    pub fn captured(&self) -> Vec<Uuid> {
+        self.lock().iter().map(|(id, _)| *id).collect()
+    }
+
+    /// Keeps `frame` if `client_id` is captured.
+    pub fn record(&self, client_id: Uuid, direction: Direction, frame: &Arc<Frame>) {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut connections = self.lock();
+        let Some((_, frames)) = connections.iter_mut().find(|(id, _)| *id == client_id) else {
+            return;
+        };
+        if frames.len() == self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(Captured {
+            at_ms: unix_time_ms(),
+            direction,
+            frame: Arc::clone(frame),
+        });
+    }
+
+    /// The frames kept for `client_id`, oldest first; `None` if it isn't
+    /// captured.
+    pub fn frames(&self, client_id: Uuid) -> Option<Vec<Captured>> {
+        self.lock()
+            .iter()
+            .find(|(id, _)| *id == client_id)
+            .map(|(_, frames)| frames.iter().cloned().collect())
+    }
+
+    /// One line per kept frame, oldest first, with the start of its payload
+    /// in hex.
+    pub fn dump(&self, client_id: Uuid) -> Option<String> {
+        let frames = self.frames(client_id)?;
+        if frames.is_empty() {
+            return Some(format!("no frames captured for {}", client_id));
+        }
+        let now_ms = unix_time_ms();
+        let lines: Vec<String> = frames
+            .iter()
+            .map(|captured| {
+                let payload = &captured.frame.payload;
+                let mut hex = String::new();
+                for byte in payload.iter().take(DUMP_BYTES) {
+                    let _ = write!(hex, "{:02x}", byte);
+                }
+                if payload.len() > DUMP_BYTES {
+                    hex.push_str("..");
+                }
+                format!(
+                    "{}ms ago {} {} {} bytes {}",
+                    now_ms.saturating_sub(captured.at_ms),
+                    captured.direction.as_str(),
+                    message_type_name(captured.frame.type_id),
+                    payload.len(),
+                    hex
+                )
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::protocol::{MSG_TYPE_PING, MSG_TYPE_PONG, ServerMessage};
+
+    #[test]
+    fn test_only_captured_connections_are_recorded() {
+        let captures = Captures::new(2);
+        let (watched, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let ping = |n| Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(n)));
+        let pong = Frame::new_arc(ServerMessage::encode(&ServerMessage::Pong(2)));
+
+        captures.record(watched, Direction::Out, &ping(0));
+        assert!(captures.start(watched));
+        assert!(!captures.start(watched));
+        captures.record(other, Direction::Out, &ping(1));
+        captures.record(watched, Direction::Out, &ping(1));
+        captures.record(watched, Direction::Out, &ping(2));
+        captures.record(watched, Direction::In, &pong);
+
+        let frames = captures.frames(watched).unwrap();
+        let kept: Vec<_> = frames
+            .iter()
+            .map(|captured| (captured.direction, captured.frame.type_id))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                (Direction::Out, MSG_TYPE_PING),
+                (Direction::In, MSG_TYPE_PONG)
+            ]
+        );
+        assert!(captures.frames(other).is_none());
+        assert!(captures.dump(watched).unwrap().contains("in Pong"));
+
+        assert!(captures.stop(watched));
+        assert!(captures.dump(watched).is_none());
+        assert!(captures.captured().is_empty());
+    }
+}
@@ -1,18 +1,152 @@
-use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+use std::collections::{HashSet, VecDeque};
+use std::net::TcpStream;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering},
+};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use common::Frame;
-use crossbeam::channel::Sender;
+use common::space::{OperationBatchProto, OperationProto, PropagationSampleProto, ViewportProto};
+use common::{
+    Frame,
+    clock::{ClockSample, unix_time_ms},
+    protocol::encode_sequenced,
+    space::PresenceStatus,
+};
+use crossbeam::channel::{Sender, TrySendError};
+use prost::Message;
 use uuid::Uuid;
 
+use crate::capabilities::Capabilities;
+use crate::dead_letters::{DEAD_LETTERS, DropReason};
+use crate::invites::Invite;
+use crate::metrics::WriterQueueMetrics;
+use crate::propagation::Propagation;
+use crate::validation::{MAX_TRANSACTION_BYTES, Rejection};
+use crate::workspaces::{Access, DEFAULT_WORKSPACE};
+use crate::writer::HangUp;
+
+/// Frames each connection's writer queue holds. A client that lets it fill
+/// is dropped as a slow consumer, unless it paces the server with credit.
+pub const WRITER_QUEUE_CAPACITY: usize = 32;
+
+/// Sequenced frames kept per connection for `resend`.
+pub const RESEND_WINDOW: usize = 256;
+
+/// Frames a flow-controlled connection may have waiting for credit before it
+/// counts as a slow consumer after all.
+pub const MAX_AWAITING_CREDIT: usize = 1024;
+
+/// What a flow-controlled connection still lets the server send. `None` is
+/// unlimited: that kind of credit was never granted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Credit {
+    frames: Option<u64>,
+    bytes: Option<u64>,
+}
+
+impl Credit {
+    /// Grants saturate: clients grant `u64::MAX` for unlimited.
+    fn grant(&mut self, frames: u64, bytes: u64) {
+        if frames > 0 {
+            self.frames = Some(self.frames.unwrap_or(0).saturating_add(frames));
+        }
+        if bytes > 0 {
+            self.bytes = Some(self.bytes.unwrap_or(0).saturating_add(bytes));
+        }
+    }
+
+    /// A frame may overdraw the byte credit, so long as some is left.
+    fn allows(&self) -> bool {
+        self.frames.is_none_or(|frames| frames > 0) && self.bytes.is_none_or(|bytes| bytes > 0)
+    }
+
+    fn spend(&mut self, frame: &Frame) {
+        if let Some(frames) = &mut self.frames {
+            *frames -= 1;
+        }
+        if let Some(bytes) = &mut self.bytes {
+            *bytes = bytes.saturating_sub(frame.total_len() as u64);
+        }
+    }
+}
+
+/// What is on its way to the connection's writer.
+#[derive(Default)]
+struct Outbound {
+    /// Numbering, for a connection that asked for it in its Hello.
+    sequenced: bool,
+    /// From the connection's Hello; frames are tailored to them before
+    /// they are numbered.
+    capabilities: Capabilities,
+    /// Number of the last frame queued; the first is 1.
+    last_seq: u64,
+    /// The last `RESEND_WINDOW` frames queued, as sent, oldest first.
+    retained: VecDeque<(u64, Arc<Frame>)>,
+    /// Set by the connection's first credit grant.
+    credit: Option<Credit>,
+    /// Frames held back for want of credit, oldest first.
+    awaiting_credit: VecDeque<Arc<Frame>>,
+}
+
+/// A transaction arriving over several OperationBatch frames.
+#[derive(Default)]
+struct Chunked {
+    operations: Vec<OperationProto>,
+    /// Encoded size of `operations`.
+    bytes: usize,
+}
+
 /// Represents a connected client with its communication channel and activity tracking.
 #[derive(Clone)]
 pub struct ClientEntry {
     pub client_id: Uuid,
-    pub writer_sender: Sender<Arc<Frame>>,
+    /// Use `send`, which numbers frames for sequenced connections.
+    writer_sender: Sender<Arc<Frame>>,
     /// Last activity timestamp as milliseconds since UNIX epoch.
     /// Updated on every received message.
     last_activity_ms: Arc<AtomicU64>,
+    /// Display name announced in the client's Hello, if any.
+    display_name: Arc<Mutex<Option<String>>>,
+    /// Id the client gave itself in its Hello, which it keeps across its
+    /// connections; its acknowledgement cursors are kept under it.
+    hello_id: Arc<Mutex<Option<String>>>,
+    /// Ids of the documents this connection has opened; broadcasts are routed by these.
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    /// Viewer connection: its operations are rejected. Never cleared once set.
+    read_only: Arc<AtomicBool>,
+    /// Workspace joined by the client's first Hello; `None` counts as the
+    /// default workspace until then.
+    workspace: Arc<Mutex<Option<String>>>,
+    /// What the client's token let it into its workspace as.
+    access: Arc<Mutex<Access>>,
+    /// Last message from the user rather than the connection (edits, opens,
+    /// locks; not heartbeats), in milliseconds since UNIX epoch. Drives idle
+    /// detection.
+    last_input_ms: Arc<AtomicU64>,
+    /// Set and cleared by the client's SetPresence messages.
+    away: Arc<AtomicBool>,
+    /// Set and cleared by the client's WatchActivity messages.
+    watching_activity: Arc<AtomicBool>,
+    /// Presence last announced to other connections, as a `PresenceStatus`.
+    announced_presence: Arc<AtomicI32>,
+    /// Handle on the connection's socket, for `hang_up`. `None` for
+    /// connections without one (tests, in-memory transports).
+    socket: Arc<Mutex<Option<TcpStream>>>,
+    outbound: Arc<Mutex<Outbound>>,
+    /// Operations of a chunked transaction, held until its last batch.
+    chunked: Arc<Mutex<Chunked>>,
+    /// Data of the attachment being uploaded, held until its last chunk.
+    upload: Arc<Mutex<Vec<u8>>>,
+    /// Lines the client last said it has on screen, as relayed to followers.
+    viewport: Arc<Mutex<Option<ViewportProto>>>,
+    /// Connection whose viewport this one mirrors.
+    following: Arc<Mutex<Option<Uuid>>>,
+    /// Round trip and clock offset the client last reported from a time sync.
+    clock: Arc<Mutex<Option<ClockSample>>>,
+    /// How long the ops the client traced took to reach it.
+    propagation: Arc<Mutex<Propagation>>,
+    queue_metrics: Arc<WriterQueueMetrics>,
 }
 
 impl ClientEntry {
@@ -21,11 +155,522 @@ impl ClientEntry {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis() as u64;
-        
+
         Self {
             client_id,
             writer_sender,
             last_activity_ms: Arc::new(AtomicU64::new(now_ms)),
+            display_name: Arc::new(Mutex::new(None)),
+            hello_id: Arc::new(Mutex::new(None)),
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            read_only: Arc::new(AtomicBool::new(false)),
+            workspace: Arc::new(Mutex::new(None)),
+            access: Arc::new(Mutex::new(Access::Open)),
+            last_input_ms: Arc::new(AtomicU64::new(now_ms)),
+            away: Arc::new(AtomicBool::new(false)),
+            watching_activity: Arc::new(AtomicBool::new(false)),
+            announced_presence: Arc::new(AtomicI32::new(PresenceStatus::Active as i32)),
+            socket: Arc::new(Mutex::new(None)),
+            outbound: Arc::new(Mutex::new(Outbound::default())),
+            chunked: Arc::new(Mutex::new(Chunked::default())),
+            upload: Arc::new(Mutex::new(Vec::new())),
+            viewport: Arc::new(Mutex::new(None)),
+            following: Arc::new(Mutex::new(None)),
+            clock: Arc::new(Mutex::new(None)),
+            propagation: Arc::new(Mutex::new(Propagation::default())),
+            queue_metrics: Arc::new(WriterQueueMetrics::default()),
+        }
+    }
+
+    /// Queue a frame for the writer without blocking. Once the connection is
+    /// sequenced the frame is numbered and retained for `resend` first, so one
+    /// dropped because the queue is full can still be asked for again.
+    pub fn send(&self, frame: Arc<Frame>) -> Result<(), TrySendError<Arc<Frame>>> {
+        // Held while queueing, so frames reach the writer in number order
+        let mut outbound = self.lock_outbound();
+        for frame in outbound.capabilities.tailor(frame) {
+            self.number_and_queue(&mut outbound, frame)?;
+        }
+        Ok(())
+    }
+
+    fn number_and_queue(
+        &self,
+        outbound: &mut Outbound,
+        frame: Arc<Frame>,
+    ) -> Result<(), TrySendError<Arc<Frame>>> {
+        if !outbound.sequenced {
+            return self.queue(outbound, frame);
+        }
+        outbound.last_seq += 1;
+        let seq = outbound.last_seq;
+        let frame = Frame::new_arc(encode_sequenced(seq, &frame.payload));
+        if outbound.retained.len() == RESEND_WINDOW {
+            outbound.retained.pop_front();
+        }
+        outbound.retained.push_back((seq, Arc::clone(&frame)));
+        self.queue(outbound, frame)
+    }
+
+    /// Queue a frame outside the numbering, for a reply that has to reach a
+    /// client while it is still waiting for a gap to be filled.
+    pub fn send_unsequenced(&self, frame: Arc<Frame>) -> Result<(), TrySendError<Arc<Frame>>> {
+        let mut outbound = self.lock_outbound();
+        self.queue(&mut outbound, frame)
+    }
+
+    /// Queue a frame the next one of its kind supersedes, such as a viewport.
+    /// It is neither numbered nor kept for resends, and it is dropped rather
+    /// than held for credit or queued behind a backlog of half the writer's
+    /// queue. Returns whether it was queued.
+    pub fn send_ephemeral(&self, frame: Arc<Frame>) -> bool {
+        let mut outbound = self.lock_outbound();
+        if self.writer_sender.len() >= WRITER_QUEUE_CAPACITY / 2
+            || !outbound.awaiting_credit.is_empty()
+            || !self.spend_credit(&mut outbound, &frame)
+        {
+            return false;
+        }
+        match self.writer_sender.try_send(frame) {
+            Ok(()) => {
+                self.queue_metrics.record_depth(self.writer_sender.len());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Hands `frame` to the writer, or, on a flow-controlled connection
+    /// without credit to spare, holds it until the client grants more. Only
+    /// a full hold counts as the queue being full.
+    fn queue(
+        &self,
+        outbound: &mut Outbound,
+        frame: Arc<Frame>,
+    ) -> Result<(), TrySendError<Arc<Frame>>> {
+        if outbound.credit.is_none() {
+            return self.hand_to_writer(frame);
+        }
+        self.release_credited(outbound);
+        if !outbound.awaiting_credit.is_empty() || !self.spend_credit(outbound, &frame) {
+            if outbound.awaiting_credit.len() >= MAX_AWAITING_CREDIT {
+                self.record_drop(&frame, DropReason::CreditExhausted);
+                return Err(TrySendError::Full(frame));
+            }
+            outbound.awaiting_credit.push_back(frame);
+            return Ok(());
+        }
+        self.hand_to_writer(frame)
+    }
+
+    /// `try_send` to the writer, recording the frame as a dead letter if it
+    /// cannot be queued.
+    fn hand_to_writer(&self, frame: Arc<Frame>) -> Result<(), TrySendError<Arc<Frame>>> {
+        match self.writer_sender.try_send(frame) {
+            Ok(()) => {
+                self.queue_metrics.record_depth(self.writer_sender.len());
+                Ok(())
+            }
+            Err(err) => {
+                let (frame, reason) = match &err {
+                    TrySendError::Full(frame) => (frame, DropReason::QueueFull),
+                    TrySendError::Disconnected(frame) => (frame, DropReason::WriterGone),
+                };
+                self.record_drop(frame, reason);
+                Err(err)
+            }
+        }
+    }
+
+    fn record_drop(&self, frame: &Frame, reason: DropReason) {
+        self.queue_metrics.record_drop();
+        DEAD_LETTERS.record(self.client_id, frame, reason);
+    }
+
+    /// Frames waiting in the writer's queue now.
+    pub fn queue_depth(&self) -> usize {
+        self.writer_sender.len()
+    }
+
+    /// Most frames seen in the writer's queue at once.
+    pub fn queue_high_watermark(&self) -> usize {
+        self.queue_metrics.high_watermark()
+    }
+
+    /// Frames that could not be queued for this connection.
+    pub fn dropped_frames(&self) -> u64 {
+        self.queue_metrics.dropped()
+    }
+
+    /// Takes credit for `frame` if there is any left.
+    fn spend_credit(&self, outbound: &mut Outbound, frame: &Frame) -> bool {
+        match &mut outbound.credit {
+            Some(credit) if !credit.allows() => false,
+            Some(credit) => {
+                credit.spend(frame);
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Hands held frames to the writer, oldest first, while credit and room
+    /// in the writer's queue last. Any left for want of room go with the
+    /// next send or grant; heartbeat pings make sure there is one.
+    fn release_credited(&self, outbound: &mut Outbound) -> usize {
+        let mut released = 0;
+        while let Some(frame) = outbound.awaiting_credit.front() {
+            let frame = Arc::clone(frame);
+            if self.writer_sender.is_full() || !self.spend_credit(outbound, &frame) {
+                break;
+            }
+            outbound.awaiting_credit.pop_front();
+            if let Err(err) = self.writer_sender.try_send(frame) {
+                outbound.awaiting_credit.push_front(err.into_inner());
+                break;
+            }
+            released += 1;
+        }
+        if released > 0 {
+            self.queue_metrics.record_depth(self.writer_sender.len());
+        }
+        released
+    }
+
+    /// Add to the connection's flow-control credit, turning flow control on
+    /// with the first grant. Returns how many held frames that released.
+    pub fn grant_credit(&self, frames: u64, bytes: u64) -> usize {
+        let mut outbound = self.lock_outbound();
+        outbound
+            .credit
+            .get_or_insert_with(Credit::default)
+            .grant(frames, bytes);
+        self.release_credited(&mut outbound)
+    }
+
+    /// Number every frame queued from now on.
+    pub fn set_sequenced(&self) {
+        self.lock_outbound().sequenced = true;
+    }
+
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.lock_outbound().capabilities = capabilities;
+    }
+
+    /// Queue the retained frames numbered `from_seq` and later again, with
+    /// their original numbers. Returns how many were queued, or the oldest
+    /// number still retained if `from_seq` is older than that.
+    pub fn resend(&self, from_seq: u64) -> Result<usize, u64> {
+        let mut outbound = self.lock_outbound();
+        let oldest = outbound
+            .retained
+            .front()
+            .map_or(outbound.last_seq + 1, |&(seq, _)| seq);
+        if from_seq < oldest && from_seq <= outbound.last_seq {
+            return Err(oldest);
+        }
+        let frames: Vec<Arc<Frame>> = outbound
+            .retained
+            .iter()
+            .filter(|(seq, _)| *seq >= from_seq)
+            .map(|(_, frame)| Arc::clone(frame))
+            .collect();
+        let mut queued = 0;
+        for frame in frames {
+            if self.queue(&mut outbound, frame).is_err() {
+                // The client will see the gap again and ask from there
+                break;
+            }
+            queued += 1;
+        }
+        Ok(queued)
+    }
+
+    fn lock_outbound(&self) -> std::sync::MutexGuard<'_, Outbound> {
+        match self.outbound.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Takes a batch of operations from the client, returning the whole
+    /// transaction once a batch without `more` completes it. A transaction
+    /// growing past `MAX_TRANSACTION_BYTES` is refused with the id of its
+    /// first op, and what was held of it dropped.
+    pub fn add_chunk(
+        &self,
+        batch: OperationBatchProto,
+    ) -> Result<Option<Vec<OperationProto>>, (u64, Rejection)> {
+        let mut chunked = match self.chunked.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        chunked.bytes += batch
+            .operations
+            .iter()
+            .map(Message::encoded_len)
+            .sum::<usize>();
+        chunked.operations.extend(batch.operations);
+        if chunked.bytes > MAX_TRANSACTION_BYTES {
+            let chunked = std::mem::take(&mut *chunked);
+            let op_id = chunked.operations.first().map_or(0, |op| op.op_id);
+            return Err((
+                op_id,
+                Rejection::TransactionTooLarge {
+                    len: chunked.bytes,
+                    max: MAX_TRANSACTION_BYTES,
+                },
+            ));
+        }
+        if batch.more {
+            return Ok(None);
+        }
+        Ok(Some(std::mem::take(&mut *chunked).operations))
+    }
+
+    /// Takes a chunk of the attachment the client is uploading, returning
+    /// the whole of it once the last chunk completes it. An upload growing
+    /// past `max_bytes` is dropped, and refused with its size.
+    pub fn add_upload_chunk(
+        &self,
+        data: &[u8],
+        more: bool,
+        max_bytes: Option<u64>,
+    ) -> Result<Option<Vec<u8>>, u64> {
+        let mut upload = match self.upload.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        upload.extend_from_slice(data);
+        let size = upload.len() as u64;
+        if max_bytes.is_some_and(|max| size > max) {
+            *upload = Vec::new();
+            return Err(size);
+        }
+        if more {
+            return Ok(None);
+        }
+        Ok(Some(std::mem::take(&mut *upload)))
+    }
+
+    /// Drops what was held of an upload the server refused.
+    pub fn abandon_upload(&self) {
+        let mut upload = match self.upload.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *upload = Vec::new();
+    }
+
+    /// Keep a handle on the connection's socket so it can be hung up.
+    pub fn with_socket(self, socket: TcpStream) -> Self {
+        Self {
+            socket: Arc::new(Mutex::new(Some(socket))),
+            ..self
+        }
+    }
+
+    /// Shut the socket down in both directions without waiting for the
+    /// writer to drain: pending and later writes fail, so the writer exits,
+    /// and the reader sees the connection end and cleans up.
+    pub fn hang_up(&self) {
+        let socket = match self.socket.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(socket) = socket.as_ref() {
+            socket.hang_up();
+        }
+    }
+
+    pub fn subscribe(&self, doc_id: &str) {
+        self.lock_subscriptions().insert(doc_id.to_string());
+    }
+
+    /// Returns whether the client was subscribed.
+    pub fn unsubscribe(&self, doc_id: &str) -> bool {
+        self.lock_subscriptions().remove(doc_id)
+    }
+
+    pub fn is_subscribed(&self, doc_id: &str) -> bool {
+        self.lock_subscriptions().contains(doc_id)
+    }
+
+    pub fn subscription_count(&self) -> usize {
+        self.lock_subscriptions().len()
+    }
+
+    fn lock_subscriptions(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        match self.subscriptions.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn set_display_name(&self, name: String) {
+        let mut display_name = match self.display_name.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *display_name = Some(name);
+    }
+
+    pub fn set_hello_id(&self, id: String) {
+        let mut hello_id = match self.hello_id.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *hello_id = Some(id);
+    }
+
+    pub fn hello_id(&self) -> Option<String> {
+        match self.hello_id.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// What the client's acknowledgement cursors are kept under: the id from
+    /// its Hello if it gave one, otherwise this connection's.
+    pub fn cursor_key(&self) -> String {
+        self.hello_id()
+            .unwrap_or_else(|| self.client_id.to_string())
+    }
+
+    pub fn set_read_only(&self) {
+        self.read_only.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    pub fn workspace(&self) -> String {
+        self.lock_workspace()
+            .clone()
+            .unwrap_or_else(|| DEFAULT_WORKSPACE.to_string())
+    }
+
+    pub fn has_joined_workspace(&self) -> bool {
+        self.lock_workspace().is_some()
+    }
+
+    /// Joins workspace `name` with `access`, unless the connection already
+    /// joined one. Returns whether it did.
+    pub fn join_workspace(&self, name: &str, access: Access) -> bool {
+        let mut workspace = self.lock_workspace();
+        if workspace.is_some() {
+            return false;
+        }
+        *workspace = Some(name.to_string());
+        *self.lock_access() = access;
+        true
+    }
+
+    pub fn member(&self) -> Option<String> {
+        match &*self.lock_access() {
+            Access::Member(member) => Some(member.clone()),
+            Access::Open | Access::Guest(_) => None,
+        }
+    }
+
+    /// The invite a guest connection joined with.
+    pub fn guest(&self) -> Option<Invite> {
+        match &*self.lock_access() {
+            Access::Guest(invite) => Some(invite.clone()),
+            Access::Open | Access::Member(_) => None,
+        }
+    }
+
+    fn lock_access(&self) -> std::sync::MutexGuard<'_, Access> {
+        match self.access.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn lock_workspace(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        match self.workspace.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Display name if announced, otherwise the client id.
+    pub fn label(&self) -> String {
+        let display_name = match self.display_name.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        display_name
+            .clone()
+            .unwrap_or_else(|| self.client_id.to_string())
+    }
+
+    pub fn viewport(&self) -> Option<ViewportProto> {
+        match self.viewport.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn set_viewport(&self, viewport: ViewportProto) {
+        match self.viewport.lock() {
+            Ok(mut guard) => *guard = Some(viewport),
+            Err(poisoned) => *poisoned.into_inner() = Some(viewport),
+        }
+    }
+
+    pub fn clock(&self) -> Option<ClockSample> {
+        match self.clock.lock() {
+            Ok(guard) => *guard,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+
+    pub fn set_clock(&self, sample: ClockSample) {
+        match self.clock.lock() {
+            Ok(mut guard) => *guard = Some(sample),
+            Err(poisoned) => *poisoned.into_inner() = Some(sample),
+        }
+    }
+
+    /// Records the client's traced ops, unless it has yet to report its
+    /// clock, without which its times can't be compared with the server's.
+    /// Returns whether they were recorded.
+    pub fn record_propagation(&self, samples: &[PropagationSampleProto]) -> bool {
+        let Some(clock) = self.clock() else {
+            return false;
+        };
+        let mut propagation = match self.propagation.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for sample in samples {
+            propagation.record(sample, clock);
+        }
+        true
+    }
+
+    pub fn propagation(&self) -> Propagation {
+        match self.propagation.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn following(&self) -> Option<Uuid> {
+        match self.following.lock() {
+            Ok(guard) => *guard,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+
+    /// Mirror `leader`'s viewport from now on, or no one's.
+    pub fn follow(&self, leader: Option<Uuid>) {
+        match self.following.lock() {
+            Ok(mut guard) => *guard = leader,
+            Err(poisoned) => *poisoned.into_inner() = leader,
         }
     }
 
@@ -38,6 +683,60 @@ impl ClientEntry {
         self.last_activity_ms.store(now_ms, Ordering::Relaxed);
     }
 
+    /// Record input from the user, which makes an idle connection active again.
+    pub fn note_input(&self) {
+        self.last_input_ms.store(unix_time_ms(), Ordering::Relaxed);
+    }
+
+    /// Mark the connection away, or back. Coming back counts as input.
+    pub fn set_away(&self, away: bool) {
+        self.away.store(away, Ordering::Relaxed);
+        if !away {
+            self.note_input();
+        }
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.away.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe the connection to its workspace's activity feed, or not.
+    pub fn watch_activity(&self, watch: bool) {
+        self.watching_activity.store(watch, Ordering::Relaxed);
+    }
+
+    pub fn is_watching_activity(&self) -> bool {
+        self.watching_activity.load(Ordering::Relaxed)
+    }
+
+    /// Away if the client said so, idle once it has had no input for
+    /// `idle_after_ms` (never, if `None`), active otherwise.
+    pub fn presence(&self, idle_after_ms: Option<u64>) -> PresenceStatus {
+        if self.away.load(Ordering::Relaxed) {
+            return PresenceStatus::Away;
+        }
+        let quiet_ms = unix_time_ms().saturating_sub(self.last_input_ms.load(Ordering::Relaxed));
+        match idle_after_ms {
+            Some(idle_after_ms) if quiet_ms > idle_after_ms => PresenceStatus::Idle,
+            _ => PresenceStatus::Active,
+        }
+    }
+
+    /// Record `status` as announced. Returns whether it differs from the
+    /// previous announcement, i.e. whether it needs announcing.
+    pub fn announce_presence(&self, status: PresenceStatus) -> bool {
+        let previous = self
+            .announced_presence
+            .swap(status as i32, Ordering::Relaxed);
+        previous != status as i32
+    }
+
+    /// The presence other connections were last told about.
+    pub fn announced_presence(&self) -> PresenceStatus {
+        PresenceStatus::try_from(self.announced_presence.load(Ordering::Relaxed))
+            .unwrap_or(PresenceStatus::Active)
+    }
+
     /// Get milliseconds since last activity.
     pub fn ms_since_last_activity(&self) -> u64 {
         let now_ms = SystemTime::now()
@@ -0,0 +1,187 @@
+use common::{
+    protocol::{PROTOCOL_VERSION, message_type_name},
+    space::{DiagnosticKind, DiagnosticsProto, HelloProto},
+};
+
+use crate::capabilities::Capabilities;
+
+/// This build, as reported to clients in diagnostics.
+pub const SERVER_VERSION: &str = concat!("dist-space-server/", env!("CARGO_PKG_VERSION"));
+
+/// Capabilities a server can insist on, by CapabilitiesProto field name.
+const REQUIRABLE: [&str; 2] = ["batches", "presence"];
+
+/// What a client's Hello must say for it to be let in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientRequirements {
+    /// Oldest protocol version accepted; 0 also accepts clients from before
+    /// versions were exchanged.
+    pub min_protocol: u32,
+    /// Capabilities the Hello must claim.
+    pub capabilities: Vec<&'static str>,
+}
+
+impl ClientRequirements {
+    /// Parses the oldest protocol version to accept, which can't be newer
+    /// than this build's.
+    pub fn parse_min_protocol(value: &str) -> Result<u32, String> {
+        let version = value
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid protocol version '{}'", value))?;
+        if version > PROTOCOL_VERSION {
+            return Err(format!(
+                "Protocol version {} is newer than this server's, {}",
+                version, PROTOCOL_VERSION
+            ));
+        }
+        Ok(version)
+    }
+
+    /// Parses a comma-separated list of capabilities, e.g. `batches,presence`.
+    pub fn parse_capabilities(list: &str) -> Result<Vec<&'static str>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                REQUIRABLE
+                    .into_iter()
+                    .find(|known| *known == name)
+                    .ok_or_else(|| format!("Unknown capability '{}' (batches, presence)", name))
+            })
+            .collect()
+    }
+
+    /// Why `hello` falls short, or `None` if the client may stay.
+    pub fn check(&self, hello: &HelloProto) -> Option<DiagnosticsProto> {
+        let client = match &hello.client_version {
+            version if version.is_empty() => "The client".to_string(),
+            version => format!("The client ({})", version),
+        };
+        if hello.protocol_version < self.min_protocol {
+            let speaks = match hello.protocol_version {
+                0 => "predates protocol versions".to_string(),
+                version => format!("speaks protocol {}", version),
+            };
+            return Some(self.diagnostics(
+                DiagnosticKind::ProtocolTooOld,
+                format!(
+                    "{} {}, and this server accepts protocol {} or later",
+                    client, speaks, self.min_protocol
+                ),
+                format!(
+                    "Upgrade the client to one speaking protocol {} or later",
+                    self.min_protocol
+                ),
+            ));
+        }
+        let capabilities = hello
+            .capabilities
+            .as_ref()
+            .map_or_else(Capabilities::default, Capabilities::from_proto);
+        let missing: Vec<String> = self
+            .capabilities
+            .iter()
+            .filter(|name| match **name {
+                "batches" => !capabilities.batches,
+                "presence" => !capabilities.presence,
+                _ => false,
+            })
+            .map(|name| name.to_string())
+            .collect();
+        if missing.is_empty() {
+            return None;
+        }
+        let list = missing.join(", ");
+        Some(DiagnosticsProto {
+            missing_capabilities: missing,
+            ..self.diagnostics(
+                DiagnosticKind::MissingCapability,
+                format!(
+                    "{} does not handle {}, which this server requires",
+                    client, list
+                ),
+                format!("Use a client that handles {}", list),
+            )
+        })
+    }
+
+    /// Tells a client the server could not decode a frame of type `type_id`.
+    pub fn unknown_message(&self, type_id: u8) -> DiagnosticsProto {
+        DiagnosticsProto {
+            message_type: type_id as u32,
+            ..self.diagnostics(
+                DiagnosticKind::UnknownMessage,
+                format!("Message type {} is unknown to this server", type_id),
+                format!(
+                    "The client is newer than the server ({}): upgrade the server, or stop using what needs message type {}",
+                    SERVER_VERSION, type_id
+                ),
+            )
+        }
+    }
+
+    fn diagnostics(
+        &self,
+        kind: DiagnosticKind,
+        message: String,
+        guidance: String,
+    ) -> DiagnosticsProto {
+        DiagnosticsProto {
+            kind: kind as i32,
+            message,
+            guidance,
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: self.min_protocol,
+            server_version: SERVER_VERSION.to_string(),
+            missing_capabilities: Vec::new(),
+            message_type: 0,
+        }
+    }
+}
+
+/// Whether `type_id` is a message type this build knows.
+pub fn is_known_type(type_id: u8) -> bool {
+    message_type_name(type_id) != "Unknown"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::space::CapabilitiesProto;
+
+    fn hello(protocol_version: u32, presence: bool) -> HelloProto {
+        HelloProto {
+            protocol_version,
+            client_version: "dist-space-client/0.1.0".to_string(),
+            capabilities: Some(CapabilitiesProto {
+                batches: true,
+                presence,
+                ..CapabilitiesProto::default()
+            }),
+            ..HelloProto::default()
+        }
+    }
+
+    #[test]
+    fn test_hellos_are_checked_against_the_requirements() {
+        assert_eq!(ClientRequirements::default().check(&hello(0, false)), None);
+
+        let requirements = ClientRequirements {
+            min_protocol: PROTOCOL_VERSION,
+            capabilities: ClientRequirements::parse_capabilities("presence").unwrap(),
+        };
+        let too_old = requirements.check(&hello(0, true)).unwrap();
+        assert_eq!(too_old.kind(), DiagnosticKind::ProtocolTooOld);
+        assert!(too_old.message.contains("predates protocol versions"));
+
+        let missing = requirements.check(&hello(PROTOCOL_VERSION, false)).unwrap();
+        assert_eq!(missing.kind(), DiagnosticKind::MissingCapability);
+        assert_eq!(missing.missing_capabilities, ["presence"]);
+        assert_eq!(requirements.check(&hello(PROTOCOL_VERSION, true)), None);
+
+        assert!(ClientRequirements::parse_capabilities("crdt").is_err());
+        assert!(
+            ClientRequirements::parse_min_protocol(&(PROTOCOL_VERSION + 1).to_string()).is_err()
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use common::clock::unix_time_ms;
+use common::space::{OperationProto, PresenceProto, SyncDocumentProto, operation_proto::Kind};
+use uuid::Uuid;
+
+use crate::events::{escape, unescape};
+use crate::log::error;
+use crate::sha256;
+use crate::validation::Rejection;
+
+/// The hash the first entry chains from.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Which workspaces are recorded: `*` for every one, the default included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordedWorkspaces(Vec<String>);
+
+impl RecordedWorkspaces {
+    /// Parses workspace names, comma-separated.
+    pub fn parse(names: &str) -> Self {
+        Self(
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, workspace: &str) -> bool {
+        self.0.iter().any(|name| name == "*" || name == workspace)
+    }
+}
+
+/// A record of everything applied in the workspaces that opted in, and who
+/// came and went, for deployments that must show their history was not
+/// altered after the fact. Only ever appended to `file`, which should sit on
+/// write-once storage (an append-only file attribute, a WORM volume): one
+/// `seq<TAB>at_ms<TAB>workspace<TAB>kind<TAB>fields...<TAB>hash` line per
+/// entry, with tabs, newlines and backslashes in the text escaped. `hash` is
+/// the SHA-256 of the previous entry's hash and this entry's line up to it,
+/// so changing, dropping or reordering entries breaks every hash after.
+/// Dropping entries from the end leaves a chain that still verifies; only
+/// a head hash kept elsewhere catches that, so the server logs the head it
+/// starts from.
+///
+/// An `op` entry has the sending connection, path, document id, version it
+/// was applied to, and `insert`, `delete`, `replace` or `noop` with start,
+/// end and text; a `content` entry, for a document created with content
+/// (imported or from a template), has the sending connection, path,
+/// document id, version, how it was created and the content; a `presence`
+/// entry has the connection, display name, status and reason. What the
+/// server starts with (documents loaded, seeded or taken over at startup)
+/// is not a change, and not recorded. A `torn` entry, written on startup,
+/// has the length and SHA-256 of a line a crash or failed write left
+/// unfinished on the line before it, which is kept as it was.
+///
+/// Once an entry cannot be written nothing more is, and edits to the
+/// recorded workspaces are refused until the server is restarted with a log
+/// it can write. The entry itself, whose op was already applied, goes to the
+/// server log instead.
+#[derive(Debug, Default)]
+pub struct ComplianceLog {
+    workspaces: RecordedWorkspaces,
+    file: Option<(PathBuf, File)>,
+    /// Entries so far, and the hash of the last.
+    seq: u64,
+    hash: String,
+    /// Why the last entry could not be written, if one could not.
+    failed: Option<String>,
+}
+
+impl ComplianceLog {
+    /// Records `workspaces` to `file`, after the entries already there,
+    /// whose chain must be intact. The file is only opened to append: a
+    /// last line cut short by a crash or a failed write is ended and
+    /// accounted for by a `torn` entry.
+    pub fn open(file: PathBuf, workspaces: RecordedWorkspaces) -> io::Result<Self> {
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let complete = bytes
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+        let (seq, hash) = verify(&bytes[..complete]).map_err(|(line, what)| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", file.display(), line, what),
+            )
+        })?;
+        let mut handle = OpenOptions::new().create(true).append(true).open(&file)?;
+        let torn = &bytes[complete..];
+        if !torn.is_empty() {
+            error!(
+                "[Compliance] {} ends in a torn line of {} bytes, keeping it",
+                file.display(),
+                torn.len()
+            );
+            handle.write_all(b"\n")?;
+        }
+        let mut log = Self {
+            workspaces,
+            file: Some((file, handle)),
+            seq,
+            hash,
+            failed: None,
+        };
+        if !torn.is_empty() {
+            let fields = [torn.len().to_string(), sha256::hex_digest(torn)];
+            log.append("", unix_time_ms(), "torn", &fields);
+            if let Some(reason) = log.failed {
+                return Err(io::Error::other(reason));
+            }
+        }
+        Ok(log)
+    }
+
+    /// Whether `workspace` is recorded.
+    pub fn records(&self, workspace: &str) -> bool {
+        self.file.is_some() && self.workspaces.contains(workspace)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.seq
+    }
+
+    /// The hash of the last entry, which only a copy kept elsewhere can
+    /// show was not dropped.
+    pub fn head(&self) -> &str {
+        &self.hash
+    }
+
+    /// Refuses edits to `workspace` if it is recorded and an entry could not
+    /// be written, so none goes unrecorded.
+    pub fn check(&self, workspace: &str) -> Result<(), Rejection> {
+        match &self.failed {
+            Some(reason) if self.records(workspace) => Err(Rejection::Refused {
+                middleware: "compliance log".to_string(),
+                reason: reason.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records `operation`, as applied in `workspace`.
+    pub fn op(&mut self, workspace: &str, origin: Uuid, path: &str, operation: &OperationProto) {
+        let (edit, start, end, text) = match &operation.kind {
+            Some(Kind::Insert(insert)) => ("insert", insert.index, insert.index, &*insert.text),
+            Some(Kind::Delete(delete)) => ("delete", delete.start, delete.end, ""),
+            Some(Kind::Replace(replace)) => ("replace", replace.start, replace.end, &*replace.text),
+            // Only character ops are applied
+            _ => ("noop", 0, 0, ""),
+        };
+        let fields = [
+            origin.to_string(),
+            escape(path),
+            escape(&operation.doc_id),
+            operation.server_version.to_string(),
+            edit.to_string(),
+            start.to_string(),
+            end.to_string(),
+            escape(text),
+        ];
+        self.append(workspace, operation.applied_at_ms, "op", &fields);
+    }
+
+    /// Records `document` as created in `workspace`, `action` saying how.
+    pub fn content(
+        &mut self,
+        workspace: &str,
+        origin: Uuid,
+        document: &SyncDocumentProto,
+        action: &str,
+        at_ms: u64,
+    ) {
+        let fields = [
+            origin.to_string(),
+            escape(&document.path),
+            escape(&document.doc_id),
+            document.version.to_string(),
+            escape(action),
+            escape(&document.content),
+        ];
+        self.append(workspace, at_ms, "content", &fields);
+    }
+
+    /// Records a connection in `workspace` becoming `presence.status`.
+    pub fn presence(&mut self, workspace: &str, presence: &PresenceProto, at_ms: u64) {
+        let fields = [
+            escape(&presence.client_id),
+            escape(&presence.display_name),
+            presence.status().as_str_name().to_string(),
+            escape(&presence.reason),
+        ];
+        self.append(workspace, at_ms, "presence", &fields);
+    }
+
+    /// Reads the file back and checks its chain. Returns the number of
+    /// entries, or the first line that does not chain; `None` without a file.
+    pub fn verify(&self) -> Option<Result<u64, String>> {
+        let (file, _) = self.file.as_ref()?;
+        let verified = match fs::read(file) {
+            Ok(bytes) => verify(&bytes)
+                .map(|(seq, _)| seq)
+                .map_err(|(line, what)| format!("{}:{}: {}", file.display(), line, what)),
+            Err(e) => Err(format!("{}: {}", file.display(), e)),
+        };
+        Some(verified)
+    }
+
+    /// After a failed write, which may have left part of a line, entries
+    /// only go to the server log.
+    fn append(&mut self, workspace: &str, at_ms: u64, kind: &str, fields: &[String]) {
+        let Some((file, handle)) = &mut self.file else {
+            return;
+        };
+        let seq = self.seq + 1;
+        let mut body = format!("{}\t{}\t{}\t{}", seq, at_ms, escape(workspace), kind);
+        for field in fields {
+            body.push('\t');
+            body.push_str(field);
+        }
+        if self.failed.is_some() {
+            error!("[Compliance] Not recorded: {}", body);
+            return;
+        }
+        let hash = chain(&self.hash, &body);
+        match handle.write_all(format!("{}\t{}\n", body, hash).as_bytes()) {
+            Ok(()) => {
+                self.seq = seq;
+                self.hash = hash;
+            }
+            Err(e) => {
+                error!(
+                    "[Compliance] Cannot append to {}, refusing edits to recorded workspaces: {}",
+                    file.display(),
+                    e
+                );
+                error!("[Compliance] Not recorded: {}", body);
+                self.failed = Some("the compliance log cannot be written".to_string());
+            }
+        }
+    }
+}
+
+fn chain(previous: &str, body: &str) -> String {
+    sha256::hex_digest(format!("{}\n{}", previous, body).as_bytes())
+}
+
+/// The number of entries in `bytes` and the hash of the last, or the line
+/// number where the chain breaks and how. A line that does not chain is
+/// only let through if the next entry is the `torn` one accounting for it.
+fn verify(bytes: &[u8]) -> Result<(u64, String), (usize, String)> {
+    let mut hash = GENESIS.to_string();
+    let mut seq = 0;
+    if bytes.is_empty() {
+        return Ok((seq, hash));
+    }
+    let mut torn: Option<(usize, &[u8], String)> = None;
+    let lines = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    for (number, line) in lines.split(|&byte| byte == b'\n').enumerate() {
+        match entry(line, seq, &hash) {
+            Ok((body, expected)) => {
+                if let Some((number, line, what)) = torn.take() {
+                    let mut fields = body.split('\t');
+                    let accounted = fields.nth(3) == Some("torn")
+                        && fields.next() == Some(&line.len().to_string())
+                        && fields.next() == Some(&sha256::hex_digest(line));
+                    if !accounted {
+                        return Err((number, what));
+                    }
+                }
+                (seq, hash) = (seq + 1, expected);
+            }
+            Err(what) => {
+                if let Some((number, _, what)) = torn {
+                    return Err((number, what));
+                }
+                torn = Some((number + 1, line, what));
+            }
+        }
+    }
+    match torn {
+        Some((number, _, what)) => Err((number, what)),
+        None => Ok((seq, hash)),
+    }
+}
+
+/// The body of the entry on `line` and its hash, if it is entry `seq + 1`
+/// and chains from `previous`.
+fn entry<'a>(line: &'a [u8], seq: u64, previous: &str) -> Result<(&'a str, String), String> {
+    let line = std::str::from_utf8(line).map_err(|_| "entry is not UTF-8".to_string())?;
+    let Some((body, recorded)) = line.rsplit_once('\t') else {
+        return Err("expected a hash after the entry".to_string());
+    };
+    let numbered: u64 = body
+        .split('\t')
+        .next()
+        .and_then(|seq| unescape(seq).parse().ok())
+        .ok_or_else(|| "invalid seq".to_string())?;
+    if numbered != seq + 1 {
+        return Err(format!("expected entry {}, found {}", seq + 1, numbered));
+    }
+    let expected = chain(previous, body);
+    if recorded != expected {
+        return Err("hash does not chain from the entry before".to_string());
+    }
+    Ok((body, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::space::{InsertOp, PresenceStatus};
+
+    #[test]
+    fn test_entries_chain_and_tampering_is_caught() {
+        let file = std::env::temp_dir().join(format!("dist-space-compliance-{}", Uuid::new_v4()));
+        let workspaces = RecordedWorkspaces::parse("team-a, team-b");
+        assert!(!RecordedWorkspaces::parse(" , ").contains("team-a"));
+        assert!(RecordedWorkspaces::parse("*").contains(""));
+
+        let mut log = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap();
+        assert!(log.records("team-a") && !log.records("team-c"));
+        let operation = OperationProto {
+            doc_id: "d1".to_string(),
+            server_version: 4,
+            applied_at_ms: 10,
+            kind: Some(Kind::Insert(InsertOp {
+                index: 2,
+                text: "tab\there".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        log.op("team-a", Uuid::nil(), "notes.md", &operation);
+        let presence = PresenceProto {
+            client_id: "c1".to_string(),
+            display_name: "ada".to_string(),
+            status: PresenceStatus::Away as i32,
+            reason: String::new(),
+        };
+        log.presence("team-b", &presence, 20);
+        assert_eq!(ComplianceLog::default().verify(), None);
+        assert_eq!(log.verify(), Some(Ok(2)));
+
+        // Reopened, the chain carries on
+        let mut log = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap();
+        log.presence("team-a", &presence, 30);
+        assert_eq!(log.verify(), Some(Ok(3)));
+        let text = fs::read_to_string(&file).unwrap();
+        assert!(text.lines().next().unwrap().contains("\top\t"));
+
+        // A torn last line, cut inside a character, is kept and accounted
+        // for, and the chain carries on after it
+        let torn = b"4\t40\tteam-a\tpresence\t\xc3";
+        let mut handle = OpenOptions::new().append(true).open(&file).unwrap();
+        handle.write_all(torn).unwrap();
+        let mut log = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap();
+        assert_eq!(log.len(), 4);
+        log.presence("team-a", &presence, 40);
+        assert_eq!(log.verify(), Some(Ok(5)));
+        let bytes = fs::read(&file).unwrap();
+        assert!(
+            bytes
+                .windows(torn.len() + 1)
+                .any(|line| line == [&torn[..], b"\n"].concat())
+        );
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let log = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap();
+        assert_eq!(log.len(), 5);
+
+        // Changing the torn line breaks the chain too
+        fs::write(&file, text.as_bytes()).unwrap();
+        let refused = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap_err();
+        assert!(
+            refused
+                .to_string()
+                .ends_with(":4: hash does not chain from the entry before")
+        );
+
+        // Editing an entry, or dropping one, breaks the chain
+        fs::write(&file, text.replacen("notes.md", "other.md", 1)).unwrap();
+        let refused = ComplianceLog::open(file.clone(), workspaces.clone()).unwrap_err();
+        assert!(
+            refused
+                .to_string()
+                .ends_with(":1: hash does not chain from the entry before")
+        );
+        let dropped: Vec<&str> = text.lines().skip(1).collect();
+        fs::write(&file, dropped.join("\n")).unwrap();
+        assert!(ComplianceLog::open(file.clone(), workspaces).is_err());
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_edits_are_refused_once_an_entry_cannot_be_written() {
+        let file = std::env::temp_dir().join(format!("dist-space-compliance-{}", Uuid::new_v4()));
+        let mut log =
+            ComplianceLog::open(file.clone(), RecordedWorkspaces::parse("team-a")).unwrap();
+        let presence = PresenceProto::default();
+        log.presence("team-a", &presence, 10);
+        assert_eq!(log.check("team-a"), Ok(()));
+
+        // Opened for reading only, so the next write fails
+        log.file = Some((file.clone(), File::open(&file).unwrap()));
+        log.presence("team-a", &presence, 20);
+        assert!(matches!(
+            log.check("team-a"),
+            Err(Rejection::Refused { .. })
+        ));
+        assert_eq!(log.check("team-b"), Ok(()));
+        assert_eq!(log.verify(), Some(Ok(1)));
+        fs::remove_file(&file).unwrap();
+    }
+}
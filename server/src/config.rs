@@ -0,0 +1,924 @@
+use std::{env, path::PathBuf, time::Duration};
+
+use crate::attachments::AttachmentLimits;
+use crate::authorization::OpRules;
+use crate::compatibility::ClientRequirements;
+use crate::compliance::RecordedWorkspaces;
+use crate::conflict::ConflictPolicies;
+use crate::doc_ids::{DocIds, MissingDocuments};
+use crate::log::LogLevel;
+use crate::log_file::RotationPolicy;
+use crate::normalize::Normalization;
+use crate::offline::OFFLINE_GRACE;
+use crate::plugins::PluginSpec;
+use crate::retention::RetentionPolicy;
+use crate::seed::Seed;
+use crate::state::{DEFAULT_DOC_PATH, HEARTBEAT_INTERVAL_MS, IDLE_AFTER_MS};
+use crate::workspaces::WorkspaceQuota;
+
+pub const USAGE: &str = "\
+Usage: server [OPTIONS]
+
+Options:
+      --batch-window-ms <MS>      group applied ops into one broadcast per window; 0 disables [env: DIST_SPACE_BATCH_WINDOW_MS] [default: 0]
+      --ping-interval-ms <MS>     how often clients are pinged; 0 disables [env: DIST_SPACE_PING_INTERVAL_MS] [default: 10000]
+      --sweep-interval-ms <MS>    how often timed-out clients are removed; 0 disables [env: DIST_SPACE_SWEEP_INTERVAL_MS] [default: 10000]
+      --compact-interval-ms <MS>  how often op logs are compacted; 0 disables [env: DIST_SPACE_COMPACT_INTERVAL_MS] [default: 60000]
+      --metrics-interval-ms <MS>  how often metrics are logged; 0 disables [env: DIST_SPACE_METRICS_INTERVAL_MS] [default: 10000]
+      --presence-interval-ms <MS> how often connections are checked for going idle; 0 disables [env: DIST_SPACE_PRESENCE_INTERVAL_MS] [default: 10000]
+      --idle-after-ms <MS>        show connections without input for this long as idle; 0 disables [env: DIST_SPACE_IDLE_AFTER_MS] [default: 300000]
+      --doc-file <PATH>           file the default document is loaded from and saved to; empty disables [env: DIST_SPACE_DOC_FILE] [default: main.txt]
+      --seed <SEEDS>              start documents from a file, an http:// URL or - for stdin, as SOURCE for the default document or PATH=SOURCE, comma-separated [env: DIST_SPACE_SEED]
+      --seed-max-bytes <BYTES>    longest a seeded document may be; 0 for no limit [env: DIST_SPACE_SEED_MAX_BYTES] [default: 16777216]
+      --autosave-ms <MS>          save edits once they have been quiet this long; 0 saves only at shutdown [env: DIST_SPACE_AUTOSAVE_MS] [default: 1000]
+      --git-repo <PATH>           commit persisted documents to this git repository, created if missing [env: DIST_SPACE_GIT_REPO]
+      --git-commit-ms <MS>        how often persisted documents are committed; 0 commits only on a client's checkpoint [env: DIST_SPACE_GIT_COMMIT_MS] [default: 300000]
+      --oplog-export <PATH>       append applied ops to this JSON Lines file [env: DIST_SPACE_OPLOG_EXPORT]
+      --oplog-export-ms <MS>      how often new ops are appended; 0 exports only at shutdown [env: DIST_SPACE_OPLOG_EXPORT_MS] [default: 5000]
+      --oplog-max-versions <N>    versions of history kept in each op log; 0 for no limit [env: DIST_SPACE_OPLOG_MAX_VERSIONS] [default: 10000]
+      --oplog-max-bytes <BYTES>   memory each op log may hold; 0 for no limit [env: DIST_SPACE_OPLOG_MAX_BYTES] [default: 67108864]
+      --oplog-max-age-ms <MS>     how long applied ops are kept in op logs; 0 for no limit [env: DIST_SPACE_OPLOG_MAX_AGE_MS] [default: 0]
+      --template-dir <PATH>       directory of templates clients can create documents from [env: DIST_SPACE_TEMPLATE_DIR]
+      --conflict-policy <RULES>   how concurrent edits are settled: merge, keep-inserts, delete-wins or first-writer-wins, optionally per document as PATH=POLICY, comma-separated [env: DIST_SPACE_CONFLICT_POLICY] [default: merge]
+      --doc-ids <RULES>           how documents get their ids: random, or path to derive them from workspace and path; optionally fixed for a default-workspace document as PATH=UUID, comma-separated [env: DIST_SPACE_DOC_IDS] [default: random]
+      --missing-docs <POLICY>     what opening a path with no document does: create an empty one, or refuse [env: DIST_SPACE_MISSING_DOCS] [default: create]
+      --normalize <RULES>         rewrite inserted text: crlf turns \\r\\n into \\n outside CRLF documents, bom drops byte order marks, none does neither; comma-separated [env: DIST_SPACE_NORMALIZE] [default: bom]
+      --op-rate <N>               ops each connection may send a second, in bursts of as many; 0 for no limit [env: DIST_SPACE_OP_RATE] [default: 0]
+      --audit-ops <BOOL>          log every applied op with who sent it; true or false [env: DIST_SPACE_AUDIT_OPS] [default: false]
+      --plugins <PLUGINS>         WASM modules ops are run through: FILE for every workspace or WORKSPACE=FILE, comma-separated [env: DIST_SPACE_PLUGINS]
+      --op-rules <RULES>          what members may edit, as MEMBER=append-only or MEMBER=max-delete:N, comma-separated [env: DIST_SPACE_OP_RULES]
+      --workspace-max-clients <N> connections each workspace may have; 0 for no limit [env: DIST_SPACE_WORKSPACE_MAX_CLIENTS] [default: 0]
+      --workspace-max-docs <N>    documents each workspace may open; 0 for no limit [env: DIST_SPACE_WORKSPACE_MAX_DOCS] [default: 0]
+      --members-file <PATH>       file workspace members and their tokens are kept in; without one they last until shutdown [env: DIST_SPACE_MEMBERS_FILE]
+      --events-file <PATH>        file each workspace's activity (documents created, members, checkpoints) is appended to; without one it lasts until shutdown [env: DIST_SPACE_EVENTS_FILE]
+      --cursors-file <PATH>       file the version each client last acknowledged of each document is kept in, for resuming it; without one they last until shutdown [env: DIST_SPACE_CURSORS_FILE]
+      --compliance-file <PATH>    append-only file every applied op and presence change in the recorded workspaces is chained into by hash, for showing history was not altered [env: DIST_SPACE_COMPLIANCE_FILE]
+      --compliance-workspaces <WS> workspaces recorded to the compliance file, comma-separated, or * for all [env: DIST_SPACE_COMPLIANCE_WORKSPACES] [default: *]
+      --offline-grace-ms <MS>     how long a disconnected client's session and cursors are kept for it to resume, holding back op log compaction; 0 disables [env: DIST_SPACE_OFFLINE_GRACE_MS] [default: 300000]
+      --attach-dir <PATH>         directory attachments are stored in; without one they last until shutdown [env: DIST_SPACE_ATTACH_DIR]
+      --attach-max-bytes <BYTES>  largest attachment clients may upload; 0 for no limit [env: DIST_SPACE_ATTACH_MAX_BYTES] [default: 16777216]
+      --attach-quota <BYTES>      bytes all attachments together may take; 0 for no limit [env: DIST_SPACE_ATTACH_QUOTA] [default: 1073741824]
+      --attach-gc-ms <MS>         how often attachments no document references are deleted; 0 disables [env: DIST_SPACE_ATTACH_GC_MS] [default: 600000]
+      --freeze-check-ms <MS>      how often scheduled freeze windows are started and ended; 0 disables [env: DIST_SPACE_FREEZE_CHECK_MS] [default: 1000]
+      --activity-ms <MS>          how often activity feed watchers are sent what happened in their workspace; 0 disables [env: DIST_SPACE_ACTIVITY_MS] [default: 1000]
+      --min-protocol <N>          oldest client protocol version accepted; 0 also accepts clients that predate versions [env: DIST_SPACE_MIN_PROTOCOL] [default: 0]
+      --require-caps <LIST>       turn away clients that don't handle these: batches, presence; comma-separated [env: DIST_SPACE_REQUIRE_CAPS]
+      --handover-to <ADDR>        at shutdown, leave every document in the handover file and send clients to this server, which shares our storage; requires --handover-file [env: DIST_SPACE_HANDOVER_TO]
+      --handover-file <PATH>      file documents are left in for the server taking over, and taken over from at startup [env: DIST_SPACE_HANDOVER_FILE]
+      --log-level <LEVEL>         error, info, debug or trace [env: DIST_SPACE_LOG_LEVEL] [default: info]
+      --log-file <PATH>           log to this file instead of stdout and stderr [env: DIST_SPACE_LOG_FILE]
+      --log-max-bytes <BYTES>     rotate the log file before it grows past this size; 0 disables [env: DIST_SPACE_LOG_MAX_BYTES] [default: 10485760]
+      --log-max-age-ms <MS>       rotate the log file once it is this old; 0 disables [env: DIST_SPACE_LOG_MAX_AGE_MS] [default: 0]
+      --log-keep <N>              rotated log files to keep [env: DIST_SPACE_LOG_KEEP] [default: 5]
+  -h, --help                      print this help";
+
+/// Intervals for the periodic maintenance tasks; `None` disables a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceIntervals {
+    pub ping: Option<Duration>,
+    pub timeout_sweep: Option<Duration>,
+    pub log_compaction: Option<Duration>,
+    pub metrics: Option<Duration>,
+    /// How often connections are checked for having gone idle.
+    pub presence: Option<Duration>,
+    /// Autosave debounce: edits are written once quiet for about this long.
+    pub autosave: Option<Duration>,
+    /// How often new ops are appended to the op log export, if there is one.
+    pub oplog_export: Option<Duration>,
+    /// How often persisted documents are committed, if there is a git
+    /// repository to commit them to.
+    pub git_commit: Option<Duration>,
+    /// How often attachments no document references are deleted.
+    pub attachment_gc: Option<Duration>,
+    /// How often freeze windows are checked for having started or ended.
+    pub freeze_windows: Option<Duration>,
+    /// How often activity feed watchers are sent their workspace's activity.
+    pub activity: Option<Duration>,
+}
+
+impl Default for MaintenanceIntervals {
+    fn default() -> Self {
+        Self {
+            ping: Some(Duration::from_millis(HEARTBEAT_INTERVAL_MS)),
+            timeout_sweep: Some(Duration::from_millis(HEARTBEAT_INTERVAL_MS)),
+            log_compaction: Some(Duration::from_millis(60_000)),
+            metrics: Some(Duration::from_millis(HEARTBEAT_INTERVAL_MS)),
+            presence: Some(Duration::from_millis(HEARTBEAT_INTERVAL_MS)),
+            autosave: Some(Duration::from_millis(1_000)),
+            oplog_export: Some(Duration::from_millis(5_000)),
+            git_commit: Some(Duration::from_millis(300_000)),
+            attachment_gc: Some(Duration::from_millis(600_000)),
+            freeze_windows: Some(Duration::from_millis(1_000)),
+            activity: Some(Duration::from_millis(1_000)),
+        }
+    }
+}
+
+/// Where the server logs, and how much.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    pub level: LogLevel,
+    /// Log file; `None` logs to stdout and stderr.
+    pub file: Option<PathBuf>,
+    pub rotation: RotationPolicy,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            file: None,
+            rotation: RotationPolicy {
+                max_bytes: Some(10 * 1024 * 1024),
+                max_age: None,
+                keep: 5,
+            },
+        }
+    }
+}
+
+/// Server settings, from command-line flags falling back to environment variables.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Micro-batching window for the broadcast path; `None` sends every op immediately.
+    pub batch_window: Option<Duration>,
+    pub maintenance: MaintenanceIntervals,
+    /// Backing file for the default document; `None` keeps it in memory only.
+    pub doc_file: Option<PathBuf>,
+    /// Documents given starting content at startup.
+    pub seeds: Vec<Seed>,
+    /// Longest a seeded document may be; `None` for no limit.
+    pub seed_max_bytes: Option<u64>,
+    /// Git repository persisted documents are committed to; `None` keeps no
+    /// history beyond the op logs.
+    pub git_repo: Option<PathBuf>,
+    /// JSON Lines file applied ops are exported to; `None` disables the export.
+    pub oplog_export: Option<PathBuf>,
+    /// Directory of document templates; `None` disables creating from templates.
+    pub template_dir: Option<PathBuf>,
+    /// Input-free time after which a connection is shown as idle; `None` never idles.
+    pub idle_after: Option<Duration>,
+    /// Conflict policy for each document, by path.
+    pub conflict_policies: ConflictPolicies,
+    /// Id each document is registered under, by path.
+    pub doc_ids: DocIds,
+    /// What opening a path with no document at it does.
+    pub missing_documents: MissingDocuments,
+    /// Rewrites applied to text clients insert.
+    pub normalization: Normalization,
+    /// Ops each connection may send a second; `None` for no limit.
+    pub op_rate: Option<usize>,
+    /// Log every applied op.
+    pub audit_ops: bool,
+    /// WASM modules ops are run through, after the rate limit.
+    pub plugins: Vec<PluginSpec>,
+    /// What members may edit, asked before their ops are transformed.
+    pub op_rules: OpRules,
+    /// Limits each workspace is held to.
+    pub workspace_quota: WorkspaceQuota,
+    /// File workspace members are kept in; `None` keeps them in memory only.
+    pub members_file: Option<PathBuf>,
+    /// File workspace events are appended to; `None` keeps them in memory
+    /// only.
+    pub events_file: Option<PathBuf>,
+    /// File acknowledgement cursors are kept in; `None` keeps them in memory
+    /// only.
+    pub cursors_file: Option<PathBuf>,
+    /// File ops and presence are recorded to for compliance; `None` records
+    /// nothing.
+    pub compliance_file: Option<PathBuf>,
+    /// Workspaces recorded to `compliance_file`.
+    pub compliance_workspaces: RecordedWorkspaces,
+    /// How long a disconnected client can come back and resume; `None`
+    /// forgets a client as it goes.
+    pub offline_grace: Option<Duration>,
+    /// Directory attachments are stored in; `None` keeps them in memory only.
+    pub attach_dir: Option<PathBuf>,
+    pub attachment_limits: AttachmentLimits,
+    /// History op log compaction keeps.
+    pub retention: RetentionPolicy,
+    /// Server clients are sent to at shutdown; `None` just disconnects them.
+    pub handover_to: Option<String>,
+    /// Where documents are left for, and taken over from, another server.
+    pub handover_file: Option<PathBuf>,
+    /// Protocol version and capabilities clients must have.
+    pub client_requirements: ClientRequirements,
+    pub log: LogConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            batch_window: None,
+            maintenance: MaintenanceIntervals::default(),
+            doc_file: Some(PathBuf::from(DEFAULT_DOC_PATH)),
+            seeds: Vec::new(),
+            seed_max_bytes: Some(16 * 1024 * 1024),
+            git_repo: None,
+            oplog_export: None,
+            template_dir: None,
+            idle_after: Some(Duration::from_millis(IDLE_AFTER_MS)),
+            conflict_policies: ConflictPolicies::default(),
+            doc_ids: DocIds::default(),
+            missing_documents: MissingDocuments::default(),
+            normalization: Normalization::default(),
+            op_rate: None,
+            audit_ops: false,
+            plugins: Vec::new(),
+            op_rules: OpRules::default(),
+            workspace_quota: WorkspaceQuota::default(),
+            members_file: None,
+            events_file: None,
+            cursors_file: None,
+            compliance_file: None,
+            compliance_workspaces: RecordedWorkspaces::parse("*"),
+            offline_grace: Some(OFFLINE_GRACE),
+            attach_dir: None,
+            attachment_limits: AttachmentLimits {
+                max_bytes: Some(16 * 1024 * 1024),
+                quota: Some(1024 * 1024 * 1024),
+            },
+            retention: RetentionPolicy::default(),
+            handover_to: None,
+            handover_file: None,
+            client_requirements: ClientRequirements::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses `env::args()`. Returns `Err` with a message (or the usage text for `--help`).
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(env::args().skip(1), |key| env::var(key).ok())
+    }
+
+    fn parse(
+        args: impl IntoIterator<Item = String>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let mut config = ServerConfig::default();
+        let settings = [
+            ("DIST_SPACE_BATCH_WINDOW_MS", &mut config.batch_window),
+            ("DIST_SPACE_PING_INTERVAL_MS", &mut config.maintenance.ping),
+            (
+                "DIST_SPACE_SWEEP_INTERVAL_MS",
+                &mut config.maintenance.timeout_sweep,
+            ),
+            (
+                "DIST_SPACE_COMPACT_INTERVAL_MS",
+                &mut config.maintenance.log_compaction,
+            ),
+            (
+                "DIST_SPACE_METRICS_INTERVAL_MS",
+                &mut config.maintenance.metrics,
+            ),
+            (
+                "DIST_SPACE_PRESENCE_INTERVAL_MS",
+                &mut config.maintenance.presence,
+            ),
+            ("DIST_SPACE_IDLE_AFTER_MS", &mut config.idle_after),
+            (
+                "DIST_SPACE_LOG_MAX_AGE_MS",
+                &mut config.log.rotation.max_age,
+            ),
+            ("DIST_SPACE_AUTOSAVE_MS", &mut config.maintenance.autosave),
+            (
+                "DIST_SPACE_GIT_COMMIT_MS",
+                &mut config.maintenance.git_commit,
+            ),
+            (
+                "DIST_SPACE_ATTACH_GC_MS",
+                &mut config.maintenance.attachment_gc,
+            ),
+            (
+                "DIST_SPACE_FREEZE_CHECK_MS",
+                &mut config.maintenance.freeze_windows,
+            ),
+            ("DIST_SPACE_ACTIVITY_MS", &mut config.maintenance.activity),
+            (
+                "DIST_SPACE_OPLOG_EXPORT_MS",
+                &mut config.maintenance.oplog_export,
+            ),
+            ("DIST_SPACE_OPLOG_MAX_AGE_MS", &mut config.retention.max_age),
+            ("DIST_SPACE_OFFLINE_GRACE_MS", &mut config.offline_grace),
+        ];
+        for (key, setting) in settings {
+            if let Some(value) = var(key) {
+                *setting = parse_interval(&value)?;
+            }
+        }
+        if let Some(value) = var("DIST_SPACE_DOC_FILE") {
+            config.doc_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_SEED") {
+            config.seeds = Seed::parse_list(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_SEED_MAX_BYTES") {
+            config.seed_max_bytes = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_GIT_REPO") {
+            config.git_repo = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_OPLOG_EXPORT") {
+            config.oplog_export = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_OPLOG_MAX_VERSIONS") {
+            config.retention.max_versions = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_OPLOG_MAX_BYTES") {
+            config.retention.max_bytes = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_TEMPLATE_DIR") {
+            config.template_dir = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_CONFLICT_POLICY") {
+            config.conflict_policies = ConflictPolicies::parse(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_DOC_IDS") {
+            config.doc_ids = DocIds::parse(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_MISSING_DOCS") {
+            config.missing_documents = value.parse()?;
+        }
+        if let Some(value) = var("DIST_SPACE_NORMALIZE") {
+            config.normalization = Normalization::parse(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_OP_RATE") {
+            config.op_rate = parse_limit(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_AUDIT_OPS") {
+            config.audit_ops = parse_bool(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_PLUGINS") {
+            config.plugins = PluginSpec::parse_list(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_OP_RULES") {
+            config.op_rules = OpRules::parse(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_WORKSPACE_MAX_CLIENTS") {
+            config.workspace_quota.max_clients = parse_limit(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_WORKSPACE_MAX_DOCS") {
+            config.workspace_quota.max_documents = parse_limit(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_MEMBERS_FILE") {
+            config.members_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_EVENTS_FILE") {
+            config.events_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_CURSORS_FILE") {
+            config.cursors_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_COMPLIANCE_FILE") {
+            config.compliance_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_COMPLIANCE_WORKSPACES") {
+            config.compliance_workspaces = RecordedWorkspaces::parse(&value);
+        }
+        if let Some(value) = var("DIST_SPACE_MIN_PROTOCOL") {
+            config.client_requirements.min_protocol =
+                ClientRequirements::parse_min_protocol(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_REQUIRE_CAPS") {
+            config.client_requirements.capabilities =
+                ClientRequirements::parse_capabilities(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_HANDOVER_TO") {
+            config.handover_to = parse_address(value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_HANDOVER_FILE") {
+            config.handover_file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_ATTACH_DIR") {
+            config.attach_dir = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_ATTACH_MAX_BYTES") {
+            config.attachment_limits.max_bytes = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_ATTACH_QUOTA") {
+            config.attachment_limits.quota = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_LOG_LEVEL") {
+            config.log.level = value.parse()?;
+        }
+        if let Some(value) = var("DIST_SPACE_LOG_FILE") {
+            config.log.file = parse_file(value);
+        }
+        if let Some(value) = var("DIST_SPACE_LOG_MAX_BYTES") {
+            config.log.rotation.max_bytes = parse_size(&value)?;
+        }
+        if let Some(value) = var("DIST_SPACE_LOG_KEEP") {
+            config.log.rotation.keep = parse_count(&value)?;
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Accept both `--flag value` and `--flag=value`.
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", flag))
+            };
+
+            let maintenance = &mut config.maintenance;
+            match flag.as_str() {
+                "--batch-window-ms" => config.batch_window = parse_interval(&value()?)?,
+                "--ping-interval-ms" => maintenance.ping = parse_interval(&value()?)?,
+                "--sweep-interval-ms" => maintenance.timeout_sweep = parse_interval(&value()?)?,
+                "--compact-interval-ms" => maintenance.log_compaction = parse_interval(&value()?)?,
+                "--metrics-interval-ms" => maintenance.metrics = parse_interval(&value()?)?,
+                "--presence-interval-ms" => maintenance.presence = parse_interval(&value()?)?,
+                "--idle-after-ms" => config.idle_after = parse_interval(&value()?)?,
+                "--autosave-ms" => maintenance.autosave = parse_interval(&value()?)?,
+                "--doc-file" => config.doc_file = parse_file(value()?),
+                "--seed" => config.seeds = Seed::parse_list(&value()?)?,
+                "--seed-max-bytes" => config.seed_max_bytes = parse_size(&value()?)?,
+                "--git-repo" => config.git_repo = parse_file(value()?),
+                "--git-commit-ms" => maintenance.git_commit = parse_interval(&value()?)?,
+                "--oplog-export" => config.oplog_export = parse_file(value()?),
+                "--oplog-export-ms" => maintenance.oplog_export = parse_interval(&value()?)?,
+                "--oplog-max-versions" => config.retention.max_versions = parse_size(&value()?)?,
+                "--oplog-max-bytes" => config.retention.max_bytes = parse_size(&value()?)?,
+                "--oplog-max-age-ms" => config.retention.max_age = parse_interval(&value()?)?,
+                "--template-dir" => config.template_dir = parse_file(value()?),
+                "--conflict-policy" => {
+                    config.conflict_policies = ConflictPolicies::parse(&value()?)?
+                }
+                "--doc-ids" => config.doc_ids = DocIds::parse(&value()?)?,
+                "--missing-docs" => config.missing_documents = value()?.parse()?,
+                "--normalize" => config.normalization = Normalization::parse(&value()?)?,
+                "--op-rate" => config.op_rate = parse_limit(&value()?)?,
+                "--audit-ops" => config.audit_ops = parse_bool(&value()?)?,
+                "--plugins" => config.plugins = PluginSpec::parse_list(&value()?)?,
+                "--op-rules" => config.op_rules = OpRules::parse(&value()?)?,
+                "--workspace-max-clients" => {
+                    config.workspace_quota.max_clients = parse_limit(&value()?)?
+                }
+                "--workspace-max-docs" => {
+                    config.workspace_quota.max_documents = parse_limit(&value()?)?
+                }
+                "--members-file" => config.members_file = parse_file(value()?),
+                "--events-file" => config.events_file = parse_file(value()?),
+                "--cursors-file" => config.cursors_file = parse_file(value()?),
+                "--compliance-file" => config.compliance_file = parse_file(value()?),
+                "--compliance-workspaces" => {
+                    config.compliance_workspaces = RecordedWorkspaces::parse(&value()?)
+                }
+                "--offline-grace-ms" => config.offline_grace = parse_interval(&value()?)?,
+                "--attach-dir" => config.attach_dir = parse_file(value()?),
+                "--attach-max-bytes" => config.attachment_limits.max_bytes = parse_size(&value()?)?,
+                "--attach-quota" => config.attachment_limits.quota = parse_size(&value()?)?,
+                "--attach-gc-ms" => maintenance.attachment_gc = parse_interval(&value()?)?,
+                "--freeze-check-ms" => maintenance.freeze_windows = parse_interval(&value()?)?,
+                "--activity-ms" => maintenance.activity = parse_interval(&value()?)?,
+                "--min-protocol" => {
+                    config.client_requirements.min_protocol =
+                        ClientRequirements::parse_min_protocol(&value()?)?
+                }
+                "--require-caps" => {
+                    config.client_requirements.capabilities =
+                        ClientRequirements::parse_capabilities(&value()?)?
+                }
+                "--handover-to" => config.handover_to = parse_address(value()?)?,
+                "--handover-file" => config.handover_file = parse_file(value()?),
+                "--log-level" => config.log.level = value()?.parse()?,
+                "--log-file" => config.log.file = parse_file(value()?),
+                "--log-max-bytes" => config.log.rotation.max_bytes = parse_size(&value()?)?,
+                "--log-max-age-ms" => config.log.rotation.max_age = parse_interval(&value()?)?,
+                "--log-keep" => config.log.rotation.keep = parse_count(&value()?)?,
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                other => return Err(format!("Unknown argument '{}'\n\n{}", other, USAGE)),
+            }
+        }
+
+        // Documents only persisted by autosave would be lost to the new server
+        if config.handover_to.is_some() && config.handover_file.is_none() {
+            return Err("--handover-to requires --handover-file".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Milliseconds, with 0 meaning "disabled".
+fn parse_interval(value: &str) -> Result<Option<Duration>, String> {
+    let ms = value
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid interval '{}'", value))?;
+    Ok((ms > 0).then(|| Duration::from_millis(ms)))
+}
+
+/// Bytes, with 0 meaning "no limit".
+fn parse_size(value: &str) -> Result<Option<u64>, String> {
+    let bytes = value
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid size '{}'", value))?;
+    Ok((bytes > 0).then_some(bytes))
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid count '{}'", value))
+}
+
+/// A count, with 0 meaning "no limit".
+fn parse_limit(value: &str) -> Result<Option<usize>, String> {
+    let count = parse_count(value)?;
+    Ok((count > 0).then_some(count))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!("Invalid boolean '{}'; use true or false", other)),
+    }
+}
+
+/// A path, with an empty value meaning "none".
+fn parse_file(value: String) -> Option<PathBuf> {
+    (!value.is_empty()).then(|| PathBuf::from(value))
+}
+
+/// A host:port, with an empty value meaning "none".
+fn parse_address(value: String) -> Result<Option<String>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Some(value)),
+        _ => Err(format!("Invalid address '{}', expected HOST:PORT", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], vars: &[(&str, &str)]) -> Result<ServerConfig, String> {
+        ServerConfig::parse(args.iter().map(|a| a.to_string()), |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_batch_window() {
+        assert_eq!(parse(&[], &[]).unwrap().batch_window, None);
+        assert_eq!(
+            parse(&[], &[("DIST_SPACE_BATCH_WINDOW_MS", "10")])
+                .unwrap()
+                .batch_window,
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            parse(
+                &["--batch-window-ms=0"],
+                &[("DIST_SPACE_BATCH_WINDOW_MS", "10")]
+            )
+            .unwrap()
+            .batch_window,
+            None
+        );
+        assert!(parse(&["--batch-window-ms", "soon"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_maintenance_intervals() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().maintenance,
+            MaintenanceIntervals::default()
+        );
+        let maintenance = parse(
+            &["--compact-interval-ms", "0", "--ping-interval-ms=500"],
+            &[("DIST_SPACE_METRICS_INTERVAL_MS", "2000")],
+        )
+        .unwrap()
+        .maintenance;
+        assert_eq!(maintenance.log_compaction, None);
+        assert_eq!(maintenance.ping, Some(Duration::from_millis(500)));
+        assert_eq!(maintenance.metrics, Some(Duration::from_millis(2000)));
+        assert_eq!(
+            maintenance.presence,
+            MaintenanceIntervals::default().presence
+        );
+        assert_eq!(
+            maintenance.timeout_sweep,
+            MaintenanceIntervals::default().timeout_sweep
+        );
+    }
+
+    #[test]
+    fn test_idle_after() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().idle_after,
+            Some(Duration::from_millis(IDLE_AFTER_MS))
+        );
+        assert_eq!(
+            parse(&["--idle-after-ms", "60000"], &[])
+                .unwrap()
+                .idle_after,
+            Some(Duration::from_millis(60_000))
+        );
+        assert_eq!(
+            parse(&[], &[("DIST_SPACE_IDLE_AFTER_MS", "0")])
+                .unwrap()
+                .idle_after,
+            None
+        );
+    }
+
+    #[test]
+    fn test_log_config() {
+        assert_eq!(parse(&[], &[]).unwrap().log, LogConfig::default());
+        let log = parse(
+            &[
+                "--log-file",
+                "server.log",
+                "--log-max-bytes=0",
+                "--log-keep",
+                "2",
+            ],
+            &[
+                ("DIST_SPACE_LOG_LEVEL", "info"),
+                ("DIST_SPACE_LOG_MAX_AGE_MS", "3600000"),
+            ],
+        )
+        .unwrap()
+        .log;
+        assert_eq!(log.level, LogLevel::Info);
+        assert_eq!(log.file, Some(PathBuf::from("server.log")));
+        assert_eq!(log.rotation.max_bytes, None);
+        assert_eq!(log.rotation.max_age, Some(Duration::from_secs(3600)));
+        assert_eq!(log.rotation.keep, 2);
+        assert!(parse(&["--log-level", "loud"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_doc_file() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().doc_file,
+            Some(PathBuf::from("main.txt"))
+        );
+        assert_eq!(
+            parse(&["--doc-file", "/srv/notes.txt"], &[])
+                .unwrap()
+                .doc_file,
+            Some(PathBuf::from("/srv/notes.txt"))
+        );
+        assert_eq!(
+            parse(&[], &[("DIST_SPACE_DOC_FILE", "")]).unwrap().doc_file,
+            None
+        );
+        let config = parse(
+            &["--seed", "notes.txt=-", "--seed-max-bytes=0"],
+            &[("DIST_SPACE_SEED", "intro.md")],
+        )
+        .unwrap();
+        assert_eq!(config.seeds.len(), 1);
+        assert_eq!(config.seeds[0].path, "notes.txt");
+        assert_eq!(config.seed_max_bytes, None);
+        assert_eq!(parse(&[], &[]).unwrap().git_repo, None);
+        let config = parse(
+            &["--git-repo=history", "--git-commit-ms", "0"],
+            &[("DIST_SPACE_GIT_COMMIT_MS", "60000")],
+        )
+        .unwrap();
+        assert_eq!(config.git_repo, Some(PathBuf::from("history")));
+        assert_eq!(config.maintenance.git_commit, None);
+        let config = parse(
+            &["--attach-dir=files", "--attach-quota", "0"],
+            &[
+                ("DIST_SPACE_ATTACH_MAX_BYTES", "1024"),
+                ("DIST_SPACE_ATTACH_GC_MS", "0"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.attach_dir, Some(PathBuf::from("files")));
+        assert_eq!(
+            config.attachment_limits,
+            AttachmentLimits {
+                max_bytes: Some(1024),
+                quota: None,
+            }
+        );
+        assert_eq!(config.maintenance.attachment_gc, None);
+        let config = parse(&[], &[("DIST_SPACE_FREEZE_CHECK_MS", "0")]).unwrap();
+        assert_eq!(config.maintenance.freeze_windows, None);
+        let config = parse(&["--freeze-check-ms=250"], &[]).unwrap();
+        assert_eq!(
+            config.maintenance.freeze_windows,
+            Some(Duration::from_millis(250))
+        );
+        let config = parse(&["--activity-ms", "0"], &[]).unwrap();
+        assert_eq!(config.maintenance.activity, None);
+        assert_eq!(parse(&[], &[]).unwrap().oplog_export, None);
+        assert_eq!(
+            parse(&["--oplog-export=ops.jsonl"], &[])
+                .unwrap()
+                .oplog_export,
+            Some(PathBuf::from("ops.jsonl"))
+        );
+        assert_eq!(parse(&[], &[]).unwrap().template_dir, None);
+        assert_eq!(
+            parse(&[], &[("DIST_SPACE_TEMPLATE_DIR", "templates")])
+                .unwrap()
+                .template_dir,
+            Some(PathBuf::from("templates"))
+        );
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().conflict_policies,
+            ConflictPolicies::default()
+        );
+        let policies = parse(
+            &["--conflict-policy", "notes.md=keep-inserts"],
+            &[("DIST_SPACE_CONFLICT_POLICY", "delete-wins")],
+        )
+        .unwrap()
+        .conflict_policies;
+        assert_eq!(
+            policies.for_path("notes.md"),
+            crate::conflict::ConflictPolicy::KeepInserts
+        );
+        assert!(parse(&["--conflict-policy=loudest"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_workspace_quota() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().workspace_quota,
+            WorkspaceQuota::default()
+        );
+        let quota = parse(
+            &["--workspace-max-clients=10"],
+            &[("DIST_SPACE_WORKSPACE_MAX_DOCS", "50")],
+        )
+        .unwrap()
+        .workspace_quota;
+        assert_eq!(quota.max_clients, Some(10));
+        assert_eq!(quota.max_documents, Some(50));
+        assert!(parse(&["--workspace-max-docs", "many"], &[]).is_err());
+        assert_eq!(
+            parse(&["--members-file=members.tsv"], &[])
+                .unwrap()
+                .members_file,
+            Some(PathBuf::from("members.tsv"))
+        );
+        assert_eq!(
+            parse(&[], &[("DIST_SPACE_EVENTS_FILE", "events.tsv")])
+                .unwrap()
+                .events_file,
+            Some(PathBuf::from("events.tsv"))
+        );
+    }
+
+    #[test]
+    fn test_compliance() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config.compliance_file, None);
+        assert!(config.compliance_workspaces.contains("team-a"));
+        let config = parse(
+            &["--compliance-file=audit.log"],
+            &[("DIST_SPACE_COMPLIANCE_WORKSPACES", "legal,finance")],
+        )
+        .unwrap();
+        assert_eq!(config.compliance_file, Some(PathBuf::from("audit.log")));
+        assert!(config.compliance_workspaces.contains("legal"));
+        assert!(!config.compliance_workspaces.contains("default"));
+    }
+
+    #[test]
+    fn test_cursors() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config.cursors_file, None);
+        assert_eq!(config.offline_grace, Some(OFFLINE_GRACE));
+        let config = parse(
+            &["--cursors-file", "cursors.tsv"],
+            &[("DIST_SPACE_OFFLINE_GRACE_MS", "0")],
+        )
+        .unwrap();
+        assert_eq!(config.cursors_file, Some(PathBuf::from("cursors.tsv")));
+        assert_eq!(config.offline_grace, None);
+        assert_eq!(
+            parse(&["--offline-grace-ms=60000"], &[])
+                .unwrap()
+                .offline_grace,
+            Some(Duration::from_millis(60_000))
+        );
+    }
+
+    #[test]
+    fn test_normalization() {
+        assert!(parse(&[], &[]).unwrap().normalization.strip_bom);
+        let normalization = parse(&["--normalize=crlf"], &[("DIST_SPACE_NORMALIZE", "none")])
+            .unwrap()
+            .normalization;
+        assert!(normalization.crlf_to_lf && !normalization.strip_bom);
+        assert!(parse(&["--normalize", "latin1"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_op_middleware() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!((config.op_rate, config.audit_ops), (None, false));
+        let config = parse(&["--op-rate=20"], &[("DIST_SPACE_AUDIT_OPS", "true")]).unwrap();
+        assert_eq!((config.op_rate, config.audit_ops), (Some(20), true));
+        assert!(parse(&["--audit-ops", "yes"], &[]).is_err());
+        let config = parse(&[], &[("DIST_SPACE_PLUGINS", "docs=filter.wasm")]).unwrap();
+        assert_eq!(config.plugins[0].file, PathBuf::from("filter.wasm"));
+        assert!(config.op_rules.is_empty());
+        let config = parse(
+            &["--op-rules", "ci-bot=append-only,intern=max-delete:100"],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            config.op_rules.to_string(),
+            "ci-bot=append-only,intern=max-delete:100"
+        );
+        assert!(parse(&[], &[("DIST_SPACE_OP_RULES", "intern=delete-nothing")]).is_err());
+    }
+
+    #[test]
+    fn test_doc_ids_and_missing_documents() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config.doc_ids, DocIds::default());
+        assert_eq!(config.missing_documents, MissingDocuments::Create);
+
+        let config = parse(
+            &["--doc-ids", "path", "--missing-docs=refuse"],
+            &[("DIST_SPACE_DOC_IDS", "random")],
+        )
+        .unwrap();
+        assert_eq!(config.doc_ids, DocIds::parse("path").unwrap());
+        assert_eq!(config.missing_documents, MissingDocuments::Refuse);
+        assert!(parse(&["--missing-docs", "maybe"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_client_requirements() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().client_requirements,
+            ClientRequirements::default()
+        );
+        let requirements = parse(
+            &["--min-protocol=1", "--require-caps", "batches"],
+            &[("DIST_SPACE_REQUIRE_CAPS", "presence")],
+        )
+        .unwrap()
+        .client_requirements;
+        assert_eq!(requirements.min_protocol, 1);
+        assert_eq!(requirements.capabilities, ["batches"]);
+        assert!(parse(&["--min-protocol", "999"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_handover() {
+        assert_eq!(parse(&[], &[]).unwrap().handover_to, None);
+        let config = parse(
+            &["--handover-to", "10.0.0.2:8000"],
+            &[("DIST_SPACE_HANDOVER_FILE", "handover.bin")],
+        )
+        .unwrap();
+        assert_eq!(config.handover_to.as_deref(), Some("10.0.0.2:8000"));
+        assert_eq!(config.handover_file, Some(PathBuf::from("handover.bin")));
+        assert!(parse(&["--handover-to", "10.0.0.2:8000"], &[]).is_err());
+        assert!(parse(&[], &[("DIST_SPACE_HANDOVER_TO", "10.0.0.2")]).is_err());
+    }
+
+    #[test]
+    fn test_retention() {
+        assert_eq!(
+            parse(&[], &[]).unwrap().retention,
+            RetentionPolicy::default()
+        );
+        let retention = parse(
+            &["--oplog-max-versions=0", "--oplog-max-age-ms", "60000"],
+            &[("DIST_SPACE_OPLOG_MAX_BYTES", "1024")],
+        )
+        .unwrap()
+        .retention;
+        assert_eq!(
+            retention,
+            RetentionPolicy {
+                max_versions: None,
+                max_bytes: Some(1024),
+                max_age: Some(Duration::from_secs(60)),
+            }
+        );
+    }
+}
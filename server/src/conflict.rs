@@ -0,0 +1,132 @@
+use std::{fmt, str::FromStr};
+
+/// How `transform` settles the cases concurrent edits leave open: inserts at
+/// the same index, text inserted inside a range someone else deletes, and
+/// replaces over overlapping ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Inserts at one index go in client id order. An insert inside a range
+    /// deleted before it lands at the range's start, while a delete or replace
+    /// applied after it grows to cover it. Overlapping replaces both keep
+    /// their text.
+    #[default]
+    Merge,
+    /// Like `Merge`, but text inserted inside a deleted or replaced range is
+    /// kept whichever of the two applies first.
+    KeepInserts,
+    /// Like `Merge`, but text inserted inside a deleted or replaced range is
+    /// removed whichever of the two applies first.
+    DeleteWins,
+    /// The server's order decides: at one index the insert applied first
+    /// stays first, and a replace overlapping one applied before it is
+    /// dropped.
+    FirstWriterWins,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 4] = [
+        ConflictPolicy::Merge,
+        ConflictPolicy::KeepInserts,
+        ConflictPolicy::DeleteWins,
+        ConflictPolicy::FirstWriterWins,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Merge => "merge",
+            ConflictPolicy::KeepInserts => "keep-inserts",
+            ConflictPolicy::DeleteWins => "delete-wins",
+            ConflictPolicy::FirstWriterWins => "first-writer-wins",
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        Self::ALL.get(value as usize).copied().unwrap_or_default()
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.as_str() == value.to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "Unknown conflict policy '{}' (merge, keep-inserts, delete-wins, first-writer-wins)",
+                    value
+                )
+            })
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The policy each document starts with: a default, and overrides by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConflictPolicies {
+    pub default: ConflictPolicy,
+    by_path: Vec<(String, ConflictPolicy)>,
+}
+
+impl ConflictPolicies {
+    /// Parses comma-separated rules, each a policy for every document or
+    /// `PATH=POLICY` for one, e.g. `delete-wins,notes.md=keep-inserts`.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut policies = Self::default();
+        for rule in rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            match rule.split_once('=') {
+                Some((path, policy)) => policies.set(path.trim(), policy.trim().parse()?),
+                None => policies.default = rule.parse()?,
+            }
+        }
+        Ok(policies)
+    }
+
+    pub fn set(&mut self, path: &str, policy: ConflictPolicy) {
+        self.by_path.retain(|(p, _)| p != path);
+        self.by_path.push((path.to_string(), policy));
+    }
+
+    pub fn for_path(&self, path: &str) -> ConflictPolicy {
+        self.by_path
+            .iter()
+            .find(|(p, _)| p == path)
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_set_default_and_per_path_policies() {
+        let policies = ConflictPolicies::parse("delete-wins, notes.md=keep-inserts").unwrap();
+        assert_eq!(policies.for_path("notes.md"), ConflictPolicy::KeepInserts);
+        assert_eq!(policies.for_path("main.txt"), ConflictPolicy::DeleteWins);
+        assert_eq!(
+            ConflictPolicies::parse("").unwrap().for_path("main.txt"),
+            ConflictPolicy::Merge
+        );
+        assert!(ConflictPolicies::parse("a.txt=loudest").is_err());
+
+        for policy in ConflictPolicy::ALL {
+            assert_eq!(ConflictPolicy::from_u8(policy.to_u8()), policy);
+            assert_eq!(policy.as_str().parse(), Ok(policy));
+        }
+    }
+}
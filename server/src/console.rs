@@ -0,0 +1,938 @@
+use std::{
+    io::{self, BufRead},
+    sync::Arc,
+    thread,
+};
+
+use common::clock::unix_time_ms;
+use uuid::Uuid;
+
+use crate::autosave;
+use crate::capture::{CAPTURE_CAPACITY, CAPTURES};
+use crate::client_entry::WRITER_QUEUE_CAPACITY;
+use crate::dead_letters::DEAD_LETTERS;
+use crate::documents::DocumentEntry;
+use crate::freezes::Freeze;
+use crate::log::{self, LogLevel, info};
+use crate::metrics::{COMPACTIONS, EVICTIONS, RESUMES, WRITER_QUEUES};
+use crate::state::{MAX_CLIENTS, ServerState};
+
+pub const HELP: &str = "\
+Console commands:
+  status                          connections, documents and workspaces
+  clients                         list connections
+  latency                         how long traced ops took to reach and be applied by each connection
+  docs                            list open documents
+  workspaces                      list workspaces with their connections and versions
+  members                         list the members of each workspace that has them
+  invite <ws> <name>              make someone a member of a workspace and print their token
+  uninvite <ws> <name>            take someone out of a workspace and close their connections
+  guest <ws> <path> <min> [edit]  let a guest view (or edit) one document for a while; prints the token
+  stats <path>                    a document's length, lines, last edit and edits per author
+  history <path>                  a document's milestones, tags and the versions its op log holds
+  checkpoint <path> <label>       squash a document's op log into a milestone named by the label
+  appendonly <path> [a..b ...]    replace a document's append-only regions; none lifts them all
+  freeze <ws> <path|*> [min] [in] make a document, or * the whole workspace, read-only for min minutes (0: until unfrozen), starting in `in` minutes
+  unfreeze <ws> <path|*>          lift a freeze, started or not
+  notice <ws|*> <message>         show a message to every client in a workspace, or * every client
+  freezes                         list freeze windows
+  kick <id>                       disconnect a client (a unique id prefix will do)
+  deadletters                     list recently dropped frames
+  compliance                      check the compliance record's hash chain
+  capture <id>                    record a client's recent frames in both directions
+  dump <id>                       print a captured client's frames
+  uncapture <id>                  stop capturing a client and discard its frames
+  snapshot                        save every persisted document now
+  loglevel [<lvl>]                show or set the log level (error .. trace)
+  shutdown                        notify clients, save and exit
+  help                            print this help";
+
+/// A line typed on the server's stdin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    Status,
+    Clients,
+    Latency,
+    Docs,
+    Workspaces,
+    Members,
+    /// Workspace and member names.
+    Invite(String, String),
+    Uninvite(String, String),
+    /// Workspace, document path, minutes and whether the guest may edit.
+    Guest(String, String, u64, bool),
+    /// By document path.
+    Stats(String),
+    History(String),
+    /// Document path and label.
+    Checkpoint(String, String),
+    /// Document path and the byte ranges to keep append-only.
+    AppendOnly(String, Vec<(u32, u32)>),
+    /// Workspace, document path (`None` for all of them), minutes (0 until
+    /// unfrozen) and minutes until it starts.
+    Freeze(String, Option<String>, u64, u64),
+    Unfreeze(String, Option<String>),
+    Freezes,
+    /// Workspace (`None` for every one) and message.
+    Notice(Option<String>, String),
+    Kick(String),
+    DeadLetters,
+    Compliance,
+    Capture(String),
+    Dump(String),
+    Uncapture(String),
+    Snapshot,
+    /// `None` prints the current level.
+    LogLevel(Option<LogLevel>),
+    Shutdown,
+    Help,
+}
+
+impl ConsoleCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arguments: Vec<&str> = words.collect();
+        let most = match name {
+            "invite" | "uninvite" | "unfreeze" => 2,
+            "freeze" => 4,
+            "guest" => 4,
+            "checkpoint" | "notice" | "appendonly" => usize::MAX,
+            _ => 1,
+        };
+        if arguments.len() > most {
+            return Err(format!("Too many arguments for '{}'", name));
+        }
+        let argument = arguments.first().copied();
+
+        let command = match name {
+            "status" => ConsoleCommand::Status,
+            "clients" => ConsoleCommand::Clients,
+            "latency" => ConsoleCommand::Latency,
+            "docs" => ConsoleCommand::Docs,
+            "workspaces" => ConsoleCommand::Workspaces,
+            "members" => ConsoleCommand::Members,
+            "freezes" => ConsoleCommand::Freezes,
+            "deadletters" => ConsoleCommand::DeadLetters,
+            "compliance" => ConsoleCommand::Compliance,
+            "snapshot" => ConsoleCommand::Snapshot,
+            "shutdown" => ConsoleCommand::Shutdown,
+            "help" => ConsoleCommand::Help,
+            "stats" => {
+                let path = argument.ok_or("Usage: stats <path>")?;
+                return Ok(ConsoleCommand::Stats(path.to_string()));
+            }
+            "history" => {
+                let path = argument.ok_or("Usage: history <path>")?;
+                return Ok(ConsoleCommand::History(path.to_string()));
+            }
+            "checkpoint" => {
+                let [path, ref label @ ..] = arguments[..] else {
+                    return Err("Usage: checkpoint <path> <label>".to_string());
+                };
+                if label.is_empty() {
+                    return Err("Usage: checkpoint <path> <label>".to_string());
+                }
+                return Ok(ConsoleCommand::Checkpoint(
+                    path.to_string(),
+                    label.join(" "),
+                ));
+            }
+            "appendonly" => {
+                let [path, ref ranges @ ..] = arguments[..] else {
+                    return Err("Usage: appendonly <path> [a..b ...]".to_string());
+                };
+                let regions = ranges
+                    .iter()
+                    .map(|range| {
+                        range
+                            .split_once("..")
+                            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+                            .ok_or_else(|| format!("Invalid range '{}'", range))
+                    })
+                    .collect::<Result<_, _>>()?;
+                return Ok(ConsoleCommand::AppendOnly(path.to_string(), regions));
+            }
+            "notice" => {
+                let [workspace, ref message @ ..] = arguments[..] else {
+                    return Err("Usage: notice <workspace|*> <message>".to_string());
+                };
+                if message.is_empty() {
+                    return Err("Usage: notice <workspace|*> <message>".to_string());
+                }
+                // The rest of the line as typed, spacing and all
+                let rest = line.trim_start()[name.len()..].trim_start();
+                let message = rest[workspace.len()..]
+                    .trim_start()
+                    .trim_end_matches(['\r', '\n']);
+                let workspace = (workspace != "*").then(|| workspace.to_string());
+                return Ok(ConsoleCommand::Notice(workspace, message.to_string()));
+            }
+            "kick" => {
+                let id = argument.ok_or("Usage: kick <id>")?;
+                return Ok(ConsoleCommand::Kick(id.to_string()));
+            }
+            "capture" => {
+                let id = argument.ok_or("Usage: capture <id>")?;
+                return Ok(ConsoleCommand::Capture(id.to_string()));
+            }
+            "dump" => {
+                let id = argument.ok_or("Usage: dump <id>")?;
+                return Ok(ConsoleCommand::Dump(id.to_string()));
+            }
+            "uncapture" => {
+                let id = argument.ok_or("Usage: uncapture <id>")?;
+                return Ok(ConsoleCommand::Uncapture(id.to_string()));
+            }
+            "invite" | "uninvite" => {
+                let [workspace, member] = arguments[..] else {
+                    return Err(format!("Usage: {} <workspace> <member>", name));
+                };
+                let (workspace, member) = (workspace.to_string(), member.to_string());
+                return Ok(match name {
+                    "invite" => ConsoleCommand::Invite(workspace, member),
+                    _ => ConsoleCommand::Uninvite(workspace, member),
+                });
+            }
+            "freeze" => {
+                let [workspace, path, ref timing @ ..] = arguments[..] else {
+                    return Err("Usage: freeze <workspace> <path|*> [minutes] [in]".to_string());
+                };
+                let minutes = |word: Option<&&str>| match word {
+                    Some(word) => word
+                        .parse::<u64>()
+                        .map_err(|_| format!("Invalid number of minutes '{}'", word)),
+                    None => Ok(0),
+                };
+                return Ok(ConsoleCommand::Freeze(
+                    workspace.to_string(),
+                    freeze_path(path),
+                    minutes(timing.first())?,
+                    minutes(timing.get(1))?,
+                ));
+            }
+            "unfreeze" => {
+                let [workspace, path] = arguments[..] else {
+                    return Err("Usage: unfreeze <workspace> <path|*>".to_string());
+                };
+                return Ok(ConsoleCommand::Unfreeze(
+                    workspace.to_string(),
+                    freeze_path(path),
+                ));
+            }
+            "guest" => {
+                let (workspace, path, minutes, edit) = match arguments[..] {
+                    [workspace, path, minutes] => (workspace, path, minutes, false),
+                    [workspace, path, minutes, "edit"] => (workspace, path, minutes, true),
+                    _ => return Err("Usage: guest <workspace> <path> <minutes> [edit]".to_string()),
+                };
+                let minutes = minutes
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|minutes| *minutes > 0)
+                    .ok_or_else(|| format!("Invalid number of minutes '{}'", minutes))?;
+                return Ok(ConsoleCommand::Guest(
+                    workspace.to_string(),
+                    path.to_string(),
+                    minutes,
+                    edit,
+                ));
+            }
+            "loglevel" => {
+                let level = argument.map(str::parse).transpose()?;
+                return Ok(ConsoleCommand::LogLevel(level));
+            }
+            other => return Err(format!("Unknown command '{}'; try 'help'", other)),
+        };
+        match argument {
+            Some(_) => Err(format!("'{}' takes no arguments", name)),
+            None => Ok(command),
+        }
+    }
+}
+
+/// `*` freezes every document in the workspace.
+fn freeze_path(path: &str) -> Option<String> {
+    (path != "*").then(|| path.to_string())
+}
+
+/// Reads commands from stdin until it closes. `shutdown` runs on the
+/// console thread, so it is expected not to return.
+pub fn spawn(state: Arc<ServerState>, shutdown: impl FnOnce() + Send + 'static) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match ConsoleCommand::parse(&line) {
+                Ok(ConsoleCommand::Shutdown) => {
+                    shutdown();
+                    return;
+                }
+                Ok(command) => println!("{}", execute(&state, &command)),
+                Err(message) => println!("{}", message),
+            }
+        }
+        // Detached servers have no stdin; that's not a reason to stop
+        info!("[Console] stdin closed; console disabled");
+    });
+}
+
+/// Runs a command and returns what to print. `Shutdown` is left to the
+/// caller.
+pub fn execute(state: &ServerState, command: &ConsoleCommand) -> String {
+    match command {
+        ConsoleCommand::Status => {
+            let (attachments, attachment_bytes) = state.attachment_usage();
+            format!(
+                "clients: {}/{}\ndocuments: {}\nworkspaces: {}\nattachments: {} ({} bytes)\naccept: {}\nevictions: {}\nresumes: {}\nop log compaction: {}\nwriter queues: {}\ndropped frames: {}\nlog level: {}",
+                state.client_count(),
+                MAX_CLIENTS,
+                state.documents().len(),
+                state.workspaces().len(),
+                attachments,
+                attachment_bytes,
+                state.accept_metrics().summary(),
+                EVICTIONS.summary(),
+                RESUMES.summary(),
+                COMPACTIONS.summary(),
+                WRITER_QUEUES.summary(&state.writer_queue_depths(), WRITER_QUEUE_CAPACITY),
+                DEAD_LETTERS.total(),
+                log::level()
+            )
+        }
+        ConsoleCommand::Clients => clients(state),
+        ConsoleCommand::Latency => latency(state),
+        ConsoleCommand::Docs => docs(state),
+        ConsoleCommand::Workspaces => workspaces(state),
+        ConsoleCommand::Members => members(state),
+        ConsoleCommand::Invite(workspace, member) => match state.add_member(workspace, member) {
+            Ok(token) => format!(
+                "Invited '{}' into '{}'; token: {}",
+                member, workspace, token
+            ),
+            Err(e) => e.to_string(),
+        },
+        ConsoleCommand::Guest(workspace, path, minutes, edit) => {
+            let invite = state.add_invite(workspace, path, !edit, minutes * 60_000);
+            format!(
+                "Invited a guest to {} '{}' in '{}' for {} minute(s); token: {}",
+                if *edit { "edit" } else { "view" },
+                invite.path,
+                workspace,
+                minutes,
+                invite.token
+            )
+        }
+        ConsoleCommand::Uninvite(workspace, member) => {
+            match state.revoke_member(workspace, member) {
+                Ok(closed) => format!(
+                    "Removed '{}' from '{}' and closed {} connection(s)",
+                    member, workspace, closed
+                ),
+                Err(e) => e.to_string(),
+            }
+        }
+        ConsoleCommand::Stats(path) => stats(state, path),
+        ConsoleCommand::History(path) => history(state, path),
+        ConsoleCommand::Checkpoint(path, label) => {
+            let Some(entry) = find_document(state, path) else {
+                return format!("No open document '{}'", path);
+            };
+            match state.squash_history(&entry, label) {
+                Ok(milestone) => format!(
+                    "'{}' v{}: squashed {} version(s) into '{}'",
+                    path, milestone.version, milestone.squashed, milestone.label
+                ),
+                Err(e) => e.to_string(),
+            }
+        }
+        ConsoleCommand::AppendOnly(path, regions) => {
+            let Some(entry) = find_document(state, path) else {
+                return format!("No open document '{}'", path);
+            };
+            match state.set_append_only(&entry, regions.clone()) {
+                Ok(()) => format!("'{}' has {} append-only region(s)", path, regions.len()),
+                Err(e) => e.to_string(),
+            }
+        }
+        ConsoleCommand::Freeze(workspace, path, minutes, delay) => {
+            let starts_at_ms = unix_time_ms() + delay * 60_000;
+            let freeze = Freeze {
+                workspace: workspace.clone(),
+                path: path.clone(),
+                starts_at_ms,
+                ends_at_ms: (*minutes > 0).then_some(starts_at_ms + minutes * 60_000),
+            };
+            let target = freeze.target();
+            state.freeze(freeze);
+            match (*delay, *minutes) {
+                (0, 0) => format!("Froze {} until unfrozen", target),
+                (0, minutes) => format!("Froze {} for {} minute(s)", target, minutes),
+                (delay, 0) => format!("Freezing {} in {} minute(s)", target, delay),
+                (delay, minutes) => format!(
+                    "Freezing {} in {} minute(s) for {} minute(s)",
+                    target, delay, minutes
+                ),
+            }
+        }
+        ConsoleCommand::Unfreeze(workspace, path) => {
+            match state.unfreeze(workspace, path.as_deref()) {
+                true => format!(
+                    "Unfroze '{}' in '{}'",
+                    path.as_deref().unwrap_or("*"),
+                    workspace
+                ),
+                false => format!(
+                    "No freeze on '{}' in '{}'",
+                    path.as_deref().unwrap_or("*"),
+                    workspace
+                ),
+            }
+        }
+        ConsoleCommand::Freezes => freezes(state),
+        ConsoleCommand::Notice(workspace, message) => {
+            let sent = state.send_notice(workspace.as_deref(), message);
+            format!("Sent the notice to {} connection(s)", sent)
+        }
+        ConsoleCommand::Kick(id) => match find_client(state, id) {
+            Ok(client_id) => match state.kick_client(client_id) {
+                Some(client) => format!("Kicked {}", client.label()),
+                None => format!("Client {} already left", client_id),
+            },
+            Err(message) => message,
+        },
+        ConsoleCommand::DeadLetters => DEAD_LETTERS.report(),
+        ConsoleCommand::Compliance => match state.verify_compliance() {
+            Some(Ok(entries)) => format!("{} entries, chained intact", entries),
+            Some(Err(broken)) => format!("Compliance record broken at {}", broken),
+            None => "nothing is recorded for compliance".to_string(),
+        },
+        ConsoleCommand::Capture(id) => match find_client(state, id) {
+            Ok(client_id) if CAPTURES.start(client_id) => {
+                format!(
+                    "Capturing up to {} frames of {}",
+                    CAPTURE_CAPACITY, client_id
+                )
+            }
+            Ok(client_id) => format!("Already capturing {}", client_id),
+            Err(message) => message,
+        },
+        ConsoleCommand::Dump(id) => match find_captured(id) {
+            Ok(client_id) => CAPTURES.dump(client_id).unwrap_or_default(),
+            Err(message) => message,
+        },
+        ConsoleCommand::Uncapture(id) => match find_captured(id) {
+            Ok(client_id) => {
+                CAPTURES.stop(client_id);
+                format!("Stopped capturing {}", client_id)
+            }
+            Err(message) => message,
+        },
+        ConsoleCommand::Snapshot => {
+            let persisted = state
+                .documents()
+                .iter()
+                .filter(|entry| entry.backing_file.is_some())
+                .count();
+            let saved = autosave::save_all(state);
+            format!(
+                "Saved {} document(s); {} of {} persisted document(s) were unchanged",
+                saved,
+                persisted.saturating_sub(saved),
+                persisted
+            )
+        }
+        ConsoleCommand::LogLevel(None) => format!("log level: {}", log::level()),
+        ConsoleCommand::LogLevel(Some(level)) => {
+            log::set_level(*level);
+            format!("log level set to {}", level)
+        }
+        ConsoleCommand::Shutdown | ConsoleCommand::Help => HELP.to_string(),
+    }
+}
+
+fn clients(state: &ServerState) -> String {
+    let clients = state.get_clients_arc();
+    let clients = match clients.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    if clients.is_empty() {
+        return "no clients".to_string();
+    }
+    clients
+        .iter()
+        .map(|client| {
+            let viewer = if client.is_read_only() {
+                " (viewer)"
+            } else {
+                ""
+            };
+            let clock = match client.clock() {
+                Some(sample) => format!("rtt={}ms clock={:+}ms", sample.rtt_ms, -sample.offset_ms),
+                None => "rtt=? clock=?".to_string(),
+            };
+            format!(
+                "{} {}{} {} docs={} last_seen={}ms {} queue={}/{} peak={} dropped={}",
+                client.client_id,
+                client.label(),
+                viewer,
+                client.announced_presence().as_str_name(),
+                client.subscription_count(),
+                client.ms_since_last_activity(),
+                clock,
+                client.queue_depth(),
+                WRITER_QUEUE_CAPACITY,
+                client.queue_high_watermark(),
+                client.dropped_frames()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn latency(state: &ServerState) -> String {
+    let clients = state.get_clients_arc();
+    let clients = match clients.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let lines: Vec<String> = clients
+        .iter()
+        .map(|client| (client, client.propagation()))
+        .filter(|(_, propagation)| !propagation.is_empty())
+        .map(|(client, propagation)| {
+            format!(
+                "{} {}: {}",
+                client.client_id,
+                client.label(),
+                propagation.summary()
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        return "no traced ops".to_string();
+    }
+    lines.join("\n")
+}
+
+fn docs(state: &ServerState) -> String {
+    let clients = state.get_clients_arc();
+    let clients = match clients.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let mut documents = state.documents();
+    documents.sort_by(|a, b| a.path.cmp(&b.path));
+    documents
+        .iter()
+        .map(|entry| {
+            let (doc_id, version, len) = {
+                let doc = match entry.document.lock() {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                (doc.uuid.to_string(), doc.version, doc.content.len())
+            };
+            let subscribers = clients
+                .iter()
+                .filter(|client| client.is_subscribed(&doc_id))
+                .count();
+            let file = match &entry.backing_file {
+                Some(file) => file.display().to_string(),
+                None => "in memory".to_string(),
+            };
+            format!(
+                "'{}' {} v{} {} bytes, {} op(s) logged, {} subscriber(s), {}, {}",
+                entry.path,
+                doc_id,
+                version,
+                len,
+                entry.op_log.len(),
+                subscribers,
+                file,
+                entry.conflict_policy()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn workspaces(state: &ServerState) -> String {
+    let clients = state.get_clients_arc();
+    let clients = match clients.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let mut workspaces = state.workspaces();
+    workspaces.sort_by(|a, b| a.name.cmp(&b.name));
+    workspaces
+        .iter()
+        .map(|workspace| {
+            let clients = clients
+                .iter()
+                .filter(|client| client.workspace() == workspace.name)
+                .count();
+            format!(
+                "{} {} client(s), {} document(s), v{}",
+                workspace.label(),
+                clients,
+                workspace.lock_documents().len(),
+                workspace.global_version()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn members(state: &ServerState) -> String {
+    let members = state.members();
+    if members.is_empty() {
+        return "every workspace is open to all".to_string();
+    }
+    members
+        .iter()
+        .map(|(workspace, members)| format!("{}: {}", workspace, members.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn freezes(state: &ServerState) -> String {
+    let freezes = state.freezes();
+    if freezes.is_empty() {
+        return "nothing is frozen".to_string();
+    }
+    let now = unix_time_ms();
+    freezes
+        .iter()
+        .map(|freeze| {
+            let until = match freeze.ends_at_ms {
+                Some(end) => format!("for {}ms more", end.saturating_sub(now)),
+                None => "until unfrozen".to_string(),
+            };
+            match freeze.is_active(now) {
+                true => format!("{}: frozen {}", freeze.target(), until),
+                false => format!(
+                    "{}: freezes in {}ms, {}",
+                    freeze.target(),
+                    freeze.starts_at_ms.saturating_sub(now),
+                    until
+                ),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn find_document(state: &ServerState, path: &str) -> Option<Arc<DocumentEntry>> {
+    state
+        .documents()
+        .into_iter()
+        .find(|entry| entry.path == path)
+}
+
+fn stats(state: &ServerState, path: &str) -> String {
+    let Some(entry) = find_document(state, path) else {
+        return format!("No open document '{}'", path);
+    };
+    let stats = entry.stats().clone();
+    let last_edit = match stats.last_edit.wall_ms {
+        0 => "never".to_string(),
+        ms => format!("{}ms ago", unix_time_ms().saturating_sub(ms)),
+    };
+    let mut lines = vec![format!(
+        "'{}' {} bytes, {} line(s), last edit {}",
+        path,
+        stats.len,
+        stats.line_count(),
+        last_edit
+    )];
+    lines.extend(
+        stats
+            .edits
+            .iter()
+            .map(|(author, edits)| format!("  {} {} edit(s)", author, edits)),
+    );
+    lines.join("\n")
+}
+
+fn history(state: &ServerState, path: &str) -> String {
+    let Some(entry) = find_document(state, path) else {
+        return format!("No open document '{}'", path);
+    };
+    let now = unix_time_ms();
+    let mut lines: Vec<String> = entry
+        .op_log
+        .milestones()
+        .iter()
+        .map(|milestone| {
+            format!(
+                "v{} '{}', {} version(s) squashed {}ms ago",
+                milestone.version,
+                milestone.label,
+                milestone.squashed,
+                now.saturating_sub(milestone.created_ms)
+            )
+        })
+        .collect();
+    lines.extend(entry.op_log.tags().iter().map(|tag| {
+        format!(
+            "v{} tagged '{}' by {} {}ms ago",
+            tag.version,
+            tag.name,
+            tag.client_id,
+            now.saturating_sub(tag.created_ms)
+        )
+    }));
+    lines.push(format!(
+        "v{}..v{} in the op log",
+        entry.op_log.first_version(),
+        entry.op_log.next_version()
+    ));
+    lines.join("\n")
+}
+
+/// The connected client whose id is, or uniquely starts with, `id`.
+fn find_client(state: &ServerState, id: &str) -> Result<Uuid, String> {
+    let clients = state.get_clients_arc();
+    let clients = match clients.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    let matching: Vec<Uuid> = clients
+        .iter()
+        .map(|client| client.client_id)
+        .filter(|client_id| client_id.to_string().starts_with(id))
+        .collect();
+    match matching[..] {
+        [client_id] => Ok(client_id),
+        [] => Err(format!("No client with id '{}'", id)),
+        _ => Err(format!("'{}' matches {} clients", id, matching.len())),
+    }
+}
+
+/// The captured client, connected or not, whose id is, or uniquely starts
+/// with, `id`.
+fn find_captured(id: &str) -> Result<Uuid, String> {
+    let matching: Vec<Uuid> = CAPTURES
+        .captured()
+        .into_iter()
+        .filter(|client_id| client_id.to_string().starts_with(id))
+        .collect();
+    match matching[..] {
+        [client_id] => Ok(client_id),
+        [] => Err(format!("Not capturing a client with id '{}'", id)),
+        _ => Err(format!(
+            "'{}' matches {} captured clients",
+            id,
+            matching.len()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Frame, protocol::ServerMessage, space::DisconnectReason};
+
+    use crate::capture::Direction;
+    use crate::client_entry::ClientEntry;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(ConsoleCommand::parse(" docs "), Ok(ConsoleCommand::Docs));
+        assert_eq!(
+            ConsoleCommand::parse("latency"),
+            Ok(ConsoleCommand::Latency)
+        );
+        assert_eq!(
+            ConsoleCommand::parse("loglevel INFO"),
+            Ok(ConsoleCommand::LogLevel(Some(LogLevel::Info)))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("loglevel"),
+            Ok(ConsoleCommand::LogLevel(None))
+        );
+        assert!(ConsoleCommand::parse("loglevel loud").is_err());
+        assert!(ConsoleCommand::parse("kick").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("dump 3f2a"),
+            Ok(ConsoleCommand::Dump("3f2a".to_string()))
+        );
+        assert!(ConsoleCommand::parse("capture").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("stats notes.txt"),
+            Ok(ConsoleCommand::Stats("notes.txt".to_string()))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("invite docs-team grace"),
+            Ok(ConsoleCommand::Invite(
+                "docs-team".to_string(),
+                "grace".to_string()
+            ))
+        );
+        assert!(ConsoleCommand::parse("uninvite docs-team").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("checkpoint notes.txt First  draft"),
+            Ok(ConsoleCommand::Checkpoint(
+                "notes.txt".to_string(),
+                "First draft".to_string()
+            ))
+        );
+        assert!(ConsoleCommand::parse("checkpoint notes.txt").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("appendonly notes.txt 0..4 9..9"),
+            Ok(ConsoleCommand::AppendOnly(
+                "notes.txt".to_string(),
+                vec![(0, 4), (9, 9)]
+            ))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("appendonly notes.txt"),
+            Ok(ConsoleCommand::AppendOnly(
+                "notes.txt".to_string(),
+                Vec::new()
+            ))
+        );
+        assert!(ConsoleCommand::parse("appendonly notes.txt 4-9").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("freeze docs-team * 30 5"),
+            Ok(ConsoleCommand::Freeze("docs-team".to_string(), None, 30, 5))
+        );
+        assert_eq!(
+            ConsoleCommand::parse("unfreeze docs-team notes.txt"),
+            Ok(ConsoleCommand::Unfreeze(
+                "docs-team".to_string(),
+                Some("notes.txt".to_string())
+            ))
+        );
+        assert!(ConsoleCommand::parse("freeze docs-team * soon").is_err());
+        assert_eq!(
+            ConsoleCommand::parse(" notice  * Maintenance in  5 minutes: save\twork\n"),
+            Ok(ConsoleCommand::Notice(
+                None,
+                "Maintenance in  5 minutes: save\twork".to_string()
+            ))
+        );
+        assert!(ConsoleCommand::parse("notice docs-team").is_err());
+        assert_eq!(
+            ConsoleCommand::parse("guest docs-team notes.txt 15"),
+            Ok(ConsoleCommand::Guest(
+                "docs-team".to_string(),
+                "notes.txt".to_string(),
+                15,
+                false
+            ))
+        );
+        assert!(ConsoleCommand::parse("guest docs-team notes.txt 15 admin").is_err());
+        assert!(ConsoleCommand::parse("status now").is_err());
+        assert!(ConsoleCommand::parse("stats a.txt b.txt").is_err());
+        assert!(ConsoleCommand::parse("reboot").is_err());
+    }
+
+    #[test]
+    fn test_kick_by_id_prefix() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(4);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+        let prefix = client_id.to_string()[..8].to_string();
+        assert!(execute(&state, &ConsoleCommand::Clients).starts_with(&client_id.to_string()));
+        execute(&state, &ConsoleCommand::Kick(prefix.clone()));
+        assert_eq!(state.client_count(), 0);
+        match ServerMessage::decode_bytes(&rx.recv().unwrap().payload) {
+            Ok(ServerMessage::Disconnect(disconnect)) => {
+                assert_eq!(disconnect.reason_code(), DisconnectReason::Kicked)
+            }
+            _ => panic!("expected Disconnect"),
+        }
+        assert!(execute(&state, &ConsoleCommand::Kick(prefix)).starts_with("No client"));
+    }
+
+    #[test]
+    fn test_notices_reach_their_workspace() {
+        let state = ServerState::new();
+        let token = state.add_member("docs-team", "grace").unwrap();
+        let connect = || {
+            let (tx, rx) = crossbeam::channel::bounded(4);
+            let client_id = Uuid::new_v4();
+            state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+            (client_id, rx)
+        };
+        let (grace, grace_rx) = connect();
+        state.join_workspace(grace, "docs-team", &token).unwrap();
+        let (_, other_rx) = connect();
+        while grace_rx.try_recv().is_ok() {}
+
+        let notice = |workspace: Option<&str>| {
+            let workspace = workspace.map(str::to_string);
+            execute(
+                &state,
+                &ConsoleCommand::Notice(workspace, "Back soon".to_string()),
+            )
+        };
+        assert_eq!(
+            notice(Some("docs-team")),
+            "Sent the notice to 1 connection(s)"
+        );
+        assert!(other_rx.try_recv().is_err());
+        match ServerMessage::decode_bytes(&grace_rx.recv().unwrap().payload) {
+            Ok(ServerMessage::Notice(notice)) => {
+                assert_eq!(
+                    (notice.message.as_str(), notice.workspace.as_str()),
+                    ("Back soon", "docs-team")
+                )
+            }
+            _ => panic!("expected Notice"),
+        }
+        assert_eq!(notice(None), "Sent the notice to 2 connection(s)");
+    }
+
+    #[test]
+    fn test_checkpoint_shows_in_history() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, _rx) = crossbeam::channel::bounded(4);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        state.open_document(client_id, "notes.txt").unwrap();
+
+        let checkpoint = |label: &str| {
+            execute(
+                &state,
+                &ConsoleCommand::Checkpoint("notes.txt".to_string(), label.to_string()),
+            )
+        };
+        assert_eq!(
+            checkpoint("First draft"),
+            "'notes.txt' v0: squashed 0 version(s) into 'First draft'"
+        );
+        assert!(checkpoint(" ").contains("needs a label"));
+        let history = execute(&state, &ConsoleCommand::History("notes.txt".to_string()));
+        assert!(history.starts_with("v0 'First draft', 0 version(s) squashed"));
+        assert!(history.ends_with("v0..v0 in the op log"));
+    }
+
+    #[test]
+    fn test_capture_outlives_the_connection() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, _rx) = crossbeam::channel::bounded(4);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+        let id = client_id.to_string();
+        assert!(execute(&state, &ConsoleCommand::Capture(id.clone())).starts_with("Capturing"));
+        let ping = Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(1)));
+        CAPTURES.record(client_id, Direction::Out, &ping);
+        state.kick_client(client_id);
+
+        let dump = execute(&state, &ConsoleCommand::Dump(id[..8].to_string()));
+        assert!(dump.contains("out Ping"), "{}", dump);
+        execute(&state, &ConsoleCommand::Uncapture(id.clone()));
+        assert!(execute(&state, &ConsoleCommand::Dump(id)).starts_with("Not capturing"));
+    }
+}
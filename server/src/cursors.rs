@@ -0,0 +1,368 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::PathBuf,
+};
+
+use crate::{
+    autosave,
+    events::{escape, unescape},
+    log::error,
+};
+
+/// Clients, by the id cursors are kept under, and their workspaces.
+pub type ClientKeys = HashSet<(String, String)>;
+
+/// How far a client has applied a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub doc_id: String,
+    /// The server run `version` counts in: versions start over when the
+    /// server restarts, so a cursor from an earlier run is not resumed from.
+    pub epoch: String,
+    pub version: u64,
+    /// When the client last acknowledged anything in the document.
+    pub seen_ms: u64,
+}
+
+/// The version of a persisted document whose content was last saved, so
+/// cursors at it can be carried over to the next run once that content is
+/// loaded back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedMark {
+    pub epoch: String,
+    pub version: u64,
+    /// SHA-256 of the content saved.
+    pub hash: String,
+}
+
+/// Each client's latest acknowledgement of each document it has open, by
+/// the id the client gave in its Hello, workspace and path, and where each
+/// persisted document was saved. Saved to `file`, if there is one, as a
+/// whole by `save`: one
+/// `cursor<TAB>client<TAB>workspace<TAB>path<TAB>doc_id<TAB>epoch<TAB>version<TAB>seen_ms`
+/// line per cursor and one `saved<TAB>workspace<TAB>path<TAB>epoch<TAB>version<TAB>hash`
+/// line per document, with tabs, newlines and backslashes in the text escaped.
+#[derive(Debug, Default)]
+pub struct AckCursors {
+    cursors: BTreeMap<(String, String, String), Cursor>,
+    /// By workspace and path.
+    marks: BTreeMap<(String, String), SavedMark>,
+    file: Option<PathBuf>,
+    /// Set when the cursors changed since they were last saved.
+    dirty: bool,
+}
+
+impl AckCursors {
+    /// The cursors saved in `file`; none if it does not exist yet.
+    pub fn load(file: PathBuf) -> io::Result<Self> {
+        let mut cursors = Self::default();
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        for (number, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", file.display(), number + 1, what),
+                )
+            };
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            match &fields[..] {
+                [
+                    kind,
+                    client,
+                    workspace,
+                    path,
+                    doc_id,
+                    epoch,
+                    version,
+                    seen_ms,
+                ] if kind == "cursor" => {
+                    let cursor = Cursor {
+                        doc_id: doc_id.clone(),
+                        epoch: epoch.clone(),
+                        version: version.parse().map_err(|_| invalid("invalid version"))?,
+                        seen_ms: seen_ms.parse().map_err(|_| invalid("invalid time"))?,
+                    };
+                    cursors
+                        .cursors
+                        .insert((client.clone(), workspace.clone(), path.clone()), cursor);
+                }
+                [kind, workspace, path, epoch, version, hash] if kind == "saved" => {
+                    let mark = SavedMark {
+                        epoch: epoch.clone(),
+                        version: version.parse().map_err(|_| invalid("invalid version"))?,
+                        hash: hash.clone(),
+                    };
+                    cursors
+                        .marks
+                        .insert((workspace.clone(), path.clone()), mark);
+                }
+                _ => return Err(invalid("expected a cursor or saved line")),
+            }
+        }
+        cursors.file = Some(file);
+        Ok(cursors)
+    }
+
+    /// Records that `client` has applied the document at `path` up to
+    /// `cursor.version`. A cursor never moves back within a document and
+    /// run, but one for another document or run replaces it.
+    pub fn ack(&mut self, client: &str, workspace: &str, path: &str, cursor: Cursor) {
+        let key = (client.to_string(), workspace.to_string(), path.to_string());
+        match self.cursors.get_mut(&key) {
+            Some(known) if known.doc_id == cursor.doc_id && known.epoch == cursor.epoch => {
+                known.version = known.version.max(cursor.version);
+                known.seen_ms = known.seen_ms.max(cursor.seen_ms);
+            }
+            _ => {
+                self.cursors.insert(key, cursor);
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub fn get(&self, client: &str, workspace: &str, path: &str) -> Option<&Cursor> {
+        self.cursors
+            .get(&(client.to_string(), workspace.to_string(), path.to_string()))
+    }
+
+    /// The oldest version of `doc_id`, in run `epoch`, that a client in
+    /// `present` or seen since `since_ms` has yet to apply past; `None` if
+    /// there is none.
+    pub fn floor(
+        &self,
+        doc_id: &str,
+        epoch: &str,
+        since_ms: u64,
+        present: &ClientKeys,
+    ) -> Option<u64> {
+        self.cursors
+            .iter()
+            .filter(|((client, workspace, _), cursor)| {
+                cursor.doc_id == doc_id
+                    && cursor.epoch == epoch
+                    && (cursor.seen_ms >= since_ms
+                        || present.contains(&(client.clone(), workspace.clone())))
+            })
+            .map(|(_, cursor)| cursor.version)
+            .min()
+    }
+
+    /// Forgets the cursors of clients neither in `present` nor seen since
+    /// `since_ms`. Returns the number forgotten.
+    pub fn prune(&mut self, since_ms: u64, present: &ClientKeys) -> usize {
+        let before = self.cursors.len();
+        self.cursors.retain(|(client, workspace, _), cursor| {
+            cursor.seen_ms >= since_ms || present.contains(&(client.clone(), workspace.clone()))
+        });
+        let pruned = before - self.cursors.len();
+        if pruned > 0 {
+            self.dirty = true;
+        }
+        pruned
+    }
+
+    /// Forgets every cursor of `client` in `workspace`. Returns the number
+    /// forgotten.
+    pub fn forget(&mut self, client: &str, workspace: &str) -> usize {
+        let before = self.cursors.len();
+        self.cursors.retain(|(known, known_workspace, _), _| {
+            known != client || known_workspace != workspace
+        });
+        let forgotten = before - self.cursors.len();
+        if forgotten > 0 {
+            self.dirty = true;
+        }
+        forgotten
+    }
+
+    pub fn len(&self) -> usize {
+        self.cursors.len()
+    }
+
+    /// Records that the document at `path` was saved as `mark`.
+    pub fn mark_saved(&mut self, workspace: &str, path: &str, mark: SavedMark) {
+        let key = (workspace.to_string(), path.to_string());
+        if self.marks.get(&key) != Some(&mark) {
+            self.marks.insert(key, mark);
+            self.dirty = true;
+        }
+    }
+
+    /// Moves the cursors of the document at `path` that were at its saved
+    /// version onto `to`, the same content loaded back in a new run, if it
+    /// hashes to `hash`. Cursors at any other version of the old run stay
+    /// behind and are never resumed from. Returns the number carried over.
+    pub fn carry_over(&mut self, workspace: &str, path: &str, hash: &str, to: &Cursor) -> usize {
+        let key = (workspace.to_string(), path.to_string());
+        let Some(mark) = self.marks.get(&key).filter(|mark| mark.hash == hash) else {
+            return 0;
+        };
+        let mut carried = 0;
+        for ((_, known_workspace, known_path), cursor) in &mut self.cursors {
+            if (known_workspace, known_path) == (&key.0, &key.1)
+                && cursor.epoch == mark.epoch
+                && cursor.version == mark.version
+            {
+                cursor.doc_id = to.doc_id.clone();
+                cursor.epoch = to.epoch.clone();
+                cursor.version = to.version;
+                carried += 1;
+            }
+        }
+        let mark = SavedMark {
+            epoch: to.epoch.clone(),
+            version: to.version,
+            hash: hash.to_string(),
+        };
+        self.marks.insert(key, mark);
+        self.dirty = true;
+        carried
+    }
+
+    /// Writes the cursors to the file, if they changed. A failed write is
+    /// logged and tried again at the next save.
+    pub fn save(&mut self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if !self.dirty {
+            return;
+        }
+        let mut text = String::new();
+        for ((client, workspace, path), cursor) in &self.cursors {
+            text.push_str(&format!(
+                "cursor\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                escape(client),
+                escape(workspace),
+                escape(path),
+                escape(&cursor.doc_id),
+                escape(&cursor.epoch),
+                cursor.version,
+                cursor.seen_ms
+            ));
+        }
+        for ((workspace, path), mark) in &self.marks {
+            text.push_str(&format!(
+                "saved\t{}\t{}\t{}\t{}\t{}\n",
+                escape(workspace),
+                escape(path),
+                escape(&mark.epoch),
+                mark.version,
+                mark.hash
+            ));
+        }
+        match autosave::write_atomically(file, text.as_bytes()) {
+            Ok(()) => self.dirty = false,
+            Err(e) => error!("[Cursors] Cannot save to {}: {}", file.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn cursor(doc_id: &str, version: u64, seen_ms: u64) -> Cursor {
+        Cursor {
+            doc_id: doc_id.to_string(),
+            epoch: "run-1".to_string(),
+            version,
+            seen_ms,
+        }
+    }
+
+    #[test]
+    fn test_cursors_advance_and_survive_a_reload() {
+        let file = std::env::temp_dir().join(format!("dist-space-cursors-{}", Uuid::new_v4()));
+        let mut cursors = AckCursors::load(file.clone()).unwrap();
+        cursors.ack("ada", "team-a", "notes\t1.md", cursor("d1", 7, 10));
+        cursors.ack("ada", "team-a", "notes\t1.md", cursor("d1", 5, 20));
+        cursors.ack("grace", "team-a", "notes\t1.md", cursor("d1", 3, 5));
+        cursors.ack("grace", "team-a", "todo.md", cursor("d2", 9, 30));
+        // The older ack did not move ada's cursor back
+        assert_eq!(
+            cursors.get("ada", "team-a", "notes\t1.md"),
+            Some(&cursor("d1", 7, 20))
+        );
+        let none = ClientKeys::new();
+        assert_eq!(cursors.floor("d1", "run-1", 0, &none), Some(3));
+        assert_eq!(cursors.floor("d1", "run-1", 6, &none), Some(7));
+        let grace = ClientKeys::from([("grace".to_string(), "team-a".to_string())]);
+        assert_eq!(cursors.floor("d1", "run-1", 6, &grace), Some(3));
+        assert_eq!(cursors.floor("d1", "run-2", 0, &none), None);
+        cursors.save();
+
+        let mut cursors = AckCursors::load(file.clone()).unwrap();
+        assert_eq!(cursors.len(), 3);
+        assert_eq!(
+            cursors.get("grace", "team-a", "todo.md"),
+            Some(&cursor("d2", 9, 30))
+        );
+        // A document recreated under the path starts its cursor over
+        cursors.ack("grace", "team-a", "todo.md", cursor("d3", 1, 40));
+        assert_eq!(
+            cursors.get("grace", "team-a", "todo.md").unwrap().version,
+            1
+        );
+
+        assert_eq!(cursors.prune(15, &none), 1);
+        cursors.save();
+        let mut cursors = AckCursors::load(file.clone()).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(cursors.len(), 2);
+        assert!(cursors.get("grace", "team-a", "notes\t1.md").is_none());
+        assert_eq!(cursors.forget("ada", "team-a"), 1);
+        assert_eq!(cursors.len(), 1);
+    }
+
+    #[test]
+    fn test_cursors_at_the_saved_version_carry_over_to_the_next_run() {
+        let file = std::env::temp_dir().join(format!("dist-space-cursors-{}", Uuid::new_v4()));
+        let mut cursors = AckCursors::load(file.clone()).unwrap();
+        cursors.ack("ada", "default", "main.txt", cursor("d1", 7, 10));
+        cursors.ack("grace", "default", "main.txt", cursor("d1", 9, 10));
+        let mark = SavedMark {
+            epoch: "run-1".to_string(),
+            version: 7,
+            hash: "h7".to_string(),
+        };
+        cursors.mark_saved("default", "main.txt", mark);
+        cursors.save();
+
+        let mut cursors = AckCursors::load(file.clone()).unwrap();
+        fs::remove_file(&file).unwrap();
+        let next = Cursor {
+            doc_id: "d2".to_string(),
+            epoch: "run-2".to_string(),
+            version: 0,
+            seen_ms: 0,
+        };
+        // Content that changed since the save carries nothing over
+        assert_eq!(cursors.carry_over("default", "main.txt", "h8", &next), 0);
+        assert_eq!(cursors.carry_over("default", "main.txt", "h7", &next), 1);
+        let ada = cursors.get("ada", "default", "main.txt").unwrap();
+        assert_eq!(
+            (
+                ada.doc_id.as_str(),
+                ada.epoch.as_str(),
+                ada.version,
+                ada.seen_ms
+            ),
+            ("d2", "run-2", 0, 10)
+        );
+        // Grace had applied edits that were never saved
+        assert_eq!(
+            cursors.get("grace", "default", "main.txt").unwrap().epoch,
+            "run-1"
+        );
+    }
+}
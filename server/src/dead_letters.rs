@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use common::{
+    Frame,
+    clock::unix_time_ms,
+    protocol::{ServerMessage, message_type_name},
+};
+use uuid::Uuid;
+
+/// Dropped frames kept for the console's `deadletters`.
+pub const DEAD_LETTER_CAPACITY: usize = 256;
+
+/// Why a frame never reached its client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The writer's queue was full.
+    QueueFull,
+    /// Too many frames were already waiting for flow-control credit.
+    CreditExhausted,
+    /// The writer had already exited.
+    WriterGone,
+    /// Writing failed; the frame was being written or still queued.
+    WriteFailed,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::QueueFull => "queue full",
+            DropReason::CreditExhausted => "credit exhausted",
+            DropReason::WriterGone => "writer gone",
+            DropReason::WriteFailed => "write failed",
+        }
+    }
+}
+
+/// A frame the server gave up on, with enough of its content to tell which
+/// document state the client missed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub dropped_at_ms: u64,
+    pub client_id: Uuid,
+    /// Of the message inside, for sequenced frames.
+    pub type_id: u8,
+    pub doc_id: Option<String>,
+    /// The document version the frame carried: the op's server_version, or
+    /// the version of a sync or lock update.
+    pub server_version: Option<u64>,
+    pub reason: DropReason,
+}
+
+impl DeadLetter {
+    pub fn new(client_id: Uuid, frame: &Frame, reason: DropReason) -> Self {
+        let mut dead_letter = DeadLetter {
+            dropped_at_ms: unix_time_ms(),
+            client_id,
+            type_id: frame.type_id,
+            doc_id: None,
+            server_version: None,
+            reason,
+        };
+        // Only decoded here, off the path of frames that do get sent
+        let mut message = match ServerMessage::decode_bytes(&frame.payload) {
+            Ok(message) => message,
+            Err(_) => return dead_letter,
+        };
+        if let ServerMessage::Sequenced(_, inner) = message {
+            message = *inner;
+        }
+        dead_letter.type_id = message.get_message_type_id();
+        let (doc_id, server_version) = match message {
+            ServerMessage::Operation(op) => (op.doc_id, op.server_version),
+            ServerMessage::OperationBatch(batch) => match batch.operations.last() {
+                Some(op) => (op.doc_id.clone(), op.server_version),
+                None => return dead_letter,
+            },
+            ServerMessage::SyncDocument(sync) => (sync.doc_id, sync.version),
+            ServerMessage::RangeLocks(locks) => (locks.doc_id, locks.version),
+            _ => return dead_letter,
+        };
+        dead_letter.doc_id = Some(doc_id);
+        dead_letter.server_version = Some(server_version);
+        dead_letter
+    }
+}
+
+/// Dropped frames since startup, across every client.
+pub static DEAD_LETTERS: DeadLetters = DeadLetters::new(DEAD_LETTER_CAPACITY);
+
+/// The most recent dropped frames, oldest first, and a count of them all.
+pub struct DeadLetters {
+    capacity: usize,
+    recent: Mutex<VecDeque<DeadLetter>>,
+    total: AtomicU64,
+}
+
+impl DeadLetters {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            recent: Mutex::new(VecDeque::new()),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, client_id: Uuid, frame: &Frame, reason: DropReason) {
+        let dead_letter = DeadLetter::new(client_id, frame, reason);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        let mut recent = match self.recent.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(dead_letter);
+    }
+
+    /// Frames dropped since startup, including those no longer kept.
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// The kept dead letters, oldest first.
+    pub fn recent(&self) -> Vec<DeadLetter> {
+        match self.recent.lock() {
+            Ok(guard) => guard.iter().cloned().collect(),
+            Err(poisoned) => poisoned.into_inner().iter().cloned().collect(),
+        }
+    }
+
+    /// One line per kept dead letter, oldest first.
+    pub fn report(&self) -> String {
+        let recent = self.recent();
+        if recent.is_empty() {
+            return "no dropped frames".to_string();
+        }
+        let now_ms = unix_time_ms();
+        let mut lines: Vec<String> = recent
+            .iter()
+            .map(|dead_letter| {
+                format!(
+                    "{}ms ago {} {} doc={} version={} ({})",
+                    now_ms.saturating_sub(dead_letter.dropped_at_ms),
+                    dead_letter.client_id,
+                    message_type_name(dead_letter.type_id),
+                    dead_letter.doc_id.as_deref().unwrap_or("-"),
+                    dead_letter
+                        .server_version
+                        .map_or("-".to_string(), |version| version.to_string()),
+                    dead_letter.reason.as_str()
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{} kept of {} dropped since startup",
+            recent.len(),
+            self.total()
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{
+        protocol::{MSG_TYPE_PING, MSG_TYPE_SYNC_DOCUMENT, encode_sequenced},
+        space::SyncDocumentProto,
+    };
+
+    #[test]
+    fn test_dead_letter_reads_sequenced_sync() {
+        let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+            doc_id: "doc".to_string(),
+            version: 7,
+            ..Default::default()
+        });
+        let frame = Frame::new_arc(encode_sequenced(3, &ServerMessage::encode(&sync)));
+        let dead_letter = DeadLetter::new(Uuid::nil(), &frame, DropReason::QueueFull);
+        assert_eq!(dead_letter.type_id, MSG_TYPE_SYNC_DOCUMENT);
+        assert_eq!(dead_letter.doc_id.as_deref(), Some("doc"));
+        assert_eq!(dead_letter.server_version, Some(7));
+    }
+
+    #[test]
+    fn test_only_the_latest_are_kept() {
+        let dead_letters = DeadLetters::new(2);
+        let ping = |n| Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(n)));
+        for n in 0..3 {
+            dead_letters.record(Uuid::nil(), &ping(n), DropReason::WriterGone);
+        }
+        let recent = dead_letters.recent();
+        assert_eq!(recent.len(), 2);
+        assert!(recent.iter().all(|dead_letter| {
+            dead_letter.type_id == MSG_TYPE_PING && dead_letter.doc_id.is_none()
+        }));
+        assert_eq!(dead_letters.total(), 3);
+    }
+}
@@ -0,0 +1,1156 @@
+use std::{sync::Arc, thread};
+
+use common::{
+    Frame,
+    clock::{self, ClockSample},
+    protocol::ServerMessage,
+    space::{DisconnectReason, ErrorCode, ErrorProto, TimeSyncProto},
+};
+use crossbeam::channel::{Receiver, Sender};
+use uuid::Uuid;
+
+use crate::broadcaster::BroadcastFn;
+use crate::capabilities::Capabilities;
+use crate::compatibility;
+use crate::error::ServerError;
+use crate::log::{debug, error, info, trace};
+use crate::state::ServerState;
+use crate::validation::Rejection;
+use crate::worker;
+
+/// Frames waiting on each decode thread. A full queue blocks the reader that
+/// submitted, which stops it reading from its (too fast) client.
+pub const DECODE_QUEUE_CAPACITY: usize = 64;
+
+/// A frame read from `client_id`, waiting to be decoded and dispatched.
+struct DecodeJob {
+    client_id: Uuid,
+    frame: Arc<Frame>,
+}
+
+/// Decodes and dispatches client frames off the reader threads, so a reader
+/// only does framed I/O and a slow decode never delays the next read.
+///
+/// Each connection is pinned to one decode thread, which keeps its frames
+/// in the order they were read.
+pub struct DecodePool {
+    queues: Vec<Sender<DecodeJob>>,
+}
+
+impl DecodePool {
+    /// Starts `size` decode threads (at least one).
+    pub fn spawn(size: usize, state: Arc<ServerState>, broadcast_fn: BroadcastFn) -> Self {
+        let queues = (0..size.max(1))
+            .map(|_| {
+                let (tx, rx) = crossbeam::channel::bounded(DECODE_QUEUE_CAPACITY);
+                let state = Arc::clone(&state);
+                thread::spawn(move || run_decoder(state, rx, broadcast_fn));
+                tx
+            })
+            .collect::<Vec<_>>();
+        info!("[Decoder] Started {} decode thread(s)", queues.len());
+        Self { queues }
+    }
+
+    /// Queues a frame for decoding. Returns false if its decode thread is gone.
+    pub fn submit(&self, client_id: Uuid, frame: Arc<Frame>) -> bool {
+        let index = (client_id.as_u128() % self.queues.len() as u128) as usize;
+        self.queues[index]
+            .send(DecodeJob { client_id, frame })
+            .is_ok()
+    }
+}
+
+fn run_decoder(state: Arc<ServerState>, jobs: Receiver<DecodeJob>, broadcast_fn: BroadcastFn) {
+    for job in jobs {
+        dispatch(&state, job.client_id, &job.frame, broadcast_fn);
+    }
+}
+
+/// Decodes one frame and acts on it.
+fn dispatch(state: &Arc<ServerState>, client_id: Uuid, frame: &Frame, broadcast_fn: BroadcastFn) {
+    let message = ServerMessage::decode_bytes(&frame.payload);
+    if let Ok(message) = &message
+        && is_user_input(message)
+    {
+        state.note_input(client_id);
+    }
+    match message {
+        Ok(ServerMessage::Operation(op)) => {
+            trace!("[{}] Received Operation from client", client_id);
+
+            worker::submit(state, client_id, op, broadcast_fn);
+        }
+        Ok(ServerMessage::SyncDocument(_)) => {
+            // Server doesn't expect SyncDocument from clients
+            info!("[{}] Ignoring SyncDocument from client", client_id);
+        }
+        Ok(ServerMessage::OperationBatch(batch)) => {
+            debug!(
+                "[{}] Received batch of {} operation(s)",
+                client_id,
+                batch.operations.len()
+            );
+            worker::process_batch(state, client_id, batch, broadcast_fn);
+        }
+        Ok(ServerMessage::Disconnect(_)) => {
+            // The connection closing is what actually ends the session
+            info!("[{}] Ignoring Disconnect from client", client_id);
+        }
+        Ok(ServerMessage::Error(error)) => {
+            info!(
+                "[{}] Ignoring Error from client: {}",
+                client_id, error.message
+            );
+        }
+        Ok(ServerMessage::Ping(seq)) => {
+            // Client sent a ping (unusual but handle it)
+            trace!("[{}] Received Ping({}) from client", client_id, seq);
+            let pong = ServerMessage::Pong(seq);
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&pong)));
+        }
+        Ok(ServerMessage::Pong(_)) => {
+            // Routed by type ID in the reader, before decoding
+        }
+        Ok(ServerMessage::Hello(hello)) => {
+            info!(
+                "[{}] Hello from '{}' (doc: '{}', protocol {}, {})",
+                client_id,
+                hello.display_name,
+                hello.doc_path,
+                hello.protocol_version,
+                match hello.client_version.as_str() {
+                    "" => "unknown version",
+                    version => version,
+                }
+            );
+            if let Some(diagnostics) = state.client_requirements().check(&hello) {
+                error!("[{}] Turned away: {}", client_id, diagnostics.message);
+                let message = diagnostics.message.clone();
+                let reply = ServerMessage::Diagnostics(diagnostics);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                state.notify_disconnect(client_id, DisconnectReason::Incompatible, &message);
+                state.remove_client(client_id);
+                return;
+            }
+            // Before joining, which picks up a session the client left
+            if !hello.client_id.is_empty() {
+                state.set_client_hello_id(client_id, hello.client_id);
+            }
+            if let Err(e) = state.join_workspace(client_id, &hello.workspace, &hello.auth_token) {
+                error!("[{}] Cannot join workspace: {}", client_id, e);
+                let reply = server_error(&e);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                let reason = e
+                    .disconnect_reason()
+                    .unwrap_or(DisconnectReason::ServerFull);
+                state.notify_disconnect(client_id, reason, &e.to_string());
+                state.remove_client(client_id);
+                return;
+            }
+            if hello.client_time_ms > 0 {
+                let skew = hello.client_time_ms as i64 - clock::unix_time_ms() as i64;
+                info!(
+                    "[{}] Client clock is {}ms off the server's",
+                    client_id, skew
+                );
+            }
+            if hello.read_only {
+                info!("[{}] Connected as a read-only viewer", client_id);
+                state.set_client_read_only(client_id);
+            }
+            if !hello.display_name.is_empty() {
+                state.set_client_name(client_id, hello.display_name);
+            }
+            // Answered ahead of the numbering, and of the sync
+            if let Some(capabilities) = &hello.capabilities {
+                state.set_client_capabilities(client_id, Capabilities::from_proto(capabilities));
+                let reply = ServerMessage::Capabilities(Capabilities::server());
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+            // Before the sync, so the client's first numbered frame is that sync
+            if hello.sequenced {
+                state.set_client_sequenced(client_id);
+            }
+            open_document(state, client_id, &hello.doc_path, false);
+        }
+        Ok(ServerMessage::OpenDocument(open)) => {
+            open_document(state, client_id, &open.path, open.resume);
+        }
+        Ok(ServerMessage::CloseDocument(close)) => {
+            if state.close_document(client_id, &close.doc_id) {
+                info!("[{}] Closed document {}", client_id, close.doc_id);
+            }
+        }
+        Ok(ServerMessage::ExportDocument(export)) => {
+            let reply = match state.export_document(client_id, &export.doc_id) {
+                Ok(archive) => {
+                    info!(
+                        "[{}] Exporting '{}' at v{} with {} ops",
+                        client_id,
+                        archive.path,
+                        archive.version,
+                        archive.operations.len()
+                    );
+                    ServerMessage::DocumentArchive(archive)
+                }
+                Err(rejection) => {
+                    error!(
+                        "[{}] Cannot export {}: {}",
+                        client_id, export.doc_id, rejection
+                    );
+                    error(rejection.code(), rejection.to_string())
+                }
+            };
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::ExportRequest(request)) => match state.export_copy(client_id, &request) {
+            Ok(chunks) => {
+                info!(
+                    "[{}] Exporting {} in {} chunk(s)",
+                    client_id,
+                    request.doc_id,
+                    chunks.len()
+                );
+                for chunk in chunks {
+                    let frame =
+                        Frame::new_arc(ServerMessage::encode(&ServerMessage::ExportChunk(chunk)));
+                    if !state.send_to_client(client_id, frame) {
+                        error!("[{}] Failed to queue an export chunk", client_id);
+                        break;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("[{}] Cannot export {}: {}", client_id, request.doc_id, e);
+                let reply = server_error(&e);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+        },
+        Ok(ServerMessage::ExportChunk(_)) => {
+            info!("[{}] Ignoring ExportChunk from client", client_id);
+        }
+        Ok(ServerMessage::UploadAttachment(chunk)) => {
+            match state.upload_attachment(client_id, &chunk) {
+                Ok(None) => {}
+                Ok(Some(attachment)) => {
+                    let reply = ServerMessage::Attachment(attachment);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+                Err(e) => {
+                    error!("[{}] Cannot attach to {}: {}", client_id, chunk.doc_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::FetchAttachment(request)) => {
+            match state.fetch_attachment(client_id, &request) {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        let frame = Frame::new_arc(ServerMessage::encode(
+                            &ServerMessage::AttachmentChunk(chunk),
+                        ));
+                        if !state.send_to_client(client_id, frame) {
+                            error!("[{}] Failed to queue an attachment chunk", client_id);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "[{}] Cannot fetch attachment {}: {}",
+                        client_id, request.attachment_id, e
+                    );
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::SetViewport(request)) => match state.set_viewport(client_id, &request) {
+            Ok(reached) => trace!(
+                "[{}] Viewport {}+{} of {} relayed to {} follower(s)",
+                client_id, request.first_line, request.line_count, request.doc_id, reached
+            ),
+            Err(e) => {
+                error!("[{}] Cannot share viewport: {}", client_id, e);
+                let reply = server_error(&e);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+        },
+        Ok(ServerMessage::Follow(request)) => match state.follow(client_id, &request) {
+            Ok(None) => {}
+            Ok(Some(viewport)) => {
+                let reply = ServerMessage::Viewport(viewport);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+            Err(e) => {
+                error!("[{}] Cannot follow {}: {}", client_id, request.client_id, e);
+                let reply = server_error(&e);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+        },
+        Ok(ServerMessage::Viewport(_)) => {
+            info!("[{}] Ignoring Viewport from client", client_id);
+        }
+        Ok(ServerMessage::Attachment(_) | ServerMessage::AttachmentChunk(_)) => {
+            info!("[{}] Ignoring attachment reply from client", client_id);
+        }
+        Ok(ServerMessage::DocumentArchive(archive)) => {
+            let path = archive.path.clone();
+            if state.is_read_only(client_id) {
+                error!("[{}] Cannot import '{}': read-only", client_id, path);
+                let rejection = Rejection::ReadOnly;
+                let reply = error(rejection.code(), rejection.to_string());
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                return;
+            }
+            match state.import_document(client_id, archive) {
+                Ok(sync) => {
+                    state.send_to_client(client_id, sync);
+                }
+                Err(e) => {
+                    error!("[{}] Cannot import '{}': {}", client_id, path, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::CreateFromTemplate(request)) => {
+            if state.is_read_only(client_id) {
+                error!(
+                    "[{}] Cannot create '{}': read-only",
+                    client_id, request.path
+                );
+                let rejection = Rejection::ReadOnly;
+                let reply = error(rejection.code(), rejection.to_string());
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                return;
+            }
+            match state.create_from_template(client_id, &request) {
+                Ok(sync) => {
+                    state.send_to_client(client_id, sync);
+                }
+                Err(e) => {
+                    error!(
+                        "[{}] Cannot create '{}' from template '{}': {}",
+                        client_id, request.path, request.template, e
+                    );
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::LockRange(lock)) => {
+            // Subscribers, this client included, learn of the lock from the
+            // RangeLocks broadcast
+            match state.lock_range(client_id, &lock) {
+                Ok(lock_id) => info!(
+                    "[{}] Locked {}..{} of {} as #{}",
+                    client_id, lock.start, lock.end, lock.doc_id, lock_id
+                ),
+                Err(rejection) => {
+                    error!("[{}] Cannot lock: {}", client_id, rejection);
+                    let reply = error(rejection.code(), rejection.to_string());
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::UnlockRange(unlock)) => {
+            if state.unlock_range(client_id, &unlock) {
+                info!(
+                    "[{}] Unlocked #{} of {}",
+                    client_id, unlock.lock_id, unlock.doc_id
+                );
+            }
+        }
+        Ok(ServerMessage::RangeLocks(_)) => {
+            info!("[{}] Ignoring RangeLocks from client", client_id);
+        }
+        Ok(ServerMessage::SetPresence(presence)) => {
+            state.set_presence(client_id, presence.away);
+        }
+        Ok(ServerMessage::Presence(_)) => {
+            info!("[{}] Ignoring Presence from client", client_id);
+        }
+        Ok(ServerMessage::SetOverlays(set)) => {
+            // Subscribers, this client included, learn of them from the
+            // Overlays broadcast
+            match state.set_overlays(client_id, &set) {
+                Ok(count) => debug!(
+                    "[{}] Set {} '{}' overlays on {}",
+                    client_id, count, set.kind, set.doc_id
+                ),
+                Err(e) => {
+                    error!("[{}] Cannot set overlays: {}", client_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::SetDocumentSettings(set)) => {
+            // Answered by the SyncDocument broadcast
+            match state.set_document_settings(client_id, &set) {
+                Ok(()) => info!("[{}] Changed the settings of {}", client_id, set.doc_id),
+                Err(e) => {
+                    error!("[{}] Cannot change settings: {}", client_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::Signal(signal)) => {
+            // Subscribers, this client included, get it back from the
+            // broadcast
+            match state.signal(client_id, &signal) {
+                Ok(()) => debug!(
+                    "[{}] Signalled {:?} on {}",
+                    client_id,
+                    signal.kind(),
+                    signal.doc_id
+                ),
+                Err(e) => {
+                    error!("[{}] Cannot signal: {}", client_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::TagVersion(request)) => {
+            // Subscribers, this client included, see the tag in the History
+            // broadcast
+            match state.tag_version(client_id, &request) {
+                Ok(tag) => info!(
+                    "[{}] Tagged v{} of {} '{}'",
+                    client_id, tag.version, request.doc_id, tag.name
+                ),
+                Err(e) => {
+                    error!("[{}] Cannot tag: {}", client_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::GetHistory(request)) => {
+            let reply = match state.history(client_id, &request) {
+                Ok(history) => ServerMessage::History(history),
+                Err(e) => {
+                    error!("[{}] Cannot send history: {}", client_id, e);
+                    server_error(&e)
+                }
+            };
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::History(_)) => {
+            info!("[{}] Ignoring History from client", client_id);
+        }
+        Ok(ServerMessage::Freeze(_)) => {
+            info!("[{}] Ignoring Freeze from client", client_id);
+        }
+        Ok(ServerMessage::Notice(_)) => {
+            info!("[{}] Ignoring Notice from client", client_id);
+        }
+        Ok(ServerMessage::TimeSync(sync)) => {
+            let server_receive_ms = clock::unix_time_ms();
+            // All zero until the client has measured a round trip
+            if sync.rtt_ms > 0 || sync.offset_ms != 0 {
+                trace!(
+                    "[{}] Round trip {}ms, clock {}ms behind the server's",
+                    client_id, sync.rtt_ms, sync.offset_ms
+                );
+                state.set_client_clock(
+                    client_id,
+                    ClockSample {
+                        offset_ms: sync.offset_ms,
+                        rtt_ms: sync.rtt_ms,
+                    },
+                );
+            }
+            let reply = ServerMessage::TimeSync(TimeSyncProto {
+                server_receive_ms,
+                server_send_ms: clock::unix_time_ms(),
+                ..sync
+            });
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::Propagation(propagation)) => {
+            if !state.record_propagation(client_id, &propagation.samples) {
+                debug!(
+                    "[{}] Dropping {} propagation sample(s) sent before a time sync",
+                    client_id,
+                    propagation.samples.len()
+                );
+            }
+        }
+        Ok(ServerMessage::Overlays(_)) => {
+            info!("[{}] Ignoring Overlays from client", client_id);
+        }
+        Ok(ServerMessage::Redirect(_)) => {
+            info!("[{}] Ignoring Redirect from client", client_id);
+        }
+        Ok(ServerMessage::Diagnostics(_)) => {
+            info!("[{}] Ignoring Diagnostics from client", client_id);
+        }
+        Ok(ServerMessage::WatchActivity(request)) => {
+            match state.watch_activity(client_id, request.watch) {
+                Ok(()) if request.watch => debug!("[{}] Watching the activity feed", client_id),
+                Ok(()) => debug!("[{}] Stopped watching the activity feed", client_id),
+                Err(e) => {
+                    error!("[{}] Cannot watch activity: {}", client_id, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::Activity(_)) => {
+            info!("[{}] Ignoring Activity from client", client_id);
+        }
+        Ok(ServerMessage::Ack(ack)) => {
+            if let Err(e) = state.acknowledge(client_id, &ack) {
+                error!("[{}] Cannot acknowledge {}: {}", client_id, ack.doc_id, e);
+                let reply = server_error(&e);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+        }
+        Ok(ServerMessage::Capabilities(_)) => {
+            info!("[{}] Ignoring Capabilities outside a Hello", client_id);
+        }
+        Ok(ServerMessage::InviteMember(invite)) => {
+            let reply = match state.invite_member(client_id, &invite.member) {
+                Ok(token) => {
+                    info!(
+                        "[{}] Invited '{}' into workspace '{}'",
+                        client_id, token.member, token.workspace
+                    );
+                    ServerMessage::MemberToken(token)
+                }
+                Err(e) => {
+                    error!("[{}] Cannot invite '{}': {}", client_id, invite.member, e);
+                    server_error(&e)
+                }
+            };
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::RemoveMember(remove)) => {
+            match state.remove_member(client_id, &remove.member) {
+                Ok(closed) => info!(
+                    "[{}] Removed '{}' and closed {} of their connection(s)",
+                    client_id, remove.member, closed
+                ),
+                Err(e) => {
+                    error!("[{}] Cannot remove '{}': {}", client_id, remove.member, e);
+                    let reply = server_error(&e);
+                    state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+                }
+            }
+        }
+        Ok(ServerMessage::CreateInvite(request)) => {
+            let reply = match state.create_invite(client_id, &request) {
+                Ok(invite) => {
+                    info!(
+                        "[{}] Invited a guest to '{}' until {}",
+                        client_id, invite.path, invite.expires_at_ms
+                    );
+                    ServerMessage::Invite(invite)
+                }
+                Err(e) => {
+                    error!("[{}] Cannot invite a guest: {}", client_id, e);
+                    server_error(&e)
+                }
+            };
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::Checkpoint(request)) => {
+            let reply = match state.checkpoint(client_id, &request) {
+                Ok(checkpoint) => {
+                    let done = match (&checkpoint.milestone, checkpoint.commit.as_str()) {
+                        (Some(milestone), _) => {
+                            format!("milestone '{}' at v{}", milestone.label, milestone.version)
+                        }
+                        (None, "") => "nothing to commit".to_string(),
+                        (None, commit) => commit.to_string(),
+                    };
+                    info!("[{}] Checkpoint: {}", client_id, done);
+                    ServerMessage::Checkpoint(checkpoint)
+                }
+                Err(e) => {
+                    error!("[{}] Cannot checkpoint: {}", client_id, e);
+                    server_error(&e)
+                }
+            };
+            state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+        }
+        Ok(ServerMessage::Invite(_)) => {
+            info!("[{}] Ignoring Invite from client", client_id);
+        }
+        Ok(ServerMessage::MemberToken(_)) => {
+            info!("[{}] Ignoring MemberToken from client", client_id);
+        }
+        Ok(ServerMessage::Resend(resend)) => {
+            if let Err(oldest) = state.resend(client_id, resend.from_seq) {
+                info!(
+                    "[{}] Cannot resend from #{}: oldest retained is #{}",
+                    client_id, resend.from_seq, oldest
+                );
+                let reply = error(
+                    ErrorCode::ResendUnavailable,
+                    format!(
+                        "Frames before #{} are no longer retained; reopen your documents",
+                        oldest
+                    ),
+                );
+                // A numbered reply would land behind the gap the client is waiting on
+                state.send_unsequenced_to_client(
+                    client_id,
+                    Frame::new_arc(ServerMessage::encode(&reply)),
+                );
+            }
+        }
+        Ok(ServerMessage::Credit(credit)) => {
+            state.grant_credit(client_id, credit.frames as u64, credit.bytes);
+        }
+        Ok(ServerMessage::Sequenced(..)) => {
+            info!("[{}] Ignoring Sequenced from client", client_id);
+        }
+        Err(e) => {
+            error!("[{}] Failed to decode message: {}", client_id, e);
+            if !compatibility::is_known_type(frame.type_id) {
+                let diagnostics = state.client_requirements().unknown_message(frame.type_id);
+                let reply = ServerMessage::Diagnostics(diagnostics);
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            } else if is_invalid_utf8(e.as_ref()) {
+                let reply = error(
+                    ErrorCode::InvalidText,
+                    "Message text is not valid UTF-8".to_string(),
+                );
+                state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&reply)));
+            }
+        }
+    }
+}
+
+/// Whether decoding failed on a string field holding bytes that are not
+/// UTF-8, as opposed to a frame that is malformed throughout.
+fn is_invalid_utf8(e: &(dyn std::error::Error + 'static)) -> bool {
+    // prost only says so in the error's description
+    e.downcast_ref::<prost::DecodeError>()
+        .is_some_and(|e| e.to_string().contains("UTF-8"))
+}
+
+/// Messages sent on the user's behalf, as opposed to the connection's
+/// (heartbeats, handshakes). Only these keep a connection from going idle.
+fn is_user_input(message: &ServerMessage) -> bool {
+    matches!(
+        message,
+        ServerMessage::Operation(_)
+            | ServerMessage::OperationBatch(_)
+            | ServerMessage::OpenDocument(_)
+            | ServerMessage::CloseDocument(_)
+            | ServerMessage::ExportDocument(_)
+            | ServerMessage::DocumentArchive(_)
+            | ServerMessage::CreateFromTemplate(_)
+            | ServerMessage::LockRange(_)
+            | ServerMessage::UnlockRange(_)
+            | ServerMessage::SetDocumentSettings(_)
+            | ServerMessage::Checkpoint(_)
+            | ServerMessage::ExportRequest(_)
+            | ServerMessage::UploadAttachment(_)
+            | ServerMessage::FetchAttachment(_)
+            | ServerMessage::SetViewport(_)
+            | ServerMessage::Follow(_)
+            | ServerMessage::Signal(_)
+            | ServerMessage::TagVersion(_)
+            | ServerMessage::GetHistory(_)
+    )
+}
+
+fn error(code: ErrorCode, message: String) -> ServerMessage {
+    ServerMessage::Error(ErrorProto {
+        code: code as i32,
+        message,
+        retry_after_ms: 0,
+        op_id: 0,
+    })
+}
+
+/// The Error frame telling the client about `e`.
+fn server_error(e: &ServerError) -> ServerMessage {
+    let message = match e {
+        ServerError::ImportRejected(reason)
+        | ServerError::TemplateRejected(reason)
+        | ServerError::MembershipRejected(reason)
+        | ServerError::CheckpointFailed(reason)
+        | ServerError::AttachmentRejected(reason)
+        | ServerError::FollowRejected(reason)
+        | ServerError::SignalRejected(reason)
+        | ServerError::TagRejected(reason) => reason.clone(),
+        e => e.to_string(),
+    };
+    error(e.code().unwrap_or(ErrorCode::Unspecified), message)
+}
+
+/// Subscribes the client to `path` and sends it the document's current state,
+/// or what it missed since its cursor if it asks to `resume`.
+fn open_document(state: &ServerState, client_id: Uuid, path: &str, resume: bool) {
+    let opened = if resume {
+        state.resume_document(client_id, path)
+    } else {
+        state.open_document(client_id, path).map(|sync| vec![sync])
+    };
+    match opened {
+        Ok(frames) => {
+            for frame in frames {
+                if !state.send_to_client(client_id, frame) {
+                    error!(
+                        "[{}] Failed to queue initial sync for '{}'",
+                        client_id, path
+                    );
+                    return;
+                }
+            }
+            state.send_overlays(client_id, path);
+            state.send_freezes(client_id, path);
+        }
+        Err(ServerError::NotConnected) => error!(
+            "[{}] Cannot open '{}': client not registered",
+            client_id, path
+        ),
+        Err(e) => {
+            error!("[{}] Cannot open '{}': {}", client_id, path, e);
+            state.send_to_client(
+                client_id,
+                Frame::new_arc(ServerMessage::encode(&server_error(&e))),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    use common::{
+        document::apply_to_text,
+        operation::{Operation, OperationKind},
+        space::{
+            CapabilitiesProto, HelloProto, OperationBatchProto, OperationProto, PresenceProto,
+        },
+    };
+
+    use crate::broadcaster::broadcast;
+    use crate::client_entry::ClientEntry;
+    use crate::compatibility::ClientRequirements;
+    use common::{protocol::PROTOCOL_VERSION, space::DiagnosticKind};
+
+    fn ignore_broadcast(_: Uuid, _: &str, _: Arc<Frame>, _: &ServerState) {}
+
+    #[test]
+    fn test_frames_are_decoded_in_order_per_client() {
+        let state = Arc::new(ServerState::new());
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        let pool = DecodePool::spawn(2, Arc::clone(&state), ignore_broadcast);
+
+        let hello = ServerMessage::Hello(HelloProto {
+            doc_path: "notes.txt".to_string(),
+            ..Default::default()
+        });
+        assert!(pool.submit(client_id, Frame::new_arc(ServerMessage::encode(&hello))));
+        let ping = ServerMessage::Ping(7);
+        assert!(pool.submit(client_id, Frame::new_arc(ServerMessage::encode(&ping))));
+
+        let timeout = Duration::from_secs(5);
+        let first = rx.recv_timeout(timeout).unwrap();
+        match ServerMessage::decode_bytes(&first.payload).unwrap() {
+            ServerMessage::SyncDocument(sync) => assert_eq!(sync.path, "notes.txt"),
+            _ => panic!("expected SyncDocument first"),
+        }
+        let second = rx.recv_timeout(timeout).unwrap();
+        assert!(matches!(
+            ServerMessage::decode_bytes(&second.payload).unwrap(),
+            ServerMessage::Pong(7)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_refused_with_an_error() {
+        let state = Arc::new(ServerState::new());
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+        // OpenDocument whose path (field 1) is the single byte 0xff
+        let mut payload = ServerMessage::encode(&ServerMessage::OpenDocument(Default::default()));
+        payload.extend_from_slice(&[0x0a, 0x01, 0xff]);
+        let len = payload.len() as u32 - 4;
+        payload[..4].copy_from_slice(&len.to_be_bytes());
+        let frame = Frame::new_arc(payload);
+        dispatch(&state, client_id, &frame, ignore_broadcast);
+
+        let reply = rx.try_recv().unwrap();
+        match ServerMessage::decode_bytes(&reply.payload).unwrap() {
+            ServerMessage::Error(error) => assert_eq!(error.code(), ErrorCode::InvalidText),
+            _ => panic!("expected an Error"),
+        }
+    }
+
+    #[test]
+    fn test_incompatible_clients_are_told_why() {
+        let state = Arc::new(
+            ServerState::new().with_client_requirements(ClientRequirements {
+                min_protocol: PROTOCOL_VERSION,
+                capabilities: Vec::new(),
+            }),
+        );
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+        // A frame of a type from some newer protocol
+        let mut unknown = ServerMessage::encode(&ServerMessage::Ping(1));
+        unknown[4] = u8::MAX;
+        dispatch(
+            &state,
+            client_id,
+            &Frame::new_arc(unknown),
+            ignore_broadcast,
+        );
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload).unwrap() {
+            ServerMessage::Diagnostics(diagnostics) => {
+                assert_eq!(diagnostics.kind(), DiagnosticKind::UnknownMessage);
+                assert_eq!(diagnostics.message_type, u8::MAX as u32);
+            }
+            _ => panic!("expected Diagnostics"),
+        }
+
+        // A Hello from before protocol versions
+        let hello = ServerMessage::Hello(HelloProto::default());
+        dispatch(
+            &state,
+            client_id,
+            &Frame::new_arc(ServerMessage::encode(&hello)),
+            ignore_broadcast,
+        );
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload).unwrap() {
+            ServerMessage::Diagnostics(diagnostics) => {
+                assert_eq!(diagnostics.kind(), DiagnosticKind::ProtocolTooOld);
+                assert_eq!(diagnostics.min_protocol_version, PROTOCOL_VERSION);
+            }
+            _ => panic!("expected Diagnostics"),
+        }
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload).unwrap() {
+            ServerMessage::Disconnect(disconnect) => {
+                assert_eq!(disconnect.reason_code(), DisconnectReason::Incompatible)
+            }
+            _ => panic!("expected a Disconnect"),
+        }
+        assert_eq!(state.client_count(), 0);
+    }
+
+    #[test]
+    fn test_hello_with_capabilities_is_answered_before_the_sync() {
+        let state = Arc::new(ServerState::new());
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+        let hello = ServerMessage::Hello(HelloProto {
+            sequenced: true,
+            capabilities: Some(CapabilitiesProto {
+                batches: true,
+                presence: false,
+                delta_sync: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        dispatch(
+            &state,
+            client_id,
+            &Frame::new_arc(ServerMessage::encode(&hello)),
+            ignore_broadcast,
+        );
+
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload).unwrap() {
+            ServerMessage::Capabilities(capabilities) => {
+                assert!(capabilities.batches && !capabilities.delta_sync)
+            }
+            _ => panic!("expected Capabilities first"),
+        }
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload).unwrap() {
+            ServerMessage::Sequenced(1, message) => {
+                assert!(matches!(*message, ServerMessage::SyncDocument(_)))
+            }
+            _ => panic!("expected the sync as frame #1"),
+        }
+
+        // Presence it said it does without is not sent
+        let presence = ServerMessage::Presence(PresenceProto::default());
+        state.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&presence)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    /// A connection as its client sees it.
+    struct Peer {
+        id: Uuid,
+        hello: HelloProto,
+        frames: Receiver<Arc<Frame>>,
+    }
+
+    impl Peer {
+        fn connect(state: &Arc<ServerState>, hello: HelloProto) -> Self {
+            let id = Uuid::new_v4();
+            let (tx, frames) = crossbeam::channel::bounded(64);
+            state.add_client(ClientEntry::new(id, tx)).unwrap();
+            let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Hello(hello.clone())));
+            dispatch(state, id, &frame, broadcast);
+            Self { id, hello, frames }
+        }
+
+        fn send(&self, state: &Arc<ServerState>, message: ServerMessage) {
+            let frame = Frame::new_arc(ServerMessage::encode(&message));
+            dispatch(state, self.id, &frame, broadcast);
+        }
+
+        /// Replays every frame received so far the way a client limited to
+        /// what its Hello said would, checking each sync against the ops
+        /// before it. Returns the text it ends up with.
+        fn replay(&self) -> String {
+            // A Hello without capabilities takes batches and presence
+            let capabilities = self.hello.capabilities.unwrap_or(CapabilitiesProto {
+                batches: true,
+                presence: true,
+                ..Default::default()
+            });
+            let mut text = String::new();
+            let mut next_seq = 1;
+            for frame in self.frames.try_iter() {
+                let mut message = ServerMessage::decode_bytes(&frame.payload).unwrap();
+                if let ServerMessage::Sequenced(seq, inner) = message {
+                    assert!(self.hello.sequenced, "unasked-for numbering");
+                    assert_eq!(seq, next_seq, "numbering has a gap");
+                    next_seq += 1;
+                    message = *inner;
+                }
+                let ops = match message {
+                    ServerMessage::Capabilities(server) => {
+                        assert!(self.hello.capabilities.is_some());
+                        assert!(!server.compression && !server.delta_sync && !server.crdt);
+                        continue;
+                    }
+                    ServerMessage::SyncDocument(sync) => {
+                        assert_eq!(text, sync.content, "ops disagree with the sync");
+                        continue;
+                    }
+                    ServerMessage::Presence(_) => {
+                        assert!(capabilities.presence, "presence it cannot handle");
+                        continue;
+                    }
+                    ServerMessage::Operation(op) => vec![op],
+                    ServerMessage::OperationBatch(batch) => {
+                        assert!(capabilities.batches, "a batch it cannot handle");
+                        batch.operations
+                    }
+                    message => panic!("unexpected {:?}", message),
+                };
+                for op in ops {
+                    let kind = Operation::convert_operation(op).unwrap();
+                    apply_to_text(&mut text, &kind).unwrap();
+                }
+            }
+            text
+        }
+    }
+
+    fn op(
+        peer: &Peer,
+        doc_id: &str,
+        op_id: u64,
+        client_version: u64,
+        kind: OperationKind,
+    ) -> OperationProto {
+        OperationProto {
+            op_id,
+            kind: Some(kind.to_proto()),
+            doc_id: doc_id.to_string(),
+            client_id: peer.id.to_string(),
+            client_version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clients_get_only_frames_they_handle_and_still_converge() {
+        let state = Arc::new(ServerState::new());
+        let hello = |capabilities: Option<(bool, bool)>, sequenced| HelloProto {
+            doc_path: "notes.txt".to_string(),
+            sequenced,
+            capabilities: capabilities.map(|(batches, presence)| CapabilitiesProto {
+                batches,
+                presence,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let peers = [
+            // From before capabilities were exchanged
+            Peer::connect(&state, hello(None, false)),
+            Peer::connect(&state, hello(Some((true, true)), true)),
+            Peer::connect(&state, hello(Some((false, true)), true)),
+            Peer::connect(&state, hello(Some((true, false)), false)),
+            // No compression, batches or presence
+            Peer::connect(&state, hello(Some((false, false)), true)),
+        ];
+        let entry = state
+            .documents()
+            .into_iter()
+            .find(|entry| entry.path == "notes.txt")
+            .unwrap();
+        let doc_id = entry.sync_proto().doc_id;
+        let wait_for_applied = |count| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while entry.metrics.applied.load(Ordering::Relaxed) < count {
+                assert!(Instant::now() < deadline, "worker did not apply the op");
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        let [legacy, full, unbatched, _, minimal] = &peers;
+        minimal.send(
+            &state,
+            ServerMessage::Operation(op(
+                minimal,
+                &doc_id,
+                1,
+                0,
+                OperationKind::insert_at(0, "hello"),
+            )),
+        );
+        wait_for_applied(1);
+        full.send(
+            &state,
+            ServerMessage::OperationBatch(OperationBatchProto {
+                operations: vec![
+                    op(full, &doc_id, 1, 1, OperationKind::insert_at(5, " world")),
+                    op(full, &doc_id, 2, 1, OperationKind::insert_at(0, "> ")),
+                ],
+                more: false,
+            }),
+        );
+        // Written before the batch landed, so transformed through it
+        let delete = OperationKind::delete_range(0, 1);
+        legacy.send(
+            &state,
+            ServerMessage::Operation(op(legacy, &doc_id, 1, 1, delete)),
+        );
+        wait_for_applied(4);
+        state.set_presence(unbatched.id, true);
+
+        let content = entry.sync_proto().content;
+        assert_eq!(content, "> ello world");
+        for peer in &peers {
+            assert_eq!(peer.replay(), content);
+        }
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    use common::{
+        protocol::MSG_TYPE_ACTIVITY,
+        space::{HelloProto, InsertOp, OperationProto, operation_proto::Kind},
+    };
+
+    use crate::client_entry::ClientEntry;
+
+    /// Length and type id ahead of every payload.
+    const HEADER_LEN: usize = 5;
+
+    fn ignore_broadcast(_: Uuid, _: &str, _: Arc<Frame>, _: &ServerState) {}
+
+    /// A header with any length and type, mostly types this build knows,
+    /// followed by arbitrary bytes.
+    fn arb_garbage() -> impl Strategy<Value = Vec<u8>> {
+        (
+            any::<u32>(),
+            prop_oneof![4 => 0u8..=MSG_TYPE_ACTIVITY, 1 => any::<u8>()],
+            prop::collection::vec(any::<u8>(), 0..48),
+        )
+            .prop_map(|(len, type_id, body)| {
+                let mut payload = len.to_be_bytes().to_vec();
+                payload.push(type_id);
+                payload.extend(body);
+                payload
+            })
+    }
+
+    /// A well-formed Hello or Operation, cut short or with bytes appended;
+    /// string fields may hold invalid UTF-8.
+    fn arb_mangled() -> impl Strategy<Value = Vec<u8>> {
+        let message = prop_oneof![
+            "\\PC{0,8}".prop_map(|doc_path| ServerMessage::Hello(HelloProto {
+                doc_path,
+                ..Default::default()
+            })),
+            ("\\PC{0,8}", any::<u32>(), any::<u64>()).prop_map(|(text, index, version)| {
+                ServerMessage::Operation(OperationProto {
+                    client_version: version,
+                    server_version: version,
+                    kind: Some(Kind::Insert(InsertOp {
+                        index,
+                        text,
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                })
+            }),
+        ];
+        (
+            message,
+            any::<prop::sample::Index>(),
+            prop::collection::vec(any::<u8>(), 0..8),
+        )
+            .prop_map(|(message, cut, tail)| {
+                let mut payload = ServerMessage::encode(&message);
+                payload.truncate(HEADER_LEN + cut.index(payload.len() - HEADER_LEN + 1));
+                // A string field (client_id) holding a lone 0xff
+                payload.extend_from_slice(&[0x1a, 0x01, 0xff]);
+                payload.extend(tail);
+                payload
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(300))]
+
+        /// No frame a client sends takes the server down: after any mix of
+        /// garbage and mangled messages, a Ping is still answered.
+        #[test]
+        fn prop_arbitrary_frames_never_panic(
+            frames in prop::collection::vec(prop_oneof![arb_garbage(), arb_mangled()], 1..16),
+        ) {
+            let state = Arc::new(ServerState::new());
+            let client_id = Uuid::new_v4();
+            let (tx, rx) = crossbeam::channel::unbounded();
+            state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+
+            for payload in frames {
+                let _ = ServerMessage::decode(&payload);
+                dispatch(&state, client_id, &Frame::new_arc(payload), ignore_broadcast);
+            }
+            let ping = ServerMessage::Ping(u64::MAX);
+            dispatch(&state, client_id, &Frame::new_arc(ServerMessage::encode(&ping)), ignore_broadcast);
+
+            let answered = rx.try_iter().any(|frame| {
+                matches!(ServerMessage::decode_bytes(&frame.payload), Ok(ServerMessage::Pong(u64::MAX)))
+            });
+            prop_assert!(answered);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use uuid::{Builder, Uuid};
+
+use crate::sha256;
+use crate::workspaces::DEFAULT_WORKSPACE;
+
+/// How documents without a configured id get one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DocIdScheme {
+    /// A fresh id every time the document is created, so a restart gives
+    /// the same path a new id.
+    #[default]
+    Random,
+    /// Derived from the workspace and path: the same document keeps its id
+    /// across restarts, and across servers sharing the configuration.
+    Path,
+}
+
+impl FromStr for DocIdScheme {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "random" => Ok(DocIdScheme::Random),
+            "path" => Ok(DocIdScheme::Path),
+            _ => Err(format!(
+                "Unknown document id scheme '{}' (random, path)",
+                value
+            )),
+        }
+    }
+}
+
+/// The id each document is registered under: a scheme, and ids configured
+/// for paths in the default workspace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocIds {
+    pub scheme: DocIdScheme,
+    fixed: Vec<(String, Uuid)>,
+}
+
+impl DocIds {
+    /// Parses comma-separated rules, each a scheme or `PATH=UUID`, e.g.
+    /// `path,main.txt=6f1c0b1e-3d2a-4c8e-9a53-2b7d4e0f9c11`.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut ids = Self::default();
+        for rule in rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            match rule.split_once('=') {
+                Some((path, id)) => {
+                    let id = id
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("Invalid document id '{}'", id.trim()))?;
+                    ids.set(path.trim(), id)?;
+                }
+                None => ids.scheme = rule.parse()?,
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Gives the document at `path` in the default workspace the id `id`.
+    pub fn set(&mut self, path: &str, id: Uuid) -> Result<(), String> {
+        self.fixed.retain(|(p, _)| p != path);
+        if let Some((other, _)) = self.fixed.iter().find(|(_, taken)| *taken == id) {
+            return Err(format!(
+                "Document id {} is given to both '{}' and '{}'",
+                id, other, path
+            ));
+        }
+        self.fixed.push((path.to_string(), id));
+        Ok(())
+    }
+
+    /// The id the document at `path` in `workspace` is registered under, or
+    /// `None` to keep the one it was created with.
+    pub fn for_path(&self, workspace: &str, path: &str) -> Option<Uuid> {
+        let fixed = self
+            .fixed
+            .iter()
+            .find(|(p, _)| workspace == DEFAULT_WORKSPACE && p == path);
+        match (fixed, self.scheme) {
+            (Some((_, id)), _) => Some(*id),
+            (None, DocIdScheme::Path) => Some(derived(workspace, path)),
+            (None, DocIdScheme::Random) => None,
+        }
+    }
+}
+
+/// A version 8 UUID from the SHA-256 of the workspace and path.
+fn derived(workspace: &str, path: &str) -> Uuid {
+    let digest = sha256::digest(format!("{}\0{}", workspace, path).as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// What opening a path with no document at it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingDocuments {
+    /// Creates an empty document there, if the workspace has room.
+    #[default]
+    Create,
+    /// Refuses with an ERROR_CODE_NO_SUCH_DOCUMENT error. Documents still
+    /// come from seeds, templates and imports, and the default document
+    /// always exists.
+    Refuse,
+}
+
+impl FromStr for MissingDocuments {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "create" => Ok(MissingDocuments::Create),
+            "refuse" => Ok(MissingDocuments::Refuse),
+            _ => Err(format!(
+                "Unknown missing document policy '{}' (create, refuse)",
+                value
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_by_scheme_and_path() {
+        let fixed: Uuid = "6f1c0b1e-3d2a-4c8e-9a53-2b7d4e0f9c11".parse().unwrap();
+        let ids = DocIds::parse(&format!("path, main.txt={}", fixed)).unwrap();
+
+        assert_eq!(ids.for_path(DEFAULT_WORKSPACE, "main.txt"), Some(fixed));
+        let notes = ids.for_path(DEFAULT_WORKSPACE, "notes.md").unwrap();
+        assert_eq!(ids.for_path(DEFAULT_WORKSPACE, "notes.md"), Some(notes));
+        assert_ne!(ids.for_path("team-a", "notes.md"), Some(notes));
+        // Configured ids only name the default workspace's documents
+        assert_ne!(ids.for_path("team-a", "main.txt"), Some(fixed));
+
+        assert_eq!(DocIds::parse("").unwrap().for_path("", "main.txt"), None);
+        assert!(DocIds::parse("sequential").is_err());
+        assert!(DocIds::parse("a.txt=not-a-uuid").is_err());
+        assert!(DocIds::parse(&format!("a.txt={0},b.txt={0}", fixed)).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock,
+        atomic::{AtomicU8, AtomicU64, Ordering},
+    },
+};
+
+use common::{Document, clock::Timestamp, ids, operation::OperationLog, space::SyncDocumentProto};
+use crossbeam::channel::Sender;
+
+use crate::conflict::{ConflictPolicies, ConflictPolicy};
+use crate::doc_ids::DocIds;
+use crate::locks::RangeLocks;
+use crate::log::info;
+use crate::metrics::DocumentMetrics;
+use crate::overlays::Overlays;
+use crate::settings::DocumentSettings;
+use crate::stats::DocumentStats;
+use crate::worker::OpJob;
+
+/// A document together with the operation log used to transform stale edits against it.
+pub struct DocumentEntry {
+    pub path: String,
+    pub document: Mutex<Document>,
+    pub op_log: OperationLog,
+    pub metrics: DocumentMetrics,
+    /// Queue into the document's worker thread, started by the first operation.
+    pub(crate) queue: OnceLock<Sender<OpJob>>,
+    /// File the document is loaded from and autosaved to, if it is persisted.
+    pub backing_file: Option<PathBuf>,
+    /// Version last written to `backing_file`.
+    pub saved_version: AtomicU64,
+    /// Exclusive ranges claimed by clients. Taken after `document` when both
+    /// are needed, and kept at the document's version.
+    range_locks: Mutex<RangeLocks>,
+    /// Decorations set by clients. Taken after `document` like `range_locks`.
+    overlays: Mutex<Overlays>,
+    /// Held from applying ops until their frames are queued, so frames leave
+    /// in version order even when a transaction races the document's worker.
+    /// Taken before `document`.
+    emit: Mutex<()>,
+    /// How concurrent edits are transformed; see `ConflictPolicy`.
+    conflict_policy: AtomicU8,
+    /// Line endings, language and size limit. Taken after `document`.
+    settings: Mutex<DocumentSettings>,
+    /// Kept at the document's version. Taken after `document`.
+    stats: Mutex<DocumentStats>,
+}
+
+impl DocumentEntry {
+    fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            document: Mutex::new(Document {
+                uuid: ids::new_uuid(),
+                content: Arc::new(String::new()),
+                version: 0,
+            }),
+            op_log: OperationLog::new(),
+            metrics: DocumentMetrics::default(),
+            queue: OnceLock::new(),
+            backing_file: None,
+            saved_version: AtomicU64::new(0),
+            range_locks: Mutex::new(RangeLocks::new()),
+            overlays: Mutex::new(Overlays::new()),
+            emit: Mutex::new(()),
+            conflict_policy: AtomicU8::new(ConflictPolicy::default().to_u8()),
+            settings: Mutex::new(DocumentSettings::inferred(path, "")),
+            stats: Mutex::new(DocumentStats::default()),
+        }
+    }
+
+    /// A document persisted in `file`, starting from `content` (the file as loaded).
+    pub fn backed_by(path: &str, file: PathBuf, content: String) -> Self {
+        Self::with_content(path, content, Some(file))
+    }
+
+    /// A new document whose version 0 is `content` rather than empty.
+    pub fn with_content(path: &str, content: String, backing_file: Option<PathBuf>) -> Self {
+        Self {
+            settings: Mutex::new(DocumentSettings::inferred(path, &content)),
+            stats: Mutex::new(DocumentStats::for_content(&content)),
+            document: Mutex::new(Document {
+                uuid: ids::new_uuid(),
+                content: Arc::new(content),
+                version: 0,
+            }),
+            backing_file,
+            ..Self::new(path)
+        }
+    }
+
+    /// A document rebuilt from an archive, with the history it was exported with.
+    pub fn restored(
+        path: &str,
+        document: Document,
+        op_log: OperationLog,
+        backing_file: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            settings: Mutex::new(DocumentSettings::inferred(path, &document.content)),
+            stats: Mutex::new(DocumentStats::for_content(&document.content)),
+            document: Mutex::new(document),
+            op_log,
+            backing_file,
+            ..Self::new(path)
+        }
+    }
+
+    pub fn range_locks(&self) -> MutexGuard<'_, RangeLocks> {
+        match self.range_locks.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn overlays(&self) -> MutexGuard<'_, Overlays> {
+        match self.overlays.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn settings(&self) -> MutexGuard<'_, DocumentSettings> {
+        match self.settings.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn stats(&self) -> MutexGuard<'_, DocumentStats> {
+        match self.stats.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        ConflictPolicy::from_u8(self.conflict_policy.load(Ordering::Relaxed))
+    }
+
+    pub fn set_conflict_policy(&self, policy: ConflictPolicy) {
+        self.conflict_policy
+            .store(policy.to_u8(), Ordering::Relaxed);
+    }
+
+    pub fn emitting(&self) -> MutexGuard<'_, ()> {
+        match self.emit.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Current state as a SyncDocument message. The text is copied after the
+    /// document lock is released.
+    pub fn sync_proto(&self) -> SyncDocumentProto {
+        let (doc_id, content, version, taken_at, stats) = {
+            let doc = match self.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let stats = self.stats().to_proto();
+            (
+                doc.uuid,
+                doc.snapshot(),
+                doc.version,
+                Timestamp::now(),
+                stats,
+            )
+        };
+        SyncDocumentProto {
+            doc_id: doc_id.to_string(),
+            content: unwrap_snapshot(content),
+            version,
+            path: self.path.clone(),
+            server_time_ms: taken_at.wall_ms,
+            server_mono_ms: taken_at.mono_ms,
+            settings: Some(self.settings().to_proto()),
+            stats: Some(stats),
+            resumed: false,
+        }
+    }
+}
+
+/// Takes ownership of a snapshot's text, copying only if the document (or
+/// another reader) still shares it.
+pub fn unwrap_snapshot(snapshot: Arc<String>) -> String {
+    Arc::try_unwrap(snapshot).unwrap_or_else(|shared| shared.as_str().to_owned())
+}
+
+/// All open documents, addressable by id (routing of operations) and by path (opening).
+#[derive(Default)]
+pub struct DocumentRegistry {
+    by_id: HashMap<String, Arc<DocumentEntry>>,
+    by_path: HashMap<String, String>,
+    /// Conflict policy given to each document by path.
+    policies: ConflictPolicies,
+    /// Workspace the documents belong to, which path-derived ids depend on.
+    workspace: String,
+    /// Id each document is registered under by path.
+    ids: DocIds,
+}
+
+impl DocumentRegistry {
+    /// A registry for the documents of workspace `name`.
+    pub fn in_workspace(name: &str) -> Self {
+        Self {
+            workspace: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the document at `path`, creating an empty one if it doesn't exist yet.
+    pub fn open(&mut self, path: &str) -> Arc<DocumentEntry> {
+        if let Some(entry) = self.at_path(path) {
+            return entry;
+        }
+
+        let entry = Arc::new(DocumentEntry::new(path));
+        entry.set_conflict_policy(self.policies.for_path(path));
+        let doc_id = self.register(&entry);
+        info!("[Documents] Created '{}' as {}", path, doc_id);
+        entry
+    }
+
+    /// Registers `entry` under its path, replacing any document already there.
+    pub fn insert(&mut self, entry: DocumentEntry) -> Arc<DocumentEntry> {
+        if let Some(old_id) = self.by_path.remove(&entry.path) {
+            self.by_id.remove(&old_id);
+        }
+        let entry = Arc::new(entry);
+        entry.set_conflict_policy(self.policies.for_path(&entry.path));
+        self.register(&entry);
+        entry
+    }
+
+    /// Gives `entry` the id `ids` has for its path, if any, and makes it
+    /// addressable. Returns its id.
+    fn register(&mut self, entry: &Arc<DocumentEntry>) -> String {
+        if let Some(id) = self.ids.for_path(&self.workspace, &entry.path) {
+            match entry.document.lock() {
+                Ok(mut doc) => doc.uuid = id,
+                Err(poisoned) => poisoned.into_inner().uuid = id,
+            }
+        }
+        let doc_id = entry.sync_proto().doc_id;
+        self.by_path.insert(entry.path.clone(), doc_id.clone());
+        self.by_id.insert(doc_id.clone(), Arc::clone(entry));
+        doc_id
+    }
+
+    /// Registers the documents open now, and those opened later, under the
+    /// ids `ids` gives them.
+    pub fn set_ids(&mut self, ids: DocIds) {
+        self.ids = ids;
+        let entries = self.entries();
+        self.by_id.clear();
+        self.by_path.clear();
+        for entry in &entries {
+            self.register(entry);
+        }
+    }
+
+    /// Applies `policies` to the documents open now and to those opened later.
+    pub fn set_policies(&mut self, policies: ConflictPolicies) {
+        for entry in self.by_id.values() {
+            entry.set_conflict_policy(policies.for_path(&entry.path));
+        }
+        self.policies = policies;
+    }
+
+    pub fn at_path(&self, path: &str) -> Option<Arc<DocumentEntry>> {
+        self.by_path.get(path).and_then(|id| self.get(id))
+    }
+
+    pub fn get(&self, doc_id: &str) -> Option<Arc<DocumentEntry>> {
+        self.by_id.get(doc_id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn entries(&self) -> Vec<Arc<DocumentEntry>> {
+        self.by_id.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_reuses_documents_by_path() {
+        let mut registry = DocumentRegistry::default();
+        let a = registry.open("a.txt");
+        let b = registry.open("b.txt");
+        let doc_a = a.sync_proto().doc_id;
+
+        assert!(Arc::ptr_eq(&a, &registry.open("a.txt")));
+        assert_ne!(doc_a, b.sync_proto().doc_id);
+        assert_eq!(registry.get(&doc_a).unwrap().path, "a.txt");
+        assert!(registry.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_documents_take_their_policy_by_path() {
+        let mut registry = DocumentRegistry::default();
+        let existing = registry.open("a.txt");
+        registry.set_policies(ConflictPolicies::parse("delete-wins,b.txt=keep-inserts").unwrap());
+
+        assert_eq!(existing.conflict_policy(), ConflictPolicy::DeleteWins);
+        assert_eq!(
+            registry.open("b.txt").conflict_policy(),
+            ConflictPolicy::KeepInserts
+        );
+        let replaced = registry.insert(DocumentEntry::with_content("a.txt", "x".into(), None));
+        assert_eq!(replaced.conflict_policy(), ConflictPolicy::DeleteWins);
+    }
+
+    #[test]
+    fn test_path_derived_ids_survive_recreation() {
+        let ids = DocIds::parse("path").unwrap();
+        let mut registry = DocumentRegistry::in_workspace("team-a");
+        let random = registry.open("a.txt").sync_proto().doc_id;
+        registry.set_ids(ids.clone());
+
+        let doc_id = registry.open("a.txt").sync_proto().doc_id;
+        assert_ne!(doc_id, random);
+        assert!(registry.get(&random).is_none());
+        assert_eq!(registry.get(&doc_id).unwrap().path, "a.txt");
+
+        let mut restarted = DocumentRegistry::in_workspace("team-a");
+        restarted.set_ids(ids);
+        assert_eq!(restarted.open("a.txt").sync_proto().doc_id, doc_id);
+        let replaced = restarted.insert(DocumentEntry::with_content("a.txt", "x".into(), None));
+        assert_eq!(replaced.sync_proto().doc_id, doc_id);
+        assert_eq!(restarted.len(), 1);
+    }
+}
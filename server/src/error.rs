@@ -0,0 +1,144 @@
+use std::io;
+
+use common::{
+    error::FrameError,
+    space::{DisconnectReason, ErrorCode},
+};
+use crossbeam::channel::TrySendError;
+use thiserror::Error;
+
+use crate::validation::Rejection;
+
+/// Everything that can go wrong while serving clients, grouped by what the
+/// server does about it: tell the client (`code`), close the connection
+/// (`disconnect_reason`), or only log it.
+#[derive(Debug, Error)]
+pub enum ServerError {
+    /// The request is invalid against the document or connection.
+    #[error("rejected: {0}")]
+    Rejected(#[from] Rejection),
+    /// A document archive was malformed, or its path already holds a document.
+    #[error("import rejected: {0}")]
+    ImportRejected(String),
+    /// The template is unknown or incomplete, or its path is taken.
+    #[error("template rejected: {0}")]
+    TemplateRejected(String),
+    /// An invitation or removal that does not fit the workspace's members.
+    #[error("membership rejected: {0}")]
+    MembershipRejected(String),
+    /// The server keeps no git history, or committing to it failed.
+    #[error("checkpoint failed: {0}")]
+    CheckpointFailed(String),
+    /// An upload too large or over quota, or a fetch of an attachment the
+    /// document does not reference.
+    #[error("attachment rejected: {0}")]
+    AttachmentRejected(String),
+    /// The connection to follow is not in the workspace, or is the follower.
+    #[error("follow rejected: {0}")]
+    FollowRejected(String),
+    /// A signal of no kind, or a reaction without a usable emoji.
+    #[error("signal rejected: {0}")]
+    SignalRejected(String),
+    /// A tag without a usable name, or a history request for a missing tag.
+    #[error("tag rejected: {0}")]
+    TagRejected(String),
+    /// The server is at its connection limit.
+    #[error("connection limit reached: {0} clients already connected")]
+    ServerFull(usize),
+    /// A message decoded, but is missing or garbling a field it needs.
+    #[error("malformed message: {0}")]
+    Malformed(&'static str),
+    /// The client has already gone.
+    #[error("client is not connected")]
+    NotConnected,
+    /// Server state is not as it should be; nothing the client did.
+    #[error("internal error: {0}")]
+    Internal(String),
+    /// Reading a frame from the client failed.
+    #[error(transparent)]
+    Read(#[from] FrameError),
+    /// Writing to the client failed.
+    #[error("write failed: {0}")]
+    Write(#[source] io::Error),
+    /// The client's writer queue was full.
+    #[error("writer queue full")]
+    QueueFull,
+    /// The client's writer has exited.
+    #[error("writer gone")]
+    WriterGone,
+}
+
+impl ServerError {
+    /// The code of the Error frame to send the client, for failures it is
+    /// told about.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ServerError::Rejected(rejection) => Some(rejection.code()),
+            ServerError::ImportRejected(_) => Some(ErrorCode::ImportRejected),
+            ServerError::TemplateRejected(_) => Some(ErrorCode::TemplateRejected),
+            ServerError::MembershipRejected(_) => Some(ErrorCode::MembershipRejected),
+            ServerError::CheckpointFailed(_) => Some(ErrorCode::CheckpointFailed),
+            ServerError::AttachmentRejected(_) => Some(ErrorCode::AttachmentRejected),
+            ServerError::FollowRejected(_) => Some(ErrorCode::FollowRejected),
+            ServerError::SignalRejected(_) => Some(ErrorCode::SignalRejected),
+            ServerError::TagRejected(_) => Some(ErrorCode::TagRejected),
+            ServerError::ServerFull(_) => Some(ErrorCode::ServerFull),
+            _ => None,
+        }
+    }
+
+    /// Why the connection is closed, for failures that end it.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        match self {
+            ServerError::ServerFull(_) => Some(DisconnectReason::ServerFull),
+            ServerError::Rejected(
+                Rejection::NotAMember { .. } | Rejection::InviteExpired { .. },
+            ) => Some(DisconnectReason::NotAMember),
+            ServerError::Read(
+                FrameError::Timeout(_) | FrameError::PayloadTooLarge(..) | FrameError::Protocol(_),
+            ) => Some(DisconnectReason::ProtocolViolation),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<TrySendError<T>> for ServerError {
+    fn from(e: TrySendError<T>) -> Self {
+        match e {
+            TrySendError::Full(_) => ServerError::QueueFull,
+            TrySendError::Disconnected(_) => ServerError::WriterGone,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_failure_class_has_one_reaction() {
+        let rejected = ServerError::from(Rejection::ReadOnly);
+        assert_eq!(rejected.code(), Some(ErrorCode::ReadOnly));
+        assert_eq!(rejected.disconnect_reason(), None);
+
+        let full = ServerError::ServerFull(100);
+        assert_eq!(full.code(), Some(ErrorCode::ServerFull));
+        assert_eq!(full.disconnect_reason(), Some(DisconnectReason::ServerFull));
+
+        let stalled = ServerError::from(FrameError::Timeout("stalled".to_string()));
+        assert_eq!(stalled.code(), None);
+        assert_eq!(
+            stalled.disconnect_reason(),
+            Some(DisconnectReason::ProtocolViolation)
+        );
+
+        for logged_only in [
+            ServerError::from(FrameError::Disconnected),
+            ServerError::Internal("log behind document".to_string()),
+            ServerError::from(TrySendError::Full(())),
+        ] {
+            assert_eq!(logged_only.code(), None, "{}", logged_only);
+            assert_eq!(logged_only.disconnect_reason(), None, "{}", logged_only);
+        }
+    }
+}
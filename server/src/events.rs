@@ -0,0 +1,207 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use common::space::{WorkspaceEventKind, WorkspaceEventProto};
+
+use crate::log::error;
+
+/// Events kept in memory per workspace; older ones stay only in the file.
+pub const MAX_RETAINED_EVENTS: usize = 10_000;
+
+/// Most events answered to one history request.
+pub const EVENTS_PER_PAGE: usize = 500;
+
+/// What happened in each workspace besides edits: documents created,
+/// members coming and going, checkpoints. Appended to `file`, if there is
+/// one, as each event is recorded: one
+/// `workspace<TAB>seq<TAB>at_ms<TAB>kind<TAB>path<TAB>member<TAB>detail` line
+/// per event, with tabs, newlines and backslashes in the text escaped.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    /// The latest events, oldest first, by workspace.
+    workspaces: BTreeMap<String, VecDeque<WorkspaceEventProto>>,
+    file: Option<PathBuf>,
+}
+
+impl EventLog {
+    /// The events saved in `file`; none if it does not exist yet.
+    pub fn load(file: PathBuf) -> io::Result<Self> {
+        let mut log = Self::default();
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        for (number, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", file.display(), number + 1, what),
+                )
+            };
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            let [workspace, seq, at_ms, kind, path, member, detail] = &fields[..] else {
+                return Err(invalid("expected seven tab-separated fields"));
+            };
+            let event = WorkspaceEventProto {
+                seq: seq.parse().map_err(|_| invalid("invalid seq"))?,
+                at_ms: at_ms.parse().map_err(|_| invalid("invalid time"))?,
+                kind: WorkspaceEventKind::from_str_name(kind)
+                    .ok_or_else(|| invalid("unknown event kind"))? as i32,
+                path: path.clone(),
+                member: member.clone(),
+                detail: detail.clone(),
+            };
+            log.retain(workspace, event);
+        }
+        log.file = Some(file);
+        Ok(log)
+    }
+
+    /// Records an event in `workspace` at `at_ms`, numbered after the last
+    /// one.
+    pub fn record(
+        &mut self,
+        workspace: &str,
+        kind: WorkspaceEventKind,
+        path: &str,
+        member: &str,
+        detail: &str,
+        at_ms: u64,
+    ) -> WorkspaceEventProto {
+        let seq = self.last_seq(workspace) + 1;
+        let event = WorkspaceEventProto {
+            seq,
+            at_ms,
+            kind: kind as i32,
+            path: path.to_string(),
+            member: member.to_string(),
+            detail: detail.to_string(),
+        };
+        self.append(workspace, &event);
+        self.retain(workspace, event.clone());
+        event
+    }
+
+    /// Workspaces that have events, sorted.
+    pub fn workspaces(&self) -> Vec<&str> {
+        self.workspaces.keys().map(String::as_str).collect()
+    }
+
+    /// A page of `workspace`'s events after `seq`, oldest first.
+    pub fn after(&self, workspace: &str, seq: u64) -> Vec<WorkspaceEventProto> {
+        self.workspaces
+            .get(workspace)
+            .map(|events| {
+                events
+                    .iter()
+                    .filter(|event| event.seq > seq)
+                    .take(EVENTS_PER_PAGE)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn last_seq(&self, workspace: &str) -> u64 {
+        self.workspaces
+            .get(workspace)
+            .and_then(VecDeque::back)
+            .map_or(0, |event| event.seq)
+    }
+
+    fn retain(&mut self, workspace: &str, event: WorkspaceEventProto) {
+        let events = self.workspaces.entry(workspace.to_string()).or_default();
+        if events.len() == MAX_RETAINED_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// A failed write is logged; the event is still answered until the
+    /// server restarts.
+    fn append(&self, workspace: &str, event: &WorkspaceEventProto) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let line = format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            escape(workspace),
+            event.seq,
+            event.at_ms,
+            event.kind().as_str_name(),
+            escape(&event.path),
+            escape(&event.member),
+            escape(&event.detail)
+        );
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            error!("[Events] Cannot append to {}: {}", file.display(), e);
+        }
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+pub(crate) fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_events_survive_a_reload() {
+        let file = std::env::temp_dir().join(format!("dist-space-events-{}", Uuid::new_v4()));
+        let mut log = EventLog::load(file.clone()).unwrap();
+        assert!(log.after("team-a", 0).is_empty());
+
+        let kind = WorkspaceEventKind::DocumentCreated;
+        let created = log.record("team-a", kind, "notes\tv2.md", "ada", "", 10);
+        assert_eq!(created.seq, 1);
+        let joined = WorkspaceEventKind::MemberJoined;
+        assert_eq!(log.record("team-a", joined, "", "grace\\", "", 20).seq, 2);
+        assert_eq!(log.record("team-b", kind, "a\nb", "", "", 30).seq, 1);
+
+        let log = EventLog::load(file.clone()).unwrap();
+        fs::remove_file(&file).unwrap();
+        let events = log.after("team-a", 0);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].path, "notes\tv2.md");
+        assert_eq!(events[1].member, "grace\\");
+        assert_eq!(events[1].kind(), joined);
+        assert_eq!(log.after("team-a", 1), &events[1..]);
+        assert_eq!(log.after("team-b", 0)[0].path, "a\nb");
+    }
+}
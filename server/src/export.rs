@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use common::space::{ExportChunkProto, ExportFormat};
+
+use crate::client_entry::WRITER_QUEUE_CAPACITY;
+use crate::documents::DocumentEntry;
+use crate::markdown;
+
+/// Data carried by each chunk of an exported copy, at the least.
+pub const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Chunks an exported copy is split into, at most. Larger copies get larger
+/// chunks, so an export never takes more than half the writer queue.
+const MAX_EXPORT_CHUNKS: usize = WRITER_QUEUE_CAPACITY / 2;
+
+/// A copy of `entry` in `format`, as the chunks it is sent in.
+pub fn export(entry: &DocumentEntry, format: ExportFormat) -> Vec<ExportChunkProto> {
+    let (doc_id, content, version) = {
+        let doc = match entry.document.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        (doc.uuid.to_string(), doc.snapshot(), doc.version)
+    };
+    let name = Path::new(&entry.path).file_name().map_or_else(
+        || entry.path.clone(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let (file_name, data) = match format {
+        ExportFormat::Plain => (name, content.as_bytes().to_vec()),
+        ExportFormat::Html => (
+            Path::new(&name)
+                .with_extension("html")
+                .to_string_lossy()
+                .into_owned(),
+            markdown::to_html_page(&name, &content).into_bytes(),
+        ),
+    };
+
+    let parts = split(&data);
+    let last = parts.len() - 1;
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(n, part)| ExportChunkProto {
+            doc_id: doc_id.clone(),
+            format: format as i32,
+            file_name: file_name.clone(),
+            version,
+            data: part.to_vec(),
+            more: n < last,
+        })
+        .collect()
+}
+
+/// `data` as the parts it is sent in: never more than half the writer queue
+/// of them, and at least one even for no data.
+pub fn split(data: &[u8]) -> Vec<&[u8]> {
+    let chunk_bytes = data
+        .len()
+        .div_ceil(MAX_EXPORT_CHUNKS)
+        .max(EXPORT_CHUNK_BYTES);
+    match data.is_empty() {
+        true => vec![data],
+        false => data.chunks(chunk_bytes).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copies_are_rendered_and_split_into_few_chunks() {
+        let entry = DocumentEntry::with_content("notes/plan.md", "# Plan".to_string(), None);
+        let html = export(&entry, ExportFormat::Html);
+        assert_eq!(html.len(), 1);
+        assert_eq!(html[0].file_name, "plan.html");
+        assert!(!html[0].more);
+        assert!(String::from_utf8_lossy(&html[0].data).contains("<h1>Plan</h1>"));
+
+        let content = "x".repeat(3 * EXPORT_CHUNK_BYTES + 1);
+        let entry = DocumentEntry::with_content("big.txt", content.clone(), None);
+        let plain = export(&entry, ExportFormat::Plain);
+        assert_eq!(plain.len(), 4);
+        assert!(plain[..3].iter().all(|chunk| chunk.more) && !plain[3].more);
+        let data: Vec<u8> = plain.iter().flat_map(|chunk| chunk.data.clone()).collect();
+        assert_eq!(data, content.as_bytes());
+
+        let huge = "x".repeat(100 * EXPORT_CHUNK_BYTES);
+        let entry = DocumentEntry::with_content("huge.txt", huge, None);
+        assert_eq!(export(&entry, ExportFormat::Plain).len(), MAX_EXPORT_CHUNKS);
+        let empty = DocumentEntry::with_content("empty.txt", String::new(), None);
+        assert_eq!(export(&empty, ExportFormat::Plain).len(), 1);
+    }
+}
@@ -0,0 +1,215 @@
+use common::space::FreezeProto;
+
+use crate::validation::Rejection;
+use crate::workspaces::DEFAULT_WORKSPACE;
+
+/// A window during which a workspace's documents, or one of them, refuse
+/// edits, e.g. while a release is cut. Syncs, presence and everything else
+/// that doesn't change a document go on as usual.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Freeze {
+    pub workspace: String,
+    /// The document frozen; `None` for the whole workspace.
+    pub path: Option<String>,
+    /// Server wall clock, in ms since the Unix epoch.
+    pub starts_at_ms: u64,
+    /// `None` to stay frozen until unfrozen.
+    pub ends_at_ms: Option<u64>,
+}
+
+impl Freeze {
+    pub fn is_active(&self, now_ms: u64) -> bool {
+        self.starts_at_ms <= now_ms && self.ends_at_ms.is_none_or(|end| now_ms < end)
+    }
+
+    fn is_over(&self, now_ms: u64) -> bool {
+        self.ends_at_ms.is_some_and(|end| end <= now_ms)
+    }
+
+    /// Whether it freezes the document at `path` in `workspace`.
+    pub fn covers(&self, workspace: &str, path: &str) -> bool {
+        self.workspace == workspace && self.path.as_deref().is_none_or(|frozen| frozen == path)
+    }
+
+    fn same_target(&self, other: &Freeze) -> bool {
+        self.workspace == other.workspace && self.path == other.path
+    }
+
+    /// What is frozen, for messages.
+    pub fn target(&self) -> String {
+        let workspace = match self.workspace.as_str() {
+            DEFAULT_WORKSPACE => "the default workspace".to_string(),
+            name => format!("workspace '{}'", name),
+        };
+        match &self.path {
+            Some(path) => format!("'{}' in {}", path, workspace),
+            None => workspace,
+        }
+    }
+
+    pub fn to_proto(&self, frozen: bool) -> FreezeProto {
+        FreezeProto {
+            workspace: self.workspace.clone(),
+            path: self.path.clone().unwrap_or_default(),
+            frozen,
+            starts_at_ms: self.starts_at_ms,
+            ends_at_ms: self.ends_at_ms.unwrap_or_default(),
+        }
+    }
+}
+
+struct Window {
+    freeze: Freeze,
+    /// Whether clients were told it started.
+    announced: bool,
+}
+
+/// Freeze windows set by the operator, at most one per workspace or
+/// document. Kept in memory only.
+#[derive(Default)]
+pub struct Freezes {
+    windows: Vec<Window>,
+}
+
+impl Freezes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `freeze`, replacing the window on the same workspace or
+    /// document. Returns the replaced window if clients were told it started.
+    pub fn schedule(&mut self, freeze: Freeze) -> Option<Freeze> {
+        let replaced = self.remove(|window| window.same_target(&freeze));
+        self.windows.push(Window {
+            freeze,
+            announced: false,
+        });
+        replaced
+    }
+
+    /// Drops the window on the whole workspace (`path` `None`) or on one
+    /// document. Returns it, and whether clients were told it started.
+    pub fn unfreeze(&mut self, workspace: &str, path: Option<&str>) -> Option<(Freeze, bool)> {
+        let at = self.windows.iter().position(|window| {
+            window.freeze.workspace == workspace && window.freeze.path.as_deref() == path
+        })?;
+        let window = self.windows.remove(at);
+        Some((window.freeze, window.announced))
+    }
+
+    fn remove(&mut self, matches: impl Fn(&Freeze) -> bool) -> Option<Freeze> {
+        let at = self
+            .windows
+            .iter()
+            .position(|window| matches(&window.freeze))?;
+        let window = self.windows.remove(at);
+        window.announced.then_some(window.freeze)
+    }
+
+    /// Refuses an edit to the document at `path` in `workspace` while a
+    /// window covering it is open.
+    pub fn check(&self, workspace: &str, path: &str, now_ms: u64) -> Result<(), Rejection> {
+        match self.active(workspace, path, now_ms).next() {
+            Some(freeze) => Err(Rejection::Frozen {
+                target: freeze.target(),
+                ends_at_ms: freeze.ends_at_ms,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The open windows covering the document at `path` in `workspace`.
+    pub fn active<'a>(
+        &'a self,
+        workspace: &'a str,
+        path: &'a str,
+        now_ms: u64,
+    ) -> impl Iterator<Item = &'a Freeze> {
+        self.windows
+            .iter()
+            .map(|window| &window.freeze)
+            .filter(move |freeze| freeze.is_active(now_ms) && freeze.covers(workspace, path))
+    }
+
+    /// Every window, open or not, in the order they were set.
+    pub fn list(&self) -> Vec<Freeze> {
+        self.windows
+            .iter()
+            .map(|window| window.freeze.clone())
+            .collect()
+    }
+
+    /// Moves the windows on to `now_ms`: returns the ones that opened,
+    /// with `true`, and the ones that closed, with `false`, for clients to
+    /// be told. Closed windows are dropped.
+    pub fn tick(&mut self, now_ms: u64) -> Vec<(Freeze, bool)> {
+        let mut changed = Vec::new();
+        for window in &mut self.windows {
+            if !window.announced && window.freeze.is_active(now_ms) {
+                window.announced = true;
+                changed.push((window.freeze.clone(), true));
+            }
+        }
+        self.windows.retain(|window| {
+            if !window.freeze.is_over(now_ms) {
+                return true;
+            }
+            if window.announced {
+                changed.push((window.freeze.clone(), false));
+            }
+            false
+        });
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn freeze(path: Option<&str>, starts_at_ms: u64, ends_at_ms: Option<u64>) -> Freeze {
+        Freeze {
+            workspace: "docs".to_string(),
+            path: path.map(String::from),
+            starts_at_ms,
+            ends_at_ms,
+        }
+    }
+
+    #[test]
+    fn test_windows_open_and_close_on_schedule() {
+        let mut freezes = Freezes::new();
+        assert_eq!(freezes.schedule(freeze(None, 100, Some(200))), None);
+        freezes.schedule(freeze(Some("notes.txt"), 0, None));
+
+        // Only the document's window is open yet
+        assert_eq!(freezes.tick(50).len(), 1);
+        assert!(freezes.check("docs", "notes.txt", 50).is_err());
+        assert!(freezes.check("docs", "plan.txt", 50).is_ok());
+        assert!(freezes.check("other", "notes.txt", 50).is_ok());
+
+        assert_eq!(
+            freezes.tick(100),
+            vec![(freeze(None, 100, Some(200)), true)]
+        );
+        assert!(matches!(
+            freezes.check("docs", "plan.txt", 150),
+            Err(Rejection::Frozen {
+                ends_at_ms: Some(200),
+                ..
+            })
+        ));
+        assert_eq!(
+            freezes.tick(200),
+            vec![(freeze(None, 100, Some(200)), false)]
+        );
+        assert!(freezes.check("docs", "plan.txt", 200).is_ok());
+
+        let (unfrozen, announced) = freezes.unfreeze("docs", Some("notes.txt")).unwrap();
+        assert_eq!(
+            (unfrozen.path.as_deref(), announced),
+            (Some("notes.txt"), true)
+        );
+        assert!(freezes.list().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+use uuid::Uuid;
+
+use crate::autosave;
+use crate::documents::DocumentEntry;
+use crate::log::{error, info};
+
+/// Summary line of the commits the server makes on its own.
+pub const AUTOSAVE_SUMMARY: &str = "Autosave";
+
+/// Identity the server commits as; who made the edits is in the message.
+const COMMITTER: [&str; 4] = [
+    "-c",
+    "user.name=dist-space",
+    "-c",
+    "user.email=dist-space@localhost",
+];
+
+/// A commit made by `GitHistory`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub id: String,
+    /// Display names, or client ids, in the order they first edited.
+    pub contributors: Vec<String>,
+}
+
+/// Coarse history of the persisted documents: every so often, or when a
+/// client asks for a checkpoint, the git repository they are saved in is
+/// committed with a message naming who edited them since the last commit.
+/// The op logs keep the fine-grained history.
+pub struct GitHistory {
+    /// The repository's root, canonicalized.
+    repo: PathBuf,
+    /// Edits per author of each document as of the last commit, by path.
+    committed: HashMap<String, Vec<(Uuid, u64)>>,
+}
+
+impl GitHistory {
+    /// Keeps history in the repository at `repo`, creating it (and the
+    /// directory) if there is none yet.
+    pub fn open(repo: &Path) -> io::Result<Self> {
+        fs::create_dir_all(repo)?;
+        let repo = repo.canonicalize()?;
+        if !repo.join(".git").exists() {
+            git(&repo, &["init", "-q"])?;
+            info!("[Git] Created a repository in {}", repo.display());
+        }
+        Ok(Self {
+            repo,
+            committed: HashMap::new(),
+        })
+    }
+
+    /// Saves the persisted documents among `documents` that live in the
+    /// repository and commits everything changed in it, with `summary` as the
+    /// first line of the message (`AUTOSAVE_SUMMARY` if empty). Returns
+    /// `None` if there was nothing to commit.
+    pub fn commit(
+        &mut self,
+        documents: &[Arc<DocumentEntry>],
+        summary: &str,
+        label: impl Fn(Uuid) -> String,
+    ) -> io::Result<Option<Commit>> {
+        let mut authors: Vec<(Uuid, u64)> = Vec::new();
+        let mut edits = HashMap::new();
+        for entry in documents.iter().filter(|entry| self.tracks(entry)) {
+            if let Err(e) = autosave::save(entry) {
+                error!("[Git] Failed to save '{}': {}", entry.path, e);
+            }
+            let current = entry.stats().edits.clone();
+            let before = self.committed.get(&entry.path);
+            for &(author, count) in &current {
+                let committed = before
+                    .and_then(|before| before.iter().find(|(id, _)| *id == author))
+                    .map_or(0, |(_, count)| *count);
+                match authors.iter_mut().find(|(id, _)| *id == author) {
+                    Some((_, total)) => *total += count.saturating_sub(committed),
+                    None => authors.push((author, count.saturating_sub(committed))),
+                }
+            }
+            edits.insert(entry.path.clone(), current);
+        }
+
+        git(&self.repo, &["add", "-A"])?;
+        if git(&self.repo, &["status", "--porcelain"])?.is_empty() {
+            self.committed.extend(edits);
+            return Ok(None);
+        }
+        let contributors: Vec<String> = authors
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(author, _)| label(author))
+            .collect();
+        let message = message(summary, &contributors);
+        let mut args = COMMITTER.to_vec();
+        args.extend(["commit", "-q", "-m", &message]);
+        git(&self.repo, &args)?;
+        self.committed.extend(edits);
+
+        let id = git(&self.repo, &["rev-parse", "HEAD"])?;
+        info!(
+            "[Git] Committed {} ({} contributor(s))",
+            id,
+            contributors.len()
+        );
+        Ok(Some(Commit { id, contributors }))
+    }
+
+    /// Whether `entry` is saved inside the repository.
+    fn tracks(&self, entry: &DocumentEntry) -> bool {
+        let Some(file) = &entry.backing_file else {
+            return false;
+        };
+        // A file not saved yet has no canonical path; its directory does
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+        dir.map_or_else(|| Path::new(".").canonicalize(), Path::canonicalize)
+            .is_ok_and(|dir| dir.starts_with(&self.repo))
+    }
+}
+
+/// `summary`, then one line per contributor.
+fn message(summary: &str, contributors: &[String]) -> String {
+    let mut message = match summary.trim() {
+        "" => AUTOSAVE_SUMMARY.to_string(),
+        summary => summary.to_string(),
+    };
+    if !contributors.is_empty() {
+        message.push_str("\n\nContributors:\n");
+        for contributor in contributors {
+            message.push_str(&format!("- {}\n", contributor));
+        }
+    }
+    message
+}
+
+/// Runs git in `repo`. Returns its output, trimmed, or an error with what it
+/// printed if it failed.
+fn git(repo: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.iter().find(|arg| !arg.starts_with('-')).unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ServerState;
+    use common::operation::{InsertOp, OperationKind};
+
+    #[test]
+    fn test_commits_name_who_edited_since_the_last_one() {
+        let repo = std::env::temp_dir().join(format!("dist-space-git-{}", Uuid::new_v4()));
+        let mut history = GitHistory::open(&repo).unwrap();
+        let state = ServerState::new()
+            .with_backing_file(repo.join("main.txt"))
+            .unwrap();
+        let entry = state.documents().pop().unwrap();
+        let edit = |author: Uuid, text: &str| {
+            let op = OperationKind::Insert(InsertOp {
+                index: 0,
+                text: text.to_string(),
+                client_id: author.to_string(),
+                client_version: 0,
+            });
+            let mut doc = entry.document.lock().unwrap();
+            entry
+                .stats()
+                .record(&op, &doc.content, author, Default::default());
+            doc.apply_op(&op).unwrap();
+        };
+        let (ada, grace) = (Uuid::new_v4(), Uuid::new_v4());
+        let label = |id: Uuid| if id == ada { "ada" } else { "grace" }.to_string();
+
+        edit(ada, "a");
+        edit(grace, "b");
+        let first = history
+            .commit(&state.documents(), "", label)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.contributors, ["ada", "grace"]);
+        assert_eq!(history.commit(&state.documents(), "", label).unwrap(), None);
+
+        edit(grace, "c");
+        let second = history
+            .commit(&state.documents(), "Before the release", label)
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.contributors, ["grace"]);
+        let log = git(&repo, &["log", "--format=%B"]).unwrap();
+        assert!(log.starts_with("Before the release\n\nContributors:\n- grace"));
+        assert!(log.contains("Autosave\n\nContributors:\n- ada\n- grace"));
+        assert_eq!(fs::read_to_string(repo.join("main.txt")).unwrap(), "cba");
+
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
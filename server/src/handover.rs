@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use common::space::DocumentArchiveProto;
+use prost::Message;
+
+use crate::autosave;
+use crate::sha256;
+
+/// Content is cut where the rolling hash has these bits clear, about every
+/// 8 KiB, but never into chunks shorter than `MIN_CHUNK` or longer than
+/// `MAX_CHUNK`.
+const CHUNK_MASK: u64 = ((1 << 13) - 1) << 51;
+const MIN_CHUNK: usize = 2 * 1024;
+const MAX_CHUNK: usize = 64 * 1024;
+
+/// Writes every document, as `(workspace, archive)`, to `file` for the
+/// server taking over. Content is stored once per distinct chunk, keyed by
+/// its SHA-256, so identical documents, and the unchanged parts of similar
+/// large ones, take the space of one. The file is the number of chunks,
+/// the chunks, then each document as its workspace name, its archive
+/// without content and the indices of its content's chunks; every part is
+/// prefixed with its length, and numbers are big-endian u32s.
+pub fn save(file: &Path, documents: Vec<(String, DocumentArchiveProto)>) -> io::Result<()> {
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+    let mut stored: HashMap<[u8; 32], u32> = HashMap::new();
+    let mut parts = Vec::new();
+    for (workspace, mut archive) in documents {
+        let content = std::mem::take(&mut archive.content);
+        let mut indices = Vec::new();
+        for chunk in chunks_of(content.as_bytes()) {
+            let index = *stored.entry(sha256::digest(chunk)).or_insert_with(|| {
+                chunks.push(chunk.to_vec());
+                chunks.len() as u32 - 1
+            });
+            indices.write_u32::<BigEndian>(index)?;
+        }
+        parts.extend([workspace.into_bytes(), archive.encode_to_vec(), indices]);
+    }
+    let mut bytes = Vec::new();
+    bytes.write_u32::<BigEndian>(chunks.len() as u32)?;
+    for part in chunks.iter().chain(&parts) {
+        bytes.write_u32::<BigEndian>(part.len() as u32)?;
+        bytes.extend_from_slice(part);
+    }
+    autosave::write_atomically(file, &bytes)
+}
+
+/// The documents a server that handed over left in `file`, if it did, and
+/// removes it, so a later restart does not bring them back over newer edits.
+pub fn take(file: &Path) -> io::Result<Vec<(String, DocumentArchiveProto)>> {
+    let bytes = match fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let mut reader = bytes.as_slice();
+    let count = reader.read_u32::<BigEndian>()?;
+    let chunks = (0..count)
+        .map(|_| read_part(&mut reader))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut documents = Vec::new();
+    while !reader.is_empty() {
+        let workspace = String::from_utf8(read_part(&mut reader)?)
+            .map_err(|_| invalid("workspace name is not UTF-8"))?;
+        let mut archive = DocumentArchiveProto::decode(read_part(&mut reader)?.as_slice())
+            .map_err(|_| invalid("invalid document archive"))?;
+        let indices = read_part(&mut reader)?;
+        let mut indices = indices.as_slice();
+        let mut content = Vec::new();
+        while !indices.is_empty() {
+            let index = indices.read_u32::<BigEndian>()? as usize;
+            let chunk = chunks.get(index).ok_or_else(|| invalid("unknown chunk"))?;
+            content.extend_from_slice(chunk);
+        }
+        archive.content =
+            String::from_utf8(content).map_err(|_| invalid("document content is not UTF-8"))?;
+        documents.push((workspace, archive));
+    }
+    fs::remove_file(file)?;
+    Ok(documents)
+}
+
+fn read_part(reader: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32::<BigEndian>()? as usize;
+    if len > reader.len() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let mut part = vec![0; len];
+    reader.read_exact(&mut part)?;
+    Ok(part)
+}
+
+/// Splits `content` where a hash of the 64 bytes before says to, so the same
+/// text is cut the same way wherever it sits: an edit only changes the
+/// chunks around it.
+fn chunks_of(content: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let (mut start, mut hash) = (0, 0u64);
+    for (i, &byte) in content.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear(byte));
+        let len = i + 1 - start;
+        if len >= MAX_CHUNK || (len >= MIN_CHUNK && hash & CHUNK_MASK == 0) {
+            chunks.push(&content[start..=i]);
+            (start, hash) = (i + 1, 0);
+        }
+    }
+    if start < content.len() {
+        chunks.push(&content[start..]);
+    }
+    chunks
+}
+
+/// A fixed pseudo-random value for each byte (splitmix64).
+fn gear(byte: u8) -> u64 {
+    let mut z = (byte as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(path: &str, content: &str) -> DocumentArchiveProto {
+        DocumentArchiveProto {
+            path: path.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_identical_and_similar_content_is_stored_once() {
+        // Words from a fixed pseudo-random sequence, so chunks have edges
+        let mut seed = 1u64;
+        let words = ["alpha ", "beta ", "gamma\n", "delta ", "epsilon "];
+        let large: String = (0..40_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                words[(seed >> 33) as usize % words.len()]
+            })
+            .collect();
+        let edited = format!("{}an edit near the start{}", &large[..100], &large[100..]);
+        let documents = vec![
+            ("".to_string(), archive("a.txt", "hello")),
+            ("team-a".to_string(), archive("b.txt", "hello")),
+            ("".to_string(), archive("large.txt", &large)),
+            ("".to_string(), archive("edited.txt", &edited)),
+            ("".to_string(), archive("empty.txt", "")),
+        ];
+        assert!(chunks_of(large.as_bytes()).len() > 10);
+
+        let file =
+            std::env::temp_dir().join(format!("dist-space-handover-{}", uuid::Uuid::new_v4()));
+        save(&file, documents.clone()).unwrap();
+        let size = fs::metadata(&file).unwrap().len() as usize;
+        assert!(size < large.len() + large.len() / 4, "{} bytes", size);
+        assert_eq!(take(&file).unwrap(), documents);
+        assert!(!file.exists());
+        assert!(take(&file).unwrap().is_empty());
+    }
+}
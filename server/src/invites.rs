@@ -0,0 +1,125 @@
+use std::{collections::HashMap, time::Duration};
+
+use common::space::InviteProto;
+use uuid::Uuid;
+
+use crate::validation::Rejection;
+
+/// How long an invite is good for when its creator does not say.
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest an invite may be good for.
+pub const MAX_INVITE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Guest access to one document of a workspace, until it expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub workspace: String,
+    pub path: String,
+    pub read_only: bool,
+    /// Server wall clock, in ms since the Unix epoch.
+    pub expires_at_ms: u64,
+}
+
+impl Invite {
+    /// Refuses an invite that has expired by `now_ms`.
+    pub fn check(&self, now_ms: u64) -> Result<(), Rejection> {
+        if now_ms >= self.expires_at_ms {
+            return Err(Rejection::InviteExpired {
+                expired_ms_ago: now_ms - self.expires_at_ms,
+            });
+        }
+        Ok(())
+    }
+
+    /// Refuses opening any document but the invited one.
+    pub fn check_path(&self, path: &str) -> Result<(), Rejection> {
+        if path != self.path {
+            return Err(Rejection::GuestRestricted {
+                path: self.path.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_proto(&self, token: &str) -> InviteProto {
+        InviteProto {
+            token: token.to_string(),
+            workspace: self.workspace.clone(),
+            path: self.path.clone(),
+            read_only: self.read_only,
+            expires_at_ms: self.expires_at_ms,
+        }
+    }
+}
+
+/// Guest invites by token. Kept in memory only: a restart ends them all,
+/// which suits access meant to be short-lived.
+#[derive(Debug, Default)]
+pub struct InviteStore {
+    invites: HashMap<String, Invite>,
+}
+
+impl InviteStore {
+    /// Stores `invite` and returns its token, dropping the invites that
+    /// expired by `now_ms` on the way.
+    pub fn create(&mut self, invite: Invite, now_ms: u64) -> String {
+        self.invites
+            .retain(|_, invite| invite.expires_at_ms > now_ms);
+        let token = Uuid::new_v4().simple().to_string();
+        self.invites.insert(token.clone(), invite);
+        token
+    }
+
+    /// The invite `token` is for, if it is one for `workspace`, expired or
+    /// not.
+    pub fn get(&self, workspace: &str, token: &str) -> Option<&Invite> {
+        self.invites
+            .get(token)
+            .filter(|invite| invite.workspace == workspace)
+    }
+}
+
+/// When an invite created at `now_ms` asking to last `ttl_ms` expires: a day
+/// on for 0, and never more than `MAX_INVITE_TTL` on.
+pub fn expiry(now_ms: u64, ttl_ms: u64) -> u64 {
+    let ttl = match ttl_ms {
+        0 => DEFAULT_INVITE_TTL,
+        ms => Duration::from_millis(ms).min(MAX_INVITE_TTL),
+    };
+    now_ms + ttl.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invites_expire_and_stay_in_their_workspace() {
+        let mut invites = InviteStore::default();
+        let invite = Invite {
+            workspace: "team-a".to_string(),
+            path: "notes.txt".to_string(),
+            read_only: true,
+            expires_at_ms: expiry(1_000, 500),
+        };
+        let token = invites.create(invite.clone(), 1_000);
+        assert_eq!(invites.get("team-a", &token), Some(&invite));
+        assert_eq!(invites.get("team-b", &token), None);
+
+        assert!(invite.check(1_499).is_ok());
+        assert_eq!(
+            invite.check(1_600),
+            Err(Rejection::InviteExpired {
+                expired_ms_ago: 100
+            })
+        );
+        assert!(invite.check_path("other.txt").is_err());
+
+        // Dropped once a later invite is created past its expiry
+        invites.create(invite.clone(), 2_000);
+        assert_eq!(invites.get("team-a", &token), None);
+        assert_eq!(expiry(0, 0), DEFAULT_INVITE_TTL.as_millis() as u64);
+        assert_eq!(expiry(0, u64::MAX), MAX_INVITE_TTL.as_millis() as u64);
+    }
+}
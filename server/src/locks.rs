@@ -0,0 +1,244 @@
+use common::{
+    checked,
+    operation::OperationKind,
+    space::{RangeLockProto, RangeLocksProto},
+};
+use uuid::Uuid;
+
+use crate::validation::Rejection;
+
+/// An exclusive claim on `[start, end)` of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeLock {
+    pub id: u64,
+    pub owner: Uuid,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl RangeLock {
+    /// Whether an edit of `[start, end)` touches the locked text. An empty
+    /// range (an insert) only does so strictly inside the lock, so text can
+    /// still be added right before or after it.
+    fn blocks(&self, start: u32, end: u32) -> bool {
+        if start == end {
+            self.start < start && start < self.end
+        } else {
+            start < self.end && self.start < end
+        }
+    }
+
+    /// Moves the lock to where its text is after `op` was applied.
+    fn transform(&mut self, op: &OperationKind) {
+        (self.start, self.end) = transform_range(self.start, self.end, op);
+    }
+
+    pub fn to_proto(&self) -> RangeLockProto {
+        RangeLockProto {
+            lock_id: self.id,
+            client_id: self.owner.to_string(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+/// Where `[start, end)` is after `op` was applied. Inserts at the range's
+/// start go before it and at its end after it; a replace within the range
+/// keeps the new text inside.
+pub fn transform_range(start: u32, end: u32, op: &OperationKind) -> (u32, u32) {
+    match op {
+        OperationKind::Insert(insert) => {
+            let len = insert.text.len();
+            let (mut start, mut end) = (start, end);
+            if insert.index <= start {
+                start = checked::shift(start, len);
+            }
+            if insert.index < end {
+                end = checked::shift(end, len);
+            }
+            (start, end)
+        }
+        OperationKind::Delete(delete) => (
+            map_delete(start, delete.start, delete.end),
+            map_delete(end, delete.start, delete.end),
+        ),
+        OperationKind::Replace(replace) => {
+            let len = replace.text.len();
+            if start <= replace.start && replace.end <= end {
+                return (
+                    start,
+                    checked::shift(end - (replace.end - replace.start), len),
+                );
+            }
+            let mut start = map_delete(start, replace.start, replace.end);
+            let mut end = map_delete(end, replace.start, replace.end);
+            if replace.start < start {
+                start = checked::shift(start, len);
+            }
+            if replace.start < end {
+                end = checked::shift(end, len);
+            }
+            (start, end)
+        }
+        // Resolved into one of the above before they are applied
+        OperationKind::Noop(_)
+        | OperationKind::InsertLine(_)
+        | OperationKind::DeleteLine(_)
+        | OperationKind::MoveLine(_) => (start, end),
+    }
+}
+
+/// Where `position` ends up once `[start, end)` is deleted.
+fn map_delete(position: u32, start: u32, end: u32) -> u32 {
+    if position <= start {
+        position
+    } else if position >= end {
+        position - (end - start)
+    } else {
+        start
+    }
+}
+
+/// The range an op edits, `None` for ops that change nothing.
+fn edited_range(op: &OperationKind) -> Option<(u32, u32)> {
+    match op {
+        OperationKind::Insert(insert) => Some((insert.index, insert.index)),
+        OperationKind::Delete(delete) => Some((delete.start, delete.end)),
+        OperationKind::Replace(replace) => Some((replace.start, replace.end)),
+        OperationKind::Noop(_) => None,
+        OperationKind::InsertLine(insert) => Some((insert.index, insert.index)),
+        OperationKind::DeleteLine(delete) => Some((delete.start, delete.end)),
+        OperationKind::MoveLine(moved) => {
+            Some((moved.start.min(moved.to), moved.end.max(moved.to)))
+        }
+    }
+}
+
+/// The range locks held on one document. Positions are kept at the
+/// document's current version: callers transform them through every applied
+/// op while holding the document lock.
+#[derive(Debug, Default, Clone)]
+pub struct RangeLocks {
+    next_id: u64,
+    locks: Vec<RangeLock>,
+}
+
+impl RangeLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grants `owner` a lock on `[start, end)` unless another connection
+    /// holds an overlapping one.
+    pub fn acquire(&mut self, owner: Uuid, start: u32, end: u32) -> Result<&RangeLock, Rejection> {
+        if start >= end {
+            return Err(Rejection::InvalidRange { start, end });
+        }
+        if let Some(held) = self.conflict(owner, start, end) {
+            return Err(Rejection::RangeLocked {
+                start: held.start,
+                end: held.end,
+            });
+        }
+        self.next_id += 1;
+        self.locks.push(RangeLock {
+            id: self.next_id,
+            owner,
+            start,
+            end,
+        });
+        Ok(&self.locks[self.locks.len() - 1])
+    }
+
+    /// Releases one of `owner`'s locks. Returns whether it was held.
+    pub fn release(&mut self, owner: Uuid, id: u64) -> bool {
+        let before = self.locks.len();
+        self.locks
+            .retain(|lock| !(lock.id == id && lock.owner == owner));
+        self.locks.len() != before
+    }
+
+    /// Releases every lock `owner` holds. Returns how many there were.
+    pub fn release_all(&mut self, owner: Uuid) -> usize {
+        let before = self.locks.len();
+        self.locks.retain(|lock| lock.owner != owner);
+        before - self.locks.len()
+    }
+
+    /// Refuses an op by `author` that edits text another connection locked.
+    pub fn check(&self, author: Uuid, op: &OperationKind) -> Result<(), Rejection> {
+        let Some((start, end)) = edited_range(op) else {
+            return Ok(());
+        };
+        match self.conflict(author, start, end) {
+            Some(held) => Err(Rejection::RangeLocked {
+                start: held.start,
+                end: held.end,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves every lock through an applied op.
+    pub fn transform(&mut self, op: &OperationKind) {
+        for lock in &mut self.locks {
+            lock.transform(op);
+        }
+    }
+
+    fn conflict(&self, owner: Uuid, start: u32, end: u32) -> Option<&RangeLock> {
+        self.locks
+            .iter()
+            .find(|lock| lock.owner != owner && lock.blocks(start, end))
+    }
+
+    pub fn to_proto(&self, doc_id: &str, version: u64) -> RangeLocksProto {
+        RangeLocksProto {
+            doc_id: doc_id.to_string(),
+            version,
+            locks: self.locks.iter().map(RangeLock::to_proto).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locks_block_others_and_move_with_edits() {
+        let (alice, bob) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let mut locks = RangeLocks::new();
+        let id = locks.acquire(alice, 10, 20).unwrap().id;
+        assert!(locks.acquire(bob, 15, 25).is_err());
+
+        // Bob may edit around the lock but not inside it; Alice may
+        assert!(locks.check(bob, &OperationKind::insert_at(10, "x")).is_ok());
+        assert!(locks.check(bob, &OperationKind::insert_at(20, "x")).is_ok());
+        assert!(
+            locks
+                .check(bob, &OperationKind::insert_at(11, "x"))
+                .is_err()
+        );
+        assert!(
+            locks
+                .check(bob, &OperationKind::delete_range(5, 11))
+                .is_err()
+        );
+        assert!(
+            locks
+                .check(alice, &OperationKind::delete_range(12, 14))
+                .is_ok()
+        );
+
+        locks.transform(&OperationKind::insert_at(0, "abc"));
+        locks.transform(&OperationKind::delete_range(15, 17));
+        locks.transform(&OperationKind::insert_at(14, "hello"));
+        assert_eq!((locks.locks[0].start, locks.locks[0].end), (13, 26));
+
+        assert!(!locks.release(bob, id));
+        assert_eq!(locks.release_all(alice), 1);
+        assert!(locks.check(bob, &OperationKind::insert_at(15, "x")).is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+
+use crate::log_file::RotatingFile;
+
+/// How much the server logs. `Info` is connections, maintenance and
+/// periodic summaries; `Debug` adds a line per operation batch and `Trace`
+/// a line per frame read, written or broadcast. `Error` keeps only failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 0,
+    Info = 1,
+    Debug = 2,
+    Trace = 3,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Where log lines go; `None` is stdout (stderr for errors).
+static SINK: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Info,
+        2 => LogLevel::Debug,
+        _ => LogLevel::Trace,
+    }
+}
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Send log lines to `file` instead of stdout and stderr.
+pub fn log_to(file: RotatingFile) {
+    *lock_sink() = Some(file);
+}
+
+fn lock_sink() -> std::sync::MutexGuard<'static, Option<RotatingFile>> {
+    match SINK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Writes one line at `level`, if the current level lets it through. Use
+/// the `error!`, `info!` and `debug!` macros rather than calling this.
+pub fn write(level: LogLevel, args: fmt::Arguments<'_>) {
+    if level > self::level() {
+        return;
+    }
+    let mut sink = lock_sink();
+    match sink.as_mut() {
+        Some(file) => {
+            if let Err(e) = file.write_line(level, args) {
+                // Nowhere better to say so; keep the line rather than lose it
+                let _ = writeln!(io::stderr(), "[Log] Cannot write to log file: {}", e);
+                let _ = writeln!(io::stderr(), "{}", args);
+            }
+        }
+        None if level == LogLevel::Error => {
+            let _ = writeln!(io::stderr(), "{}", args);
+        }
+        None => {
+            let _ = writeln!(io::stdout(), "{}", args);
+        }
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::LogLevel::Trace, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, error, info, trace};
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "Unknown log level '{}' (error, info, debug, trace)",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "error"),
+            LogLevel::Info => write!(f, "info"),
+            LogLevel::Debug => write!(f, "debug"),
+            LogLevel::Trace => write!(f, "trace"),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use common::clock::unix_time_ms;
+
+use crate::log::LogLevel;
+
+/// When a log file is rotated, and how many rotated files are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate before the file would grow past this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Rotate once the file has been written to for this long.
+    pub max_age: Option<Duration>,
+    /// Rotated files kept as `<file>.1` (newest) to `<file>.<keep>`.
+    pub keep: usize,
+}
+
+/// A log file that is rotated according to its `RotationPolicy`: the file
+/// becomes `<file>.1`, `<file>.1` becomes `<file>.2` and so on, and the
+/// oldest beyond `keep` is overwritten.
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    file: File,
+    /// Bytes in the current file, including any from before it was opened.
+    written: u64,
+    opened_at: Instant,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: PathBuf, policy: RotationPolicy) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            policy,
+            file,
+            written,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// Appends one line, prefixed with the time and level, rotating first
+    /// if the policy says the line should start a new file.
+    pub fn write_line(&mut self, level: LogLevel, args: fmt::Arguments<'_>) -> io::Result<()> {
+        let line = format!(
+            "{} {:<5} {}\n",
+            unix_time_ms(),
+            level.to_string().to_uppercase(),
+            args
+        );
+        if self.rotation_due(line.len() as u64) {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// An empty file is never rotated, so a line longer than `max_bytes`
+    /// still gets written.
+    fn rotation_due(&self, next_len: u64) -> bool {
+        if self.written == 0 {
+            return false;
+        }
+        let too_big = self
+            .policy
+            .max_bytes
+            .is_some_and(|max_bytes| self.written + next_len > max_bytes);
+        let too_old = self
+            .policy
+            .max_age
+            .is_some_and(|max_age| self.opened_at.elapsed() >= max_age);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..self.policy.keep).rev() {
+            rename_if_exists(&self.numbered(n), &self.numbered(n + 1))?;
+        }
+        if self.policy.keep > 0 {
+            rename_if_exists(&self.path, &self.numbered(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_rotates_by_size_and_keeps_the_newest() {
+        let dir = std::env::temp_dir().join(format!("dist-space-logs-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let policy = RotationPolicy {
+            max_bytes: Some(32),
+            max_age: None,
+            keep: 2,
+        };
+        let mut log = RotatingFile::open(dir.join("server.log"), policy).unwrap();
+
+        // Each line is 27 bytes, so every line after the first rotates
+        for n in 0..4 {
+            log.write_line(LogLevel::Info, format_args!("line {}", n))
+                .unwrap();
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert!(read("server.log").ends_with("INFO  line 3\n"));
+        assert!(read("server.log.1").ends_with("line 2\n"));
+        assert!(read("server.log.2").ends_with("line 1\n"));
+        assert!(!dir.join("server.log.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,153 +1,637 @@
+mod activity;
+mod analytics;
+mod applied;
+mod archive;
+mod attachments;
+mod authorization;
+mod autosave;
+mod batcher;
 mod broadcaster;
+mod capabilities;
+mod capture;
 mod client_entry;
+mod compatibility;
+mod compliance;
+mod config;
+mod conflict;
+mod console;
+mod cursors;
+mod dead_letters;
+mod decoder;
+mod doc_ids;
+mod documents;
+mod error;
+mod events;
+mod export;
+mod freezes;
+mod git;
+mod handover;
+mod invites;
+mod locks;
+mod log;
+mod log_file;
+mod maintenance;
+mod markdown;
+mod membership;
+mod metrics;
+mod middleware;
+mod normalize;
+mod offline;
+mod overlays;
+mod plugins;
+mod propagation;
 mod reader;
+mod retention;
+mod seed;
+mod settings;
+mod sha256;
 mod state;
+mod stats;
+mod templates;
 mod transform;
+mod validation;
+mod worker;
+mod workspaces;
 mod writer;
 
-use common::protocol::ServerMessage;
-use std::net::TcpListener;
-use std::sync::Arc;
+use std::net::{TcpListener, TcpStream};
+use std::process;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use common::Frame;
-use common::proto::space::SyncDocumentProto;
-use uuid::Uuid;
+use common::clock::unix_time_ms;
+use common::ids;
+use common::protocol::ServerMessage;
+use common::space::{DisconnectReason, ErrorCode, ErrorProto};
+use crossbeam::channel::TrySendError;
 
+use crate::analytics::OpLogExporter;
+use crate::attachments::AttachmentStore;
+use crate::autosave::Autosave;
 use crate::broadcaster::broadcast;
-use crate::client_entry::ClientEntry;
+use crate::client_entry::{ClientEntry, WRITER_QUEUE_CAPACITY};
+use crate::config::{MaintenanceIntervals, ServerConfig};
+use crate::decoder::DecodePool;
+use crate::log::{debug, error, info};
+use crate::log_file::RotatingFile;
+use crate::maintenance::Scheduler;
+use crate::metrics::{AcceptMetrics, COMPACTIONS, EVICTIONS, RESUMES, TRAFFIC, WRITER_QUEUES};
+use crate::middleware::{Audit, RateLimit};
+use crate::plugins::WasmPlugin;
 use crate::reader::Reader;
-use crate::state::{ServerState, MAX_CLIENTS, HEARTBEAT_INTERVAL_MS};
+use crate::retention::RetentionPolicy;
+use crate::state::{
+    ACCEPT_QUEUE_CAPACITY, MAX_CLIENTS, SERVER_FULL_RETRY_AFTER_MS, ServerState, disconnect_frame,
+};
 use crate::writer::Writer;
 
 fn main() -> std::io::Result<()> {
+    let config = match ServerConfig::from_env() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    log::set_level(config.log.level);
+    if let Some(file) = &config.log.file {
+        match RotatingFile::open(file.clone(), config.log.rotation.clone()) {
+            Ok(file) => log::log_to(file),
+            Err(e) => {
+                eprintln!("Failed to open log file {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        }
+    }
+
     let listener = TcpListener::bind("127.0.0.1:8000")?;
+    let mut server_state = ServerState::new()
+        .with_batch_window(config.batch_window)
+        .with_idle_after(config.idle_after)
+        .with_conflict_policies(config.conflict_policies.clone())
+        .with_doc_ids(config.doc_ids.clone())
+        .with_missing_documents(config.missing_documents)
+        .with_normalization(config.normalization)
+        .with_workspace_quota(config.workspace_quota)
+        .with_handover(config.handover_to.clone())
+        .with_client_requirements(config.client_requirements.clone());
+    if let Some(per_second) = config.op_rate {
+        server_state = server_state.with_middleware(RateLimit::new(per_second));
+    }
+    for spec in &config.plugins {
+        server_state = match WasmPlugin::load(spec) {
+            Ok(plugin) => server_state.with_middleware(plugin),
+            Err(e) => {
+                error!("Failed to load plugin {}: {}", spec, e);
+                process::exit(2);
+            }
+        };
+    }
+    if config.audit_ops {
+        server_state = server_state.with_middleware(Audit);
+    }
+    if !config.op_rules.is_empty() {
+        info!("Op rules: {}", config.op_rules);
+        server_state = server_state.with_authorizer(config.op_rules.clone());
+    }
+    info!(
+        "Ops go through: {}",
+        server_state.middleware_names().join(", ")
+    );
+    if let Some(file) = &config.doc_file {
+        server_state = match server_state.with_backing_file(file.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    for seed in &config.seeds {
+        server_state = match seed.read(config.seed_max_bytes) {
+            Ok(content) => server_state.with_seed(&seed.path, content),
+            Err(e) => {
+                error!("Failed to seed '{}': {}", seed.path, e);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(dir) = &config.template_dir {
+        server_state = server_state.with_templates(dir.clone());
+    }
+    if let Some(file) = &config.members_file {
+        server_state = match server_state.with_members_file(file.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(file) = &config.events_file {
+        server_state = match server_state.with_events_file(file.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(file) = &config.handover_file {
+        server_state = match server_state.with_handover_file(file.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to take over {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(file) = &config.cursors_file {
+        server_state = match server_state.with_cursors_file(file.clone()) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to load {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    if let Some(file) = &config.compliance_file {
+        server_state = match server_state
+            .with_compliance_log(file.clone(), config.compliance_workspaces.clone())
+        {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to open {}: {}", file.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    server_state = server_state.with_offline_grace(config.offline_grace.unwrap_or_default());
+    if let Some(repo) = &config.git_repo {
+        server_state = match server_state.with_git_history(repo) {
+            Ok(state) => state,
+            Err(e) => {
+                error!("Failed to open git repository {}: {}", repo.display(), e);
+                process::exit(2);
+            }
+        };
+    }
+    let attachments = match &config.attach_dir {
+        Some(dir) => AttachmentStore::open(dir.clone(), config.attachment_limits, unix_time_ms()),
+        None => Ok(AttachmentStore::new(config.attachment_limits)),
+    };
+    server_state = match attachments {
+        Ok(store) => server_state.with_attachments(store),
+        Err(e) => {
+            error!("Failed to open the attachment directory: {}", e);
+            process::exit(2);
+        }
+    };
     // Wrap the server state in an Arc *once* outside the loop.
-    let server_state_arc = Arc::new(ServerState::new());
+    let server_state_arc = Arc::new(server_state);
     
     println!("═══════════════════════════════════════════════════════════");
     println!("  Dist-Space Server v0.1.0");
     println!("  Listening on 127.0.0.1:8000");
     println!("  Max clients: {}", MAX_CLIENTS);
-    println!("  Heartbeat interval: {}ms", HEARTBEAT_INTERVAL_MS);
+    match config.maintenance.ping {
+        Some(interval) => println!("  Heartbeat interval: {}ms", interval.as_millis()),
+        None => println!("  Heartbeat: disabled"),
+    }
+    if let Some(file) = &config.log.file {
+        println!("  Logging to {} ({})", file.display(), config.log.level);
+    }
+    println!("  Console: type 'help' for commands");
     println!("═══════════════════════════════════════════════════════════");
 
-    // Spawn heartbeat monitoring thread
-    let heartbeat_state = Arc::clone(&server_state_arc);
+    batcher::spawn_flusher(Arc::clone(&server_state_arc), broadcast);
+
+    // Readers hand frames to this pool for decoding and dispatch
+    let decode_threads = thread::available_parallelism().map_or(2, |n| n.get());
+    let decoder = Arc::new(DecodePool::spawn(
+        decode_threads,
+        Arc::clone(&server_state_arc),
+        broadcast,
+    ));
+
+    let exporter = config.oplog_export.clone().map(|file| {
+        info!("[Analytics] Exporting ops to {}", file.display());
+        Arc::new(Mutex::new(OpLogExporter::new(file)))
+    });
+
+    let mut scheduler = maintenance_scheduler(
+        &server_state_arc,
+        &config.maintenance,
+        config.retention.clone(),
+    );
+    if let Some(exporter) = &exporter {
+        let export_state = Arc::clone(&server_state_arc);
+        let exporter = Arc::clone(exporter);
+        scheduler = scheduler.every(
+            "op log export",
+            config.maintenance.oplog_export,
+            move || export_ops(&exporter, &export_state),
+        );
+    }
+    if config.git_repo.is_some() {
+        let history_state = Arc::clone(&server_state_arc);
+        scheduler = scheduler.every("git commit", config.maintenance.git_commit, move || {
+            if let Err(e) = history_state.commit_history(git::AUTOSAVE_SUMMARY) {
+                error!("[Git] {}", e);
+            }
+        });
+    }
+    scheduler.spawn();
+    spawn_shutdown_handler(Arc::clone(&server_state_arc), exporter.clone());
+    let console_state = Arc::clone(&server_state_arc);
+    console::spawn(Arc::clone(&server_state_arc), move || {
+        shutdown(&console_state, exporter.as_deref())
+    });
+
+    // Registration happens off the accept thread so a burst of connections
+    // queues up (bounded) instead of stalling the listener.
+    let (accept_tx, accept_rx) = crossbeam::channel::bounded::<TcpStream>(ACCEPT_QUEUE_CAPACITY);
+    let registration_state = Arc::clone(&server_state_arc);
     thread::spawn(move || {
-        run_heartbeat_loop(heartbeat_state);
+        for stream in accept_rx {
+            register_client(stream, &registration_state, &decoder);
+        }
     });
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
                 let peer_addr = stream.peer_addr().unwrap();
-                println!("\n[Server] New connection: {}", peer_addr);
-
-                // Check connection limit before proceeding
-                if server_state_arc.client_count() >= MAX_CLIENTS {
-                    eprintln!(
-                        "[Server] Connection rejected: max clients ({}) reached",
-                        MAX_CLIENTS
-                    );
-                    // Let the stream drop, closing the connection
-                    continue;
-                }
+                info!("[Server] New connection: {}", peer_addr);
+                let metrics = server_state_arc.accept_metrics();
+                AcceptMetrics::record(&metrics.accepted);
 
-                // Generate new client_id for incoming connection
-                let client_id = Uuid::new_v4();
-
-                // Create a bounded channel
-                let (tx, rx) = crossbeam::channel::bounded::<Arc<Frame>>(32);
-
-                // Clone the stream for the writer thread
-                let stream_writer = stream.try_clone()?;
-
-                // Get the authoritative Document type
-                let document = Arc::clone(&server_state_arc.get_document());
-
-                // Lock the document to access its fields
-                let (doc_id, content, version) = {
-                    let doc_guard = document.lock().unwrap();
-                    (
-                        doc_guard.uuid.to_string(),
-                        doc_guard.content.clone(),
-                        doc_guard.version,
-                    )
-                };
-
-                // Construct a new SyncDocument based on ServerMessage enum
-                let server_message = ServerMessage::SyncDocument(SyncDocumentProto {
-                    doc_id,
-                    content,
-                    version,
-                });
-
-                // Encode SyncDocument proto to Frame
-                let frame = ServerMessage::encode(&server_message);
-
-                // Spawn writer thread with its dedicated stream handle
-                let _ = Writer::spawn_writer_thread(client_id, stream_writer, rx);
-
-                // Immediately send a frame to the writer channel
-                tx.send(Frame::new_arc(frame))
-                    .expect("Failed to send frame to writer thread");
-
-                // Create a new client_entry
-                let client_entry = ClientEntry::new(client_id, tx);
-
-                // Add client_entry to server state
-                match server_state_arc.add_client(client_entry) {
-                    Ok(()) => {
-                        println!(
-                            "[Server] Client {} registered (total: {})",
-                            client_id,
-                            server_state_arc.client_count()
+                match accept_tx.try_send(stream) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(stream)) => {
+                        let shed = AcceptMetrics::record(&metrics.shed_overload);
+                        error!(
+                            "[Server] Shedding {}: accept queue full ({} shed so far)",
+                            peer_addr, shed
                         );
+                        reject_connection(stream, "Server overloaded, accept queue full");
                     }
-                    Err(e) => {
-                        eprintln!("[Server] Failed to add client: {}", e);
-                        continue;
+                    Err(TrySendError::Disconnected(_)) => {
+                        error!("[Server] Registration thread exited; stopping");
+                        break;
                     }
                 }
-
-                let state_clone = Arc::clone(&server_state_arc);
-
-                let _ = Reader::spawn_reader_thread(stream, client_id, state_clone, broadcast);
             }
             Err(e) => {
-                eprintln!("[Server] Connection failed: {}", e);
+                error!("[Server] Connection failed: {}", e);
             }
         }
     }
     Ok(())
 }
 
-/// Heartbeat monitoring loop.
-/// Periodically sends pings to all clients and removes timed-out clients.
-fn run_heartbeat_loop(state: Arc<ServerState>) {
+/// Registers an accepted connection and spawns its reader and writer threads,
+/// or turns it away with a server-full error if MAX_CLIENTS is reached.
+fn register_client(stream: TcpStream, state: &Arc<ServerState>, decoder: &Arc<DecodePool>) {
+    // Check connection limit before proceeding
+    if state.client_count() >= MAX_CLIENTS {
+        let rejected = AcceptMetrics::record(&state.accept_metrics().rejected_full);
+        error!(
+            "[Server] Connection rejected: max clients ({}) reached ({} rejected so far)",
+            MAX_CLIENTS, rejected
+        );
+        reject_connection(stream, "Server full");
+        return;
+    }
+
+    // Generate new client_id for incoming connection
+    let client_id = ids::new_uuid();
+
+    // Create a bounded channel
+    let (tx, rx) = crossbeam::channel::bounded::<Arc<Frame>>(WRITER_QUEUE_CAPACITY);
+
+    // Clone the stream for the writer thread
+    let stream_writer = match stream.try_clone() {
+        Ok(stream_writer) => stream_writer,
+        Err(e) => {
+            error!("[Server] Failed to clone stream: {}", e);
+            return;
+        }
+    };
+
+    // Kept by the client entry so a stuck connection can be hung up
+    let stream_handle = match stream.try_clone() {
+        Ok(stream_handle) => stream_handle,
+        Err(e) => {
+            error!("[Server] Failed to clone stream: {}", e);
+            return;
+        }
+    };
+
+    // Spawn writer thread with its dedicated stream handle
+    let writer = Writer::spawn_writer_thread(client_id, stream_writer, rx);
+
+    // Create a new client_entry
+    let client_entry = ClientEntry::new(client_id, tx).with_socket(stream_handle);
+
+    // Add client_entry to server state
+    match state.add_client(client_entry) {
+        Ok(()) => {
+            info!(
+                "[Server] Client {} registered (total: {})",
+                client_id,
+                state.client_count()
+            );
+        }
+        Err(e) => {
+            error!("[Server] Failed to add client: {}", e);
+            return;
+        }
+    }
+
+    let _ = Reader::spawn_reader_thread(
+        stream,
+        client_id,
+        writer,
+        Arc::clone(state),
+        Arc::clone(decoder),
+    );
+}
+
+/// Write timeout for the error frame sent to rejected connections.
+const REJECT_WRITE_TIMEOUT_MS: u64 = 200;
+
+/// Best-effort server-full error with a retry hint and a matching Disconnect,
+/// then the stream is dropped (closed).
+fn reject_connection(mut stream: TcpStream, reason: &str) {
+    let error = ServerMessage::Error(ErrorProto {
+        code: ErrorCode::ServerFull as i32,
+        message: reason.to_string(),
+        retry_after_ms: SERVER_FULL_RETRY_AFTER_MS,
+        op_id: 0,
+    });
+    let frame = Frame::new_arc(ServerMessage::encode(&error));
+
+    // Never let a peer that doesn't read stall the accept path.
+    let _ = stream.set_write_timeout(Some(Duration::from_millis(REJECT_WRITE_TIMEOUT_MS)));
+    let _ = frame.write_to(&mut stream).and_then(|()| {
+        disconnect_frame(DisconnectReason::ServerFull, reason).write_to(&mut stream)
+    });
+}
+
+/// Periodic housekeeping, each task on its configured interval.
+fn maintenance_scheduler(
+    state: &Arc<ServerState>,
+    intervals: &MaintenanceIntervals,
+    retention: RetentionPolicy,
+) -> Scheduler {
+    let ping_state = Arc::clone(state);
     let ping_sequence = AtomicU64::new(0);
-    
-    println!("[Heartbeat] Monitoring thread started");
-    
-    loop {
-        thread::sleep(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
-        
-        // Get next ping sequence number
-        let seq = ping_sequence.fetch_add(1, Ordering::Relaxed);
-        
-        // Remove timed-out clients
-        let removed = state.remove_timed_out_clients();
-        if removed > 0 {
-            println!("[Heartbeat] Removed {} timed-out client(s)", removed);
+    let sweep_state = Arc::clone(state);
+    let compact_state = Arc::clone(state);
+    let presence_state = Arc::clone(state);
+    let metrics_state = Arc::clone(state);
+    let metrics_interval = intervals.metrics.unwrap_or_default();
+    let autosave_state = Arc::clone(state);
+    let mut autosave = Autosave::new();
+    let attachment_state = Arc::clone(state);
+    let freeze_state = Arc::clone(state);
+    let activity_state = Arc::clone(state);
+    let sessions_state = Arc::clone(state);
+
+    Scheduler::new()
+        .every("ping", intervals.ping, move || {
+            let seq = ping_sequence.fetch_add(1, Ordering::Relaxed);
+            let pinged = ping_state.send_ping_to_all(seq);
+            if pinged > 0 {
+                debug!("[Heartbeat] Sent ping #{} to {} client(s)", seq, pinged);
+            }
+        })
+        .every("timeout sweep", intervals.timeout_sweep, move || {
+            let removed = sweep_state.remove_timed_out_clients();
+            if removed > 0 {
+                info!("[Heartbeat] Removed {} timed-out client(s)", removed);
+            }
+        })
+        .every("log compaction", intervals.log_compaction, move || {
+            let compacted = compact_state.compact_op_logs(&retention);
+            if compacted.total() > 0 {
+                info!(
+                    "[Maintenance] Compacted {} op log entries (versions={} age={} bytes={})",
+                    compacted.total(),
+                    compacted.versions,
+                    compacted.age,
+                    compacted.bytes
+                );
+            }
+        })
+        .every("presence", intervals.presence, move || {
+            let announced = presence_state.update_presence();
+            if announced > 0 {
+                info!("[Presence] {} connection(s) went idle", announced);
+            }
+        })
+        .every("autosave", intervals.autosave, move || {
+            autosave.tick(&autosave_state)
+        })
+        .every("offline sessions", intervals.autosave, move || {
+            let expired = sessions_state.expire_offline_sessions();
+            if expired > 0 {
+                info!("[Sessions] {} offline session(s) expired", expired);
+            }
+            sessions_state.save_cursors()
+        })
+        .every("attachment gc", intervals.attachment_gc, move || {
+            let (deleted, bytes) = attachment_state.collect_attachments();
+            if deleted > 0 {
+                info!(
+                    "[Attachments] Deleted {} unreferenced attachment(s), {} bytes",
+                    deleted, bytes
+                );
+            }
+        })
+        .every("freeze windows", intervals.freeze_windows, move || {
+            freeze_state.tick_freezes()
+        })
+        .every("activity", intervals.activity, move || {
+            let sent = activity_state.send_activity();
+            if sent > 0 {
+                debug!("[Activity] Sent activity to {} watcher(s)", sent);
+            }
+        })
+        .every("metrics", intervals.metrics, move || {
+            info!(
+                "[Metrics] Accept: {}",
+                metrics_state.accept_metrics().summary()
+            );
+            info!("[Metrics] Traffic: {}", TRAFFIC.report(metrics_interval));
+            info!("[Metrics] Evictions: {}", EVICTIONS.summary());
+            info!("[Metrics] Resumes: {}", RESUMES.summary());
+            info!("[Metrics] Op log compaction: {}", COMPACTIONS.summary());
+            let propagation = metrics_state.propagation();
+            if !propagation.is_empty() {
+                info!("[Metrics] Propagation: {}", propagation.summary());
+            }
+            info!(
+                "[Metrics] Writer queues: {}",
+                WRITER_QUEUES.report(
+                    metrics_interval,
+                    &metrics_state.writer_queue_depths(),
+                    WRITER_QUEUE_CAPACITY
+                )
+            );
+            for entry in metrics_state.documents() {
+                let queued = entry.queue.get().map_or(0, |queue| queue.len());
+                info!(
+                    "[Metrics] Document '{}': {}",
+                    entry.path,
+                    entry.metrics.report(metrics_interval, queued)
+                );
+            }
+        })
+}
+
+/// Appends newly applied ops to the op log export, logging failures.
+fn export_ops(exporter: &Mutex<OpLogExporter>, state: &ServerState) {
+    let mut exporter = match exporter.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if let Err(e) = exporter.export(state) {
+        error!("[Analytics] Failed to export ops: {}", e);
+    }
+}
+
+/// How long queued Disconnect frames get to reach clients before exiting.
+const SHUTDOWN_GRACE_MS: u64 = 200;
+
+/// How long a handover waits for queued ops to be applied before saving.
+const HANDOVER_DRAIN_MS: u64 = 2000;
+
+/// Runs `shutdown` on Ctrl-C or SIGTERM.
+fn spawn_shutdown_handler(state: Arc<ServerState>, exporter: Option<Arc<Mutex<OpLogExporter>>>) {
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!("[Server] Cannot listen for shutdown signals: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = runtime.block_on(wait_for_shutdown_signal()) {
+            error!("[Server] Cannot listen for shutdown signals: {}", e);
+            return;
         }
-        
-        // Send ping to all remaining clients
-        let pinged = state.send_ping_to_all(seq);
-        if pinged > 0 {
-            println!("[Heartbeat] Sent ping #{} to {} client(s)", seq, pinged);
+
+        shutdown(&state, exporter.as_deref());
+    });
+}
+
+/// Tells clients the server is going away, saves unsaved documents, exports
+/// any remaining ops and exits. With a handover target, ops are refused
+/// instead and clients only hear of it once everything is saved, when they
+/// are sent on to the replacement, or disconnected if the documents could not
+/// be left for it.
+fn shutdown(state: &ServerState, exporter: Option<&Mutex<OpLogExporter>>) -> ! {
+    info!("[Server] Shutting down");
+    let notified = match state.handover_target() {
+        Some(server) => {
+            info!("[Server] Handing documents over to {}", server);
+            let queued = state.begin_handover(Duration::from_millis(HANDOVER_DRAIN_MS));
+            if queued > 0 {
+                error!(
+                    "[Server] Handing over with {} op(s) still queued; they are lost",
+                    queued
+                );
+            }
+            None
+        }
+        None => Some(state.disconnect_all(DisconnectReason::Shutdown, "Server shutting down")),
+    };
+    let saved = autosave::save_all(state);
+    let notified = notified.or_else(|| match state.save_handover() {
+        Ok(left) => {
+            info!("[Server] Left {} document(s) for the new server", left);
+            None
+        }
+        // Sent on, clients would find their edits gone
+        Err(e) => {
+            error!("[Server] Cannot leave documents for the new server: {}", e);
+            Some(state.disconnect_all(DisconnectReason::Shutdown, "Server shutting down"))
         }
+    });
+    state.save_cursors();
+    if let Some(exporter) = exporter {
+        export_ops(exporter, state);
     }
+    let notified = notified.unwrap_or_else(|| state.redirect_all());
+    info!(
+        "[Server] Notified {} client(s), saved {} document(s)",
+        notified, saved
+    );
+    thread::sleep(Duration::from_millis(SHUTDOWN_GRACE_MS));
+    process::exit(0);
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
 }
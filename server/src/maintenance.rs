@@ -0,0 +1,120 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::log::info;
+
+/// A periodic job run by the `Scheduler`.
+struct Task {
+    name: &'static str,
+    interval: Duration,
+    next_run: Instant,
+    run: Box<dyn FnMut() + Send>,
+}
+
+/// Runs the server's periodic housekeeping (pings, timeout sweeps, log
+/// compaction, metrics, ...) on one thread, each task on its own interval.
+/// New periodic work is registered here instead of getting its own thread.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `task` every `interval`, the first time one interval from now.
+    /// A `None` interval leaves the task disabled.
+    pub fn every(
+        mut self,
+        name: &'static str,
+        interval: Option<Duration>,
+        task: impl FnMut() + Send + 'static,
+    ) -> Self {
+        match interval {
+            Some(interval) => {
+                info!("[Maintenance] '{}' every {}ms", name, interval.as_millis());
+                self.tasks.push(Task {
+                    name,
+                    interval,
+                    next_run: Instant::now() + interval,
+                    run: Box::new(task),
+                });
+            }
+            None => info!("[Maintenance] '{}' disabled", name),
+        }
+        self
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.tasks.iter().map(|task| task.next_run).min()
+    }
+
+    /// Runs every task due at `now` and schedules its next run. Returns the
+    /// names of the tasks that ran, in registration order.
+    fn run_due(&mut self, now: Instant) -> Vec<&'static str> {
+        let mut ran = Vec::new();
+        for task in self.tasks.iter_mut().filter(|task| task.next_run <= now) {
+            (task.run)();
+            // Skip missed runs rather than firing them back to back
+            while task.next_run <= now {
+                task.next_run += task.interval;
+            }
+            ran.push(task.name);
+        }
+        ran
+    }
+
+    /// Runs the tasks on a dedicated thread. Returns `None` if none are enabled.
+    pub fn spawn(mut self) -> Option<thread::JoinHandle<()>> {
+        self.next_due()?;
+        info!("[Maintenance] Scheduler started");
+        Some(thread::spawn(move || {
+            while let Some(next) = self.next_due() {
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+                self.run_due(Instant::now());
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    #[test]
+    fn test_tasks_run_on_their_own_intervals() {
+        let fast_runs = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&fast_runs);
+        let mut scheduler = Scheduler::new()
+            .every("fast", Some(Duration::from_secs(1)), move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .every("slow", Some(Duration::from_secs(3)), || {})
+            .every("off", None, || panic!("disabled task ran"));
+        let start = Instant::now();
+
+        assert!(scheduler.run_due(start).is_empty());
+        assert_eq!(scheduler.run_due(start + Duration::from_secs(1)), ["fast"]);
+        assert_eq!(scheduler.run_due(start + Duration::from_secs(2)), ["fast"]);
+        assert_eq!(
+            scheduler.run_due(start + Duration::from_secs(3)),
+            ["fast", "slow"]
+        );
+        assert_eq!(fast_runs.load(Ordering::Relaxed), 3);
+
+        // A stall longer than several intervals runs each task once
+        assert_eq!(
+            scheduler.run_due(start + Duration::from_secs(10)),
+            ["fast", "slow"]
+        );
+        assert!(scheduler.next_due().unwrap() > start + Duration::from_secs(10));
+    }
+}
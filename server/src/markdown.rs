@@ -0,0 +1,262 @@
+//! A small Markdown renderer for HTML exports: ATX headings, paragraphs,
+//! bullet and numbered lists, block quotes, fenced code, rules, and inline
+//! code, emphasis and links. Anything else comes out as text. Raw HTML in
+//! the document is escaped, never passed through.
+
+/// `markdown` as a standalone HTML page titled `title`.
+pub fn to_html_page(title: &str, markdown: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        to_html(markdown)
+    )
+}
+
+/// The block being built from consecutive lines.
+enum Block<'a> {
+    None,
+    Paragraph(Vec<&'a str>),
+    Quote(Vec<&'a str>),
+    /// The list's tag, and its items.
+    List(&'static str, Vec<&'a str>),
+}
+
+/// `markdown` rendered to HTML body elements, one per line.
+pub fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut block = Block::None;
+    let mut lines = markdown.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            close(&mut html, &mut block);
+            html.push_str("<pre><code>");
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                html.push_str(&escape(line));
+                html.push('\n');
+            }
+            html.push_str("</code></pre>\n");
+        } else if trimmed.is_empty() {
+            close(&mut html, &mut block);
+        } else if let Some((level, text)) = heading(trimmed) {
+            close(&mut html, &mut block);
+            html.push_str(&format!("<h{0}>{1}</h{0}>\n", level, inline(text)));
+        } else if is_rule(trimmed) {
+            close(&mut html, &mut block);
+            html.push_str("<hr>\n");
+        } else if let Some(text) = quote(trimmed) {
+            match &mut block {
+                Block::Quote(lines) => lines.push(text),
+                _ => {
+                    close(&mut html, &mut block);
+                    block = Block::Quote(vec![text]);
+                }
+            }
+        } else if let Some((tag, text)) = list_item(trimmed) {
+            match &mut block {
+                Block::List(open, items) if *open == tag => items.push(text),
+                _ => {
+                    close(&mut html, &mut block);
+                    block = Block::List(tag, vec![text]);
+                }
+            }
+        } else {
+            match &mut block {
+                Block::Paragraph(lines) => lines.push(trimmed),
+                _ => {
+                    close(&mut html, &mut block);
+                    block = Block::Paragraph(vec![trimmed]);
+                }
+            }
+        }
+    }
+    close(&mut html, &mut block);
+    html
+}
+
+/// Writes out `block` and leaves none open.
+fn close(html: &mut String, block: &mut Block) {
+    match std::mem::replace(block, Block::None) {
+        Block::None => {}
+        Block::Paragraph(lines) => {
+            html.push_str(&format!("<p>{}</p>\n", inline(&lines.join("\n"))));
+        }
+        Block::Quote(lines) => {
+            html.push_str(&format!(
+                "<blockquote><p>{}</p></blockquote>\n",
+                inline(&lines.join("\n"))
+            ));
+        }
+        Block::List(tag, items) => {
+            html.push_str(&format!("<{}>\n", tag));
+            for item in items {
+                html.push_str(&format!("<li>{}</li>\n", inline(item)));
+            }
+            html.push_str(&format!("</{}>\n", tag));
+        }
+    }
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level, text.trim_end_matches('#').trim()))
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|c| c == *mark))
+}
+
+fn quote(line: &str) -> Option<&str> {
+    line.strip_prefix('>').map(str::trim_start)
+}
+
+fn list_item(line: &str) -> Option<(&'static str, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(text) = line.strip_prefix(bullet) {
+            return Some(("ul", text));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let text = line[digits..].strip_prefix(". ")?;
+    (digits > 0).then_some(("ol", text))
+}
+
+/// Inline code, `**strong**`, `*emphasis*` or `_emphasis_` and
+/// `[links](url)` in `text`, with everything else escaped.
+fn inline(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        if c == '`'
+            && let Some(end) = after.find('`')
+        {
+            html.push_str(&format!("<code>{}</code>", escape(&after[..end])));
+            rest = &after[end + 1..];
+            continue;
+        }
+        if let Some(inner) = rest.strip_prefix("**")
+            && let Some(end) = inner.find("**").filter(|end| *end > 0)
+        {
+            html.push_str(&format!("<strong>{}</strong>", inline(&inner[..end])));
+            rest = &inner[end + 2..];
+            continue;
+        }
+        if (c == '*' || c == '_')
+            && let Some(end) = after.find(c).filter(|end| *end > 0)
+        {
+            html.push_str(&format!("<em>{}</em>", inline(&after[..end])));
+            rest = &after[end + 1..];
+            continue;
+        }
+        if c == '['
+            && let Some(close) = after.find("](")
+            && let Some(end) = after[close + 2..].find(')')
+        {
+            let label = &after[..close];
+            let url = &after[close + 2..close + 2 + end];
+            match is_safe_url(url) {
+                true => html.push_str(&format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape(url),
+                    inline(label)
+                )),
+                false => html.push_str(&inline(label)),
+            }
+            rest = &after[close + 2 + end + 1..];
+            continue;
+        }
+        html.push_str(&escape(&rest[..c.len_utf8()]));
+        rest = after;
+    }
+    html
+}
+
+/// Links that cannot run script when clicked.
+fn is_safe_url(url: &str) -> bool {
+    // Browsers skip whitespace and control characters in a scheme
+    let scheme = url.split_once(':').map(|(scheme, _)| {
+        scheme
+            .chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .collect::<String>()
+            .to_ascii_lowercase()
+    });
+    match scheme {
+        // A colon after a slash, `?` or `#` is not a scheme's
+        Some(scheme) if !scheme.contains(['/', '?', '#']) => {
+            ["http", "https", "mailto"].contains(&scheme.as_str())
+        }
+        _ => true,
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_and_inline_markup_render() {
+        let markdown = "\
+# Plan *v2*
+
+Ship the **export**
+with `<tags>` & [docs](https://example.com/?a=1&b=2).
+
+- one
+- two
+1. first
+
+> quoted
+---
+```
+<raw> stays text
+```
+[click](javascript:alert(1)) <script>";
+        assert_eq!(
+            to_html(markdown),
+            "\
+<h1>Plan <em>v2</em></h1>
+<p>Ship the <strong>export</strong>
+with <code>&lt;tags&gt;</code> &amp; <a href=\"https://example.com/?a=1&amp;b=2\">docs</a>.</p>
+<ul>
+<li>one</li>
+<li>two</li>
+</ul>
+<ol>
+<li>first</li>
+</ol>
+<blockquote><p>quoted</p></blockquote>
+<hr>
+<pre><code>&lt;raw&gt; stays text
+</code></pre>
+<p>click) &lt;script&gt;</p>
+"
+        );
+        assert!(to_html_page("a<b", "").contains("<title>a&lt;b</title>"));
+    }
+}
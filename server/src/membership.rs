@@ -0,0 +1,167 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use uuid::Uuid;
+
+use crate::log::error;
+
+/// Who may join each workspace: its members by name, each with the token
+/// they join with. A workspace without members is open to every connection.
+/// Saved to `file`, if there is one, after every change: one
+/// `workspace<TAB>member<TAB>token` line per member, and a line with just the
+/// name for a workspace whose members have all been removed.
+#[derive(Debug, Default)]
+pub struct MembershipStore {
+    /// Tokens by member name, by workspace.
+    workspaces: BTreeMap<String, BTreeMap<String, String>>,
+    file: Option<PathBuf>,
+}
+
+impl MembershipStore {
+    /// The members saved in `file`; none if it does not exist yet.
+    pub fn load(file: PathBuf) -> io::Result<Self> {
+        let mut store = Self::default();
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        for (number, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let members = store.workspaces.entry(fields[0].to_string()).or_default();
+            match fields[1..] {
+                [] => {}
+                [member, token] => {
+                    members.insert(member.to_string(), token.to_string());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}:{}: expected a workspace, member and token",
+                            file.display(),
+                            number + 1
+                        ),
+                    ));
+                }
+            }
+        }
+        store.file = Some(file);
+        Ok(store)
+    }
+
+    /// Whether any connection may join `workspace`, token or not.
+    pub fn is_open(&self, workspace: &str) -> bool {
+        !self.workspaces.contains_key(workspace)
+    }
+
+    /// The member of `workspace` whose token is `token`.
+    pub fn member(&self, workspace: &str, token: &str) -> Option<&str> {
+        self.workspaces
+            .get(workspace)?
+            .iter()
+            .find(|(_, member_token)| member_token.as_str() == token)
+            .map(|(member, _)| member.as_str())
+    }
+
+    /// Member names, sorted.
+    pub fn members(&self, workspace: &str) -> Vec<&str> {
+        self.workspaces
+            .get(workspace)
+            .map(|members| members.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Workspaces that have members, sorted.
+    pub fn workspaces(&self) -> Vec<&str> {
+        self.workspaces.keys().map(String::as_str).collect()
+    }
+
+    /// Makes `member` a member of `workspace`, closing it to everyone else if
+    /// it was open. Returns their token.
+    pub fn invite(&mut self, workspace: &str, member: &str) -> Result<String, String> {
+        if member.is_empty() {
+            return Err("a member needs a name".to_string());
+        }
+        if [workspace, member]
+            .iter()
+            .any(|name| name.contains(char::is_control))
+        {
+            return Err("names may not contain control characters".to_string());
+        }
+        let members = self.workspaces.entry(workspace.to_string()).or_default();
+        if members.contains_key(member) {
+            return Err(format!("'{}' is already a member", member));
+        }
+        let token = Uuid::new_v4().simple().to_string();
+        members.insert(member.to_string(), token.clone());
+        self.save();
+        Ok(token)
+    }
+
+    /// Takes `member` out of `workspace`. The workspace stays closed, even
+    /// once its last member is gone.
+    pub fn remove(&mut self, workspace: &str, member: &str) -> Result<(), String> {
+        let removed = self
+            .workspaces
+            .get_mut(workspace)
+            .and_then(|members| members.remove(member));
+        if removed.is_none() {
+            return Err(format!("'{}' is not a member", member));
+        }
+        self.save();
+        Ok(())
+    }
+
+    /// A failed save is logged; the change still holds until the server
+    /// restarts.
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut text = String::new();
+        for (workspace, members) in &self.workspaces {
+            if members.is_empty() {
+                text.push_str(&format!("{}\n", workspace));
+            }
+            for (member, token) in members {
+                text.push_str(&format!("{}\t{}\t{}\n", workspace, member, token));
+            }
+        }
+        if let Err(e) = fs::write(file, text) {
+            error!("[Membership] Cannot save {}: {}", file.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_members_survive_a_reload() {
+        let file = std::env::temp_dir().join(format!("dist-space-members-{}", Uuid::new_v4()));
+        let mut store = MembershipStore::load(file.clone()).unwrap();
+        assert!(store.is_open("team-a"));
+
+        let token = store.invite("team-a", "ada").unwrap();
+        store.invite("team-a", "grace").unwrap();
+        assert!(store.invite("team-a", "ada").is_err());
+        assert!(store.invite("team-a", "").is_err());
+        store.remove("team-a", "grace").unwrap();
+        assert!(store.remove("team-a", "grace").is_err());
+        let emptied = store.invite("team-b", "alan").unwrap();
+        store.remove("team-b", "alan").unwrap();
+
+        let store = MembershipStore::load(file.clone()).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(!store.is_open("team-a"));
+        assert!(!store.is_open("team-b"));
+        assert_eq!(store.member("team-b", &emptied), None);
+        assert_eq!(store.member("team-a", &token), Some("ada"));
+        assert_eq!(store.member("team-b", &token), None);
+        assert_eq!(store.members("team-a"), ["ada"]);
+    }
+}
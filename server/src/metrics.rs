@@ -0,0 +1,371 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use common::Frame;
+
+use crate::retention::Compacted;
+
+/// Counters for the accept path, reported by the heartbeat loop.
+#[derive(Default)]
+pub struct AcceptMetrics {
+    /// Connections taken off the listener.
+    pub accepted: AtomicU64,
+    /// Connections turned away because MAX_CLIENTS was reached.
+    pub rejected_full: AtomicU64,
+    /// Connections shed because the accept queue was full.
+    pub shed_overload: AtomicU64,
+}
+
+impl AcceptMetrics {
+    pub fn record(counter: &AtomicU64) -> u64 {
+        counter.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "accepted={} rejected_full={} shed_overload={}",
+            self.accepted.load(Ordering::Relaxed),
+            self.rejected_full.load(Ordering::Relaxed),
+            self.shed_overload.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Frames through the writer and broadcast paths, summed over every client.
+/// Logging these periodically replaces a log line per frame.
+pub static TRAFFIC: TrafficMetrics = TrafficMetrics::new();
+
+/// Counts since the previous `report`.
+pub struct TrafficMetrics {
+    frames_written: AtomicU64,
+    bytes_written: AtomicU64,
+    broadcast: AtomicU64,
+}
+
+impl TrafficMetrics {
+    pub const fn new() -> Self {
+        Self {
+            frames_written: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            broadcast: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_written(&self, frame: &Frame) {
+        self.frames_written.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(frame.total_len() as u64, Ordering::Relaxed);
+    }
+
+    /// A frame queued for one broadcast recipient.
+    pub fn record_broadcast(&self) {
+        self.broadcast.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Summary of the counts since the previous call, which resets them.
+    pub fn report(&self, interval: Duration) -> String {
+        let frames = self.frames_written.swap(0, Ordering::Relaxed);
+        format!(
+            "{:.1} frames/s written={} bytes={} broadcast={}",
+            frames as f64 / interval.as_secs_f64(),
+            frames,
+            self.bytes_written.swap(0, Ordering::Relaxed),
+            self.broadcast.swap(0, Ordering::Relaxed)
+        )
+    }
+}
+
+/// Why the server dropped a connection, as opposed to the peer closing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// Its writer channel was full when a broadcast came in.
+    SlowConsumer,
+    /// Its writer had already exited, usually after a write error.
+    WriterGone,
+    /// It stopped answering heartbeats.
+    TimedOut,
+    /// Removed from the console.
+    Kicked,
+}
+
+impl Eviction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Eviction::SlowConsumer => "slow consumer",
+            Eviction::WriterGone => "writer gone",
+            Eviction::TimedOut => "timed out",
+            Eviction::Kicked => "kicked",
+        }
+    }
+}
+
+/// Op log entries dropped by compaction since startup, by the retention
+/// limit that dropped them.
+pub static COMPACTIONS: CompactionMetrics = CompactionMetrics::new();
+
+pub struct CompactionMetrics {
+    versions: AtomicU64,
+    age: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl CompactionMetrics {
+    pub const fn new() -> Self {
+        Self {
+            versions: AtomicU64::new(0),
+            age: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, compacted: Compacted) {
+        self.versions
+            .fetch_add(compacted.versions as u64, Ordering::Relaxed);
+        self.age.fetch_add(compacted.age as u64, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(compacted.bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "versions={} age={} bytes={}",
+            self.versions.load(Ordering::Relaxed),
+            self.age.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Evictions since startup, by reason.
+pub static EVICTIONS: EvictionMetrics = EvictionMetrics::new();
+
+pub struct EvictionMetrics {
+    slow_consumer: AtomicU64,
+    writer_gone: AtomicU64,
+    timed_out: AtomicU64,
+    kicked: AtomicU64,
+}
+
+impl EvictionMetrics {
+    pub const fn new() -> Self {
+        Self {
+            slow_consumer: AtomicU64::new(0),
+            writer_gone: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            kicked: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, eviction: Eviction) -> u64 {
+        let counter = match eviction {
+            Eviction::SlowConsumer => &self.slow_consumer,
+            Eviction::WriterGone => &self.writer_gone,
+            Eviction::TimedOut => &self.timed_out,
+            Eviction::Kicked => &self.kicked,
+        };
+        AcceptMetrics::record(counter)
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "slow_consumer={} writer_gone={} timed_out={} kicked={}",
+            self.slow_consumer.load(Ordering::Relaxed),
+            self.writer_gone.load(Ordering::Relaxed),
+            self.timed_out.load(Ordering::Relaxed),
+            self.kicked.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// What became of clients coming back, since startup.
+pub static RESUMES: ResumeMetrics = ResumeMetrics::new();
+
+/// How a returning client, or one that never came back, was dealt with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Reconnected within the offline grace period and got its session back.
+    Session,
+    /// Stayed away past the grace period; its session and cursors are gone.
+    Expired,
+    /// Resumed a document from its cursor.
+    Document,
+    /// Asked to resume a document but had to be sent all of it.
+    FullResync,
+}
+
+pub struct ResumeMetrics {
+    sessions: AtomicU64,
+    expired: AtomicU64,
+    documents: AtomicU64,
+    full_resyncs: AtomicU64,
+}
+
+impl ResumeMetrics {
+    pub const fn new() -> Self {
+        Self {
+            sessions: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            documents: AtomicU64::new(0),
+            full_resyncs: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, resume: Resume) -> u64 {
+        let counter = match resume {
+            Resume::Session => &self.sessions,
+            Resume::Expired => &self.expired,
+            Resume::Document => &self.documents,
+            Resume::FullResync => &self.full_resyncs,
+        };
+        AcceptMetrics::record(counter)
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "sessions={} expired={} documents={} full_resyncs={}",
+            self.sessions.load(Ordering::Relaxed),
+            self.expired.load(Ordering::Relaxed),
+            self.documents.load(Ordering::Relaxed),
+            self.full_resyncs.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// Depth of one connection's writer queue, sampled as frames are queued, and
+/// the frames it refused.
+#[derive(Default)]
+pub struct WriterQueueMetrics {
+    /// Most frames seen queued at once.
+    high_watermark: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl WriterQueueMetrics {
+    pub fn record_depth(&self, depth: usize) {
+        self.high_watermark.fetch_max(depth, Ordering::Relaxed);
+        WRITER_QUEUES
+            .high_watermark
+            .fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        WRITER_QUEUES.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn high_watermark(&self) -> usize {
+        self.high_watermark.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Writer queues summed over every client since startup.
+pub static WRITER_QUEUES: WriterQueueTotals = WriterQueueTotals::new();
+
+pub struct WriterQueueTotals {
+    high_watermark: AtomicUsize,
+    dropped: AtomicU64,
+    /// `dropped` as of the previous report, for the drop rate.
+    reported: AtomicU64,
+}
+
+impl WriterQueueTotals {
+    pub const fn new() -> Self {
+        Self {
+            high_watermark: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            reported: AtomicU64::new(0),
+        }
+    }
+
+    /// Gauges over the current queue `depths`, one per client, out of
+    /// `capacity` each, and the totals since startup.
+    pub fn summary(&self, depths: &[usize], capacity: usize) -> String {
+        format!(
+            "queued={} deepest={}/{} high_watermark={} dropped={}",
+            depths.iter().sum::<usize>(),
+            depths.iter().max().copied().unwrap_or(0),
+            capacity,
+            self.high_watermark.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed)
+        )
+    }
+
+    /// `summary` with the drop rate since the previous call.
+    pub fn report(&self, interval: Duration, depths: &[usize], capacity: usize) -> String {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        let since_last = dropped - self.reported.swap(dropped, Ordering::Relaxed);
+        format!(
+            "{:.1} drops/s {}",
+            since_last as f64 / interval.as_secs_f64(),
+            self.summary(depths, capacity)
+        )
+    }
+}
+
+/// Per-document counters, updated by the document's worker thread.
+#[derive(Default)]
+pub struct DocumentMetrics {
+    /// Operations applied to the document.
+    pub applied: AtomicU64,
+    /// Operations refused with an error to the sender.
+    pub rejected: AtomicU64,
+    /// `applied` as of the previous report, for throughput.
+    reported: AtomicU64,
+}
+
+impl DocumentMetrics {
+    /// Summary line with the applied rate since the previous call.
+    pub fn report(&self, interval: Duration, queued: usize) -> String {
+        let applied = self.applied.load(Ordering::Relaxed);
+        let since_last = applied - self.reported.swap(applied, Ordering::Relaxed);
+        format!(
+            "{:.1} ops/s applied={} rejected={} queued={}",
+            since_last as f64 / interval.as_secs_f64(),
+            applied,
+            self.rejected.load(Ordering::Relaxed),
+            queued
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_report_resets() {
+        let traffic = TrafficMetrics::new();
+        traffic.record_written(&Frame::new_arc(vec![0; 6]));
+        traffic.record_written(&Frame::new_arc(Vec::new()));
+        traffic.record_broadcast();
+
+        assert_eq!(
+            traffic.report(Duration::from_secs(2)),
+            "1.0 frames/s written=2 bytes=14 broadcast=1"
+        );
+        assert_eq!(
+            traffic.report(Duration::from_secs(2)),
+            "0.0 frames/s written=0 bytes=0 broadcast=0"
+        );
+    }
+
+    #[test]
+    fn test_writer_queue_summary() {
+        let queues = WriterQueueTotals::new();
+        queues.high_watermark.store(9, Ordering::Relaxed);
+        queues.dropped.store(4, Ordering::Relaxed);
+
+        assert_eq!(
+            queues.report(Duration::from_secs(2), &[3, 0, 5], 32),
+            "2.0 drops/s queued=8 deepest=5/32 high_watermark=9 dropped=4"
+        );
+        assert_eq!(
+            queues.report(Duration::from_secs(2), &[], 32),
+            "0.0 drops/s queued=0 deepest=0/32 high_watermark=9 dropped=4"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use common::operation::OperationKind;
+use common::space::{OperationProto, operation_proto::Kind};
+use uuid::Uuid;
+
+use crate::log::info;
+use crate::validation::{Rejection, validate};
+
+/// Buckets kept for rate limiting before those of quiet connections are
+/// dropped.
+const MAX_RATE_BUCKETS: usize = 1024;
+
+/// The op a middleware is looking at, and where it comes from.
+pub struct OpContext<'a> {
+    /// The connection that sent the op.
+    pub origin: Uuid,
+    pub workspace: &'a str,
+    pub doc_id: &'a str,
+    pub path: &'a str,
+}
+
+/// A step every op goes through: `before_apply` once it is transformed to
+/// the document's current version, where it may be rejected or rewritten,
+/// and `after_apply` once it is applied and logged. Lets a deployment plug
+/// in its own policies, e.g. a profanity filter or a tighter size cap.
+pub trait Middleware: Send + Sync {
+    /// For logs.
+    fn name(&self) -> &str;
+
+    /// `content` is the text `op` is about to be applied to.
+    fn before_apply(
+        &self,
+        _context: &OpContext,
+        _op: &mut OperationKind,
+        _content: &str,
+    ) -> Result<(), Rejection> {
+        Ok(())
+    }
+
+    /// `applied` is the op as it is broadcast.
+    fn after_apply(&self, _context: &OpContext, _applied: &OperationProto) {}
+}
+
+/// The middlewares ops go through, in the order they were added. Validation
+/// always comes last, so whatever the others leave of an op is checked
+/// against the document before it is applied.
+pub struct MiddlewareChain {
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl Default for MiddlewareChain {
+    fn default() -> Self {
+        Self {
+            middlewares: vec![Box::new(Validation)],
+        }
+    }
+}
+
+impl MiddlewareChain {
+    /// Adds `middleware` ahead of validation.
+    pub fn push(&mut self, middleware: Box<dyn Middleware>) {
+        let validation = self.middlewares.len() - 1;
+        self.middlewares.insert(validation, middleware);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.middlewares.iter().map(|m| m.name()).collect()
+    }
+
+    /// Stops at the first middleware that rejects the op.
+    pub fn before_apply(
+        &self,
+        context: &OpContext,
+        op: &mut OperationKind,
+        content: &str,
+    ) -> Result<(), Rejection> {
+        self.middlewares
+            .iter()
+            .try_for_each(|middleware| middleware.before_apply(context, op, content))
+    }
+
+    pub fn after_apply(&self, context: &OpContext, applied: &OperationProto) {
+        for middleware in &self.middlewares {
+            middleware.after_apply(context, applied);
+        }
+    }
+}
+
+/// Refuses ops that don't fit the text they are applied to, so applying
+/// them can't panic.
+pub struct Validation;
+
+impl Middleware for Validation {
+    fn name(&self) -> &str {
+        "validation"
+    }
+
+    fn before_apply(
+        &self,
+        _context: &OpContext,
+        op: &mut OperationKind,
+        content: &str,
+    ) -> Result<(), Rejection> {
+        validate(op, content)
+    }
+}
+
+/// Holds each connection to `per_second` ops a second on average, in bursts
+/// of up to as many.
+pub struct RateLimit {
+    per_second: usize,
+    buckets: Mutex<HashMap<Uuid, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimit {
+    pub fn new(per_second: usize) -> Self {
+        Self {
+            per_second: per_second.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes an op's worth from `origin`'s bucket, or says how long until
+    /// there is one.
+    fn take(&self, origin: Uuid, now: Instant) -> Result<(), Rejection> {
+        let mut buckets = match self.buckets.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let full = self.per_second as f64;
+        if buckets.len() >= MAX_RATE_BUCKETS {
+            // Buckets a second old are full again; forgetting them changes nothing
+            buckets.retain(|_, bucket| {
+                now.duration_since(bucket.refilled_at) < Duration::from_secs(1)
+            });
+        }
+        let bucket = buckets.entry(origin).or_insert(Bucket {
+            tokens: full,
+            refilled_at: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * full).min(full);
+        bucket.refilled_at = now;
+        if bucket.tokens < 1.0 {
+            let retry_after = (1.0 - bucket.tokens) / full;
+            return Err(Rejection::RateLimited {
+                retry_after_ms: (retry_after * 1000.0).ceil() as u64,
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+impl Middleware for RateLimit {
+    fn name(&self) -> &str {
+        "rate limit"
+    }
+
+    fn before_apply(
+        &self,
+        context: &OpContext,
+        _op: &mut OperationKind,
+        _content: &str,
+    ) -> Result<(), Rejection> {
+        self.take(context.origin, Instant::now())
+    }
+}
+
+/// Logs every applied op: who sent it, to which document, and what it did.
+pub struct Audit;
+
+impl Middleware for Audit {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn after_apply(&self, context: &OpContext, applied: &OperationProto) {
+        info!(
+            "[Audit] {} '{}' ({}) v{} op {} by {}: {}",
+            context.origin,
+            context.path,
+            context.doc_id,
+            applied.server_version,
+            applied.op_id,
+            applied.client_id,
+            describe(applied.kind.as_ref())
+        );
+    }
+}
+
+fn describe(kind: Option<&Kind>) -> String {
+    match kind {
+        Some(Kind::Insert(insert)) => {
+            format!("insert {} bytes at {}", insert.text.len(), insert.index)
+        }
+        Some(Kind::Delete(delete)) => format!("delete {}..{}", delete.start, delete.end),
+        Some(Kind::Replace(replace)) => format!(
+            "replace {}..{} with {} bytes",
+            replace.start,
+            replace.end,
+            replace.text.len()
+        ),
+        Some(Kind::InsertLine(insert)) => {
+            format!(
+                "insert a line of {} bytes at {}",
+                insert.text.len(),
+                insert.index
+            )
+        }
+        Some(Kind::DeleteLine(delete)) => {
+            format!("delete lines {}..{}", delete.start, delete.end)
+        }
+        Some(Kind::MoveLine(moved)) => {
+            format!("move lines {}..{} to {}", moved.start, moved.end, moved.to)
+        }
+        Some(Kind::Noop(_)) | None => "nothing".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shout;
+
+    impl Middleware for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn before_apply(
+            &self,
+            _context: &OpContext,
+            op: &mut OperationKind,
+            _content: &str,
+        ) -> Result<(), Rejection> {
+            if let OperationKind::Insert(insert) = op {
+                insert.text = insert.text.to_uppercase();
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_added_middlewares_run_before_validation() {
+        let mut chain = MiddlewareChain::default();
+        chain.push(Box::new(Shout));
+        assert_eq!(chain.names(), ["shout", "validation"]);
+
+        let context = OpContext {
+            origin: Uuid::new_v4(),
+            workspace: "",
+            doc_id: "d1",
+            path: "notes.txt",
+        };
+        let mut op = OperationKind::insert_at(0, "hi");
+        chain.before_apply(&context, &mut op, "").unwrap();
+        assert_eq!(op, OperationKind::insert_at(0, "HI"));
+        assert!(matches!(
+            chain.before_apply(&context, &mut OperationKind::insert_at(5, "hi"), ""),
+            Err(Rejection::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rate_limit_refills_over_time() {
+        let limit = RateLimit::new(2);
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let start = Instant::now();
+        assert!(limit.take(alice, start).is_ok());
+        assert!(limit.take(alice, start).is_ok());
+        assert!(matches!(
+            limit.take(alice, start),
+            Err(Rejection::RateLimited {
+                retry_after_ms: 500
+            })
+        ));
+        // Each connection has its own bucket
+        assert!(limit.take(bob, start).is_ok());
+        assert!(
+            limit
+                .take(alice, start + Duration::from_millis(500))
+                .is_ok()
+        );
+    }
+}
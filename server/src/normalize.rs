@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+use common::operation::OperationKind;
+
+const BOM: char = '\u{feff}';
+
+/// Rewrites applied to text clients insert, before the op is transformed,
+/// so documents don't pick up a mix of conventions from different editors.
+/// Clients see the rewritten text in the op's broadcast and the next sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalization {
+    /// Turn `\r\n` into `\n`, except in documents whose settings ask for
+    /// CRLF line endings.
+    pub crlf_to_lf: bool,
+    /// Drop byte order marks, which editors add at the start of files and
+    /// which end up mid-document once pasted.
+    pub strip_bom: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            crlf_to_lf: false,
+            strip_bom: true,
+        }
+    }
+}
+
+impl Normalization {
+    /// Parses comma-separated rules: `crlf`, `bom`, or `none` for neither.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut normalization = Self {
+            crlf_to_lf: false,
+            strip_bom: false,
+        };
+        for rule in rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+        {
+            match rule.to_ascii_lowercase().as_str() {
+                "crlf" => normalization.crlf_to_lf = true,
+                "bom" => normalization.strip_bom = true,
+                "none" => {}
+                _ => {
+                    return Err(format!(
+                        "Unknown normalization '{}' (crlf, bom, none)",
+                        rule
+                    ));
+                }
+            }
+        }
+        Ok(normalization)
+    }
+
+    /// `text` as it should be inserted; `keep_crlf` for documents that use
+    /// CRLF line endings.
+    pub fn text<'a>(&self, text: &'a str, keep_crlf: bool) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if self.strip_bom && text.contains(BOM) {
+            text = Cow::Owned(text.replace(BOM, ""));
+        }
+        if self.crlf_to_lf && !keep_crlf && text.contains("\r\n") {
+            text = Cow::Owned(text.replace("\r\n", "\n"));
+        }
+        text
+    }
+
+    /// Rewrites the text `op` inserts, if any.
+    pub fn op(&self, op: &mut OperationKind, keep_crlf: bool) {
+        let text = match op {
+            OperationKind::Insert(insert) => &mut insert.text,
+            OperationKind::Replace(replace) => &mut replace.text,
+            OperationKind::InsertLine(insert) => &mut insert.text,
+            OperationKind::Delete(_)
+            | OperationKind::Noop(_)
+            | OperationKind::DeleteLine(_)
+            | OperationKind::MoveLine(_) => return,
+        };
+        if let Cow::Owned(normalized) = self.text(text, keep_crlf) {
+            *text = normalized;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_pick_the_rewrites() {
+        let all = Normalization::parse("crlf, bom").unwrap();
+        assert_eq!(all.text("\u{feff}a\r\nb\r", false), "a\nb\r");
+        assert_eq!(all.text("a\r\nb", true), "a\r\nb");
+        assert!(matches!(all.text("plain\n", false), Cow::Borrowed(_)));
+
+        let none = Normalization::parse("none").unwrap();
+        assert_eq!(none.text("\u{feff}a\r\n", false), "\u{feff}a\r\n");
+        assert_eq!(
+            Normalization::default().text("\u{feff}a\r\n", false),
+            "a\r\n"
+        );
+        assert!(Normalization::parse("tabs").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::HashMap, time::Duration};
+
+/// How long a disconnected client's session is kept for it to resume, by
+/// default.
+pub const OFFLINE_GRACE: Duration = Duration::from_secs(5 * 60);
+
+/// What a disconnected client leaves behind for its next connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfflineSession {
+    /// What its departure is told to the activity feed as, once the session
+    /// expires.
+    pub activity_name: String,
+    /// Whether it had said it was away, which its next connection is again.
+    pub away: bool,
+    pub departed_ms: u64,
+}
+
+/// Sessions of clients that disconnected, by the id from their Hello and
+/// workspace. A client that says Hello again under the same id before its
+/// session expires carries on where it left off: its acknowledgement cursors
+/// are still there and the workspace never saw it leave. Once the session
+/// expires it is gone for good and the client starts afresh.
+#[derive(Debug, Default)]
+pub struct OfflineSessions {
+    sessions: HashMap<(String, String), OfflineSession>,
+}
+
+impl OfflineSessions {
+    pub fn depart(&mut self, client: &str, workspace: &str, session: OfflineSession) {
+        self.sessions
+            .insert((client.to_string(), workspace.to_string()), session);
+    }
+
+    /// Takes the session `client` left in `workspace`, if it has not expired.
+    pub fn resume(&mut self, client: &str, workspace: &str) -> Option<OfflineSession> {
+        self.sessions
+            .remove(&(client.to_string(), workspace.to_string()))
+    }
+
+    /// Removes the sessions of clients that departed before `before_ms`,
+    /// returning them with their client and workspace.
+    pub fn expire(&mut self, before_ms: u64) -> Vec<(String, String, OfflineSession)> {
+        let expired: Vec<(String, String)> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.departed_ms < before_ms)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                let session = self.sessions.remove(&key)?;
+                Some((key.0, key.1, session))
+            })
+            .collect()
+    }
+
+    /// Clients and workspaces with a session waiting.
+    pub fn keys(&self) -> impl Iterator<Item = &(String, String)> {
+        self.sessions.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(departed_ms: u64) -> OfflineSession {
+        OfflineSession {
+            activity_name: "ada".to_string(),
+            away: true,
+            departed_ms,
+        }
+    }
+
+    #[test]
+    fn test_sessions_resume_once_until_they_expire() {
+        let mut sessions = OfflineSessions::default();
+        sessions.depart("laptop", "team-a", session(10));
+        sessions.depart("phone", "team-a", session(20));
+        assert_eq!(sessions.resume("laptop", "team-b"), None);
+        assert_eq!(sessions.resume("laptop", "team-a"), Some(session(10)));
+        assert_eq!(sessions.resume("laptop", "team-a"), None);
+
+        sessions.depart("laptop", "team-a", session(30));
+        let expired = sessions.expire(25);
+        assert_eq!(
+            expired,
+            vec![("phone".to_string(), "team-a".to_string(), session(20))]
+        );
+        assert_eq!(sessions.keys().count(), 1);
+        assert_eq!(sessions.resume("phone", "team-a"), None);
+    }
+}
@@ -0,0 +1,132 @@
+use common::{
+    operation::OperationKind,
+    space::{OverlayProto, OverlaysProto},
+};
+use uuid::Uuid;
+
+use crate::locks::transform_range;
+
+/// A decoration on `[start, end)` of a document, set by a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlay {
+    pub owner: Uuid,
+    pub kind: String,
+    pub start: u32,
+    pub end: u32,
+    pub payload: String,
+}
+
+impl Overlay {
+    pub fn to_proto(&self) -> OverlayProto {
+        OverlayProto {
+            client_id: self.owner.to_string(),
+            kind: self.kind.clone(),
+            start: self.start,
+            end: self.end,
+            payload: self.payload.clone(),
+        }
+    }
+}
+
+/// The overlays on one document. Like range locks, positions are kept at the
+/// document's current version, and nothing here is logged or persisted.
+#[derive(Debug, Default, Clone)]
+pub struct Overlays {
+    overlays: Vec<Overlay>,
+}
+
+impl Overlays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces `owner`'s overlays of `kind` with `overlays`, given as
+    /// `(start, end, payload)`.
+    pub fn set(&mut self, owner: Uuid, kind: &str, overlays: Vec<(u32, u32, String)>) {
+        self.overlays
+            .retain(|overlay| !(overlay.owner == owner && overlay.kind == kind));
+        self.overlays
+            .extend(overlays.into_iter().map(|(start, end, payload)| Overlay {
+                owner,
+                kind: kind.to_string(),
+                start,
+                end,
+                payload,
+            }));
+    }
+
+    /// Drops every overlay `owner` set. Returns how many there were.
+    pub fn clear_all(&mut self, owner: Uuid) -> usize {
+        let before = self.overlays.len();
+        self.overlays.retain(|overlay| overlay.owner != owner);
+        before - self.overlays.len()
+    }
+
+    /// Moves every overlay through an applied op. An overlay whose text was
+    /// all deleted goes with it; empty ones (markers between characters)
+    /// only move.
+    pub fn transform(&mut self, op: &OperationKind) {
+        self.overlays.retain_mut(|overlay| {
+            let was_empty = overlay.start == overlay.end;
+            (overlay.start, overlay.end) = transform_range(overlay.start, overlay.end, op);
+            was_empty || overlay.start < overlay.end
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    pub fn to_proto(&self, doc_id: &str, version: u64) -> OverlaysProto {
+        OverlaysProto {
+            doc_id: doc_id.to_string(),
+            version,
+            overlays: self.overlays.iter().map(Overlay::to_proto).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(overlays: &Overlays) -> Vec<(&str, u32, u32)> {
+        overlays
+            .overlays
+            .iter()
+            .map(|overlay| (overlay.kind.as_str(), overlay.start, overlay.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_overlays_are_replaced_per_kind_and_move_with_edits() {
+        let (bot, other) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let mut overlays = Overlays::new();
+        overlays.set(bot, "spelling", vec![(0, 3, "teh".to_string())]);
+        overlays.set(bot, "lint", vec![(10, 12, String::new())]);
+        overlays.set(other, "spelling", vec![(4, 6, String::new())]);
+        overlays.set(
+            bot,
+            "spelling",
+            vec![(1, 3, String::new()), (5, 5, String::new())],
+        );
+        assert_eq!(
+            ranges(&overlays),
+            vec![
+                ("lint", 10, 12),
+                ("spelling", 4, 6),
+                ("spelling", 1, 3),
+                ("spelling", 5, 5)
+            ]
+        );
+
+        // Deleting all of an overlay's text removes it; a marker only moves
+        overlays.transform(&OperationKind::insert_at(0, "ab"));
+        overlays.transform(&OperationKind::delete_range(2, 8));
+        assert_eq!(ranges(&overlays), vec![("lint", 6, 8), ("spelling", 2, 2)]);
+
+        assert_eq!(overlays.clear_all(other), 0);
+        assert_eq!(overlays.clear_all(bot), 2);
+        assert!(overlays.is_empty());
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use common::clock::Timestamp;
+use common::operation::{Operation, OperationKind};
+use common::space::OperationProto;
+use uuid::Uuid;
+use wasmi::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::analytics::op_line;
+use crate::log::{error, info};
+use crate::middleware::{Middleware, OpContext};
+use crate::validation::Rejection;
+
+/// Instructions a plugin may run per call before it is stopped.
+pub const PLUGIN_FUEL: u64 = 10_000_000;
+
+/// Largest a plugin's memory may grow.
+pub const PLUGIN_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Longest message a plugin may log or refuse an op with; the rest is cut.
+const MAX_PLUGIN_MESSAGE_BYTES: usize = 1024;
+
+/// A WASM module ops are run through, and the workspace it is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginSpec {
+    /// `None` for every workspace.
+    pub workspace: Option<String>,
+    pub file: PathBuf,
+}
+
+impl PluginSpec {
+    /// Parses comma-separated plugins: `FILE` for every workspace, or
+    /// `WORKSPACE=FILE`.
+    pub fn parse_list(plugins: &str) -> Result<Vec<PluginSpec>, String> {
+        plugins
+            .split(',')
+            .map(str::trim)
+            .filter(|plugin| !plugin.is_empty())
+            .map(|plugin| {
+                let (workspace, file) = match plugin.split_once('=') {
+                    Some((workspace, file)) => (Some(workspace.trim().to_string()), file.trim()),
+                    None => (None, plugin),
+                };
+                if file.is_empty() {
+                    return Err(format!("No file for plugin '{}'", plugin));
+                }
+                Ok(PluginSpec {
+                    workspace,
+                    file: PathBuf::from(file),
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for PluginSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.workspace {
+            Some(workspace) => write!(f, "{}={}", workspace, self.file.display()),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+/// What a plugin's host functions leave behind during a call.
+struct PluginState {
+    limits: StoreLimits,
+    /// Set by `env.refuse`.
+    refusal: Option<String>,
+    name: String,
+}
+
+struct Loaded {
+    store: Store<PluginState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_text: Option<TypedFunc<(i32, i32), i64>>,
+    on_applied: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+/// A middleware written in WebAssembly, run in a sandbox: it sees only what
+/// it is handed, its memory is capped at `PLUGIN_MEMORY_BYTES`, and each call
+/// may run for `PLUGIN_FUEL` instructions. The module exports:
+///
+/// - `memory`, and `alloc(len) -> ptr`, which the server writes text to;
+/// - optionally `on_text(ptr, len) -> i64`, given the text an op inserts.
+///   It returns 0 to leave it as it is, or `ptr << 32 | len` of the text to
+///   insert instead; calling `env.refuse(ptr, len)` refuses the op with
+///   that reason;
+/// - optionally `on_applied(ptr, len)`, given each applied op as a JSON
+///   line, like the op log export writes.
+///
+/// It may import `env.log(ptr, len)` to write to the server log. A plugin
+/// that traps or runs out of fuel refuses the op it was looking at.
+pub struct WasmPlugin {
+    name: String,
+    workspace: Option<String>,
+    loaded: Mutex<Loaded>,
+}
+
+impl WasmPlugin {
+    pub fn load(spec: &PluginSpec) -> Result<Self, String> {
+        let wasm = fs::read(&spec.file)
+            .map_err(|e| format!("cannot read {}: {}", spec.file.display(), e))?;
+        Self::from_bytes(&plugin_name(&spec.file), spec.workspace.clone(), &wasm)
+    }
+
+    /// Instantiates the module in `wasm`, binary or text.
+    pub fn from_bytes(name: &str, workspace: Option<String>, wasm: &[u8]) -> Result<Self, String> {
+        let fail = |e: wasmi::Error| format!("plugin '{}': {}", name, e);
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(fail)?;
+        let mut store = Store::new(
+            &engine,
+            PluginState {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(PLUGIN_MEMORY_BYTES)
+                    .build(),
+                refusal: None,
+                name: name.to_string(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(PLUGIN_FUEL).map_err(fail)?;
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap(
+                "env",
+                "log",
+                |caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+                    let message = read_message(&caller, ptr, len);
+                    info!("[Plugin {}] {}", caller.data().name, message);
+                },
+            )
+            .map_err(|e| format!("plugin '{}': {}", name, e))?;
+        linker
+            .func_wrap(
+                "env",
+                "refuse",
+                |mut caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+                    let reason = read_message(&caller, ptr, len);
+                    caller.data_mut().refusal = Some(reason);
+                },
+            )
+            .map_err(|e| format!("plugin '{}': {}", name, e))?;
+        let instance = linker
+            .instantiate_and_start(&mut store, &module)
+            .map_err(fail)?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| format!("plugin '{}' exports no memory", name))?;
+        let alloc = instance.get_typed_func(&store, "alloc").map_err(fail)?;
+        let on_text = optional_func(&instance, &store, "on_text").map_err(fail)?;
+        let on_applied = optional_func(&instance, &store, "on_applied").map_err(fail)?;
+        if on_text.is_none() && on_applied.is_none() {
+            return Err(format!(
+                "plugin '{}' exports neither on_text nor on_applied",
+                name
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            workspace,
+            loaded: Mutex::new(Loaded {
+                store,
+                memory,
+                alloc,
+                on_text,
+                on_applied,
+            }),
+        })
+    }
+
+    fn lock_loaded(&self) -> std::sync::MutexGuard<'_, Loaded> {
+        match self.loaded.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn applies_to(&self, workspace: &str) -> bool {
+        self.workspace
+            .as_deref()
+            .is_none_or(|name| name == workspace)
+    }
+
+    /// Runs `on_text` on `text`: `Ok(None)` keeps it, `Ok(Some(..))` replaces it.
+    fn check_text(&self, text: &str) -> Result<Option<String>, Rejection> {
+        let mut loaded = self.lock_loaded();
+        let Some(on_text) = loaded.on_text else {
+            return Ok(None);
+        };
+        let refuse = |reason: String| Rejection::Refused {
+            middleware: self.name.clone(),
+            reason,
+        };
+        let result = loaded
+            .pass(text)
+            .and_then(|(ptr, len)| on_text.call(&mut loaded.store, (ptr, len)))
+            .map_err(|e| {
+                error!("[Plugin {}] on_text failed: {}", self.name, e);
+                refuse("the plugin failed".to_string())
+            })?;
+        if let Some(reason) = loaded.store.data_mut().refusal.take() {
+            return Err(refuse(reason));
+        }
+        if result == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = ((result >> 32) as u32 as usize, result as u32 as usize);
+        let bytes = loaded
+            .memory
+            .data(&loaded.store)
+            .get(ptr..ptr.saturating_add(len))
+            .ok_or_else(|| refuse("the plugin returned text out of its memory".to_string()))?;
+        String::from_utf8(bytes.to_vec())
+            .map(Some)
+            .map_err(|_| refuse("the plugin returned text that is not UTF-8".to_string()))
+    }
+}
+
+impl Loaded {
+    /// Copies `text` into the plugin's memory, with fuel for the call that
+    /// follows.
+    fn pass(&mut self, text: &str) -> Result<(i32, i32), wasmi::Error> {
+        self.store.set_fuel(PLUGIN_FUEL)?;
+        self.store.data_mut().refusal = None;
+        let len = i32::try_from(text.len())
+            .map_err(|_| wasmi::Error::new("text too long for the plugin"))?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, text.as_bytes())
+            .map_err(|e| wasmi::Error::new(e.to_string()))?;
+        Ok((ptr, len))
+    }
+}
+
+impl Middleware for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn before_apply(
+        &self,
+        context: &OpContext,
+        op: &mut OperationKind,
+        _content: &str,
+    ) -> Result<(), Rejection> {
+        if !self.applies_to(context.workspace) {
+            return Ok(());
+        }
+        let text = match op {
+            OperationKind::Insert(insert) => &mut insert.text,
+            OperationKind::Replace(replace) => &mut replace.text,
+            OperationKind::InsertLine(insert) => &mut insert.text,
+            _ => return Ok(()),
+        };
+        if let Some(rewritten) = self.check_text(text)? {
+            *text = rewritten;
+        }
+        Ok(())
+    }
+
+    fn after_apply(&self, context: &OpContext, applied: &OperationProto) {
+        if !self.applies_to(context.workspace) {
+            return;
+        }
+        let mut loaded = self.lock_loaded();
+        let Some(on_applied) = loaded.on_applied else {
+            return;
+        };
+        let Some(operation) = to_operation(applied) else {
+            return;
+        };
+        let line = op_line(context.path, &operation);
+        if let Err(e) = loaded
+            .pass(&line)
+            .and_then(|(ptr, len)| on_applied.call(&mut loaded.store, (ptr, len)))
+        {
+            error!("[Plugin {}] on_applied failed: {}", self.name, e);
+        }
+    }
+}
+
+/// The file name without its extension.
+fn plugin_name(file: &Path) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file.display().to_string())
+}
+
+fn optional_func<Params, Results>(
+    instance: &Instance,
+    store: &Store<PluginState>,
+    name: &str,
+) -> Result<Option<TypedFunc<Params, Results>>, wasmi::Error>
+where
+    Params: wasmi::WasmParams,
+    Results: wasmi::WasmResults,
+{
+    match instance.get_export(store, name) {
+        Some(Extern::Func(_)) => instance.get_typed_func(store, name).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Up to `MAX_PLUGIN_MESSAGE_BYTES` of the plugin's memory at `ptr`, as text.
+fn read_message(caller: &Caller<'_, PluginState>, ptr: i32, len: i32) -> String {
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return String::new();
+    };
+    let start = ptr as u32 as usize;
+    let len = (len as u32 as usize).min(MAX_PLUGIN_MESSAGE_BYTES);
+    let bytes = memory
+        .data(caller)
+        .get(start..start.saturating_add(len))
+        .unwrap_or_default();
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn to_operation(applied: &OperationProto) -> Option<Operation> {
+    Some(Operation {
+        op_id: applied.op_id,
+        doc_id: applied.doc_id.clone(),
+        new_content: String::new(),
+        client_id: Uuid::parse_str(&applied.client_id).ok()?,
+        client_version: applied.client_version,
+        server_version: applied.server_version,
+        applied_at: Timestamp {
+            wall_ms: applied.applied_at_ms,
+            mono_ms: applied.applied_mono_ms,
+        },
+        kind: Operation::convert_operation(applied.clone())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Refuses text starting with "!", upper-cases the rest, and logs
+    /// applied ops.
+    const SHOUT: &str = r#"
+        (module
+          (import "env" "log" (func $log (param i32 i32)))
+          (import "env" "refuse" (func $refuse (param i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "no bangs")
+          (func (export "alloc") (param $len i32) (result i32) (i32.const 1024))
+          (func (export "on_text") (param $ptr i32) (param $len i32) (result i64)
+            (local $i i32)
+            (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33))
+              (then (call $refuse (i32.const 0) (i32.const 8)) (return (i64.const 0))))
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+                (i32.store8
+                  (i32.add (local.get $ptr) (local.get $i))
+                  (i32.sub (i32.load8_u (i32.add (local.get $ptr) (local.get $i))) (i32.const 32)))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len))))
+          (func (export "on_applied") (param $ptr i32) (param $len i32)
+            (call $log (local.get $ptr) (local.get $len))))
+    "#;
+
+    const SPIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "on_text") (param i32 i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    fn context(workspace: &str) -> OpContext<'_> {
+        OpContext {
+            origin: Uuid::new_v4(),
+            workspace,
+            doc_id: "d1",
+            path: "notes.txt",
+        }
+    }
+
+    #[test]
+    fn test_plugins_rewrite_and_refuse_ops_in_their_workspace() {
+        let plugin =
+            WasmPlugin::from_bytes("shout", Some("docs".to_string()), SHOUT.as_bytes()).unwrap();
+        let mut op = OperationKind::insert_at(0, "hi");
+        plugin.before_apply(&context("docs"), &mut op, "").unwrap();
+        assert_eq!(op, OperationKind::insert_at(0, "HI"));
+        match plugin.before_apply(
+            &context("docs"),
+            &mut OperationKind::insert_at(0, "!hi"),
+            "",
+        ) {
+            Err(Rejection::Refused { middleware, reason }) => {
+                assert_eq!(
+                    (middleware.as_str(), reason.as_str()),
+                    ("shout", "no bangs")
+                );
+            }
+            other => panic!("expected a refusal, got {:?}", other),
+        }
+        // Other workspaces are left alone
+        let mut op = OperationKind::insert_at(0, "hi");
+        plugin.before_apply(&context("other"), &mut op, "").unwrap();
+        assert_eq!(op, OperationKind::insert_at(0, "hi"));
+    }
+
+    #[test]
+    fn test_plugins_that_run_away_refuse_the_op() {
+        let plugin = WasmPlugin::from_bytes("spin", None, SPIN.as_bytes()).unwrap();
+        assert!(matches!(
+            plugin.before_apply(&context(""), &mut OperationKind::insert_at(0, "hi"), ""),
+            Err(Rejection::Refused { .. })
+        ));
+        assert!(WasmPlugin::from_bytes("empty", None, b"(module)").is_err());
+    }
+
+    #[test]
+    fn test_parse_plugin_list() {
+        assert_eq!(
+            PluginSpec::parse_list("filter.wasm, docs = notify.wasm"),
+            Ok(vec![
+                PluginSpec {
+                    workspace: None,
+                    file: PathBuf::from("filter.wasm"),
+                },
+                PluginSpec {
+                    workspace: Some("docs".to_string()),
+                    file: PathBuf::from("notify.wasm"),
+                },
+            ])
+        );
+        assert!(PluginSpec::parse_list("docs=").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use common::clock::ClockSample;
+use common::space::PropagationSampleProto;
+
+/// Upper edges of the latency heatmap's buckets, in ms; a last bucket holds
+/// everything slower.
+pub const BUCKET_EDGES_MS: [u64; 7] = [10, 25, 50, 100, 250, 500, 1000];
+
+/// Latencies kept for percentiles; older ones only count in the buckets.
+const RECENT_SAMPLES: usize = 256;
+
+/// How long traced ops took, in ms.
+#[derive(Debug, Clone, Default)]
+pub struct Latencies {
+    recent: VecDeque<u64>,
+    buckets: [u64; BUCKET_EDGES_MS.len() + 1],
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency_ms: u64) {
+        if self.recent.len() == RECENT_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(latency_ms);
+        let bucket = BUCKET_EDGES_MS
+            .iter()
+            .position(|edge| latency_ms < *edge)
+            .unwrap_or(BUCKET_EDGES_MS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    /// Every latency recorded, including those no longer recent.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Adds `other`'s latencies, for totals over several connections.
+    pub fn merge(&mut self, other: &Latencies) {
+        self.recent.extend(&other.recent);
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += count;
+        }
+    }
+
+    /// The `percent`th percentile of the recent latencies, by nearest rank.
+    pub fn percentile(&self, percent: usize) -> Option<u64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (percent * sorted.len()).div_ceil(100).max(1);
+        Some(sorted[rank.min(sorted.len()) - 1])
+    }
+
+    /// p50/p90/p99 and the count in each bucket, for logs and the console.
+    pub fn summary(&self) -> String {
+        let [p50, p90, p99] = [50, 90, 99].map(|percent| match self.percentile(percent) {
+            Some(ms) => format!("{}ms", ms),
+            None => "?".to_string(),
+        });
+        let heatmap = BUCKET_EDGES_MS
+            .iter()
+            .map(|edge| format!("<{}", edge))
+            .chain([format!(">={}", BUCKET_EDGES_MS[BUCKET_EDGES_MS.len() - 1])])
+            .zip(self.buckets)
+            .map(|(bucket, count)| format!("{}:{}", bucket, count))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "p50={} p90={} p99={} n={} [{}]",
+            p50,
+            p90,
+            p99,
+            self.count(),
+            heatmap
+        )
+    }
+}
+
+/// How long the ops one connection traced took to reach it, and to be
+/// applied once there, from when the server applied them.
+#[derive(Debug, Clone, Default)]
+pub struct Propagation {
+    pub received: Latencies,
+    pub applied: Latencies,
+}
+
+impl Propagation {
+    /// Records `sample`, taken on a client clock `clock.offset_ms` behind the
+    /// server's. The times are the client's word, so a sample with one past
+    /// `i64::MAX` is dropped.
+    pub fn record(&mut self, sample: &PropagationSampleProto, clock: ClockSample) {
+        let since_applied = |client_ms: u64| {
+            let on_server = i64::try_from(client_ms)
+                .ok()?
+                .saturating_add(clock.offset_ms);
+            let applied_at = i64::try_from(sample.applied_at_ms).ok()?;
+            Some(on_server.saturating_sub(applied_at).max(0) as u64)
+        };
+        let received = since_applied(sample.received_ms);
+        let applied = since_applied(sample.displayed_ms.max(sample.received_ms));
+        if let (Some(received), Some(applied)) = (received, applied) {
+            self.received.record(received);
+            self.applied.record(applied);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Propagation) {
+        self.received.merge(&other.received);
+        self.applied.merge(&other.applied);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.received.count() == 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "received {}; applied {}",
+            self.received.summary(),
+            self.applied.summary()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(applied_at_ms: u64, received_ms: u64, displayed_ms: u64) -> PropagationSampleProto {
+        PropagationSampleProto {
+            doc_id: "d1".to_string(),
+            server_version: 16,
+            applied_at_ms,
+            received_ms,
+            displayed_ms,
+        }
+    }
+
+    #[test]
+    fn test_latencies_are_taken_on_the_server_clock() {
+        let mut propagation = Propagation::default();
+        // The client's clock is 100ms behind the server's
+        let clock = ClockSample {
+            offset_ms: 100,
+            rtt_ms: 20,
+        };
+        propagation.record(&sample(1_000, 912, 915), clock);
+        propagation.record(&sample(2_000, 1_930, 1_990), clock);
+
+        assert_eq!(propagation.received.percentile(50), Some(12));
+        assert_eq!(propagation.received.percentile(99), Some(30));
+        assert_eq!(propagation.applied.percentile(50), Some(15));
+        assert_eq!(
+            propagation.applied.summary(),
+            "p50=15ms p90=90ms p99=90ms n=2 [<10:0 <25:1 <50:0 <100:1 <250:0 <500:0 <1000:0 >=1000:0]"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_samples_are_dropped() {
+        let mut propagation = Propagation::default();
+        let skewed = ClockSample {
+            offset_ms: i64::MAX,
+            rtt_ms: 20,
+        };
+        propagation.record(&sample(0, i64::MAX as u64, i64::MAX as u64), skewed);
+        assert_eq!(propagation.received.percentile(50), Some(i64::MAX as u64));
+        let behind = ClockSample {
+            offset_ms: i64::MIN,
+            rtt_ms: 20,
+        };
+        propagation.record(&sample(i64::MAX as u64, 0, 0), behind);
+        assert_eq!(propagation.received.count(), 2);
+
+        // Times past i64::MAX are not recorded at all
+        propagation.record(&sample(1_000, u64::MAX, 1_000), skewed);
+        propagation.record(&sample(u64::MAX, 1_000, 1_000), skewed);
+        assert_eq!(propagation.received.count(), 2);
+        assert_eq!(propagation.applied.count(), 2);
+    }
+
+    #[test]
+    fn test_percentiles_cover_recent_samples_only() {
+        let mut latencies = Latencies::default();
+        for _ in 0..RECENT_SAMPLES {
+            latencies.record(2_000);
+        }
+        for _ in 0..RECENT_SAMPLES {
+            latencies.record(5);
+        }
+        assert_eq!(latencies.percentile(99), Some(5));
+        assert_eq!(latencies.count(), 2 * RECENT_SAMPLES as u64);
+    }
+}
@@ -1,37 +1,77 @@
 // reader/src/lib.rs
-use std::io::Read;
+use std::io::{ErrorKind, Read};
 use std::net::TcpStream;
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use common::error::FrameError;
 use common::frame::Frame;
-use common::protocol::ServerMessage;
+use common::protocol::MSG_TYPE_PONG;
+use common::transport::ReadTimeout;
 
-use crate::ClientEntry;
+use crate::capture::{CAPTURES, Direction};
+use crate::decoder::DecodePool;
+use crate::error::ServerError;
+use crate::log::{error, info};
 use crate::state::ServerState;
 use uuid::Uuid;
 
 pub struct Reader;
 
-type BroadcastFn =
-    fn(origin_id: Uuid, frame: Arc<Frame>, clients: Arc<std::sync::Mutex<Vec<Arc<ClientEntry>>>>);
+/// Largest payload accepted from a client.
+const MAX_PAYLOAD_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Deadlines that stop a peer from pinning a reader thread by sending a
+/// length prefix and then trickling (or withholding) the rest of the frame.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadLimits {
+    /// How long a single read may wait for bytes once a frame has started.
+    pub stall_timeout: Duration,
+    /// Stalls tolerated within one frame before the peer is disconnected.
+    pub max_stalls: u32,
+    /// Upper bound for receiving a whole frame, however slowly bytes arrive.
+    pub frame_deadline: Duration,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_millis(2_000),
+            max_stalls: 3,
+            frame_deadline: Duration::from_millis(10_000),
+        }
+    }
+}
+
+/// Per-frame bookkeeping for `ReadLimits`.
+#[derive(Default)]
+struct FrameProgress {
+    started: Option<Instant>,
+    stalls: u32,
+}
+
 
 impl Reader {
     /// Reads exactly one length-prefixed frame from the stream.
     /// Returns Arc<Frame> for zero-copy broadcast.
-    pub fn read_frame(stream: &mut TcpStream) -> Result<Arc<Frame>, FrameError> {
-        const MAX_PAYLOAD_SIZE: usize = 1024 * 1024; // 1MB
+    pub fn read_frame<R: ReadTimeout>(stream: &mut R) -> Result<Arc<Frame>, FrameError> {
+        Reader::read_frame_with(stream, &ReadLimits::default())
+    }
+
+    /// `read_frame` with explicit slow-peer limits. Waiting for a frame to start
+    /// is unbounded (idle clients are the heartbeat's concern), but once its
+    /// first byte arrives the rest must follow within `limits`.
+    pub fn read_frame_with<R: ReadTimeout>(
+        stream: &mut R,
+        limits: &ReadLimits,
+    ) -> Result<Arc<Frame>, FrameError> {
+        stream.set_read_timeout(Some(limits.stall_timeout))?;
+        let mut progress = FrameProgress::default();
 
         // Read prefix (length)
         let mut prefix = [0u8; 4];
-        stream.read_exact(&mut prefix).map_err(|e| {
-            if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                FrameError::Disconnected
-            } else {
-                FrameError::Io(e)
-            }
-        })?;
+        Reader::read_exact_within(stream, &mut prefix, limits, &mut progress)?;
 
         let length = u32::from_be_bytes(prefix) as usize;
 
@@ -47,28 +87,76 @@ impl Reader {
 
         // Read payload
         let mut payload = vec![0u8; length];
-        stream.read_exact(&mut payload).map_err(|e| {
-            if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                FrameError::Disconnected
-            } else {
-                FrameError::Io(e)
-            }
-        })?;
+        Reader::read_exact_within(stream, &mut payload, limits, &mut progress)?;
 
         // Return Arc<Frame> without storing the prefix
         Ok(Frame::new_arc(payload))
     }
 
+    /// Fills `buf`, treating each read that times out mid-frame as an offense.
+    fn read_exact_within<R: Read>(
+        stream: &mut R,
+        buf: &mut [u8],
+        limits: &ReadLimits,
+        progress: &mut FrameProgress,
+    ) -> Result<(), FrameError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            if let Some(started) = progress.started
+                && started.elapsed() > limits.frame_deadline
+            {
+                return Err(FrameError::Timeout(format!(
+                    "frame not completed within {}ms",
+                    limits.frame_deadline.as_millis()
+                )));
+            }
+
+            match stream.read(&mut buf[filled..]) {
+                Ok(0) => return Err(FrameError::Disconnected),
+                Ok(n) => {
+                    filled += n;
+                    progress.started.get_or_insert_with(Instant::now);
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if progress.started.is_none() {
+                        // Idle between frames
+                        continue;
+                    }
+                    progress.stalls += 1;
+                    if progress.stalls >= limits.max_stalls {
+                        return Err(FrameError::Timeout(format!(
+                            "stalled {} times mid-frame",
+                            progress.stalls
+                        )));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Err(FrameError::Disconnected);
+                }
+                Err(e) => return Err(FrameError::Io(e)),
+            }
+        }
+        Ok(())
+    }
+
     /// Spawns a reader thread for a client connection
     /// Returns join handle for the thread
     pub fn spawn_reader_thread(
         stream: TcpStream,
         client_id: Uuid,
+        writer: thread::JoinHandle<()>,
         state: Arc<ServerState>,
-        broadcast_fn: BroadcastFn,
+        decoder: Arc<DecodePool>,
     ) -> thread::JoinHandle<()> {
         thread::spawn(move || {
-            Reader::run_reader_loop(stream, client_id, state, broadcast_fn);
+            Reader::run_reader_loop(stream, client_id, state, decoder);
+            // Removing the client closed the writer's channel; it exits once
+            // whatever is still queued (a Disconnect, say) is written.
+            if writer.join().is_err() {
+                error!("[{}] Writer thread panicked", client_id);
+            }
+            info!("[{}] Connection closed", client_id);
         })
     }
 
@@ -77,85 +165,52 @@ impl Reader {
         mut stream: TcpStream,
         client_id: Uuid,
         state: Arc<ServerState>,
-        broadcast_fn: BroadcastFn,
+        decoder: Arc<DecodePool>,
     ) {
         let peer_addr = match stream.peer_addr() {
             Ok(addr) => addr,
             Err(_) => {
-                eprintln!("[{}] Failed to get peer address", client_id);
+                error!("[{}] Failed to get peer address", client_id);
                 // Remove client from clients list
                 state.remove_client(client_id);
                 return;
             }
         };
 
-        println!("[{}] Reader thread started for {}", client_id, peer_addr);
+        info!("[{}] Reader thread started for {}", client_id, peer_addr);
 
         loop {
             match Reader::read_frame(&mut stream) {
                 Ok(frame) => {
                     // Update client activity timestamp on any received message
                     state.touch_client(client_id);
+                    CAPTURES.record(client_id, Direction::In, &frame);
+
+                    // Heartbeat replies only need the touch above; skip decoding them.
+                    if frame.type_id == MSG_TYPE_PONG {
+                        continue;
+                    }
 
-                    match ServerMessage::decode(&frame.payload) {
-                        Ok(ServerMessage::Operation(op)) => {
-                            println!("[{}] Received Operation from client", client_id);
-
-                            match ServerState::send_applied_op(&state, op) {
-                                Ok(frame) => {
-                                    broadcast_fn(
-                                        client_id,
-                                        frame,
-                                        Arc::clone(&state.get_clients_arc()),
-                                    );
-                                }
-                                Err(e) => {
-                                    eprintln!(
-                                        "[{}] Error applying operation for: {}",
-                                        client_id, e
-                                    );
-                                }
-                            }
-                        }
-                        Ok(ServerMessage::SyncDocument(_)) => {
-                            // Server doesn't expect SyncDocument from clients
-                            println!("[{}] Ignoring SyncDocument from client", client_id);
-                        }
-                        Ok(ServerMessage::Ping(seq)) => {
-                            // Client sent a ping (unusual but handle it)
-                            println!("[{}] Received Ping({}) from client", client_id, seq);
-                            // Respond with Pong
-                            let pong = ServerMessage::Pong(seq);
-                            let pong_frame = Frame::new_arc(ServerMessage::encode(&pong));
-                            // Send pong back to just this client
-                            if let Ok(clients) = state.get_clients_arc().lock() {
-                                if let Some(client) = clients.iter().find(|c| c.client_id == client_id) {
-                                    let _ = client.writer_sender.try_send(pong_frame);
-                                }
-                            }
-                        }
-                        Ok(ServerMessage::Pong(seq)) => {
-                            // Client responded to our ping - activity already updated above
-                            println!("[{}] Received Pong({}) from client", client_id, seq);
-                        }
-                        Err(e) => {
-                            eprintln!("[{}] Failed to decode message: {}", client_id, e);
-                        }
+                    // Decoding and dispatch happen on the pool; this thread
+                    // goes straight back to reading.
+                    if !decoder.submit(client_id, frame) {
+                        error!("[{}] Decode pool stopped - disconnecting", client_id);
+                        break;
                     }
                 }
                 Err(FrameError::Disconnected) => {
-                    println!("[{}] Client disconnected: {}", client_id, peer_addr);
-                    break;
-                }
-                Err(FrameError::PayloadTooLarge(size, max)) => {
-                    eprintln!(
-                        "[{}] Payload too large: {} > {} - disconnecting",
-                        client_id, size, max
-                    );
+                    info!("[{}] Client disconnected: {}", client_id, peer_addr);
                     break;
                 }
                 Err(e) => {
-                    eprintln!("[{}] Read error: {} - disconnecting", client_id, e);
+                    let e = ServerError::from(e);
+                    error!(
+                        "[{}] Read error from {}: {} - disconnecting",
+                        client_id, peer_addr, e
+                    );
+                    if let Some(reason) = e.disconnect_reason() {
+                        state.notify_disconnect(client_id, reason, &e.to_string());
+                    }
                     break;
                 }
             }
@@ -163,6 +218,126 @@ impl Reader {
 
         // Cleanup: remove client from clients list
         state.remove_client(client_id);
-        println!("[{}] Reader thread exiting", client_id);
+        info!("[{}] Reader thread exiting", client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use common::transport::duplex;
+
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (server, client)
+    }
+
+    const FAST: ReadLimits = ReadLimits {
+        stall_timeout: Duration::from_millis(20),
+        max_stalls: 3,
+        frame_deadline: Duration::from_millis(500),
+    };
+
+    #[test]
+    fn test_stalled_frame_times_out() {
+        let (mut server, mut client) = pair();
+        // Promise 10 bytes, deliver 2
+        client.write_all(&[0, 0, 0, 10, 1, 2]).unwrap();
+
+        let result = Reader::read_frame_with(&mut server, &FAST);
+        assert!(matches!(result, Err(FrameError::Timeout(_))));
+    }
+
+    #[test]
+    fn test_idle_then_complete_frame_is_read() {
+        let (mut server, mut client) = pair();
+        let writer = thread::spawn(move || {
+            // Idle for several stall timeouts before sending anything
+            thread::sleep(Duration::from_millis(100));
+            client.write_all(&[0, 0, 0, 2, 7, 8]).unwrap();
+            client
+        });
+
+        let frame = Reader::read_frame_with(&mut server, &FAST).unwrap();
+        assert_eq!(&frame.payload[..], &[7, 8]);
+        drop(writer.join());
+    }
+
+    fn prefixed(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_zero_length_frame() {
+        let (mut server, mut client) = duplex();
+        client.write_all(&[0, 0, 0, 0]).unwrap();
+
+        let frame = Reader::read_frame_with(&mut server, &FAST).unwrap();
+        assert!(frame.payload.is_empty());
+        assert_eq!(frame.type_id, 0);
+    }
+
+    #[test]
+    fn test_max_size_frames() {
+        let (mut server, mut client) = duplex();
+        client
+            .write_all(&prefixed(&vec![7; MAX_PAYLOAD_SIZE]))
+            .unwrap();
+        let frame = Reader::read_frame_with(&mut server, &FAST).unwrap();
+        assert_eq!(frame.payload.len(), MAX_PAYLOAD_SIZE);
+
+        // Rejected on the prefix alone, before any payload arrives
+        client
+            .write_all(&((MAX_PAYLOAD_SIZE + 1) as u32).to_be_bytes())
+            .unwrap();
+        assert!(matches!(
+            Reader::read_frame_with(&mut server, &FAST),
+            Err(FrameError::PayloadTooLarge(len, MAX_PAYLOAD_SIZE)) if len == MAX_PAYLOAD_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn test_split_reads_assemble_frames() {
+        let (server, mut client) = duplex();
+        let mut server = server.with_read_chunk(1);
+        let mut bytes = prefixed(&[0, 0, 0, 1, 3, 42]);
+        bytes.extend(prefixed(&[9]));
+        client.write_all(&bytes).unwrap();
+
+        let first = Reader::read_frame_with(&mut server, &FAST).unwrap();
+        assert_eq!(&first.payload[..], &[0, 0, 0, 1, 3, 42]);
+        assert_eq!(first.type_id, 3);
+        let second = Reader::read_frame_with(&mut server, &FAST).unwrap();
+        assert_eq!(&second.payload[..], &[9]);
+    }
+
+    #[test]
+    fn test_torn_prefix_is_a_disconnect() {
+        let (mut server, mut client) = duplex();
+        client.write_all(&[0, 0]).unwrap();
+        drop(client);
+
+        assert!(matches!(
+            Reader::read_frame_with(&mut server, &FAST),
+            Err(FrameError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn test_torn_prefix_stall_times_out() {
+        let (mut server, mut client) = duplex();
+        client.write_all(&[0, 0, 0]).unwrap();
+
+        assert!(matches!(
+            Reader::read_frame_with(&mut server, &FAST),
+            Err(FrameError::Timeout(_))
+        ));
     }
 }
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use common::{clock::unix_time_ms, operation::OperationLog};
+
+use crate::state::LOG_RETAIN_VERSIONS;
+
+/// Memory each document's op log may hold by default.
+pub const LOG_RETAIN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How much history compaction leaves in each document's op log, whether or
+/// not anyone still needs it. `None` is no limit; the newest entry is always
+/// kept. Edits based on a version that was dropped are rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Versions of history kept.
+    pub max_versions: Option<u64>,
+    /// Memory the log may hold, as `OperationLog::bytes` counts it.
+    pub max_bytes: Option<u64>,
+    /// How long applied ops are kept.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_versions: Some(LOG_RETAIN_VERSIONS),
+            max_bytes: Some(LOG_RETAIN_BYTES),
+            max_age: None,
+        }
+    }
+}
+
+/// Log entries a compaction dropped, by the limit that dropped them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Compacted {
+    pub versions: usize,
+    pub age: usize,
+    pub bytes: usize,
+}
+
+impl Compacted {
+    pub fn total(&self) -> usize {
+        self.versions + self.age + self.bytes
+    }
+
+    pub fn add(&mut self, other: Compacted) {
+        self.versions += other.versions;
+        self.age += other.age;
+        self.bytes += other.bytes;
+    }
+}
+
+impl RetentionPolicy {
+    /// Trims `log`, of a document now at `version`, to the policy's limits,
+    /// the size limit last. The version and age limits leave the ops from
+    /// `keep_from` on, which a client that was recently connected still
+    /// needs to resume; the size limit does not.
+    pub fn compact(&self, log: &OperationLog, version: u64, keep_from: Option<u64>) -> Compacted {
+        let keep_from = keep_from.unwrap_or(u64::MAX);
+        let versions = self.max_versions.map_or(0, |max| {
+            log.truncate_before(version.saturating_sub(max).min(keep_from))
+        });
+        let age = self.max_age.map_or(0, |max| {
+            let before = unix_time_ms().saturating_sub(max.as_millis() as u64);
+            log.truncate_applied_before_keeping(before, keep_from)
+        });
+        let bytes = self
+            .max_bytes
+            .map_or(0, |max| log.truncate_to_bytes(max as usize));
+        Compacted {
+            versions,
+            age,
+            bytes,
+        }
+    }
+}
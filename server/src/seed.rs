@@ -0,0 +1,245 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::state::DEFAULT_DOC_PATH;
+
+/// How long fetching a seed URL may wait on the network at each step.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a seeded document's starting content comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedSource {
+    File(PathBuf),
+    /// A plain `http://` URL.
+    Url(String),
+    /// Read to its end, which leaves the console without input.
+    Stdin,
+}
+
+/// A document of the default workspace that starts out with content read at
+/// startup instead of empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    pub path: String,
+    pub source: SeedSource,
+}
+
+impl Seed {
+    /// Parses comma-separated seeds: `SOURCE` for the default document, or
+    /// `PATH=SOURCE`, where `SOURCE` is a file, an `http://` URL or `-` for
+    /// stdin.
+    pub fn parse_list(seeds: &str) -> Result<Vec<Seed>, String> {
+        let mut parsed: Vec<Seed> = Vec::new();
+        for seed in seeds
+            .split(',')
+            .map(str::trim)
+            .filter(|seed| !seed.is_empty())
+        {
+            let (path, source) = match seed.split_once('=') {
+                Some((path, source)) if !is_url(seed) => (path.trim(), source.trim()),
+                _ => (DEFAULT_DOC_PATH, seed),
+            };
+            let source = match source {
+                "" => return Err(format!("No source for seed '{}'", seed)),
+                "-" => SeedSource::Stdin,
+                url if is_url(url) => SeedSource::Url(url.to_string()),
+                file => SeedSource::File(PathBuf::from(file)),
+            };
+            if parsed.iter().any(|seed| seed.path == path) {
+                return Err(format!("'{}' is seeded twice", path));
+            }
+            if source == SeedSource::Stdin && parsed.iter().any(|s| s.source == source) {
+                return Err("Only one seed can read stdin".to_string());
+            }
+            parsed.push(Seed {
+                path: path.to_string(),
+                source,
+            });
+        }
+        Ok(parsed)
+    }
+
+    /// The document's starting content, refused if it is longer than
+    /// `max_bytes` or not UTF-8.
+    pub fn read(&self, max_bytes: Option<u64>) -> Result<String, String> {
+        let bytes = match &self.source {
+            SeedSource::File(file) => fs::File::open(file)
+                .and_then(|file| read_limited(file, max_bytes))
+                .map_err(|e| format!("cannot read {}: {}", file.display(), e))?,
+            SeedSource::Url(url) => fetch(url, max_bytes)?,
+            SeedSource::Stdin => read_limited(io::stdin().lock(), max_bytes)
+                .map_err(|e| format!("cannot read stdin: {}", e))?,
+        };
+        String::from_utf8(bytes).map_err(|_| format!("{} is not UTF-8 text", self))
+    }
+}
+
+impl std::fmt::Display for Seed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            SeedSource::File(file) => write!(f, "{}", file.display()),
+            SeedSource::Url(url) => write!(f, "{}", url),
+            SeedSource::Stdin => write!(f, "stdin"),
+        }
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Reads `reader` to its end, failing once it has given more than
+/// `max_bytes`.
+fn read_limited(reader: impl Read, max_bytes: Option<u64>) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match max_bytes {
+        Some(max) => {
+            reader.take(max + 1).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("longer than the {} bytes allowed", max),
+                ));
+            }
+        }
+        None => {
+            let mut reader = reader;
+            reader.read_to_end(&mut bytes)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// GETs `url` over plain HTTP/1.0, so the body comes unchunked and ends
+/// with the connection. Redirects are not followed, and there is no TLS:
+/// an `https://` document has to be downloaded and seeded from its file.
+fn fetch(url: &str, max_bytes: Option<u64>) -> Result<Vec<u8>, String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err(format!(
+            "cannot fetch {}: only http:// URLs are supported; download it and seed from the file",
+            url
+        ));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let address = match authority.contains(':') {
+        true => authority.to_string(),
+        false => format!("{}:80", authority),
+    };
+    let failed = |e: io::Error| format!("cannot fetch {}: {}", url, e);
+
+    let socket = address
+        .to_socket_addrs()
+        .map_err(failed)?
+        .next()
+        .ok_or_else(|| format!("cannot fetch {}: {} does not resolve", url, authority))?;
+    let mut stream = TcpStream::connect_timeout(&socket, FETCH_TIMEOUT).map_err(failed)?;
+    stream
+        .set_read_timeout(Some(FETCH_TIMEOUT))
+        .map_err(failed)?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes()).map_err(failed)?;
+
+    // The headers get their own allowance on top of the body's
+    let response = read_limited(stream, max_bytes.map(|max| max + 64 * 1024)).map_err(failed)?;
+    let Some(end) = response.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Err(format!("cannot fetch {}: malformed response", url));
+    };
+    let head = String::from_utf8_lossy(&response[..end]);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("cannot fetch {}: {}", url, status));
+    }
+    let body = response[end + 4..].to_vec();
+    match max_bytes {
+        Some(max) if body.len() as u64 > max => Err(format!(
+            "cannot fetch {}: longer than the {} bytes allowed",
+            url, max
+        )),
+        _ => Ok(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_seeds_parse_from_sources_and_paths() {
+        let seeds =
+            Seed::parse_list("http://example.com/a?b=c, notes.txt=-, x.md=intro.md").unwrap();
+        assert_eq!(
+            seeds,
+            [
+                Seed {
+                    path: DEFAULT_DOC_PATH.to_string(),
+                    source: SeedSource::Url("http://example.com/a?b=c".to_string()),
+                },
+                Seed {
+                    path: "notes.txt".to_string(),
+                    source: SeedSource::Stdin,
+                },
+                Seed {
+                    path: "x.md".to_string(),
+                    source: SeedSource::File(PathBuf::from("intro.md")),
+                },
+            ]
+        );
+        assert!(Seed::parse_list("a.txt, main.txt=b.txt").is_err());
+        assert!(Seed::parse_list("x=-, y=-").is_err());
+        assert!(Seed::parse_list("x=").is_err());
+    }
+
+    #[test]
+    fn test_seeds_are_read_within_their_limit() {
+        let file = std::env::temp_dir().join(format!("dist-space-seed-{}", uuid::Uuid::new_v4()));
+        fs::write(&file, "hello").unwrap();
+        let seed = Seed {
+            path: DEFAULT_DOC_PATH.to_string(),
+            source: SeedSource::File(file.clone()),
+        };
+        assert_eq!(seed.read(Some(5)).unwrap(), "hello");
+        assert!(seed.read(Some(4)).is_err());
+        fs::write(&file, [0xff, 0xfe]).unwrap();
+        assert!(seed.read(None).is_err());
+        fs::remove_file(&file).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                let response = match request.starts_with(b"GET /notes.txt ") {
+                    true => "HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+                    false => "HTTP/1.0 404 Not Found\r\n\r\n",
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let seed = |path: &str| Seed {
+            path: DEFAULT_DOC_PATH.to_string(),
+            source: SeedSource::Url(format!("http://{}/{}", address, path)),
+        };
+        assert_eq!(seed("notes.txt").read(Some(5)).unwrap(), "hello");
+        assert!(seed("missing.txt").read(None).unwrap_err().contains("404"));
+        server.join().unwrap();
+        assert!(
+            Seed::parse_list("https://example.com/a").unwrap()[0]
+                .read(None)
+                .is_err()
+        );
+    }
+}
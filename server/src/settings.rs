@@ -0,0 +1,343 @@
+use common::{
+    checked,
+    operation::OperationKind,
+    space::{DocumentSettingsProto, LineEnding, TextRangeProto},
+};
+
+use crate::locks::transform_range;
+use crate::validation::{Rejection, check_range};
+
+/// Language ids by file extension, for documents created at a path.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("c", "c"),
+    ("cpp", "cpp"),
+    ("css", "css"),
+    ("go", "go"),
+    ("h", "c"),
+    ("html", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("md", "markdown"),
+    ("py", "python"),
+    ("rs", "rust"),
+    ("sh", "shellscript"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("txt", "plaintext"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+];
+
+/// Per-document settings: what editors need to show the text, and the rules
+/// the server holds edits to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentSettings {
+    pub line_ending: LineEnding,
+    pub tab_width: u32,
+    pub language_id: String,
+    /// 0 for no limit.
+    pub max_bytes: u64,
+    /// `(start, end)` byte ranges edits may add to but not remove from.
+    pub append_only: Vec<(u32, u32)>,
+}
+
+impl DocumentSettings {
+    /// The settings a document created at `path` with `content` starts
+    /// with: its language from the extension, and the line ending style the
+    /// content uses throughout, if any.
+    pub fn inferred(path: &str, content: &str) -> Self {
+        let extension = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+        let language_id = LANGUAGES
+            .iter()
+            .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+            .map_or("", |(_, language)| language);
+        let crlf = content.matches("\r\n").count();
+        let line_ending = match (crlf, content.matches('\n').count()) {
+            (_, 0) => LineEnding::Any,
+            (0, _) => LineEnding::Lf,
+            (crlf, lf) if crlf == lf => LineEnding::Crlf,
+            _ => LineEnding::Any,
+        };
+        Self {
+            line_ending,
+            language_id: language_id.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_proto(proto: &DocumentSettingsProto) -> Self {
+        Self {
+            line_ending: proto.line_ending(),
+            tab_width: proto.tab_width,
+            language_id: proto.language_id.clone(),
+            max_bytes: proto.max_bytes,
+            append_only: proto
+                .append_only
+                .iter()
+                .map(|range| (range.start, range.end))
+                .collect(),
+        }
+    }
+
+    pub fn to_proto(&self) -> DocumentSettingsProto {
+        DocumentSettingsProto {
+            line_ending: self.line_ending as i32,
+            tab_width: self.tab_width,
+            language_id: self.language_id.clone(),
+            max_bytes: self.max_bytes,
+            append_only: self
+                .append_only
+                .iter()
+                .map(|&(start, end)| TextRangeProto { start, end })
+                .collect(),
+        }
+    }
+
+    /// Refuses settings `content` already breaks.
+    pub fn check_content(&self, content: &str) -> Result<(), Rejection> {
+        for &(start, end) in &self.append_only {
+            check_range(start, end, content)?;
+        }
+        self.check_size(content.len())?;
+        self.check_line_endings(content.as_bytes(), false)
+    }
+
+    /// Refuses `next` if it drops or narrows one of these append-only
+    /// regions: clients may add regions or widen them, but only the operator
+    /// lifts them.
+    pub fn check_keeps_append_only(&self, next: &Self) -> Result<(), Rejection> {
+        for &(start, end) in &self.append_only {
+            let kept = next
+                .append_only
+                .iter()
+                .any(|&(next_start, next_end)| next_start <= start && end <= next_end);
+            if !kept {
+                return Err(Rejection::AppendOnly { start, end });
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuses a character op that would break the settings once applied to
+    /// `content`. Only the text around the edit is looked at, so a document
+    /// that already broke them can still be fixed.
+    pub fn check_op(&self, op: &OperationKind, content: &str) -> Result<(), Rejection> {
+        let (start, end, text) = match op {
+            OperationKind::Insert(insert) => (insert.index, insert.index, insert.text.as_str()),
+            OperationKind::Delete(delete) => (delete.start, delete.end, ""),
+            OperationKind::Replace(replace) => (replace.start, replace.end, replace.text.as_str()),
+            _ => return Ok(()),
+        };
+        // Text may only go into an append-only region; an empty one still
+        // guards its position
+        if let Some(&(region_start, region_end)) =
+            self.append_only.iter().find(|(region_start, region_end)| {
+                start < end && start < *region_end && *region_start < end
+            })
+        {
+            return Err(Rejection::AppendOnly {
+                start: region_start,
+                end: region_end,
+            });
+        }
+        let (start, end) = (start as usize, end as usize);
+
+        // The edit with the characters either side of it, which it may pair
+        // up with
+        let bytes = content.as_bytes();
+        let mut window = Vec::with_capacity(text.len() + 2);
+        window.extend(start.checked_sub(1).map(|before| bytes[before]));
+        window.extend_from_slice(text.as_bytes());
+        window.extend(bytes.get(end));
+        self.check_line_endings(&window, start > 0)?;
+        self.check_size(content.len() - (end - start) + text.len())
+    }
+
+    /// Moves the append-only regions through an applied character op. Text
+    /// inserted at a region's end is appended to it.
+    pub fn transform(&mut self, op: &OperationKind) {
+        for region in &mut self.append_only {
+            let (start, end) = *region;
+            *region = match op {
+                OperationKind::Insert(insert) if insert.index == end => {
+                    (start, checked::shift(end, insert.text.len()))
+                }
+                _ => transform_range(start, end, op),
+            };
+        }
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), Rejection> {
+        if self.max_bytes > 0 && len as u64 > self.max_bytes {
+            return Err(Rejection::DocumentTooLarge {
+                len,
+                max: self.max_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// With `leading_context`, the first byte comes from before the edit
+    /// and is only looked at as what precedes the second.
+    fn check_line_endings(&self, bytes: &[u8], leading_context: bool) -> Result<(), Rejection> {
+        let mixed = match self.line_ending {
+            LineEnding::Any => false,
+            LineEnding::Lf => bytes.windows(2).any(|pair| pair == b"\r\n"),
+            LineEnding::Crlf => bytes.iter().enumerate().any(|(i, byte)| {
+                *byte == b'\n' && !(i == 0 && leading_context) && (i == 0 || bytes[i - 1] != b'\r')
+            }),
+        };
+        if mixed {
+            return Err(Rejection::LineEnding {
+                expected: self.line_ending,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_are_inferred_from_path_and_content() {
+        let settings = DocumentSettings::inferred("src/main.rs", "fn main() {}\n");
+        assert_eq!(settings.language_id, "rust");
+        assert_eq!(settings.line_ending, LineEnding::Lf);
+        let settings = DocumentSettings::inferred("NOTES", "a\r\nb\r\n");
+        assert_eq!(settings.language_id, "");
+        assert_eq!(settings.line_ending, LineEnding::Crlf);
+        assert_eq!(
+            DocumentSettings::inferred("a.txt", "a\r\nb\n").line_ending,
+            LineEnding::Any
+        );
+    }
+
+    #[test]
+    fn test_edits_keep_the_line_ending_style_and_size() {
+        let lf = DocumentSettings {
+            line_ending: LineEnding::Lf,
+            max_bytes: 8,
+            ..DocumentSettings::default()
+        };
+        assert!(
+            lf.check_op(&OperationKind::insert_at(2, "x\ny"), "ab\ncd")
+                .is_ok()
+        );
+        assert!(
+            lf.check_op(&OperationKind::insert_at(2, "x\r\ny"), "ab\ncd")
+                .is_err()
+        );
+        // A \r right before an existing \n makes a CRLF too
+        assert!(
+            lf.check_op(&OperationKind::insert_at(2, "\r"), "ab\ncd")
+                .is_err()
+        );
+        assert!(matches!(
+            lf.check_op(&OperationKind::insert_at(0, "1234"), "ab\ncd"),
+            Err(Rejection::DocumentTooLarge { len: 9, max: 8 })
+        ));
+
+        let crlf = DocumentSettings {
+            line_ending: LineEnding::Crlf,
+            ..DocumentSettings::default()
+        };
+        assert!(
+            crlf.check_op(&OperationKind::insert_at(4, "x\r\n"), "ab\r\ncd")
+                .is_ok()
+        );
+        assert!(
+            crlf.check_op(&OperationKind::insert_at(0, "\n"), "ab\r\ncd")
+                .is_err()
+        );
+        assert!(
+            crlf.check_op(&OperationKind::insert_at(3, "x"), "ab\r\ncd")
+                .is_err()
+        );
+        let unpair = OperationKind::delete_range(2, 3);
+        assert!(crlf.check_op(&unpair, "ab\r\ncd").is_err());
+        assert!(crlf.check_content("ab\ncd").is_err());
+    }
+
+    #[test]
+    fn test_append_only_regions_only_grow() {
+        // "# Notes\n## Log\n- one\n": the log is 15..21
+        let content = "# Notes\n## Log\n- one\n";
+        let mut settings = DocumentSettings {
+            append_only: vec![(15, 21)],
+            ..DocumentSettings::default()
+        };
+        assert!(settings.check_content(content).is_ok());
+        assert!(
+            settings
+                .check_op(&OperationKind::insert_at(21, "- two\n"), content)
+                .is_ok()
+        );
+        assert!(
+            settings
+                .check_op(&OperationKind::insert_at(17, "x"), content)
+                .is_ok()
+        );
+        assert!(
+            settings
+                .check_op(&OperationKind::delete_range(0, 15), content)
+                .is_ok()
+        );
+        assert!(matches!(
+            settings.check_op(&OperationKind::delete_range(14, 16), content),
+            Err(Rejection::AppendOnly { start: 15, end: 21 })
+        ));
+
+        // Appending grows the region; edits before it move it along
+        settings.transform(&OperationKind::insert_at(21, "- two\n"));
+        assert_eq!(settings.append_only, [(15, 27)]);
+        settings.transform(&OperationKind::insert_at(15, "\n"));
+        settings.transform(&OperationKind::delete_range(0, 2));
+        assert_eq!(settings.append_only, [(14, 26)]);
+
+        // Even an empty region keeps its place
+        let empty = DocumentSettings {
+            append_only: vec![(2, 2)],
+            ..DocumentSettings::default()
+        };
+        assert!(
+            empty
+                .check_op(&OperationKind::delete_range(1, 3), "abcd")
+                .is_err()
+        );
+        assert!(
+            empty
+                .check_op(&OperationKind::delete_range(0, 2), "abcd")
+                .is_ok()
+        );
+        assert!(empty.check_content("a").is_err());
+
+        // New settings may widen the regions, not lift or narrow them
+        let with = |append_only: Vec<(u32, u32)>| DocumentSettings {
+            append_only,
+            ..DocumentSettings::default()
+        };
+        assert!(
+            settings
+                .check_keeps_append_only(&with(vec![(0, 30)]))
+                .is_ok()
+        );
+        assert!(
+            settings
+                .check_keeps_append_only(&with(vec![(2, 2), (14, 26)]))
+                .is_ok()
+        );
+        assert!(matches!(
+            settings.check_keeps_append_only(&with(vec![])),
+            Err(Rejection::AppendOnly { start: 14, end: 26 })
+        ));
+        assert!(
+            settings
+                .check_keeps_append_only(&with(vec![(15, 26)]))
+                .is_err()
+        );
+        assert!(empty.check_keeps_append_only(&with(vec![(0, 1)])).is_err());
+    }
+}
@@ -4,273 +4,5289 @@
 // or version vectors that rely on persistent client IDs and data stability.
 // The transport layer is currently unaffected as it does not depend on order.
 
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use common::{
-    Document, Frame,
-    operation::{Operation, OperationLog},
+    Document, Frame, checked,
+    clock::{ClockSample, Timestamp, unix_time_ms},
+    document::apply_to_text,
+    lines,
+    operation::{Milestone, Operation, OperationKind, Tag},
     protocol::ServerMessage,
-    space::{OperationProto, SyncDocumentProto},
+    space::{
+        AckProto, AttachmentChunkProto, AttachmentProto, CheckpointProto, CreateFromTemplateProto,
+        CreateInviteProto, DisconnectProto, DisconnectReason, DocumentArchiveProto,
+        ExportChunkProto, ExportFormat, ExportRequestProto, FetchAttachmentProto, FollowProto,
+        GetHistoryProto, HistoryProto, InviteProto, LineEnding, LockRangeProto, MemberTokenProto,
+        NoticeProto, OperationBatchProto, OperationProto, OverlaysProto, PresenceProto,
+        PresenceStatus, PropagationSampleProto, RedirectProto, SetDocumentSettingsProto,
+        SetOverlaysProto, SetViewportProto, SignalKind, SignalProto, SyncDocumentProto,
+        TagVersionProto, UnlockRangeProto, UploadAttachmentProto, ViewportProto,
+        WorkspaceEventKind,
+    },
 };
+use crossbeam::channel::{self, Receiver};
 use uuid::Uuid;
 
+use crate::applied::{AppliedOp, AppliedOps};
+use crate::archive;
+use crate::attachments::{self, AttachmentLimits, AttachmentStore};
+use crate::authorization::{Authorizer, OpRequest};
+use crate::autosave;
+use crate::batcher::Batcher;
+use crate::capabilities::Capabilities;
 use crate::client_entry::ClientEntry;
+use crate::compatibility::ClientRequirements;
+use crate::compliance::{ComplianceLog, RecordedWorkspaces};
+use crate::conflict::ConflictPolicies;
+use crate::cursors::{AckCursors, ClientKeys, Cursor, SavedMark};
+use crate::doc_ids::{DocIds, MissingDocuments};
+use crate::documents::{DocumentEntry, unwrap_snapshot};
+use crate::error::ServerError;
+use crate::events::EventLog;
+use crate::export;
+use crate::freezes::{Freeze, Freezes};
+use crate::git::{Commit, GitHistory};
+use crate::handover;
+use crate::invites::{self, Invite, InviteStore};
+use crate::locks::{RangeLocks, transform_range};
+use crate::log::{debug, error, info, trace};
+use crate::membership::MembershipStore;
+use crate::metrics::{AcceptMetrics, COMPACTIONS, EVICTIONS, Eviction, RESUMES, Resume};
+use crate::middleware::{Middleware, MiddlewareChain, OpContext};
+use crate::normalize::Normalization;
+use crate::offline::{OFFLINE_GRACE, OfflineSession, OfflineSessions};
+use crate::propagation::Propagation;
+use crate::retention::{Compacted, RetentionPolicy};
+use crate::settings::DocumentSettings;
+use crate::sha256;
+use crate::templates::TemplateStore;
+use crate::transform::transform_with;
+use crate::validation::Rejection;
+use crate::workspaces::{Access, DEFAULT_WORKSPACE, Workspace, WorkspaceQuota};
 
-/// Default document path for Phase 1 (single-document mode).
-/// Will be replaced by dynamic file paths in Phase 2 (VFS).
-#[allow(dead_code)]
-const DEFAULT_DOC_PATH: &str = "main.txt";
+/// Document opened for clients that don't ask for a specific path.
+pub const DEFAULT_DOC_PATH: &str = "main.txt";
 
 /// Maximum number of concurrent client connections.
 /// Protects against denial-of-service attacks.
 pub const MAX_CLIENTS: usize = 100;
 
+/// Accepted connections waiting to be registered. When full, new connections
+/// are shed with a server-full error instead of piling up.
+pub const ACCEPT_QUEUE_CAPACITY: usize = 16;
+
+/// Retry hint sent with server-full errors.
+pub const SERVER_FULL_RETRY_AFTER_MS: u64 = 5_000;
+
+/// Versions of history kept in each document's op log by compaction. Edits
+/// based on anything older can no longer be transformed.
+pub const LOG_RETAIN_VERSIONS: u64 = 10_000;
+
 /// Client timeout in milliseconds (30 seconds).
 /// Clients that don't respond to heartbeats within this window are disconnected.
 pub const CLIENT_TIMEOUT_MS: u64 = 30_000;
 
+/// Input-free time after which a connection is shown as idle (5 minutes).
+pub const IDLE_AFTER_MS: u64 = 300_000;
+
 /// Heartbeat interval in milliseconds (10 seconds).
 /// Server sends ping to clients at this interval.
 pub const HEARTBEAT_INTERVAL_MS: u64 = 10_000;
 
+/// Longest emoji a reaction signal may carry; enough for a flag or a
+/// skin-toned family, not for a message.
+pub const MAX_SIGNAL_EMOJI_BYTES: usize = 32;
+
+/// Tags a document may have. Each keeps a copy of the content it names.
+pub const MAX_TAGS_PER_DOCUMENT: usize = 100;
+
+/// Longest tag name, in bytes.
+pub const MAX_TAG_NAME_BYTES: usize = 64;
+
+/// Frames produced by applying an operation, broadcast to collaborators in order:
+/// the transformed operation (for activity and precise reconciliation) followed by
+/// the resulting document state. The originator receives only the operation.
+pub struct AppliedFrames {
+    /// The transformed operation, for batching.
+    pub operation_proto: OperationProto,
+    pub operation: Arc<Frame>,
+    pub sync: Arc<Frame>,
+}
+
+/// A transaction as applied: one workspace version, and per document the
+/// ops it applied there, framed as an OperationBatch and the resulting sync.
+pub struct AppliedTransaction {
+    pub global_version: u64,
+    pub documents: Vec<AppliedDocument>,
+}
+
+pub struct AppliedDocument {
+    pub doc_id: String,
+    pub operations: Vec<OperationProto>,
+    pub batch: Arc<Frame>,
+    pub sync: Arc<Frame>,
+}
+
+/// The last frame sent on a connection the server is about to close.
+pub fn disconnect_frame(reason: DisconnectReason, message: &str) -> Arc<Frame> {
+    let disconnect = ServerMessage::Disconnect(DisconnectProto {
+        reason_code: reason as i32,
+        message: message.to_string(),
+    });
+    Frame::new_arc(ServerMessage::encode(&disconnect))
+}
+
+/// Tells other connections that `client` is now `status`.
+fn presence_frame(client: &ClientEntry, status: PresenceStatus, reason: &str) -> Arc<Frame> {
+    let presence = ServerMessage::Presence(presence_proto(client, status, reason));
+    Frame::new_arc(ServerMessage::encode(&presence))
+}
+
+fn presence_proto(client: &ClientEntry, status: PresenceStatus, reason: &str) -> PresenceProto {
+    PresenceProto {
+        client_id: client.client_id.to_string(),
+        display_name: client.label(),
+        status: status as i32,
+        reason: reason.to_string(),
+    }
+}
+
 pub struct ServerState {
     clients: Arc<Mutex<Vec<Arc<ClientEntry>>>>,
-    /// The default document for single-document mode (Phase 1).
-    /// In Phase 2, this will be replaced by `workspace: Arc<Mutex<Workspace>>`
-    /// with a HashMap<Path, Document> structure.
-    document: Arc<Mutex<Document>>,
-    op_log: Arc<OperationLog>,
+    /// Workspaces by name, each with its own documents. Each connection
+    /// subscribes to the ones it opens in its workspace, so a single
+    /// connection can collaborate on many documents. The default workspace
+    /// always exists; the others are created as clients join them.
+    workspaces: Mutex<HashMap<String, Arc<Workspace>>>,
+    /// Conflict policy given to each workspace's documents by path.
+    policies: ConflictPolicies,
+    /// Id each workspace's documents are registered under by path.
+    doc_ids: DocIds,
+    /// What opening a path with no document at it does.
+    missing_documents: MissingDocuments,
+    quota: WorkspaceQuota,
+    /// Who may join the workspaces that are not open to all.
+    members: Mutex<MembershipStore>,
+    /// Guest access to single documents, by token.
+    invites: Mutex<InviteStore>,
+    /// What happened in each workspace besides edits.
+    events: Mutex<EventLog>,
+    /// How far each client has applied the documents it has open.
+    cursors: Mutex<AckCursors>,
+    /// Sessions of clients that disconnected within `offline_grace`.
+    offline: Mutex<OfflineSessions>,
+    /// Hash-chained record of the ops and presence in the workspaces that
+    /// opted in; records nothing unless given a file.
+    compliance: Mutex<ComplianceLog>,
+    /// How long a disconnected client's session and cursors are kept, and
+    /// hold back compaction, for it to resume.
+    offline_grace: Duration,
+    /// Identifies this run of the server, which document versions count
+    /// in: op logs are not kept across restarts. Always random, even when
+    /// ids are seeded, so no two runs share one.
+    epoch: String,
+    accept_metrics: AcceptMetrics,
+    batcher: Option<Batcher>,
+    /// Where CreateFromTemplate looks templates up; `None` refuses them.
+    templates: Option<TemplateStore>,
+    /// Input-free time after which a connection is announced as idle; `None`
+    /// leaves connections active until they say they are away.
+    idle_after: Option<Duration>,
+    /// Rewrites applied to the text of incoming ops.
+    normalization: Normalization,
+    /// Who may make which edits, asked in the order they were added before
+    /// an op is transformed.
+    authorizers: Vec<Box<dyn Authorizer>>,
+    /// What ops go through before and after they are applied.
+    middleware: MiddlewareChain,
+    /// Consumers of every applied op inside the server.
+    applied: AppliedOps,
+    /// Applied ops not yet counted in their workspace's activity feed.
+    activity_feed: Receiver<Arc<AppliedOp>>,
+    /// Git history of the persisted documents; `None` refuses checkpoints.
+    history: Option<Mutex<GitHistory>>,
+    /// Files clients attach to documents.
+    attachments: Mutex<AttachmentStore>,
+    /// Windows in which the operator made documents read-only.
+    freezes: Mutex<Freezes>,
+    /// Server that takes over the documents at shutdown; `None` just closes
+    /// every connection.
+    handover: Option<String>,
+    /// Where every document is left for the server taking over.
+    handover_file: Option<PathBuf>,
+    /// Set once the handover has begun: ops are refused from then on.
+    handing_over: AtomicBool,
+    /// What a Hello must say for the client to be let in.
+    requirements: ClientRequirements,
 }
 
 impl ServerState {
     pub fn new() -> Self {
-        let doc_id = Uuid::new_v4();
-        Self {
+        let default = Workspace::new(
+            DEFAULT_WORKSPACE,
+            ConflictPolicies::default(),
+            DocIds::default(),
+        );
+        default.lock_documents().open(DEFAULT_DOC_PATH);
+        let workspaces = HashMap::from([(DEFAULT_WORKSPACE.to_string(), Arc::new(default))]);
+        let mut state = Self {
             clients: Arc::new(Mutex::new(Vec::new())),
-            document: Arc::new(Mutex::new(Document {
-                uuid: doc_id,
-                content: String::new(),
-                version: 0,
-            })),
-            op_log: Arc::new(OperationLog::new()),
-        }
+            workspaces: Mutex::new(workspaces),
+            policies: ConflictPolicies::default(),
+            doc_ids: DocIds::default(),
+            missing_documents: MissingDocuments::default(),
+            quota: WorkspaceQuota::default(),
+            members: Mutex::new(MembershipStore::default()),
+            invites: Mutex::new(InviteStore::default()),
+            events: Mutex::new(EventLog::default()),
+            cursors: Mutex::new(AckCursors::default()),
+            offline: Mutex::new(OfflineSessions::default()),
+            compliance: Mutex::new(ComplianceLog::default()),
+            offline_grace: OFFLINE_GRACE,
+            epoch: Uuid::new_v4().to_string(),
+            accept_metrics: AcceptMetrics::default(),
+            batcher: None,
+            templates: None,
+            idle_after: None,
+            normalization: Normalization::default(),
+            authorizers: Vec::new(),
+            middleware: MiddlewareChain::default(),
+            applied: AppliedOps::default(),
+            activity_feed: channel::never(),
+            history: None,
+            attachments: Mutex::new(AttachmentStore::new(AttachmentLimits::default())),
+            freezes: Mutex::new(Freezes::new()),
+            handover: None,
+            handover_file: None,
+            handing_over: AtomicBool::new(false),
+            requirements: ClientRequirements::default(),
+        };
+        state.activity_feed = state.subscribe_applied("activity");
+        state
     }
 
-    pub fn get_document(&self) -> Arc<Mutex<Document>> {
-        Arc::clone(&self.document)
+    /// Broadcast applied ops in batches of `window` instead of one by one.
+    pub fn with_batch_window(mut self, window: Option<Duration>) -> Self {
+        self.batcher = window.map(Batcher::new);
+        self
     }
 
-    /// Add a new client to the server state.
-    /// Returns Err if the maximum client limit is reached.
-    pub fn add_client(&self, client: ClientEntry) -> Result<(), String> {
-        let mut clients = match self.clients.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("WARNING: Mutex was poisoned. Data might be in an inconsistent state.");
-                poisoned.into_inner()
-            }
-        };
+    /// Persist the default document in `file`, loading its current content.
+    pub fn with_backing_file(self, file: PathBuf) -> std::io::Result<Self> {
+        let content = autosave::load(&file)?;
+        info!(
+            "[ServerState] Loaded '{}' ({} bytes) from {}",
+            DEFAULT_DOC_PATH,
+            content.len(),
+            file.display()
+        );
+        self.workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .insert(DocumentEntry::backed_by(DEFAULT_DOC_PATH, file, content));
+        Ok(self)
+    }
 
-        // Check connection limit
-        if clients.len() >= MAX_CLIENTS {
-            return Err(format!(
-                "Connection limit reached: {} clients already connected",
-                MAX_CLIENTS
-            ));
+    /// Start the default workspace's document at `path` out as `content`.
+    /// A document already there, such as the default document loaded from
+    /// its backing file, is replaced, but keeps that file: the seeded
+    /// content is written to it with the first edit.
+    pub fn with_seed(self, path: &str, content: String) -> Self {
+        let workspace = self.workspace(DEFAULT_WORKSPACE);
+        let mut documents = workspace.lock_documents();
+        let backing_file = documents
+            .at_path(path)
+            .and_then(|existing| existing.backing_file.clone());
+        info!(
+            "[ServerState] Seeded '{}' with {} bytes",
+            path,
+            content.len()
+        );
+        documents.insert(DocumentEntry::with_content(path, content, backing_file));
+        self
+    }
+
+    /// Let clients create documents from the templates in `dir`.
+    pub fn with_templates(mut self, dir: PathBuf) -> Self {
+        self.templates = Some(TemplateStore::new(dir));
+        self
+    }
+
+    /// Transform concurrent edits on each document as `policies` say.
+    pub fn with_conflict_policies(mut self, policies: ConflictPolicies) -> Self {
+        for workspace in self.workspaces() {
+            workspace.lock_documents().set_policies(policies.clone());
         }
+        self.policies = policies;
+        self
+    }
 
-        clients.push(Arc::new(client));
-        println!(
-            "[ServerState] Client added. Total clients: {}",
-            clients.len()
+    /// Register each document under the id `ids` gives its path. Documents
+    /// already open, like the default one, are registered again.
+    pub fn with_doc_ids(mut self, ids: DocIds) -> Self {
+        for workspace in self.workspaces() {
+            workspace.lock_documents().set_ids(ids.clone());
+        }
+        self.doc_ids = ids;
+        self
+    }
+
+    /// Create documents at the paths clients open, or refuse paths with none.
+    pub fn with_missing_documents(mut self, missing: MissingDocuments) -> Self {
+        self.missing_documents = missing;
+        self
+    }
+
+    /// Keep workspace members in `file`, loading the ones already there.
+    pub fn with_members_file(mut self, file: PathBuf) -> std::io::Result<Self> {
+        let members = MembershipStore::load(file.clone())?;
+        info!(
+            "[ServerState] Loaded members of {} workspace(s) from {}",
+            members.workspaces().len(),
+            file.display()
         );
+        self.members = Mutex::new(members);
+        Ok(self)
+    }
 
-        Ok(())
+    /// Keep workspace events in `file`, loading the ones already there.
+    pub fn with_events_file(mut self, file: PathBuf) -> std::io::Result<Self> {
+        let events = EventLog::load(file.clone())?;
+        info!(
+            "[ServerState] Loaded events of {} workspace(s) from {}",
+            events.workspaces().len(),
+            file.display()
+        );
+        self.events = Mutex::new(events);
+        Ok(self)
     }
 
-    /// Get the current number of connected clients.
-    pub fn client_count(&self) -> usize {
-        match self.clients.lock() {
-            Ok(guard) => guard.len(),
-            Err(poisoned) => poisoned.into_inner().len(),
+    /// Keep acknowledgement cursors in `file`, loading the ones already there.
+    /// Cursors at the version a persisted document was last saved at carry
+    /// over to this run if it loaded the same content back, so must come
+    /// after the documents are loaded.
+    pub fn with_cursors_file(mut self, file: PathBuf) -> std::io::Result<Self> {
+        let mut cursors = AckCursors::load(file.clone())?;
+        let mut carried = 0;
+        for workspace in self.workspaces() {
+            for entry in workspace.lock_documents().entries() {
+                if entry.backing_file.is_none() {
+                    continue;
+                }
+                let (hash, to) = {
+                    let doc = match entry.document.lock() {
+                        Ok(doc) => doc,
+                        Err(poisoned) => poisoned.into_inner(),
+                    };
+                    let to = Cursor {
+                        doc_id: doc.uuid.to_string(),
+                        epoch: self.epoch.clone(),
+                        version: doc.version,
+                        seen_ms: 0,
+                    };
+                    (sha256::hex_digest(doc.content.as_bytes()), to)
+                };
+                carried += cursors.carry_over(&workspace.name, &entry.path, &hash, &to);
+            }
         }
+        info!(
+            "[ServerState] Loaded {} acknowledgement cursor(s) from {}, {} carried over",
+            cursors.len(),
+            file.display(),
+            carried
+        );
+        self.cursors = Mutex::new(cursors);
+        Ok(self)
     }
 
-    pub fn remove_client(&self, client_id: Uuid) -> Option<Arc<ClientEntry>> {
-        let mut clients = match self.clients.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("WARNING: Mutex was poisoned. Data might be in an inconsistent state.");
-                poisoned.into_inner()
-            }
-        };
+    /// Record every op applied in `workspaces`, and their presence changes,
+    /// to `file` for compliance, after the entries already there.
+    pub fn with_compliance_log(
+        mut self,
+        file: PathBuf,
+        workspaces: RecordedWorkspaces,
+    ) -> std::io::Result<Self> {
+        let log = ComplianceLog::open(file.clone(), workspaces)?;
+        info!(
+            "[ServerState] Recording for compliance to {} after {} entries, head {}",
+            file.display(),
+            log.len(),
+            log.head()
+        );
+        self.compliance = Mutex::new(log);
+        Ok(self)
+    }
 
-        if let Some(pos) = clients.iter().position(|c| c.client_id == client_id) {
-            let removed = clients.remove(pos);
-            println!(
-                "[ServerState] Client {} removed. Remaining clients: {}",
-                client_id,
-                clients.len()
-            );
-            Some(removed)
-        } else {
-            None
+    /// Keep what a disconnected client needs to resume, and the history it
+    /// needs, for `grace`; zero forgets clients as they go.
+    pub fn with_offline_grace(mut self, grace: Duration) -> Self {
+        self.offline_grace = grace;
+        self
+    }
+
+    /// Commit the persisted documents to the git repository at `repo`,
+    /// creating it if needed.
+    pub fn with_git_history(mut self, repo: &Path) -> std::io::Result<Self> {
+        self.history = Some(Mutex::new(GitHistory::open(repo)?));
+        Ok(self)
+    }
+
+    /// Keep attachments in `store`.
+    pub fn with_attachments(mut self, store: AttachmentStore) -> Self {
+        self.attachments = Mutex::new(store);
+        self
+    }
+
+    /// Hold every workspace to `quota`.
+    pub fn with_workspace_quota(mut self, quota: WorkspaceQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Rewrite the text of incoming ops as `normalization` says.
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Ask `authorizer`, after those already added, whether the sender may
+    /// make each op, before it is transformed.
+    pub fn with_authorizer(mut self, authorizer: impl Authorizer + 'static) -> Self {
+        self.authorizers.push(Box::new(authorizer));
+        self
+    }
+
+    /// Put ops through `middleware` after those already added, ahead of
+    /// validation.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Every op applied from now on, in any workspace, as it is broadcast,
+    /// for data derived from documents: a search index, statistics,
+    /// webhooks. `name` is for logs. Ops are dropped while the receiver is
+    /// more than `SUBSCRIBER_CAPACITY` behind; dropping it unsubscribes.
+    pub fn subscribe_applied(&self, name: &str) -> Receiver<Arc<AppliedOp>> {
+        self.applied.subscribe(name)
+    }
+
+    /// The names of the middlewares ops go through, in order.
+    pub fn middleware_names(&self) -> Vec<&str> {
+        self.middleware.names()
+    }
+
+    /// Hand documents over to `server` at shutdown. It is expected to share
+    /// this server's storage (document files, members and attachments).
+    pub fn with_handover(mut self, server: Option<String>) -> Self {
+        self.handover = server;
+        self
+    }
+
+    /// Leave every document in `file` when handing over, and take over the
+    /// ones a server handing over to this one left there, in place of those
+    /// loaded from their own files. So must come after the documents are
+    /// loaded, and before the cursors are.
+    pub fn with_handover_file(mut self, file: PathBuf) -> std::io::Result<Self> {
+        let documents = handover::take(&file)?;
+        let count = documents.len();
+        for (workspace, archive) in documents {
+            let workspace = self.workspace(&workspace);
+            let mut registry = workspace.lock_documents();
+            let backing_file = registry
+                .at_path(&archive.path)
+                .and_then(|entry| entry.backing_file.clone());
+            let entry = archive::restore(archive, backing_file)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            registry.insert(entry);
         }
+        info!(
+            "[ServerState] Took over {} document(s) from {}",
+            count,
+            file.display()
+        );
+        self.handover_file = Some(file);
+        Ok(self)
     }
 
-    /// Remove all clients that have timed out.
-    /// Returns the number of clients removed.
-    pub fn remove_timed_out_clients(&self) -> usize {
-        let mut clients = match self.clients.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("WARNING: Mutex was poisoned. Recovering.");
-                poisoned.into_inner()
-            }
+    /// Writes every document, saved or not, to the handover file for the
+    /// server taking over. Returns how many.
+    pub fn save_handover(&self) -> std::io::Result<usize> {
+        let Some(file) = &self.handover_file else {
+            return Ok(0);
         };
-
-        let before = clients.len();
-        clients.retain(|client| {
-            let timed_out = client.is_timed_out(CLIENT_TIMEOUT_MS);
-            if timed_out {
-                println!(
-                    "[ServerState] Client {} timed out ({}ms since last activity)",
-                    client.client_id,
-                    client.ms_since_last_activity()
-                );
+        let mut documents = Vec::new();
+        for workspace in self.workspaces() {
+            for entry in workspace.lock_documents().entries() {
+                documents.push((workspace.name.clone(), archive::export(&entry)));
             }
-            !timed_out
-        });
+        }
+        let count = documents.len();
+        handover::save(file, documents)?;
+        Ok(count)
+    }
 
-        before - clients.len()
+    pub fn handover_target(&self) -> Option<&str> {
+        self.handover.as_deref()
     }
 
-    /// Send a ping to all connected clients.
-    /// Returns the number of clients pinged.
-    pub fn send_ping_to_all(&self, sequence: u64) -> usize {
+    /// Turn away clients whose Hello falls short of `requirements`.
+    pub fn with_client_requirements(mut self, requirements: ClientRequirements) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    pub fn client_requirements(&self) -> &ClientRequirements {
+        &self.requirements
+    }
+
+    /// Starts refusing ops, then waits up to `timeout` for those already
+    /// queued to be applied, so the documents saved next are final. Returns
+    /// how many are still queued.
+    pub fn begin_handover(&self, timeout: Duration) -> usize {
+        self.handing_over.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let queued: usize = self
+                .documents()
+                .iter()
+                .map(|entry| entry.queue.get().map_or(0, |queue| queue.len()))
+                .sum();
+            if queued == 0 || Instant::now() >= deadline {
+                return queued;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Sends every connection on to the handover target. Returns how many
+    /// were told.
+    pub fn redirect_all(&self) -> usize {
+        let Some(server) = &self.handover else {
+            return 0;
+        };
         let clients = match self.clients.lock() {
             Ok(guard) => guard.clone(),
             Err(poisoned) => poisoned.into_inner().clone(),
         };
+        let message = format!("Server handed over to {}", server);
+        let redirect = ServerMessage::Redirect(RedirectProto {
+            address: server.clone(),
+            message: message.clone(),
+            doc_id: String::new(),
+        });
+        let redirect = Frame::new_arc(ServerMessage::encode(&redirect));
+        // For clients that don't know Redirect
+        let disconnect = disconnect_frame(DisconnectReason::Shutdown, &message);
+        clients
+            .iter()
+            .filter(|client| {
+                client.send(Arc::clone(&redirect)).is_ok()
+                    && client.send(Arc::clone(&disconnect)).is_ok()
+            })
+            .count()
+    }
 
-        let ping_msg = ServerMessage::Ping(sequence);
-        let ping_frame = Frame::new_arc(ServerMessage::encode(&ping_msg));
+    /// Announce connections as idle once they have had no input for `idle_after`.
+    pub fn with_idle_after(mut self, idle_after: Option<Duration>) -> Self {
+        self.idle_after = idle_after;
+        self
+    }
 
-        let mut pinged = 0;
-        for client in clients.iter() {
-            if client.writer_sender.try_send(Arc::clone(&ping_frame)).is_ok() {
-                pinged += 1;
-            }
-        }
+    pub fn batcher(&self) -> Option<&Batcher> {
+        self.batcher.as_ref()
+    }
 
-        pinged
+    pub fn accept_metrics(&self) -> &AcceptMetrics {
+        &self.accept_metrics
     }
 
-    /// Update last activity time for a client.
-    pub fn touch_client(&self, client_id: Uuid) {
-        let clients = match self.clients.lock() {
+    fn lock_workspaces(&self) -> MutexGuard<'_, HashMap<String, Arc<Workspace>>> {
+        match self.workspaces.lock() {
             Ok(guard) => guard,
             Err(poisoned) => poisoned.into_inner(),
-        };
-
-        if let Some(client) = clients.iter().find(|c| c.client_id == client_id) {
-            client.touch();
         }
     }
 
-    pub fn append_op_log(&self, op: Operation) -> Result<(), String> {
-        OperationLog::append_log_arc(Arc::clone(&self.op_log), op)
+    /// The workspace called `name`, created if no one has joined it yet.
+    pub fn workspace(&self, name: &str) -> Arc<Workspace> {
+        let mut workspaces = self.lock_workspaces();
+        let workspace = workspaces.entry(name.to_string()).or_insert_with(|| {
+            info!("[ServerState] Created workspace '{}'", name);
+            Arc::new(Workspace::new(
+                name,
+                self.policies.clone(),
+                self.doc_ids.clone(),
+            ))
+        });
+        Arc::clone(workspace)
     }
 
-    pub fn get_clients_arc(&self) -> Arc<Mutex<Vec<Arc<ClientEntry>>>> {
-        Arc::clone(&self.clients)
+    pub fn workspaces(&self) -> Vec<Arc<Workspace>> {
+        self.lock_workspaces().values().cloned().collect()
     }
 
-    pub fn send_applied_op(
-        &self,
-        operation_proto: OperationProto,
-    ) -> Result<Arc<Frame>, std::io::Error> {
-        let doc_mutex = self.get_document();
+    /// The workspace the client joined; the default one for clients that
+    /// are gone or have not said.
+    fn client_workspace(&self, client_id: Uuid) -> Arc<Workspace> {
+        let name = self.get_client(client_id).map_or_else(
+            || DEFAULT_WORKSPACE.to_string(),
+            |client| client.workspace(),
+        );
+        self.workspace(&name)
+    }
 
-        if operation_proto.doc_id.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Operation missing doc_id",
-            ));
+    /// Puts the client in workspace `name` for the rest of its connection,
+    /// if `token` is a member's or an unexpired guest invite's, for a
+    /// workspace that has members, and the workspace has room. Guests on a
+    /// read-only invite are made viewers. A client that already joined one stays where it
+    /// is.
+    pub fn join_workspace(
+        &self,
+        client_id: Uuid,
+        name: &str,
+        token: &str,
+    ) -> Result<(), ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if client.has_joined_workspace() {
+            if client.workspace() != name {
+                info!(
+                    "[ServerState] Client {} stays in workspace '{}'",
+                    client.label(),
+                    client.workspace()
+                );
+            }
+            return Ok(());
+        }
+        let workspace = self.workspace(name);
+        let access = {
+            let members = self.lock_members();
+            let invites = self.lock_invites();
+            if let Some(member) = members.member(name, token) {
+                Access::Member(member.to_string())
+            } else if let Some(invite) = invites.get(name, token) {
+                invite.check(unix_time_ms())?;
+                Access::Guest(invite.clone())
+            } else if members.is_open(name) {
+                Access::Open
+            } else {
+                return Err(Rejection::NotAMember {
+                    workspace: workspace.label().to_string(),
+                }
+                .into());
+            }
+        };
+        let members = match self.clients.lock() {
+            Ok(guard) => guard.iter().filter(|c| c.workspace() == name).count(),
+            Err(poisoned) => poisoned
+                .into_inner()
+                .iter()
+                .filter(|c| c.workspace() == name)
+                .count(),
+        };
+        // The client counts towards the default workspace until it joins
+        let members = members - usize::from(name == DEFAULT_WORKSPACE);
+        self.quota.check_clients(&workspace, members)?;
+        let joined_as = match &access {
+            Access::Open => String::new(),
+            Access::Member(member) => format!(" as '{}'", member),
+            Access::Guest(invite) => format!(" as a guest of '{}'", invite.path),
+        };
+        info!(
+            "[ServerState] Client {} joined workspace {}{}",
+            client.label(),
+            workspace.label(),
+            joined_as
+        );
+        if let Access::Guest(Invite {
+            read_only: true, ..
+        }) = access
+        {
+            client.set_read_only();
+        }
+        if let Access::Member(member) = &access {
+            self.record_event(name, WorkspaceEventKind::MemberJoined, "", member, "");
+        }
+        client.join_workspace(name, access);
+        let session = client
+            .hello_id()
+            .and_then(|id| self.lock_offline().resume(&id, name));
+        match session {
+            Some(session) => {
+                RESUMES.record(Resume::Session);
+                info!(
+                    "[ServerState] Client {} resumed its session in workspace {}",
+                    client.label(),
+                    workspace.label()
+                );
+                if session.away {
+                    client.set_away(true);
+                    self.refresh_presence(&client);
+                }
+            }
+            None => workspace.activity().joined(activity_name(&client)),
         }
+        Ok(())
+    }
 
-        let parsed_client_id = Uuid::parse_str(&operation_proto.client_id).map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid client UUID")
-        })?;
+    /// The client's workspace, provided the client may use it: any
+    /// connection may use a workspace open to all, but once it has members
+    /// only they may, which leaves out connections that joined before.
+    /// Guests may until their invite expires.
+    fn member_workspace(&self, client: &ClientEntry) -> Result<Arc<Workspace>, Rejection> {
+        let workspace = self.workspace(&client.workspace());
+        match client.guest() {
+            Some(invite) => invite.check(unix_time_ms())?,
+            None if client.member().is_none() && !self.lock_members().is_open(&workspace.name) => {
+                return Err(Rejection::NotAMember {
+                    workspace: workspace.label().to_string(),
+                });
+            }
+            None => {}
+        }
+        Ok(workspace)
+    }
 
-        let mut op_kind =
-            Operation::convert_operation(operation_proto.clone()).ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing op kind")
-            })?;
+    fn lock_invites(&self) -> MutexGuard<'_, InviteStore> {
+        match self.invites.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
 
-        let client_version = operation_proto.client_version;
+    fn lock_freezes(&self) -> MutexGuard<'_, Freezes> {
+        match self.freezes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
 
-        let (updated_content, new_version) = {
-            let mut doc = doc_mutex.lock().map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to lock document: {}", e),
-                )
-            })?;
+    /// Makes a workspace, or one document in it, read-only for the window
+    /// `freeze` gives, on the operator's behalf, in place of any window it
+    /// already had. Clients are told when it starts, which may be now.
+    pub fn freeze(&self, mut freeze: Freeze) {
+        freeze.path = freeze.path.map(|path| resolve_path(&path).to_string());
+        info!(
+            "[ServerState] Freezing {} from {} until {}",
+            freeze.target(),
+            freeze.starts_at_ms,
+            freeze
+                .ends_at_ms
+                .map_or_else(|| "unfrozen".to_string(), |end| end.to_string())
+        );
+        if let Some(replaced) = self.lock_freezes().schedule(freeze) {
+            self.announce_freeze(&replaced, false);
+        }
+        self.tick_freezes();
+    }
 
-            if client_version > doc.version {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!(
-                        "Client version {} is from the future (server is {})",
-                        client_version, doc.version
-                    ),
-                ));
-            }
+    /// Lifts the window on workspace `name` (`path` `None`) or on one of its
+    /// documents, telling clients if it had started. False if there was none.
+    pub fn unfreeze(&self, name: &str, path: Option<&str>) -> bool {
+        let path = path.map(resolve_path);
+        let Some((freeze, announced)) = self.lock_freezes().unfreeze(name, path) else {
+            return false;
+        };
+        info!("[ServerState] Unfroze {}", freeze.target());
+        if announced {
+            self.announce_freeze(&freeze, false);
+        }
+        true
+    }
 
-            if client_version < doc.version {
-                // Get ops from log: [client_version, doc.version)
-                let past_ops = self
-                    .op_log
-                    .get_ops_in_range(client_version, doc.version)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    /// Every freeze window, started or not.
+    pub fn freezes(&self) -> Vec<Freeze> {
+        self.lock_freezes().list()
+    }
 
-                // Transform incoming op against all past ops
-                for past_op in past_ops {
-                    op_kind = crate::transform::transform(op_kind, past_op.kind);
+    /// Tells clients about the freeze windows that started or ended since
+    /// the last call, dropping the ended ones.
+    pub fn tick_freezes(&self) {
+        let changed = self.lock_freezes().tick(unix_time_ms());
+        for (freeze, frozen) in changed {
+            info!(
+                "[ServerState] {} {}",
+                freeze.target(),
+                if frozen {
+                    "is now frozen"
+                } else {
+                    "is no longer frozen"
                 }
-            }
+            );
+            self.announce_freeze(&freeze, frozen);
+        }
+    }
 
-            // Apply transformed op
-            doc.apply_op(&op_kind)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    fn announce_freeze(&self, freeze: &Freeze, frozen: bool) {
+        let message = ServerMessage::Freeze(freeze.to_proto(frozen));
+        self.send_to_workspace(
+            &freeze.workspace,
+            Frame::new_arc(ServerMessage::encode(&message)),
+        );
+    }
+
+    /// Sends the operator's `message` to every connection in workspace
+    /// `name`, or to every connection with `None`. Returns how many it was
+    /// queued for.
+    pub fn send_notice(&self, name: Option<&str>, message: &str) -> usize {
+        let notice = NoticeProto {
+            message: message.to_string(),
+            workspace: name.unwrap_or_default().to_string(),
+            sent_at_ms: unix_time_ms(),
+        };
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Notice(notice)));
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let sent = clients
+            .iter()
+            .filter(|c| name.is_none_or(|name| c.workspace() == name))
+            .filter(|c| c.send(Arc::clone(&frame)).is_ok())
+            .count();
+        info!(
+            "[ServerState] Sent a notice to {} connection(s){}: {}",
+            sent,
+            name.map(|name| format!(" in workspace {}", name))
+                .unwrap_or_default(),
+            message
+        );
+        sent
+    }
 
-            (doc.content.clone(), doc.version)
+    /// Creates a guest invite to the document at `path` in workspace `name`
+    /// on the operator's behalf, good for `ttl_ms` (0 for the default).
+    pub fn add_invite(&self, name: &str, path: &str, read_only: bool, ttl_ms: u64) -> InviteProto {
+        let now_ms = unix_time_ms();
+        let invite = Invite {
+            workspace: name.to_string(),
+            path: resolve_path(path).to_string(),
+            read_only,
+            expires_at_ms: invites::expiry(now_ms, ttl_ms),
         };
+        let token = self.lock_invites().create(invite.clone(), now_ms);
+        info!(
+            "[ServerState] Invited a guest {} '{}' in workspace {} for {}ms",
+            if read_only { "to view" } else { "to edit" },
+            invite.path,
+            self.workspace(name).label(),
+            invite.expires_at_ms - now_ms
+        );
+        invite.to_proto(&token)
+    }
 
-        // Log the operation
-        // server_version is the version this op was applied TO (i.e., new_version - 1)
-        let final_op = Operation {
-            op_id: operation_proto.op_id,
-            kind: op_kind,
-            doc_id: operation_proto.doc_id.clone(),
-            new_content: String::new(),
-            client_id: parsed_client_id,
-            client_version,
-            server_version: new_version - 1,
+    /// Creates a guest invite into the client's workspace, which only its
+    /// members may do.
+    pub fn create_invite(
+        &self,
+        client_id: Uuid,
+        request: &CreateInviteProto,
+    ) -> Result<InviteProto, ServerError> {
+        let workspace = self.managed_workspace(client_id)?;
+        Ok(self.add_invite(&workspace, &request.path, request.read_only, request.ttl_ms))
+    }
+
+    /// Commits the persisted documents to git with `summary` as the
+    /// message's first line. Returns `None` if nothing changed since the
+    /// last commit.
+    pub fn commit_history(&self, summary: &str) -> Result<Option<Commit>, ServerError> {
+        let Some(history) = &self.history else {
+            return Err(ServerError::CheckpointFailed(
+                "the server keeps no git history".to_string(),
+            ));
+        };
+        let mut history = match history.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
         };
+        let label = |client_id: Uuid| {
+            self.get_client(client_id)
+                .map_or_else(|| client_id.to_string(), |client| client.label())
+        };
+        history
+            .commit(&self.documents(), summary, label)
+            .map_err(|e| ServerError::CheckpointFailed(e.to_string()))
+    }
 
-        if let Err(e) = self.append_op_log(final_op) {
-            eprintln!("Failed to append to op_log: {}", e);
+    /// Squashes a document's op log up to its current version into a
+    /// milestone labelled `label`, short of the ops clients that are
+    /// connected, or were within the offline grace period, still need to
+    /// catch up or have their edits in flight transformed.
+    pub fn squash_history(
+        &self,
+        entry: &DocumentEntry,
+        label: &str,
+    ) -> Result<Milestone, ServerError> {
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(ServerError::CheckpointFailed(
+                "a document checkpoint needs a label".to_string(),
+            ));
         }
+        let doc_id = match entry.document.lock() {
+            Ok(doc) => doc.uuid.to_string(),
+            Err(poisoned) => poisoned.into_inner().uuid.to_string(),
+        };
+        // Cursors only move forward, so one read before the lock is taken
+        // keeps no less than needed
+        let present = self.present_clients();
+        let keep_from =
+            self.lock_cursors()
+                .floor(&doc_id, &self.epoch, self.offline_since_ms(), &present);
+        // Ops are logged under the document lock, so none can slip in
+        // between the version read and the squash
+        let milestone = {
+            let _doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            entry.op_log.squash(label, unix_time_ms(), keep_from)
+        };
+        info!(
+            "[History] '{}' v{}: squashed {} version(s) into '{}'",
+            entry.path, milestone.version, milestone.squashed, milestone.label
+        );
+        Ok(milestone)
+    }
 
-        let sync_doc = SyncDocumentProto {
-            doc_id: operation_proto.doc_id.clone(),
-            content: updated_content,
-            version: new_version,
+    /// Names the current version of a document the client has open, and
+    /// sends its subscribers the document's history with the new tag.
+    /// Read-only connections and guests may not tag.
+    pub fn tag_version(
+        &self,
+        client_id: Uuid,
+        request: &TagVersionProto,
+    ) -> Result<Tag, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if let Some(invite) = client.guest() {
+            return Err(Rejection::GuestRestricted { path: invite.path }.into());
+        }
+        if client.is_read_only() {
+            return Err(Rejection::ReadOnly.into());
+        }
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > MAX_TAG_NAME_BYTES {
+            return Err(ServerError::TagRejected(format!(
+                "a tag needs a name of up to {} bytes",
+                MAX_TAG_NAME_BYTES
+            )));
+        }
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let tag = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let tag = Tag {
+                name: name.to_string(),
+                version: doc.version,
+                content: doc.snapshot(),
+                client_id,
+                created_ms: unix_time_ms(),
+            };
+            entry
+                .op_log
+                .add_tag(tag.clone(), MAX_TAGS_PER_DOCUMENT)
+                .map_err(ServerError::TagRejected)?;
+            tag
         };
+        let history = ServerMessage::History(history_proto(&entry));
+        self.send_to_subscribers(
+            &request.doc_id,
+            Frame::new_arc(ServerMessage::encode(&history)),
+        );
+        Ok(tag)
+    }
 
-        let server_message = ServerMessage::SyncDocument(sync_doc);
-        Ok(Frame::new_arc(ServerMessage::encode(&server_message)))
+    /// The history of a document the client has open, with its content at
+    /// the tag asked for, if any.
+    pub fn history(
+        &self,
+        client_id: Uuid,
+        request: &GetHistoryProto,
+    ) -> Result<HistoryProto, ServerError> {
+        if request.doc_id.is_empty() {
+            return self.workspace_history(client_id, request.events_after);
+        }
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let mut history = history_proto(&entry);
+        if !request.tag.is_empty() {
+            let tag = entry
+                .op_log
+                .find_tag(&request.tag)
+                .ok_or_else(|| ServerError::TagRejected(format!("no tag '{}'", request.tag)))?;
+            history.tag = tag.name;
+            history.content = tag.content.as_str().to_owned();
+        }
+        Ok(history)
+    }
+
+    /// The events of the client's workspace after `events_after`; guests,
+    /// who only see their one document, get none of it.
+    fn workspace_history(
+        &self,
+        client_id: Uuid,
+        events_after: u64,
+    ) -> Result<HistoryProto, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if let Some(invite) = client.guest() {
+            return Err(Rejection::GuestRestricted { path: invite.path }.into());
+        }
+        let workspace = self.member_workspace(&client)?;
+        Ok(HistoryProto {
+            events: self.lock_events().after(&workspace.name, events_after),
+            ..Default::default()
+        })
+    }
+
+    fn lock_events(&self) -> MutexGuard<'_, EventLog> {
+        match self.events.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn lock_compliance(&self) -> MutexGuard<'_, ComplianceLog> {
+        match self.compliance.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Reads the compliance record back and checks its chain: the number of
+    /// entries, or where it breaks. `None` when nothing is recorded.
+    pub fn verify_compliance(&self) -> Option<Result<u64, String>> {
+        self.lock_compliance().verify()
+    }
+
+    /// Records `client` becoming `status` if its workspace is recorded.
+    fn record_presence(&self, client: &ClientEntry, status: PresenceStatus, reason: &str) {
+        let workspace = client.workspace();
+        let mut compliance = self.lock_compliance();
+        if compliance.records(&workspace) {
+            let presence = presence_proto(client, status, reason);
+            compliance.presence(&workspace, &presence, unix_time_ms());
+        }
+    }
+
+    fn record_event(
+        &self,
+        workspace: &str,
+        kind: WorkspaceEventKind,
+        path: &str,
+        member: &str,
+        detail: &str,
+    ) {
+        let event =
+            self.lock_events()
+                .record(workspace, kind, path, member, detail, unix_time_ms());
+        self.workspace(workspace).activity().event(event);
+    }
+
+    /// Subscribes the client to its workspace's activity feed, or
+    /// unsubscribes it. Guests, who only see their one document, may not.
+    pub fn watch_activity(&self, client_id: Uuid, watch: bool) -> Result<(), ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if let Some(invite) = client.guest() {
+            return Err(Rejection::GuestRestricted { path: invite.path }.into());
+        }
+        self.member_workspace(&client)?;
+        client.watch_activity(watch);
+        Ok(())
+    }
+
+    /// Sends each workspace's activity since the last call to its
+    /// connections watching it. Returns how many frames were queued.
+    pub fn send_activity(&self) -> usize {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let mut editors = HashMap::new();
+        for applied in self.activity_feed.try_iter() {
+            let editor = editors.entry(applied.origin).or_insert_with(|| {
+                clients
+                    .iter()
+                    .find(|c| c.client_id == applied.origin)
+                    .map_or_else(|| applied.origin.to_string(), |c| activity_name(c))
+            });
+            let operation = &applied.operation;
+            self.workspace(&applied.workspace).activity().edit(
+                &operation.doc_id,
+                &applied.path,
+                editor,
+                1,
+                operation.server_version + 1,
+            );
+        }
+
+        let mut sent = 0;
+        for workspace in self.workspaces() {
+            let Some(activity) = workspace.activity().take() else {
+                continue;
+            };
+            let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Activity(activity)));
+            for client in clients
+                .iter()
+                .filter(|c| c.is_watching_activity() && c.workspace() == workspace.name)
+            {
+                if client.send(Arc::clone(&frame)).is_ok() {
+                    sent += 1;
+                }
+            }
+        }
+        sent
+    }
+
+    /// Commits the persisted documents at the client's request, or squashes
+    /// the history of the document it names; read-only connections and
+    /// guests may do neither.
+    pub fn checkpoint(
+        &self,
+        client_id: Uuid,
+        request: &CheckpointProto,
+    ) -> Result<CheckpointProto, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if let Some(invite) = client.guest() {
+            return Err(Rejection::GuestRestricted { path: invite.path }.into());
+        }
+        if client.is_read_only() {
+            return Err(Rejection::ReadOnly.into());
+        }
+        let member = client.member().unwrap_or_default();
+        if !request.doc_id.is_empty() {
+            let entry = self.subscribed_document(client_id, &request.doc_id)?;
+            let milestone = self.squash_history(&entry, &request.message)?;
+            self.record_event(
+                &client.workspace(),
+                WorkspaceEventKind::CheckpointTaken,
+                &entry.path,
+                &member,
+                &milestone.label,
+            );
+            return Ok(CheckpointProto {
+                message: milestone.label.clone(),
+                doc_id: request.doc_id.clone(),
+                milestone: Some(milestone.to_proto()),
+                ..Default::default()
+            });
+        }
+        let commit = self.commit_history(&request.message)?;
+        if let Some(commit) = &commit {
+            self.record_event(
+                &client.workspace(),
+                WorkspaceEventKind::CheckpointTaken,
+                "",
+                &member,
+                &commit.id,
+            );
+        }
+        Ok(CheckpointProto {
+            message: request.message.clone(),
+            commit: commit.as_ref().map(|c| c.id.clone()).unwrap_or_default(),
+            contributors: commit.map(|c| c.contributors).unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    fn lock_members(&self) -> MutexGuard<'_, MembershipStore> {
+        match self.members.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Member names by workspace, for the workspaces that have members.
+    pub fn members(&self) -> Vec<(String, Vec<String>)> {
+        let members = self.lock_members();
+        members
+            .workspaces()
+            .into_iter()
+            .map(|workspace| {
+                let names = members.members(workspace);
+                (
+                    workspace.to_string(),
+                    names.into_iter().map(String::from).collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Makes `member` a member of workspace `name` on the operator's behalf.
+    /// Returns their token.
+    pub fn add_member(&self, name: &str, member: &str) -> Result<String, ServerError> {
+        let token = self
+            .lock_members()
+            .invite(name, member)
+            .map_err(ServerError::MembershipRejected)?;
+        info!(
+            "[ServerState] '{}' is now a member of workspace {}",
+            member,
+            self.workspace(name).label()
+        );
+        self.record_event(name, WorkspaceEventKind::MemberAdded, "", member, "");
+        Ok(token)
+    }
+
+    /// Takes `member` out of workspace `name` and closes their connections
+    /// to it. Returns how many were closed.
+    pub fn revoke_member(&self, name: &str, member: &str) -> Result<usize, ServerError> {
+        self.lock_members()
+            .remove(name, member)
+            .map_err(ServerError::MembershipRejected)?;
+        self.record_event(name, WorkspaceEventKind::MemberRemoved, "", member, "");
+        let connections: Vec<Uuid> = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+        .iter()
+        .filter(|c| c.workspace() == name && c.member().as_deref() == Some(member))
+        .map(|c| c.client_id)
+        .collect();
+        for &client_id in &connections {
+            self.notify_disconnect(
+                client_id,
+                DisconnectReason::NotAMember,
+                "Your membership of this workspace was revoked",
+            );
+            self.drop_client(client_id, "membership revoked");
+        }
+        info!(
+            "[ServerState] '{}' is no longer a member of workspace {}; closed {} connection(s)",
+            member,
+            self.workspace(name).label(),
+            connections.len()
+        );
+        Ok(connections.len())
+    }
+
+    /// Invites `member` into the client's workspace, which only its members
+    /// may do.
+    pub fn invite_member(
+        &self,
+        client_id: Uuid,
+        member: &str,
+    ) -> Result<MemberTokenProto, ServerError> {
+        let workspace = self.managed_workspace(client_id)?;
+        let token = self.add_member(&workspace, member)?;
+        Ok(MemberTokenProto {
+            workspace,
+            member: member.to_string(),
+            token,
+        })
+    }
+
+    /// Removes `member` from the client's workspace, which only its members
+    /// may do.
+    pub fn remove_member(&self, client_id: Uuid, member: &str) -> Result<usize, ServerError> {
+        let workspace = self.managed_workspace(client_id)?;
+        self.revoke_member(&workspace, member)
+    }
+
+    /// The name of the client's workspace, if the client is a member who may
+    /// change who else is.
+    fn managed_workspace(&self, client_id: Uuid) -> Result<String, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if client.member().is_none() {
+            return Err(Rejection::NotAMember {
+                workspace: self.workspace(&client.workspace()).label().to_string(),
+            }
+            .into());
+        }
+        if client.is_read_only() {
+            return Err(Rejection::ReadOnly.into());
+        }
+        Ok(client.workspace())
+    }
+
+    /// Looks `doc_id` up across workspaces; ids are unique, and a client can
+    /// only reach documents it has open, which are all in its workspace.
+    pub fn get_document(&self, doc_id: &str) -> Option<Arc<DocumentEntry>> {
+        self.workspaces()
+            .iter()
+            .find_map(|workspace| workspace.lock_documents().get(doc_id))
+    }
+
+    /// Every workspace's documents.
+    pub fn documents(&self) -> Vec<Arc<DocumentEntry>> {
+        self.workspaces()
+            .iter()
+            .flat_map(|workspace| workspace.lock_documents().entries())
+            .collect()
+    }
+
+    /// Trims every document's op log to `policy`, short of the ops clients
+    /// that are connected, or were within the offline grace period, still
+    /// need to resume it, unless the size limit says otherwise. Returns the
+    /// log entries dropped, which are also added to the compaction metrics.
+    pub fn compact_op_logs(&self, policy: &RetentionPolicy) -> Compacted {
+        let since_ms = self.offline_since_ms();
+        let present = self.present_clients();
+        let mut compacted = Compacted::default();
+        for entry in self.documents() {
+            let (doc_id, version) = match entry.document.lock() {
+                Ok(doc) => (doc.uuid.to_string(), doc.version),
+                Err(poisoned) => {
+                    let doc = poisoned.into_inner();
+                    (doc.uuid.to_string(), doc.version)
+                }
+            };
+            let keep_from = self
+                .lock_cursors()
+                .floor(&doc_id, &self.epoch, since_ms, &present);
+            compacted.add(policy.compact(&entry.op_log, version, keep_from));
+        }
+        COMPACTIONS.record(compacted);
+        compacted
+    }
+
+    /// Subscribe a client to the document at `path` in its workspace (the
+    /// default document, or a guest's invited one, if empty), creating it if
+    /// the workspace has room and missing documents are created. The default
+    /// document is always created.
+    /// Returns the SyncDocument frame to send to the client.
+    pub fn open_document(&self, client_id: Uuid, path: &str) -> Result<Arc<Frame>, ServerError> {
+        let (_, _, sync) = self.open(client_id, path)?;
+        let message = ServerMessage::SyncDocument(sync);
+        Ok(Frame::new_arc(ServerMessage::encode(&message)))
+    }
+
+    /// `open_document` for a client that still has the document as of its
+    /// acknowledgement cursor. Returns the frames to send it: the ops applied
+    /// since the cursor, if any, then a sync without the content. A cursor
+    /// for another document or server run, or whose ops were compacted, gets
+    /// the full sync instead.
+    pub fn resume_document(
+        &self,
+        client_id: Uuid,
+        path: &str,
+    ) -> Result<Vec<Arc<Frame>>, ServerError> {
+        let (client, entry, sync) = self.open(client_id, path)?;
+        let cursor = self
+            .lock_cursors()
+            .get(&client.cursor_key(), &client.workspace(), &sync.path)
+            .cloned();
+        let since = cursor
+            .filter(|cursor| {
+                cursor.doc_id == sync.doc_id
+                    && cursor.epoch == self.epoch
+                    && cursor.version <= sync.version
+            })
+            .and_then(|cursor| {
+                entry
+                    .op_log
+                    .get_ops_in_range(cursor.version, sync.version)
+                    .ok()
+            });
+        let Some(since) = since else {
+            RESUMES.record(Resume::FullResync);
+            debug!(
+                "[ServerState] Client {} cannot resume '{}'; sending it whole",
+                client.label(),
+                sync.path
+            );
+            let message = ServerMessage::SyncDocument(sync);
+            return Ok(vec![Frame::new_arc(ServerMessage::encode(&message))]);
+        };
+        RESUMES.record(Resume::Document);
+        info!(
+            "[ServerState] Client {} resumed '{}' with {} op(s)",
+            client.label(),
+            sync.path,
+            since.len()
+        );
+        let mut frames = Vec::with_capacity(2);
+        if !since.is_empty() {
+            let batch = ServerMessage::OperationBatch(OperationBatchProto {
+                operations: since.iter().map(Operation::to_proto).collect(),
+                more: false,
+            });
+            frames.push(Frame::new_arc(ServerMessage::encode(&batch)));
+        }
+        let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+            content: String::new(),
+            resumed: true,
+            ..sync
+        });
+        frames.push(Frame::new_arc(ServerMessage::encode(&sync)));
+        Ok(frames)
+    }
+
+    /// Moves the client's cursor in a document it has open up to
+    /// `ack.version`, which the document must have reached.
+    pub fn acknowledge(&self, client_id: Uuid, ack: &AckProto) -> Result<(), ServerError> {
+        let entry = self.subscribed_document(client_id, &ack.doc_id)?;
+        let current = match entry.document.lock() {
+            Ok(doc) => doc.version,
+            Err(poisoned) => poisoned.into_inner().version,
+        };
+        if ack.version > current {
+            return Err(Rejection::UnknownVersion {
+                version: ack.version,
+                first: entry.op_log.first_version(),
+                current,
+            }
+            .into());
+        }
+        let workspace = self.client_workspace(client_id);
+        self.record_ack(
+            client_id,
+            &workspace.name,
+            &entry.path,
+            &ack.doc_id,
+            ack.version,
+        );
+        Ok(())
+    }
+
+    fn record_ack(&self, client_id: Uuid, workspace: &str, path: &str, doc_id: &str, version: u64) {
+        let Some(client) = self.get_client(client_id) else {
+            return;
+        };
+        let cursor = Cursor {
+            doc_id: doc_id.to_string(),
+            epoch: self.epoch.clone(),
+            version,
+            seen_ms: unix_time_ms(),
+        };
+        self.lock_cursors()
+            .ack(&client.cursor_key(), workspace, path, cursor);
+    }
+
+    /// Forgets the cursors of clients gone longer than the offline grace
+    /// period, and saves the rest if they are kept in a file, with the
+    /// version of each persisted document that is on disk.
+    pub fn save_cursors(&self) {
+        let present = self.present_clients();
+        let mut marks = Vec::new();
+        for workspace in self.workspaces() {
+            for entry in workspace.lock_documents().entries() {
+                if entry.backing_file.is_none() {
+                    continue;
+                }
+                let doc = match entry.document.lock() {
+                    Ok(doc) => doc,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                // Edited since: the mark of the last save still stands
+                if doc.version != entry.saved_version.load(Ordering::Acquire) {
+                    continue;
+                }
+                let mark = SavedMark {
+                    epoch: self.epoch.clone(),
+                    version: doc.version,
+                    hash: sha256::hex_digest(doc.content.as_bytes()),
+                };
+                marks.push((workspace.name.clone(), entry.path.clone(), mark));
+            }
+        }
+        let mut cursors = self.lock_cursors();
+        for (workspace, path, mark) in marks {
+            cursors.mark_saved(&workspace, &path, mark);
+        }
+        cursors.prune(self.offline_since_ms(), &present);
+        cursors.save();
+    }
+
+    /// Ends the sessions of clients that stayed away past the offline grace
+    /// period: the workspace is told they left, and their cursors are
+    /// forgotten, so they start afresh. Returns the number ended.
+    pub fn expire_offline_sessions(&self) -> usize {
+        let expired = self.lock_offline().expire(self.offline_since_ms());
+        for (client, workspace, session) in &expired {
+            RESUMES.record(Resume::Expired);
+            self.workspace(workspace)
+                .activity()
+                .left(session.activity_name.clone());
+            let forgotten = self.lock_cursors().forget(client, workspace);
+            info!(
+                "[ServerState] Session of {} in workspace '{}' expired; forgot {} cursor(s)",
+                client, workspace, forgotten
+            );
+        }
+        expired.len()
+    }
+
+    /// Connected clients, and those within the offline grace period, by
+    /// cursor key and workspace.
+    fn present_clients(&self) -> ClientKeys {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let mut present: ClientKeys = clients
+            .iter()
+            .map(|client| (client.cursor_key(), client.workspace()))
+            .collect();
+        present.extend(self.lock_offline().keys().cloned());
+        present
+    }
+
+    fn offline_since_ms(&self) -> u64 {
+        unix_time_ms().saturating_sub(self.offline_grace.as_millis() as u64)
+    }
+
+    fn lock_offline(&self) -> MutexGuard<'_, OfflineSessions> {
+        match self.offline.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn lock_cursors(&self) -> MutexGuard<'_, AckCursors> {
+        match self.cursors.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Subscribes the client to the document at `path`, as `open_document`
+    /// describes, and takes its sync.
+    fn open(
+        &self,
+        client_id: Uuid,
+        path: &str,
+    ) -> Result<(Arc<ClientEntry>, Arc<DocumentEntry>, SyncDocumentProto), ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        let workspace = self.member_workspace(&client)?;
+        // A guest's Hello opens the invited document
+        let guest = client.guest();
+        let path = match &guest {
+            Some(invite) if path.is_empty() => invite.path.as_str(),
+            _ => resolve_path(path),
+        };
+        if let Some(invite) = &guest {
+            invite.check_path(path)?;
+        }
+        let (entry, created) = {
+            let mut documents = workspace.lock_documents();
+            let created = documents.at_path(path).is_none();
+            if created {
+                if self.missing_documents == MissingDocuments::Refuse && path != DEFAULT_DOC_PATH {
+                    return Err(Rejection::NoSuchDocument {
+                        path: path.to_string(),
+                    }
+                    .into());
+                }
+                self.quota.check_documents(&workspace, documents.len())?;
+            }
+            (documents.open(path), created)
+        };
+        if created {
+            self.record_event(
+                &workspace.name,
+                WorkspaceEventKind::DocumentCreated,
+                path,
+                &client.member().unwrap_or_default(),
+                "opened",
+            );
+        }
+        let sync = entry.sync_proto();
+
+        client.subscribe(&sync.doc_id);
+        info!(
+            "[ServerState] Client {} opened '{}' ({})",
+            client.label(),
+            path,
+            sync.doc_id
+        );
+        Ok((client, entry, sync))
+    }
+
+    /// The document `doc_id`, provided `client_id` has it open.
+    fn subscribed_document(
+        &self,
+        client_id: Uuid,
+        doc_id: &str,
+    ) -> Result<Arc<DocumentEntry>, Rejection> {
+        let entry = self
+            .get_document(doc_id)
+            .ok_or_else(|| Rejection::UnknownDocument {
+                doc_id: doc_id.to_string(),
+            })?;
+        let client = self.get_client(client_id);
+        if let Some(invite) = client.as_ref().and_then(|client| client.guest()) {
+            invite.check(unix_time_ms())?;
+        }
+        let subscribed = client.is_some_and(|client| client.is_subscribed(doc_id));
+        if !subscribed {
+            return Err(Rejection::NotSubscribed {
+                doc_id: doc_id.to_string(),
+            });
+        }
+        Ok(entry)
+    }
+
+    /// Archive a document the client has open, with its retained history.
+    pub fn export_document(
+        &self,
+        client_id: Uuid,
+        doc_id: &str,
+    ) -> Result<DocumentArchiveProto, Rejection> {
+        let entry = self.subscribed_document(client_id, doc_id)?;
+        Ok(archive::export(&entry))
+    }
+
+    /// A copy of a document the client has open, rendered as it asks, in
+    /// the chunks it is sent in.
+    pub fn export_copy(
+        &self,
+        client_id: Uuid,
+        request: &ExportRequestProto,
+    ) -> Result<Vec<ExportChunkProto>, ServerError> {
+        let format = ExportFormat::try_from(request.format)
+            .map_err(|_| ServerError::Malformed("unknown export format"))?;
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        Ok(export::export(&entry, format))
+    }
+
+    fn lock_attachments(&self) -> MutexGuard<'_, AttachmentStore> {
+        match self.attachments.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Attachments stored, and their bytes.
+    pub fn attachment_usage(&self) -> (usize, u64) {
+        self.lock_attachments().usage()
+    }
+
+    /// Takes a chunk of a file the client attaches to a document it has
+    /// open, which read-only connections may not. Returns the stored
+    /// attachment once the last chunk is in; a refused chunk drops the
+    /// upload.
+    pub fn upload_attachment(
+        &self,
+        client_id: Uuid,
+        chunk: &UploadAttachmentProto,
+    ) -> Result<Option<AttachmentProto>, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        let allowed = match client.is_read_only() {
+            true => Err(Rejection::ReadOnly),
+            false => self.subscribed_document(client_id, &chunk.doc_id).map(drop),
+        };
+        if let Err(rejection) = allowed {
+            client.abandon_upload();
+            return Err(rejection.into());
+        }
+        let max_bytes = self.lock_attachments().limits().max_bytes;
+        let data = match client.add_upload_chunk(&chunk.data, chunk.more, max_bytes) {
+            Ok(Some(data)) => data,
+            Ok(None) => return Ok(None),
+            Err(size) => {
+                return Err(ServerError::AttachmentRejected(format!(
+                    "attachment of {} bytes so far is over the {} allowed",
+                    size,
+                    max_bytes.unwrap_or_default()
+                )));
+            }
+        };
+        let size = data.len() as u64;
+        let attachment_id = self
+            .lock_attachments()
+            .store(data, unix_time_ms())
+            .map_err(ServerError::AttachmentRejected)?;
+        info!(
+            "[ServerState] Client {} attached {} ({} bytes) to {}",
+            client.label(),
+            attachment_id,
+            size,
+            chunk.doc_id
+        );
+        Ok(Some(AttachmentProto {
+            doc_id: chunk.doc_id.clone(),
+            attachment_id,
+            size,
+        }))
+    }
+
+    /// An attachment referenced by a document the client has open, in the
+    /// chunks it is sent in.
+    pub fn fetch_attachment(
+        &self,
+        client_id: Uuid,
+        request: &FetchAttachmentProto,
+    ) -> Result<Vec<AttachmentChunkProto>, ServerError> {
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let content = match entry.document.lock() {
+            Ok(doc) => doc.snapshot(),
+            Err(poisoned) => poisoned.into_inner().snapshot(),
+        };
+        if !attachments::references(&content).any(|id| id == request.attachment_id) {
+            return Err(ServerError::AttachmentRejected(format!(
+                "{} does not reference attachment {}",
+                entry.path, request.attachment_id
+            )));
+        }
+        let data = self
+            .lock_attachments()
+            .get(&request.attachment_id)
+            .map_err(ServerError::AttachmentRejected)?;
+        let parts = export::split(&data);
+        let last = parts.len() - 1;
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(n, part)| AttachmentChunkProto {
+                attachment_id: request.attachment_id.clone(),
+                data: part.to_vec(),
+                more: n < last,
+            })
+            .collect())
+    }
+
+    /// Deletes the attachments no document references, once their grace
+    /// period is over. Returns how many went, and their bytes.
+    pub fn collect_attachments(&self) -> (usize, u64) {
+        let mut referenced = HashSet::new();
+        for entry in self.documents() {
+            let content = match entry.document.lock() {
+                Ok(doc) => doc.snapshot(),
+                Err(poisoned) => poisoned.into_inner().snapshot(),
+            };
+            referenced.extend(attachments::references(&content).map(String::from));
+        }
+        self.lock_attachments()
+            .collect_garbage(&referenced, unix_time_ms())
+    }
+
+    /// Restore an archived document at its path and subscribe the client to it.
+    /// Returns the SyncDocument frame for the restored document.
+    pub fn import_document(
+        &self,
+        client_id: Uuid,
+        archive: DocumentArchiveProto,
+    ) -> Result<Arc<Frame>, ServerError> {
+        let path = archive.path.clone();
+        let rejected = ServerError::ImportRejected;
+        self.install_document(client_id, &path, "imported", rejected, |backing_file| {
+            archive::restore(archive, backing_file).map_err(rejected)
+        })
+    }
+
+    /// Create a document at `request.path` from a named template and subscribe
+    /// the client to it. Returns the SyncDocument frame for the new document.
+    pub fn create_from_template(
+        &self,
+        client_id: Uuid,
+        request: &CreateFromTemplateProto,
+    ) -> Result<Arc<Frame>, ServerError> {
+        let rejected = |reason: &str| ServerError::TemplateRejected(reason.to_string());
+        let templates = self
+            .templates
+            .as_ref()
+            .ok_or_else(|| rejected("templates are not enabled on this server"))?;
+        if request.path.is_empty() {
+            return Err(rejected("a path is required"));
+        }
+        let content = templates
+            .instantiate(&request.template, &request.variables)
+            .map_err(ServerError::TemplateRejected)?;
+        let rejected = ServerError::TemplateRejected;
+        self.install_document(
+            client_id,
+            &request.path,
+            "created",
+            rejected,
+            |backing_file| {
+                Ok(DocumentEntry::with_content(
+                    &request.path,
+                    content,
+                    backing_file,
+                ))
+            },
+        )
+    }
+
+    /// Register the document built by `build` at `path` and subscribe the
+    /// client to it. Only a path that is free or holds a never-edited, empty
+    /// document can be taken; anything else would silently discard edits.
+    /// Clients that had the replaced document open are moved over to the new
+    /// one. `build` is given the replaced document's backing file, if any;
+    /// a taken path is reported with `rejected`.
+    fn install_document(
+        &self,
+        client_id: Uuid,
+        path: &str,
+        action: &str,
+        rejected: fn(String) -> ServerError,
+        build: impl FnOnce(Option<PathBuf>) -> Result<DocumentEntry, ServerError>,
+    ) -> Result<Arc<Frame>, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if let Some(invite) = client.guest() {
+            return Err(Rejection::GuestRestricted { path: invite.path }.into());
+        }
+
+        let workspace = self.member_workspace(&client)?;
+        self.lock_compliance().check(&workspace.name)?;
+        let (entry, replaced) = {
+            let mut documents = workspace.lock_documents();
+            let existing = documents.at_path(path);
+            if existing.is_none() {
+                self.quota.check_documents(&workspace, documents.len())?;
+            }
+            if let Some(existing) = &existing {
+                let doc = match existing.document.lock() {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                if doc.version > 0 || !doc.content.is_empty() {
+                    return Err(rejected(format!(
+                        "'{}' already has content at version {}",
+                        path, doc.version
+                    )));
+                }
+            }
+            // Keep persisting to the file the replaced document was backed by
+            let backing_file = existing.as_ref().and_then(|e| e.backing_file.clone());
+            let entry = documents.insert(build(backing_file)?);
+            (entry, existing)
+        };
+
+        let sync = entry.sync_proto();
+        info!(
+            "[ServerState] Client {} {} '{}' at v{} ({})",
+            client.label(),
+            action,
+            entry.path,
+            sync.version,
+            sync.doc_id
+        );
+        self.record_event(
+            &workspace.name,
+            WorkspaceEventKind::DocumentCreated,
+            &entry.path,
+            &client.member().unwrap_or_default(),
+            action,
+        );
+        {
+            let mut compliance = self.lock_compliance();
+            if compliance.records(&workspace.name) {
+                compliance.content(&workspace.name, client_id, &sync, action, unix_time_ms());
+            }
+        }
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::SyncDocument(
+            sync.clone(),
+        )));
+
+        if let Some(replaced) = replaced {
+            let old_id = replaced.sync_proto().doc_id;
+            let clients = match self.clients.lock() {
+                Ok(guard) => guard.clone(),
+                Err(poisoned) => poisoned.into_inner().clone(),
+            };
+            for other in clients.iter().filter(|c| c.client_id != client_id) {
+                if other.unsubscribe(&old_id) {
+                    other.subscribe(&sync.doc_id);
+                    let _ = other.send(Arc::clone(&frame));
+                }
+            }
+            client.unsubscribe(&old_id);
+        }
+        client.subscribe(&sync.doc_id);
+        Ok(frame)
+    }
+
+    pub fn close_document(&self, client_id: Uuid, doc_id: &str) -> bool {
+        let closed = self
+            .get_client(client_id)
+            .is_some_and(|client| client.unsubscribe(doc_id));
+        if closed && let Some(entry) = self.get_document(doc_id) {
+            let released = entry.range_locks().release_all(client_id);
+            if released > 0 {
+                self.broadcast_locks(&entry);
+            }
+            if entry.overlays().clear_all(client_id) > 0 {
+                self.broadcast_overlays(&entry);
+            }
+        }
+        closed
+    }
+
+    /// Claim `[start, end)` of an open document for the client, and tell the
+    /// document's subscribers about it.
+    pub fn lock_range(&self, client_id: Uuid, request: &LockRangeProto) -> Result<u64, Rejection> {
+        if self.is_read_only(client_id) {
+            return Err(Rejection::ReadOnly);
+        }
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let lock_id = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            // Positions refer to the current version, like a fresh op would
+            let len = doc.content.len();
+            if request.end as usize > len {
+                return Err(Rejection::OutOfBounds {
+                    position: request.end,
+                    len,
+                });
+            }
+            let mut locks = entry.range_locks();
+            locks.acquire(client_id, request.start, request.end)?.id
+        };
+        self.broadcast_locks(&entry);
+        Ok(lock_id)
+    }
+
+    /// Release one of the client's locks. Returns whether it held it.
+    pub fn unlock_range(&self, client_id: Uuid, request: &UnlockRangeProto) -> bool {
+        let Some(entry) = self.get_document(&request.doc_id) else {
+            return false;
+        };
+        let released = entry.range_locks().release(client_id, request.lock_id);
+        if released {
+            self.broadcast_locks(&entry);
+        }
+        released
+    }
+
+    /// Drop every lock a departing client held.
+    fn release_locks(&self, client_id: Uuid) {
+        for entry in self.documents() {
+            if entry.range_locks().release_all(client_id) > 0 {
+                self.broadcast_locks(&entry);
+            }
+        }
+    }
+
+    /// Send a document's current locks to everyone who has it open.
+    fn broadcast_locks(&self, entry: &DocumentEntry) {
+        let locks = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            entry
+                .range_locks()
+                .to_proto(&doc.uuid.to_string(), doc.version)
+        };
+        let doc_id = locks.doc_id.clone();
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::RangeLocks(locks)));
+        self.send_to_subscribers(&doc_id, frame);
+    }
+
+    /// Change an open document's settings, provided its content already
+    /// keeps to them and they keep its append-only regions, and send
+    /// everyone who has it open a sync carrying them.
+    pub fn set_document_settings(
+        &self,
+        client_id: Uuid,
+        request: &SetDocumentSettingsProto,
+    ) -> Result<(), ServerError> {
+        if self.is_read_only(client_id) {
+            return Err(Rejection::ReadOnly.into());
+        }
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let settings = request
+            .settings
+            .as_ref()
+            .map(DocumentSettings::from_proto)
+            .ok_or(ServerError::Malformed(
+                "SetDocumentSettings without settings",
+            ))?;
+        self.update_settings(&entry, |current| {
+            current.check_keeps_append_only(&settings)?;
+            Ok(settings)
+        })
+    }
+
+    /// Replaces a document's append-only regions, for the operator: unlike
+    /// clients, it may lift or narrow them.
+    pub fn set_append_only(
+        &self,
+        entry: &DocumentEntry,
+        regions: Vec<(u32, u32)>,
+    ) -> Result<(), ServerError> {
+        self.update_settings(entry, |current| {
+            Ok(DocumentSettings {
+                append_only: regions,
+                ..current.clone()
+            })
+        })
+    }
+
+    /// Replaces a document's settings with what `update` makes of them, if
+    /// its content keeps to them, and syncs its subscribers.
+    fn update_settings(
+        &self,
+        entry: &DocumentEntry,
+        update: impl FnOnce(&DocumentSettings) -> Result<DocumentSettings, Rejection>,
+    ) -> Result<(), ServerError> {
+        {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let mut current = entry.settings();
+            let settings = update(&current)?;
+            settings.check_content(&doc.content)?;
+            *current = settings;
+        }
+        let sync = entry.sync_proto();
+        let doc_id = sync.doc_id.clone();
+        let sync = ServerMessage::SyncDocument(sync);
+        self.send_to_subscribers(&doc_id, Frame::new_arc(ServerMessage::encode(&sync)));
+        Ok(())
+    }
+
+    /// Record the lines the client has on screen of a document it has open,
+    /// and relay them to the connections following it. Returns how many of
+    /// them it reached.
+    pub fn set_viewport(
+        &self,
+        client_id: Uuid,
+        request: &SetViewportProto,
+    ) -> Result<usize, ServerError> {
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        let viewport = ViewportProto {
+            client_id: client_id.to_string(),
+            doc_id: request.doc_id.clone(),
+            path: entry.path.clone(),
+            first_line: request.first_line,
+            line_count: request.line_count,
+        };
+        client.set_viewport(viewport.clone());
+
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Viewport(viewport)));
+        Ok(clients
+            .iter()
+            .filter(|c| c.following() == Some(client_id))
+            .filter(|c| may_see_viewport(c, &request.doc_id))
+            .filter(|c| c.send_ephemeral(Arc::clone(&frame)))
+            .count())
+    }
+
+    /// Mirror the viewport of another connection of the client's workspace
+    /// from now on, or stop following for an empty id. Returns the
+    /// connection's current viewport, if it has shared one the client may
+    /// see.
+    pub fn follow(
+        &self,
+        client_id: Uuid,
+        request: &FollowProto,
+    ) -> Result<Option<ViewportProto>, ServerError> {
+        let client = self
+            .get_client(client_id)
+            .ok_or(ServerError::NotConnected)?;
+        if request.client_id.is_empty() {
+            client.follow(None);
+            return Ok(None);
+        }
+        let leader = Uuid::parse_str(&request.client_id)
+            .ok()
+            .and_then(|leader| self.get_client(leader))
+            .filter(|leader| leader.workspace() == client.workspace())
+            .ok_or_else(|| {
+                ServerError::FollowRejected(format!(
+                    "no connection {} in the workspace",
+                    request.client_id
+                ))
+            })?;
+        if leader.client_id == client_id {
+            return Err(ServerError::FollowRejected(
+                "a connection cannot follow itself".to_string(),
+            ));
+        }
+        client.follow(Some(leader.client_id));
+        info!(
+            "[ServerState] Client {} follows {}",
+            client.label(),
+            leader.label()
+        );
+        Ok(leader
+            .viewport()
+            .filter(|viewport| may_see_viewport(&client, &viewport.doc_id)))
+    }
+
+    /// Replace the client's overlays of one kind on an open document, moving
+    /// them from the version they were computed at to the current one, and
+    /// tell the document's subscribers. Returns how many were set.
+    pub fn set_overlays(
+        &self,
+        client_id: Uuid,
+        request: &SetOverlaysProto,
+    ) -> Result<usize, ServerError> {
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let count = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let ranges: Vec<(u32, u32)> = request
+                .overlays
+                .iter()
+                .map(|overlay| (overlay.start, overlay.end))
+                .collect();
+            let overlays: Vec<(u32, u32, String)> =
+                move_to_current(&entry, &doc, request.version, &ranges)?
+                    .into_iter()
+                    .zip(&request.overlays)
+                    .map(|((start, end), overlay)| (start, end, overlay.payload.clone()))
+                    .collect();
+            let count = overlays.len();
+            entry.overlays().set(client_id, &request.kind, overlays);
+            count
+        };
+        self.broadcast_overlays(&entry);
+        Ok(count)
+    }
+
+    /// Broadcast a client's signal to everyone with the document open, the
+    /// client included, with its range moved from the version it was sent
+    /// at to the current one.
+    pub fn signal(&self, client_id: Uuid, request: &SignalProto) -> Result<(), ServerError> {
+        match request.kind() {
+            SignalKind::Unspecified => {
+                return Err(ServerError::SignalRejected(
+                    "the signal has no kind".to_string(),
+                ));
+            }
+            SignalKind::Reaction => {
+                if request.emoji.is_empty() || request.emoji.len() > MAX_SIGNAL_EMOJI_BYTES {
+                    return Err(ServerError::SignalRejected(format!(
+                        "a reaction takes one emoji of up to {} bytes",
+                        MAX_SIGNAL_EMOJI_BYTES
+                    )));
+                }
+            }
+            SignalKind::RaiseHand | SignalKind::PointAtRange => {}
+        }
+        let entry = self.subscribed_document(client_id, &request.doc_id)?;
+        let signal = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let (start, end) = move_to_current(
+                &entry,
+                &doc,
+                request.version,
+                &[(request.start, request.end)],
+            )?[0];
+            SignalProto {
+                client_id: client_id.to_string(),
+                doc_id: request.doc_id.clone(),
+                version: doc.version,
+                kind: request.kind,
+                start,
+                end,
+                emoji: if request.kind() == SignalKind::Reaction {
+                    request.emoji.clone()
+                } else {
+                    String::new()
+                },
+            }
+        };
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Signal(signal)));
+        self.send_to_subscribers(&request.doc_id, frame);
+        Ok(())
+    }
+
+    /// Drop every overlay a departing client set.
+    fn clear_overlays(&self, client_id: Uuid) {
+        for entry in self.documents() {
+            if entry.overlays().clear_all(client_id) > 0 {
+                self.broadcast_overlays(&entry);
+            }
+        }
+    }
+
+    /// Send a document's current overlays to everyone who has it open.
+    fn broadcast_overlays(&self, entry: &DocumentEntry) {
+        let overlays = overlays_proto(entry);
+        let doc_id = overlays.doc_id.clone();
+        let frame = Frame::new_arc(ServerMessage::encode(&ServerMessage::Overlays(overlays)));
+        self.send_to_subscribers(&doc_id, frame);
+    }
+
+    /// Send the overlays on the document at `path` to a client that just
+    /// opened it, if there are any.
+    pub fn send_overlays(&self, client_id: Uuid, path: &str) {
+        let workspace = self.client_workspace(client_id);
+        let Some(entry) = workspace.lock_documents().at_path(resolve_path(path)) else {
+            return;
+        };
+        if entry.overlays().is_empty() {
+            return;
+        }
+        let overlays = ServerMessage::Overlays(overlays_proto(&entry));
+        self.send_to_client(client_id, Frame::new_arc(ServerMessage::encode(&overlays)));
+    }
+
+    /// Send the freeze windows under way on the document at `path` to a
+    /// client that just opened it, if there are any.
+    pub fn send_freezes(&self, client_id: Uuid, path: &str) {
+        let Some(client) = self.get_client(client_id) else {
+            return;
+        };
+        let workspace = client.workspace();
+        let frames: Vec<Arc<Frame>> = self
+            .lock_freezes()
+            .active(&workspace, resolve_path(path), unix_time_ms())
+            .map(|freeze| {
+                let message = ServerMessage::Freeze(freeze.to_proto(true));
+                Frame::new_arc(ServerMessage::encode(&message))
+            })
+            .collect();
+        for frame in frames {
+            self.send_to_client(client_id, frame);
+        }
+    }
+
+    /// Queue a frame for every client subscribed to `doc_id`.
+    fn send_to_subscribers(&self, doc_id: &str, frame: Arc<Frame>) {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        for client in clients.iter().filter(|c| c.is_subscribed(doc_id)) {
+            let _ = client.send(Arc::clone(&frame));
+        }
+    }
+
+    fn get_client(&self, client_id: Uuid) -> Option<Arc<ClientEntry>> {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        clients.iter().find(|c| c.client_id == client_id).cloned()
+    }
+
+    /// Queue a frame for a single client. Returns false if the client is gone or its queue is full.
+    pub fn send_to_client(&self, client_id: Uuid, frame: Arc<Frame>) -> bool {
+        self.get_client(client_id)
+            .is_some_and(|client| client.send(frame).is_ok())
+    }
+
+    /// Queue a frame for a single client outside its outbound numbering; see
+    /// `ClientEntry::send_unsequenced`.
+    pub fn send_unsequenced_to_client(&self, client_id: Uuid, frame: Arc<Frame>) -> bool {
+        self.get_client(client_id)
+            .is_some_and(|client| client.send_unsequenced(frame).is_ok())
+    }
+
+    /// Tell a client why it is about to be disconnected. The frame is queued
+    /// ahead of the writer shutting down, so it is the last one the client sees.
+    pub fn notify_disconnect(&self, client_id: Uuid, reason: DisconnectReason, message: &str) {
+        self.send_to_client(client_id, disconnect_frame(reason, message));
+    }
+
+    /// Disconnect a client on the operator's behalf. The Disconnect frame tells
+    /// it not to reconnect. Returns the removed client, if it was connected.
+    pub fn kick_client(&self, client_id: Uuid) -> Option<Arc<ClientEntry>> {
+        self.notify_disconnect(
+            client_id,
+            DisconnectReason::Kicked,
+            "Removed by the server operator",
+        );
+        let removed = self.drop_client(client_id, Eviction::Kicked.as_str());
+        if removed.is_some() {
+            EVICTIONS.record(Eviction::Kicked);
+        }
+        removed
+    }
+
+    /// Queue a Disconnect for every connected client. Returns how many were notified.
+    pub fn disconnect_all(&self, reason: DisconnectReason, message: &str) -> usize {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let frame = disconnect_frame(reason, message);
+        clients
+            .iter()
+            .filter(|client| client.send(Arc::clone(&frame)).is_ok())
+            .count()
+    }
+
+    /// Add a new client to the server state.
+    /// Returns Err if the maximum client limit is reached.
+    pub fn add_client(&self, client: ClientEntry) -> Result<(), ServerError> {
+        let mut clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("WARNING: Mutex was poisoned. Data might be in an inconsistent state.");
+                poisoned.into_inner()
+            }
+        };
+
+        // Check connection limit
+        if clients.len() >= MAX_CLIENTS {
+            return Err(ServerError::ServerFull(MAX_CLIENTS));
+        }
+
+        clients.push(Arc::new(client));
+        info!(
+            "[ServerState] Client added. Total clients: {}",
+            clients.len()
+        );
+
+        Ok(())
+    }
+
+    /// Get the current number of connected clients.
+    pub fn client_count(&self) -> usize {
+        match self.clients.lock() {
+            Ok(guard) => guard.len(),
+            Err(poisoned) => poisoned.into_inner().len(),
+        }
+    }
+
+    /// Frames waiting in each client's writer queue now.
+    pub fn writer_queue_depths(&self) -> Vec<usize> {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        clients.iter().map(|client| client.queue_depth()).collect()
+    }
+
+    /// Propagation latencies over every connection.
+    pub fn propagation(&self) -> Propagation {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut total = Propagation::default();
+        for client in clients.iter() {
+            total.merge(&client.propagation());
+        }
+        total
+    }
+
+    /// Removes a connection that closed, announcing it to the others.
+    pub fn remove_client(&self, client_id: Uuid) -> Option<Arc<ClientEntry>> {
+        self.drop_client(client_id, "")
+    }
+
+    /// Removes a connection, announcing it gone for `reason` (empty if the
+    /// peer closed it).
+    fn drop_client(&self, client_id: Uuid, reason: &str) -> Option<Arc<ClientEntry>> {
+        let mut clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("WARNING: Mutex was poisoned. Data might be in an inconsistent state.");
+                poisoned.into_inner()
+            }
+        };
+
+        let removed = clients
+            .iter()
+            .position(|c| c.client_id == client_id)
+            .map(|pos| clients.remove(pos));
+        if let Some(removed) = &removed {
+            info!(
+                "[ServerState] Client {} removed. Remaining clients: {}",
+                removed.label(),
+                clients.len()
+            );
+        }
+        drop(clients);
+        if let Some(removed) = &removed {
+            self.announce_departure(removed, reason);
+        }
+        self.release_locks(client_id);
+        self.clear_overlays(client_id);
+        removed
+    }
+
+    /// Removes a connection a broadcast could not reach, announcing it gone
+    /// for `eviction`.
+    pub fn evict(&self, client_id: Uuid, eviction: Eviction) {
+        if let Some(client) = self.drop_client(client_id, eviction.as_str()) {
+            let evicted = EVICTIONS.record(eviction);
+            info!(
+                "[ServerState] Removed client {} ({}; {} evicted so far)",
+                client.label(),
+                eviction.as_str(),
+                evicted
+            );
+        }
+    }
+
+    /// Remove all clients that have timed out.
+    /// Returns the number of clients removed.
+    pub fn remove_timed_out_clients(&self) -> usize {
+        let mut clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                error!("WARNING: Mutex was poisoned. Recovering.");
+                poisoned.into_inner()
+            }
+        };
+
+        let mut expired = Vec::new();
+        clients.retain(|client| {
+            let timed_out = client.is_timed_out(CLIENT_TIMEOUT_MS);
+            if timed_out {
+                expired.push(Arc::clone(client));
+                EVICTIONS.record(Eviction::TimedOut);
+                info!(
+                    "[ServerState] Client {} timed out ({}ms since last activity)",
+                    client.label(),
+                    client.ms_since_last_activity()
+                );
+                let _ = client.send(disconnect_frame(
+                    DisconnectReason::TimedOut,
+                    "No heartbeat response",
+                ));
+            }
+            !timed_out
+        });
+        drop(clients);
+
+        for client in &expired {
+            self.announce_departure(client, Eviction::TimedOut.as_str());
+            self.release_locks(client.client_id);
+            self.clear_overlays(client.client_id);
+        }
+        expired.len()
+    }
+
+    /// Send a ping to all connected clients.
+    /// Returns the number of clients pinged.
+    pub fn send_ping_to_all(&self, sequence: u64) -> usize {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+
+        let ping_msg = ServerMessage::Ping(sequence);
+        let ping_frame = Frame::new_arc(ServerMessage::encode(&ping_msg));
+
+        let mut pinged = 0;
+        for client in clients.iter() {
+            if client.send(Arc::clone(&ping_frame)).is_ok() {
+                pinged += 1;
+            }
+        }
+
+        pinged
+    }
+
+    /// Update last activity time for a client.
+    pub fn touch_client(&self, client_id: Uuid) {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(client) = clients.iter().find(|c| c.client_id == client_id) {
+            client.touch();
+        }
+    }
+
+    /// Record the display name a client announced in its Hello.
+    pub fn set_client_name(&self, client_id: Uuid, name: String) {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(client) = clients.iter().find(|c| c.client_id == client_id) {
+            client.set_display_name(name);
+        }
+    }
+
+    /// Keep the client's acknowledgement cursors under the id from its Hello.
+    pub fn set_client_hello_id(&self, client_id: Uuid, id: String) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_hello_id(id);
+        }
+    }
+
+    /// Make the connection a read-only viewer for the rest of its life.
+    pub fn set_client_read_only(&self, client_id: Uuid) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_read_only();
+        }
+    }
+
+    pub fn set_client_clock(&self, client_id: Uuid, sample: ClockSample) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_clock(sample);
+        }
+    }
+
+    /// Returns false for a connection that is gone or has yet to report its
+    /// clock.
+    pub fn record_propagation(&self, client_id: Uuid, samples: &[PropagationSampleProto]) -> bool {
+        self.get_client(client_id)
+            .is_some_and(|client| client.record_propagation(samples))
+    }
+
+    pub fn set_client_capabilities(&self, client_id: Uuid, capabilities: Capabilities) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_capabilities(capabilities);
+        }
+    }
+
+    pub fn set_client_sequenced(&self, client_id: Uuid) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_sequenced();
+        }
+    }
+
+    /// Add to a client's flow-control credit; see `ClientEntry::grant_credit`.
+    pub fn grant_credit(&self, client_id: Uuid, frames: u64, bytes: u64) {
+        if let Some(client) = self.get_client(client_id) {
+            let released = client.grant_credit(frames, bytes);
+            trace!(
+                "[ServerState] {} granted {} frame(s), {} byte(s); released {} held frame(s)",
+                client.label(),
+                frames,
+                bytes,
+                released
+            );
+        }
+    }
+
+    /// Queue a client's retained frames from `from_seq` on again. Returns
+    /// the oldest number still retained if `from_seq` is older.
+    pub fn resend(&self, client_id: Uuid, from_seq: u64) -> Result<(), u64> {
+        let Some(client) = self.get_client(client_id) else {
+            return Ok(());
+        };
+        let queued = client.resend(from_seq)?;
+        debug!(
+            "[ServerState] Resent {} frame(s) from #{} to {}",
+            queued,
+            from_seq,
+            client.label()
+        );
+        Ok(())
+    }
+
+    /// Hands a client's OperationBatch to its connection, which holds chunks
+    /// until the transaction is complete. See `ClientEntry::add_chunk`.
+    pub fn add_chunk(
+        &self,
+        client_id: Uuid,
+        batch: OperationBatchProto,
+    ) -> Result<Option<Vec<OperationProto>>, (u64, Rejection)> {
+        match self.get_client(client_id) {
+            Some(client) => client.add_chunk(batch),
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_read_only(&self, client_id: Uuid) -> bool {
+        self.get_client(client_id)
+            .is_some_and(|client| client.is_read_only())
+    }
+
+    /// Record input from the user behind a connection. Announces it as active
+    /// again if it had gone idle.
+    pub fn note_input(&self, client_id: Uuid) {
+        if let Some(client) = self.get_client(client_id) {
+            client.note_input();
+            self.refresh_presence(&client);
+        }
+    }
+
+    /// Mark a connection away, or back, as its client asked.
+    pub fn set_presence(&self, client_id: Uuid, away: bool) {
+        if let Some(client) = self.get_client(client_id) {
+            client.set_away(away);
+            self.refresh_presence(&client);
+        }
+    }
+
+    /// Announce every connection that went idle since the last check.
+    /// Returns the number of announcements.
+    pub fn update_presence(&self) -> usize {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        clients
+            .iter()
+            .filter(|client| self.refresh_presence(client))
+            .count()
+    }
+
+    /// Tell every other connection about the client's presence if it changed
+    /// since it was last announced. Returns whether it had.
+    fn refresh_presence(&self, client: &ClientEntry) -> bool {
+        let idle_after_ms = self
+            .idle_after
+            .map(|idle_after| idle_after.as_millis() as u64);
+        let status = client.presence(idle_after_ms);
+        if !client.announce_presence(status) {
+            return false;
+        }
+        info!(
+            "[ServerState] Client {} is {}",
+            client.label(),
+            status.as_str_name()
+        );
+
+        self.record_presence(client, status, "");
+        self.send_to_others(client, &presence_frame(client, status, ""));
+        true
+    }
+
+    /// Tell the remaining connections that `client` has gone, with `reason`
+    /// if the server dropped it. A client that gave an id in its Hello also
+    /// leaves a session to resume, and the activity feed only hears that it
+    /// left if it is not back before the session expires.
+    fn announce_departure(&self, client: &ClientEntry, reason: &str) {
+        if client.has_joined_workspace() {
+            let workspace = client.workspace();
+            match client.hello_id() {
+                Some(id) if !self.offline_grace.is_zero() => {
+                    let session = OfflineSession {
+                        activity_name: activity_name(client),
+                        away: client.is_away(),
+                        departed_ms: unix_time_ms(),
+                    };
+                    self.lock_offline().depart(&id, &workspace, session);
+                }
+                _ => self
+                    .workspace(&workspace)
+                    .activity()
+                    .left(activity_name(client)),
+            }
+        }
+        self.record_presence(client, PresenceStatus::Offline, reason);
+        let frame = presence_frame(client, PresenceStatus::Offline, reason);
+        self.send_to_others(client, &frame);
+    }
+
+    /// Queue a frame for every connection in workspace `name`.
+    fn send_to_workspace(&self, name: &str, frame: Arc<Frame>) {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        for client in clients.iter().filter(|c| c.workspace() == name) {
+            let _ = client.send(Arc::clone(&frame));
+        }
+    }
+
+    /// Queue a frame for every other connection in `client`'s workspace.
+    fn send_to_others(&self, client: &ClientEntry, frame: &Arc<Frame>) {
+        let clients = match self.clients.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let workspace = client.workspace();
+        for other in clients
+            .iter()
+            .filter(|c| c.client_id != client.client_id && c.workspace() == workspace)
+        {
+            let _ = other.send(Arc::clone(frame));
+        }
+    }
+
+    pub fn get_clients_arc(&self) -> Arc<Mutex<Vec<Arc<ClientEntry>>>> {
+        Arc::clone(&self.clients)
+    }
+
+    /// Transforms and applies an operation received from the `origin` connection.
+    pub fn send_applied_op(
+        &self,
+        origin: Uuid,
+        operation_proto: OperationProto,
+    ) -> Result<AppliedFrames, ServerError> {
+        let (entry, incoming) = self.incoming(origin, operation_proto)?;
+        let workspace = self.client_workspace(origin);
+
+        let (updated_content, operation_proto, stats) = {
+            let mut doc = entry
+                .document
+                .lock()
+                .map_err(|e| ServerError::Internal(format!("Failed to lock document: {}", e)))?;
+            let mut range_locks = entry.range_locks();
+            let op_kind = rebase(
+                &entry,
+                &incoming,
+                &doc.content,
+                doc.version,
+                &[],
+                &range_locks,
+                &self.middleware,
+            )?;
+
+            // Apply transformed op
+            let applied_at = Timestamp::now();
+            let mut stats = entry.stats();
+            stats.record(&op_kind, &doc.content, incoming.client_id, applied_at);
+            // server_version is the version this op was applied TO
+            let applied_to = doc.version;
+            doc.apply_op(&op_kind).map_err(ServerError::Internal)?;
+            let stats = stats.to_proto();
+            range_locks.transform(&op_kind);
+            drop(range_locks);
+            entry.overlays().transform(&op_kind);
+            entry.settings().transform(&op_kind);
+
+            // Log the operation while still holding the document lock, so the
+            // log and the document are always at the same version.
+            let global_version = workspace.next_version();
+            let operation_proto = record(
+                &entry,
+                incoming,
+                op_kind,
+                applied_to,
+                applied_at,
+                global_version,
+            );
+
+            // Only bump the refcount here; the text is copied for the sync
+            // frame below, after the lock is released.
+            (doc.snapshot(), operation_proto, stats)
+        };
+
+        self.after_apply(origin, &workspace.name, &entry.path, &operation_proto);
+
+        let operation_message = ServerMessage::Operation(operation_proto.clone());
+        let sync_doc = SyncDocumentProto {
+            doc_id: operation_proto.doc_id.clone(),
+            content: unwrap_snapshot(updated_content),
+            version: operation_proto.server_version + 1,
+            path: entry.path.clone(),
+            // The snapshot is the document as of this op
+            server_time_ms: operation_proto.applied_at_ms,
+            server_mono_ms: operation_proto.applied_mono_ms,
+            settings: Some(entry.settings().to_proto()),
+            stats: Some(stats),
+            resumed: false,
+        };
+
+        let server_message = ServerMessage::SyncDocument(sync_doc);
+        Ok(AppliedFrames {
+            operation_proto,
+            operation: Frame::new_arc(ServerMessage::encode(&operation_message)),
+            sync: Frame::new_arc(ServerMessage::encode(&server_message)),
+        })
+    }
+
+    /// Applies operations from the `origin` connection, on any documents it
+    /// has open, as one change: either every op is applied, in order, or none
+    /// is and the error names the op that failed. The workspace version goes
+    /// up once for the whole transaction.
+    pub fn apply_transaction(
+        &self,
+        origin: Uuid,
+        operations: Vec<OperationProto>,
+    ) -> Result<AppliedTransaction, (u64, ServerError)> {
+        let mut by_document: BTreeMap<String, (Arc<DocumentEntry>, Vec<Incoming>)> =
+            BTreeMap::new();
+        for operation in operations {
+            let op_id = operation.op_id;
+            let (entry, incoming) = self.incoming(origin, operation).map_err(|e| (op_id, e))?;
+            by_document
+                .entry(incoming.doc_id.clone())
+                .or_insert_with(|| (entry, Vec::new()))
+                .1
+                .push(incoming);
+        }
+
+        let workspace = self.client_workspace(origin);
+        if by_document.is_empty() {
+            return Ok(AppliedTransaction {
+                global_version: workspace.global_version(),
+                documents: Vec::new(),
+            });
+        }
+
+        // Documents are locked in id order, so transactions cannot deadlock
+        // on each other; single ops only ever hold one document.
+        let (entries, incoming): (Vec<_>, Vec<_>) = by_document.into_values().unzip();
+        let mut docs: Vec<MutexGuard<'_, Document>> = entries
+            .iter()
+            .map(|entry| match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            })
+            .collect();
+
+        // Check every op against its document as the transaction's earlier
+        // ops leave it, without touching the documents yet
+        let applied_at = Timestamp::now();
+        let mut staged = Vec::with_capacity(docs.len());
+        for ((entry, incoming), doc) in entries.iter().zip(&incoming).zip(&docs) {
+            let mut content = doc.content.as_str().to_owned();
+            let mut range_locks = entry.range_locks().clone();
+            let mut stats = entry.stats().clone();
+            let mut kinds = Vec::with_capacity(incoming.len());
+            for op in incoming {
+                let fail = |e| (op.op_id, e);
+                let kind = rebase(
+                    entry,
+                    op,
+                    &content,
+                    doc.version,
+                    &kinds,
+                    &range_locks,
+                    &self.middleware,
+                )
+                .map_err(fail)?;
+                stats.record(&kind, &content, op.client_id, applied_at);
+                apply_to_text(&mut content, &kind).map_err(|e| fail(ServerError::Internal(e)))?;
+                range_locks.transform(&kind);
+                kinds.push(kind);
+            }
+            staged.push((content, range_locks, stats, kinds));
+        }
+
+        // Everything applies: commit it all under one workspace version
+        let global_version = workspace.next_version();
+        let mut committed = Vec::with_capacity(docs.len());
+        for (((entry, incoming), doc), (content, range_locks, stats, kinds)) in entries
+            .iter()
+            .zip(incoming)
+            .zip(docs.iter_mut())
+            .zip(staged)
+        {
+            let base_version = doc.version;
+            doc.content = Arc::new(content);
+            // rebase made sure every staged op has a version to take
+            doc.version += kinds.len() as u64;
+            *entry.range_locks() = range_locks;
+            let stats_proto = stats.to_proto();
+            *entry.stats() = stats;
+            let mut overlays = entry.overlays();
+            let mut settings = entry.settings();
+            for kind in &kinds {
+                overlays.transform(kind);
+                settings.transform(kind);
+            }
+            drop((overlays, settings));
+            let operations = (base_version..)
+                .zip(incoming.into_iter().zip(kinds))
+                .map(|(version, (op, kind))| {
+                    record(entry, op, kind, version, applied_at, global_version)
+                })
+                .collect::<Vec<_>>();
+            committed.push((
+                Arc::clone(entry),
+                doc.snapshot(),
+                doc.version,
+                stats_proto,
+                operations,
+            ));
+        }
+        drop(docs);
+        for (entry, _, _, _, operations) in &committed {
+            for operation in operations {
+                self.after_apply(origin, &workspace.name, &entry.path, operation);
+            }
+        }
+
+        let documents = committed
+            .into_iter()
+            .map(|(entry, snapshot, version, stats, operations)| {
+                let doc_id = operations[0].doc_id.clone();
+                let sync = ServerMessage::SyncDocument(SyncDocumentProto {
+                    doc_id: doc_id.clone(),
+                    content: unwrap_snapshot(snapshot),
+                    version,
+                    path: entry.path.clone(),
+                    server_time_ms: applied_at.wall_ms,
+                    server_mono_ms: applied_at.mono_ms,
+                    settings: Some(entry.settings().to_proto()),
+                    stats: Some(stats),
+                    resumed: false,
+                });
+                let batch = ServerMessage::OperationBatch(OperationBatchProto {
+                    operations: operations.clone(),
+                    more: false,
+                });
+                AppliedDocument {
+                    doc_id,
+                    operations,
+                    batch: Frame::new_arc(ServerMessage::encode(&batch)),
+                    sync: Frame::new_arc(ServerMessage::encode(&sync)),
+                }
+            })
+            .collect();
+        Ok(AppliedTransaction {
+            global_version,
+            documents,
+        })
+    }
+
+    /// Hands an op that was just applied to the middlewares, the compliance
+    /// record, then to the subscribers. The op acknowledges the version it was written against.
+    fn after_apply(&self, origin: Uuid, workspace: &str, path: &str, operation: &OperationProto) {
+        self.record_ack(
+            origin,
+            workspace,
+            path,
+            &operation.doc_id,
+            operation.client_version,
+        );
+        let context = OpContext {
+            origin,
+            workspace,
+            doc_id: &operation.doc_id,
+            path,
+        };
+        self.middleware.after_apply(&context, operation);
+        {
+            let mut compliance = self.lock_compliance();
+            if compliance.records(workspace) {
+                compliance.op(workspace, origin, path, operation);
+            }
+        }
+        self.applied.publish(AppliedOp {
+            origin,
+            workspace: workspace.to_string(),
+            path: path.to_string(),
+            operation: operation.clone(),
+        });
+    }
+
+    /// Checks that `origin` may edit the document an op targets, and decodes it.
+    fn incoming(
+        &self,
+        origin: Uuid,
+        operation_proto: OperationProto,
+    ) -> Result<(Arc<DocumentEntry>, Incoming), ServerError> {
+        if self.is_read_only(origin) {
+            return Err(Rejection::ReadOnly.into());
+        }
+        // The op must target a document this connection has opened
+        let entry = self.subscribed_document(origin, &operation_proto.doc_id)?;
+        if self.handing_over.load(Ordering::SeqCst) {
+            let server = self.handover.clone().unwrap_or_default();
+            return Err(Rejection::HandingOver { server }.into());
+        }
+        // Syncs and presence carry on through a freeze; edits wait it out
+        let workspace = self.client_workspace(origin);
+        self.lock_freezes()
+            .check(&workspace.name, &entry.path, unix_time_ms())?;
+        self.lock_compliance().check(&workspace.name)?;
+
+        let client_id = Uuid::parse_str(&operation_proto.client_id)
+            .map_err(|_| ServerError::Malformed("invalid client UUID"))?;
+
+        let mut incoming = Incoming {
+            origin,
+            workspace: workspace.name.clone(),
+            op_id: operation_proto.op_id,
+            doc_id: operation_proto.doc_id.clone(),
+            client_id,
+            client_version: operation_proto.client_version,
+            kind: Operation::convert_operation(operation_proto)
+                .ok_or(ServerError::Malformed("missing op kind"))?,
+        };
+        let keep_crlf = entry.settings().line_ending == LineEnding::Crlf;
+        self.normalization.op(&mut incoming.kind, keep_crlf);
+        self.authorize(&entry, &incoming)?;
+        Ok((entry, incoming))
+    }
+
+    /// Asks each authorizer whether the sender of `op` may make it, as it
+    /// was written; the first to say no refuses it.
+    fn authorize(&self, entry: &DocumentEntry, op: &Incoming) -> Result<(), Rejection> {
+        if self.authorizers.is_empty() {
+            return Ok(());
+        }
+        let member = self
+            .get_client(op.origin)
+            .and_then(|client| client.member());
+        // The op was written against its client version: the length then is
+        // the length now with the ops applied since taken back out. Versions
+        // the log no longer covers are refused when the op is transformed.
+        let len = {
+            let doc = match entry.document.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let past = entry
+                .op_log
+                .get_ops_in_range(op.client_version, doc.version)
+                .unwrap_or_default();
+            len_before(doc.content.len(), &past)
+        };
+        let request = OpRequest {
+            origin: op.origin,
+            member: member.as_deref(),
+            workspace: &op.workspace,
+            doc_id: &op.doc_id,
+            path: &entry.path,
+            op: &op.kind,
+            len,
+        };
+        for authorizer in &self.authorizers {
+            if let Err(reason) = authorizer.authorize(&request) {
+                let (start, end) = request.range();
+                info!(
+                    "[ServerState] {} refused {} {}..{} from {} on {} ({}/{}): {}",
+                    authorizer.name(),
+                    request.kind(),
+                    start,
+                    end,
+                    request.origin,
+                    request.doc_id,
+                    request.workspace,
+                    request.path,
+                    reason
+                );
+                return Err(Rejection::Unauthorized {
+                    authorizer: authorizer.name().to_string(),
+                    reason,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The length of a document before the applied `past` ops, from its length
+/// after them.
+fn len_before(len: usize, past: &[Operation]) -> usize {
+    past.iter().rev().fold(len, |len, op| match &op.kind {
+        OperationKind::Insert(insert) => len.saturating_sub(insert.text.len()),
+        OperationKind::Delete(delete) => len + (delete.end - delete.start) as usize,
+        OperationKind::Replace(replace) => {
+            (len + (replace.end - replace.start) as usize).saturating_sub(replace.text.len())
+        }
+        // Only character ops are applied
+        _ => len,
+    })
+}
+
+/// A client's operation, decoded but not yet transformed.
+struct Incoming {
+    /// The connection that sent it, and its workspace.
+    origin: Uuid,
+    workspace: String,
+    op_id: u64,
+    doc_id: String,
+    client_id: Uuid,
+    client_version: u64,
+    kind: OperationKind,
+}
+
+/// Transforms `op` from its client version up to the document's `version`
+/// followed by `pending`, ops a transaction has staged but not yet applied,
+/// then puts it through `middleware`, which validates it against `content`
+/// (the text at that point), and checks it against the locks. Line ops come
+/// out as the character op they amount to in `content`.
+fn rebase(
+    entry: &DocumentEntry,
+    op: &Incoming,
+    content: &str,
+    version: u64,
+    pending: &[OperationKind],
+    range_locks: &RangeLocks,
+    middleware: &MiddlewareChain,
+) -> Result<OperationKind, ServerError> {
+    let client_version = op.client_version;
+    // Versions never wrap: the op needs a version past the staged ones
+    let head = checked::advance(version, pending.len() as u64)
+        .map_err(|_| Rejection::VersionExhausted { version })?;
+    if checked::next_version(head).is_err() {
+        return Err(Rejection::VersionExhausted { version: head }.into());
+    }
+
+    // The op can only be transformed from a version the log still
+    // covers, up to the document's current one
+    let first = entry.op_log.first_version();
+    if client_version > head || client_version < first {
+        return Err(Rejection::UnknownVersion {
+            version: client_version,
+            first,
+            current: head,
+        }
+        .into());
+    }
+
+    let policy = entry.conflict_policy();
+    let mut op_kind = op.kind.clone();
+    if client_version < version {
+        // Get ops from log: [client_version, version)
+        let past_ops = entry
+            .op_log
+            .get_ops_in_range(client_version, version)
+            .map_err(ServerError::Internal)?;
+
+        // Transform incoming op against all past ops
+        for past_op in past_ops {
+            op_kind = transform_with(op_kind, past_op.kind, policy);
+        }
+    }
+    let unseen = client_version.saturating_sub(version) as usize;
+    for staged in &pending[unseen..] {
+        op_kind = transform_with(op_kind, staged.clone(), policy);
+    }
+
+    // Validate the transformed op against the text, the document's settings
+    // and any range locks before applying; line ops are checked as the
+    // character op they amount to here, which is what gets applied and logged
+    let context = OpContext {
+        origin: op.origin,
+        workspace: &op.workspace,
+        doc_id: &op.doc_id,
+        path: &entry.path,
+    };
+    middleware.before_apply(&context, &mut op_kind, content)?;
+    let op_kind = lines::to_char_op(&op_kind, content).map_err(ServerError::Internal)?;
+    {
+        let settings = entry.settings();
+        if pending.is_empty() {
+            settings.check_op(&op_kind, content)?;
+        } else {
+            // The staged ops have moved the append-only regions too
+            let mut staged = settings.clone();
+            pending.iter().for_each(|kind| staged.transform(kind));
+            staged.check_op(&op_kind, content)?;
+        }
+    }
+    range_locks.check(op.origin, &op_kind)?;
+    Ok(op_kind)
+}
+
+/// The document at `path`, or the default document if it is empty.
+fn resolve_path(path: &str) -> &str {
+    if path.is_empty() {
+        DEFAULT_DOC_PATH
+    } else {
+        path
+    }
+}
+
+/// `ranges` of `doc`, given as of `version`, moved through the edits made
+/// since. Refused if `version` is outside the history kept, or a range is
+/// reversed or ends past the document.
+fn move_to_current(
+    entry: &DocumentEntry,
+    doc: &Document,
+    version: u64,
+    ranges: &[(u32, u32)],
+) -> Result<Vec<(u32, u32)>, ServerError> {
+    let first = entry.op_log.first_version();
+    if version > doc.version || version < first {
+        return Err(Rejection::UnknownVersion {
+            version,
+            first,
+            current: doc.version,
+        }
+        .into());
+    }
+    let since = entry
+        .op_log
+        .get_ops_in_range(version, doc.version)
+        .map_err(ServerError::Internal)?;
+    let len = doc.content.len();
+    let mut moved = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges {
+        if start > end {
+            return Err(Rejection::InvalidRange { start, end }.into());
+        }
+        let (start, end) = since.iter().fold((start, end), |range, op| {
+            transform_range(range.0, range.1, &op.kind)
+        });
+        if end as usize > len {
+            return Err(Rejection::OutOfBounds { position: end, len }.into());
+        }
+        moved.push((start, end));
+    }
+    Ok(moved)
+}
+
+/// A document's milestones and tags, and the versions its op log holds.
+pub fn history_proto(entry: &DocumentEntry) -> HistoryProto {
+    let doc = match entry.document.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    HistoryProto {
+        doc_id: doc.uuid.to_string(),
+        path: entry.path.clone(),
+        version: doc.version,
+        first_version: entry.op_log.first_version(),
+        milestones: entry
+            .op_log
+            .milestones()
+            .iter()
+            .map(Milestone::to_proto)
+            .collect(),
+        tags: entry.op_log.tags().iter().map(Tag::to_proto).collect(),
+        ..Default::default()
+    }
+}
+
+/// How the activity feed names `client`: as its member, or by its id.
+fn activity_name(client: &ClientEntry) -> String {
+    client
+        .member()
+        .unwrap_or_else(|| client.client_id.to_string())
+}
+
+/// A document's overlays, positioned at its current version.
+fn overlays_proto(entry: &DocumentEntry) -> OverlaysProto {
+    let doc = match entry.document.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    entry
+        .overlays()
+        .to_proto(&doc.uuid.to_string(), doc.version)
+}
+
+/// Logs `kind`, applied to `server_version` of the document, and returns it
+/// as broadcast.
+fn record(
+    entry: &DocumentEntry,
+    op: Incoming,
+    kind: OperationKind,
+    server_version: u64,
+    applied_at: Timestamp,
+    global_version: u64,
+) -> OperationProto {
+    let final_op = Operation {
+        op_id: op.op_id,
+        kind,
+        doc_id: op.doc_id,
+        new_content: String::new(),
+        client_id: op.client_id,
+        client_version: op.client_version,
+        server_version,
+        applied_at,
+    };
+    let mut operation_proto = final_op.to_proto();
+    operation_proto.global_version = global_version;
+    if let Err(e) = entry.op_log.append_log(final_op) {
+        error!("Failed to append to op_log: {}", e);
+    }
+    operation_proto
+}
+
+/// Guests only see where others are looking in documents they have open.
+fn may_see_viewport(follower: &ClientEntry, doc_id: &str) -> bool {
+    follower.guest().is_none() || follower.is_subscribed(doc_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::space::{
+        DeleteLineOp, DeleteOp, DocumentSettingsProto, InsertOp, LineEnding, OverlayProto,
+        PresenceStatus, TemplateVariableProto, TextRangeProto, operation_proto::Kind,
+    };
+
+    use crate::authorization::OpRules;
+    use crate::client_entry::{RESEND_WINDOW, WRITER_QUEUE_CAPACITY};
+
+    fn connect(state: &ServerState) -> Uuid {
+        let client_id = Uuid::new_v4();
+        let (tx, _rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        client_id
+    }
+
+    fn insert(doc_id: &str, client_id: Uuid) -> OperationProto {
+        OperationProto {
+            op_id: 1,
+            kind: Some(Kind::Insert(InsertOp {
+                index: 0,
+                text: "hi".to_string(),
+                client_id: client_id.to_string(),
+                client_version: 0,
+            })),
+            doc_id: doc_id.to_string(),
+            client_id: client_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn edit(doc_id: &str, client_id: Uuid, client_version: u64, kind: Kind) -> OperationProto {
+        OperationProto {
+            kind: Some(kind),
+            client_version,
+            ..insert(doc_id, client_id)
+        }
+    }
+
+    fn insert_text(index: u32, text: &str) -> Kind {
+        Kind::Insert(InsertOp {
+            index,
+            text: text.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn delete_range(start: u32, end: u32) -> Kind {
+        Kind::Delete(DeleteOp {
+            start,
+            end,
+            ..Default::default()
+        })
+    }
+
+    fn open(state: &ServerState, client_id: Uuid, path: &str) -> String {
+        state.open_document(client_id, path).unwrap();
+        let entry = state
+            .workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .open(path);
+        entry.sync_proto().doc_id
+    }
+
+    #[test]
+    fn test_documents_at_the_last_version_refuse_ops() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        let entry = state.get_document(&notes).unwrap();
+        entry.document.lock().unwrap().version = u64::MAX - 1;
+
+        let last = OperationProto {
+            client_version: u64::MAX - 1,
+            ..insert(&notes, alice)
+        };
+        state.send_applied_op(alice, last).unwrap();
+        assert_eq!(entry.sync_proto().version, u64::MAX);
+
+        let past = OperationProto {
+            client_version: u64::MAX,
+            ..insert(&notes, alice)
+        };
+        assert!(matches!(
+            state.send_applied_op(alice, past),
+            Err(ServerError::Rejected(Rejection::VersionExhausted {
+                version: u64::MAX
+            }))
+        ));
+        assert_eq!(entry.sync_proto().content, "hi");
+    }
+
+    #[test]
+    fn test_ops_only_apply_to_opened_documents() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bob, "other.txt");
+
+        assert!(state.send_applied_op(alice, insert(&notes, alice)).is_ok());
+        assert!(matches!(
+            state.send_applied_op(bob, insert(&notes, bob)),
+            Err(ServerError::Rejected(Rejection::NotSubscribed { .. }))
+        ));
+        assert!(matches!(
+            state.send_applied_op(alice, insert("no-such-doc", alice)),
+            Err(ServerError::Rejected(Rejection::UnknownDocument { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_import_restores_exported_history() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        assert!(matches!(
+            state.export_document(bob, &notes),
+            Err(Rejection::NotSubscribed { .. })
+        ));
+        let mut archive = state.export_document(alice, &notes).unwrap();
+        assert_eq!((archive.content.as_str(), archive.version), ("hihi", 2));
+        assert_eq!(archive.operations.len(), 2);
+
+        // An edited document is never overwritten
+        assert!(state.import_document(alice, archive.clone()).is_err());
+
+        // Bob's empty copy is replaced, and he follows it to the new id
+        let old = open(&state, bob, "copy.txt");
+        archive.path = "copy.txt".to_string();
+        state.import_document(alice, archive).unwrap();
+        let copy = state
+            .workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .open("copy.txt");
+        let sync = copy.sync_proto();
+        assert_ne!(sync.doc_id, old);
+        assert_eq!((sync.content.as_str(), sync.version), ("hihi", 2));
+        let history = copy.op_log.history();
+        assert_eq!(history.len(), 2);
+        // Ops keep the time they were originally applied
+        assert!(history[0].applied_at.wall_ms > 0);
+        assert!(history[0].applied_at.mono_ms <= history[1].applied_at.mono_ms);
+        assert!(state.get_document(&old).is_none());
+        assert!(
+            state
+                .send_applied_op(bob, insert(&sync.doc_id, bob))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_create_from_template() {
+        let dir = std::env::temp_dir().join(format!("dist-space-templates-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("meeting"), "# {{ title }}\n").unwrap();
+        let request = |path: &str, title: &str| CreateFromTemplateProto {
+            template: "meeting".to_string(),
+            path: path.to_string(),
+            variables: vec![TemplateVariableProto {
+                name: "title".to_string(),
+                value: title.to_string(),
+            }],
+        };
+
+        let disabled = ServerState::new();
+        let alice = connect(&disabled);
+        assert!(
+            disabled
+                .create_from_template(alice, &request("a.md", "Standup"))
+                .is_err()
+        );
+
+        let state = ServerState::new().with_templates(dir.clone());
+        let alice = connect(&state);
+        state
+            .create_from_template(alice, &request("a.md", "Standup"))
+            .unwrap();
+        let sync = state
+            .workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .open("a.md")
+            .sync_proto();
+        assert_eq!((sync.content.as_str(), sync.version), ("# Standup\n", 0));
+        // The new document is open for its creator
+        assert!(
+            state
+                .send_applied_op(alice, insert(&sync.doc_id, alice))
+                .is_ok()
+        );
+        // ...and never replaces one with content
+        assert!(
+            state
+                .create_from_template(alice, &request("a.md", "Retro"))
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_workspaces_keep_documents_versions_and_presence_apart() {
+        let state = ServerState::new().with_workspace_quota(WorkspaceQuota {
+            max_clients: Some(1),
+            max_documents: Some(1),
+        });
+        let alice = connect(&state);
+        let bob = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(bob, tx)).unwrap();
+        state.join_workspace(alice, "team-a", "").unwrap();
+        state.join_workspace(bob, "team-b", "").unwrap();
+
+        state.open_document(alice, "notes.txt").unwrap();
+        state.open_document(bob, "notes.txt").unwrap();
+        let (team_a, team_b) = (state.workspace("team-a"), state.workspace("team-b"));
+        let notes = team_a
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        let other = team_b
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        assert_ne!(notes, other);
+
+        rx.try_iter().for_each(drop);
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        state.set_presence(alice, true);
+        assert_eq!((team_a.global_version(), team_b.global_version()), (1, 0));
+        assert_eq!(rx.try_iter().count(), 0);
+
+        // Each workspace is held to its own quota
+        let carol = connect(&state);
+        assert!(matches!(
+            state.join_workspace(carol, "team-a", ""),
+            Err(ServerError::Rejected(Rejection::WorkspaceFull { .. }))
+        ));
+        assert!(state.join_workspace(carol, "team-c", "").is_ok());
+        assert!(matches!(
+            state.open_document(alice, "todo.txt"),
+            Err(ServerError::Rejected(Rejection::WorkspaceFull { .. }))
+        ));
+        assert!(state.open_document(alice, "notes.txt").is_ok());
+    }
+
+    #[test]
+    fn test_missing_documents_are_refused_and_ids_follow_paths() {
+        let ids = DocIds::parse("path").unwrap();
+        let state = ServerState::new()
+            .with_doc_ids(ids.clone())
+            .with_missing_documents(MissingDocuments::Refuse)
+            .with_seed("notes.txt", "seeded".to_string());
+        let client_id = connect(&state);
+
+        assert!(matches!(
+            state.open_document(client_id, "todo.txt"),
+            Err(ServerError::Rejected(Rejection::NoSuchDocument { .. }))
+        ));
+        assert!(state.open_document(client_id, "notes.txt").is_ok());
+        // The default document exists in every workspace
+        state.join_workspace(client_id, "team-a", "").unwrap();
+        assert!(state.open_document(client_id, "").is_ok());
+
+        let default = state.workspace(DEFAULT_WORKSPACE);
+        let documents = default.lock_documents();
+        for path in [DEFAULT_DOC_PATH, "notes.txt"] {
+            let expected = ids.for_path(DEFAULT_WORKSPACE, path).unwrap().to_string();
+            assert_eq!(
+                documents.at_path(path).unwrap().sync_proto().doc_id,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_members_use_a_workspace_that_has_them() {
+        let state = ServerState::new();
+        let token = state.add_member("team-a", "ada").unwrap();
+        let (ada, stranger) = (connect(&state), connect(&state));
+        assert!(matches!(
+            state.join_workspace(stranger, "team-a", "guess"),
+            Err(ServerError::Rejected(Rejection::NotAMember { .. }))
+        ));
+        state.join_workspace(ada, "team-a", &token).unwrap();
+        assert!(state.open_document(ada, "notes.txt").is_ok());
+
+        // Joined while the workspace was open to all; left out once it is not
+        let early = connect(&state);
+        state.join_workspace(early, "team-b", "").unwrap();
+        state.add_member("team-b", "alan").unwrap();
+        assert!(matches!(
+            state.open_document(early, "notes.txt"),
+            Err(ServerError::Rejected(Rejection::NotAMember { .. }))
+        ));
+        assert!(state.invite_member(early, "mallory").is_err());
+
+        let invited = state.invite_member(ada, "grace").unwrap();
+        assert_eq!(invited.workspace, "team-a");
+        assert!(state.invite_member(ada, "grace").is_err());
+        let grace = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(grace, tx)).unwrap();
+        state
+            .join_workspace(grace, "team-a", &invited.token)
+            .unwrap();
+
+        assert_eq!(state.remove_member(ada, "grace").unwrap(), 1);
+        assert!(state.get_client(grace).is_none());
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Disconnect(disconnect)) => {
+                assert_eq!(disconnect.reason_code(), DisconnectReason::NotAMember)
+            }
+            _ => panic!("expected Disconnect"),
+        }
+        let again = connect(&state);
+        assert!(
+            state
+                .join_workspace(again, "team-a", &invited.token)
+                .is_err()
+        );
+        assert_eq!(
+            state.members(),
+            [
+                ("team-a".to_string(), vec!["ada".to_string()]),
+                ("team-b".to_string(), vec!["alan".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workspace_events_are_recorded_and_paged() {
+        let state = ServerState::new();
+        let token = state.add_member("team-a", "ada").unwrap();
+        let ada = connect(&state);
+        state.join_workspace(ada, "team-a", &token).unwrap();
+        state.open_document(ada, "notes.txt").unwrap();
+        state.open_document(ada, "notes.txt").unwrap();
+        state.invite_member(ada, "grace").unwrap();
+        state.remove_member(ada, "grace").unwrap();
+        let notes = state
+            .workspace("team-a")
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        state
+            .checkpoint(
+                ada,
+                &CheckpointProto {
+                    doc_id: notes,
+                    message: "First draft".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let history = state.history(ada, &GetHistoryProto::default()).unwrap();
+        let events: Vec<_> = history
+            .events
+            .iter()
+            .map(|e| (e.seq, e.kind(), e.path.as_str(), e.member.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (1, WorkspaceEventKind::MemberAdded, "", "ada"),
+                (2, WorkspaceEventKind::MemberJoined, "", "ada"),
+                (3, WorkspaceEventKind::DocumentCreated, "notes.txt", "ada"),
+                (4, WorkspaceEventKind::MemberAdded, "", "grace"),
+                (5, WorkspaceEventKind::MemberRemoved, "", "grace"),
+                (6, WorkspaceEventKind::CheckpointTaken, "notes.txt", "ada"),
+            ]
+        );
+        assert_eq!(history.events[5].detail, "First draft");
+        let later = GetHistoryProto {
+            events_after: 4,
+            ..Default::default()
+        };
+        assert_eq!(state.history(ada, &later).unwrap().events.len(), 2);
+
+        // Other workspaces' events are not shown
+        let bob = connect(&state);
+        state.join_workspace(bob, "team-b", "").unwrap();
+        let history = state.history(bob, &GetHistoryProto::default()).unwrap();
+        assert!(history.events.is_empty());
+    }
+
+    #[test]
+    fn test_activity_is_sent_in_digests_to_watchers() {
+        let state = ServerState::new();
+        let ada_token = state.add_member("team-a", "ada").unwrap();
+        let grace_token = state.add_member("team-a", "grace").unwrap();
+        let ada = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(ada, tx)).unwrap();
+        state.join_workspace(ada, "team-a", &ada_token).unwrap();
+        state.watch_activity(ada, true).unwrap();
+        let grace = connect(&state);
+        state.join_workspace(grace, "team-a", &grace_token).unwrap();
+        state.open_document(grace, "notes.txt").unwrap();
+        let notes = state
+            .workspace("team-a")
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        state.send_applied_op(grace, insert(&notes, grace)).unwrap();
+        let second = OperationProto {
+            client_version: 1,
+            ..insert(&notes, grace)
+        };
+        state.send_applied_op(grace, second).unwrap();
+        rx.try_iter().for_each(drop);
+
+        assert_eq!(state.send_activity(), 1);
+        let activity = match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Activity(activity)) => activity,
+            _ => panic!("expected Activity"),
+        };
+        assert_eq!(activity.joined, ["ada", "grace"]);
+        assert_eq!(activity.edits.len(), 1);
+        assert_eq!(activity.edits[0].path, "notes.txt");
+        assert_eq!(activity.edits[0].ops, 2);
+        assert_eq!(activity.edits[0].version, 2);
+        assert_eq!(activity.edits[0].editors, ["grace"]);
+        let kinds: Vec<_> = activity.events.iter().map(|e| e.kind()).collect();
+        assert!(kinds.contains(&WorkspaceEventKind::DocumentCreated));
+
+        // Nothing new, nothing sent; unwatching stops the feed
+        assert_eq!(state.send_activity(), 0);
+        state.watch_activity(ada, false).unwrap();
+        state.send_applied_op(grace, insert(&notes, grace)).unwrap();
+        rx.try_iter().for_each(drop);
+        assert_eq!(state.send_activity(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_seeds_replace_documents_but_keep_their_backing_file() {
+        let file = std::env::temp_dir().join(format!("dist-space-{}.txt", Uuid::new_v4()));
+        let state = ServerState::new()
+            .with_backing_file(file.clone())
+            .unwrap()
+            .with_seed(DEFAULT_DOC_PATH, "seeded".to_string())
+            .with_seed("notes.txt", "more".to_string());
+        let workspace = state.workspace(DEFAULT_WORKSPACE);
+        let documents = workspace.lock_documents();
+        let main = documents.at_path(DEFAULT_DOC_PATH).unwrap();
+        assert_eq!(main.sync_proto().content, "seeded");
+        assert_eq!(main.backing_file, Some(file));
+        let notes = documents.at_path("notes.txt").unwrap();
+        assert_eq!(notes.sync_proto().content, "more");
+        assert_eq!(notes.backing_file, None);
+    }
+
+    #[test]
+    fn test_guests_reach_one_document_until_their_invite_expires() {
+        let state = ServerState::new();
+        let token = state.add_member("team-a", "ada").unwrap();
+        let ada = connect(&state);
+        state.join_workspace(ada, "team-a", &token).unwrap();
+        let notes = {
+            state.open_document(ada, "notes.txt").unwrap();
+            let workspace = state.workspace("team-a");
+            let entry = workspace.lock_documents().open("notes.txt");
+            entry.sync_proto().doc_id
+        };
+
+        let request = |read_only, ttl_ms| CreateInviteProto {
+            path: "notes.txt".to_string(),
+            read_only,
+            ttl_ms,
+        };
+        let viewer = state.create_invite(ada, &request(true, 0)).unwrap();
+        let guest = connect(&state);
+        state
+            .join_workspace(guest, "team-a", &viewer.token)
+            .unwrap();
+        assert!(state.is_read_only(guest));
+        // The Hello's empty path opens the invited document
+        assert!(state.open_document(guest, "").is_ok());
+        assert!(matches!(
+            state.open_document(guest, "secret.txt"),
+            Err(ServerError::Rejected(Rejection::GuestRestricted { .. }))
+        ));
+        assert!(state.invite_member(guest, "mallory").is_err());
+        assert!(state.create_invite(guest, &request(false, 0)).is_err());
+
+        let editor = state.create_invite(ada, &request(false, 20)).unwrap();
+        let guest = connect(&state);
+        state
+            .join_workspace(guest, "team-a", &editor.token)
+            .unwrap();
+        state.open_document(guest, "notes.txt").unwrap();
+        assert!(state.send_applied_op(guest, insert(&notes, guest)).is_ok());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(
+            state.send_applied_op(guest, insert(&notes, guest)),
+            Err(ServerError::Rejected(Rejection::InviteExpired { .. }))
+        ));
+        assert!(state.open_document(guest, "notes.txt").is_err());
+        let late = connect(&state);
+        assert!(matches!(
+            state.join_workspace(late, "team-a", &editor.token),
+            Err(ServerError::Rejected(Rejection::InviteExpired { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_presence_transitions_are_announced_to_others() {
+        let state = ServerState::new().with_idle_after(Some(Duration::from_millis(20)));
+        let alice = connect(&state);
+        let bob = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(32);
+        state.add_client(ClientEntry::new(bob, tx)).unwrap();
+        let announced = || {
+            rx.try_iter()
+                .map(|frame| match ServerMessage::decode_bytes(&frame.payload) {
+                    Ok(ServerMessage::Presence(presence)) => {
+                        assert_eq!(presence.client_id, alice.to_string());
+                        presence.status()
+                    }
+                    _ => panic!("expected Presence"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        state.set_presence(alice, true);
+        // Repeats and periodic checks don't re-announce a status
+        state.set_presence(alice, true);
+        state.update_presence();
+        assert_eq!(announced(), [PresenceStatus::Away]);
+
+        state.set_presence(alice, false);
+        assert_eq!(announced(), [PresenceStatus::Active]);
+
+        std::thread::sleep(Duration::from_millis(50));
+        // Bob has been quiet just as long, but only others hear about him
+        assert_eq!(state.update_presence(), 2);
+        assert_eq!(announced(), [PresenceStatus::Idle]);
+        state.note_input(alice);
+        assert_eq!(announced(), [PresenceStatus::Active]);
+
+        state.kick_client(alice);
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Presence(presence)) => {
+                assert_eq!(presence.status(), PresenceStatus::Offline);
+                assert_eq!(presence.reason, "kicked");
+            }
+            _ => panic!("expected Presence"),
+        }
+    }
+
+    #[test]
+    fn test_sequenced_frames_can_be_resent() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(2);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        let ping = |seq| Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(seq)));
+        let received = || {
+            rx.try_iter()
+                .map(|frame| match ServerMessage::decode_bytes(&frame.payload) {
+                    Ok(ServerMessage::Sequenced(seq, _)) => seq,
+                    _ => panic!("expected Sequenced"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        state.set_client_sequenced(client_id);
+        for seq in 0..3 {
+            state.send_to_client(client_id, ping(seq));
+        }
+        // The queue only held two; the third is retained all the same
+        assert_eq!(received(), [1, 2]);
+        assert_eq!(state.resend(client_id, 2), Ok(()));
+        assert_eq!(received(), [2, 3]);
+
+        for seq in 3..3 + RESEND_WINDOW as u64 {
+            state.send_to_client(client_id, ping(seq));
+            received();
+        }
+        assert_eq!(state.resend(client_id, 2), Err(4));
+    }
+
+    #[test]
+    fn test_frames_wait_for_credit() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(2);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        let ping = |n| Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(n)));
+        let received = || {
+            rx.try_iter()
+                .map(|frame| match ServerMessage::decode_bytes(&frame.payload) {
+                    Ok(ServerMessage::Ping(n)) => n,
+                    _ => panic!("expected Ping"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        state.grant_credit(client_id, 1, 0);
+        for n in 0..4 {
+            // More than the writer's queue holds, but none count as full
+            assert!(state.send_to_client(client_id, ping(n)));
+        }
+        assert_eq!(received(), [0]);
+        state.grant_credit(client_id, 5, 0);
+        // Released as far as the queue has room, the rest with the next send
+        assert_eq!(received(), [1, 2]);
+        assert!(state.send_to_client(client_id, ping(4)));
+        assert_eq!(received(), [3, 4]);
+    }
+
+    #[test]
+    fn test_unlimited_credit_granted_twice_stays_unlimited() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(4);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        state.grant_credit(client_id, u64::MAX, u64::MAX);
+        state.grant_credit(client_id, u64::MAX, u64::MAX);
+        for n in 0..3 {
+            let ping = Frame::new_arc(ServerMessage::encode(&ServerMessage::Ping(n)));
+            assert!(state.send_to_client(client_id, ping));
+        }
+        assert_eq!(rx.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_read_only_clients_cannot_edit() {
+        let state = ServerState::new();
+        let viewer = connect(&state);
+        let notes = open(&state, viewer, "notes.txt");
+        state.set_client_read_only(viewer);
+
+        assert!(matches!(
+            state.send_applied_op(viewer, insert(&notes, viewer)),
+            Err(ServerError::Rejected(Rejection::ReadOnly))
+        ));
+        assert_eq!(state.get_document(&notes).unwrap().sync_proto().version, 0);
+        // Viewers can still read what others write
+        assert!(state.export_document(viewer, &notes).is_ok());
+    }
+
+    /// Refuses "hi" in one document and counts what it lets through.
+    struct NoGreetings {
+        path: &'static str,
+        applied: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Middleware for NoGreetings {
+        fn name(&self) -> &str {
+            "no greetings"
+        }
+
+        fn before_apply(
+            &self,
+            context: &OpContext,
+            op: &mut OperationKind,
+            _content: &str,
+        ) -> Result<(), Rejection> {
+            match op {
+                OperationKind::Insert(insert) if context.path == self.path => {
+                    if insert.text == "hi" {
+                        return Err(Rejection::Refused {
+                            middleware: self.name().to_string(),
+                            reason: "no greetings here".to_string(),
+                        });
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn after_apply(&self, _context: &OpContext, _applied: &OperationProto) {
+            self.applied
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_middleware_can_refuse_ops_and_observe_applied_ones() {
+        let applied = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let state = ServerState::new().with_middleware(NoGreetings {
+            path: "notes.txt",
+            applied: Arc::clone(&applied),
+        });
+        assert_eq!(state.middleware_names(), ["no greetings", "validation"]);
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        let plan = open(&state, alice, "plan.txt");
+
+        assert!(matches!(
+            state.send_applied_op(alice, insert(&notes, alice)),
+            Err(ServerError::Rejected(Rejection::Refused { .. }))
+        ));
+        // A refused op fails its whole transaction
+        assert!(
+            state
+                .apply_transaction(alice, vec![insert(&plan, alice), insert(&notes, alice)])
+                .is_err()
+        );
+        assert_eq!(applied.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        state.send_applied_op(alice, insert(&plan, alice)).unwrap();
+        let mut hello = insert(&notes, alice);
+        hello.kind = Some(Kind::Insert(InsertOp {
+            index: 0,
+            text: "hello".to_string(),
+            client_id: alice.to_string(),
+            client_version: 0,
+        }));
+        state.apply_transaction(alice, vec![hello]).unwrap();
+        assert_eq!(applied.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_authorizers_judge_ops_as_their_clients_wrote_them() {
+        let rules = OpRules::parse("intern=max-delete:3,bot=append-only").unwrap();
+        let state = ServerState::new().with_authorizer(rules);
+        let join = |member: &str| {
+            let token = state.add_member("team-a", member).unwrap();
+            let client = connect(&state);
+            state.join_workspace(client, "team-a", &token).unwrap();
+            state.open_document(client, "notes.txt").unwrap();
+            client
+        };
+        let intern = join("intern");
+        let bot = join("bot");
+        let notes = state
+            .workspace("team-a")
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        let edit = |client, client_version, kind| edit(&notes, client, client_version, kind);
+        let append = |index| insert_text(index, "hello");
+
+        state.send_applied_op(bot, edit(bot, 0, append(0))).unwrap();
+        assert!(matches!(
+            state.send_applied_op(intern, edit(intern, 1, delete_range(0, 5))),
+            Err(ServerError::Rejected(Rejection::Unauthorized { .. }))
+        ));
+        state
+            .send_applied_op(intern, edit(intern, 1, delete_range(0, 3)))
+            .unwrap();
+        // "lo" is left, and the bot may only add after it
+        assert!(matches!(
+            state.send_applied_op(bot, edit(bot, 1, append(0))),
+            Err(ServerError::Rejected(Rejection::Unauthorized { .. }))
+        ));
+        state.send_applied_op(bot, edit(bot, 2, append(2))).unwrap();
+
+        // Judged against the "lohello" the bot saw, not what landed ahead
+        let ada = join("ada");
+        state.send_applied_op(ada, edit(ada, 3, append(0))).unwrap();
+        assert!(matches!(
+            state.send_applied_op(bot, edit(bot, 3, append(6))),
+            Err(ServerError::Rejected(Rejection::Unauthorized { .. }))
+        ));
+        state.send_applied_op(bot, edit(bot, 3, append(7))).unwrap();
+        let entry = state.workspace("team-a").lock_documents().open("notes.txt");
+        assert_eq!(
+            entry.document.lock().unwrap().content.as_str(),
+            "hellolohellohello"
+        );
+    }
+
+    #[test]
+    fn test_applied_ops_reach_subscribers() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        let plan = open(&state, alice, "plan.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        // Only ops applied after subscribing are received
+        let search = state.subscribe_applied("search");
+        let second = OperationProto {
+            client_version: 1,
+            ..insert(&notes, alice)
+        };
+        state.send_applied_op(alice, second).unwrap();
+        state
+            .apply_transaction(alice, vec![insert(&plan, alice)])
+            .unwrap();
+
+        let applied: Vec<_> = search.try_iter().collect();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[0].origin, alice);
+        assert_eq!(applied[0].workspace, DEFAULT_WORKSPACE);
+        assert_eq!(applied[0].path, "notes.txt");
+        assert_eq!(applied[0].operation.server_version, 1);
+        assert_eq!(
+            (
+                applied[1].path.as_str(),
+                applied[1].operation.doc_id.as_str()
+            ),
+            ("plan.txt", plan.as_str())
+        );
+    }
+
+    #[test]
+    fn test_recorded_workspaces_log_ops_and_presence_for_compliance() {
+        let file = std::env::temp_dir().join(format!("dist-space-compliance-{}", Uuid::new_v4()));
+        let state = ServerState::new()
+            .with_compliance_log(file.clone(), RecordedWorkspaces::parse("team-a"))
+            .unwrap();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        state.join_workspace(alice, "team-a", "").unwrap();
+        state.join_workspace(bob, "team-b", "").unwrap();
+        let [notes, other] = [(alice, "team-a"), (bob, "team-b")].map(|(client, workspace)| {
+            state.open_document(client, "notes.txt").unwrap();
+            let entry = state
+                .workspace(workspace)
+                .lock_documents()
+                .open("notes.txt");
+            entry.sync_proto().doc_id
+        });
+
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        let mut archive = state.export_document(alice, &notes).unwrap();
+        archive.path = "copy.txt".to_string();
+        state.import_document(alice, archive).unwrap();
+        state.set_presence(alice, true);
+        // Nothing from the workspace that did not opt in
+        state.send_applied_op(bob, insert(&other, bob)).unwrap();
+        state.set_presence(bob, true);
+        state.kick_client(alice);
+        let carol = connect(&state);
+        state.join_workspace(carol, "team-a", "").unwrap();
+        state.evict(carol, Eviction::SlowConsumer);
+
+        assert_eq!(state.verify_compliance(), Some(Ok(5)));
+        let text = std::fs::read_to_string(&file).unwrap();
+        let kinds: Vec<_> = text
+            .lines()
+            .map(|line| line.split('\t').nth(3).unwrap())
+            .collect();
+        assert_eq!(kinds, ["op", "content", "presence", "presence", "presence"]);
+        assert!(text.contains(&format!("\tnotes.txt\t{}\t0\tinsert\t0\t0\thi\t", notes)));
+        assert!(text.contains("\tcopy.txt\t"));
+        assert!(text.contains("\t1\timported\thi\t"));
+        assert!(text.contains("\tPRESENCE_STATUS_OFFLINE\tkicked\t"));
+        assert!(text.contains("\tPRESENCE_STATUS_OFFLINE\tslow consumer\t"));
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(ServerState::new().verify_compliance(), None);
+    }
+
+    #[test]
+    fn test_clients_resume_from_their_cursor() {
+        let state = ServerState::new();
+        let laptop = connect(&state);
+        state.set_client_hello_id(laptop, "ada-laptop".to_string());
+        let notes = open(&state, laptop, "notes.txt");
+        for client_version in 0..3 {
+            let op = OperationProto {
+                client_version,
+                ..insert(&notes, laptop)
+            };
+            state.send_applied_op(laptop, op).unwrap();
+        }
+        // Each op acknowledged the version it was written against
+        let ack = |version| AckProto {
+            doc_id: notes.clone(),
+            version,
+        };
+        assert!(state.acknowledge(laptop, &ack(4)).is_err());
+        let phone = connect(&state);
+        state.set_client_hello_id(phone, "ada-phone".to_string());
+        open(&state, phone, "notes.txt");
+        state.acknowledge(phone, &ack(1)).unwrap();
+
+        // Compaction keeps what the phone still needs
+        let policy = RetentionPolicy {
+            max_versions: Some(0),
+            max_bytes: None,
+            max_age: None,
+        };
+        state.compact_op_logs(&policy);
+        let entry = state.get_document(&notes).unwrap();
+        assert!(entry.op_log.first_version() <= 1);
+
+        // The laptop comes back on a new connection and misses only the
+        // last op
+        let laptop = connect(&state);
+        state.set_client_hello_id(laptop, "ada-laptop".to_string());
+        let frames: Vec<ServerMessage> = state
+            .resume_document(laptop, "notes.txt")
+            .unwrap()
+            .iter()
+            .map(|frame| ServerMessage::decode_bytes(&frame.payload).unwrap())
+            .collect();
+        match &frames[..] {
+            [
+                ServerMessage::OperationBatch(batch),
+                ServerMessage::SyncDocument(sync),
+            ] => {
+                assert_eq!(batch.operations.len(), 1);
+                assert_eq!(batch.operations[0].server_version, 2);
+                assert!(sync.resumed);
+                assert_eq!((sync.content.as_str(), sync.version), ("", 3));
+            }
+            other => panic!("expected a batch and a resumed sync, got {:?}", other),
+        }
+
+        // A client with no cursor gets the whole document
+        let stranger = connect(&state);
+        let frames = state.resume_document(stranger, "notes.txt").unwrap();
+        match ServerMessage::decode_bytes(&frames[0].payload).unwrap() {
+            ServerMessage::SyncDocument(sync) => {
+                assert!(!sync.resumed);
+                assert_eq!(sync.content, "hihihi");
+            }
+            other => panic!("expected a sync, got {:?}", other),
+        }
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn test_cursors_at_the_saved_version_resume_after_a_restart() {
+        let dir = std::env::temp_dir().join(format!("dist-space-restart-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (doc_file, cursors_file) = (dir.join("main.txt"), dir.join("cursors.tsv"));
+        let run = || {
+            ServerState::new()
+                .with_backing_file(doc_file.clone())
+                .unwrap()
+                .with_cursors_file(cursors_file.clone())
+                .unwrap()
+        };
+        let state = run();
+        let (ada, grace) = (connect(&state), connect(&state));
+        state.set_client_hello_id(ada, "ada".to_string());
+        state.set_client_hello_id(grace, "grace".to_string());
+        let main = open(&state, ada, DEFAULT_DOC_PATH);
+        open(&state, grace, DEFAULT_DOC_PATH);
+        for client_version in 0..2 {
+            let op = OperationProto {
+                client_version,
+                ..insert(&main, ada)
+            };
+            state.send_applied_op(ada, op).unwrap();
+        }
+        let ack = |version| AckProto {
+            doc_id: main.clone(),
+            version,
+        };
+        state.acknowledge(ada, &ack(2)).unwrap();
+        state.acknowledge(grace, &ack(1)).unwrap();
+        autosave::save_all(&state);
+        state.save_cursors();
+        drop(state);
+
+        let resume = |state: &ServerState, name: &str| {
+            let client = connect(state);
+            state.set_client_hello_id(client, name.to_string());
+            let frames = state.resume_document(client, DEFAULT_DOC_PATH).unwrap();
+            match ServerMessage::decode_bytes(&frames[0].payload).unwrap() {
+                ServerMessage::SyncDocument(sync) => sync,
+                other => panic!("expected a sync, got {:?}", other),
+            }
+        };
+        // Ada had what was saved, and resumes without the content; Grace
+        // was behind it and gets the document whole
+        let state = run();
+        let sync = resume(&state, "ada");
+        assert!(sync.resumed);
+        assert_eq!((sync.content.as_str(), sync.version), ("", 0));
+        let sync = resume(&state, "grace");
+        assert!(!sync.resumed);
+        assert_eq!(sync.content, "hihi");
+        state.save_cursors();
+        drop(state);
+
+        // Content changed behind the server's back resumes no one
+        std::fs::write(&doc_file, "edited").unwrap();
+        let state = run();
+        assert!(!resume(&state, "ada").resumed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_offline_sessions_resume_until_they_expire() {
+        let state = ServerState::new().with_offline_grace(Duration::from_millis(50));
+        let token = state.add_member("team-a", "ada").unwrap();
+        let rejoin = || {
+            let laptop = connect(&state);
+            state.set_client_hello_id(laptop, "ada-laptop".to_string());
+            state.join_workspace(laptop, "team-a", &token).unwrap();
+            laptop
+        };
+        let resumed = |laptop| {
+            let frames = state.resume_document(laptop, "notes.txt").unwrap();
+            match ServerMessage::decode_bytes(&frames[0].payload) {
+                Ok(ServerMessage::SyncDocument(sync)) => sync.resumed,
+                _ => panic!("expected a sync"),
+            }
+        };
+        let laptop = rejoin();
+        state.open_document(laptop, "notes.txt").unwrap();
+        let notes = state
+            .workspace("team-a")
+            .lock_documents()
+            .open("notes.txt")
+            .sync_proto()
+            .doc_id;
+        let ack = AckProto {
+            doc_id: notes,
+            version: 0,
+        };
+        state.acknowledge(laptop, &ack).unwrap();
+        state.set_presence(laptop, true);
+        state.remove_client(laptop);
+
+        // Back in time: still away, never seen leaving, and resumable
+        let laptop = rejoin();
+        assert!(state.get_client(laptop).unwrap().is_away());
+        let activity = state.workspace("team-a").activity().take().unwrap();
+        assert_eq!(
+            (activity.joined, activity.left),
+            (vec!["ada".to_string()], vec![])
+        );
+        assert!(resumed(laptop));
+        state.remove_client(laptop);
+
+        // Gone too long: seen leaving, and back to a full sync
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(state.expire_offline_sessions(), 1);
+        let activity = state.workspace("team-a").activity().take().unwrap();
+        assert_eq!(activity.left, ["ada"]);
+        let laptop = rejoin();
+        assert!(!state.get_client(laptop).unwrap().is_away());
+        assert!(!resumed(laptop));
+    }
+
+    #[test]
+    fn test_frozen_documents_refuse_edits_until_unfrozen() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(WRITER_QUEUE_CAPACITY);
+        state.add_client(ClientEntry::new(bob, tx)).unwrap();
+        let notes = open(&state, alice, "notes.txt");
+        let plan = open(&state, alice, "plan.txt");
+        while rx.try_recv().is_ok() {}
+
+        state.freeze(Freeze {
+            workspace: DEFAULT_WORKSPACE.to_string(),
+            path: Some("notes.txt".to_string()),
+            starts_at_ms: 0,
+            ends_at_ms: None,
+        });
+        // Everyone in the workspace hears of it, open or not
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Freeze(freeze)) => {
+                assert_eq!((freeze.path.as_str(), freeze.frozen), ("notes.txt", true));
+            }
+            _ => panic!("expected Freeze"),
+        }
+        assert!(matches!(
+            state.send_applied_op(alice, insert(&notes, alice)),
+            Err(ServerError::Rejected(Rejection::Frozen { .. }))
+        ));
+        // Other documents and reads carry on
+        state.send_applied_op(alice, insert(&plan, alice)).unwrap();
+        assert!(state.export_document(alice, &notes).is_ok());
+
+        assert!(state.unfreeze(DEFAULT_WORKSPACE, Some("notes.txt")));
+        assert!(!state.unfreeze(DEFAULT_WORKSPACE, Some("notes.txt")));
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Freeze(freeze)) => assert!(!freeze.frozen),
+            _ => panic!("expected Freeze"),
+        }
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+    }
+
+    #[test]
+    fn test_handover_refuses_ops_then_redirects() {
+        let state = ServerState::new().with_handover(Some("10.0.0.2:8000".to_string()));
+        let alice = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(WRITER_QUEUE_CAPACITY);
+        state.add_client(ClientEntry::new(alice, tx)).unwrap();
+        let notes = open(&state, alice, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        assert_eq!(state.begin_handover(Duration::from_secs(5)), 0);
+        assert!(matches!(
+            state.send_applied_op(alice, insert(&notes, alice)),
+            Err(ServerError::Rejected(Rejection::HandingOver { .. }))
+        ));
+        while rx.try_recv().is_ok() {}
+
+        assert_eq!(state.redirect_all(), 1);
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Redirect(redirect)) => assert_eq!(redirect.address, "10.0.0.2:8000"),
+            _ => panic!("expected Redirect"),
+        }
+        assert!(matches!(
+            ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload),
+            Ok(ServerMessage::Disconnect(_))
+        ));
+    }
+
+    #[test]
+    fn test_the_new_server_takes_over_unsaved_documents() {
+        let file = std::env::temp_dir().join(format!("dist-space-handover-{}", Uuid::new_v4()));
+        let state = ServerState::new()
+            .with_handover(Some("10.0.0.2:8000".to_string()))
+            .with_handover_file(file.clone())
+            .unwrap();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        state.join_workspace(bob, "team-b", "").unwrap();
+        let notes = open(&state, alice, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        state.open_document(bob, "todo.txt").unwrap();
+        let todo = state.workspace("team-b").lock_documents().open("todo.txt");
+        let todo = todo.sync_proto().doc_id;
+        state.send_applied_op(bob, insert(&todo, bob)).unwrap();
+        state.send_applied_op(bob, insert(&todo, bob)).unwrap();
+
+        // With the default workspace's main.txt
+        assert_eq!(state.save_handover().unwrap(), 3);
+        let taken_over = ServerState::new().with_handover_file(file.clone()).unwrap();
+        let content = |workspace: &str, path: &str| {
+            let entry = taken_over
+                .workspace(workspace)
+                .lock_documents()
+                .at_path(path)
+                .unwrap();
+            let doc = entry.document.lock().unwrap();
+            (doc.content.to_string(), doc.version)
+        };
+        assert_eq!(
+            content(DEFAULT_WORKSPACE, "notes.txt"),
+            ("hi".to_string(), 1)
+        );
+        assert_eq!(content("team-b", "todo.txt"), ("hihi".to_string(), 2));
+        // Taken over once: a restart loads what the documents' own files hold
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn test_range_locks_reject_other_clients_until_released() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bob, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        let lock = |start, end| LockRangeProto {
+            doc_id: notes.clone(),
+            start,
+            end,
+        };
+        assert!(state.lock_range(alice, &lock(0, 2)).is_ok());
+        assert!(matches!(
+            state.lock_range(bob, &lock(1, 2)),
+            Err(Rejection::RangeLocked { start: 0, end: 2 })
+        ));
+
+        let delete = OperationProto {
+            op_id: 2,
+            kind: Some(Kind::Delete(DeleteOp {
+                start: 0,
+                end: 1,
+                client_id: bob.to_string(),
+                client_version: 1,
+            })),
+            doc_id: notes.clone(),
+            client_id: bob.to_string(),
+            client_version: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            state.send_applied_op(bob, delete.clone()),
+            Err(ServerError::Rejected(Rejection::RangeLocked { .. }))
+        ));
+
+        // Locks go away with the connection that held them
+        state.remove_client(alice);
+        assert!(state.send_applied_op(bob, delete).is_ok());
+    }
+
+    #[test]
+    fn test_transactions_apply_across_documents_or_not_at_all() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        let todo = open(&state, alice, "todo.txt");
+        open(&state, bob, "todo.txt");
+        let on = |doc_id: &str, op_id| OperationProto {
+            op_id,
+            ..insert(doc_id, alice)
+        };
+
+        // Bob's lock on todo.txt refuses the second op, so the first is not
+        // applied either
+        state.send_applied_op(bob, insert(&todo, bob)).unwrap();
+        let lock = LockRangeProto {
+            doc_id: todo.clone(),
+            start: 0,
+            end: 2,
+        };
+        state.lock_range(bob, &lock).unwrap();
+        let mut edit_todo = on(&todo, 2);
+        if let Some(Kind::Insert(insert)) = &mut edit_todo.kind {
+            insert.index = 1;
+        }
+        edit_todo.client_version = 1;
+        assert!(matches!(
+            state.apply_transaction(alice, vec![on(&notes, 1), edit_todo]),
+            Err((2, ServerError::Rejected(Rejection::RangeLocked { .. })))
+        ));
+        assert_eq!(state.get_document(&notes).unwrap().sync_proto().version, 0);
+
+        // Two ops on one document written against the same version are
+        // transformed against each other, like separate ops would be
+        let applied = state
+            .apply_transaction(alice, vec![on(&notes, 3), on(&notes, 4), on(&todo, 5)])
+            .unwrap();
+        assert_eq!(applied.global_version, 2);
+        assert_eq!(applied.documents.len(), 2);
+        let ops: Vec<_> = applied
+            .documents
+            .iter()
+            .flat_map(|d| &d.operations)
+            .collect();
+        assert!(ops.iter().all(|op| op.global_version == 2));
+        let notes_sync = state.get_document(&notes).unwrap().sync_proto();
+        assert_eq!(
+            (notes_sync.content.as_str(), notes_sync.version),
+            ("hihi", 2)
+        );
+        assert_eq!(
+            state.get_document(&todo).unwrap().sync_proto().content,
+            "hihi"
+        );
+
+        let single = state.send_applied_op(alice, on(&notes, 6)).unwrap();
+        assert_eq!(single.operation_proto.global_version, 3);
+    }
+
+    #[test]
+    fn test_line_ops_keep_their_intent_and_go_out_as_char_ops() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = connect(&state);
+        let notes = state
+            .workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .insert(DocumentEntry::with_content(
+                "notes.txt",
+                "one\ntwo\nthree\n".to_string(),
+                None,
+            ))
+            .sync_proto()
+            .doc_id;
+        state.open_document(alice, "notes.txt").unwrap();
+        state.open_document(bob, "notes.txt").unwrap();
+
+        let mut typed = insert(&notes, alice);
+        typed.kind = Some(Kind::Insert(InsertOp {
+            index: 6,
+            text: "X".to_string(),
+            client_id: alice.to_string(),
+            client_version: 0,
+        }));
+        state.send_applied_op(alice, typed).unwrap();
+
+        // Bob deletes "two\n" without having seen Alice's edit inside it
+        let removed = OperationProto {
+            op_id: 1,
+            kind: Some(Kind::DeleteLine(DeleteLineOp {
+                start: 4,
+                end: 8,
+                client_id: bob.to_string(),
+                client_version: 0,
+            })),
+            doc_id: notes.clone(),
+            client_id: bob.to_string(),
+            ..Default::default()
+        };
+        let applied = state.send_applied_op(bob, removed).unwrap();
+        // The smallest edit: "one\nt|wXo\nt|hree\n"
+        assert!(matches!(
+            applied.operation_proto.kind,
+            Some(Kind::Delete(DeleteOp {
+                start: 5,
+                end: 10,
+                ..
+            }))
+        ));
+        let entry = state
+            .workspace(DEFAULT_WORKSPACE)
+            .lock_documents()
+            .get(&notes)
+            .unwrap();
+        assert_eq!(entry.sync_proto().content, "one\nthree\n");
+    }
+
+    #[test]
+    fn test_overlays_move_with_edits_and_leave_with_their_owner() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bot = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bot, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        // Computed against version 1 ("hi"), set after Alice typed before it
+        let mut typed = insert(&notes, alice);
+        typed.kind = Some(Kind::Insert(InsertOp {
+            index: 0,
+            text: "oh".to_string(),
+            client_id: alice.to_string(),
+            client_version: 1,
+        }));
+        typed.client_version = 1;
+        state.send_applied_op(alice, typed).unwrap();
+        let spelling = SetOverlaysProto {
+            doc_id: notes.clone(),
+            version: 1,
+            kind: "spelling".to_string(),
+            overlays: vec![OverlayProto {
+                start: 0,
+                end: 2,
+                payload: "Unknown word".to_string(),
+                ..Default::default()
+            }],
+        };
+        assert_eq!(state.set_overlays(bot, &spelling).unwrap(), 1);
+        let entry = state.get_document(&notes).unwrap();
+        let overlays = overlays_proto(&entry);
+        assert_eq!(
+            (overlays.overlays[0].start, overlays.overlays[0].end),
+            (2, 4)
+        );
+
+        // Overlays are not edits
+        assert_eq!(entry.op_log.next_version(), 2);
+
+        state.remove_client(bot);
+        assert!(overlays_proto(&entry).overlays.is_empty());
+    }
+
+    #[test]
+    fn test_signals_are_anchored_at_the_current_version_and_broadcast() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let bob = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(WRITER_QUEUE_CAPACITY);
+        state.add_client(ClientEntry::new(bob, tx)).unwrap();
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bob, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        let mut typed = insert(&notes, alice);
+        typed.kind = Some(Kind::Insert(InsertOp {
+            index: 0,
+            text: "oh".to_string(),
+            client_id: alice.to_string(),
+            client_version: 1,
+        }));
+        typed.client_version = 1;
+        state.send_applied_op(alice, typed).unwrap();
+        while rx.try_recv().is_ok() {}
+
+        // Bob reacts to "hi" as he saw it, before Alice typed in front of it
+        let reaction = SignalProto {
+            doc_id: notes.clone(),
+            version: 1,
+            kind: SignalKind::Reaction as i32,
+            start: 0,
+            end: 2,
+            emoji: "👍".to_string(),
+            ..Default::default()
+        };
+        state.signal(bob, &reaction).unwrap();
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Signal(signal)) => {
+                assert_eq!(signal.client_id, bob.to_string());
+                assert_eq!((signal.version, signal.start, signal.end), (2, 2, 4));
+                assert_eq!(signal.emoji, "👍");
+            }
+            _ => panic!("expected Signal"),
+        }
+        // Signals are not edits
+        assert_eq!(state.get_document(&notes).unwrap().op_log.next_version(), 2);
+
+        let rejected = |signal: SignalProto| {
+            matches!(
+                state.signal(bob, &signal),
+                Err(ServerError::SignalRejected(_))
+            )
+        };
+        assert!(rejected(SignalProto {
+            emoji: String::new(),
+            ..reaction.clone()
+        }));
+        assert!(rejected(SignalProto {
+            kind: SignalKind::Unspecified as i32,
+            ..reaction.clone()
+        }));
+        assert!(matches!(
+            state.signal(
+                bob,
+                &SignalProto {
+                    kind: SignalKind::PointAtRange as i32,
+                    end: 9,
+                    ..reaction
+                }
+            ),
+            Err(ServerError::Rejected(Rejection::OutOfBounds { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_document_checkpoints_squash_history_into_a_milestone() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        let ack = AckProto {
+            doc_id: notes.clone(),
+            version: 1,
+        };
+        state.acknowledge(alice, &ack).unwrap();
+        let checkpoint = |message: &str| {
+            state.checkpoint(
+                alice,
+                &CheckpointProto {
+                    message: message.to_string(),
+                    doc_id: notes.clone(),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(matches!(
+            checkpoint(" "),
+            Err(ServerError::CheckpointFailed(_))
+        ));
+        // No git history is needed to squash a document's
+        let milestone = checkpoint("First draft").unwrap().milestone.unwrap();
+        assert_eq!((milestone.version, milestone.squashed), (1, 1));
+        let entry = state.get_document(&notes).unwrap();
+        assert!(entry.op_log.is_empty());
+        assert_eq!(entry.op_log.milestones()[0].label, "First draft");
+
+        // Edits written before the milestone can no longer be transformed
+        assert!(state.send_applied_op(alice, insert(&notes, alice)).is_err());
+    }
+
+    #[test]
+    fn test_checkpoints_keep_the_ops_edits_in_flight_need() {
+        let state = ServerState::new();
+        let (alice, bob) = (connect(&state), connect(&state));
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bob, "notes.txt");
+        // Bob's first edit leaves his cursor at version 0
+        state.send_applied_op(bob, insert(&notes, bob)).unwrap();
+        for client_version in 1..3 {
+            let op = OperationProto {
+                client_version,
+                ..insert(&notes, alice)
+            };
+            state.send_applied_op(alice, op).unwrap();
+        }
+        let entry = state.get_document(&notes).unwrap();
+        let milestone = state.squash_history(&entry, "Draft").unwrap();
+        assert_eq!((milestone.version, milestone.squashed), (3, 0));
+
+        // Bob wrote his next edit having seen only his own
+        let in_flight = OperationProto {
+            op_id: 2,
+            client_version: 1,
+            ..insert(&notes, bob)
+        };
+        let applied = state.send_applied_op(bob, in_flight).unwrap();
+        assert_eq!(applied.operation_proto.server_version, 3);
+        // Once everyone has caught up, the next checkpoint squashes it all
+        for client in [alice, bob] {
+            let ack = AckProto {
+                doc_id: notes.clone(),
+                version: 4,
+            };
+            state.acknowledge(client, &ack).unwrap();
+        }
+        let milestone = state.squash_history(&entry, "Final").unwrap();
+        assert_eq!((milestone.version, milestone.squashed), (4, 4));
+        assert!(entry.op_log.is_empty());
+    }
+
+    #[test]
+    fn test_tagged_versions_can_be_viewed_after_later_edits() {
+        let state = ServerState::new();
+        let alice = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(WRITER_QUEUE_CAPACITY);
+        state.add_client(ClientEntry::new(alice, tx)).unwrap();
+        let notes = open(&state, alice, "notes.txt");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        let tag = |name: &str| {
+            state.tag_version(
+                alice,
+                &TagVersionProto {
+                    doc_id: notes.clone(),
+                    name: name.to_string(),
+                },
+            )
+        };
+
+        assert_eq!(tag(" draft-1 ").unwrap().version, 1);
+        assert!(matches!(tag("draft-1"), Err(ServerError::TagRejected(_))));
+        assert!(matches!(tag(""), Err(ServerError::TagRejected(_))));
+        let broadcast = rx
+            .try_iter()
+            .filter_map(|frame| match ServerMessage::decode_bytes(&frame.payload) {
+                Ok(ServerMessage::History(history)) => Some(history),
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        assert_eq!(broadcast.tags[0].name, "draft-1");
+        assert!(broadcast.content.is_empty());
+
+        let mut typed = insert(&notes, alice);
+        typed.client_version = 1;
+        state.send_applied_op(alice, typed).unwrap();
+        let history = |tag: &str| {
+            state.history(
+                alice,
+                &GetHistoryProto {
+                    doc_id: notes.clone(),
+                    tag: tag.to_string(),
+                    ..Default::default()
+                },
+            )
+        };
+        let at_tag = history("draft-1").unwrap();
+        assert_eq!((at_tag.version, at_tag.tag.as_str()), (2, "draft-1"));
+        assert_eq!(at_tag.content, "hi");
+        assert!(matches!(history("final"), Err(ServerError::TagRejected(_))));
+    }
+
+    #[test]
+    fn test_document_settings_are_enforced_on_edits() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.md");
+        let entry = state.get_document(&notes).unwrap();
+        assert_eq!(entry.sync_proto().settings.unwrap().language_id, "markdown");
+        state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+
+        let settings = |line_ending: LineEnding, max_bytes| SetDocumentSettingsProto {
+            doc_id: notes.clone(),
+            settings: Some(DocumentSettingsProto {
+                line_ending: line_ending as i32,
+                max_bytes,
+                ..Default::default()
+            }),
+        };
+        state
+            .set_document_settings(alice, &settings(LineEnding::Lf, 3))
+            .unwrap();
+        assert_eq!(entry.settings().max_bytes, 3);
+
+        let mut crlf = insert(&notes, alice);
+        crlf.kind = Some(Kind::Insert(InsertOp {
+            index: 2,
+            text: "\r\n".to_string(),
+            client_id: alice.to_string(),
+            client_version: 1,
+        }));
+        crlf.client_version = 1;
+        assert!(matches!(
+            state.send_applied_op(alice, crlf),
+            Err(ServerError::Rejected(Rejection::LineEnding { .. }))
+        ));
+        assert!(matches!(
+            state.send_applied_op(alice, insert(&notes, alice)),
+            Err(ServerError::Rejected(Rejection::DocumentTooLarge {
+                len: 4,
+                max: 3
+            }))
+        ));
+        // "hi" is already over a 1 byte limit
+        assert!(
+            state
+                .set_document_settings(alice, &settings(LineEnding::Lf, 1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_append_only_regions_move_with_edits() {
+        let state = ServerState::new();
+        let alice = connect(&state);
+        let notes = open(&state, alice, "notes.md");
+        let entry = state.get_document(&notes).unwrap();
+        let edit = |client_version, kind| edit(&notes, alice, client_version, kind);
+        state
+            .send_applied_op(alice, edit(0, insert_text(0, "intro\nlog\n")))
+            .unwrap();
+        let request = SetDocumentSettingsProto {
+            doc_id: notes.clone(),
+            settings: Some(DocumentSettingsProto {
+                append_only: vec![TextRangeProto { start: 6, end: 10 }],
+                ..Default::default()
+            }),
+        };
+        state.set_document_settings(alice, &request).unwrap();
+
+        // Written before the intro grew, the delete still lands on the log
+        state
+            .send_applied_op(alice, edit(1, insert_text(0, "an ")))
+            .unwrap();
+        assert_eq!(entry.settings().append_only, [(9, 13)]);
+        assert!(matches!(
+            state.send_applied_op(alice, edit(1, delete_range(6, 8))),
+            Err(ServerError::Rejected(Rejection::AppendOnly {
+                start: 9,
+                end: 13
+            }))
+        ));
+
+        // Within a transaction, an append is protected by the time the next
+        // op is checked
+        assert!(
+            state
+                .apply_transaction(
+                    alice,
+                    vec![
+                        edit(2, insert_text(13, "more\n")),
+                        edit(3, delete_range(15, 16))
+                    ],
+                )
+                .is_err()
+        );
+        state
+            .apply_transaction(
+                alice,
+                vec![
+                    edit(2, insert_text(13, "more\n")),
+                    edit(3, delete_range(0, 3)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(entry.settings().append_only, [(6, 15)]);
+        assert_eq!(
+            entry.sync_proto().settings.unwrap().append_only,
+            [TextRangeProto { start: 6, end: 15 }]
+        );
+
+        // Clients cannot lift the region, or narrow it, to delete its text;
+        // the operator can
+        for append_only in [vec![TextRangeProto { start: 6, end: 8 }], Vec::new()] {
+            let request = SetDocumentSettingsProto {
+                doc_id: notes.clone(),
+                settings: Some(DocumentSettingsProto {
+                    append_only,
+                    ..Default::default()
+                }),
+            };
+            assert!(matches!(
+                state.set_document_settings(alice, &request),
+                Err(ServerError::Rejected(Rejection::AppendOnly {
+                    start: 6,
+                    end: 15
+                }))
+            ));
+        }
+        assert_eq!(entry.settings().append_only, [(6, 15)]);
+        state.set_append_only(&entry, Vec::new()).unwrap();
+        state
+            .send_applied_op(alice, edit(4, delete_range(6, 15)))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_stats_follow_single_ops_and_transactions() {
+        let state = ServerState::new();
+        let (alice, bob) = (connect(&state), connect(&state));
+        let notes = open(&state, alice, "notes.txt");
+        open(&state, bob, "notes.txt");
+        let entry = state.get_document(&notes).unwrap();
+
+        let applied = state.send_applied_op(alice, insert(&notes, alice)).unwrap();
+        let lines = |version: u64, index: u32| {
+            let mut op = insert(&notes, bob);
+            op.kind = Some(Kind::Insert(InsertOp {
+                index,
+                text: "\na\n".to_string(),
+                client_id: bob.to_string(),
+                client_version: version,
+            }));
+            op.client_version = version;
+            op
+        };
+        state
+            .apply_transaction(bob, vec![lines(1, 2), lines(2, 5)])
+            .unwrap();
+
+        let stats = entry.sync_proto().stats.unwrap();
+        assert_eq!((stats.length, stats.line_count), (8, 5));
+        assert!(stats.last_edit_ms >= applied.operation_proto.applied_at_ms);
+        let edits: Vec<_> = stats
+            .edits
+            .iter()
+            .map(|author| (author.client_id.clone(), author.edits))
+            .collect();
+        assert_eq!(edits, [(alice.to_string(), 1), (bob.to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_attachments_upload_in_chunks_and_fetch_once_referenced() {
+        let picture = crate::sha256::hex_digest(b"picture");
+        let content = format!("![plan]({}{})", attachments::REFERENCE_PREFIX, picture);
+        let limits = AttachmentLimits {
+            max_bytes: Some(8),
+            quota: None,
+        };
+        let state = ServerState::new()
+            .with_seed(DEFAULT_DOC_PATH, content)
+            .with_attachments(AttachmentStore::new(limits));
+        let client = connect(&state);
+        state.open_document(client, DEFAULT_DOC_PATH).unwrap();
+        let doc_id = state.documents()[0].sync_proto().doc_id;
+        let upload = |data: &[u8], more| {
+            state.upload_attachment(
+                client,
+                &UploadAttachmentProto {
+                    doc_id: doc_id.clone(),
+                    data: data.to_vec(),
+                    more,
+                },
+            )
+        };
+
+        assert_eq!(upload(b"pic", true).unwrap(), None);
+        let attachment = upload(b"ture", false).unwrap().unwrap();
+        assert_eq!(attachment.attachment_id, picture);
+        assert_eq!(attachment.size, 7);
+        assert!(matches!(
+            upload(b"too large", true),
+            Err(ServerError::AttachmentRejected(_))
+        ));
+        // The refused upload was dropped, so the next one starts afresh
+        let pdf = upload(b"pdf", false).unwrap().unwrap();
+        assert_eq!(pdf.size, 3);
+        assert_eq!(state.attachment_usage(), (2, 10));
+
+        let fetch = |attachment_id: &str| {
+            state.fetch_attachment(
+                client,
+                &FetchAttachmentProto {
+                    doc_id: doc_id.clone(),
+                    attachment_id: attachment_id.to_string(),
+                },
+            )
+        };
+        let chunks = fetch(&picture).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data, b"picture");
+        assert!(!chunks[0].more);
+        assert!(matches!(
+            fetch(&pdf.attachment_id),
+            Err(ServerError::AttachmentRejected(_))
+        ));
+        // Both are within their grace period
+        assert_eq!(state.collect_attachments(), (0, 0));
+    }
+
+    #[test]
+    fn test_viewports_reach_followers_and_are_dropped_behind_a_backlog() {
+        let state = ServerState::new();
+        let leader = connect(&state);
+        let follower = Uuid::new_v4();
+        let (tx, rx) = crossbeam::channel::bounded(WRITER_QUEUE_CAPACITY);
+        state.add_client(ClientEntry::new(follower, tx)).unwrap();
+        for client in [leader, follower] {
+            state.open_document(client, DEFAULT_DOC_PATH).unwrap();
+        }
+        let doc_id = state.documents()[0].sync_proto().doc_id;
+        let viewport = |first_line| {
+            state.set_viewport(
+                leader,
+                &SetViewportProto {
+                    doc_id: doc_id.clone(),
+                    first_line,
+                    line_count: 20,
+                },
+            )
+        };
+        let follow = |client_id: String| state.follow(follower, &FollowProto { client_id });
+
+        assert_eq!(viewport(0).unwrap(), 0);
+        // Following starts from where the leader is looking
+        assert_eq!(follow(leader.to_string()).unwrap().unwrap().first_line, 0);
+        assert!(matches!(
+            follow(follower.to_string()),
+            Err(ServerError::FollowRejected(_))
+        ));
+        assert!(matches!(
+            follow(Uuid::new_v4().to_string()),
+            Err(ServerError::FollowRejected(_))
+        ));
+
+        while rx.try_recv().is_ok() {}
+        assert_eq!(viewport(10).unwrap(), 1);
+        match ServerMessage::decode_bytes(&rx.try_recv().unwrap().payload) {
+            Ok(ServerMessage::Viewport(viewport)) => {
+                assert_eq!(viewport.client_id, leader.to_string());
+                assert_eq!(viewport.path, DEFAULT_DOC_PATH);
+                assert_eq!(viewport.first_line, 10);
+            }
+            _ => panic!("expected Viewport"),
+        }
+
+        let filler = Frame::new_arc(ServerMessage::encode(&ServerMessage::Follow(
+            FollowProto::default(),
+        )));
+        for _ in 0..WRITER_QUEUE_CAPACITY / 2 {
+            assert!(state.send_to_client(follower, Arc::clone(&filler)));
+        }
+        assert_eq!(viewport(20).unwrap(), 0);
+        assert_eq!(rx.len(), WRITER_QUEUE_CAPACITY / 2);
+
+        while rx.try_recv().is_ok() {}
+        assert_eq!(follow(String::new()).unwrap(), None);
+        assert_eq!(viewport(30).unwrap(), 0);
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use common::space::{
+        DeleteLineOp, DeleteOp, InsertLineOp, InsertOp, MoveLineOp, Noop, ReplaceOp,
+        operation_proto::Kind,
+    };
+    use proptest::prelude::*;
+
+    const DOC_ID: &str = "6f1c0b1e-3d2a-4c8e-9a53-2b7d4e0f9c11";
+    const CLIENT_ID: &str = "0b6e2f3a-1c4d-4e5f-8a9b-7c6d5e4f3a2b";
+
+    /// Mostly `valid`, sometimes empty or garbage.
+    fn arb_id(valid: &'static str) -> impl Strategy<Value = String> {
+        prop_oneof![
+            6 => Just(valid.to_string()),
+            1 => Just(String::new()),
+            1 => "\\PC{0,8}",
+        ]
+    }
+
+    /// Offsets at the edges that matter: the start, within or just past a
+    /// short document, and far past any.
+    fn arb_offset() -> impl Strategy<Value = u32> {
+        prop_oneof![Just(0), 0u32..24, Just(u32::MAX), any::<u32>()]
+    }
+
+    fn arb_version() -> impl Strategy<Value = u64> {
+        prop_oneof![Just(0), 0u64..8, Just(u64::MAX), any::<u64>()]
+    }
+
+    /// Empty, multi-byte and line-breaking text among the ordinary kind.
+    fn arb_text() -> impl Strategy<Value = String> {
+        prop_oneof![Just(String::new()), Just("é\r\n".to_string()), "\\PC{0,6}"]
+    }
+
+    fn arb_kind() -> impl Strategy<Value = Option<Kind>> {
+        let client = prop_oneof![Just(String::new()), "\\PC{0,4}"];
+        (
+            0..8,
+            (arb_offset(), arb_offset(), arb_offset()),
+            arb_text(),
+            client,
+            arb_version(),
+        )
+            .prop_map(|(which, (a, b, c), text, client_id, client_version)| {
+                Some(match which {
+                    0 => Kind::Insert(InsertOp {
+                        index: a,
+                        text,
+                        client_id,
+                        client_version,
+                    }),
+                    1 => Kind::Delete(DeleteOp {
+                        start: a,
+                        end: b,
+                        client_id,
+                        client_version,
+                    }),
+                    2 => Kind::Replace(ReplaceOp {
+                        start: a,
+                        end: b,
+                        text,
+                        client_id,
+                        client_version,
+                    }),
+                    3 => Kind::Noop(Noop {
+                        client_id,
+                        client_version,
+                    }),
+                    4 => Kind::InsertLine(InsertLineOp {
+                        index: a,
+                        text,
+                        client_id,
+                        client_version,
+                    }),
+                    5 => Kind::DeleteLine(DeleteLineOp {
+                        start: a,
+                        end: b,
+                        client_id,
+                        client_version,
+                    }),
+                    6 => Kind::MoveLine(MoveLineOp {
+                        start: a,
+                        end: b,
+                        to: c,
+                        client_id,
+                        client_version,
+                    }),
+                    _ => return None,
+                })
+            })
+    }
+
+    /// An op as a hostile or broken client might send it, mostly from
+    /// `CLIENT_ID` on `DOC_ID`.
+    fn arb_op() -> impl Strategy<Value = OperationProto> {
+        (
+            arb_kind(),
+            arb_id(DOC_ID),
+            arb_id(CLIENT_ID),
+            any::<u64>(),
+            arb_version(),
+            arb_version(),
+        )
+            .prop_map(
+                |(kind, doc_id, client_id, op_id, client_version, server_version)| OperationProto {
+                    op_id,
+                    kind,
+                    doc_id,
+                    client_id,
+                    client_version,
+                    server_version,
+                    ..Default::default()
+                },
+            )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(500))]
+
+        /// Whatever ops arrive, the server applies or refuses each one, and
+        /// the document's version counts exactly the ones applied.
+        #[test]
+        fn prop_arbitrary_ops_are_applied_or_refused(
+            seed in "[a-zé\n]{0,12}",
+            ops in prop::collection::vec(arb_op(), 1..12),
+        ) {
+            let state = ServerState::new()
+                .with_doc_ids(DocIds::parse(&format!("{}={}", DEFAULT_DOC_PATH, DOC_ID)).unwrap())
+                .with_seed(DEFAULT_DOC_PATH, seed);
+            let client_id = Uuid::parse_str(CLIENT_ID).unwrap();
+            let (tx, _rx) = crossbeam::channel::unbounded();
+            state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+            state.open_document(client_id, "").unwrap();
+
+            let mut applied = 0;
+            for op in ops {
+                if state.send_applied_op(client_id, op).is_ok() {
+                    applied += 1;
+                }
+            }
+            prop_assert_eq!(state.get_document(DOC_ID).unwrap().sync_proto().version, applied);
+        }
     }
 }
@@ -0,0 +1,139 @@
+use common::{
+    clock::Timestamp,
+    operation::OperationKind,
+    space::{AuthorEditsProto, DocumentStatsProto},
+};
+use uuid::Uuid;
+
+/// A document's length, line count, last edit and edits per author, kept up
+/// to date from each applied op rather than recounted from the content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    pub len: u64,
+    /// Line breaks in the content; there is one line more than that.
+    pub line_breaks: u64,
+    /// Zero until the first edit.
+    pub last_edit: Timestamp,
+    /// In the order authors first edited.
+    pub edits: Vec<(Uuid, u64)>,
+}
+
+impl DocumentStats {
+    /// Stats for a document starting out as `content`, counted once.
+    pub fn for_content(content: &str) -> Self {
+        Self {
+            len: content.len() as u64,
+            line_breaks: line_breaks(content),
+            ..Self::default()
+        }
+    }
+
+    pub fn line_count(&self) -> u64 {
+        self.line_breaks + 1
+    }
+
+    /// Accounts for a character op by `author`, about to be applied to
+    /// `content` at `at`. Only the text the op removes or inserts is read.
+    pub fn record(&mut self, op: &OperationKind, content: &str, author: Uuid, at: Timestamp) {
+        let (start, end, text) = match op {
+            OperationKind::Insert(insert) => (insert.index, insert.index, insert.text.as_str()),
+            OperationKind::Delete(delete) => (delete.start, delete.end, ""),
+            OperationKind::Replace(replace) => (replace.start, replace.end, replace.text.as_str()),
+            _ => return,
+        };
+        let removed = content
+            .get(start as usize..end as usize)
+            .unwrap_or_default();
+        self.len = self.len + text.len() as u64 - removed.len() as u64;
+        self.line_breaks = self.line_breaks + line_breaks(text) - line_breaks(removed);
+        self.last_edit = at;
+        match self.edits.iter_mut().find(|(id, _)| *id == author) {
+            Some((_, edits)) => *edits += 1,
+            None => self.edits.push((author, 1)),
+        }
+    }
+
+    pub fn to_proto(&self) -> DocumentStatsProto {
+        DocumentStatsProto {
+            length: self.len,
+            line_count: self.line_count(),
+            last_edit_ms: self.last_edit.wall_ms,
+            edits: self
+                .edits
+                .iter()
+                .map(|(client_id, edits)| AuthorEditsProto {
+                    client_id: client_id.to_string(),
+                    edits: *edits,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn line_breaks(text: &str) -> u64 {
+    text.bytes().filter(|byte| *byte == b'\n').count() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{
+        document::apply_to_text,
+        operation::{DeleteOp, InsertOp, ReplaceOp},
+    };
+
+    #[test]
+    fn test_stats_follow_edits() {
+        let (alice, bob) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let mut content = "one\ntwo".to_string();
+        let mut stats = DocumentStats::for_content(&content);
+        assert_eq!((stats.len, stats.line_count()), (7, 2));
+
+        let ops = [
+            (
+                alice,
+                OperationKind::Insert(InsertOp {
+                    index: 7,
+                    text: "\nthree\n".to_string(),
+                    client_id: String::new(),
+                    client_version: 0,
+                }),
+            ),
+            (
+                bob,
+                OperationKind::Delete(DeleteOp {
+                    start: 0,
+                    end: 4,
+                    client_id: String::new(),
+                    client_version: 0,
+                }),
+            ),
+            (
+                alice,
+                OperationKind::Replace(ReplaceOp {
+                    start: 0,
+                    end: 3,
+                    text: "2\n".to_string(),
+                    client_id: String::new(),
+                    client_version: 0,
+                }),
+            ),
+        ];
+        for (at, (author, op)) in ops.iter().enumerate() {
+            let at = Timestamp {
+                wall_ms: at as u64 + 1,
+                mono_ms: 0,
+            };
+            stats.record(op, &content, *author, at);
+            apply_to_text(&mut content, op).unwrap();
+        }
+
+        assert_eq!(stats, {
+            let mut recounted = DocumentStats::for_content(&content);
+            recounted.last_edit = stats.last_edit;
+            recounted.edits = vec![(alice, 2), (bob, 1)];
+            recounted
+        });
+        assert_eq!(stats.to_proto().last_edit_ms, 3);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fs, io, path::PathBuf};
+
+use common::space::TemplateVariableProto;
+
+/// Named document templates: plain files in a directory, named by file name.
+/// `{{name}}` placeholders are filled in when a document is created from one.
+pub struct TemplateStore {
+    dir: PathBuf,
+}
+
+impl TemplateStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The text of template `name` with its placeholders filled in.
+    pub fn instantiate(
+        &self,
+        name: &str,
+        variables: &[TemplateVariableProto],
+    ) -> Result<String, String> {
+        // Names may not reach outside the template directory
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(format!("invalid template name '{}'", name));
+        }
+        let template = fs::read_to_string(self.dir.join(name)).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!("no template named '{}'", name),
+            _ => format!("cannot read template '{}': {}", name, e),
+        })?;
+        render(&template, variables)
+    }
+}
+
+/// Replaces each `{{name}}` (spaces inside the braces are ignored) with the
+/// variable of that name. Placeholders left without a value are an error.
+fn render(template: &str, variables: &[TemplateVariableProto]) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut missing: Vec<&str> = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..open]);
+        let name = rest[open + 2..open + 2 + close].trim();
+        match variables.iter().find(|variable| variable.name == name) {
+            Some(variable) => rendered.push_str(&variable.value),
+            None if !missing.contains(&name) => missing.push(name),
+            None => {}
+        }
+        rest = &rest[open + 2 + close + 2..];
+    }
+    rendered.push_str(rest);
+
+    if missing.is_empty() {
+        Ok(rendered)
+    } else {
+        Err(format!("no value for {}", missing.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, value: &str) -> TemplateVariableProto {
+        TemplateVariableProto {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let variables = [variable("title", "Sync"), variable("date", "2024-05-01")];
+        assert_eq!(
+            render("# {{title}} ({{ date }})\n{{title}}: {{", &variables),
+            Ok("# Sync (2024-05-01)\nSync: {{".to_string())
+        );
+        assert_eq!(
+            render("{{owner}} {{title}} {{owner}}", &variables),
+            Err("no value for owner".to_string())
+        );
+
+        let store = TemplateStore::new(PathBuf::from("templates"));
+        assert!(store.instantiate("../secrets", &variables).is_err());
+        assert!(store.instantiate(".hidden", &variables).is_err());
+    }
+}
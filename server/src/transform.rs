@@ -1,37 +1,135 @@
-use common::operation::{DeleteOp, InsertOp, NoopOp, OperationKind};
+use common::checked;
+use common::operation::{DeleteOp, InsertOp, NoopOp, OperationKind, ReplaceOp};
+
+use crate::conflict::ConflictPolicy;
 
 fn map_index_after_deletion(i: usize, del_start: usize, del_end: usize) -> usize {
     if i <= del_start {
         i
     } else if i >= del_end {
-        i - (del_end - del_start)
+        i - del_end.saturating_sub(del_start)
     } else {
         del_start
     }
 }
 
 fn map_index_after_insertion(i: usize, ins_pos: usize, ins_len: usize) -> usize {
-    if i < ins_pos { i } else { i + ins_len }
+    if i < ins_pos { i } else { i.saturating_add(ins_len) }
+}
+
+fn noop(client_id: String, client_version: u64) -> OperationKind {
+    OperationKind::Noop(NoopOp {
+        client_id,
+        client_version,
+    })
+}
+
+/// Where the insertion point `index` is after `prev`.
+fn map_position(index: u32, client_id: &str, prev: &OperationKind) -> u32 {
+    let point = OperationKind::Insert(InsertOp {
+        index,
+        text: String::new(),
+        client_id: client_id.to_string(),
+        client_version: 0,
+    });
+    match transform_with(point, prev.clone(), ConflictPolicy::Merge) {
+        OperationKind::Insert(op) => op.index,
+        _ => index,
+    }
+}
+
+/// Where `start..end` is after `prev`, grown over anything inserted inside
+/// it; `None` if all of it was deleted.
+fn map_range(start: u32, end: u32, client_id: &str, prev: &OperationKind) -> Option<(u32, u32)> {
+    if start == end {
+        let at = map_position(start, client_id, prev);
+        return Some((at, at));
+    }
+    let range = OperationKind::Delete(DeleteOp {
+        start,
+        end,
+        client_id: client_id.to_string(),
+        client_version: 0,
+    });
+    match transform_with(range, prev.clone(), ConflictPolicy::Merge) {
+        OperationKind::Delete(op) => Some((op.start, op.end)),
+        _ => None,
+    }
 }
 
+/// Rebases `op_in` past the already-applied `op_prev` under the default
+/// `ConflictPolicy`. The server always goes through `transform_with`.
+#[cfg(test)]
 pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind {
+    transform_with(op_in, op_prev, ConflictPolicy::default())
+}
+
+/// Rebases `op_in` past the already-applied `op_prev`, settling ambiguous
+/// cases as `policy` says.
+pub fn transform_with(
+    op_in: OperationKind,
+    op_prev: OperationKind,
+    policy: ConflictPolicy,
+) -> OperationKind {
+    // Line ops are resolved into character ops before they are applied, so
+    // one is never `op_prev`
     match op_in {
         OperationKind::Noop(_) => op_in,
 
+        // Line ops follow the text they name like the equivalent character
+        // ops would; which lines that is gets settled when they are applied
+        OperationKind::InsertLine(mut op) => {
+            op.index = map_position(op.index, &op.client_id, &op_prev);
+            OperationKind::InsertLine(op)
+        }
+
+        OperationKind::DeleteLine(mut op) => {
+            match map_range(op.start, op.end, &op.client_id, &op_prev) {
+                Some((start, end)) => {
+                    (op.start, op.end) = (start, end);
+                    OperationKind::DeleteLine(op)
+                }
+                // The lines are already gone
+                None => noop(op.client_id, op.client_version),
+            }
+        }
+
+        OperationKind::MoveLine(mut op) => {
+            match map_range(op.start, op.end, &op.client_id, &op_prev) {
+                Some((start, end)) => {
+                    (op.start, op.end) = (start, end);
+                    op.to = map_position(op.to, &op.client_id, &op_prev);
+                    OperationKind::MoveLine(op)
+                }
+                None => noop(op.client_id, op.client_version),
+            }
+        }
+
         OperationKind::Insert(mut op) => match op_prev {
-            OperationKind::Noop(_) => OperationKind::Insert(op),
+            OperationKind::Noop(_)
+            | OperationKind::InsertLine(_)
+            | OperationKind::DeleteLine(_)
+            | OperationKind::MoveLine(_) => OperationKind::Insert(op),
 
             OperationKind::Insert(prev) => {
-                // If previous insert was before us (or at same spot with lower ID), we shift right
-                if prev.index < op.index
-                    || (prev.index == op.index && prev.client_id < op.client_id)
-                {
-                    op.index += prev.text.len() as u32;
+                // If previous insert was before us (or at same spot and goes first), we shift right
+                let prev_first = match policy {
+                    ConflictPolicy::FirstWriterWins => true,
+                    _ => prev.client_id < op.client_id,
+                };
+                if prev.index < op.index || (prev.index == op.index && prev_first) {
+                    op.index = checked::shift(op.index, prev.text.len());
                 }
                 OperationKind::Insert(op)
             }
 
             OperationKind::Delete(prev) => {
+                if policy == ConflictPolicy::DeleteWins
+                    && prev.start < op.index
+                    && op.index < prev.end
+                {
+                    return noop(op.client_id, op.client_version);
+                }
                 // Map our insertion point past the deletion
                 op.index = map_index_after_deletion(
                     op.index as usize,
@@ -42,6 +140,12 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
             }
 
             OperationKind::Replace(prev) => {
+                if policy == ConflictPolicy::DeleteWins
+                    && prev.start < op.index
+                    && op.index < prev.end
+                {
+                    return noop(op.client_id, op.client_version);
+                }
                 // Replace is effectively Delete then Insert
                 // Map past deletion
                 let after_del = map_index_after_deletion(
@@ -58,17 +162,30 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
         },
 
         OperationKind::Delete(mut op) => match op_prev {
-            OperationKind::Noop(_) => OperationKind::Delete(op),
+            OperationKind::Noop(_)
+            | OperationKind::InsertLine(_)
+            | OperationKind::DeleteLine(_)
+            | OperationKind::MoveLine(_) => OperationKind::Delete(op),
 
             OperationKind::Insert(prev) => {
                 // If insert is before our delete start, shift both start and end
-                if (prev.index as u32) <= op.start {
-                    op.start += prev.text.len() as u32;
-                    op.end += prev.text.len() as u32;
+                if prev.index <= op.start {
+                    op.start = checked::shift(op.start, prev.text.len());
+                    op.end = checked::shift(op.end, prev.text.len());
                 }
-                // If insert is inside our delete range, we expand to include it (simplification)
-                else if (prev.index as u32) < op.end {
-                    op.end += prev.text.len() as u32;
+                // If insert is inside our delete range, we expand to include it,
+                // putting it back if inserts are kept
+                else if prev.index < op.end {
+                    op.end = checked::shift(op.end, prev.text.len());
+                    if policy == ConflictPolicy::KeepInserts {
+                        return OperationKind::Replace(ReplaceOp {
+                            start: op.start,
+                            end: op.end,
+                            text: prev.text,
+                            client_id: op.client_id,
+                            client_version: op.client_version,
+                        });
+                    }
                 }
                 // If insert is after, no change
                 OperationKind::Delete(op)
@@ -132,11 +249,20 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
                 let ins_index = prev.start;
                 let ins_len = prev.text.len();
 
-                if (ins_index as u32) <= temp_op.start {
-                    temp_op.start += ins_len as u32;
-                    temp_op.end += ins_len as u32;
-                } else if (ins_index as u32) < temp_op.end {
-                    temp_op.end += ins_len as u32;
+                if ins_index <= temp_op.start {
+                    temp_op.start = checked::shift(temp_op.start, ins_len);
+                    temp_op.end = checked::shift(temp_op.end, ins_len);
+                } else if ins_index < temp_op.end {
+                    temp_op.end = checked::shift(temp_op.end, ins_len);
+                    if policy == ConflictPolicy::KeepInserts {
+                        return OperationKind::Replace(ReplaceOp {
+                            start: temp_op.start,
+                            end: temp_op.end,
+                            text: prev.text,
+                            client_id: temp_op.client_id,
+                            client_version: temp_op.client_version,
+                        });
+                    }
                 }
 
                 OperationKind::Delete(temp_op)
@@ -144,15 +270,22 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
         },
 
         OperationKind::Replace(mut op) => match op_prev {
-            OperationKind::Noop(_) => OperationKind::Replace(op),
+            OperationKind::Noop(_)
+            | OperationKind::InsertLine(_)
+            | OperationKind::DeleteLine(_)
+            | OperationKind::MoveLine(_) => OperationKind::Replace(op),
 
             OperationKind::Insert(prev) => {
                 // Adjust start/end like Delete
-                if (prev.index as u32) <= op.start {
-                    op.start += prev.text.len() as u32;
-                    op.end += prev.text.len() as u32;
-                } else if (prev.index as u32) < op.end {
-                    op.end += prev.text.len() as u32;
+                if prev.index <= op.start {
+                    op.start = checked::shift(op.start, prev.text.len());
+                    op.end = checked::shift(op.end, prev.text.len());
+                } else if prev.index < op.end {
+                    op.end = checked::shift(op.end, prev.text.len());
+                    if policy == ConflictPolicy::KeepInserts {
+                        // Where the insert would land had it come second
+                        op.text.push_str(&prev.text);
+                    }
                 }
                 OperationKind::Replace(op)
             }
@@ -170,7 +303,9 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
                     prev.end as usize,
                 );
 
-                if new_start == new_end {
+                if new_start == new_end && policy == ConflictPolicy::DeleteWins {
+                    noop(op.client_id, op.client_version)
+                } else if new_start == new_end {
                     // Range collapsed, but we still have text to insert!
                     // Becomes an Insert
                     OperationKind::Insert(InsertOp {
@@ -187,6 +322,12 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
             }
 
             OperationKind::Replace(prev) => {
+                if policy == ConflictPolicy::FirstWriterWins
+                    && op.start < prev.end
+                    && prev.start < op.end
+                {
+                    return noop(op.client_id, op.client_version);
+                }
                 // Map range against prev (Delete + Insert)
                 // Map against Delete part
                 let start_after_del = map_index_after_deletion(
@@ -230,7 +371,6 @@ pub fn transform(op_in: OperationKind, op_prev: OperationKind) -> OperationKind
 #[cfg(test)]
 mod tests {
     use super::*;
-    use common::operation::ReplaceOp;
 
     // ============================================
     // HELPER FUNCTIONS FOR TESTING
@@ -296,6 +436,10 @@ mod tests {
                 Ok(())
             }
             OperationKind::Noop(_) => Ok(()),
+            line_op => {
+                let op = common::lines::to_char_op(line_op, doc)?;
+                apply_op(doc, &op)
+            }
         }
     }
 
@@ -732,6 +876,134 @@ mod tests {
             make_delete(5, 9, "B", 1),
         );
     }
+
+    #[test]
+    fn test_conformance_vectors() {
+        let failures = common::conformance::check_transform(|op, prev| {
+            transform(op.clone(), prev.clone())
+        });
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    // ============================================
+    // CONFLICT POLICIES
+    // ============================================
+
+    /// The text after `a` then `b`, and after `b` then `a`, each rebased past
+    /// the other under `policy`.
+    fn both_orders(
+        policy: ConflictPolicy,
+        initial: &str,
+        a: &OperationKind,
+        b: &OperationKind,
+    ) -> (String, String) {
+        let run = |first: &OperationKind, second: &OperationKind| {
+            let mut doc = initial.to_string();
+            apply_op(&mut doc, first).unwrap();
+            apply_op(
+                &mut doc,
+                &transform_with(second.clone(), first.clone(), policy),
+            )
+            .unwrap();
+            doc
+        };
+        (run(a, b), run(b, a))
+    }
+
+    #[test]
+    fn test_merge_policy_is_the_default() {
+        let insert = make_insert(3, "XX", "A", 1);
+        let delete = make_delete(1, 8, "B", 1);
+        assert_eq!(
+            transform(insert.clone(), delete.clone()),
+            transform_with(insert.clone(), delete.clone(), ConflictPolicy::Merge)
+        );
+        // Which of the two survives depends on which applies first
+        assert_eq!(
+            both_orders(ConflictPolicy::Merge, "hello world", &insert, &delete),
+            ("hrld".to_string(), "hXXrld".to_string())
+        );
+    }
+
+    #[test]
+    fn test_keep_inserts_policy() {
+        let insert = make_insert(3, "XX", "A", 1);
+        for (other, want) in [
+            (make_delete(1, 8, "B", 1), "hXXrld"),
+            (make_replace(1, 8, "R", "B", 1), "hRXXrld"),
+        ] {
+            let (ab, ba) = both_orders(ConflictPolicy::KeepInserts, "hello world", &insert, &other);
+            assert_eq!((ab.as_str(), ba.as_str()), (want, want), "{:?}", other);
+        }
+
+        // A replace made inside a deleted range keeps its text too
+        let delete = make_delete(1, 8, "A", 1);
+        let replace = make_replace(3, 5, "R", "B", 1);
+        let (ab, ba) = both_orders(
+            ConflictPolicy::KeepInserts,
+            "hello world",
+            &delete,
+            &replace,
+        );
+        assert_eq!((ab.as_str(), ba.as_str()), ("hRrld", "hRrld"));
+    }
+
+    #[test]
+    fn test_delete_wins_policy() {
+        let insert = make_insert(3, "XX", "A", 1);
+        for (other, want) in [
+            (make_delete(1, 8, "B", 1), "hrld"),
+            (make_replace(1, 8, "R", "B", 1), "hRrld"),
+        ] {
+            let (ab, ba) = both_orders(ConflictPolicy::DeleteWins, "hello world", &insert, &other);
+            assert_eq!((ab.as_str(), ba.as_str()), (want, want), "{:?}", other);
+        }
+
+        let delete = make_delete(1, 8, "A", 1);
+        let replace = make_replace(3, 5, "R", "B", 1);
+        let (ab, ba) = both_orders(ConflictPolicy::DeleteWins, "hello world", &delete, &replace);
+        assert_eq!((ab.as_str(), ba.as_str()), ("hrld", "hrld"));
+
+        // Inserts at the edges of the range are not inside it
+        let edge = make_insert(1, "XX", "A", 1);
+        assert_eq!(
+            transform_with(edge, make_delete(1, 8, "B", 1), ConflictPolicy::DeleteWins),
+            make_insert(1, "XX", "A", 1)
+        );
+    }
+
+    #[test]
+    fn test_first_writer_wins_policy() {
+        // At one index the insert applied first stays first, whatever the ids
+        let late = make_insert(5, "X", "A", 1);
+        let applied = make_insert(5, "Y", "B", 1);
+        assert_eq!(
+            transform_with(
+                late.clone(),
+                applied.clone(),
+                ConflictPolicy::FirstWriterWins
+            ),
+            make_insert(6, "X", "A", 1)
+        );
+        assert_eq!(transform(late, applied), make_insert(5, "X", "A", 1));
+
+        // A replace overlapping one applied before it is dropped
+        let late = make_replace(2, 5, "Y", "A", 1);
+        let applied = make_replace(1, 4, "X", "B", 1);
+        assert_eq!(
+            transform_with(late, applied, ConflictPolicy::FirstWriterWins),
+            make_noop("A", 1)
+        );
+        let apart = make_replace(6, 8, "Y", "A", 1);
+        assert_eq!(
+            transform_with(
+                apart,
+                make_replace(1, 4, "X", "B", 1),
+                ConflictPolicy::FirstWriterWins
+            ),
+            make_replace(4, 6, "Y", "A", 1)
+        );
+    }
 }
 
 // ============================================
@@ -846,6 +1118,10 @@ mod proptests {
                 Ok(())
             }
             OperationKind::Noop(_) => Ok(()),
+            line_op => {
+                let op = common::lines::to_char_op(line_op, doc)?;
+                apply_op(doc, &op)
+            }
         }
     }
 
@@ -955,3 +1231,262 @@ mod proptests {
         }
     }
 }
+
+// ============================================
+// TRANSFORM PROPERTIES (TP1, TP2, NOOP, COMPOSITION)
+// ============================================
+//
+// Generators draw positions from the document length and short lowercase
+// texts, so failures shrink to a few characters. Each op's client id is fixed
+// by its role ("A", "B", "C"), keeping tie-breaks reproducible.
+//
+// The server applies concurrent ops in one total order, so only TP1 (two
+// paths over the same pair converge) is needed for clients to agree. TP2
+// (transforming past two concurrent ops gives the same op either way) holds
+// for inserts alone and deletes alone; mixing them breaks it, e.g. on "a",
+// insert "a" at 1 vs delete 0..1 vs insert "b" at 0.
+
+#[cfg(test)]
+mod transform_properties {
+    use super::*;
+    use common::document::apply_to_text;
+    use common::operation::{Operation, OperationLog, ReplaceOp};
+    use proptest::prelude::*;
+    use uuid::Uuid;
+
+    #[derive(Clone, Copy, Debug)]
+    enum Kinds {
+        Inserts,
+        Deletes,
+        InsertsAndDeletes,
+    }
+
+    fn arb_insert(len: u32, client_id: &'static str) -> BoxedStrategy<OperationKind> {
+        (0..=len, "[a-z]{1,3}")
+            .prop_map(move |(index, text)| {
+                OperationKind::Insert(InsertOp {
+                    index,
+                    text,
+                    client_id: client_id.to_string(),
+                    client_version: 0,
+                })
+            })
+            .boxed()
+    }
+
+    /// A non-empty range; `len` must be at least 1.
+    fn arb_delete(len: u32, client_id: &'static str) -> BoxedStrategy<OperationKind> {
+        (0..len)
+            .prop_flat_map(move |start| (Just(start), start + 1..=len))
+            .prop_map(move |(start, end)| {
+                OperationKind::Delete(DeleteOp {
+                    start,
+                    end,
+                    client_id: client_id.to_string(),
+                    client_version: 0,
+                })
+            })
+            .boxed()
+    }
+
+    fn arb_op(kinds: Kinds, len: u32, client_id: &'static str) -> BoxedStrategy<OperationKind> {
+        match kinds {
+            Kinds::Inserts => arb_insert(len, client_id),
+            Kinds::Deletes => arb_delete(len, client_id),
+            Kinds::InsertsAndDeletes => {
+                prop_oneof![arb_insert(len, client_id), arb_delete(len, client_id)].boxed()
+            }
+        }
+    }
+
+    /// A document and `N` ops made concurrently against it by clients A, B, ...
+    fn arb_concurrent<const N: usize>(
+        kinds: Kinds,
+    ) -> impl Strategy<Value = (String, Vec<OperationKind>)> {
+        const CLIENTS: [&str; 3] = ["A", "B", "C"];
+        "[a-z]{1,8}".prop_flat_map(move |doc| {
+            let len = doc.len() as u32;
+            let ops: Vec<_> = CLIENTS[..N]
+                .iter()
+                .map(|client_id| arb_op(kinds, len, client_id))
+                .collect();
+            (Just(doc), ops)
+        })
+    }
+
+    fn range(op: &OperationKind) -> Option<(u32, u32)> {
+        match op {
+            OperationKind::Delete(DeleteOp { start, end, .. })
+            | OperationKind::Replace(ReplaceOp { start, end, .. }) => Some((*start, *end)),
+            _ => None,
+        }
+    }
+
+    /// An insert strictly inside a concurrently deleted range: whether the
+    /// text survives is ambiguous, and the two paths currently disagree.
+    fn insert_inside_delete(a: &OperationKind, b: &OperationKind) -> bool {
+        let inside = |op: &OperationKind, other: &OperationKind| match (op, range(other)) {
+            (OperationKind::Insert(insert), Some((start, end))) => {
+                start < insert.index && insert.index < end
+            }
+            _ => false,
+        };
+        inside(a, b) || inside(b, a)
+    }
+
+    fn apply_all(doc: &str, ops: &[OperationKind]) -> Result<String, String> {
+        let mut text = doc.to_string();
+        for op in ops {
+            apply_to_text(&mut text, op)?;
+        }
+        Ok(text)
+    }
+
+    /// Both orders of applying a concurrent pair give the same text.
+    fn check_tp1(doc: &str, a: &OperationKind, b: &OperationKind) -> Result<(), TestCaseError> {
+        let a_first = apply_all(doc, &[a.clone(), transform(b.clone(), a.clone())]);
+        let b_first = apply_all(doc, &[b.clone(), transform(a.clone(), b.clone())]);
+        prop_assert!(a_first.is_ok(), "{:?}", a_first);
+        prop_assert_eq!(a_first, b_first);
+        Ok(())
+    }
+
+    /// Transforming `c` past a and b gives the same effect in either order.
+    fn check_tp2(
+        doc: &str,
+        a: &OperationKind,
+        b: &OperationKind,
+        c: &OperationKind,
+    ) -> Result<(), TestCaseError> {
+        let b_after_a = transform(b.clone(), a.clone());
+        let a_after_b = transform(a.clone(), b.clone());
+        let via_a = transform(transform(c.clone(), a.clone()), b_after_a.clone());
+        let via_b = transform(transform(c.clone(), b.clone()), a_after_b);
+        let base = apply_all(doc, &[a.clone(), b_after_a]).unwrap();
+        prop_assert_eq!(apply_all(&base, &[via_a]), apply_all(&base, &[via_b]));
+        Ok(())
+    }
+
+    fn logged(kind: OperationKind, server_version: u64) -> Operation {
+        Operation {
+            op_id: server_version,
+            kind,
+            doc_id: String::new(),
+            new_content: String::new(),
+            client_id: Uuid::nil(),
+            client_version: server_version,
+            server_version,
+            applied_at: Default::default(),
+        }
+    }
+
+    /// Typing or backspacing by client A, one op per keystroke.
+    fn arb_run(len: u32) -> impl Strategy<Value = Vec<OperationKind>> {
+        let typing =
+            (0..=len, prop::collection::vec("[a-z]{1,2}", 2..5)).prop_map(|(mut index, texts)| {
+                texts
+                    .into_iter()
+                    .map(|text| {
+                        let op = InsertOp {
+                            index,
+                            client_id: "A".to_string(),
+                            client_version: 0,
+                            text,
+                        };
+                        index += op.text.len() as u32;
+                        OperationKind::Insert(op)
+                    })
+                    .collect::<Vec<_>>()
+            });
+        let backspacing = (2..=len).prop_map(|mut end| {
+            let mut ops = Vec::new();
+            while end > 0 && ops.len() < 3 {
+                ops.push(OperationKind::Delete(DeleteOp {
+                    start: end - 1,
+                    end,
+                    client_id: "A".to_string(),
+                    client_version: 0,
+                }));
+                end -= 1;
+            }
+            ops
+        });
+        prop_oneof![typing, backspacing]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(500))]
+
+        #[test]
+        fn prop_tp1_inserts((doc, ops) in arb_concurrent::<2>(Kinds::Inserts)) {
+            check_tp1(&doc, &ops[0], &ops[1])?;
+        }
+
+        #[test]
+        fn prop_tp1_deletes((doc, ops) in arb_concurrent::<2>(Kinds::Deletes)) {
+            check_tp1(&doc, &ops[0], &ops[1])?;
+        }
+
+        #[test]
+        fn prop_tp1_inserts_and_deletes(
+            (doc, ops) in arb_concurrent::<2>(Kinds::InsertsAndDeletes)
+                .prop_filter("insert inside a deleted range", |(_, ops)| {
+                    !insert_inside_delete(&ops[0], &ops[1])
+                })
+        ) {
+            check_tp1(&doc, &ops[0], &ops[1])?;
+        }
+
+        #[test]
+        fn prop_tp2_inserts((doc, ops) in arb_concurrent::<3>(Kinds::Inserts)) {
+            check_tp2(&doc, &ops[0], &ops[1], &ops[2])?;
+        }
+
+        #[test]
+        fn prop_tp2_deletes((doc, ops) in arb_concurrent::<3>(Kinds::Deletes)) {
+            check_tp2(&doc, &ops[0], &ops[1], &ops[2])?;
+        }
+
+        /// Any number of noops leave an op as it was, and a noop stays one.
+        #[test]
+        fn prop_noop_chains_are_identity(
+            (_, ops) in arb_concurrent::<1>(Kinds::InsertsAndDeletes),
+            chain in 1..8usize,
+        ) {
+            let noop = || OperationKind::Noop(NoopOp {
+                client_id: "B".to_string(),
+                client_version: 0,
+            });
+            let op = ops[0].clone();
+            let after = (0..chain).fold(op.clone(), |op, _| transform(op, noop()));
+            prop_assert_eq!(format!("{:?}", after), format!("{:?}", op));
+            let still_noop = transform(noop(), op);
+            prop_assert!(matches!(still_noop, OperationKind::Noop(_)));
+        }
+
+        /// Transforming past a run the log composed into one entry matches
+        /// transforming past each of its ops in turn.
+        #[test]
+        fn prop_transform_past_composed_run(
+            (doc, run, concurrent) in "[a-z]{2,8}".prop_flat_map(|doc| {
+                let len = doc.len() as u32;
+                (Just(doc), arb_run(len), arb_op(Kinds::InsertsAndDeletes, len, "B"))
+            })
+        ) {
+            let log = OperationLog::new();
+            for (version, op) in run.iter().enumerate() {
+                log.append_log(logged(op.clone(), version as u64)).unwrap();
+            }
+            prop_assert_eq!(log.len(), 1);
+            let composed = log.get_ops_in_range(0, run.len() as u64).unwrap();
+            prop_assert_eq!(composed.len(), 1);
+
+            let stepwise = run.iter().fold(concurrent.clone(), |op, prev| {
+                transform(op, prev.clone())
+            });
+            let at_once = transform(concurrent, composed[0].kind.clone());
+            let base = apply_all(&doc, &run).unwrap();
+            prop_assert_eq!(apply_all(&base, &[stepwise]), apply_all(&base, &[at_once]));
+        }
+    }
+}
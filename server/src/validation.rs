@@ -0,0 +1,343 @@
+use std::fmt;
+
+use common::{
+    operation::{
+        DeleteLineOp, DeleteOp, InsertLineOp, InsertOp, MoveLineOp, OperationKind, ReplaceOp,
+    },
+    space::{ErrorCode, LineEnding},
+};
+
+/// Largest text a single insert or replace may carry.
+pub const MAX_OP_TEXT_BYTES: usize = 256 * 1024;
+
+/// Largest transaction, as encoded operations, a client may send in chunks.
+pub const MAX_TRANSACTION_BYTES: usize = 64 * 1024 * 1024;
+
+/// Why an operation was refused. Sent back to the originating client as an
+/// Error frame carrying the op_id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// An index or range end lies past the end of the document.
+    OutOfBounds { position: u32, len: usize },
+    /// A range whose start is after its end.
+    InvalidRange { start: u32, end: u32 },
+    /// An index that splits a multi-byte UTF-8 character.
+    NotCharBoundary { position: u32 },
+    /// Inserted text exceeds `MAX_OP_TEXT_BYTES`.
+    TextTooLarge { len: usize, max: usize },
+    /// No document has this id (or the op carried none).
+    UnknownDocument { doc_id: String },
+    /// The document exists but this connection never opened it.
+    NotSubscribed { doc_id: String },
+    /// The connection announced itself as a read-only viewer.
+    ReadOnly,
+    /// The edit overlaps `[start, end)`, which another connection has locked.
+    RangeLocked { start: u32, end: u32 },
+    /// The op was written against a version the server cannot transform
+    /// from: ahead of the document, or before its retained history.
+    UnknownVersion {
+        version: u64,
+        first: u64,
+        current: u64,
+    },
+    /// The edit brings in line endings other than the document's `expected`.
+    LineEnding { expected: LineEnding },
+    /// The document would be `len` bytes, past its `max`.
+    DocumentTooLarge { len: usize, max: u64 },
+    /// A chunked transaction grew to `len` bytes, past `MAX_TRANSACTION_BYTES`.
+    TransactionTooLarge { len: usize, max: usize },
+    /// The workspace already has its `max` connections or documents (`what`).
+    WorkspaceFull {
+        workspace: String,
+        what: &'static str,
+        max: usize,
+    },
+    /// The workspace has members and the connection is not one of them.
+    NotAMember { workspace: String },
+    /// The guest invite expired `expired_ms_ago`.
+    InviteExpired { expired_ms_ago: u64 },
+    /// A guest asked for something other than opening `path`, its document.
+    GuestRestricted { path: String },
+    /// The operator froze `target`, a document or workspace, until
+    /// `ends_at_ms` or until lifted.
+    Frozen {
+        target: String,
+        ends_at_ms: Option<u64>,
+    },
+    /// The connection is over its op rate; it may send again in
+    /// `retry_after_ms`.
+    RateLimited { retry_after_ms: u64 },
+    /// A middleware the deployment added, such as a plugin, refused the op.
+    Refused { middleware: String, reason: String },
+    /// The server is shutting down and handing its documents over to
+    /// `server`.
+    HandingOver { server: String },
+    /// Nothing is at `path`, and the server doesn't create documents on open.
+    NoSuchDocument { path: String },
+    /// The document is at `version`, and versions never wrap, so it takes
+    /// no more ops.
+    VersionExhausted { version: u64 },
+    /// An authorizer the deployment added does not let the sender make the
+    /// edit.
+    Unauthorized { authorizer: String, reason: String },
+    /// The edit removes text from `[start, end)`, an append-only region of
+    /// the document.
+    AppendOnly { start: u32, end: u32 },
+}
+
+impl Rejection {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Rejection::OutOfBounds { .. } => ErrorCode::OpOutOfBounds,
+            Rejection::InvalidRange { .. } => ErrorCode::OpInvalidRange,
+            Rejection::NotCharBoundary { .. } => ErrorCode::OpNotCharBoundary,
+            Rejection::TextTooLarge { .. } => ErrorCode::OpTextTooLarge,
+            Rejection::UnknownDocument { .. } => ErrorCode::OpUnknownDocument,
+            Rejection::NotSubscribed { .. } => ErrorCode::OpNotSubscribed,
+            Rejection::ReadOnly => ErrorCode::ReadOnly,
+            Rejection::RangeLocked { .. } => ErrorCode::RangeLocked,
+            Rejection::UnknownVersion { .. } => ErrorCode::OpUnknownVersion,
+            Rejection::LineEnding { .. } => ErrorCode::OpLineEnding,
+            Rejection::DocumentTooLarge { .. } => ErrorCode::DocumentTooLarge,
+            Rejection::TransactionTooLarge { .. } => ErrorCode::TransactionTooLarge,
+            Rejection::WorkspaceFull { .. } => ErrorCode::WorkspaceFull,
+            Rejection::NotAMember { .. } => ErrorCode::NotAMember,
+            Rejection::InviteExpired { .. } => ErrorCode::InviteExpired,
+            Rejection::GuestRestricted { .. } => ErrorCode::GuestRestricted,
+            Rejection::Frozen { .. } => ErrorCode::Frozen,
+            Rejection::RateLimited { .. } => ErrorCode::RateLimited,
+            Rejection::Refused { .. } => ErrorCode::OpRefused,
+            Rejection::HandingOver { .. } => ErrorCode::HandingOver,
+            Rejection::NoSuchDocument { .. } => ErrorCode::NoSuchDocument,
+            Rejection::VersionExhausted { .. } => ErrorCode::VersionExhausted,
+            Rejection::Unauthorized { .. } => ErrorCode::OpUnauthorized,
+            Rejection::AppendOnly { .. } => ErrorCode::AppendOnly,
+        }
+    }
+
+    /// How long the client should wait before sending again; 0 for no hint.
+    pub fn retry_after_ms(&self) -> u64 {
+        match self {
+            Rejection::RateLimited { retry_after_ms } => *retry_after_ms,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::OutOfBounds { position, len } => {
+                write!(
+                    f,
+                    "position {} is past the end of the document (len {})",
+                    position, len
+                )
+            }
+            Rejection::InvalidRange { start, end } => {
+                write!(f, "range start {} is after end {}", start, end)
+            }
+            Rejection::NotCharBoundary { position } => {
+                write!(f, "position {} is inside a UTF-8 character", position)
+            }
+            Rejection::TextTooLarge { len, max } => {
+                write!(f, "text of {} bytes exceeds the {} byte limit", len, max)
+            }
+            Rejection::UnknownDocument { doc_id } => write!(f, "unknown document '{}'", doc_id),
+            Rejection::NotSubscribed { doc_id } => {
+                write!(f, "document '{}' was not opened on this connection", doc_id)
+            }
+            Rejection::ReadOnly => write!(f, "this connection is read-only"),
+            Rejection::RangeLocked { start, end } => {
+                write!(
+                    f,
+                    "range {}..{} is locked by another connection",
+                    start, end
+                )
+            }
+            Rejection::UnknownVersion {
+                version,
+                first,
+                current,
+            } => write!(
+                f,
+                "version {} is outside the document's history ({}..={})",
+                version, first, current
+            ),
+            Rejection::LineEnding { expected } => {
+                let style = match expected {
+                    LineEnding::Lf => "LF",
+                    LineEnding::Crlf => "CRLF",
+                    LineEnding::Any => "any",
+                };
+                write!(f, "the document only uses {} line endings", style)
+            }
+            Rejection::DocumentTooLarge { len, max } => {
+                write!(
+                    f,
+                    "the document would be {} bytes, past its {} byte limit",
+                    len, max
+                )
+            }
+            Rejection::TransactionTooLarge { len, max } => {
+                write!(
+                    f,
+                    "transaction of {} bytes exceeds the {} byte limit",
+                    len, max
+                )
+            }
+            Rejection::WorkspaceFull {
+                workspace,
+                what,
+                max,
+            } => write!(
+                f,
+                "workspace '{}' already has its limit of {} {}",
+                workspace, max, what
+            ),
+            Rejection::NotAMember { workspace } => {
+                write!(f, "not a member of workspace '{}'", workspace)
+            }
+            Rejection::InviteExpired { expired_ms_ago } => {
+                write!(f, "the invite expired {}ms ago", expired_ms_ago)
+            }
+            Rejection::GuestRestricted { path } => {
+                write!(f, "guests may only open '{}'", path)
+            }
+            Rejection::Frozen {
+                target,
+                ends_at_ms: Some(ends_at_ms),
+            } => write!(f, "{} is frozen until {}", target, ends_at_ms),
+            Rejection::Frozen {
+                target,
+                ends_at_ms: None,
+            } => write!(f, "{} is frozen", target),
+            Rejection::RateLimited { retry_after_ms } => {
+                write!(f, "too many ops; retry in {}ms", retry_after_ms)
+            }
+            Rejection::Refused { middleware, reason } => {
+                write!(f, "refused by {}: {}", middleware, reason)
+            }
+            Rejection::HandingOver { server } => {
+                write!(f, "the server is handing its documents over to {}", server)
+            }
+            Rejection::NoSuchDocument { path } => write!(f, "no document at '{}'", path),
+            Rejection::VersionExhausted { version } => write!(
+                f,
+                "the document is at version {}, the last it can reach",
+                version
+            ),
+            Rejection::Unauthorized { authorizer, reason } => {
+                write!(f, "not allowed by {}: {}", authorizer, reason)
+            }
+            Rejection::AppendOnly { start, end } => {
+                write!(f, "{}..{} is append-only", start, end)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Checks a (transformed) operation against the document it is about to be
+/// applied to, so `apply_op` never sees an index it could panic on.
+pub fn validate(op: &OperationKind, content: &str) -> Result<(), Rejection> {
+    match op {
+        OperationKind::Insert(InsertOp { index, text, .. }) => {
+            check_text(text)?;
+            check_position(*index, content)
+        }
+        OperationKind::Delete(DeleteOp { start, end, .. }) => check_range(*start, *end, content),
+        OperationKind::Replace(ReplaceOp {
+            start, end, text, ..
+        }) => {
+            check_text(text)?;
+            check_range(*start, *end, content)
+        }
+        OperationKind::Noop(_) => Ok(()),
+        OperationKind::InsertLine(InsertLineOp { index, text, .. }) => {
+            check_text(text)?;
+            check_position(*index, content)
+        }
+        OperationKind::DeleteLine(DeleteLineOp { start, end, .. }) => {
+            check_range(*start, *end, content)
+        }
+        OperationKind::MoveLine(MoveLineOp { start, end, to, .. }) => {
+            check_range(*start, *end, content)?;
+            check_position(*to, content)
+        }
+    }
+}
+
+fn check_text(text: &str) -> Result<(), Rejection> {
+    if text.len() > MAX_OP_TEXT_BYTES {
+        return Err(Rejection::TextTooLarge {
+            len: text.len(),
+            max: MAX_OP_TEXT_BYTES,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn check_range(start: u32, end: u32, content: &str) -> Result<(), Rejection> {
+    if start > end {
+        return Err(Rejection::InvalidRange { start, end });
+    }
+    check_position(start, content)?;
+    check_position(end, content)
+}
+
+fn check_position(position: u32, content: &str) -> Result<(), Rejection> {
+    let index = position as usize;
+    if index > content.len() {
+        return Err(Rejection::OutOfBounds {
+            position,
+            len: content.len(),
+        });
+    }
+    if !content.is_char_boundary(index) {
+        return Err(Rejection::NotCharBoundary { position });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_ops_pass() {
+        assert_eq!(validate(&OperationKind::insert_at(5, "!"), "hello"), Ok(()));
+        assert_eq!(
+            validate(&OperationKind::delete_range(0, 5), "hello"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_rejections() {
+        assert_eq!(
+            validate(&OperationKind::insert_at(6, "x"), "hello"),
+            Err(Rejection::OutOfBounds {
+                position: 6,
+                len: 5
+            })
+        );
+        assert_eq!(
+            validate(&OperationKind::delete_range(3, 1), "hello"),
+            Err(Rejection::InvalidRange { start: 3, end: 1 })
+        );
+        // 'é' is two bytes; index 1 splits it
+        assert_eq!(
+            validate(&OperationKind::insert_at(1, "x"), "é"),
+            Err(Rejection::NotCharBoundary { position: 1 })
+        );
+        let huge = "x".repeat(MAX_OP_TEXT_BYTES + 1);
+        assert_eq!(
+            validate(&OperationKind::insert_at(0, &huge), "")
+                .unwrap_err()
+                .code(),
+            ErrorCode::OpTextTooLarge
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, atomic::Ordering},
+    thread,
+};
+
+use common::{
+    Frame,
+    protocol::ServerMessage,
+    space::{ErrorProto, OperationBatchProto, OperationProto},
+};
+use crossbeam::channel::{Receiver, Sender};
+use uuid::Uuid;
+
+use crate::broadcaster::BroadcastFn;
+use crate::documents::DocumentEntry;
+use crate::error::ServerError;
+use crate::log::{debug, error, info};
+use crate::state::ServerState;
+use crate::validation::Rejection;
+
+/// Operations waiting for a document's worker. A full queue blocks the
+/// submitting reader, which in turn stops reading from that client.
+pub const OP_QUEUE_CAPACITY: usize = 256;
+
+/// An operation received from `origin`, queued for its document's worker.
+pub struct OpJob {
+    origin: Uuid,
+    operation: OperationProto,
+}
+
+/// Hands an operation to the worker of the document it targets, starting the
+/// worker on first use. Each document's operations are applied one at a time,
+/// in arrival order, so readers never contend on the document lock.
+pub fn submit(
+    state: &Arc<ServerState>,
+    origin: Uuid,
+    operation: OperationProto,
+    broadcast_fn: BroadcastFn,
+) {
+    let Some(entry) = state.get_document(&operation.doc_id) else {
+        // Nothing to serialise against; this only produces the rejection.
+        let _ = process(state, origin, operation, broadcast_fn);
+        return;
+    };
+
+    let queue = entry
+        .queue
+        .get_or_init(|| spawn_worker(Arc::clone(state), Arc::clone(&entry), broadcast_fn));
+    if queue.send(OpJob { origin, operation }).is_err() {
+        error!("[Worker] Worker for '{}' has exited", entry.path);
+    }
+}
+
+fn spawn_worker(
+    state: Arc<ServerState>,
+    entry: Arc<DocumentEntry>,
+    broadcast_fn: BroadcastFn,
+) -> Sender<OpJob> {
+    let (tx, rx) = crossbeam::channel::bounded::<OpJob>(OP_QUEUE_CAPACITY);
+    info!("[Worker] Starting worker for '{}'", entry.path);
+    thread::spawn(move || run_worker(state, entry, rx, broadcast_fn));
+    tx
+}
+
+fn run_worker(
+    state: Arc<ServerState>,
+    entry: Arc<DocumentEntry>,
+    jobs: Receiver<OpJob>,
+    broadcast_fn: BroadcastFn,
+) {
+    for job in jobs {
+        let _emitting = entry.emitting();
+        let counter = match process(&state, job.origin, job.operation, broadcast_fn) {
+            Ok(()) => &entry.metrics.applied,
+            Err(ServerError::Rejected(_)) => &entry.metrics.rejected,
+            Err(_) => continue,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Applies one operation and emits its frames: batched if the state has a
+/// batcher, otherwise echoed to the origin and broadcast to its collaborators.
+/// Rejections are reported to the origin as an Error frame.
+fn process(
+    state: &ServerState,
+    origin: Uuid,
+    operation: OperationProto,
+    broadcast_fn: BroadcastFn,
+) -> Result<(), ServerError> {
+    let doc_id = operation.doc_id.clone();
+    let op_id = operation.op_id;
+    match state.send_applied_op(origin, operation) {
+        Ok(applied) => {
+            if let Some(batcher) = state.batcher() {
+                // Flushed to all subscribers, the originator included
+                batcher.push(&doc_id, applied.operation_proto, applied.sync);
+                return Ok(());
+            }
+
+            // The origin already has its own edit; it only needs
+            // the transformed op to learn where it landed.
+            state.send_to_client(origin, Arc::clone(&applied.operation));
+            for frame in [applied.operation, applied.sync] {
+                broadcast_fn(origin, &doc_id, frame, state);
+            }
+            Ok(())
+        }
+        Err(ServerError::Rejected(rejection)) => {
+            error!("[{}] Rejected operation {}: {}", origin, op_id, rejection);
+            reject(state, origin, op_id, &rejection);
+            Err(ServerError::Rejected(rejection))
+        }
+        Err(e) => {
+            error!("[{}] Error applying operation for: {}", origin, e);
+            Err(e)
+        }
+    }
+}
+
+/// Takes an OperationBatch from a client: a whole transaction, or a chunk of
+/// one, applied with the batch that completes it.
+pub fn process_batch(
+    state: &ServerState,
+    origin: Uuid,
+    batch: OperationBatchProto,
+    broadcast_fn: BroadcastFn,
+) {
+    match state.add_chunk(origin, batch) {
+        Ok(Some(operations)) => process_transaction(state, origin, operations, broadcast_fn),
+        Ok(None) => debug!("[{}] Holding chunk of transaction", origin),
+        Err((op_id, rejection)) => {
+            error!(
+                "[{}] Rejected transaction at operation {}: {}",
+                origin, op_id, rejection
+            );
+            reject(state, origin, op_id, &rejection);
+        }
+    }
+}
+
+/// Applies a client's OperationBatch as one transaction, right away rather
+/// than through the documents' workers, and emits each document's ops as a
+/// batch followed by its sync; the origin gets only the batches. A rejection
+/// names the op that failed, and nothing is applied.
+pub fn process_transaction(
+    state: &ServerState,
+    origin: Uuid,
+    operations: Vec<OperationProto>,
+    broadcast_fn: BroadcastFn,
+) {
+    // In id order, like the document locks taken by apply_transaction
+    let entries: BTreeMap<String, Arc<DocumentEntry>> = operations
+        .iter()
+        .filter_map(|op| Some((op.doc_id.clone(), state.get_document(&op.doc_id)?)))
+        .collect();
+    let _emitting: Vec<_> = entries.values().map(|entry| entry.emitting()).collect();
+
+    let count = operations.len();
+    match state.apply_transaction(origin, operations) {
+        Ok(applied) => {
+            debug!(
+                "[{}] Applied {} operation(s) on {} document(s) as workspace version {}",
+                origin,
+                count,
+                applied.documents.len(),
+                applied.global_version
+            );
+            for document in &applied.documents {
+                if let Some(entry) = entries.get(&document.doc_id) {
+                    let applied = document.operations.len() as u64;
+                    entry.metrics.applied.fetch_add(applied, Ordering::Relaxed);
+                }
+            }
+            if let Some(batcher) = state.batcher() {
+                batcher.push_transaction(&applied.documents);
+                return;
+            }
+            for document in applied.documents {
+                state.send_to_client(origin, Arc::clone(&document.batch));
+                for frame in [document.batch, document.sync] {
+                    broadcast_fn(origin, &document.doc_id, frame, state);
+                }
+            }
+        }
+        Err((op_id, ServerError::Rejected(rejection))) => {
+            error!(
+                "[{}] Rejected transaction at operation {}: {}",
+                origin, op_id, rejection
+            );
+            reject(state, origin, op_id, &rejection);
+        }
+        Err((op_id, e)) => {
+            error!(
+                "[{}] Error applying transaction at operation {}: {}",
+                origin, op_id, e
+            );
+        }
+    }
+}
+
+/// Tells the origin why its operation was refused.
+fn reject(state: &ServerState, origin: Uuid, op_id: u64, rejection: &Rejection) {
+    let error = ServerMessage::Error(ErrorProto {
+        code: rejection.code() as i32,
+        message: rejection.to_string(),
+        retry_after_ms: rejection.retry_after_ms(),
+        op_id,
+    });
+    state.send_to_client(origin, Frame::new_arc(ServerMessage::encode(&error)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    use common::space::{InsertOp, operation_proto::Kind};
+
+    use crate::client_entry::ClientEntry;
+
+    fn ignore_broadcast(_: Uuid, _: &str, _: Arc<Frame>, _: &ServerState) {}
+
+    #[test]
+    fn test_worker_applies_ops_in_order() {
+        let state = Arc::new(ServerState::new());
+        let client_id = Uuid::new_v4();
+        let (tx, _rx) = crossbeam::channel::bounded(64);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        state.open_document(client_id, "").unwrap();
+        let entry = state.documents().pop().unwrap();
+        let doc_id = entry.sync_proto().doc_id;
+
+        for (version, text) in ["a", "b", "c"].into_iter().enumerate() {
+            let op = OperationProto {
+                op_id: version as u64,
+                kind: Some(Kind::Insert(InsertOp {
+                    index: version as u32,
+                    text: text.to_string(),
+                    client_id: client_id.to_string(),
+                    client_version: version as u64,
+                })),
+                doc_id: doc_id.clone(),
+                client_id: client_id.to_string(),
+                client_version: version as u64,
+                ..Default::default()
+            };
+            submit(&state, client_id, op, ignore_broadcast);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while entry.metrics.applied.load(Ordering::Relaxed) < 3 {
+            assert!(Instant::now() < deadline, "worker did not apply all ops");
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(entry.sync_proto().content, "abc");
+    }
+
+    #[test]
+    fn test_chunked_transaction_applies_with_its_last_batch() {
+        let state = ServerState::new();
+        let client_id = Uuid::new_v4();
+        let (tx, _rx) = crossbeam::channel::bounded(64);
+        state.add_client(ClientEntry::new(client_id, tx)).unwrap();
+        state.open_document(client_id, "").unwrap();
+        let entry = state.documents().pop().unwrap();
+        let doc_id = entry.sync_proto().doc_id;
+
+        let chunk = |version: u64, text: &str| OperationProto {
+            op_id: version,
+            kind: Some(Kind::Insert(InsertOp {
+                index: version as u32 * 2,
+                text: text.to_string(),
+                client_id: client_id.to_string(),
+                client_version: version,
+            })),
+            doc_id: doc_id.clone(),
+            client_id: client_id.to_string(),
+            client_version: version,
+            ..Default::default()
+        };
+        let batch = |operations, more| OperationBatchProto { operations, more };
+
+        process_batch(
+            &state,
+            client_id,
+            batch(vec![chunk(0, "ab"), chunk(1, "cd")], true),
+            ignore_broadcast,
+        );
+        assert_eq!(entry.sync_proto().content, "");
+        process_batch(
+            &state,
+            client_id,
+            batch(vec![chunk(2, "ef")], false),
+            ignore_broadcast,
+        );
+        assert_eq!(entry.sync_proto().content, "abcdef");
+        assert_eq!(entry.sync_proto().version, 3);
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::{
+    Mutex, MutexGuard,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::activity::ActivityDigest;
+use crate::conflict::ConflictPolicies;
+use crate::doc_ids::DocIds;
+use crate::documents::DocumentRegistry;
+use crate::invites::Invite;
+use crate::validation::Rejection;
+
+/// Workspace of connections whose Hello names none.
+pub const DEFAULT_WORKSPACE: &str = "";
+
+/// Limits each workspace is held to; `None` for no limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WorkspaceQuota {
+    pub max_clients: Option<usize>,
+    pub max_documents: Option<usize>,
+}
+
+/// What let a connection into its workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// The workspace was open to all when the connection joined.
+    Open,
+    /// A member's token, naming them.
+    Member(String),
+    /// A guest invite's token.
+    Guest(Invite),
+}
+
+/// One team's share of the server: its documents, with their op logs, and
+/// its own workspace version. Connections only ever see the workspace they
+/// joined, so documents at the same path in two workspaces are unrelated.
+pub struct Workspace {
+    pub name: String,
+    documents: Mutex<DocumentRegistry>,
+    /// Bumped once per applied change, either a single op or a whole
+    /// transaction, whichever of the workspace's documents it touches.
+    global_version: AtomicU64,
+    /// What happened since the activity feed was last sent.
+    activity: Mutex<ActivityDigest>,
+}
+
+impl Workspace {
+    pub fn new(name: &str, policies: ConflictPolicies, ids: DocIds) -> Self {
+        let mut documents = DocumentRegistry::in_workspace(name);
+        documents.set_policies(policies);
+        documents.set_ids(ids);
+        Self {
+            name: name.to_string(),
+            documents: Mutex::new(documents),
+            global_version: AtomicU64::new(0),
+            activity: Mutex::new(ActivityDigest::default()),
+        }
+    }
+
+    pub fn lock_documents(&self) -> MutexGuard<'_, DocumentRegistry> {
+        match self.documents.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn activity(&self) -> MutexGuard<'_, ActivityDigest> {
+        match self.activity.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn global_version(&self) -> u64 {
+        self.global_version.load(Ordering::Relaxed)
+    }
+
+    /// Takes the next workspace version.
+    pub fn next_version(&self) -> u64 {
+        self.global_version.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Name as shown in logs and the console.
+    pub fn label(&self) -> &str {
+        match self.name.as_str() {
+            DEFAULT_WORKSPACE => "(default)",
+            name => name,
+        }
+    }
+}
+
+impl WorkspaceQuota {
+    /// Refuses a connection joining a workspace that has `clients` already.
+    pub fn check_clients(&self, workspace: &Workspace, clients: usize) -> Result<(), Rejection> {
+        check(workspace, "connections", clients, self.max_clients)
+    }
+
+    /// Refuses a new document in a workspace that has `documents` already.
+    pub fn check_documents(
+        &self,
+        workspace: &Workspace,
+        documents: usize,
+    ) -> Result<(), Rejection> {
+        check(workspace, "documents", documents, self.max_documents)
+    }
+}
+
+fn check(
+    workspace: &Workspace,
+    what: &'static str,
+    count: usize,
+    max: Option<usize>,
+) -> Result<(), Rejection> {
+    match max {
+        Some(max) if count >= max => Err(Rejection::WorkspaceFull {
+            workspace: workspace.label().to_string(),
+            what,
+            max,
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_counts_against_each_limit() {
+        let workspace = Workspace::new("team-a", ConflictPolicies::default(), DocIds::default());
+        let quota = WorkspaceQuota {
+            max_clients: Some(2),
+            max_documents: None,
+        };
+        assert!(quota.check_clients(&workspace, 1).is_ok());
+        assert_eq!(
+            quota.check_clients(&workspace, 2),
+            Err(Rejection::WorkspaceFull {
+                workspace: "team-a".to_string(),
+                what: "connections",
+                max: 2,
+            })
+        );
+        assert!(quota.check_documents(&workspace, 1_000).is_ok());
+        assert_eq!(workspace.next_version(), 1);
+        assert_eq!(workspace.global_version(), 1);
+    }
+}
@@ -1,55 +1,83 @@
-use std::{io::Write, net::TcpStream, sync::Arc, thread};
+use std::{
+    io::Write,
+    net::{Shutdown, TcpStream},
+    sync::Arc,
+    thread,
+};
 
 use common::Frame;
 use crossbeam::channel::{Receiver, RecvError};
 use uuid::Uuid;
 
+use crate::capture::{CAPTURES, Direction};
+use crate::dead_letters::{DEAD_LETTERS, DropReason};
+use crate::error::ServerError;
+use crate::log::{debug, error, trace};
+use crate::metrics::TRAFFIC;
+
 pub struct Writer;
 
+/// A transport that can be closed from the writing side, so the peer and the
+/// connection's reader both see it end.
+pub trait HangUp {
+    fn hang_up(&self);
+}
+
+impl HangUp for TcpStream {
+    fn hang_up(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
 impl Writer {
-    pub fn spawn_writer_thread(
+    /// Spawns a thread writing queued frames to `stream` (a TCP socket, or any
+    /// other transport) until the channel closes or a write fails. Frames
+    /// already queued when the channel closes are still written, then the
+    /// stream is hung up.
+    pub fn spawn_writer_thread<W: Write + HangUp + Send + 'static>(
         client_id: Uuid,
-        mut stream: TcpStream,
+        mut stream: W,
         rx: Receiver<Arc<Frame>>,
     ) -> thread::JoinHandle<()> {
         thread::spawn(move || {
-            Writer::write_frames(client_id, &mut stream, rx);
+            if let Err(e) = Writer::write_frames(client_id, &mut stream, rx) {
+                error!("[WRITE] Writer for {} exiting: {}", client_id, e);
+            }
+            stream.hang_up();
         })
     }
 
-    pub fn write_frames(client_id: Uuid, stream: &mut TcpStream, rx: Receiver<Arc<Frame>>) {
+    /// Writes queued frames until the channel closes, then flushes. Fails on
+    /// the first write that does, dead-lettering whatever was left.
+    pub fn write_frames<W: Write + ?Sized>(
+        client_id: Uuid,
+        stream: &mut W,
+        rx: Receiver<Arc<Frame>>,
+    ) -> Result<(), ServerError> {
         loop {
             match rx.recv() {
                 Ok(frame) => {
-                    let payload_length = frame.payload.len();
-
-                    let prefix = (payload_length as u32).to_be_bytes();
-
-                    if let Err(e) = stream.write_all(&prefix) {
-                        eprintln!(
-                            "[WRITE] Writer for {} exiting: write error (prefix) - {}",
-                            client_id, e
-                        );
-                        return; // Exit function on write error
-                    }
-
-                    if let Err(e) = stream.write_all(&frame.payload) {
-                        eprintln!(
-                            "[WRITE] Writer for {} exiting: write error payload - {}",
-                            client_id, e
-                        );
-                        return; // Exit function on write error
+                    if let Err(e) = frame.write_to(stream) {
+                        // Neither this frame nor any still queued will be written
+                        for frame in std::iter::once(frame).chain(rx.try_iter()) {
+                            DEAD_LETTERS.record(client_id, &frame, DropReason::WriteFailed);
+                        }
+                        return Err(ServerError::Write(e));
                     }
 
-                    println!(
-                        "[WRITE] wrote frame with prefix=4 bytes and payload of length {} to writer of {}",
-                        payload_length, client_id,
+                    TRAFFIC.record_written(&frame);
+                    CAPTURES.record(client_id, Direction::Out, &frame);
+                    trace!(
+                        "[WRITE] wrote frame type={} with prefix=4 bytes and payload of length {} to writer of {}",
+                        frame.type_id,
+                        frame.payload.len(),
+                        client_id,
                     );
                 }
 
                 Err(RecvError) => {
                     // Channel closed - exit the loop gracefully to flush
-                    eprintln!(
+                    error!(
                         "[WRITE] Writer for {} exiting: channel disconnected",
                         client_id
                     );
@@ -58,16 +86,46 @@ impl Writer {
             }
         }
 
-        match stream.flush() {
-            Ok(()) => {
-                println!("[WRITE] Write completed and flushed the stream")
-            }
-            Err(e) => {
-                eprintln!(
-                    "[WRITE] Writer for {} exiting: flush error - {}",
-                    client_id, e
-                );
-            }
-        }
+        stream.flush().map_err(ServerError::Write)?;
+        debug!("[WRITE] Write completed and flushed the stream");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_frames_to_buffer() {
+        let (tx, rx) = crossbeam::channel::bounded(4);
+        tx.send(Frame::new_arc(vec![1, 2])).unwrap();
+        tx.send(Frame::new_arc(Vec::new())).unwrap();
+        drop(tx);
+
+        let mut out: Vec<u8> = Vec::new();
+        Writer::write_frames(Uuid::nil(), &mut out, rx).unwrap();
+        assert_eq!(out, vec![0, 0, 0, 2, 1, 2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_writer_drains_then_hangs_up() {
+        use std::io::Read;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let (tx, rx) = crossbeam::channel::bounded(4);
+        tx.send(Frame::new_arc(vec![7])).unwrap();
+        drop(tx);
+        Writer::spawn_writer_thread(Uuid::nil(), server, rx)
+            .join()
+            .unwrap();
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        assert_eq!(received, vec![0, 0, 0, 1, 7]);
     }
 }
@@ -5,7 +5,10 @@ use std::{
     thread,
 };
 
-use common::{protocol::ServerMessage, space::OperationProto};
+use common::{
+    protocol::ServerMessage,
+    space::{HelloProto, OperationProto},
+};
 
 pub struct ClientState {
     pub client_id: String,
@@ -15,7 +18,7 @@ pub struct ClientState {
 }
 
 fn main() -> io::Result<()> {
-    let client_id = uuid::Uuid::new_v4().to_string();
+    let client_id = common::ids::new_uuid().to_string();
     let state = Arc::new(Mutex::new(ClientState {
         client_id,
         doc_id: String::new(),
@@ -56,19 +59,29 @@ fn main() -> io::Result<()> {
 
                 match TcpStream::connect(parts[1]) {
                     Ok(new_stream) => {
-                        let stream_clone = new_stream.try_clone()?;
+                        let mut stream_clone = new_stream.try_clone()?;
 
                         // Reset state
+                        let client_id = common::ids::new_uuid().to_string();
                         {
                             let mut state_guard = state.lock().unwrap();
                             *state_guard = ClientState {
-                                client_id: uuid::Uuid::new_v4().to_string(),
+                                client_id: client_id.clone(),
                                 doc_id: String::new(),
                                 version: 0,
                                 buffer: String::new(),
                             };
                         }
 
+                        // The server sends the initial SYNC in response to Hello
+                        let hello = ServerMessage::encode(&ServerMessage::Hello(HelloProto {
+                            client_id,
+                            ..Default::default()
+                        }));
+                        stream_clone.write_all(&(hello.len() as u32).to_be_bytes())?;
+                        stream_clone.write_all(&hello)?;
+                        stream_clone.flush()?;
+
                         // Spawn reader thread
                         let state_for_reader = Arc::clone(&state);
                         thread::spawn(move || {
@@ -122,13 +135,17 @@ fn main() -> io::Result<()> {
                     });
 
                     let operation = ServerMessage::Operation(OperationProto {
-                        op_id: uuid::Uuid::new_v4().as_u64_pair().0,
+                        op_id: common::ids::new_op_id(),
                         kind: Some(op_kind),
                         doc_id,
                         client_id,
                         client_version: version,
                         server_version: 0,
                         new_content: text.to_string(),
+                        // Stamped by the server when applied
+                        applied_at_ms: 0,
+                        applied_mono_ms: 0,
+                        global_version: 0,
                     });
 
                     let message = ServerMessage::encode(&operation);
@@ -180,12 +197,18 @@ fn reader_loop(stream: TcpStream, state: Arc<Mutex<ClientState>>) -> io::Result<
         );
 
         // Decode ServerMessage
-        match ServerMessage::decode(&*payload_buffer) {
+        match ServerMessage::decode(&payload_buffer) {
             Ok(message) => {
                 match message {
                     ServerMessage::Operation(_) => {
                         println!("[DEBUG] Decoded as Operation");
                     }
+                    ServerMessage::OperationBatch(batch) => {
+                        println!(
+                            "[DEBUG] Decoded as OperationBatch of {}",
+                            batch.operations.len()
+                        );
+                    }
                     ServerMessage::SyncDocument(doc) => {
                         println!("[DEBUG] Decoded as SyncDocument");
                         // Update local state
@@ -209,6 +232,197 @@ fn reader_loop(stream: TcpStream, state: Arc<Mutex<ClientState>>) -> io::Result<
                     ServerMessage::Pong(seq) => {
                         println!("[DEBUG] Received Pong({})", seq);
                     }
+                    ServerMessage::Error(error) => {
+                        println!(
+                            "ERROR {{ code: {}, message: \"{}\" }}",
+                            error.code, error.message
+                        );
+                    }
+                    ServerMessage::Disconnect(disconnect) => {
+                        println!(
+                            "DISCONNECT {{ reason_code: {}, message: \"{}\" }}",
+                            disconnect.reason_code, disconnect.message
+                        );
+                    }
+                    ServerMessage::DocumentArchive(archive) => {
+                        println!(
+                            "ARCHIVE {{ path: \"{}\", version: {}, operations: {} }}",
+                            archive.path,
+                            archive.version,
+                            archive.operations.len()
+                        );
+                    }
+                    ServerMessage::RangeLocks(locks) => {
+                        println!(
+                            "LOCKS {{ doc_id: \"{}\", version: {}, locks: {} }}",
+                            locks.doc_id,
+                            locks.version,
+                            locks.locks.len()
+                        );
+                    }
+                    ServerMessage::Overlays(overlays) => {
+                        println!(
+                            "OVERLAYS {{ doc_id: \"{}\", version: {}, overlays: {} }}",
+                            overlays.doc_id,
+                            overlays.version,
+                            overlays.overlays.len()
+                        );
+                    }
+                    ServerMessage::Presence(presence) => {
+                        println!(
+                            "PRESENCE {{ client_id: \"{}\", status: {}, reason: \"{}\" }}",
+                            presence.client_id, presence.status, presence.reason
+                        );
+                    }
+                    ServerMessage::Capabilities(capabilities) => {
+                        println!(
+                            "CAPABILITIES {{ batches: {}, presence: {} }}",
+                            capabilities.batches, capabilities.presence
+                        );
+                    }
+                    ServerMessage::Invite(invite) => {
+                        println!(
+                            "INVITE {{ workspace: '{}', path: '{}', read_only: {} }}",
+                            invite.workspace, invite.path, invite.read_only
+                        );
+                    }
+                    ServerMessage::ExportChunk(chunk) => {
+                        println!(
+                            "EXPORT_CHUNK {{ file_name: '{}', bytes: {}, more: {} }}",
+                            chunk.file_name,
+                            chunk.data.len(),
+                            chunk.more
+                        );
+                    }
+                    ServerMessage::Attachment(attachment) => {
+                        println!(
+                            "ATTACHMENT {{ id: '{}', size: {} }}",
+                            attachment.attachment_id, attachment.size
+                        );
+                    }
+                    ServerMessage::AttachmentChunk(chunk) => {
+                        println!(
+                            "ATTACHMENT_CHUNK {{ id: '{}', bytes: {}, more: {} }}",
+                            chunk.attachment_id,
+                            chunk.data.len(),
+                            chunk.more
+                        );
+                    }
+                    ServerMessage::History(history) => {
+                        println!(
+                            "HISTORY {{ path: '{}', version: {}, milestones: {}, tags: {}, tag: '{}' }}",
+                            history.path,
+                            history.version,
+                            history.milestones.len(),
+                            history.tags.len(),
+                            history.tag
+                        );
+                    }
+                    ServerMessage::Freeze(freeze) => {
+                        println!(
+                            "FREEZE {{ workspace: '{}', path: '{}', frozen: {}, ends_at_ms: {} }}",
+                            freeze.workspace, freeze.path, freeze.frozen, freeze.ends_at_ms
+                        );
+                    }
+                    ServerMessage::Notice(notice) => {
+                        println!(
+                            "NOTICE {{ workspace: '{}', message: '{}' }}",
+                            notice.workspace, notice.message
+                        );
+                    }
+                    ServerMessage::Redirect(redirect) => {
+                        println!(
+                            "REDIRECT {{ address: '{}', doc_id: '{}', message: '{}' }}",
+                            redirect.address, redirect.doc_id, redirect.message
+                        );
+                    }
+                    ServerMessage::Diagnostics(diagnostics) => {
+                        println!(
+                            "DIAGNOSTICS {{ kind: {}, message: '{}', guidance: '{}', server_version: '{}' }}",
+                            diagnostics.kind().as_str_name(),
+                            diagnostics.message,
+                            diagnostics.guidance,
+                            diagnostics.server_version
+                        );
+                    }
+                    ServerMessage::TimeSync(sync) => {
+                        println!(
+                            "TIME_SYNC {{ server_receive_ms: {}, server_send_ms: {} }}",
+                            sync.server_receive_ms, sync.server_send_ms
+                        );
+                    }
+                    ServerMessage::Signal(signal) => {
+                        println!(
+                            "SIGNAL {{ client_id: '{}', kind: {:?}, range: {}..{}, emoji: '{}' }}",
+                            signal.client_id,
+                            signal.kind(),
+                            signal.start,
+                            signal.end,
+                            signal.emoji
+                        );
+                    }
+                    ServerMessage::Viewport(viewport) => {
+                        println!(
+                            "VIEWPORT {{ client_id: '{}', path: '{}', first_line: {}, line_count: {} }}",
+                            viewport.client_id,
+                            viewport.path,
+                            viewport.first_line,
+                            viewport.line_count
+                        );
+                    }
+                    ServerMessage::Checkpoint(checkpoint) => {
+                        println!(
+                            "CHECKPOINT {{ commit: '{}', contributors: {}, milestone: {:?} }}",
+                            checkpoint.commit,
+                            checkpoint.contributors.len(),
+                            checkpoint.milestone.map(|milestone| milestone.version)
+                        );
+                    }
+                    ServerMessage::MemberToken(token) => {
+                        println!(
+                            "MEMBER_TOKEN {{ workspace: '{}', member: '{}' }}",
+                            token.workspace, token.member
+                        );
+                    }
+                    ServerMessage::Activity(activity) => {
+                        println!(
+                            "ACTIVITY {{ events: {}, edits: {}, joined: {}, left: {} }}",
+                            activity.events.len(),
+                            activity.edits.len(),
+                            activity.joined.len(),
+                            activity.left.len()
+                        );
+                    }
+                    ServerMessage::Hello(_)
+                    | ServerMessage::OpenDocument(_)
+                    | ServerMessage::CloseDocument(_)
+                    | ServerMessage::ExportDocument(_)
+                    | ServerMessage::ExportRequest(_)
+                    | ServerMessage::UploadAttachment(_)
+                    | ServerMessage::FetchAttachment(_)
+                    | ServerMessage::SetViewport(_)
+                    | ServerMessage::Follow(_)
+                    | ServerMessage::TagVersion(_)
+                    | ServerMessage::GetHistory(_)
+                    | ServerMessage::CreateFromTemplate(_)
+                    | ServerMessage::SetPresence(_)
+                    | ServerMessage::LockRange(_)
+                    | ServerMessage::UnlockRange(_)
+                    | ServerMessage::Resend(_)
+                    | ServerMessage::Credit(_)
+                    | ServerMessage::SetOverlays(_)
+                    | ServerMessage::SetDocumentSettings(_)
+                    | ServerMessage::InviteMember(_)
+                    | ServerMessage::RemoveMember(_)
+                    | ServerMessage::CreateInvite(_)
+                    | ServerMessage::Propagation(_)
+                    | ServerMessage::WatchActivity(_)
+                    | ServerMessage::Ack(_) => {
+                        println!("[DEBUG] Ignoring client-only message from server");
+                    }
+                    ServerMessage::Sequenced(seq, _) => {
+                        println!("[DEBUG] Ignoring sequenced frame #{} (not requested)", seq);
+                    }
                 }
             }
             Err(e) => {
@@ -140,7 +140,7 @@ fn main() {
     client_a.wait_for_op_sent();
 
     // Only Client B waits for SYNC (since Client A already knows about its own operation)
-    let sync_b1 = client_b.wait_for_sync();
+    client_b.wait_for_sync();
 
     println!("✓ Round 1 - Client B synced");
 
@@ -152,7 +152,7 @@ fn main() {
     client_b.wait_for_op_sent();
 
     // Only Client A waits for SYNC
-    let sync_a2 = client_a.wait_for_sync();
+    client_a.wait_for_sync();
 
     println!("✓ Round 2 - Client A synced");
 